@@ -0,0 +1,21 @@
+//! Loads the traffic corpus shared by the benchmarks.
+
+/// The traffic of a layout session, in the capture format of `locodrive-monitor`.
+const CAPTURE: &str = include_str!("../../fixtures/traffic.capture");
+
+/// # Returns
+///
+/// The frames of the traffic corpus in order.
+pub fn frames() -> Vec<Vec<u8>> {
+    CAPTURE
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            // Skips the offset and the direction of the frame
+            line.split_whitespace()
+                .skip(2)
+                .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,93 @@
+//! Benchmarks parsing and encoding the messages of a realistic traffic mix.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use locodrive::message_ref::MessageRef;
+use locodrive::protocol::{Message, MAX_MESSAGE_LEN};
+
+mod corpus;
+
+/// Benchmarks [`Message::parse()`] over the whole corpus and over single frames of each length.
+fn parse(c: &mut Criterion) {
+    let frames = corpus::frames();
+    let bytes = frames.iter().map(Vec::len).sum::<usize>();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("traffic", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(Message::parse(black_box(frame)).unwrap());
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("parse_frame");
+    for len in [2, 4, 14] {
+        let frame = frames.iter().find(|frame| frame.len() == len).unwrap();
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_function(format!("{}_bytes", len), |b| {
+            b.iter(|| Message::parse(black_box(frame)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Benchmarks [`Message::to_message()`] and [`Message::write_to()`] over the messages of the whole corpus.
+fn to_message(c: &mut Criterion) {
+    let frames = corpus::frames();
+    let bytes = frames.iter().map(Vec::len).sum::<usize>();
+    let messages = frames
+        .iter()
+        .map(|frame| Message::parse(frame).unwrap())
+        .collect::<Vec<Message>>();
+
+    let mut group = c.benchmark_group("to_message");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("traffic", |b| {
+        b.iter(|| {
+            for message in &messages {
+                black_box(black_box(message).to_message());
+            }
+        })
+    });
+    group.bench_function("write_to", |b| {
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        b.iter(|| {
+            for message in &messages {
+                black_box(black_box(message).write_to(&mut buf).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Benchmarks inspecting the slot of every frame of the corpus,
+/// by decoding the whole messages and by borrowing the frames as [`MessageRef`].
+fn inspect_slot(c: &mut Criterion) {
+    let frames = corpus::frames();
+    let bytes = frames.iter().map(Vec::len).sum::<usize>();
+
+    let mut group = c.benchmark_group("inspect_slot");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(match Message::parse(black_box(frame)).unwrap() {
+                    Message::LocoSpd(slot, _) | Message::SlRdData(slot, ..) => Some(slot),
+                    _ => None,
+                });
+            }
+        })
+    });
+    group.bench_function("message_ref", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(MessageRef::new(black_box(frame)).unwrap().slot());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse, to_message, inspect_slot);
+criterion_main!(benches);
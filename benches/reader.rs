@@ -0,0 +1,52 @@
+//! Benchmarks the reading thread of a controller decoding a realistic traffic mix,
+//! fed through an in memory transport.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use locodrive::loco_controller::{LocoDriveController, LocoDriveMessage};
+use locodrive::transport::LocoNetTransport;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Runtime;
+
+mod corpus;
+
+/// Benchmarks reading, decoding and broadcasting all frames of the corpus.
+fn frame_reader(c: &mut Criterion) {
+    let frames = corpus::frames();
+    let traffic = frames.concat();
+    let runtime = Runtime::new().unwrap();
+
+    let (controller_end, mut bus) = LocoNetTransport::pair();
+    // The channel holds the whole corpus, so no message is lost while it is written
+    let controller = runtime
+        .block_on(
+            LocoDriveController::builder(controller_end.name(), 0)
+                .transport(controller_end)
+                .channel_capacity(frames.len() * 2)
+                .build(),
+        )
+        .unwrap();
+    let mut messages = controller.subscribe();
+
+    let mut group = c.benchmark_group("frame_reader");
+    group.throughput(Throughput::Bytes(traffic.len() as u64));
+    group.sample_size(20);
+    group.bench_function("traffic", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let receive = async {
+                    let mut received = 0;
+                    while received < frames.len() {
+                        if let LocoDriveMessage::Message(_) = messages.recv().await.unwrap() {
+                            received += 1;
+                        }
+                    }
+                };
+                let (written, ()) = tokio::join!(bus.write_all(&traffic), receive);
+                written.unwrap();
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, frame_reader);
+criterion_main!(benches);
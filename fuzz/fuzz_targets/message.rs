@@ -0,0 +1,19 @@
+#![no_main]
+
+use arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use locodrive::protocol::Message;
+
+fuzz_target!(|data: &[u8]| {
+    // Parsing any bytes must never panic
+    if let Ok(parsed) = Message::parse(data) {
+        let _ = parsed.to_message();
+    }
+
+    // Any message parsed from its own bytes must be written back to the same bytes
+    if let Ok(message) = Message::arbitrary(&mut Unstructured::new(data)) {
+        if let Ok(parsed) = Message::parse(&message.to_message()) {
+            assert_eq!(Message::parse(&parsed.to_message()).ok(), Some(parsed));
+        }
+    }
+});
@@ -0,0 +1,167 @@
+/*
+ * The C interface of locodrive, built with the `ffi` feature by
+ * `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+ *
+ * Frames are passed as byte buffers including their checksum, so layout software keeps
+ * its own message types and uses locodrive to validate, encode and transfer them.
+ * Functions returning an int32_t return a negative LOCODRIVE_ERR_* code on failure.
+ */
+
+#ifndef LOCODRIVE_H
+#define LOCODRIVE_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * A pointer argument was null or a string argument was no valid UTF-8.
+ */
+#define LOCODRIVE_ERR_INVALID_ARGUMENT -1
+
+/**
+ * The buffer does not hold the complete frame.
+ */
+#define LOCODRIVE_ERR_UNEXPECTED_END -2
+
+/**
+ * The op code of the frame is not known.
+ */
+#define LOCODRIVE_ERR_UNKNOWN_OPCODE -3
+
+/**
+ * The checksum of the frame is invalid.
+ */
+#define LOCODRIVE_ERR_INVALID_CHECKSUM -4
+
+/**
+ * The arguments of the frame are invalid.
+ */
+#define LOCODRIVE_ERR_INVALID_FORMAT -5
+
+/**
+ * The output buffer is too small.
+ */
+#define LOCODRIVE_ERR_BUFFER_TOO_SMALL -6
+
+/**
+ * The message could not be send to the model railroad.
+ */
+#define LOCODRIVE_ERR_SEND_FAILED -7
+
+/**
+ * A controller connected to a serial port, with the runtime it runs on.
+ *
+ * It is opaque to C and created by [`locodrive_open_port()`].
+ */
+typedef struct LocoDriveHandle LocoDriveHandle;
+
+/**
+ * Called with the bytes of every message received from the model railroad
+ * and the user data given on registration.
+ */
+typedef void (*LocoDriveReceiveCallback)(const uint8_t *frame, size_t len, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Parses the frame at the start of `frame` and writes its description, terminated by a null byte,
+ * to `description`. The description is truncated to fit `description_len`.
+ *
+ * # Returns
+ *
+ * The length of the parsed frame as told by its op code or count byte, which may differ
+ * from the length the message encodes to, or a negative `LOCODRIVE_ERR_*` code.
+ *
+ * # Safety
+ *
+ * `frame` has to point to `len` readable bytes and `description` to `description_len`
+ * writable bytes. `description` may be null to only validate the frame.
+ */
+int32_t locodrive_parse(const uint8_t *frame,
+                        size_t len,
+                        char *description,
+                        size_t description_len);
+
+/**
+ * Encodes the op code and arguments in `body` to a frame by appending the checksum.
+ * The frame is validated, so only known messages are encoded.
+ *
+ * # Returns
+ *
+ * The length of the frame written to `out`, or a negative `LOCODRIVE_ERR_*` code.
+ *
+ * # Safety
+ *
+ * `body` has to point to `body_len` readable bytes and `out` to `out_len` writable bytes.
+ */
+int32_t locodrive_encode(const uint8_t *body, size_t body_len, uint8_t *out, size_t out_len);
+
+/**
+ * Connects a controller to the serial port `port_name` with `baud_rate`.
+ * The controller runs on its own runtime, so C code needs none.
+ *
+ * # Returns
+ *
+ * The handle of the controller to pass to the other functions,
+ * or null if the port could not be opened.
+ *
+ * # Safety
+ *
+ * `port_name` has to point to a null terminated string.
+ */
+LocoDriveHandle *locodrive_open_port(const char *port_name, uint32_t baud_rate);
+
+/**
+ * Sends the frame in `frame` and blocks until the model railroad echoed it.
+ *
+ * # Returns
+ *
+ * Zero if the message was send, or a negative `LOCODRIVE_ERR_*` code.
+ *
+ * # Safety
+ *
+ * `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet.
+ * `frame` has to point to `len` readable bytes.
+ */
+int32_t locodrive_send(LocoDriveHandle *handle, const uint8_t *frame, size_t len);
+
+/**
+ * Registers `callback` to be called with the bytes of every message received from the model railroad
+ * and `user_data`. A callback registered before is replaced, a null callback unregisters it.
+ *
+ * The callback is called from a thread of the controllers runtime, not from the registering thread.
+ *
+ * # Returns
+ *
+ * Zero, or [`LOCODRIVE_ERR_INVALID_ARGUMENT`] if `handle` is null.
+ *
+ * # Safety
+ *
+ * `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet.
+ * `callback` has to be callable from any thread with `user_data` until it is replaced
+ * or the handle is closed. This function must not be called from the callback,
+ * as it waits for the previous callback to return.
+ */
+int32_t locodrive_set_receive_callback(LocoDriveHandle *handle,
+                                       LocoDriveReceiveCallback callback,
+                                       void *user_data);
+
+/**
+ * Stops the controller and releases `handle`. No callback is called afterwards.
+ *
+ * # Safety
+ *
+ * `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet, or be null.
+ * This function must not be called from the callback, as it waits for it to return.
+ */
+void locodrive_close(LocoDriveHandle *handle);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* LOCODRIVE_H */
@@ -0,0 +1,288 @@
+#[cfg(feature = "hotplug")]
+use crate::hotplug::InterfaceInfo;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_serial::{Error, ErrorKind, FlowControl, SerialPort, SerialStream};
+
+/// A control line of the serial port.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ControlLine {
+    /// The data terminal ready line
+    Dtr,
+    /// The request to send line
+    Rts,
+}
+
+/// One step of initializing an adapter after the serial port was opened.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum AdapterStep {
+    /// Sets the control line to the given level.
+    SetLine(ControlLine, bool),
+    /// Writes the given bytes to the adapter.
+    Write(Vec<u8>),
+    /// Waits for the adapter to settle.
+    Wait(Duration),
+    /// Waits for the adapter to set the clear to send line, at most for the given time.
+    /// Fails if the line is still not set then, as the hardware handshake is not working.
+    AwaitCts(Duration),
+}
+
+/// A user hook initializing an adapter, invoked with the opened serial port.
+pub type AdapterHook = Arc<dyn Fn(&mut SerialStream) -> Result<(), Error> + Send + Sync>;
+
+/// Describes how to initialize a serial interface to the model railroad after it was opened.
+///
+/// Some interfaces need control lines set or an init byte sequence written before they pass
+/// any messages. The profile is applied every time a controller connects to the interface,
+/// so also when a [`crate::hotplug::HotplugWatcher`] reattaches it.
+///
+/// The steps are applied first, then the hooks in order of adding.
+///
+/// A profile can also fix the baud rate, flow control and echo handling an interface needs,
+/// which take precedence over the settings of the [`crate::loco_controller::LocoDriveControllerBuilder`].
+#[derive(Clone)]
+pub struct AdapterProfile {
+    /// The name of the profile
+    name: String,
+    /// The baud rate the interface needs, if fixed
+    baud_rate: Option<u32>,
+    /// The flow control the interface needs, if fixed
+    flow_control: Option<FlowControl>,
+    /// Whether the interface echoes the messages written to it
+    echoes: bool,
+    /// The steps to apply
+    steps: Vec<AdapterStep>,
+    /// The user hooks to invoke after the steps
+    hooks: Vec<AdapterHook>,
+}
+
+impl AdapterProfile {
+    /// Creates a new profile without steps or hooks.
+    pub fn new(name: &str) -> Self {
+        AdapterProfile {
+            name: name.to_string(),
+            baud_rate: None,
+            flow_control: None,
+            echoes: true,
+            steps: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The profile for interfaces that need no initialization.
+    pub fn generic() -> Self {
+        Self::new("generic")
+    }
+
+    /// # Returns
+    ///
+    /// The profile for the RR-CirKits LocoBuffer-USB, that only passes messages
+    /// while DTR and RTS are set.
+    pub fn locobuffer_usb() -> Self {
+        Self::new("locobuffer-usb")
+            .step(AdapterStep::SetLine(ControlLine::Dtr, true))
+            .step(AdapterStep::SetLine(ControlLine::Rts, true))
+    }
+
+    /// # Returns
+    ///
+    /// The profile for the Giesler GCA85, that is powered by DTR and needs some time
+    /// to start up afterwards.
+    pub fn gca85() -> Self {
+        Self::new("gca85")
+            .step(AdapterStep::SetLine(ControlLine::Dtr, true))
+            .step(AdapterStep::SetLine(ControlLine::Rts, false))
+            .step(AdapterStep::Wait(Duration::from_millis(100)))
+    }
+
+    /// # Returns
+    ///
+    /// The profile for the USB port of the Uhlenbrock Intellibox II and its LocoNet interfaces.
+    /// They run at 115200 baud with hardware handshake, set clear to send only when ready
+    /// and do not echo the messages written to them.
+    pub fn intellibox() -> Self {
+        Self::new("intellibox")
+            .baud_rate(115_200)
+            .flow_control(FlowControl::Hardware)
+            .echoes(false)
+            .step(AdapterStep::SetLine(ControlLine::Rts, true))
+            .step(AdapterStep::AwaitCts(Duration::from_secs(1)))
+    }
+
+    /// # Returns
+    ///
+    /// The built-in profile with the given `name`, if known.
+    /// Known are `generic`, `locobuffer-usb`, `gca85` and `intellibox`.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "generic" => Some(Self::generic()),
+            "locobuffer-usb" => Some(Self::locobuffer_usb()),
+            "gca85" => Some(Self::gca85()),
+            "intellibox" => Some(Self::intellibox()),
+            _ => None,
+        }
+    }
+
+    /// Detects the built-in profile for the device described by `info`
+    /// by its manufacturer and product name.
+    ///
+    /// # Returns
+    ///
+    /// The detected profile, or [`AdapterProfile::generic()`] if the device is not recognized.
+    #[cfg(feature = "hotplug")]
+    pub fn detect(info: &InterfaceInfo) -> Self {
+        let names = [&info.manufacturer, &info.product]
+            .iter()
+            .filter_map(|name| name.as_ref())
+            .map(|name| name.to_lowercase())
+            .collect::<Vec<_>>();
+        let named = |part: &str| names.iter().any(|name| name.contains(part));
+
+        if named("uhlenbrock") || named("intellibox") {
+            Self::intellibox()
+        } else if named("locobuffer") {
+            Self::locobuffer_usb()
+        } else {
+            Self::generic()
+        }
+    }
+
+    /// Fixes the baud rate the interface needs.
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Fixes the flow control the interface needs.
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = Some(flow_control);
+        self
+    }
+
+    /// Sets whether the interface echoes the messages written to it. Defaults to `true`.
+    ///
+    /// Controllers connected to interfaces not echoing do not await echoes
+    /// and broadcast the written messages as [`crate::loco_controller::LocoDriveMessage::Sent`] instead.
+    pub fn echoes(mut self, echoes: bool) -> Self {
+        self.echoes = echoes;
+        self
+    }
+
+    /// Adds a step to apply.
+    pub fn step(mut self, step: AdapterStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Adds a hook to invoke after the steps.
+    pub fn hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut SerialStream) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The name of the profile.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// # Returns
+    ///
+    /// The steps to apply.
+    pub fn steps(&self) -> &[AdapterStep] {
+        &self.steps
+    }
+
+    /// # Returns
+    ///
+    /// The baud rate the interface needs, if fixed.
+    pub fn fixed_baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+
+    /// # Returns
+    ///
+    /// The flow control the interface needs, if fixed.
+    pub fn fixed_flow_control(&self) -> Option<FlowControl> {
+        self.flow_control
+    }
+
+    /// # Returns
+    ///
+    /// Whether the interface echoes the messages written to it.
+    pub fn has_echo(&self) -> bool {
+        self.echoes
+    }
+
+    /// Applies the steps and invokes the hooks on the opened `port`.
+    ///
+    /// # Errors
+    ///
+    /// The first error of setting a control line, writing or a hook.
+    pub async fn apply(&self, port: &mut SerialStream) -> Result<(), Error> {
+        for step in &self.steps {
+            match step {
+                AdapterStep::SetLine(ControlLine::Dtr, level) => {
+                    port.write_data_terminal_ready(*level)?
+                }
+                AdapterStep::SetLine(ControlLine::Rts, level) => {
+                    port.write_request_to_send(*level)?
+                }
+                AdapterStep::Write(bytes) => {
+                    port.write_all(bytes).await?;
+                    port.flush().await?;
+                }
+                AdapterStep::Wait(duration) => sleep(*duration).await,
+                AdapterStep::AwaitCts(timeout) => {
+                    let deadline = Instant::now() + *timeout;
+                    while !port.read_clear_to_send()? {
+                        if Instant::now() >= deadline {
+                            return Err(Error::new(
+                                ErrorKind::Io(io::ErrorKind::TimedOut),
+                                format!(
+                                    "{} did not set clear to send, check the handshake",
+                                    self.name
+                                ),
+                            ));
+                        }
+                        sleep(Duration::from_millis(10)).await;
+                    }
+                }
+            }
+        }
+
+        for hook in &self.hooks {
+            hook(port)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for AdapterProfile {
+    fn default() -> Self {
+        Self::generic()
+    }
+}
+
+impl Debug for AdapterProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdapterProfile")
+            .field("name", &self.name)
+            .field("baud_rate", &self.baud_rate)
+            .field("flow_control", &self.flow_control)
+            .field("echoes", &self.echoes)
+            .field("steps", &self.steps)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
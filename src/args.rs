@@ -2,10 +2,14 @@
 
 use crate::error::MessageParseError;
 use crate::protocol::Message;
-use std::fmt::{Debug, Display, Formatter};
+use alloc::format;
+use core::convert::TryFrom;
+use core::fmt::{Debug, Display, Formatter};
 
 /// Represents a trains address of 14 byte length.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct AddressArg(u16);
 
 impl AddressArg {
@@ -61,12 +65,14 @@ impl AddressArg {
 
 /// Which direction state a switch is orientated to
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SwitchDirection {
     Straight,
     Curved,
 }
 
-impl std::ops::Not for SwitchDirection {
+impl core::ops::Not for SwitchDirection {
     type Output = SwitchDirection;
 
     fn not(self) -> Self::Output {
@@ -78,7 +84,11 @@ impl std::ops::Not for SwitchDirection {
 }
 
 /// Holds switch state information to be read or write
+///
+/// The [`SwitchArg::reserved()`] bits are compared and hashed as well, so a parsed arg
+/// with reserved bits set is not equal to the same switch state created by [`SwitchArg::new()`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SwitchArg {
     /// The address of the switch (0 - 2047)
     address: u16,
@@ -87,6 +97,8 @@ pub struct SwitchArg {
     /// If the switch is not in the requested direction.
     /// Use true if you want the switch to go to the direction.
     state: bool,
+    /// The `sw2` bits not interpreted by this arg, kept for bit exact re-encoding.
+    reserved: u8,
 }
 
 impl SwitchArg {
@@ -103,6 +115,7 @@ impl SwitchArg {
             address,
             direction,
             state,
+            reserved: 0x00,
         }
     }
 
@@ -112,8 +125,8 @@ impl SwitchArg {
     ///
     /// - `sw1`: Seven least significant switch address bits
     /// - `sw2`: four most significant switch address bits,
-    ///          1 bit for direction and
-    ///          1 bit for activation state
+    ///   1 bit for direction and
+    ///   1 bit for activation state
     pub(crate) fn parse(sw1: u8, sw2: u8) -> Self {
         let mut address = sw1 as u16;
         address |= (sw2 as u16 & 0x0F) << 7;
@@ -129,6 +142,7 @@ impl SwitchArg {
             address,
             direction,
             state,
+            reserved: sw2 & 0x40,
         }
     }
 
@@ -150,6 +164,12 @@ impl SwitchArg {
     pub fn state(&self) -> bool {
         self.state
     }
+    /// # Returns
+    ///
+    /// The reserved `sw2` bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
 
     /// Sets the address of the switch to use.
     ///
@@ -187,7 +207,7 @@ impl SwitchArg {
     ///
     /// The four most significant address bits combined with a direction state and activation state.
     pub(crate) fn sw2(&self) -> u8 {
-        let mut sw2 = ((self.address >> 7) & 0x000F) as u8;
+        let mut sw2 = ((self.address >> 7) & 0x000F) as u8 | self.reserved;
 
         sw2 |= match self.direction {
             SwitchDirection::Curved => 0x00,
@@ -217,6 +237,8 @@ impl SwitchArg {
 /// | - 124   | programming track                  |
 /// | - 127   | command station options            |
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SlotArg(u8);
 
 impl SlotArg {
@@ -247,10 +269,20 @@ impl SlotArg {
     pub fn slot(&self) -> u8 {
         self.0
     }
+
+    /// # Returns
+    ///
+    /// If this is one of the reserved system slots (120 - 126), like the fast clock
+    /// or the programming track.
+    pub fn is_reserved(&self) -> bool {
+        (120..=126).contains(&self.0)
+    }
 }
 
 /// Represents the speed set to a [`SlotArg`].
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SpeedArg {
     /// Performs a normal stop. Trains may stop smoothly when they receive a message force them to stop.
     Stop,
@@ -271,7 +303,7 @@ impl SpeedArg {
     /// # Parameters
     ///
     /// - `spd`: The speed to create the `SpeedArg` for.
-    ///          The maximum speed is 126. Higher values may create unexpected behaviour.
+    ///   The maximum speed is 126. Higher values may create unexpected behaviour.
     pub fn new(spd: u8) -> Self {
         match spd {
             0x00 => Self::Stop,
@@ -299,7 +331,7 @@ impl SpeedArg {
         match *self {
             SpeedArg::Stop => 0x00,
             SpeedArg::EmergencyStop => 0x01,
-            SpeedArg::Drive(spd) => (spd + 1) & 0x7F,
+            SpeedArg::Drive(spd) => spd.wrapping_add(1) & 0x7F,
         }
     }
 
@@ -323,6 +355,8 @@ impl SpeedArg {
 ///
 /// Function bit 0 may control a trains light
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct DirfArg(u8);
 
 impl DirfArg {
@@ -337,7 +371,8 @@ impl DirfArg {
     /// - `f3`: Function bit 3
     /// - `f4`: Function bit 4
     pub fn new(dir: bool, f0: bool, f1: bool, f2: bool, f3: bool, f4: bool) -> Self {
-        let mut dirf = if dir { 0x20 } else { 0x00 };
+        // The direction bit is set for driving backwards
+        let mut dirf = if dir { 0x00 } else { 0x20 };
         if f0 {
             dirf |= 0x10
         }
@@ -357,8 +392,10 @@ impl DirfArg {
     }
 
     /// Parses the direction from a model railroad message.
+    ///
+    /// The reserved bit is kept, so the arg re-encodes to the same byte.
     pub(crate) fn parse(dirf: u8) -> Self {
-        Self(dirf & 0x3F)
+        Self(dirf & 0x7F)
     }
 
     /// # Returns
@@ -366,7 +403,7 @@ impl DirfArg {
     /// The direction represented by this [`DirfArg`].
     /// `true` means forward, `false` means backwards.
     pub fn dir(&self) -> bool {
-        self.0 & 0x20 != 0
+        self.0 & 0x20 == 0
     }
 
     /// # Returns
@@ -389,9 +426,9 @@ impl DirfArg {
     /// - `value`: The direction to set (`true` = forward, `false` = backward)
     pub fn set_dir(&mut self, value: bool) {
         if value {
-            self.0 |= 0x20;
-        } else {
             self.0 &= !0x20
+        } else {
+            self.0 |= 0x20;
         }
     }
 
@@ -400,7 +437,7 @@ impl DirfArg {
     /// # Parameters
     ///
     /// - `f_num`: The f-flag to set. (Only values in range of 0 to 4 may create an effect).
-    ///            Other inputs will be ignored.
+    ///   Other inputs will be ignored.
     /// - `value`: The value to set the requested flag to.
     pub fn set_f(&mut self, f_num: u8, value: bool) {
         if f_num <= 4 {
@@ -426,7 +463,7 @@ impl DirfArg {
 /// Overriding the [`Debug`] trait, to show only the corresponding arg states
 impl Debug for DirfArg {
     /// Prints the direction and all f-flags from 0 to 4 to the formatter
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "dirf: (dir: {}, f0: {}, f1: {}, f2: {}, f3: {}, f4: {})",
@@ -441,7 +478,11 @@ impl Debug for DirfArg {
 }
 
 /// Holds the track information
+///
+/// Equality and hashing include the [`TrkArg::reserved()`] bits. A parsed arg with reserved bits
+/// set therefore differs from its [`TrkArg::new()`] counterpart.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct TrkArg {
     /// The tracks power state (`ON`/`OFF`).
     power: bool,
@@ -452,6 +493,8 @@ pub struct TrkArg {
     mlok1: bool,
     /// Indicates that masters programming track is busy.
     prog_busy: bool,
+    /// The track bits not interpreted by this arg, kept for bit exact re-encoding.
+    reserved: u8,
 }
 
 impl TrkArg {
@@ -469,6 +512,7 @@ impl TrkArg {
             idle,
             mlok1,
             prog_busy,
+            reserved: 0x00,
         }
     }
 
@@ -487,6 +531,7 @@ impl TrkArg {
             idle,
             mlok1,
             prog_busy,
+            reserved: trk_arg & 0x70,
         }
     }
 
@@ -521,13 +566,23 @@ impl TrkArg {
         self.prog_busy
     }
 
+    /// # Returns
+    ///
+    /// The reserved track bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+
     /// Parses this arg to a valid model railroad track message byte.
     ///
     /// # Returns
     ///
     /// The model railroad trk message byte matching this [`TrkArg`].
     pub(crate) fn trk_arg(&self) -> u8 {
-        let mut trk_arg = if self.power { 0x01 } else { 0x00 };
+        let mut trk_arg = self.reserved;
+        if self.power {
+            trk_arg |= 0x01;
+        }
         if !self.idle {
             trk_arg |= 0x02;
         }
@@ -545,6 +600,8 @@ impl TrkArg {
 ///
 /// This function flags may be used for train sound management if available.
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SndArg(u8);
 
 impl SndArg {
@@ -574,9 +631,10 @@ impl SndArg {
     ///
     /// # Parameters
     ///
-    /// - `snd`: A model railroad formatted snd byte
+    /// - `snd`: A model railroad formatted snd byte.
+    ///   Reserved bits are kept, so the arg re-encodes to the same byte.
     pub(crate) fn parse(snd: u8) -> Self {
-        Self(snd & 0x0F)
+        Self(snd & 0x7F)
     }
 
     /// # Parameters
@@ -620,7 +678,7 @@ impl SndArg {
 /// Overrides the [`Debug`] trait to show only the corresponding function bits
 impl Debug for SndArg {
     /// Prints the f flags from 5 to 8 to the formatter
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "snd: (f5: {}, f6: {}, f7: {}, f8: {})",
@@ -634,6 +692,8 @@ impl Debug for SndArg {
 
 /// Represents the link status of a slot
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Consist {
     /// Slot is linked up and down
     LogicalMid,
@@ -647,6 +707,8 @@ pub enum Consist {
 
 /// Represents the usage status of a slot
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum State {
     /// Indicates that this slot is in use by some device. The slot holds a loc address and is refreshed.
     ///
@@ -662,6 +724,8 @@ pub enum State {
 
 /// Represents the decoders speed control message format used
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum DecoderType {
     /// 28 step decoder with advanced DCC allowed
     Dcc28,
@@ -675,10 +739,75 @@ pub enum DecoderType {
     Step14,
     /// 128 speed mode packets
     Speed128,
+    /// A decoder type reserved by the protocol.
+    /// Some command stations report it for decoders they do not know.
+    Reserved(ReservedDecoderType),
+}
+
+impl DecoderType {
+    /// Parses the decoder type bits of a model railroad formatted `stat1` byte,
+    /// keeping reserved ones as [`DecoderType::Reserved`].
+    fn parse(stat1: u8) -> Self {
+        match stat1 & 0x07 {
+            0x02 => DecoderType::Step14,
+            0x01 => DecoderType::AdrMobile28,
+            0x00 => DecoderType::Regular28,
+            0x03 => DecoderType::Speed128,
+            0x07 => DecoderType::Dcc128,
+            0x04 => DecoderType::Dcc28,
+            0x05 => DecoderType::Reserved(ReservedDecoderType::Code5),
+            // Only 0x06 remains
+            _ => DecoderType::Reserved(ReservedDecoderType::Code6),
+        }
+    }
+}
+
+/// The decoder types reserved by the protocol, see [`DecoderType::Reserved`].
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ReservedDecoderType {
+    /// The reserved decoder type `0x05`
+    Code5,
+    /// The reserved decoder type `0x06`
+    Code6,
+}
+
+impl ReservedDecoderType {
+    /// # Returns
+    ///
+    /// The raw value of the decoder type bits, `0x05` or `0x06`.
+    pub fn raw(&self) -> u8 {
+        match *self {
+            ReservedDecoderType::Code5 => 0x05,
+            ReservedDecoderType::Code6 => 0x06,
+        }
+    }
+}
+
+/// Parses the decoder type bits of a `stat1` byte, the other bits are ignored.
+///
+/// # Errors
+///
+/// [`MessageParseError::InvalidFormat`]: If the decoder type is reserved by the protocol
+impl TryFrom<u8> for DecoderType {
+    type Error = MessageParseError;
+
+    fn try_from(stat1: u8) -> Result<Self, Self::Error> {
+        match DecoderType::parse(stat1) {
+            DecoderType::Reserved(reserved) => Err(MessageParseError::invalid_format(
+                format!("The decoder type {:#04x} is reserved!", reserved.raw()),
+                0,
+            )),
+            decoder_type => Ok(decoder_type),
+        }
+    }
 }
 
 /// Holds general slot status information.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Stat1Arg {
     /// The slots purge status.
     s_purge: bool,
@@ -732,15 +861,7 @@ impl Stat1Arg {
             _ => State::Free,
         };
 
-        let decoder_type = match stat1 & 0x07 {
-            0x02 => DecoderType::Step14,
-            0x01 => DecoderType::AdrMobile28,
-            0x00 => DecoderType::Regular28,
-            0x03 => DecoderType::Speed128,
-            0x07 => DecoderType::Dcc128,
-            0x04 => DecoderType::Dcc28,
-            _ => panic!("The given decoder type was invalid!"),
-        };
+        let decoder_type = DecoderType::parse(stat1);
 
         Stat1Arg {
             s_purge,
@@ -803,14 +924,34 @@ impl Stat1Arg {
             DecoderType::AdrMobile28 => 0x01,
             DecoderType::Step14 => 0x02,
             DecoderType::Speed128 => 0x03,
+            DecoderType::Reserved(reserved) => reserved.raw(),
         };
 
         stat1
     }
 }
 
+/// Parses a model railroad formatted `stat1` byte like it is read from a message,
+/// but rejects the decoder types reserved by the protocol.
+///
+/// # Errors
+///
+/// [`MessageParseError::InvalidFormat`]: If the decoder type is reserved by the protocol
+impl TryFrom<u8> for Stat1Arg {
+    type Error = MessageParseError;
+
+    fn try_from(stat1: u8) -> Result<Self, Self::Error> {
+        DecoderType::try_from(stat1)?;
+        Ok(Stat1Arg::parse(stat1))
+    }
+}
+
 /// Extension part for the slot status holding some additional slot information
+///
+/// Equality and hashing include the [`Stat2Arg::reserved()`] bits. A parsed arg with reserved
+/// bits set therefore differs from its [`Stat2Arg::new()`] counterpart.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Stat2Arg {
     /// If slots ADV consist is suppressed
     has_adv: bool,
@@ -818,6 +959,8 @@ pub struct Stat2Arg {
     no_id_usage: bool,
     /// If this ID is no encoded alias
     id_encoded_alias: bool,
+    /// The `stat2` bits not interpreted by this arg, kept for bit exact re-encoding.
+    reserved: u8,
 }
 
 impl Stat2Arg {
@@ -833,6 +976,7 @@ impl Stat2Arg {
             has_adv,
             no_id_usage,
             id_encoded_alias,
+            reserved: 0x00,
         }
     }
 
@@ -848,6 +992,7 @@ impl Stat2Arg {
             has_adv,
             no_id_usage,
             id_encoded_alias,
+            reserved: stat2 & 0x72,
         }
     }
 
@@ -872,11 +1017,21 @@ impl Stat2Arg {
         self.id_encoded_alias
     }
 
+    /// # Returns
+    ///
+    /// The reserved `stat2` bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+
     /// # Returns
     ///
     /// The values hold by this argument as one byte
     pub(crate) fn stat2(&self) -> u8 {
-        let mut stat2 = if self.has_adv { 0x01 } else { 0x00 };
+        let mut stat2 = self.reserved;
+        if self.has_adv {
+            stat2 |= 0x01;
+        }
         if self.no_id_usage {
             stat2 |= 0x04;
         }
@@ -889,6 +1044,7 @@ impl Stat2Arg {
 
 /// Represents a copy of the operation code with the highest bit erased
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LopcArg(u8);
 
 impl LopcArg {
@@ -927,6 +1083,7 @@ impl LopcArg {
 
 /// Holds a response code for a before received message
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Ack1Arg(u8);
 
 impl Ack1Arg {
@@ -997,7 +1154,7 @@ impl Ack1Arg {
 }
 
 impl Display for Ack1Arg {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         if self.failed() {
             write!(f, "ack1: (failed)")
         } else if self.accepted() {
@@ -1010,8 +1167,67 @@ impl Display for Ack1Arg {
     }
 }
 
+/// Interprets the acknowledgment answering a [`Message::ImmPacket`].
+///
+/// The command station answers with the count of packets its queue is still able to hold.
+/// `0x00` means the queue is full and the packet was rejected,
+/// `0x7F` means the packet was accepted without reporting a limit.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ImmPacketAck(Ack1Arg);
+
+impl ImmPacketAck {
+    /// Interprets `ack` as answer to an immediate packet.
+    pub fn new(ack: Ack1Arg) -> Self {
+        ImmPacketAck(ack)
+    }
+
+    /// # Returns
+    ///
+    /// The interpreted acknowledgment
+    pub fn ack(&self) -> Ack1Arg {
+        self.0
+    }
+
+    /// # Returns
+    ///
+    /// If the immediate packet was accepted
+    pub fn accepted(&self) -> bool {
+        !self.0.failed()
+    }
+
+    /// # Returns
+    ///
+    /// How many more packets the command stations queue is able to hold,
+    /// or `None` if the command station reported no limit.
+    pub fn remaining_capacity(&self) -> Option<u8> {
+        if self.0.success() {
+            None
+        } else {
+            Some(self.0.ack1())
+        }
+    }
+}
+
+impl From<Ack1Arg> for ImmPacketAck {
+    fn from(ack: Ack1Arg) -> Self {
+        ImmPacketAck::new(ack)
+    }
+}
+
+impl Display for ImmPacketAck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self.remaining_capacity() {
+            None => write!(f, "imm_packet_ack: (accepted)"),
+            Some(0) => write!(f, "imm_packet_ack: (rejected)"),
+            Some(capacity) => write!(f, "imm_packet_ack: (accepted, remaining: {})", capacity),
+        }
+    }
+}
+
 /// Indicates which source type the input came from
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SourceType {
     /// Switch is connected over a DS54 port
     Ds54Aux,
@@ -1021,6 +1237,8 @@ pub enum SourceType {
 
 /// A sensors detection state
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SensorLevel {
     /// The sensor detects some energy flow (sensor on)
     High,
@@ -1028,7 +1246,7 @@ pub enum SensorLevel {
     Low,
 }
 
-impl std::ops::Not for SensorLevel {
+impl core::ops::Not for SensorLevel {
     type Output = SensorLevel;
 
     fn not(self) -> Self::Output {
@@ -1041,6 +1259,7 @@ impl std::ops::Not for SensorLevel {
 
 /// Represents an sensor input argument
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct InArg {
     /// The sensors argument
     address: u16,
@@ -1219,6 +1438,7 @@ impl InArg {
 
 /// Metainformation for a device
 #[derive(Copy, Clone, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SnArg {
     /// The devices meta information by device type
     /// - 0: Device address
@@ -1317,6 +1537,7 @@ impl SnArg {
 /// - 00/02 - 3F/83: System reserved
 /// - 00/04 - 3F/FE: normal throttle range
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct IdArg(u16);
 
 impl IdArg {
@@ -1358,6 +1579,7 @@ impl IdArg {
 
 /// Represents power information for a specific railway sector
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct MultiSenseArg {
     /// This messages three bit represented type
     m_type: u8,
@@ -1449,6 +1671,7 @@ impl MultiSenseArg {
 
 /// The functions group
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum FunctionGroup {
     /// Function bits 9, 10 and 11 are available
     F9TO11,
@@ -1465,6 +1688,7 @@ pub enum FunctionGroup {
 /// - 0: The functions group type
 /// - 1: The functions bits set
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct FunctionArg(u8, u8);
 
 impl FunctionArg {
@@ -1586,7 +1810,7 @@ impl FunctionArg {
 /// Overriding debug to only display the relevant function bits.
 impl Debug for FunctionArg {
     /// Prints the group corresponding function bit values.
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self.function_group() {
             FunctionGroup::F9TO11 => {
                 write!(
@@ -1638,6 +1862,144 @@ impl Debug for FunctionArg {
     }
 }
 
+/// The kind of receiver reporting the throttles attached to it
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ReceiverType {
+    /// An UR90 infrared receiver
+    Ur90,
+    /// An UR91 simplex radio receiver
+    Ur91,
+    /// An UR92 duplex radio and infrared receiver
+    Ur92,
+    /// A receiver not known to this implementation, holding its raw value
+    Unknown(u8),
+}
+
+impl ReceiverType {
+    /// Parses the receiver type from its byte `receiver`.
+    pub(crate) fn parse(receiver: u8) -> Self {
+        match receiver {
+            0x10 => ReceiverType::Ur90,
+            0x20 => ReceiverType::Ur91,
+            0x40 => ReceiverType::Ur92,
+            receiver => ReceiverType::Unknown(receiver),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The byte representing this receiver type.
+    pub(crate) fn receiver(&self) -> u8 {
+        match *self {
+            ReceiverType::Ur90 => 0x10,
+            ReceiverType::Ur91 => 0x20,
+            ReceiverType::Ur92 => 0x40,
+            ReceiverType::Unknown(receiver) => receiver & 0x7F,
+        }
+    }
+}
+
+/// The status of a throttle reported by the receiver it is attached to.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ThrottleStatusArg {
+    /// The receiver reporting the throttle
+    receiver: ReceiverType,
+    /// The 14 bit id of the throttle
+    throttle_id: u16,
+    /// The raw status bits of the throttle
+    status: u8,
+}
+
+impl ThrottleStatusArg {
+    /// Creates a new throttle status.
+    ///
+    /// # Parameters
+    ///
+    /// - `receiver`: The receiver reporting the throttle
+    /// - `throttle_id`: The id of the throttle, only the lower 14 bits are used
+    /// - `battery_low`: Whether the battery of the throttle is low
+    /// - `semaphore`: Whether the throttle holds the semaphore of the receiver
+    pub fn new(
+        receiver: ReceiverType,
+        throttle_id: u16,
+        battery_low: bool,
+        semaphore: bool,
+    ) -> Self {
+        ThrottleStatusArg {
+            receiver,
+            throttle_id: throttle_id & 0x3FFF,
+            status: (battery_low as u8) | ((semaphore as u8) << 1),
+        }
+    }
+
+    /// Parses the throttle status from the bytes `receiver`, `id1`, `id2` and `status`.
+    pub(crate) fn parse(receiver: u8, id1: u8, id2: u8, status: u8) -> Self {
+        ThrottleStatusArg {
+            receiver: ReceiverType::parse(receiver),
+            throttle_id: ((id2 as u16 & 0x7F) << 7) | (id1 as u16 & 0x7F),
+            status: status & 0x7F,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The receiver reporting the throttle.
+    pub fn receiver(&self) -> ReceiverType {
+        self.receiver
+    }
+
+    /// # Returns
+    ///
+    /// The 14 bit id of the throttle.
+    pub fn throttle_id(&self) -> u16 {
+        self.throttle_id
+    }
+
+    /// # Returns
+    ///
+    /// Whether the battery of the throttle is low.
+    pub fn battery_low(&self) -> bool {
+        self.status & 0x01 != 0
+    }
+
+    /// # Returns
+    ///
+    /// Whether the throttle holds the semaphore of the receiver, so it is allowed to transmit.
+    pub fn semaphore(&self) -> bool {
+        self.status & 0x02 != 0
+    }
+
+    /// # Returns
+    ///
+    /// The raw status bits of the throttle, including bits not interpreted by this implementation.
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// # Returns
+    ///
+    /// The first byte of the receiver type.
+    pub(crate) fn receiver_byte(&self) -> u8 {
+        self.receiver.receiver()
+    }
+
+    /// # Returns
+    ///
+    /// The low seven bits of the throttle id.
+    pub(crate) fn id1(&self) -> u8 {
+        (self.throttle_id & 0x7F) as u8
+    }
+
+    /// # Returns
+    ///
+    /// The high seven bits of the throttle id.
+    pub(crate) fn id2(&self) -> u8 {
+        ((self.throttle_id >> 7) & 0x7F) as u8
+    }
+}
+
 /// Representing the command mode used to write to the programming track
 ///
 /// # Type Codes Table
@@ -1646,12 +2008,18 @@ impl Debug for FunctionArg {
 /// |-------------------|------------------|-------------|-------------|---------------------------------|
 /// | 0                 | 0                | 0           | 0           | Abort operation                 |
 /// | 1                 | 0                | 0           | 0           | Paged mode                      |
-/// | x                 | 0                | 0           | 1           | Direct mode                     |
-/// | x                 | 0                | 1           | 0           | Physical register               |
+/// | x                 | 0                | 1           | 0           | Direct mode                     |
+/// | x                 | 0                | 0           | 1           | Physical register               |
 /// | x                 | 0                | 1           | 1           | service track reserved function |
-/// | x                 | 1                | 0           | 0           | no feedback                     |
-/// | x                 | 1                | 0           | 0           | feedback                        |
+/// | x                 | 1                | 0           | x           | no feedback                     |
+/// | x                 | 1                | 1           | x           | feedback                        |
+///
+/// In the `pcmd` byte [Pcmd::write] is bit `0x40`, [Pcmd::byte_mode] bit `0x20`,
+/// [Pcmd::ty1] bit `0x10`, [Pcmd::ty0] bit `0x08` and [Pcmd::ops_mode] bit `0x04`.
+/// The remaining [Pcmd::reserved] bits are compared and hashed as well, so a parsed `pcmd`
+/// with reserved bits set is not equal to the one created by [Pcmd::new] for the same mode.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Pcmd {
     /// Whether to write or if `false` read
     write: bool,
@@ -1663,6 +2031,8 @@ pub struct Pcmd {
     ty0: bool,
     /// Second programming type select bit
     ty1: bool,
+    /// The `pcmd` bits not interpreted by this arg, kept for bit exact re-encoding.
+    reserved: u8,
 }
 
 impl Pcmd {
@@ -1684,16 +2054,17 @@ impl Pcmd {
             ops_mode,
             ty0,
             ty1,
+            reserved: 0x00,
         }
     }
 
     /// Reads the programming control information from one byte
     pub(crate) fn parse(pcmd: u8) -> Self {
-        let write = pcmd & 0x20 == 0x20;
-        let byte_mode = pcmd & 0x40 == 0x40;
-        let ops_mode = pcmd & 0x02 == 0x02;
-        let ty0 = pcmd & 0x80 == 0x80;
-        let ty1 = pcmd & 0x01 == 0x01;
+        let write = pcmd & 0x40 == 0x40;
+        let byte_mode = pcmd & 0x20 == 0x20;
+        let ops_mode = pcmd & 0x04 == 0x04;
+        let ty0 = pcmd & 0x08 == 0x08;
+        let ty1 = pcmd & 0x10 == 0x10;
 
         Pcmd {
             write,
@@ -1701,6 +2072,7 @@ impl Pcmd {
             ops_mode,
             ty0,
             ty1,
+            reserved: pcmd & 0x03,
         }
     }
 
@@ -1735,6 +2107,13 @@ impl Pcmd {
         self.ty1
     }
 
+    /// # Returns
+    ///
+    /// The reserved `pcmd` bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+
     /// Sets the write argument
     ///
     /// # Parameters
@@ -1776,25 +2155,32 @@ impl Pcmd {
     ///
     /// Parses the programming information data into one representing byte
     pub(crate) fn pcmd(&self) -> u8 {
-        let mut pcmd = if self.write { 0x20 } else { 0x00 };
-        if self.byte_mode {
+        let mut pcmd = self.reserved;
+        if self.write {
             pcmd |= 0x40;
         }
+        if self.byte_mode {
+            pcmd |= 0x20;
+        }
         if self.ops_mode {
-            pcmd |= 0x02;
+            pcmd |= 0x04;
         }
         if self.ty0 {
-            pcmd |= 0x80;
+            pcmd |= 0x08;
         }
         if self.ty1 {
-            pcmd |= 0x01;
+            pcmd |= 0x10;
         }
         pcmd
     }
 }
 
 /// Holding programming error flags
+///
+/// Equality and hashing include the [`PStat::reserved()`] bits. A parsed arg with reserved bits
+/// set therefore differs from its [`PStat::new()`] counterpart.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PStat {
     /// User canceled operation
     user_aborted: bool,
@@ -1804,6 +2190,8 @@ pub struct PStat {
     no_write_ack: bool,
     /// No train on the programming track to programm
     programming_track_empty: bool,
+    /// The status bits not interpreted by this arg, kept for bit exact re-encoding.
+    reserved: u8,
 }
 
 impl PStat {
@@ -1826,6 +2214,7 @@ impl PStat {
             no_read_ack,
             no_write_ack,
             programming_track_empty,
+            reserved: 0x00,
         }
     }
 
@@ -1841,6 +2230,7 @@ impl PStat {
             no_read_ack,
             no_write_ack,
             programming_track_empty,
+            reserved: stat & 0x70,
         }
     }
 
@@ -1872,11 +2262,21 @@ impl PStat {
         self.programming_track_empty
     }
 
+    /// # Returns
+    ///
+    /// The reserved status bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+
     /// # Returns
     ///
     /// A byte representing all found error states
     pub(crate) fn stat(&self) -> u8 {
-        let mut stat = if self.user_aborted { 0x01 } else { 0x00 };
+        let mut stat = self.reserved;
+        if self.user_aborted {
+            stat |= 0x01;
+        }
         if self.no_read_ack {
             stat |= 0x02;
         }
@@ -1891,13 +2291,21 @@ impl PStat {
 }
 
 /// Holds control variables and data arguments.
+///
+/// - 0: The cv bits
+/// - 1: The data bits
+/// - 2: The reserved `cvh` bits, kept for bit exact re-encoding
+///
+/// The reserved bits are compared and hashed as well, so a parsed arg with reserved bits set
+/// is not equal to the one built by [`CvDataArg::new()`] with the same cv and data.
 #[derive(Copy, Clone, Eq, Hash, PartialEq, Default)]
-pub struct CvDataArg(u16, u8);
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CvDataArg(u16, u8, u8);
 
 impl CvDataArg {
     /// Creates a new empty arg.
     pub fn new() -> CvDataArg {
-        CvDataArg(0, 0)
+        CvDataArg(0, 0, 0)
     }
 
     /// Parses cv and data from three byte
@@ -1910,7 +2318,7 @@ impl CvDataArg {
 
         cv_arg |= (high_cv_arg as u16) << 7;
 
-        CvDataArg(cv_arg, data)
+        CvDataArg(cv_arg, data, cvh & 0x4C)
     }
 
     /// # Parameters
@@ -1971,14 +2379,21 @@ impl CvDataArg {
         self
     }
 
+    /// # Returns
+    ///
+    /// The reserved `cvh` bits received with this arg. Zero for self created args.
+    pub fn reserved(&self) -> u8 {
+        self.2
+    }
+
     /// # Returns
     ///
     /// The high part of the cv values and the seventh data bit as one byte
     pub(crate) fn cvh(&self) -> u8 {
         let mut cvh = (self.0 >> 7) as u8;
-        let high_cv = cvh & 0x06 << 3;
+        let high_cv = (cvh & 0x06) << 3;
         cvh &= 0x01;
-        cvh |= high_cv;
+        cvh |= high_cv | self.2;
         if self.data(7) {
             cvh |= 0x02;
         }
@@ -2003,7 +2418,7 @@ impl CvDataArg {
 /// Overridden for precise value orientated output
 impl Debug for CvDataArg {
     /// Writes all args and cv values to the formatter
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "cv_data_arg: (data: (d0: {}, d1: {}, d2: {}, d3: {}, d4: {}, d5: {}, d6: {}, d7: {}), cv: (cv0: {}, cv1: {}, cv2: {}, cv3: {}, cv4: {}, cv5: {}, cv6: {}, cv7: {}, cv8: {}, cv9: {}))",
@@ -2031,6 +2446,7 @@ impl Debug for CvDataArg {
 
 /// Holding the clocks information
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct FastClock {
     /// The clocks tick rate. (0 = Frozen), (x = x to 1 rate),
     clk_rate: u8,
@@ -2068,6 +2484,26 @@ impl FastClock {
         }
     }
 
+    /// Creates the clock information of a clock showing the given time, marked as valid.
+    ///
+    /// # Parameters
+    ///
+    /// - `clock_rate`: The clocks tick rate. (0 = Frozen), (x = x to 1 rate)
+    /// - `days`: The number of 24 hour cycles passed
+    /// - `hour`: The hour of the day (0 - 23)
+    /// - `minute`: The minute of the hour (0 - 59)
+    pub fn at_time(clk_rate: u8, days: u8, hour: u8, minute: u8) -> Self {
+        FastClock {
+            clk_rate: clk_rate & 0x7F,
+            frac_mins: 0,
+            mins: 0x7F - (60 - minute % 60) % 60,
+            hours: (128 - (24 - hour % 24) % 24) & 0x7F,
+            days: days & 0x7F,
+            // Bit 6 marks the clock data as valid
+            clk_cntrl: 0x40,
+        }
+    }
+
     /// Calculates the clock information from 7 bytes
     ///
     /// # Parameters
@@ -2147,6 +2583,20 @@ impl FastClock {
         self.days
     }
 
+    /// # Returns
+    ///
+    /// The hour of the day (0 - 23) decoded from [`FastClock::hours()`].
+    pub fn hour(&self) -> u8 {
+        (24 - ((256 - self.hours as u16) & 0x7F) as u8 % 24) % 24
+    }
+
+    /// # Returns
+    ///
+    /// The minute of the hour (0 - 59) decoded from [`FastClock::mins()`].
+    pub fn minute(&self) -> u8 {
+        (60 - ((255 - self.mins) & 0x7F) % 60) % 60
+    }
+
     /// # Returns
     ///
     /// General clock control information.
@@ -2159,6 +2609,7 @@ impl FastClock {
 
 /// The function bits accessible by the corresponding [ImArg]
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ImFunctionType {
     /// Functions 9 to 12 (inclusive) are accessible
     F9to12,
@@ -2170,6 +2621,7 @@ pub enum ImFunctionType {
 
 /// The address in the right format used by the corresponding [ImArg]
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ImAddress {
     /// A short 8 bit address
     Short(u8),
@@ -2178,7 +2630,11 @@ pub enum ImAddress {
 }
 
 /// This arg hold function bit information
+///
+/// Immediate packets not setting functions, like the ones built by [`crate::dcc::DccPacket`],
+/// are kept as they were received, see [`crate::dcc::DccPacket::from_im_arg()`] to decode them.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ImArg {
     /// I don't get the concrete meaning and functionality of this arg
     dhi: u8,
@@ -2190,6 +2646,8 @@ pub struct ImArg {
     function_bits: u8,
     /// Unused for now, do what you want
     im5: u8,
+    /// The reps and the first four im bytes, if they do not set functions
+    raw: Option<[u8; 5]>,
 }
 
 impl ImArg {
@@ -2208,6 +2666,7 @@ impl ImArg {
             function_type,
             function_bits: 0x00,
             im5,
+            raw: None,
         }
     }
 
@@ -2229,7 +2688,27 @@ impl ImArg {
         im4: u8,
         im5: u8,
     ) -> ImArg {
-        if reps == 0x44 || (reps == 0x34 && (im3 & 0x20) == 0x20) {
+        let mut im_arg = Self::parse_functions(reps, dhi, im1, im2, im3, im4, im5);
+
+        // Packets not setting functions would not be written back the same
+        let raw = [reps, im1, im2, im3, im4];
+        if [
+            im_arg.reps(),
+            im_arg.im1(),
+            im_arg.im2(),
+            im_arg.im3(),
+            im_arg.im4(),
+        ] != raw
+        {
+            im_arg.raw = Some(raw);
+        }
+        im_arg
+    }
+
+    /// Interprets the bytes of an immediate packet as setting functions.
+    fn parse_functions(reps: u8, dhi: u8, im1: u8, im2: u8, im3: u8, im4: u8, im5: u8) -> ImArg {
+        // Short addresses set the function group of functions 13 to 28 in im2
+        if reps == 0x44 || (reps == 0x34 && !matches!(im2, 0x5E | 0x5F) && (im3 & 0x20) == 0x20) {
             let address = ImAddress::Long(((im2 as u16) << 8) | im1 as u16);
 
             let function_type = if im3 == 0x5E {
@@ -2253,6 +2732,7 @@ impl ImArg {
                 function_type,
                 function_bits,
                 im5,
+                raw: None,
             }
         } else {
             let address = ImAddress::Short(im1);
@@ -2267,7 +2747,7 @@ impl ImArg {
             let mut function_bits = match function_type {
                 ImFunctionType::F13to20 => im3,
                 ImFunctionType::F21to28 => im3,
-                ImFunctionType::F9to12 => im2 & !0x2F,
+                ImFunctionType::F9to12 => im2 & !0x20,
             };
 
             function_bits &= 0x7F;
@@ -2278,6 +2758,7 @@ impl ImArg {
                 function_type,
                 function_bits,
                 im5,
+                raw: None,
             }
         }
     }
@@ -2286,6 +2767,9 @@ impl ImArg {
     ///
     /// The type of this function arg as one byte
     pub(crate) fn reps(&self) -> u8 {
+        if let Some(raw) = self.raw {
+            return raw[0];
+        }
         match self.address {
             ImAddress::Short(_) => match self.function_type {
                 ImFunctionType::F9to12 => 0x24,
@@ -2332,8 +2816,8 @@ impl ImArg {
     /// The value of the `f_num`s function bit
     pub fn f(&self, f_num: u8) -> bool {
         let dist = match self.function_type {
-            ImFunctionType::F13to20 => 21,
-            ImFunctionType::F21to28 => 13,
+            ImFunctionType::F13to20 => 13,
+            ImFunctionType::F21to28 => 21,
             ImFunctionType::F9to12 => 9,
         };
 
@@ -2342,19 +2826,22 @@ impl ImArg {
 
     /// Sets the `f_num`s function bit to the given value `f`.
     ///
+    /// Packets not setting functions are replaced by the function packet.
+    ///
     /// # Parameters
     ///
     /// - `f_num`: The function bit to set
     /// - `f`: The value to set the function bit to
     pub fn set_f(&mut self, f_num: u8, f: bool) {
         let dist = match self.function_type {
-            ImFunctionType::F13to20 => 21,
-            ImFunctionType::F21to28 => 13,
+            ImFunctionType::F13to20 => 13,
+            ImFunctionType::F21to28 => 21,
             ImFunctionType::F9to12 => 9,
         };
 
         let mask = 0x01 << (f_num - dist);
 
+        self.raw = None;
         if f {
             self.function_bits |= mask;
         } else {
@@ -2366,6 +2853,9 @@ impl ImArg {
     ///
     /// The first function arg
     pub(crate) fn im1(&self) -> u8 {
+        if let Some(raw) = self.raw {
+            return raw[1];
+        }
         match self.address {
             ImAddress::Short(adr) => adr,
             ImAddress::Long(adr) => adr as u8,
@@ -2376,6 +2866,9 @@ impl ImArg {
     ///
     /// The second function arg
     pub(crate) fn im2(&self) -> u8 {
+        if let Some(raw) = self.raw {
+            return raw[2];
+        }
         match self.address {
             ImAddress::Short(_) => match self.function_type {
                 ImFunctionType::F9to12 => (self.function_bits & 0x7F) | 0x20,
@@ -2390,6 +2883,9 @@ impl ImArg {
     ///
     /// The third function arg
     pub(crate) fn im3(&self) -> u8 {
+        if let Some(raw) = self.raw {
+            return raw[3];
+        }
         match self.address {
             ImAddress::Short(_) => {
                 if self.function_type == ImFunctionType::F9to12 {
@@ -2410,10 +2906,15 @@ impl ImArg {
     ///
     /// The fourth function arg
     pub(crate) fn im4(&self) -> u8 {
-        if self.reps() == 0x34 && self.function_type != ImFunctionType::F9to12 {
-            return self.function_bits;
+        if let Some(raw) = self.raw {
+            return raw[4];
+        }
+        match self.address {
+            ImAddress::Long(_) if self.function_type != ImFunctionType::F9to12 => {
+                self.function_bits
+            }
+            _ => 0x00,
         }
-        0x00
     }
 
     /// # Returns
@@ -2426,6 +2927,7 @@ impl ImArg {
 
 /// Holds messages for writing data to slots
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum WrSlDataStructure {
     /// Represents clock sync information
     ///
@@ -2524,45 +3026,40 @@ impl WrSlDataStructure {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message as a sequence of 13 bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum as 13 bytes to `buf`,
+    /// which has to be exactly that long.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         match self {
-            WrSlDataStructure::DataPt(pcmd, adr, trk, cv_data) => {
-                vec![
-                    0xEF,
-                    0x0E,
-                    0x7C,
-                    pcmd.pcmd(),
-                    0x00,
-                    adr.adr2(),
-                    adr.adr1(),
-                    trk.trk_arg(),
-                    cv_data.cvh(),
-                    cv_data.cvl(),
-                    cv_data.data7(),
-                    0x00,
-                    0x00,
-                ]
-            }
-            WrSlDataStructure::DataTime(fast_clock, trk, id) => {
-                vec![
-                    0xEF,
-                    0x0E,
-                    0x7B,
-                    fast_clock.clk_rate(),
-                    fast_clock.frac_minsl(),
-                    fast_clock.frac_minsh(),
-                    fast_clock.mins(),
-                    trk.trk_arg(),
-                    fast_clock.hours(),
-                    fast_clock.days(),
-                    fast_clock.clk_cntrl(),
-                    id.id1(),
-                    id.id2(),
-                ]
-            }
+            WrSlDataStructure::DataPt(pcmd, adr, trk, cv_data) => buf.copy_from_slice(&[
+                0xEF,
+                0x0E,
+                0x7C,
+                pcmd.pcmd(),
+                0x00,
+                adr.adr2(),
+                adr.adr1(),
+                trk.trk_arg(),
+                cv_data.cvh(),
+                cv_data.cvl(),
+                cv_data.data7(),
+                0x00,
+                0x00,
+            ]),
+            WrSlDataStructure::DataTime(fast_clock, trk, id) => buf.copy_from_slice(&[
+                0xEF,
+                0x0E,
+                0x7B,
+                fast_clock.clk_rate(),
+                fast_clock.frac_minsl(),
+                fast_clock.frac_minsh(),
+                fast_clock.mins(),
+                trk.trk_arg(),
+                fast_clock.hours(),
+                fast_clock.days(),
+                fast_clock.clk_cntrl(),
+                id.id1(),
+                id.id2(),
+            ]),
             WrSlDataStructure::DataGeneral(
                 slot,
                 stat1,
@@ -2573,29 +3070,28 @@ impl WrSlDataStructure {
                 trk,
                 sound,
                 id,
-            ) => {
-                vec![
-                    0xEF,
-                    0x0E,
-                    slot.slot(),
-                    stat1.stat1(),
-                    adr.adr1(),
-                    speed.spd(),
-                    dirf.dirf(),
-                    trk.trk_arg(),
-                    stat2.stat2(),
-                    adr.adr2(),
-                    sound.snd(),
-                    id.id1(),
-                    id.id2(),
-                ]
-            }
+            ) => buf.copy_from_slice(&[
+                0xEF,
+                0x0E,
+                slot.slot(),
+                stat1.stat1(),
+                adr.adr1(),
+                speed.spd(),
+                dirf.dirf(),
+                trk.trk_arg(),
+                stat2.stat2(),
+                adr.adr2(),
+                sound.snd(),
+                id.id1(),
+                id.id2(),
+            ]),
         }
     }
 }
 
 /// Lissy IR reports status information
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct LissyIrReport {
     arg1: u8,
     dir: bool,
@@ -2642,10 +3138,9 @@ impl LissyIrReport {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message represented by a vector of seven bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum as seven bytes to `buf`,
+    /// which has to be exactly that long.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         let mut high_unit = ((self.unit >> 7) as u8) & 0x3F;
         if self.dir {
             high_unit |= 0x40;
@@ -2653,9 +3148,9 @@ impl LissyIrReport {
         let low_unit = self.unit as u8 & 0x7F;
         let high_adr = ((self.address >> 7) as u8) & 0x7F;
         let low_adr = self.address as u8 & 0x7F;
-        vec![
+        buf.copy_from_slice(&[
             0xE4, 0x08, self.arg1, high_unit, low_unit, high_adr, low_adr,
-        ]
+        ])
     }
 
     /// # Returns
@@ -2689,6 +3184,7 @@ impl LissyIrReport {
 
 /// Holds report information of a rfid5 report message
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct RFID5Report {
     arg1: u8,
     address: u16,
@@ -2760,13 +3256,12 @@ impl RFID5Report {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message parsed represented by 11 bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum as 11 bytes to `buf`,
+    /// which has to be exactly that long.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         let high_adr = ((self.address >> 7) as u8) & 0x7F;
         let low_adr = (self.address as u8) & 0x7F;
-        vec![
+        buf.copy_from_slice(&[
             0xE4,
             0x0C,
             self.arg1,
@@ -2778,7 +3273,7 @@ impl RFID5Report {
             self.rfid3,
             self.rfid4,
             self.rfid_hi,
-        ]
+        ])
     }
 
     /// # Returns
@@ -2840,6 +3335,7 @@ impl RFID5Report {
 
 /// Holds report information of a rfid7 report message
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct RFID7Report {
     arg1: u8,
     address: u16,
@@ -2921,13 +3417,12 @@ impl RFID7Report {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message represented by 13 bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum as 13 bytes to `buf`,
+    /// which has to be exactly that long.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         let high_adr = ((self.address >> 7) as u8) & 0x7F;
         let low_adr = (self.address as u8) & 0x7F;
-        vec![
+        buf.copy_from_slice(&[
             0xE4,
             0x0E,
             self.arg1,
@@ -2941,7 +3436,7 @@ impl RFID7Report {
             self.rfid5,
             self.rfid6,
             self.rfid_hi,
-        ]
+        ])
     }
 
     /// # Returns
@@ -3017,6 +3512,7 @@ impl RFID7Report {
 
 /// Holds wheel counter report information
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct WheelcntReport {
     arg1: u8,
     unit: u16,
@@ -3068,10 +3564,9 @@ impl WheelcntReport {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message represented by seven bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum as seven bytes to `buf`,
+    /// which has to be exactly that long.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         let mut high_unit = ((self.unit >> 7) as u8) & 0x3F;
         if self.direction {
             high_unit |= 0x40;
@@ -3079,9 +3574,9 @@ impl WheelcntReport {
         let low_unit = self.unit as u8 & 0x7F;
         let high_count = ((self.count >> 7) as u8) & 0x7F;
         let low_count = self.count as u8 & 0x7F;
-        vec![
+        buf.copy_from_slice(&[
             0xE4, 0x08, self.arg1, high_unit, low_unit, high_count, low_count,
-        ]
+        ])
     }
 
     /// # Returns
@@ -3115,6 +3610,7 @@ impl WheelcntReport {
 
 /// Represents a report message
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum RepStructure {
     /// A Lissy IR report
     LissyIrReport(LissyIrReport),
@@ -3136,7 +3632,7 @@ impl RepStructure {
     pub(crate) fn parse(count: u8, args: &[u8]) -> Result<Self, MessageParseError> {
         if args[0] == 0x00 {
             if count != 0x08 {
-                Err(MessageParseError::UnexpectedEnd(0xE4))
+                Err(MessageParseError::unexpected_end(0xE4, 1))
             } else {
                 Ok(Self::LissyIrReport(LissyIrReport::parse(
                     args[0], args[1], args[2], args[3], args[4],
@@ -3144,7 +3640,7 @@ impl RepStructure {
             }
         } else if args[0] == 0x40 {
             if count != 0x08 {
-                Err(MessageParseError::UnexpectedEnd(0xE4))
+                Err(MessageParseError::unexpected_end(0xE4, 1))
             } else {
                 Ok(Self::WheelcntReport(WheelcntReport::parse(
                     args[0], args[1], args[2], args[3], args[4],
@@ -3160,8 +3656,9 @@ impl RepStructure {
                 args[9], args[10],
             )))
         } else {
-            Err(MessageParseError::InvalidFormat(
+            Err(MessageParseError::invalid_format(
                 "The report message (opcode: 0xE4) was in invalid format!".into(),
+                2,
             ))
         }
     }
@@ -3169,6 +3666,7 @@ impl RepStructure {
 
 /// The destination slot to move data to
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct DstArg(u16);
 
 impl DstArg {
@@ -3216,6 +3714,7 @@ impl DstArg {
 
 /// Holds eight movable bytes and peer data
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PxctData {
     pxc: u8,
     d1: u8,
@@ -3233,7 +3732,7 @@ impl PxctData {
     ///
     /// # Parameters
     ///
-    /// - `pxc`: The peer data
+    /// - `pxc`: The peer data (6 bits)
     /// - `d1` - `d8`: The data
     pub fn new(pxc: u8, d1: u8, d2: u8, d3: u8, d4: u8, d5: u8, d6: u8, d7: u8, d8: u8) -> Self {
         PxctData {
@@ -3271,14 +3770,14 @@ impl PxctData {
 
         PxctData {
             pxc,
-            d1: d1 | ((pxct1 & 0x01) << 6),
-            d2: d2 | ((pxct1 & 0x02) << 5),
-            d3: d3 | ((pxct1 & 0x04) << 4),
-            d4: d4 | ((pxct1 & 0x08) << 3),
-            d5: d5 | ((pxct2 & 0x01) << 6),
-            d6: d6 | ((pxct2 & 0x02) << 5),
-            d7: d7 | ((pxct2 & 0x04) << 4),
-            d8: d8 | ((pxct2 & 0x08) << 3),
+            d1: d1 | ((pxct1 & 0x01) << 7),
+            d2: d2 | ((pxct1 & 0x02) << 6),
+            d3: d3 | ((pxct1 & 0x04) << 5),
+            d4: d4 | ((pxct1 & 0x08) << 4),
+            d5: d5 | ((pxct2 & 0x01) << 7),
+            d6: d6 | ((pxct2 & 0x02) << 6),
+            d7: d7 | ((pxct2 & 0x04) << 5),
+            d8: d8 | ((pxct2 & 0x08) << 4),
         }
     }
 
@@ -3291,20 +3790,20 @@ impl PxctData {
 
     /// # Returns
     ///
-    /// The low part of the peer data and one data bit of the first four the data bits
+    /// The low part of the peer data and the most significant bits of the first four data bytes
     pub(crate) fn pxct1(&self) -> u8 {
         let mut pxct1 = (self.pxc & 0x07) << 4;
 
-        if self.d1 & 0x40 == 0x40 {
+        if self.d1 & 0x80 == 0x80 {
             pxct1 |= 0x01;
         }
-        if self.d2 & 0x40 == 0x40 {
+        if self.d2 & 0x80 == 0x80 {
             pxct1 |= 0x02;
         }
-        if self.d3 & 0x40 == 0x40 {
+        if self.d3 & 0x80 == 0x80 {
             pxct1 |= 0x04;
         }
-        if self.d4 & 0x40 == 0x40 {
+        if self.d4 & 0x80 == 0x80 {
             pxct1 |= 0x08;
         }
 
@@ -3313,20 +3812,20 @@ impl PxctData {
 
     /// # Returns
     ///
-    /// The high part of the peer data and one data bit of the last four the data bits
+    /// The high part of the peer data and the most significant bits of the last four data bytes
     pub(crate) fn pxct2(&self) -> u8 {
-        let mut pxct2 = (self.pxc & 0x78) << 1;
+        let mut pxct2 = (self.pxc & 0x38) << 1;
 
-        if self.d5 & 0x40 == 0x40 {
+        if self.d5 & 0x80 == 0x80 {
             pxct2 |= 0x01;
         }
-        if self.d6 & 0x40 == 0x40 {
+        if self.d6 & 0x80 == 0x80 {
             pxct2 |= 0x02;
         }
-        if self.d7 & 0x40 == 0x40 {
+        if self.d7 & 0x80 == 0x80 {
             pxct2 |= 0x04;
         }
-        if self.d8 & 0x40 == 0x40 {
+        if self.d8 & 0x80 == 0x80 {
             pxct2 |= 0x08;
         }
 
@@ -3337,56 +3836,56 @@ impl PxctData {
     ///
     /// The first data byte to move
     pub fn d1(&self) -> u8 {
-        self.d1 & 0x3F
+        self.d1
     }
 
     /// # Returns
     ///
     /// The second data byte to move
     pub fn d2(&self) -> u8 {
-        self.d2 & 0x3F
+        self.d2
     }
 
     /// # Returns
     ///
     /// The third data byte to move
     pub fn d3(&self) -> u8 {
-        self.d3 & 0x3F
+        self.d3
     }
 
     /// # Returns
     ///
     /// The fourth data byte to move
     pub fn d4(&self) -> u8 {
-        self.d4 & 0x3F
+        self.d4
     }
 
     /// # Returns
     ///
     /// The fifth data byte to move
     pub fn d5(&self) -> u8 {
-        self.d5 & 0x3F
+        self.d5
     }
 
     /// # Returns
     ///
     /// The sixth data byte to move
     pub fn d6(&self) -> u8 {
-        self.d6 & 0x3F
+        self.d6
     }
 
     /// # Returns
     ///
     /// The seventh data byte to move
     pub fn d7(&self) -> u8 {
-        self.d7 & 0x3F
+        self.d7
     }
 
     /// # Returns
     ///
     /// The eighth data byte to move
     pub fn d8(&self) -> u8 {
-        self.d8 & 0x3F
+        self.d8
     }
 }
 
@@ -3395,6 +3894,7 @@ impl PxctData {
 /// As I do not now how this message is structured this message bytes is for now open to use.
 /// Please feel free to contribute to provide a more powerful version of this arg
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ProgrammingAbortedArg {
     /// The count of args to write to the message 0x10 or 0x15
     pub arg_len: u8,
@@ -3522,20 +4022,19 @@ impl ProgrammingAbortedArg {
         }
     }
 
-    /// # Returns
-    ///
-    /// This message as a count of bytes
-    pub(crate) fn to_message(self) -> Vec<u8> {
+    /// Writes this message without its checksum to `buf`, which has to be exactly
+    /// as long as the message.
+    pub(crate) fn write_to(self, buf: &mut [u8]) {
         match self.arg_len {
-            0x10 => vec![
+            0x10 => buf.copy_from_slice(&[
                 0xE6, 0x10, self.arg01, self.arg02, self.arg03, self.arg04, self.arg05, self.arg06,
                 self.arg07, self.arg08, self.arg09, self.arg10, self.arg11, self.arg12, self.arg13,
-            ],
-            _ => vec![
+            ]),
+            _ => buf.copy_from_slice(&[
                 0xE6, 0x15, self.arg01, self.arg02, self.arg03, self.arg04, self.arg05, self.arg06,
                 self.arg07, self.arg08, self.arg09, self.arg10, self.arg11, self.arg12, self.arg13,
                 self.arg14, self.arg15, self.arg16, self.arg17, self.arg18,
-            ],
+            ]),
         }
     }
 }
@@ -0,0 +1,220 @@
+use crate::args::Ack1Arg;
+use crate::error::LocoDriveSendingError;
+use crate::protocol::Message;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Instant;
+
+/// How sending a command ended.
+#[derive(Debug, Copy, Clone)]
+pub enum AuditOutcome {
+    /// The command was written without awaiting an acknowledgment.
+    Sent,
+    /// The command station acknowledged the command.
+    Acked(Ack1Arg),
+    /// The command station did not answer or echo the command within the timeout.
+    TimedOut,
+    /// The command failed, like rejected by the command station, blocked or cancelled.
+    Failed(LocoDriveSendingError),
+}
+
+impl AuditOutcome {
+    /// # Returns
+    ///
+    /// The outcome of the `result` of sending a command.
+    fn of(result: &Result<Option<Ack1Arg>, LocoDriveSendingError>) -> Self {
+        match *result {
+            Ok(None) => AuditOutcome::Sent,
+            Ok(Some(ack)) => AuditOutcome::Acked(ack),
+            Err(LocoDriveSendingError::Timeout) => AuditOutcome::TimedOut,
+            Err(err) => AuditOutcome::Failed(err),
+        }
+    }
+}
+
+impl Display for AuditOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditOutcome::Sent => write!(f, "sent"),
+            AuditOutcome::Acked(ack) => write!(f, "acked ({})", ack),
+            AuditOutcome::TimedOut => write!(f, "timed out"),
+            AuditOutcome::Failed(err) => write!(f, "failed ({})", err),
+        }
+    }
+}
+
+/// A command send by a [`crate::loco_controller::LocoDriveController`] or its
+/// [`crate::loco_controller::CommandHandle`]s with its outcome.
+#[derive(Debug, Copy, Clone)]
+pub struct AuditRecord {
+    /// The command that was send.
+    pub message: Message,
+    /// When the command was requested to be send.
+    pub requested_at: SystemTime,
+    /// How long it took from the request until the outcome was known,
+    /// including waiting for other writers, retries and acknowledgments.
+    pub duration: Duration,
+    /// How sending the command ended.
+    pub outcome: AuditOutcome,
+}
+
+/// Formats the record as one line of `key=value` pairs, starting with the seconds
+/// since the unix epoch the command was requested at.
+impl Display for AuditRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let requested_at = self
+            .requested_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "at={:.3} duration_ms={} outcome=\"{}\" message=\"{:?}\"",
+            requested_at.as_secs_f64(),
+            self.duration.as_millis(),
+            self.outcome,
+            self.message
+        )
+    }
+}
+
+/// Receives the [`AuditRecord`] of every command send, configured by
+/// [`crate::loco_controller::LocoDriveControllerBuilder::audit_sink()`].
+///
+/// Records are passed while sending, so sinks should return quickly.
+/// Slow consumers should be fed through an [`UnboundedSender`].
+pub trait AuditSink: Send {
+    /// Stores or forwards the `record`.
+    fn record(&mut self, record: &AuditRecord);
+}
+
+/// Forwards the records to a channel, dropping them if nobody receives them anymore.
+impl AuditSink for UnboundedSender<AuditRecord> {
+    fn record(&mut self, record: &AuditRecord) {
+        let _ = self.send(*record);
+    }
+}
+
+/// Writes each record as line to a writer, like a log file.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::audit::WriteSink;
+/// use locodrive::loco_controller::LocoDriveController;
+/// use std::fs::OpenOptions;
+///
+/// # async fn audit() {
+/// let file = OpenOptions::new().create(true).append(true).open("commands.log").unwrap();
+/// let controller = LocoDriveController::builder("/dev/ttyUSB0", 115200)
+///     .audit_sink(WriteSink::new(file))
+///     .build()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct WriteSink<W: Write + Send> {
+    /// Where to write the lines to
+    writer: W,
+}
+
+impl<W: Write + Send> WriteSink<W> {
+    /// Creates a sink writing the records to `writer`.
+    pub fn new(writer: W) -> Self {
+        WriteSink { writer }
+    }
+
+    /// # Returns
+    ///
+    /// The writer the records are written to.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> AuditSink for WriteSink<W> {
+    fn record(&mut self, record: &AuditRecord) {
+        // Each record is flushed, so it is not lost if the application crashes
+        if let Err(err) = writeln!(self.writer, "{}", record).and_then(|_| self.writer.flush()) {
+            log_error!("Could not write the audit record: {}", err);
+        }
+    }
+}
+
+/// The [`AuditSink`] shared by a controller and its command handles.
+#[derive(Clone)]
+pub(crate) struct AuditLog(Arc<Mutex<Box<dyn AuditSink>>>);
+
+impl AuditLog {
+    /// Creates a log passing the records to `sink`.
+    pub(crate) fn new(sink: impl AuditSink + 'static) -> Self {
+        AuditLog(Arc::new(Mutex::new(Box::new(sink))))
+    }
+
+    /// Starts the record of sending `message`.
+    ///
+    /// # Returns
+    ///
+    /// The pending record, passed to the sink when finished or dropped.
+    pub(crate) fn begin(&self, message: Message) -> PendingAudit<'_> {
+        PendingAudit {
+            log: self,
+            message,
+            requested_at: SystemTime::now(),
+            started: Instant::now(),
+            outcome: None,
+        }
+    }
+}
+
+impl Debug for AuditLog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+/// The record of a command still being send.
+///
+/// A pending record dropped before it was finished, because the future sending the command
+/// was dropped, is recorded as [`LocoDriveSendingError::Cancelled`].
+pub(crate) struct PendingAudit<'a> {
+    /// Where to pass the record to
+    log: &'a AuditLog,
+    /// The command being send
+    message: Message,
+    /// When the command was requested to be send
+    requested_at: SystemTime,
+    /// When the command was requested, to measure its duration
+    started: Instant,
+    /// How sending the command ended, if it ended
+    outcome: Option<AuditOutcome>,
+}
+
+impl PendingAudit<'_> {
+    /// Records the `result` of sending the command.
+    pub(crate) fn finish(mut self, result: &Result<Option<Ack1Arg>, LocoDriveSendingError>) {
+        self.outcome = Some(AuditOutcome::of(result));
+    }
+}
+
+impl Drop for PendingAudit<'_> {
+    fn drop(&mut self) {
+        let record = AuditRecord {
+            message: self.message,
+            requested_at: self.requested_at,
+            duration: self.started.elapsed(),
+            outcome: self
+                .outcome
+                .unwrap_or(AuditOutcome::Failed(LocoDriveSendingError::Cancelled)),
+        };
+        // A sink that panicked before does not stop recording
+        let mut sink = match self.log.0.lock() {
+            Ok(sink) => sink,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        sink.record(&record);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::args::{InArg, SensorLevel, SlotArg, SpeedArg};
+use crate::error::LocoDriveSendingError;
+use crate::fast_clock::{ClockFollower, ClockTime};
+use crate::loco_controller::{CommandHandle, LocoDriveController, LocoDriveReceiver};
+use crate::protocol::Message;
+use crate::subscription::{self, FilteredReceiver};
+use std::future::Future;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{sleep, Duration};
+
+/// How long to wait before checking the layout clock again, while it is not running.
+const CLOCK_RECHECK: Duration = Duration::from_secs(1);
+
+/// Composable async steps to script the automation of a layout.
+///
+/// Each step subscribes to the messages of the controller when it starts,
+/// so only sensor reports received after starting a step are considered.
+/// The sensors are addressed like in the [`crate::layout::LayoutModel`],
+/// as returned by [`InArg::address_ds54()`].
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{SensorLevel, SlotArg, SpeedArg};
+/// use locodrive::automation::Automation;
+/// use locodrive::loco_controller::LocoDriveController;
+///
+/// # async fn shuttle(controller: LocoDriveController) -> Result<(), locodrive::error::LocoDriveSendingError> {
+/// let automation = Automation::new(&controller);
+/// let slot = SlotArg::new(3);
+///
+/// automation.drive_until(slot, SpeedArg::Drive(60), 20, SensorLevel::High).await?;
+/// automation
+///     .after(15, automation.drive_until(slot, SpeedArg::Drive(60), 16, SensorLevel::High))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Automation {
+    /// Writes the commands of the steps
+    handle: CommandHandle,
+    /// The receiver the steps subscribe from
+    messages: LocoDriveReceiver,
+    /// The followed layout clock
+    clock: ClockFollower,
+}
+
+impl Automation {
+    /// Creates the steps controlling the layout with `controller`.
+    /// The layout clock is followed from now on.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        Automation {
+            handle: controller.command_handle(),
+            messages: controller.subscribe(),
+            clock: ClockFollower::follow(controller),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The followed layout clock.
+    pub fn clock(&self) -> &ClockFollower {
+        &self.clock
+    }
+
+    /// Waits until the sensor with `address` reports `level`.
+    ///
+    /// # Returns
+    ///
+    /// The report of the sensor.
+    ///
+    /// # Errors
+    ///
+    /// [`LocoDriveSendingError::IllegalState`] if the controller was dropped while waiting.
+    pub async fn wait_for_sensor(
+        &self,
+        address: u16,
+        level: SensorLevel,
+    ) -> Result<InArg, LocoDriveSendingError> {
+        Self::await_sensor(self.sensors(), address, level).await
+    }
+
+    /// Drives `slot` with `speed` until the sensor with `address` reports `level`,
+    /// then stops it.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::IllegalState`]: If the controller was dropped while driving
+    /// - Any other [`LocoDriveSendingError`] if the speeds could not be send
+    pub async fn drive_until(
+        &self,
+        slot: SlotArg,
+        speed: SpeedArg,
+        address: u16,
+        level: SensorLevel,
+    ) -> Result<(), LocoDriveSendingError> {
+        // Subscribe before driving, so a sensor reporting right away is not missed
+        let sensors = self.sensors();
+        self.handle
+            .send_message(Message::LocoSpd(slot, speed))
+            .await?;
+        Self::await_sensor(sensors, address, level).await?;
+        self.handle
+            .send_message(Message::LocoSpd(slot, SpeedArg::Stop))
+            .await
+    }
+
+    /// Runs `action` after `scale_minutes` passed on the layout clock.
+    ///
+    /// The layout clock has to be synchronized by its clock data first.
+    /// While it is not synchronized or frozen, the waiting does not proceed.
+    ///
+    /// # Returns
+    ///
+    /// The output of `action`.
+    pub async fn after<F: Future>(&self, scale_minutes: u32, action: F) -> F::Output {
+        let mut due: Option<ClockTime> = None;
+        loop {
+            let wait = match (self.clock.time(), self.clock.rate()) {
+                (Some(time), Some(rate)) => {
+                    let due = *due.get_or_insert_with(|| time.add_minutes(scale_minutes));
+                    if time >= due {
+                        break;
+                    }
+                    match rate {
+                        0 => CLOCK_RECHECK,
+                        // The rate may change, so the clock is checked again after waiting
+                        _ => Duration::from_millis(
+                            (due.as_seconds() - time.as_seconds()) * 1000 / rate as u64,
+                        )
+                        .max(Duration::from_millis(10)),
+                    }
+                }
+                _ => CLOCK_RECHECK,
+            };
+            sleep(wait).await;
+        }
+        action.await
+    }
+
+    /// # Returns
+    ///
+    /// A new subscription to the sensor reports.
+    fn sensors(&self) -> FilteredReceiver<InArg> {
+        FilteredReceiver::new(self.messages.resubscribe(), subscription::sensor_event)
+    }
+
+    /// Waits until the sensor with `address` reports `level` on `sensors`.
+    async fn await_sensor(
+        mut sensors: FilteredReceiver<InArg>,
+        address: u16,
+        level: SensorLevel,
+    ) -> Result<InArg, LocoDriveSendingError> {
+        loop {
+            match sensors.recv().await {
+                Ok(sensor)
+                    if sensor.address_ds54() == address && sensor.sensor_level() == level =>
+                {
+                    return Ok(sensor)
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(lost)) => {
+                    log_error!("Sensor reports may be lost, {} messages skipped", lost);
+                }
+                Err(RecvError::Closed) => return Err(LocoDriveSendingError::IllegalState),
+            }
+        }
+    }
+}
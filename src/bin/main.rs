@@ -0,0 +1,458 @@
+//! The `locodrive-monitor` command line tool to watch, send, capture and replay LocoNet traffic.
+//!
+//! ```text
+//! locodrive-monitor [--port <name>] [--baud <rate>] [--names <file>] <command>
+//!
+//! monitor [--only <name>,...] [--no-color]  Prints the decoded traffic live
+//! send <hex bytes> | send <name> [args]      Sends one message
+//! capture -o <file>                          Writes the traffic to a capture file
+//! replay <file> [--offline]                  Sends the captured bus traffic again
+//! traffic [--window <secs>] [--threshold <n>] Prints the top talkers and chatty devices
+//! ```
+//!
+//! Without `--port` the first serial port answering with LocoNet frames is used.
+//! With `--names` the turnouts, sensors and locomotives are named by the TOML or RON
+//! [`NameRegistry`] file, which needs the `config` feature.
+use locodrive::args::{AddressArg, SlotArg, SpeedArg, SwitchArg, SwitchDirection};
+use locodrive::loco_controller::{Direction, LocoDriveController, SendOptions};
+use locodrive::names::{Describe, NameRegistry};
+use locodrive::protocol::Message;
+use locodrive::traffic::{TrafficAnalyzer, TrafficReport};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::process::exit;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval_at, sleep_until, Duration, Instant};
+
+/// The baud rates probed when no port is given.
+const BAUD_CANDIDATES: [u32; 3] = [115200, 57600, 16457];
+
+/// The usage printed on invalid arguments.
+const USAGE: &str =
+    "usage: locodrive-monitor [--port <name>] [--baud <rate>] [--names <file>] <command>
+
+commands:
+    monitor [--only <name>,...] [--no-color]  Prints the decoded traffic live
+    send <hex bytes> | send <name> [args]      Sends one message
+    capture -o <file>                          Writes the traffic to a capture file
+    replay <file> [--offline]                  Sends the captured bus traffic again
+    traffic [--window <secs>] [--threshold <n>] Prints the top talkers and chatty devices
+
+named messages:
+    gpon, gpoff, idle, locoadr <address>, rqsldata <slot>,
+    locospd <slot> <speed>, swreq <address> <straight|curved>";
+
+/// How many op codes and devices are printed by the traffic command.
+const TOP_TALKERS: usize = 10;
+
+/// The ANSI color of invalid frames.
+const RED: &str = "\x1b[31m";
+/// The ANSI color of written frames.
+const GREEN: &str = "\x1b[32m";
+/// The ANSI color of read frames.
+const CYAN: &str = "\x1b[36m";
+/// Resets the ANSI color.
+const RESET: &str = "\x1b[0m";
+
+/// The result of a command, whose error is printed to the user.
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(args).await {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
+
+/// Runs the command given by `args`.
+async fn run(mut args: Vec<String>) -> CliResult<()> {
+    let port = take_option(&mut args, "--port");
+    let baud_rate = take_option(&mut args, "--baud")
+        .map(|baud_rate| baud_rate.parse::<u32>())
+        .transpose()?;
+    let names = load_names(take_option(&mut args, "--names"))?;
+
+    if args.is_empty() {
+        return Err(USAGE.into());
+    }
+    let command = args.remove(0);
+    match command.as_str() {
+        "monitor" => {
+            let only = take_option(&mut args, "--only").map(|only| {
+                only.split(',')
+                    .map(|name| name.trim().to_ascii_lowercase())
+                    .collect::<Vec<String>>()
+            });
+            let color = !take_flag(&mut args, "--no-color");
+            let (tap, mut frames) = broadcast::channel(256);
+            let _controller = connect(port, baud_rate, Some(tap)).await?;
+            let start = Instant::now();
+            loop {
+                match frames.recv().await {
+                    Ok((direction, frame, at)) => {
+                        let offset = at.duration_since(start);
+                        let line = describe(direction, &frame, offset, color, &names);
+                        if matches_filter(&frame, only.as_deref()) {
+                            println!("{}", line);
+                        }
+                    }
+                    Err(RecvError::Lagged(lost)) => eprintln!("{} frames skipped", lost),
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+        "send" => {
+            let message = parse_message(&args)?;
+            let controller = connect(port, baud_rate, None).await?;
+            controller.send_message(message).await?;
+            println!(
+                "{}",
+                describe(
+                    Direction::Tx,
+                    &message.to_message(),
+                    Duration::ZERO,
+                    false,
+                    &names
+                )
+            );
+            Ok(())
+        }
+        "capture" => {
+            let path = take_option(&mut args, "-o").ok_or(USAGE)?;
+            let mut file = File::create(path)?;
+            let (tap, mut frames) = broadcast::channel(256);
+            let _controller = connect(port, baud_rate, Some(tap)).await?;
+            let start = Instant::now();
+            loop {
+                match frames.recv().await {
+                    Ok((direction, frame, at)) => {
+                        writeln!(
+                            file,
+                            "{}",
+                            capture_line(direction, &frame, at.duration_since(start))
+                        )?;
+                        file.flush()?;
+                    }
+                    Err(RecvError::Lagged(lost)) => eprintln!("{} frames not captured", lost),
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+        "replay" => {
+            let offline = take_flag(&mut args, "--offline");
+            let path = args.first().ok_or(USAGE)?;
+            let mut captured = Vec::new();
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(frame) = parse_capture_line(&line?)? {
+                    captured.push(frame);
+                }
+            }
+
+            let mut controller = match offline {
+                true => None,
+                false => Some(connect(port, baud_rate, None).await?),
+            };
+            let start = Instant::now();
+            for (direction, frame, offset) in captured {
+                // The bus traffic read includes the echoes of the written frames,
+                // so only the read frames are written again, with their original timing
+                if let (Some(controller), Direction::Rx) = (&mut controller, direction) {
+                    sleep_until(start + offset).await;
+                    let message = Message::parse(&frame)?;
+                    let options = SendOptions {
+                        allow_reserved_slots: true,
+                        ..SendOptions::default()
+                    };
+                    controller.send_message_with(message, options).await?;
+                }
+                println!("{}", describe(direction, &frame, offset, false, &names));
+            }
+            Ok(())
+        }
+        "traffic" => {
+            let window = take_option(&mut args, "--window")
+                .map(|window| window.parse::<u64>())
+                .transpose()?
+                .map_or(Duration::from_secs(10), Duration::from_secs);
+            let mut analyzer = TrafficAnalyzer::new(window);
+            if let Some(threshold) = take_option(&mut args, "--threshold") {
+                analyzer = analyzer.chatty_threshold(threshold.parse()?);
+            }
+            let (tap, mut frames) = broadcast::channel(256);
+            let _controller = connect(port, baud_rate, Some(tap)).await?;
+            let mut reports = interval_at(Instant::now() + window, window);
+            loop {
+                tokio::select! {
+                    frame = frames.recv() => match frame {
+                        // Only the bus traffic is counted, the written frames are echoed by it
+                        Ok((Direction::Rx, frame, at)) => {
+                            for (device, count) in analyzer.record(&frame, at) {
+                                println!(
+                                    "chatty: {} send {} frames within {:?}",
+                                    device.describe(&names), count, window
+                                );
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(lost)) => eprintln!("{} frames not counted", lost),
+                        Err(RecvError::Closed) => return Ok(()),
+                    },
+                    _ = reports.tick() => {
+                        print!("{}", describe_traffic(&analyzer.report(Instant::now()), &names));
+                    }
+                }
+            }
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Connects to `port` with `baud_rate`, or to the first port answering with LocoNet frames.
+async fn connect(
+    port: Option<String>,
+    baud_rate: Option<u32>,
+    tap: Option<broadcast::Sender<(Direction, Vec<u8>, Instant)>>,
+) -> CliResult<LocoDriveController> {
+    let (port, baud_rate) = match port {
+        Some(port) => (port, baud_rate.unwrap_or(BAUD_CANDIDATES[0])),
+        None => {
+            let baud_candidates = match baud_rate {
+                Some(baud_rate) => vec![baud_rate],
+                None => BAUD_CANDIDATES.to_vec(),
+            };
+            let candidate = LocoDriveController::discover(&baud_candidates)
+                .await
+                .into_iter()
+                .find(|candidate| candidate.answered())
+                .ok_or("no serial port answered with LocoNet frames, use --port")?;
+            eprintln!("using {}", candidate.port_name);
+            (candidate.port_name, candidate.baud_rate.unwrap_or_default())
+        }
+    };
+
+    let mut builder = LocoDriveController::builder(&port, baud_rate);
+    if let Some(tap) = tap {
+        builder = builder.raw_tap(tap);
+    }
+    Ok(builder.build().await?)
+}
+
+/// Loads the names of the file at `path`, or no names without a file.
+fn load_names(path: Option<String>) -> CliResult<NameRegistry> {
+    match path {
+        #[cfg(feature = "config")]
+        Some(path) => Ok(NameRegistry::load(path)?),
+        #[cfg(not(feature = "config"))]
+        Some(_) => Err("loading names needs the config feature".into()),
+        None => Ok(NameRegistry::new()),
+    }
+}
+
+/// # Returns
+///
+/// The line describing the `frame` transferred in `direction` after `offset`,
+/// naming its objects by `names`.
+fn describe(
+    direction: Direction,
+    frame: &[u8],
+    offset: Duration,
+    color: bool,
+    names: &NameRegistry,
+) -> String {
+    let (arrow, color_code) = match (direction, Message::parse(frame)) {
+        (Direction::Rx, Ok(_)) => ("<-", CYAN),
+        (Direction::Tx, Ok(_)) => ("->", GREEN),
+        (Direction::Rx, Err(_)) => ("<-", RED),
+        (Direction::Tx, Err(_)) => ("->", RED),
+    };
+    let decoded = match Message::parse(frame) {
+        Ok(message) => message.describe(names),
+        Err(err) => format!("invalid: {}", err),
+    };
+    let line = format!(
+        "{:>10.3} {} {:<44} {}",
+        offset.as_secs_f64(),
+        arrow,
+        hex(frame),
+        decoded
+    );
+    match color {
+        true => format!("{}{}{}", color_code, line, RESET),
+        false => line,
+    }
+}
+
+/// # Returns
+///
+/// The lines listing the busiest op codes and devices of `report` with their rates,
+/// naming the devices by `names`.
+fn describe_traffic(report: &TrafficReport, names: &NameRegistry) -> String {
+    let mut lines = format!(
+        "{} frames within {:?}, {:.1}/s\n",
+        report.frames,
+        report.window,
+        report.per_second(report.frames)
+    );
+    for (key, count) in report
+        .opcodes
+        .iter()
+        .take(TOP_TALKERS)
+        .chain(report.devices.iter().take(TOP_TALKERS))
+    {
+        let chatty = match report.chatty.iter().any(|(chatty, _)| chatty == key) {
+            true => " chatty",
+            false => "",
+        };
+        lines += &format!(
+            "    {:<16} {:>8} {:>8.1}/s{}\n",
+            key.describe(names),
+            count,
+            report.per_second(*count),
+            chatty
+        );
+    }
+    lines
+}
+
+/// # Returns
+///
+/// If the message of `frame` is named in the filter `only`, or no filter is set.
+fn matches_filter(frame: &[u8], only: Option<&[String]>) -> bool {
+    let only = match only {
+        Some(only) => only,
+        None => return true,
+    };
+    let name = match Message::parse(frame) {
+        Ok(message) => format!("{:?}", message),
+        Err(_) => "invalid".to_string(),
+    };
+    let name = name
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    only.contains(&name)
+}
+
+/// # Returns
+///
+/// The line of the capture file recording `frame` transferred in `direction` after `offset`.
+///
+/// Each line holds the offset in milliseconds, `RX` or `TX` and the bytes in hex.
+fn capture_line(direction: Direction, frame: &[u8], offset: Duration) -> String {
+    let direction = match direction {
+        Direction::Rx => "RX",
+        Direction::Tx => "TX",
+    };
+    format!("{} {} {}", offset.as_millis(), direction, hex(frame))
+}
+
+/// Parses a `line` of a capture file, see [`capture_line()`].
+///
+/// # Returns
+///
+/// The recorded frame, or `None` for empty and comment lines.
+fn parse_capture_line(line: &str) -> CliResult<Option<(Direction, Vec<u8>, Duration)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let mut words = line.split_whitespace();
+    let offset = Duration::from_millis(words.next().ok_or("empty line")?.parse()?);
+    let direction = match words.next() {
+        Some("RX") => Direction::Rx,
+        Some("TX") => Direction::Tx,
+        _ => return Err(format!("no direction in capture line: {}", line).into()),
+    };
+    let frame = words
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    Ok(Some((direction, frame, offset)))
+}
+
+/// Parses the message to send from its hex bytes or its name and arguments.
+fn parse_message(args: &[String]) -> CliResult<Message> {
+    let name = args.first().ok_or(USAGE)?.to_ascii_lowercase();
+    let arg = |index: usize| -> CliResult<u16> {
+        Ok(args
+            .get(index)
+            .ok_or_else(|| format!("{} misses its argument {}", name, index))?
+            .parse()?)
+    };
+    let message = match name.as_str() {
+        "gpon" => Message::GpOn,
+        "gpoff" => Message::GpOff,
+        "idle" => Message::Idle,
+        "locoadr" => Message::LocoAdr(AddressArg::new(arg(1)?)),
+        "rqsldata" => Message::RqSlData(SlotArg::new(arg(1)? as u8)),
+        "locospd" => {
+            let speed = match arg(2)? {
+                0 => SpeedArg::Stop,
+                speed => SpeedArg::Drive(speed as u8),
+            };
+            Message::LocoSpd(SlotArg::new(arg(1)? as u8), speed)
+        }
+        "swreq" => {
+            let direction = match args.get(2).map(|direction| direction.as_str()) {
+                Some("straight") => SwitchDirection::Straight,
+                Some("curved") => SwitchDirection::Curved,
+                _ => return Err("swreq expects straight or curved".into()),
+            };
+            Message::SwReq(SwitchArg::new(arg(1)?, direction, true))
+        }
+        _ => {
+            let frame = args
+                .iter()
+                .map(|byte| u8::from_str_radix(byte, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| format!("unknown message: {}\n\n{}", name, USAGE))?;
+            Message::parse(&frame)?
+        }
+    };
+    Ok(message)
+}
+
+/// # Returns
+///
+/// The bytes of `frame` as whitespace separated hex.
+fn hex(frame: &[u8]) -> String {
+    frame
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Removes the option `name` with its value from `args`.
+///
+/// # Returns
+///
+/// The value of the option, if given.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    match index < args.len() {
+        true => Some(args.remove(index)),
+        false => None,
+    }
+}
+
+/// Removes the flag `name` from `args`.
+///
+/// # Returns
+///
+/// If the flag was given.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
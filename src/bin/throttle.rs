@@ -0,0 +1,412 @@
+//! The `throttle` terminal program driving one locomotive and switching turnouts.
+//!
+//! ```text
+//! throttle --port <name> [--baud <rate>]
+//!
+//! a <address> Enter   Selects the locomotive, acquiring its slot
+//! Up / Down           Speeds up / down by one step, Page Up / Page Down by ten
+//! Left / Right        Drives backwards / forwards
+//! Space / e           Stops / stops immediately
+//! l, F1 - F8          Toggles the light and the functions 1 to 8
+//! t <address> Enter   Selects the turnout
+//! s / c               Switches the turnout straight / curved
+//! q / Esc             Quits
+//! ```
+//!
+//! Every command shows whether the model railroad echoed and acknowledged it,
+//! so the throttle doubles as an end-to-end check of the function mapping and the ack handling.
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use locodrive::args::{AddressArg, DirfArg, SlotArg, SndArg, SpeedArg, SwitchArg, SwitchDirection};
+use locodrive::loco_controller::{LocoDriveController, LocoDriveMessage, SendOptions};
+use locodrive::manager::{Manager, SlotManager, TurnoutTable};
+use locodrive::protocol::Message;
+use locodrive::slot_cache::SlotResolver;
+use std::env;
+use std::error::Error;
+use std::io::{stdout, Write};
+use std::process::exit;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time::Duration;
+
+/// The usage printed on invalid arguments.
+const USAGE: &str = "usage: throttle --port <name> [--baud <rate>]";
+
+/// How long a switch request may be answered with a failed acknowledgment.
+const SWITCH_ACK_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The result of the program, whose error is printed to the user.
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let port = match take_option(&mut args, "--port") {
+        Some(port) => port,
+        None => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+    let baud_rate = match take_option(&mut args, "--baud").map(|baud| baud.parse::<u32>()) {
+        Some(Ok(baud_rate)) => baud_rate,
+        None => 115200,
+        Some(Err(err)) => {
+            eprintln!("error: {}", err);
+            exit(1);
+        }
+    };
+
+    let controller = match LocoDriveController::builder(&port, baud_rate).build().await {
+        Ok(controller) => controller,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            exit(1);
+        }
+    };
+
+    // The terminal is restored however the throttle ends
+    let result = match enable_raw_mode() {
+        Ok(()) => run(controller, &port).await,
+        Err(err) => Err(err.into()),
+    };
+    let _ = execute!(stdout(), Show, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
+
+/// What the typed digits are entered for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Input {
+    /// The address of the locomotive to select
+    Locomotive,
+    /// The address of the turnout to select
+    Turnout,
+}
+
+/// What to do after a key was pressed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Action {
+    /// Acquires the slot of the locomotive with the address
+    Acquire(AddressArg),
+    /// Sends the message with the options
+    Send(Message, SendOptions),
+    /// Ends the throttle
+    Quit,
+}
+
+/// The state of the throttle, fed with the traffic of the model railroad.
+#[derive(Debug, Default)]
+struct Throttle {
+    /// The known state of the slots
+    slots: SlotManager,
+    /// The known direction of the turnouts
+    turnouts: TurnoutTable,
+    /// The selected locomotive and its slot
+    locomotive: Option<(AddressArg, SlotArg)>,
+    /// The selected turnout
+    turnout: Option<u16>,
+    /// The digits typed so far and what they are entered for
+    input: Option<(Input, String)>,
+    /// The outcome of the last command
+    status: String,
+}
+
+impl Throttle {
+    /// Handles a pressed `key`.
+    ///
+    /// # Returns
+    ///
+    /// What to do because of the key, if anything.
+    fn key(&mut self, key: KeyEvent) -> Option<Action> {
+        if let Some((input, digits)) = &mut self.input {
+            match key.code {
+                KeyCode::Char(digit) if digit.is_ascii_digit() => digits.push(digit),
+                KeyCode::Backspace => {
+                    digits.pop();
+                }
+                KeyCode::Enter => {
+                    let (input, address) = (*input, digits.parse::<u16>().ok());
+                    self.input = None;
+                    match (input, address) {
+                        (Input::Locomotive, Some(address)) => {
+                            return Some(Action::Acquire(AddressArg::new(address)))
+                        }
+                        (Input::Turnout, Some(address)) => self.turnout = Some(address),
+                        (_, None) => self.status = "no address entered".to_string(),
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                _ => {}
+            }
+            return None;
+        }
+
+        let slot = self.locomotive.map(|(_, slot)| slot);
+        let state = slot
+            .and_then(|slot| self.slots.slot(slot))
+            .copied()
+            .unwrap_or_default();
+        let speed = state.speed.map_or(0, |speed| speed.get_spd());
+        let mut dirf = state
+            .dirf
+            .unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false));
+        let mut snd = state
+            .snd
+            .unwrap_or_else(|| SndArg::new(false, false, false, false));
+
+        let message = match (key.code, slot) {
+            (KeyCode::Char('q'), _) | (KeyCode::Esc, _) => return Some(Action::Quit),
+            (KeyCode::Char('c'), _) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Some(Action::Quit)
+            }
+            (KeyCode::Char('a'), _) => {
+                self.input = Some((Input::Locomotive, String::new()));
+                return None;
+            }
+            (KeyCode::Char('t'), _) => {
+                self.input = Some((Input::Turnout, String::new()));
+                return None;
+            }
+            (KeyCode::Char(direction @ ('s' | 'c')), _) => {
+                let address = match self.turnout {
+                    Some(address) => address,
+                    None => {
+                        self.status = "select a turnout with t first".to_string();
+                        return None;
+                    }
+                };
+                let direction = match direction {
+                    's' => SwitchDirection::Straight,
+                    _ => SwitchDirection::Curved,
+                };
+                // Switch requests are only answered if they failed
+                let options = SendOptions {
+                    ack_timeout: Some(SWITCH_ACK_TIMEOUT),
+                    ..SendOptions::default()
+                };
+                let request = Message::SwReq(SwitchArg::new(address, direction, true));
+                return Some(Action::Send(request, options));
+            }
+            (_, None) => {
+                self.status = "select a locomotive with a first".to_string();
+                return None;
+            }
+            (KeyCode::Up, Some(slot)) => {
+                Message::LocoSpd(slot, SpeedArg::new(speed.saturating_add(1).min(126)))
+            }
+            (KeyCode::Down, Some(slot)) => {
+                Message::LocoSpd(slot, SpeedArg::new(speed.saturating_sub(1)))
+            }
+            (KeyCode::PageUp, Some(slot)) => {
+                Message::LocoSpd(slot, SpeedArg::new(speed.saturating_add(10).min(126)))
+            }
+            (KeyCode::PageDown, Some(slot)) => {
+                Message::LocoSpd(slot, SpeedArg::new(speed.saturating_sub(10)))
+            }
+            (KeyCode::Char(' '), Some(slot)) => Message::LocoSpd(slot, SpeedArg::Stop),
+            (KeyCode::Char('e'), Some(slot)) => Message::LocoSpd(slot, SpeedArg::EmergencyStop),
+            (KeyCode::Left, Some(slot)) | (KeyCode::Right, Some(slot)) => {
+                dirf.set_dir(key.code == KeyCode::Right);
+                Message::LocoDirf(slot, dirf)
+            }
+            (KeyCode::Char('l'), Some(slot)) => {
+                dirf.set_f(0, !dirf.f(0));
+                Message::LocoDirf(slot, dirf)
+            }
+            // The functions 1 to 4 are set with the direction, 5 to 8 with the sound
+            (KeyCode::F(f_num @ 1..=4), Some(slot)) => {
+                dirf.set_f(f_num, !dirf.f(f_num));
+                Message::LocoDirf(slot, dirf)
+            }
+            (KeyCode::F(f_num @ 5..=8), Some(slot)) => {
+                snd.set_f(f_num, !snd.f(f_num));
+                Message::LocoSnd(slot, snd)
+            }
+            _ => return None,
+        };
+        Some(Action::Send(message, SendOptions::default()))
+    }
+
+    /// Updates the known state of the slots and turnouts by a received `message`.
+    fn handle(&mut self, message: &Message) {
+        self.slots.handle(message);
+        self.turnouts.update(message);
+    }
+
+    /// Draws the throttle to the terminal.
+    fn draw(&self, port: &str) -> CliResult<()> {
+        let mut lines = vec![format!("LocoDrive throttle on {}", port), String::new()];
+
+        match self.locomotive {
+            Some((address, slot)) => {
+                let state = self.slots.slot(slot).copied().unwrap_or_default();
+                lines.push(format!(
+                    "Locomotive  {} in slot {}",
+                    address.address(),
+                    slot.slot()
+                ));
+                lines.push(format!(
+                    "Speed       {} {}",
+                    state
+                        .speed
+                        .map_or("?".to_string(), |speed| format!("{:?}", speed)),
+                    match state.dirf.map(|dirf| dirf.dir()) {
+                        Some(true) => "forwards",
+                        Some(false) => "backwards",
+                        None => "",
+                    }
+                ));
+                let functions = (0..=8)
+                    .map(|f_num| {
+                        let on = match f_num {
+                            0..=4 => state.dirf.map(|dirf| dirf.f(f_num)),
+                            _ => state.snd.map(|snd| snd.f(f_num)),
+                        };
+                        match on {
+                            Some(true) => format!("F{}[x]", f_num),
+                            Some(false) => format!("F{}[ ]", f_num),
+                            None => format!("F{}[?]", f_num),
+                        }
+                    })
+                    .collect::<Vec<String>>();
+                lines.push(format!("Functions   {}", functions.join(" ")));
+            }
+            None => lines.push("Locomotive  none, press a to select one".to_string()),
+        }
+        lines.push(String::new());
+
+        match self.turnout {
+            Some(address) => {
+                lines.push(format!(
+                    "Turnout     {} {}",
+                    address,
+                    self.turnouts.state(address).map_or(
+                        "unknown".to_string(),
+                        |direction| format!("{:?}", direction)
+                    )
+                ))
+            }
+            None => lines.push("Turnout     none, press t to select one".to_string()),
+        }
+        let mut turnouts = self
+            .turnouts
+            .turnouts()
+            .iter()
+            .filter_map(|(address, turnout)| Some((*address, turnout.direction()?)))
+            .collect::<Vec<_>>();
+        turnouts.sort_by_key(|(address, _)| *address);
+        let turnouts = turnouts
+            .iter()
+            .map(|(address, direction)| format!("{}: {:?}", address, direction))
+            .collect::<Vec<String>>();
+        lines.push(format!("Turnouts    {}", turnouts.join(", ")));
+        lines.push(String::new());
+
+        lines.push(match &self.input {
+            Some((Input::Locomotive, digits)) => format!("Locomotive address: {}", digits),
+            Some((Input::Turnout, digits)) => format!("Turnout address: {}", digits),
+            None => String::new(),
+        });
+        lines.push(self.status.clone());
+        lines.push(String::new());
+        lines.push(
+            "a: locomotive  arrows: speed/direction  space/e: stop  l, F1-F8: functions  \
+             t: turnout  s/c: switch  q: quit"
+                .to_string(),
+        );
+
+        let mut out = stdout();
+        queue!(out, Clear(ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(out, MoveTo(0, row as u16), Print(line))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Runs the throttle with `controller` connected to `port` until it is quit.
+async fn run(controller: LocoDriveController, port: &str) -> CliResult<()> {
+    execute!(stdout(), EnterAlternateScreen, Hide)?;
+
+    // The terminal events are read blocking, so they are forwarded from their own thread
+    let (keys_to, mut keys) = unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if keys_to.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let resolver = SlotResolver::new(&controller);
+    let mut messages = controller.subscribe();
+    let mut throttle = Throttle::default();
+
+    loop {
+        throttle.draw(port)?;
+        tokio::select! {
+            event = keys.recv() => {
+                let key = match event {
+                    Some(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                    Some(_) => continue,
+                    None => return Ok(()),
+                };
+                match throttle.key(key) {
+                    Some(Action::Quit) => return Ok(()),
+                    Some(Action::Acquire(address)) => {
+                        throttle.status = format!("acquiring {}", address.address());
+                        throttle.draw(port)?;
+                        throttle.status = match resolver.slot_for(address).await {
+                            Ok(slot) => {
+                                throttle.locomotive = Some((address, slot));
+                                format!("acquired slot {}", slot.slot())
+                            }
+                            Err(err) => format!("acquiring failed: {}", err),
+                        };
+                    }
+                    Some(Action::Send(message, options)) => {
+                        throttle.status = match controller.send_message_acked(message, options).await {
+                            Ok(Some(ack)) => format!("{:?}: acknowledged {}", message, ack),
+                            Ok(None) => format!("{:?}: echoed", message),
+                            Err(err) => format!("{:?}: {}", message, err),
+                        };
+                    }
+                    None => {}
+                }
+            }
+            message = messages.recv() => match message {
+                Ok(LocoDriveMessage::Message(message)) | Ok(LocoDriveMessage::Echo(message)) => {
+                    throttle.handle(&message)
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Removes the option `name` with its value from `args`.
+///
+/// # Returns
+///
+/// The value of the option, if given.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    match index < args.len() {
+        true => Some(args.remove(index)),
+        false => None,
+    }
+}
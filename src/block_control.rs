@@ -0,0 +1,369 @@
+use crate::args::{SlotArg, SpeedArg};
+use crate::loco_controller::{CommandHandle, LocoDriveController, LocoDriveMessage};
+use crate::occupancy::{BlockOccupancy, OccupancyEvent};
+use crate::protocol::Message;
+use crate::signals::{Aspect, SignalSetter};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{sleep_until, Instant};
+
+/// What the [`BlockControl`] does to protect the trains.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ControlAction {
+    /// Shows the aspect on the signal with the name.
+    Aspect(String, Aspect),
+    /// Sets the speed of the train driven by the slot.
+    Speed(SlotArg, SpeedArg),
+}
+
+/// A block controlled by the [`BlockControl`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct ControlledBlock {
+    /// The name of the block
+    name: String,
+    /// The signal protecting the entry of the block
+    signal: Option<String>,
+}
+
+/// A train driving through the controlled blocks.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct Train {
+    /// The slot driving the train
+    slot: SlotArg,
+    /// The speed the train drives with on a clear line
+    speed: SpeedArg,
+}
+
+/// Protects trains driving through blocks by automatic brake control.
+///
+/// The blocks are detected by a [`BlockOccupancy`] and connected by the transitions trains may
+/// drive along. Each block may be protected by a signal at its entry, which shows
+/// [`Aspect::Stop`] while the block is occupied, [`Aspect::Approach`] while a following block
+/// is occupied and [`Aspect::Proceed`] otherwise. A train approaching a signal showing
+/// stop is stopped, one approaching a signal showing approach is slowed down to the approach speed.
+/// Once the line is clear again the train drives on with the speed it was placed with.
+///
+/// Trains are placed in their block by [`BlockControl::place()`] and follow the transitions
+/// to the next block occupied. For blocks with several exits the most restrictive one is
+/// applied, as the turnouts are not tracked.
+///
+/// The control only computes the [`ControlAction`]s like the occupancy computes its events,
+/// [`BlockControl::run()`] applies them to the layout.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{InArg, SensorLevel, SlotArg, SourceType, SpeedArg};
+/// use locodrive::block_control::{BlockControl, ControlAction};
+/// use locodrive::occupancy::{BlockOccupancy, Detector};
+/// use locodrive::protocol::Message;
+/// use locodrive::signals::Aspect;
+/// use std::time::Duration;
+/// use tokio::time::Instant;
+///
+/// let mut occupancy = BlockOccupancy::new().debounce(Duration::ZERO);
+/// occupancy.add_block("east", [Detector::Sensor(6)]);
+/// occupancy.add_block("middle", [Detector::Sensor(4)]);
+/// occupancy.add_block("west", [Detector::Sensor(2)]);
+///
+/// let mut control = BlockControl::new(occupancy);
+/// control.add_block("east", None);
+/// control.add_block("middle", Some("M"));
+/// control.add_block("west", Some("W"));
+/// control.add_transition("east", "middle");
+/// control.add_transition("middle", "west");
+/// control.place("east", SlotArg::new(3), SpeedArg::Drive(80));
+///
+/// // A wagon left in the west block slows the train down
+/// let wagon = InArg::new(1, SourceType::Ds54Aux, SensorLevel::High, false);
+/// let actions = control.handle(&Message::InputRep(wagon), Instant::now());
+/// assert_eq!(
+///     actions,
+///     vec![
+///         ControlAction::Aspect("M".to_string(), Aspect::Approach),
+///         ControlAction::Aspect("W".to_string(), Aspect::Stop),
+///         ControlAction::Speed(SlotArg::new(3), SpeedArg::Drive(30)),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockControl {
+    /// Detects the trains in the blocks
+    occupancy: BlockOccupancy,
+    /// The controlled blocks in the order they were added
+    blocks: Vec<ControlledBlock>,
+    /// The transitions trains may drive along, from one block to the next
+    transitions: Vec<(String, String)>,
+    /// The trains by the block they are in
+    trains: HashMap<String, Train>,
+    /// The highest speed step of trains approaching a signal showing approach
+    approach_speed: u8,
+    /// The aspects last shown by the signals
+    shown: HashMap<String, Aspect>,
+    /// The speeds last set for the trains
+    applied: HashMap<SlotArg, SpeedArg>,
+}
+
+impl BlockControl {
+    /// Creates a control of the blocks detected by `occupancy`, without any controlled block.
+    pub fn new(occupancy: BlockOccupancy) -> Self {
+        BlockControl {
+            occupancy,
+            blocks: Vec::new(),
+            transitions: Vec::new(),
+            trains: HashMap::new(),
+            approach_speed: 30,
+            shown: HashMap::new(),
+            applied: HashMap::new(),
+        }
+    }
+
+    /// Sets the highest speed step of trains approaching a signal showing approach.
+    ///
+    /// Defaults to `30`.
+    pub fn approach_speed(mut self, approach_speed: u8) -> Self {
+        self.approach_speed = approach_speed;
+        self
+    }
+
+    /// Controls the block `name`, protected by the signal named `signal` at its entry.
+    /// The block has to be detected by the occupancy under the same name.
+    pub fn add_block(&mut self, name: &str, signal: Option<&str>) {
+        self.blocks.retain(|block| block.name != name);
+        self.blocks.push(ControlledBlock {
+            name: name.to_string(),
+            signal: signal.map(str::to_string),
+        });
+    }
+
+    /// Allows trains to drive from the block `from` to the block `to`.
+    pub fn add_transition(&mut self, from: &str, to: &str) {
+        self.transitions.push((from.to_string(), to.to_string()));
+    }
+
+    /// Places the train driven by `slot` in the block `name`, driving with `speed` on a clear line.
+    /// A train placed before is moved.
+    ///
+    /// # Returns
+    ///
+    /// The actions protecting the trains after placing the train.
+    pub fn place(&mut self, name: &str, slot: SlotArg, speed: SpeedArg) -> Vec<ControlAction> {
+        self.trains.retain(|_, train| train.slot != slot);
+        self.trains.insert(name.to_string(), Train { slot, speed });
+        self.evaluate()
+    }
+
+    /// Removes the train driven by `slot` from the control, so its speed is not set anymore.
+    pub fn remove(&mut self, slot: SlotArg) {
+        self.trains.retain(|_, train| train.slot != slot);
+        self.applied.remove(&slot);
+    }
+
+    /// # Returns
+    ///
+    /// The block the train driven by `slot` is in, if it is placed.
+    pub fn block_of(&self, slot: SlotArg) -> Option<&str> {
+        self.trains
+            .iter()
+            .find(|(_, train)| train.slot == slot)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// # Returns
+    ///
+    /// When the next change of a block is confirmed, see [`BlockOccupancy::next_deadline()`].
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.occupancy.next_deadline()
+    }
+
+    /// Updates the blocks by one `message` received at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The actions protecting the trains, if they changed.
+    pub fn handle(&mut self, message: &Message, now: Instant) -> Vec<ControlAction> {
+        let events = self.occupancy.handle(message, now);
+        self.follow(events)
+    }
+
+    /// # Returns
+    ///
+    /// The actions protecting the trains after the changes of the blocks confirmed until `now`,
+    /// see [`BlockOccupancy::poll()`].
+    pub fn poll(&mut self, now: Instant) -> Vec<ControlAction> {
+        let events = self.occupancy.poll(now);
+        self.follow(events)
+    }
+
+    /// Moves the trains to the blocks that became occupied by `events`.
+    ///
+    /// # Returns
+    ///
+    /// The actions protecting the trains after the moves.
+    fn follow(&mut self, events: Vec<OccupancyEvent>) -> Vec<ControlAction> {
+        if events.is_empty() {
+            return Vec::new();
+        }
+        for event in events {
+            let to = match event {
+                OccupancyEvent::Entered { block, .. } => block,
+                OccupancyEvent::Left { .. } => continue,
+            };
+            if self.trains.contains_key(&to) {
+                continue;
+            }
+            let from = self
+                .transitions
+                .iter()
+                .find(|(from, next)| *next == to && self.trains.contains_key(from))
+                .map(|(from, _)| from.clone());
+            if let Some(train) = from.and_then(|from| self.trains.remove(&from)) {
+                self.trains.insert(to, train);
+            }
+        }
+        self.evaluate()
+    }
+
+    /// # Returns
+    ///
+    /// If the block `name` is detected as occupied or a train is placed in it.
+    fn occupied(&self, name: &str) -> bool {
+        self.occupancy.is_occupied(name) || self.trains.contains_key(name)
+    }
+
+    /// # Returns
+    ///
+    /// The blocks trains may drive to from the block `name`.
+    fn next(&self, name: &str) -> impl Iterator<Item = &str> {
+        let name = name.to_string();
+        self.transitions
+            .iter()
+            .filter(move |(from, _)| *from == name)
+            .map(|(_, to)| to.as_str())
+    }
+
+    /// # Returns
+    ///
+    /// The aspect protecting the entry of the block `name`.
+    fn aspect(&self, name: &str) -> Aspect {
+        if self.occupied(name) {
+            Aspect::Stop
+        } else if self.next(name).any(|next| self.occupied(next)) {
+            Aspect::Approach
+        } else {
+            Aspect::Proceed
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The actions changing the signals and the speeds of the trains to the current occupancy.
+    fn evaluate(&mut self) -> Vec<ControlAction> {
+        let mut actions = Vec::new();
+        for block in &self.blocks {
+            if let Some(signal) = &block.signal {
+                let aspect = self.aspect(&block.name);
+                if self.shown.get(signal) != Some(&aspect) {
+                    actions.push(ControlAction::Aspect(signal.clone(), aspect));
+                }
+            }
+        }
+
+        let mut speeds: Vec<(SlotArg, SpeedArg)> = self
+            .trains
+            .iter()
+            .map(|(name, train)| {
+                // The most restrictive of the following blocks limits the train
+                let limit = self.next(name).map(|next| self.aspect(next)).fold(
+                    Aspect::Proceed,
+                    |limit, aspect| match (limit, aspect) {
+                        (Aspect::Stop, _) | (_, Aspect::Stop) => Aspect::Stop,
+                        (Aspect::Approach, _) | (_, Aspect::Approach) => Aspect::Approach,
+                        _ => Aspect::Proceed,
+                    },
+                );
+                let speed = match (limit, train.speed) {
+                    (Aspect::Stop, _) => SpeedArg::Stop,
+                    (Aspect::Approach, SpeedArg::Drive(speed)) => {
+                        SpeedArg::Drive(speed.min(self.approach_speed))
+                    }
+                    (_, speed) => speed,
+                };
+                (train.slot, speed)
+            })
+            .filter(|(slot, speed)| self.applied.get(slot) != Some(speed))
+            .collect();
+        speeds.sort_by_key(|(slot, _)| slot.slot());
+        actions.extend(
+            speeds
+                .into_iter()
+                .map(|(slot, speed)| ControlAction::Speed(slot, speed)),
+        );
+
+        for action in &actions {
+            match action {
+                ControlAction::Aspect(signal, aspect) => {
+                    self.shown.insert(signal.clone(), *aspect);
+                }
+                ControlAction::Speed(slot, speed) => {
+                    self.applied.insert(*slot, *speed);
+                }
+            }
+        }
+        actions
+    }
+
+    /// Applies `actions` to the layout, setting the speeds with `handle` and the aspects
+    /// with `signals`. Actions that could not be applied are logged.
+    pub async fn apply(actions: &[ControlAction], handle: &CommandHandle, signals: &SignalSetter) {
+        for action in actions {
+            match action {
+                ControlAction::Aspect(signal, aspect) => {
+                    if let Err(err) = signals.set_aspect(signal, *aspect).await {
+                        log_error!("Could not show {} on signal {}: {}", aspect, signal, err);
+                    }
+                }
+                ControlAction::Speed(slot, speed) => {
+                    let message = Message::LocoSpd(*slot, *speed);
+                    if let Err(err) = handle.send_message(message).await {
+                        log_error!("Could not set the speed of slot {}: {}", slot.slot(), err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Controls the blocks with the messages received by `controller`, until it is dropped.
+    ///
+    /// The control is shared, so trains can be placed and removed while it runs.
+    /// The actions of placing them have to be applied by [`BlockControl::apply()`].
+    pub async fn run(
+        control: Arc<Mutex<BlockControl>>,
+        controller: &LocoDriveController,
+        signals: SignalSetter,
+    ) {
+        let handle = controller.command_handle();
+        let mut messages = controller.subscribe();
+        loop {
+            let deadline = control.lock().unwrap().next_deadline();
+            let actions = tokio::select! {
+                message = messages.recv() => match message {
+                    Ok(LocoDriveMessage::Message(message)) => {
+                        control.lock().unwrap().handle(&message, Instant::now())
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(lost)) => {
+                        log_error!("Block occupancy may be lost, {} messages skipped", lost);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    control.lock().unwrap().poll(Instant::now())
+                }
+            };
+            Self::apply(&actions, &handle, &signals).await;
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::error::{LocoDriveSendingError, MessageParseError};
+use crate::protocol::Message;
+use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::io::{ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long the reading thread waits for a byte before rechecking if it should stop.
+const READ_POLL: Duration = Duration::from_millis(100);
+
+/// The last written message and the notification of it being received back
+type SendSynchronisation = Arc<(Mutex<Option<Message>>, Condvar)>;
+
+/// A blocking variant of the [`crate::loco_controller::LocoDriveController`] for applications
+/// without an async runtime.
+///
+/// The messages are read by a background thread and passed to a [`Sender`].
+/// Sending a message blocks until the message was received back from the model railroad.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{SlotArg, SpeedArg};
+/// use locodrive::blocking::BlockingLocoDriveController;
+/// use locodrive::protocol::Message;
+/// use serialport::FlowControl;
+/// use std::sync::mpsc::channel;
+///
+/// let (sender, receiver) = channel();
+///
+/// let mut controller = BlockingLocoDriveController::new(
+///     "/dev/ttyUSB0",
+///     115_200,
+///     5000,
+///     FlowControl::Software,
+///     sender,
+///     false,
+/// )
+/// .unwrap();
+///
+/// controller
+///     .send_message(Message::LocoSpd(SlotArg::new(1), SpeedArg::Drive(20)))
+///     .unwrap();
+///
+/// for message in receiver {
+///     println!("GOT = {:?}", message);
+/// }
+/// ```
+pub struct BlockingLocoDriveController {
+    /// The port to write to
+    port: Box<dyn SerialPort>,
+    /// The last written message, cleared by the reading thread when received back
+    send: SendSynchronisation,
+    /// Tells the reading thread to stop
+    stop: Arc<AtomicBool>,
+    /// The reading thread
+    reading_thread: Option<JoinHandle<()>>,
+    /// How long to wait on success of sending
+    sending_timeout: u64,
+}
+
+impl BlockingLocoDriveController {
+    /// Connects to the serial port `port_name` and starts a thread reading on that port.
+    ///
+    /// # Parameters
+    ///
+    /// - `port_name`: The port to connect to
+    /// - `baud_rate`: The baud rate to use
+    /// - `sending_timeout`: How long to wait in milliseconds for a send message to be received back
+    /// - `flow_control`: The flow control to use
+    /// - `send_to`: Where to send the read messages and parsing errors
+    /// - `ignore_send_messages`: Whether to not pass messages send by this controller to `send_to`
+    ///
+    /// # Errors
+    ///
+    /// If the serial port is not reachable or could not be configured correctly.
+    pub fn new(
+        port_name: &str,
+        baud_rate: u32,
+        sending_timeout: u64,
+        flow_control: FlowControl,
+        send_to: Sender<Result<Message, MessageParseError>>,
+        ignore_send_messages: bool,
+    ) -> serialport::Result<Self> {
+        let port = serialport::new(port_name, baud_rate)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::Two)
+            .parity(Parity::None)
+            .flow_control(flow_control)
+            .timeout(Duration::from_millis(sending_timeout))
+            .open()?;
+
+        // The reader polls, so it is able to stop
+        let mut reader = port.try_clone()?;
+        reader.set_timeout(READ_POLL)?;
+
+        let send: SendSynchronisation = Arc::new((Mutex::new(None), Condvar::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_send = send.clone();
+        let thread_stop = stop.clone();
+        let reading_thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                let parsed = match Self::read_next_message(&mut *reader, &thread_stop) {
+                    Ok(Some(buf)) => match Message::parse(&buf) {
+                        Ok(message)
+                            if Self::received_back(&thread_send, message)
+                                && ignore_send_messages =>
+                        {
+                            continue
+                        }
+                        parsed => parsed,
+                    },
+                    // We were told to stop
+                    Ok(None) => break,
+                    Err(err) => Err(err),
+                };
+
+                // The receiver was dropped, so nobody is interested in reading anymore
+                if send_to.send(parsed).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(BlockingLocoDriveController {
+            port,
+            send,
+            stop,
+            reading_thread: Some(reading_thread),
+            sending_timeout,
+        })
+    }
+
+    /// Sends a message to the model railroad and blocks until it is received back.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::IllegalState`]: If the reading thread has stopped
+    /// - [`LocoDriveSendingError::NotWritable`]: If writing to the port failed
+    /// - [`LocoDriveSendingError::Timeout`]: If the message was not received back in time
+    pub fn send_message(&mut self, message: Message) -> Result<(), LocoDriveSendingError> {
+        match &self.reading_thread {
+            Some(reading_thread) if !reading_thread.is_finished() => {}
+            _ => return Err(LocoDriveSendingError::IllegalState),
+        }
+
+        let bytes = message.to_message();
+        let (lock, received) = &*self.send;
+
+        // We say the reader which message to expect
+        *lock.lock().unwrap() = Some(message);
+
+        if self.port.write_all(&bytes).is_err() {
+            *lock.lock().unwrap() = None;
+            return Err(LocoDriveSendingError::NotWritable);
+        }
+
+        let (expected, timeout) = received
+            .wait_timeout_while(
+                lock.lock().unwrap(),
+                Duration::from_millis(self.sending_timeout),
+                |expected| expected.is_some(),
+            )
+            .unwrap();
+
+        if timeout.timed_out() && expected.is_some() {
+            drop(expected);
+            *lock.lock().unwrap() = None;
+            Err(LocoDriveSendingError::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// # Return
+    ///
+    /// The port the controller is connected to.
+    pub fn get_port_name(&self) -> Option<String> {
+        self.port.name()
+    }
+
+    /// # Return
+    ///
+    /// The maximum time to wait for a message to be send correctly.
+    pub fn get_sending_timeout(&self) -> u64 {
+        self.sending_timeout
+    }
+
+    /// Checks if `message` is the last written message and notifies the writer if so.
+    ///
+    /// Some interfaces alter the bytes of the echo, so the parsed messages are compared.
+    fn received_back(send: &SendSynchronisation, message: Message) -> bool {
+        let (lock, received) = &**send;
+        let mut expected = lock.lock().unwrap();
+
+        if *expected == Some(message) {
+            *expected = None;
+            received.notify_all();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads the bytes of the next message from the port.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(bytes))`: The bytes of the read message
+    /// - `Ok(None)`: If the thread should stop
+    /// - `Err(err)`: If the message could not be read
+    fn read_next_message(
+        port: &mut dyn SerialPort,
+        stop: &AtomicBool,
+    ) -> Result<Option<Vec<u8>>, MessageParseError> {
+        let mut opc = [0u8; 1];
+
+        // We wait for the next op code, rechecking for stopping
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            match port.read(&mut opc) {
+                Ok(1) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::TimedOut => {}
+                Err(err) => {
+                    // Give the port some time before reading again
+                    std::thread::sleep(READ_POLL);
+                    return Err(err.into());
+                }
+            }
+        }
+        let opc = opc[0];
+
+        if !Message::known_opc(opc) {
+            return Err(MessageParseError::unknown_opcode(opc).with_frame(&[opc]));
+        }
+
+        let mut buf = vec![opc];
+
+        // We calculate the length of the remaining message to read
+        let len = match opc & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            0xE0 => {
+                let mut read_len = [0u8; 1];
+                Self::read_exact(port, &mut read_len, &buf)?;
+                buf.push(read_len[0]);
+                read_len[0] as usize - 1
+            }
+            _ => return Err(MessageParseError::unknown_opcode(opc).with_frame(&[opc])),
+        };
+
+        let mut message = vec![0u8; len - 1];
+        Self::read_exact(port, &mut message, &buf)?;
+        buf.append(&mut message);
+
+        Ok(Some(buf))
+    }
+
+    /// Reads exactly `buf.len()` bytes of the message, whose bytes `read_before` were read already.
+    ///
+    /// The remaining bytes of a message follow directly, so a timeout means the message ended.
+    fn read_exact(
+        port: &mut dyn SerialPort,
+        buf: &mut [u8],
+        read_before: &[u8],
+    ) -> Result<(), MessageParseError> {
+        // The bytes read of the frame, when the message ended
+        let ended = |read: usize, buf: &[u8]| {
+            let frame = [read_before, &buf[..read]].concat();
+            MessageParseError::unexpected_end(read_before[0], frame.len()).with_frame(&frame)
+        };
+        let deadline = Instant::now() + READ_POLL;
+        let mut read = 0;
+
+        while read < buf.len() {
+            match port.read(&mut buf[read..]) {
+                Ok(count) => read += count,
+                Err(err) if err.kind() == ErrorKind::TimedOut => {}
+                Err(_) => return Err(ended(read, buf)),
+            }
+            if read < buf.len() && Instant::now() >= deadline {
+                return Err(ended(read, buf));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Extends standard drop implementation to stop the reading thread.
+impl Drop for BlockingLocoDriveController {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reading_thread) = self.reading_thread.take() {
+            let _ = reading_thread.join();
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::loco_controller::{LocoDriveController, LocoDriveMessage};
+use crate::protocol::Message;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tokio_stream::{Stream, StreamExt, StreamMap};
+
+/// Decides which messages are forwarded from or to a [`BridgePort`].
+pub type BridgeFilter = Box<dyn Fn(&Message) -> bool + Send>;
+
+/// One transport connected to a [`LocoNetBridge`], like a serial interface or a TCP server.
+///
+/// A port is made of the stream of messages it received and a channel
+/// the messages of the other ports are forwarded to.
+pub struct BridgePort {
+    /// The name of the port used in the logs
+    name: String,
+    /// The messages received by the transport
+    incoming: Pin<Box<dyn Stream<Item = Message> + Send>>,
+    /// Where the messages forwarded to the transport are sent to
+    outgoing: Sender<Message>,
+    /// Decides which received messages are forwarded to the other ports
+    accept: Option<BridgeFilter>,
+    /// Decides which messages of the other ports are forwarded to this port
+    pass: Option<BridgeFilter>,
+}
+
+impl BridgePort {
+    /// Creates a port of any transport.
+    ///
+    /// # Parameter
+    ///
+    /// - `name`: The name of the port used in the logs
+    /// - `incoming`: The messages received by the transport
+    /// - `outgoing`: Where the messages forwarded to the transport are sent to
+    pub fn new<S>(name: impl Into<String>, incoming: S, outgoing: Sender<Message>) -> Self
+    where
+        S: Stream<Item = Message> + Send + 'static,
+    {
+        BridgePort {
+            name: name.into(),
+            incoming: Box::pin(incoming),
+            outgoing,
+            accept: None,
+            pass: None,
+        }
+    }
+
+    /// Creates a port forwarding the messages read by `controller`
+    /// and writing the messages of the other ports with it.
+    ///
+    /// The controller should tag its own messages with
+    /// [`crate::loco_controller::LocoDriveControllerBuilder::tag_send_messages()`],
+    /// so the echoes of forwarded messages are recognized without relying on the loop window.
+    pub fn controller(name: impl Into<String>, controller: &LocoDriveController) -> Self {
+        let name = name.into();
+        let handle = controller.command_handle();
+        let (outgoing, mut forwarded) = channel::<Message>(64);
+
+        let port_name = name.clone();
+        tokio::spawn(async move {
+            while let Some(message) = forwarded.recv().await {
+                if let Err(err) = handle.send_message(message).await {
+                    log_error!("Could not forward {:?} to {}: {}", message, port_name, err);
+                }
+            }
+        });
+
+        let incoming = controller.messages().filter_map(|message| match message {
+            LocoDriveMessage::Message(message) => Some(message),
+            _ => None,
+        });
+
+        Self::new(name, incoming, outgoing)
+    }
+
+    /// Sets which messages received by this port are forwarded to the other ports.
+    ///
+    /// Defaults to all messages.
+    pub fn accept(mut self, filter: impl Fn(&Message) -> bool + Send + 'static) -> Self {
+        self.accept = Some(Box::new(filter));
+        self
+    }
+
+    /// Sets which messages of the other ports are forwarded to this port.
+    ///
+    /// Defaults to all messages.
+    pub fn pass(mut self, filter: impl Fn(&Message) -> bool + Send + 'static) -> Self {
+        self.pass = Some(Box::new(filter));
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The name of the port used in the logs.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Connects two or more transports and forwards the messages between them,
+/// so the crate acts as gateway, for example between a serial interface and a TCP server.
+///
+/// Every message received by one port is forwarded to all other ports, as far as their filters allow.
+/// Transports typically echo the messages written to them, which would be forwarded back
+/// and loop forever. So a message received by a port is dropped, if the same message was
+/// forwarded to that port within the loop window.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::bridge::{BridgePort, LocoNetBridge};
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::protocol::Message;
+///
+/// # async fn bridge(serial: LocoDriveController, other: LocoDriveController) {
+/// let _bridge = LocoNetBridge::new()
+///     .port(BridgePort::controller("serial", &serial))
+///     .port(BridgePort::controller("other", &other)
+///         .pass(|message| !matches!(message, Message::ImmPacket(..))))
+///     .start();
+/// # }
+/// ```
+pub struct LocoNetBridge {
+    /// The connected transports
+    ports: Vec<BridgePort>,
+    /// How long a forwarded message is recognized when read back from its port
+    loop_window: Duration,
+}
+
+impl LocoNetBridge {
+    /// Creates a bridge without any port.
+    pub fn new() -> Self {
+        LocoNetBridge {
+            ports: Vec::new(),
+            loop_window: Duration::from_secs(1),
+        }
+    }
+
+    /// Connects `port` to the bridge.
+    pub fn port(mut self, port: BridgePort) -> Self {
+        self.ports.push(port);
+        self
+    }
+
+    /// Sets how long a message forwarded to a port is dropped when read back from that port.
+    /// Should exceed the time the transport needs to echo a message.
+    ///
+    /// Defaults to one second.
+    pub fn loop_window(mut self, loop_window: Duration) -> Self {
+        self.loop_window = loop_window;
+        self
+    }
+
+    /// Starts forwarding the messages between the ports.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the bridge. Dropping it stops forwarding.
+    pub fn start(self) -> BridgeHandle {
+        BridgeHandle {
+            task: tokio::spawn(self.run()),
+        }
+    }
+
+    /// Forwards the messages until all ports are closed.
+    async fn run(self) {
+        let mut incoming = StreamMap::new();
+        let mut ports = Vec::with_capacity(self.ports.len());
+        for (index, port) in self.ports.into_iter().enumerate() {
+            incoming.insert(index, port.incoming);
+            ports.push(PortState {
+                name: port.name,
+                outgoing: port.outgoing,
+                accept: port.accept,
+                pass: port.pass,
+                forwarded: VecDeque::new(),
+            });
+        }
+
+        while let Some((from, message)) = incoming.next().await {
+            let now = Instant::now();
+            if !ports[from].receives(&message, now, self.loop_window) {
+                continue;
+            }
+
+            for (to, port) in ports.iter_mut().enumerate() {
+                if to != from {
+                    port.forward(message, now);
+                }
+            }
+        }
+    }
+}
+
+impl Default for LocoNetBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state of one port while the bridge is running.
+struct PortState {
+    /// The name of the port used in the logs
+    name: String,
+    /// Where the messages forwarded to the transport are sent to
+    outgoing: Sender<Message>,
+    /// Decides which received messages are forwarded to the other ports
+    accept: Option<BridgeFilter>,
+    /// Decides which messages of the other ports are forwarded to this port
+    pass: Option<BridgeFilter>,
+    /// The messages recently forwarded to this port and when
+    forwarded: VecDeque<(Message, Instant)>,
+}
+
+impl PortState {
+    /// Checks a `message` received by this port at `now`.
+    ///
+    /// # Returns
+    ///
+    /// If the message is forwarded to the other ports.
+    /// Messages read back within `loop_window` after forwarding them to this port are dropped.
+    fn receives(&mut self, message: &Message, now: Instant, loop_window: Duration) -> bool {
+        while let Some((_, at)) = self.forwarded.front() {
+            if now.saturating_duration_since(*at) < loop_window {
+                break;
+            }
+            self.forwarded.pop_front();
+        }
+
+        if let Some(index) = self.forwarded.iter().position(|(sent, _)| sent == message) {
+            self.forwarded.remove(index);
+            return false;
+        }
+
+        self.accept.as_ref().is_none_or(|accept| accept(message))
+    }
+
+    /// Forwards `message` to this port at `now`, if its filter passes it.
+    fn forward(&mut self, message: Message, now: Instant) {
+        if !self.pass.as_ref().is_none_or(|pass| pass(&message)) {
+            return;
+        }
+
+        match self.outgoing.try_send(message) {
+            Ok(()) => self.forwarded.push_back((message, now)),
+            Err(TrySendError::Full(message)) => {
+                log_error!("Dropped {:?} as {} is congested", message, self.name)
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+/// The handle of a running [`LocoNetBridge`].
+///
+/// Dropping the handle stops forwarding messages.
+#[derive(Debug)]
+pub struct BridgeHandle {
+    /// The task forwarding the messages
+    task: JoinHandle<()>,
+}
+
+impl BridgeHandle {
+    /// # Returns
+    ///
+    /// If the bridge stopped, as all of its ports are closed.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+/// Extends standard drop implementation to stop forwarding.
+impl Drop for BridgeHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
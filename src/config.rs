@@ -0,0 +1,445 @@
+use crate::adapter::AdapterProfile;
+use crate::error::ConfigError;
+use crate::layout::LayoutModel;
+use crate::loco_controller::{EchoPolicy, LocoDriveController, LocoDriveControllerBuilder};
+use crate::protocol::ExtraBytes;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_serial::FlowControl;
+
+/// Configures the whole stack: the connection to the model railroad, its timeouts and optional
+/// behaviours, the state managers, the network endpoints and the layout to load.
+///
+/// The configuration is read from TOML or RON. All values but the port have defaults,
+/// so a minimal TOML configuration is:
+///
+/// ```toml
+/// [connection]
+/// port = "/dev/ttyUSB0"
+/// ```
+///
+/// A full configuration looks like:
+///
+/// ```toml
+/// roster = "plan.xml"
+///
+/// [connection]
+/// port = "/dev/ttyUSB0"
+/// baud_rate = 115200
+/// flow_control = "software"
+/// channel_capacity = 64
+/// adapter = "locobuffer-usb"
+///
+/// [timeouts]
+/// sending_ms = 5000
+/// tx_gap_us = 1200
+/// priority_backoff_us = 60
+/// busy_hold_ms = 500
+///
+/// [features]
+/// echo_policy = "require"
+/// ignore_send_messages = false
+/// tag_send_messages = false
+/// report_sent_messages = false
+/// strict_slots = true
+/// idle_after_ms = 10000
+/// dedup_window_ms = 5
+/// extra_slot_bytes = "reject"
+/// keep_alive_ms = 60000
+/// health_interval_ms = 60000
+///
+/// [managers]
+/// slots = true
+/// switches = true
+/// sensors = true
+///
+/// [endpoints]
+/// mqtt = "localhost:1883"
+/// tcp = "0.0.0.0:12090"
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocodriveConfig {
+    /// The serial connection to the model railroad
+    pub connection: ConnectionConfig,
+    /// The timeouts and gaps used while sending
+    pub timeouts: TimeoutConfig,
+    /// The optional behaviours of the controller
+    pub features: FeatureConfig,
+    /// The state managers to run
+    pub managers: ManagerConfig,
+    /// The network endpoints to serve or connect to
+    pub endpoints: EndpointConfig,
+    /// The file to load the layout from, see [`LocodriveConfig::load_layout()`]
+    pub roster: Option<PathBuf>,
+}
+
+/// Configures the serial connection to the model railroad.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionConfig {
+    /// The port to connect to. Has no default and must be set.
+    pub port: String,
+    /// The baud rate to use. Defaults to 115200.
+    pub baud_rate: u32,
+    /// The flow control to use. Defaults to [`FlowControlConfig::Software`].
+    pub flow_control: FlowControlConfig,
+    /// The capacity of the broadcast channel. Defaults to 64 messages.
+    pub channel_capacity: usize,
+    /// The name of the built-in [`AdapterProfile`] initializing the interface.
+    /// Defaults to `generic`.
+    pub adapter: String,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfig {
+            port: String::new(),
+            baud_rate: 115_200,
+            flow_control: FlowControlConfig::Software,
+            channel_capacity: 64,
+            adapter: "generic".to_string(),
+        }
+    }
+}
+
+/// The flow control of the serial connection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControlConfig {
+    /// No flow control
+    None,
+    /// Flow control using XON/XOFF bytes
+    Software,
+    /// Flow control using RTS/CTS signals
+    Hardware,
+}
+
+impl From<FlowControlConfig> for FlowControl {
+    fn from(flow_control: FlowControlConfig) -> Self {
+        match flow_control {
+            FlowControlConfig::None => FlowControl::None,
+            FlowControlConfig::Software => FlowControl::Software,
+            FlowControlConfig::Hardware => FlowControl::Hardware,
+        }
+    }
+}
+
+/// Configures the timeouts and gaps used while sending.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// How long to wait in milliseconds for a send message to be received back.
+    /// Defaults to 5000 milliseconds.
+    pub sending_ms: u64,
+    /// The minimal gap in microseconds between the last bus activity and the next write.
+    /// Defaults to no gap.
+    pub tx_gap_us: u64,
+    /// The gap in microseconds added per priority delay step of a message.
+    /// Defaults to no backoff.
+    pub priority_backoff_us: u64,
+    /// How long in milliseconds writes are held back at most while the master is busy.
+    /// Defaults to 500 milliseconds.
+    pub busy_hold_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            sending_ms: 5000,
+            tx_gap_us: 0,
+            priority_backoff_us: 0,
+            busy_hold_ms: 500,
+        }
+    }
+}
+
+/// Configures the optional behaviours of the controller.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureConfig {
+    /// Whether to await the echo of written messages. Defaults to [`EchoPolicy::Require`].
+    pub echo_policy: EchoPolicy,
+    /// Whether to not broadcast messages send by the controller itself. Defaults to `false`.
+    pub ignore_send_messages: bool,
+    /// Whether to broadcast messages send by the controller itself as echoes. Defaults to `false`.
+    pub tag_send_messages: bool,
+    /// Whether to broadcast messages send by the controller when they are written. Defaults to `false`.
+    pub report_sent_messages: bool,
+    /// Whether to block writes to the reserved system slots. Defaults to `true`.
+    pub strict_slots: bool,
+    /// After how many milliseconds without traffic the bus is reported as idle.
+    /// Defaults to no reporting.
+    pub idle_after_ms: Option<u64>,
+    /// Within how many milliseconds identical frames are dropped as duplicates.
+    /// Defaults to passing all frames.
+    pub dedup_window_ms: Option<u64>,
+    /// How to handle slot data frames longer than the standard length.
+    /// Defaults to [`ExtraBytes::Reject`].
+    pub extra_slot_bytes: ExtraBytes,
+    /// How often in milliseconds the slots registered to be kept alive are refreshed.
+    /// Defaults to no keep alive service.
+    pub keep_alive_ms: Option<u64>,
+    /// How often in milliseconds the statistics are broadcast. Defaults to no broadcasting.
+    pub health_interval_ms: Option<u64>,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        FeatureConfig {
+            echo_policy: EchoPolicy::Require,
+            ignore_send_messages: false,
+            tag_send_messages: false,
+            report_sent_messages: false,
+            strict_slots: true,
+            idle_after_ms: None,
+            dedup_window_ms: None,
+            extra_slot_bytes: ExtraBytes::Reject,
+            keep_alive_ms: None,
+            health_interval_ms: None,
+        }
+    }
+}
+
+/// Selects the state managers to run. All managers are enabled by default.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManagerConfig {
+    /// Whether to track the slots of the command station
+    pub slots: bool,
+    /// Whether to track the turnout states
+    pub switches: bool,
+    /// Whether to track the sensor states
+    pub sensors: bool,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        ManagerConfig {
+            slots: true,
+            switches: true,
+            sensors: true,
+        }
+    }
+}
+
+/// Configures the network endpoints as `host:port`. All endpoints are disabled by default.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndpointConfig {
+    /// The MQTT broker to connect to
+    pub mqtt: Option<String>,
+    /// The address to serve the TCP bridge on
+    pub tcp: Option<String>,
+}
+
+impl LocodriveConfig {
+    /// Reads and validates a configuration from a file.
+    ///
+    /// The format is chosen by the files extension, `toml` or `ron`.
+    /// A relative roster path is resolved against the directory of the file.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be read
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    /// - The errors of [`LocodriveConfig::from_toml_str()`] or [`LocodriveConfig::from_ron_str()`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let content = std::fs::read_to_string(path)?;
+        let mut config = match extension.as_str() {
+            "toml" => Self::from_toml_str(&content)?,
+            "ron" => Self::from_ron_str(&content)?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        };
+
+        if let (Some(roster), Some(directory)) = (&config.roster, path.parent()) {
+            if roster.is_relative() {
+                config.roster = Some(directory.join(roster));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Reads and validates a configuration from TOML.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Parse`]: If `toml` is no valid configuration
+    /// - The errors of [`LocodriveConfig::validate()`]
+    pub fn from_toml_str(toml: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            toml::from_str(toml).map_err(|err| ConfigError::Parse(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads and validates a configuration from RON.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Parse`]: If `ron` is no valid configuration
+    /// - The errors of [`LocodriveConfig::validate()`]
+    pub fn from_ron_str(ron: &str) -> Result<Self, ConfigError> {
+        let config: Self = ron::from_str(ron).map_err(|err| ConfigError::Parse(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the configuration for values no controller can work with.
+    ///
+    /// # Errors
+    ///
+    /// [`ConfigError::Invalid`] naming the first invalid value.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.connection.port.trim().is_empty() {
+            return Err(ConfigError::Invalid(
+                "connection.port".to_string(),
+                "must be set".to_string(),
+            ));
+        }
+        if self.connection.baud_rate == 0 {
+            return Err(ConfigError::Invalid(
+                "connection.baud_rate".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+        if self.connection.channel_capacity == 0 {
+            return Err(ConfigError::Invalid(
+                "connection.channel_capacity".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+        if AdapterProfile::by_name(&self.connection.adapter).is_none() {
+            return Err(ConfigError::Invalid(
+                "connection.adapter".to_string(),
+                format!("{} is no known adapter", self.connection.adapter),
+            ));
+        }
+        if self.timeouts.sending_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "timeouts.sending_ms".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+        if self.features.idle_after_ms == Some(0) {
+            return Err(ConfigError::Invalid(
+                "features.idle_after_ms".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+        if self.features.keep_alive_ms == Some(0) {
+            return Err(ConfigError::Invalid(
+                "features.keep_alive_ms".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+        if self.features.health_interval_ms == Some(0) {
+            return Err(ConfigError::Invalid(
+                "features.health_interval_ms".to_string(),
+                "must be positive".to_string(),
+            ));
+        }
+
+        for (name, endpoint) in [
+            ("endpoints.mqtt", &self.endpoints.mqtt),
+            ("endpoints.tcp", &self.endpoints.tcp),
+        ] {
+            if let Some(endpoint) = endpoint {
+                let valid = match endpoint.rsplit_once(':') {
+                    Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+                    None => false,
+                };
+                if !valid {
+                    return Err(ConfigError::Invalid(
+                        name.to_string(),
+                        format!("{} is no host:port", endpoint),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// # Returns
+    ///
+    /// A [`LocoDriveControllerBuilder`] configured by the connection, timeouts and features.
+    pub fn controller_builder(&self) -> LocoDriveControllerBuilder {
+        let mut builder =
+            LocoDriveController::builder(&self.connection.port, self.connection.baud_rate)
+                .flow_control(self.connection.flow_control.into())
+                .channel_capacity(self.connection.channel_capacity)
+                .adapter(AdapterProfile::by_name(&self.connection.adapter).unwrap_or_default())
+                .sending_timeout(self.timeouts.sending_ms)
+                .tx_gap(Duration::from_micros(self.timeouts.tx_gap_us))
+                .priority_backoff(Duration::from_micros(self.timeouts.priority_backoff_us))
+                .busy_hold(Duration::from_millis(self.timeouts.busy_hold_ms))
+                .echo_policy(self.features.echo_policy)
+                .ignore_send_messages(self.features.ignore_send_messages)
+                .tag_send_messages(self.features.tag_send_messages)
+                .report_sent_messages(self.features.report_sent_messages)
+                .extra_slot_bytes(self.features.extra_slot_bytes)
+                .strict_slots(self.features.strict_slots);
+
+        if let Some(idle_after) = self.features.idle_after_ms {
+            builder = builder.idle_after(Duration::from_millis(idle_after));
+        }
+        if let Some(dedup_window) = self.features.dedup_window_ms {
+            builder = builder.dedup_window(Duration::from_millis(dedup_window));
+        }
+        if let Some(keep_alive) = self.features.keep_alive_ms {
+            builder = builder.keep_alive(Duration::from_millis(keep_alive));
+        }
+        if let Some(health_interval) = self.features.health_interval_ms {
+            builder = builder.health_interval(Duration::from_millis(health_interval));
+        }
+
+        builder
+    }
+
+    /// Loads the layout from the configured roster file.
+    ///
+    /// The format is chosen by the files extension: `xml` is imported as Rocrail plan
+    /// (requires the `rocrail` feature), `toml` and `ron` are read as [`LayoutModel`].
+    ///
+    /// # Returns
+    ///
+    /// The loaded layout or an empty layout, if no roster is configured.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be read
+    /// - [`ConfigError::Parse`]: If the file holds no valid layout
+    /// - [`ConfigError::UnsupportedFormat`]: If the files format is not supported
+    pub fn load_layout(&self) -> Result<LayoutModel, ConfigError> {
+        let path = match &self.roster {
+            Some(path) => path,
+            None => return Ok(LayoutModel::new()),
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            #[cfg(feature = "rocrail")]
+            "xml" => crate::rocrail::import_plan_file(path)
+                .map_err(|err| ConfigError::Parse(err.to_string())),
+            "toml" => toml::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|err| ConfigError::Parse(err.to_string())),
+            "ron" => ron::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|err| ConfigError::Parse(err.to_string())),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+/// Tests decoding known good byte sequences of the LocoNet personal use documentation
+/// and of JMRI monitor logs to the expected messages.
+///
+/// Other than the round trip tests, these catch mistakes made the same way in parsing and
+/// writing a message, as the expected messages are not derived from this implementation.
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod conformance {
+    use crate::args::{
+        Ack1Arg, AddressArg, Consist, CvDataArg, DecoderType, DirfArg, IdArg, InArg, LopcArg, Pcmd,
+        SensorLevel, SlotArg, SndArg, SourceType, SpeedArg, Stat1Arg, Stat2Arg, State, SwitchArg,
+        SwitchDirection, TrkArg, WrSlDataStructure,
+    };
+    use crate::protocol::Message;
+    use alloc::vec::Vec;
+
+    /// Parses the whitespace separated hex bytes of `frame`.
+    fn bytes(frame: &str) -> Vec<u8> {
+        frame
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect()
+    }
+
+    /// Asserts that `frame` decodes to `expected` and that `expected` is written as `frame`.
+    fn assert_frame(frame: &str, expected: Message) {
+        let bytes = bytes(frame);
+        assert_eq!(
+            Message::parse(&bytes).ok(),
+            Some(expected),
+            "decoding {}",
+            frame
+        );
+        assert_eq!(expected.to_message(), bytes, "encoding {:?}", expected);
+    }
+
+    /// Tests the global power and busy messages.
+    #[test]
+    fn power() {
+        assert_frame("83 7C", Message::GpOn);
+        assert_frame("82 7D", Message::GpOff);
+        assert_frame("85 7A", Message::Idle);
+        assert_frame("81 7E", Message::Busy);
+    }
+
+    /// Tests requesting and moving locomotive slots.
+    #[test]
+    fn slots() {
+        // Request the slot of short address 3
+        assert_frame("BF 00 03 43", Message::LocoAdr(AddressArg::new(3)));
+        // Request the slot of long address 255
+        assert_frame("BF 01 7F 3E", Message::LocoAdr(AddressArg::new(255)));
+        assert_frame("BB 03 00 47", Message::RqSlData(SlotArg::new(3)));
+        // A null move marking slot 3 in use
+        assert_frame(
+            "BA 03 03 45",
+            Message::MoveSlots(SlotArg::new(3), SlotArg::new(3)),
+        );
+        // Dispatch put of slot 3
+        assert_frame(
+            "BA 03 00 46",
+            Message::MoveSlots(SlotArg::new(3), SlotArg::new(0)),
+        );
+        assert_frame(
+            "B5 03 13 5A",
+            Message::SlotStat1(
+                SlotArg::new(3),
+                Stat1Arg::new(false, Consist::Free, State::Common, DecoderType::Speed128),
+            ),
+        );
+        // No free slot for a requested address
+        assert_frame(
+            "B4 3F 00 74",
+            Message::LongAck(LopcArg::new(0xBF), Ack1Arg::new(false)),
+        );
+        // An illegal move
+        assert_frame(
+            "B4 3A 00 71",
+            Message::LongAck(LopcArg::new(0xBA), Ack1Arg::new(false)),
+        );
+    }
+
+    /// Tests reading the data of an in use slot.
+    #[test]
+    fn slot_data() {
+        // Slot 3 in use by address 3 with 128 speed steps, standing forwards with light on
+        assert_frame(
+            "E7 0E 03 33 03 00 10 07 00 00 00 00 00 32",
+            Message::SlRdData(
+                SlotArg::new(3),
+                Stat1Arg::new(false, Consist::Free, State::InUse, DecoderType::Speed128),
+                AddressArg::new(3),
+                SpeedArg::Stop,
+                DirfArg::new(true, true, false, false, false, false),
+                TrkArg::new(true, false, true, false),
+                Stat2Arg::new(false, false, false),
+                SndArg::new(false, false, false, false),
+                IdArg::new(0),
+            ),
+        );
+    }
+
+    /// Tests driving a locomotive.
+    #[test]
+    fn driving() {
+        assert_frame(
+            "A0 03 00 5C",
+            Message::LocoSpd(SlotArg::new(3), SpeedArg::Stop),
+        );
+        assert_frame(
+            "A0 03 01 5D",
+            Message::LocoSpd(SlotArg::new(3), SpeedArg::EmergencyStop),
+        );
+        // The speed steps start after the emergency stop
+        assert_frame(
+            "A0 03 20 7C",
+            Message::LocoSpd(SlotArg::new(3), SpeedArg::Drive(31)),
+        );
+        // Forwards with light on
+        assert_frame(
+            "A1 03 10 4D",
+            Message::LocoDirf(
+                SlotArg::new(3),
+                DirfArg::new(true, true, false, false, false, false),
+            ),
+        );
+        // Backwards with all functions off
+        assert_frame(
+            "A1 03 20 7D",
+            Message::LocoDirf(
+                SlotArg::new(3),
+                DirfArg::new(false, false, false, false, false, false),
+            ),
+        );
+        assert_frame(
+            "A2 03 01 5F",
+            Message::LocoSnd(SlotArg::new(3), SndArg::new(true, false, false, false)),
+        );
+    }
+
+    /// Tests switching turnouts and reporting sensors.
+    #[test]
+    fn accessories() {
+        // JMRI turnout LT2 closed
+        assert_frame(
+            "B0 01 30 7E",
+            Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true)),
+        );
+        // JMRI turnout LT2 thrown
+        assert_frame(
+            "B0 01 10 5E",
+            Message::SwReq(SwitchArg::new(1, SwitchDirection::Curved, true)),
+        );
+        assert_frame(
+            "BC 01 00 42",
+            Message::SwState(SwitchArg::new(1, SwitchDirection::Curved, false)),
+        );
+        // JMRI sensor LS21 active
+        assert_frame(
+            "B2 0A 50 17",
+            Message::InputRep(InArg::new(10, SourceType::Ds54Aux, SensorLevel::High, true)),
+        );
+        // JMRI sensor LS22 inactive
+        assert_frame(
+            "B2 0A 20 67",
+            Message::InputRep(InArg::new(10, SourceType::Switch, SensorLevel::Low, false)),
+        );
+    }
+
+    /// The modes to read and write cvs with.
+    #[derive(Debug, Copy, Clone)]
+    enum Mode {
+        Paged,
+        Direct,
+        Register,
+        Ops,
+    }
+
+    /// The programming task frames reading or writing cv 1, as `(frame, mode, write)`.
+    /// The `pcmd` byte sets write `0x40`, byte mode `0x20`, TY1 `0x10`, TY0 `0x08`
+    /// and ops mode `0x04`.
+    const PROGRAMMING_TASKS: [(&str, Mode, bool); 8] = [
+        (
+            "EF 0E 7C 20 00 00 00 07 00 00 00 00 00 45",
+            Mode::Paged,
+            false,
+        ),
+        (
+            "EF 0E 7C 60 00 00 00 07 00 00 00 00 00 05",
+            Mode::Paged,
+            true,
+        ),
+        (
+            "EF 0E 7C 28 00 00 00 07 00 00 00 00 00 4D",
+            Mode::Direct,
+            false,
+        ),
+        (
+            "EF 0E 7C 68 00 00 00 07 00 00 00 00 00 0D",
+            Mode::Direct,
+            true,
+        ),
+        (
+            "EF 0E 7C 10 00 00 00 07 00 00 00 00 00 75",
+            Mode::Register,
+            false,
+        ),
+        (
+            "EF 0E 7C 50 00 00 00 07 00 00 00 00 00 35",
+            Mode::Register,
+            true,
+        ),
+        (
+            "EF 0E 7C 2C 00 00 03 07 00 00 00 00 00 4A",
+            Mode::Ops,
+            false,
+        ),
+        ("EF 0E 7C 64 00 00 03 07 00 00 00 00 00 02", Mode::Ops, true),
+    ];
+
+    /// # Returns
+    ///
+    /// The programming task with `pcmd`, addressing decoder 3 in ops mode.
+    fn programming_task(pcmd: Pcmd, mode: Mode) -> Message {
+        let address = match mode {
+            Mode::Ops => 3,
+            _ => 0,
+        };
+        Message::WrSlData(WrSlDataStructure::DataPt(
+            pcmd,
+            AddressArg::new(address),
+            TrkArg::new(true, false, true, false),
+            CvDataArg::new(),
+        ))
+    }
+
+    /// Tests the programming commands of each programming mode.
+    #[test]
+    fn programming() {
+        for &(frame, mode, write) in PROGRAMMING_TASKS.iter() {
+            // Byte mode, ops mode, TY0 and TY1
+            let (byte_mode, ops_mode, ty0, ty1) = match mode {
+                Mode::Paged => (true, false, false, false),
+                Mode::Direct => (true, false, true, false),
+                Mode::Register => (false, false, false, true),
+                // Reading on the main track requires feedback
+                Mode::Ops => (true, true, !write, false),
+            };
+            let pcmd = Pcmd::new(write, byte_mode, ops_mode, ty0, ty1);
+            assert_frame(frame, programming_task(pcmd, mode));
+        }
+    }
+
+    /// Tests the programmer uses the programming command of the requested mode.
+    #[cfg(feature = "control")]
+    #[test]
+    fn programming_modes() {
+        use crate::programmer::ProgrammingMode;
+
+        for &(frame, mode, write) in PROGRAMMING_TASKS.iter() {
+            let programming_mode = match mode {
+                Mode::Paged => ProgrammingMode::Paged,
+                Mode::Direct => ProgrammingMode::Direct,
+                Mode::Register => ProgrammingMode::Register,
+                Mode::Ops => ProgrammingMode::Ops(AddressArg::new(3)),
+            };
+            let task = programming_task(programming_mode.pcmd(write), mode);
+            assert_eq!(
+                task.to_message(),
+                bytes(frame),
+                "{:?} write {}",
+                mode,
+                write
+            );
+        }
+    }
+}
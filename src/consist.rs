@@ -0,0 +1,239 @@
+use crate::args::{Consist, DirfArg, SlotArg, Stat1Arg};
+use crate::error::{ConsistError, LocoDriveSendingError};
+use crate::loco_controller::{move_answer, CommandHandle, LocoDriveController};
+use crate::protocol::Message;
+use crate::subscription::SlotUpdate;
+use std::collections::HashMap;
+
+/// The direction a member of a consist faces, relative to the top of the consist.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Facing {
+    /// The member runs in the same direction as the top.
+    Forward,
+    /// The member is turned around, so it runs against the direction of the top.
+    Reversed,
+}
+
+/// One locomotive linked to the top of a consist.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConsistMember {
+    /// The slot of the locomotive
+    pub slot: SlotArg,
+    /// The direction the locomotive faces
+    pub facing: Facing,
+}
+
+/// Builds advanced consists on the command station by linking slots to the slot of a top locomotive.
+///
+/// The speed and direction of a consist is controlled by its top only.
+/// The manager tracks the members of each consist in the order they were linked,
+/// so a consist can be dissolved as a whole.
+///
+/// Before linking, the topology is validated: The top has to be unlinked or already the top of
+/// a consist and the member has to be unlinked. The command station has to confirm the top
+/// as [`Consist::LogicalTop`] after linking.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::SlotArg;
+/// use locodrive::consist::{ConsistManager, Facing};
+/// use locodrive::loco_controller::LocoDriveController;
+///
+/// # async fn build(controller: LocoDriveController) -> Result<(), locodrive::error::ConsistError> {
+/// let mut consists = ConsistManager::new(&controller);
+/// consists.link(SlotArg::new(1), SlotArg::new(2), Facing::Forward).await?;
+/// consists.link(SlotArg::new(1), SlotArg::new(3), Facing::Reversed).await?;
+///
+/// consists.dissolve(SlotArg::new(1)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ConsistManager {
+    /// Writes the link messages
+    handle: CommandHandle,
+    /// The members of each consist by its top
+    consists: HashMap<SlotArg, Vec<ConsistMember>>,
+}
+
+impl ConsistManager {
+    /// Creates a manager without any consist, building consists with `controller`.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        ConsistManager {
+            handle: controller.command_handle(),
+            consists: HashMap::new(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The tops of all consists built by this manager.
+    pub fn tops(&self) -> Vec<SlotArg> {
+        self.consists.keys().copied().collect()
+    }
+
+    /// # Returns
+    ///
+    /// The members of the consist of `top` in the order they were linked,
+    /// or `None` if `top` is no top of a consist built by this manager.
+    pub fn members(&self, top: SlotArg) -> Option<&[ConsistMember]> {
+        self.consists.get(&top).map(Vec::as_slice)
+    }
+
+    /// # Returns
+    ///
+    /// The top of the consist `member` is linked to.
+    pub fn top_of(&self, member: SlotArg) -> Option<SlotArg> {
+        self.consists
+            .iter()
+            .find(|(_, members)| members.iter().any(|linked| linked.slot == member))
+            .map(|(top, _)| *top)
+    }
+
+    /// Links `member` to the consist of `top`. If `top` is no top yet, a new consist is built.
+    ///
+    /// The direction of the member is set according to `facing` before linking,
+    /// as the command station keeps the relative direction of the linked slots.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConsistError::NotTop`]: If `top` is linked to another slot itself,
+    ///   or the command station did not confirm it as top
+    /// - [`ConsistError::Linked`]: If `member` is already part of a consist
+    /// - [`ConsistError::Sending`]: If the slots could not be read or linked
+    pub async fn link(
+        &mut self,
+        top: SlotArg,
+        member: SlotArg,
+        facing: Facing,
+    ) -> Result<(), ConsistError> {
+        if member == top || self.consists.contains_key(&member) || self.top_of(member).is_some() {
+            return Err(ConsistError::Linked(member));
+        }
+
+        let (top_stat1, top_dirf) = self.read_slot(top).await?;
+        let (member_stat1, member_dirf) = self.read_slot(member).await?;
+        validate_link(top, top_stat1, member, member_stat1)?;
+
+        let dir = match facing {
+            Facing::Forward => top_dirf.dir(),
+            Facing::Reversed => !top_dirf.dir(),
+        };
+        if member_dirf.dir() != dir {
+            let dirf = DirfArg::new(
+                dir,
+                member_dirf.f(0),
+                member_dirf.f(1),
+                member_dirf.f(2),
+                member_dirf.f(3),
+                member_dirf.f(4),
+            );
+            self.handle
+                .send_message(Message::LocoDirf(member, dirf))
+                .await?;
+        }
+
+        let message = Message::LinkSlots(member, top);
+        match self
+            .handle
+            .request(message, |answer| move_answer(&message, answer))
+            .await?
+        {
+            Ok(SlotUpdate::Data(slot, stat1, ..))
+                if slot == top && stat1.consist() == Consist::LogicalTop => {}
+            Ok(_) => return Err(ConsistError::NotTop(top)),
+            Err(ack) => return Err(LocoDriveSendingError::Rejected(ack).into()),
+        }
+
+        self.consists.entry(top).or_default().push(ConsistMember {
+            slot: member,
+            facing,
+        });
+        Ok(())
+    }
+
+    /// Unlinks `member` from the consist of `top`.
+    /// The consist is dissolved, if `member` was its last member.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConsistError::NotMember`]: If `member` is not linked to `top` by this manager
+    /// - [`ConsistError::Sending`]: If the slots could not be unlinked
+    pub async fn unlink(&mut self, top: SlotArg, member: SlotArg) -> Result<(), ConsistError> {
+        let index = self
+            .consists
+            .get(&top)
+            .and_then(|members| members.iter().position(|linked| linked.slot == member))
+            .ok_or(ConsistError::NotMember(member))?;
+
+        let message = Message::UnlinkSlots(member, top);
+        if let Err(ack) = self
+            .handle
+            .request(message, |answer| move_answer(&message, answer))
+            .await?
+        {
+            return Err(LocoDriveSendingError::Rejected(ack).into());
+        }
+
+        if let Some(members) = self.consists.get_mut(&top) {
+            members.remove(index);
+            if members.is_empty() {
+                self.consists.remove(&top);
+            }
+        }
+        Ok(())
+    }
+
+    /// Dissolves the consist of `top` by unlinking all of its members,
+    /// starting with the member linked last.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConsistError::NotTop`]: If `top` is no top of a consist built by this manager
+    /// - [`ConsistError::Sending`]: If a member could not be unlinked.
+    ///   The members unlinked before are removed from the consist.
+    pub async fn dissolve(&mut self, top: SlotArg) -> Result<(), ConsistError> {
+        let members = self.members(top).ok_or(ConsistError::NotTop(top))?.to_vec();
+
+        for member in members.iter().rev() {
+            self.unlink(top, member.slot).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the status and direction of `slot` from the command station.
+    async fn read_slot(&self, slot: SlotArg) -> Result<(Stat1Arg, DirfArg), LocoDriveSendingError> {
+        self.handle
+            .request(Message::RqSlData(slot), |answer| match *answer {
+                Message::SlRdData(read, stat1, _, _, dirf, ..) if read == slot => {
+                    Some((stat1, dirf))
+                }
+                _ => None,
+            })
+            .await
+    }
+}
+
+/// Validates linking the slot `member` with the status `member_stat1`
+/// to the slot `top` with the status `top_stat1`.
+///
+/// # Errors
+///
+/// - [`ConsistError::NotTop`]: If `top` is linked to another slot
+/// - [`ConsistError::Linked`]: If `member` is linked to another slot
+pub(crate) fn validate_link(
+    top: SlotArg,
+    top_stat1: Stat1Arg,
+    member: SlotArg,
+    member_stat1: Stat1Arg,
+) -> Result<(), ConsistError> {
+    match top_stat1.consist() {
+        Consist::Free | Consist::LogicalTop => {}
+        _ => return Err(ConsistError::NotTop(top)),
+    }
+
+    match member_stat1.consist() {
+        Consist::Free => Ok(()),
+        _ => Err(ConsistError::Linked(member)),
+    }
+}
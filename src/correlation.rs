@@ -0,0 +1,172 @@
+use crate::protocol::Message;
+use std::collections::HashMap;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+
+/// Matches received answers to the requests they answer.
+///
+/// Requests awaiting an answer, like [`Message::SwAck`], [`Message::SwState`] or
+/// [`Message::ImmPacket`], stay pending until their answer is received or their timeout elapsed.
+/// So other traffic between a request and its answer does not prevent matching them.
+/// All acknowledgments are matched, also limited or failed ones.
+#[derive(Debug, Clone)]
+pub(crate) struct AnswerCorrelator {
+    /// The requests awaiting an answer with the time they time out, in order of receiving
+    pending: Vec<(Message, Instant)>,
+    /// How long to await the answer to requests with the op code
+    timeouts: HashMap<u8, Duration>,
+    /// How long to await the answer to other requests
+    default_timeout: Duration,
+}
+
+impl AnswerCorrelator {
+    /// Creates a correlator awaiting answers for `default_timeout`
+    /// or for the `timeouts` of the requests op code.
+    pub(crate) fn new(default_timeout: Duration, timeouts: HashMap<u8, Duration>) -> Self {
+        AnswerCorrelator {
+            pending: Vec::new(),
+            timeouts,
+            default_timeout,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// When the next pending request times out, if any is pending.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|(_, deadline)| *deadline).min()
+    }
+
+    /// Removes the requests not answered until `at`.
+    ///
+    /// # Returns
+    ///
+    /// The timed out requests, in order of receiving.
+    pub(crate) fn expire(&mut self, at: Instant) -> Vec<Message> {
+        let mut expired = Vec::new();
+        self.pending.retain(|(request, deadline)| {
+            if *deadline > at {
+                return true;
+            }
+            expired.push(*request);
+            false
+        });
+        expired
+    }
+
+    /// Handles the `message` read at `at`.
+    ///
+    /// # Returns
+    ///
+    /// The request `message` answers, if it answers a pending request.
+    pub(crate) fn handle(&mut self, message: Message, at: Instant) -> Option<Message> {
+        // Forget requests not answered in time
+        self.pending.retain(|(_, deadline)| *deadline > at);
+
+        let answered = self
+            .pending
+            .iter()
+            .position(|(request, _)| match message {
+                Message::LongAck(lopc, _) => lopc.check_opc(request),
+                Message::SlRdData(..) => request.await_slot_data(),
+                _ => false,
+            })
+            .map(|index| self.pending.remove(index).0);
+
+        // Busy only tells the answer is delayed, it awaits none itself.
+        // Switch state requests are answered although their op code does not announce it.
+        if (message.answer_follows() || matches!(message, Message::SwState(..)))
+            && Message::Busy != message
+        {
+            let timeout = self
+                .timeouts
+                .get(&message.opc())
+                .copied()
+                .unwrap_or(self.default_timeout);
+            self.pending.push((message, at + timeout));
+        }
+
+        answered
+    }
+}
+
+/// An echo a writer awaits, with the channel to tell it the echo was read.
+#[derive(Debug)]
+struct PendingEcho {
+    /// The message written
+    message: Message,
+    /// Resolved when the echo was read
+    echoed: oneshot::Sender<()>,
+}
+
+/// Creates the channel the writers tell the reader which echoes they await through.
+///
+/// Each written message gets its own oneshot channel, resolved by the reader when it reads the echo.
+/// A writer stops awaiting its echo by dropping the receiver, which the reader notices,
+/// so the writers and the reader share no lock.
+pub(crate) fn echo_channel() -> (EchoSender, EchoMatcher) {
+    let (sender, expected) = unbounded_channel();
+    (
+        EchoSender(sender),
+        EchoMatcher {
+            expected,
+            pending: None,
+        },
+    )
+}
+
+/// Tells the reader which echoes the writers await, see [`echo_channel()`].
+#[derive(Debug, Clone)]
+pub(crate) struct EchoSender(UnboundedSender<PendingEcho>);
+
+impl EchoSender {
+    /// Tells the reader to expect the echo of `message`.
+    /// Expect it before writing `message`, to not miss a fast echo.
+    ///
+    /// # Returns
+    ///
+    /// The receiver resolved when the echo was read. It fails, if the reader stopped.
+    pub(crate) fn expect(&self, message: Message) -> oneshot::Receiver<()> {
+        let (echoed, receiver) = oneshot::channel();
+        // Without a reader the sender is dropped, which fails the receiver
+        let _ = self.0.send(PendingEcho { message, echoed });
+        receiver
+    }
+}
+
+/// Matches the read messages to the echo awaited by a writer, see [`echo_channel()`].
+#[derive(Debug)]
+pub(crate) struct EchoMatcher {
+    /// The echoes expected by the writers, in order of writing
+    expected: UnboundedReceiver<PendingEcho>,
+    /// The echo awaited by the last writer
+    pending: Option<PendingEcho>,
+}
+
+impl EchoMatcher {
+    /// Handles the read `message` and notifies the writer awaiting it as echo.
+    ///
+    /// # Returns
+    ///
+    /// If `message` is the echo of the message awaited by a writer.
+    pub(crate) fn handle(&mut self, message: &Message) -> bool {
+        // Only one message is written at once, so earlier writers do not wait anymore
+        while let Ok(expected) = self.expected.try_recv() {
+            self.pending = Some(expected);
+        }
+
+        match self.pending.take() {
+            // The writer stopped waiting
+            Some(pending) if pending.echoed.is_closed() => false,
+            Some(pending) if pending.message == *message => {
+                let _ = pending.echoed.send(());
+                true
+            }
+            pending => {
+                self.pending = pending;
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use crate::args::{ImAddress, ImArg, SpeedArg, SwitchDirection};
+use crate::error::DccError;
+use crate::protocol::Message;
+
+/// The longest DCC packet an immediate packet can hold, without the error detection byte.
+pub const MAX_PACKET_LEN: usize = 5;
+
+/// The highest long locomotive address of DCC.
+const MAX_LONG_ADDRESS: u16 = 10239;
+/// The highest accessory output address, numbered like the [`crate::args::SwitchArg`] addresses.
+const MAX_ACCESSORY_ADDRESS: u16 = 2043;
+/// The highest aspect an extended accessory decoder can be set to.
+const MAX_ASPECT: u8 = 0x1F;
+
+/// The functions set together by one DCC packet.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DccFunctionGroup {
+    /// The functions 0 to 4
+    F0to4,
+    /// The functions 5 to 8
+    F5to8,
+    /// The functions 9 to 12
+    F9to12,
+    /// The functions 13 to 20
+    F13to20,
+    /// The functions 21 to 28
+    F21to28,
+}
+
+impl DccFunctionGroup {
+    /// # Returns
+    ///
+    /// The first function of the group.
+    pub fn first(&self) -> u8 {
+        match self {
+            DccFunctionGroup::F0to4 => 0,
+            DccFunctionGroup::F5to8 => 5,
+            DccFunctionGroup::F9to12 => 9,
+            DccFunctionGroup::F13to20 => 13,
+            DccFunctionGroup::F21to28 => 21,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// How many functions the group holds.
+    pub fn count(&self) -> u8 {
+        match self {
+            DccFunctionGroup::F0to4 => 5,
+            DccFunctionGroup::F5to8 | DccFunctionGroup::F9to12 => 4,
+            DccFunctionGroup::F13to20 | DccFunctionGroup::F21to28 => 8,
+        }
+    }
+}
+
+/// The instruction of a standard DCC packet, see the NMRA standards S-9.2 and S-9.2.1.
+///
+/// Locomotive addresses up to 127 are send in the short format if given as [`ImAddress::Short`],
+/// [`ImAddress::Short`] `0` is the broadcast address.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DccCommand {
+    /// Sets the speed of the locomotive at `address` in 128 speed steps.
+    Speed {
+        /// The address of the locomotive
+        address: ImAddress,
+        /// The speed to set
+        speed: SpeedArg,
+        /// Whether the locomotive drives forwards
+        forward: bool,
+    },
+    /// Sets the functions of one group of the locomotive at `address`.
+    Functions {
+        /// The address of the locomotive
+        address: ImAddress,
+        /// The group of the functions
+        group: DccFunctionGroup,
+        /// The functions of the group, bit 0 holding the first function of the group
+        functions: u8,
+    },
+    /// Switches an output of a basic accessory decoder.
+    Accessory {
+        /// The address of the output, from 0 to 2043, numbered like the
+        /// [`crate::args::SwitchArg`] addresses
+        address: u16,
+        /// The direction to switch to
+        direction: SwitchDirection,
+        /// Whether the output is activated
+        active: bool,
+    },
+    /// Sets the aspect of an extended accessory decoder, like a signal decoder.
+    ExtendedAccessory {
+        /// The address of the decoder output, from 0 to 2043, numbered like the
+        /// [`crate::args::SwitchArg`] addresses
+        address: u16,
+        /// The aspect to set, from 0 to 31
+        aspect: u8,
+    },
+    /// Writes a cv of the locomotive at `address` on the main track, also known as POM.
+    WriteCv {
+        /// The address of the locomotive
+        address: ImAddress,
+        /// The cv to write, from 1 to 1024
+        cv: u16,
+        /// The value to write
+        value: u8,
+    },
+}
+
+impl DccCommand {
+    /// Encodes the command to a DCC packet.
+    ///
+    /// # Errors
+    ///
+    /// - [`DccError::InvalidAddress`]: If the address can not be send in its format
+    /// - [`DccError::InvalidCv`]: If the cv is not between 1 and 1024
+    /// - [`DccError::InvalidAspect`]: If the aspect is not between 0 and 31
+    pub fn encode(&self) -> Result<DccPacket, DccError> {
+        let mut packet = DccPacket::empty();
+        match *self {
+            DccCommand::Speed {
+                address,
+                speed,
+                forward,
+            } => {
+                packet.push_address(address)?;
+                packet.push(0x3F);
+                packet.push(((forward as u8) << 7) | speed.spd());
+            }
+            DccCommand::Functions {
+                address,
+                group,
+                functions,
+            } => {
+                packet.push_address(address)?;
+                match group {
+                    DccFunctionGroup::F0to4 => {
+                        packet.push(0x80 | ((functions & 0x01) << 4) | ((functions >> 1) & 0x0F))
+                    }
+                    DccFunctionGroup::F5to8 => packet.push(0xB0 | (functions & 0x0F)),
+                    DccFunctionGroup::F9to12 => packet.push(0xA0 | (functions & 0x0F)),
+                    DccFunctionGroup::F13to20 => {
+                        packet.push(0xDE);
+                        packet.push(functions);
+                    }
+                    DccFunctionGroup::F21to28 => {
+                        packet.push(0xDF);
+                        packet.push(functions);
+                    }
+                }
+            }
+            DccCommand::Accessory {
+                address,
+                direction,
+                active,
+            } => {
+                if address > MAX_ACCESSORY_ADDRESS {
+                    return Err(DccError::InvalidAddress(address));
+                }
+                // The decoder addresses start at one, each decoder has four pairs of outputs
+                let decoder = address / 4 + 1;
+                packet.push(0x80 | (decoder & 0x3F) as u8);
+                packet.push(
+                    0x80 | ((!(decoder >> 6) & 0x07) as u8) << 4
+                        | (active as u8) << 3
+                        | ((address % 4) as u8) << 1
+                        | (direction == SwitchDirection::Straight) as u8,
+                );
+            }
+            DccCommand::ExtendedAccessory { address, aspect } => {
+                if address > MAX_ACCESSORY_ADDRESS {
+                    return Err(DccError::InvalidAddress(address));
+                }
+                if aspect > MAX_ASPECT {
+                    return Err(DccError::InvalidAspect(aspect));
+                }
+                // The outputs are addressed like the basic ones, but without the activation bit
+                let decoder = address / 4 + 1;
+                packet.push(0x80 | (decoder & 0x3F) as u8);
+                packet.push(
+                    ((!(decoder >> 6) & 0x07) as u8) << 4 | ((address % 4) as u8) << 1 | 0x01,
+                );
+                packet.push(aspect);
+            }
+            DccCommand::WriteCv { address, cv, value } => {
+                if !(1..=1024).contains(&cv) {
+                    return Err(DccError::InvalidCv(cv));
+                }
+                packet.push_address(address)?;
+                packet.push(0xEC | ((cv - 1) >> 8) as u8);
+                packet.push((cv - 1) as u8);
+                packet.push(value);
+            }
+        }
+        Ok(packet)
+    }
+}
+
+/// A DCC packet without its error detection byte, as send by a [`Message::ImmPacket`].
+///
+/// Use it to send anything LocoNet has no message for, by encoding a [`DccCommand`]
+/// or by passing the packet bytes to [`DccPacket::new()`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct DccPacket {
+    /// The bytes of the packet, only the first `len` are used
+    bytes: [u8; MAX_PACKET_LEN],
+    /// How many bytes the packet holds
+    len: usize,
+}
+
+impl DccPacket {
+    /// Creates a packet of the given `bytes`, without the error detection byte.
+    ///
+    /// # Errors
+    ///
+    /// - [`DccError::InvalidLength`]: If the packet does not have 2 to 5 bytes
+    pub fn new(bytes: &[u8]) -> Result<Self, DccError> {
+        if !(2..=MAX_PACKET_LEN).contains(&bytes.len()) {
+            return Err(DccError::InvalidLength(bytes.len()));
+        }
+        let mut packet = Self::empty();
+        for byte in bytes {
+            packet.push(*byte);
+        }
+        Ok(packet)
+    }
+
+    /// Reads the DCC packet send by an immediate packet.
+    ///
+    /// # Returns
+    ///
+    /// The packet, or `None` if `im_arg` does not hold 2 to 5 bytes.
+    pub fn from_im_arg(im_arg: &ImArg) -> Option<Self> {
+        let len = ((im_arg.reps() >> 4) & 0x07) as usize;
+        let im = [
+            im_arg.im1(),
+            im_arg.im2(),
+            im_arg.im3(),
+            im_arg.im4(),
+            im_arg.im5(),
+        ];
+        let mut bytes = [0; MAX_PACKET_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            // The most significant bits are send in the dhi byte
+            *byte = (im[i] & 0x7F) | ((im_arg.dhi() >> i) & 0x01) << 7;
+        }
+        Self::new(bytes.get(..len)?).ok()
+    }
+
+    /// Reads the DCC packet of `message`.
+    ///
+    /// # Returns
+    ///
+    /// The packet, if `message` is a [`Message::ImmPacket`] holding one.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        match message {
+            Message::ImmPacket(im_arg) => Self::from_im_arg(im_arg),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The immediate packet sending this packet `repeats` times more, up to 7 times.
+    pub fn to_im_arg(&self, repeats: u8) -> ImArg {
+        let mut dhi = 0x20;
+        let mut im = [0; MAX_PACKET_LEN];
+        for (i, byte) in self.bytes().iter().enumerate() {
+            dhi |= (byte >> 7) << i;
+            im[i] = byte & 0x7F;
+        }
+        let reps = (self.len as u8) << 4 | (repeats & 0x07);
+        ImArg::parse(0x7F, reps, dhi, im[0], im[1], im[2], im[3], im[4])
+    }
+
+    /// # Returns
+    ///
+    /// The [`Message::ImmPacket`] sending this packet `repeats` times more, up to 7 times.
+    pub fn to_message(&self, repeats: u8) -> Message {
+        Message::ImmPacket(self.to_im_arg(repeats))
+    }
+
+    /// # Returns
+    ///
+    /// The bytes of the packet, without the error detection byte.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// # Returns
+    ///
+    /// The error detection byte appended by the command station when sending the packet.
+    pub fn error_byte(&self) -> u8 {
+        self.bytes().iter().fold(0, |error, byte| error ^ byte)
+    }
+
+    /// Decodes the instruction of the packet.
+    ///
+    /// # Returns
+    ///
+    /// The command, or `None` if the packet holds an instruction not modeled by [`DccCommand`].
+    pub fn decode(&self) -> Option<DccCommand> {
+        let bytes = self.bytes();
+        let (address, instruction) = match bytes[0] {
+            0x00..=0x7F => (ImAddress::Short(bytes[0]), &bytes[1..]),
+            0x80..=0xBF => return self.decode_accessory(),
+            0xC0..=0xE7 => (
+                ImAddress::Long(((bytes[0] as u16 & 0x3F) << 8) | bytes[1] as u16),
+                &bytes[2..],
+            ),
+            _ => return None,
+        };
+
+        let functions = |group, functions| {
+            Some(DccCommand::Functions {
+                address,
+                group,
+                functions,
+            })
+        };
+        match *instruction {
+            [0x3F, speed] => Some(DccCommand::Speed {
+                address,
+                speed: SpeedArg::parse(speed & 0x7F),
+                forward: speed & 0x80 == 0x80,
+            }),
+            [group] if group & 0xE0 == 0x80 => functions(
+                DccFunctionGroup::F0to4,
+                ((group >> 4) & 0x01) | (group & 0x0F) << 1,
+            ),
+            [group] if group & 0xF0 == 0xB0 => functions(DccFunctionGroup::F5to8, group & 0x0F),
+            [group] if group & 0xF0 == 0xA0 => functions(DccFunctionGroup::F9to12, group & 0x0F),
+            [0xDE, bits] => functions(DccFunctionGroup::F13to20, bits),
+            [0xDF, bits] => functions(DccFunctionGroup::F21to28, bits),
+            [cv_high, cv_low, value] if cv_high & 0xFC == 0xEC => Some(DccCommand::WriteCv {
+                address,
+                cv: ((cv_high as u16 & 0x03) << 8 | cv_low as u16) + 1,
+                value,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Decodes the packet as switching an output of a basic accessory decoder
+    /// or setting the aspect of an extended accessory decoder.
+    fn decode_accessory(&self) -> Option<DccCommand> {
+        let output = |low: u8, high: u8| {
+            let decoder = (low as u16 & 0x3F) | ((!high as u16 >> 4) & 0x07) << 6;
+            // The decoder address 0 has no output numbered like the switches
+            Some(decoder.checked_sub(1)? * 4 + ((high >> 1) & 0x03) as u16)
+        };
+        match *self.bytes() {
+            [low, high] if high & 0x80 == 0x80 => Some(DccCommand::Accessory {
+                address: output(low, high)?,
+                direction: match high & 0x01 {
+                    0x01 => SwitchDirection::Straight,
+                    _ => SwitchDirection::Curved,
+                },
+                active: high & 0x08 == 0x08,
+            }),
+            [low, high, aspect] if high & 0x89 == 0x01 && aspect <= MAX_ASPECT => {
+                Some(DccCommand::ExtendedAccessory {
+                    address: output(low, high)?,
+                    aspect,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// A packet without any bytes, to push them to.
+    fn empty() -> Self {
+        DccPacket {
+            bytes: [0; MAX_PACKET_LEN],
+            len: 0,
+        }
+    }
+
+    /// Appends `byte` to the packet. The encoded commands never exceed the maximum length.
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.len] = byte;
+        self.len += 1;
+    }
+
+    /// Appends the locomotive `address` to the packet.
+    ///
+    /// # Errors
+    ///
+    /// - [`DccError::InvalidAddress`]: If the address can not be send in its format
+    fn push_address(&mut self, address: ImAddress) -> Result<(), DccError> {
+        match address {
+            ImAddress::Short(address) if address <= 0x7F => self.push(address),
+            ImAddress::Long(address) if address <= MAX_LONG_ADDRESS => {
+                self.push(0xC0 | (address >> 8) as u8);
+                self.push(address as u8);
+            }
+            ImAddress::Short(address) => return Err(DccError::InvalidAddress(address as u16)),
+            ImAddress::Long(address) => return Err(DccError::InvalidAddress(address)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use tokio::time::{Duration, Instant};
+
+/// Drops frames repeated within a window, as delivered by noisy optoisolated taps.
+///
+/// Only a frame identical to the directly preceding one is a duplicate.
+/// A duplicate does not extend the window, so a frame repeated periodically is passed once per window.
+#[derive(Debug, Clone)]
+pub(crate) struct DuplicateFilter {
+    /// How long an identical frame is treated as duplicate, or `None` to pass all frames
+    window: Option<Duration>,
+    /// The last passed frame and when it was read
+    last: Option<(Vec<u8>, Instant)>,
+}
+
+impl DuplicateFilter {
+    /// Creates a filter dropping identical frames read within `window`.
+    pub(crate) fn new(window: Option<Duration>) -> Self {
+        DuplicateFilter { window, last: None }
+    }
+
+    /// Checks the `frame` read at `at`.
+    ///
+    /// # Returns
+    ///
+    /// If the frame is a duplicate of the last passed frame and should be dropped.
+    pub(crate) fn is_duplicate(&mut self, frame: &[u8], at: Instant) -> bool {
+        let window = match self.window {
+            Some(window) => window,
+            None => return false,
+        };
+
+        if let Some((last, read_at)) = &self.last {
+            if last.as_slice() == frame && at.saturating_duration_since(*read_at) < window {
+                return true;
+            }
+        }
+
+        self.last = Some((frame.to_vec(), at));
+        false
+    }
+}
@@ -0,0 +1,143 @@
+use crate::args::SlotArg;
+use crate::protocol::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::{sleep_until, Duration, Instant};
+use tokio_serial::{
+    DataBits, Error, FlowControl, Parity, SerialPortBuilderExt, SerialPortType, StopBits,
+};
+
+/// How long to listen for frames on a port after sending the query.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A serial port found by [`crate::loco_controller::LocoDriveController::discover()`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PortCandidate {
+    /// The name of the serial port, like `/dev/ttyUSB0` or `COM3`
+    pub port_name: String,
+    /// The product name reported by the device, if connected by USB
+    pub product: Option<String>,
+    /// The baud rate valid frames were received with, or `None` if no frames were received
+    pub baud_rate: Option<u32>,
+    /// The count of valid frames received with that baud rate
+    pub frames: usize,
+}
+
+impl PortCandidate {
+    /// # Returns
+    ///
+    /// If the port answered with valid frames, so a model railroad is connected to it.
+    pub fn answered(&self) -> bool {
+        self.baud_rate.is_some()
+    }
+}
+
+/// Enumerates the serial ports and probes each of them with the `baud_candidates` in order.
+///
+/// Probing sends the harmless query [`Message::RqSlData`] of slot 0 and listens for valid frames,
+/// so the answer as well as other traffic on the bus are recognized.
+/// The first baud rate receiving frames is reported.
+pub(crate) async fn discover(baud_candidates: &[u32]) -> Vec<PortCandidate> {
+    let ports = match tokio_serial::available_ports() {
+        Ok(ports) => ports,
+        Err(err) => {
+            log_error!("Could not enumerate serial ports: {}", err);
+            return Vec::new();
+        }
+    };
+
+    let mut candidates = Vec::with_capacity(ports.len());
+    for port in ports {
+        let mut candidate = PortCandidate {
+            product: match port.port_type {
+                SerialPortType::UsbPort(usb) => usb.product,
+                _ => None,
+            },
+            port_name: port.port_name,
+            baud_rate: None,
+            frames: 0,
+        };
+
+        for &baud_rate in baud_candidates {
+            match probe(&candidate.port_name, baud_rate).await {
+                Ok(0) => {}
+                Ok(frames) => {
+                    candidate.baud_rate = Some(baud_rate);
+                    candidate.frames = frames;
+                    break;
+                }
+                // The port is not usable at all, so other baud rates will fail as well
+                Err(_) => break,
+            }
+        }
+
+        candidates.push(candidate);
+    }
+
+    candidates
+}
+
+/// Sends the query to the port and counts the valid frames received until the timeout.
+async fn probe(port_name: &str, baud_rate: u32) -> Result<usize, Error> {
+    let mut port = tokio_serial::new(port_name, baud_rate)
+        .data_bits(DataBits::Eight)
+        .stop_bits(StopBits::Two)
+        .parity(Parity::None)
+        .flow_control(FlowControl::None)
+        .open_native_async()?;
+
+    port.write_all(&Message::RqSlData(SlotArg::new(0)).to_message())
+        .await?;
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+
+    loop {
+        let read = tokio::select! {
+            read = port.read(&mut buf) => read?,
+            _ = sleep_until(deadline) => break,
+        };
+        if read == 0 {
+            break;
+        }
+        received.extend_from_slice(&buf[..read]);
+    }
+
+    Ok(count_frames(&received))
+}
+
+/// Counts the valid frames in `bytes`, skipping bytes not belonging to one.
+///
+/// A frame is valid if its op code is known and its checksum is correct.
+/// The frames are not parsed, as noise on ports without a model railroad may look like frames.
+pub(crate) fn count_frames(bytes: &[u8]) -> usize {
+    let mut frames = 0;
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let opc = bytes[start];
+        let len = match opc & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            0xE0 if start + 1 < bytes.len() => bytes[start + 1] as usize,
+            _ => 0,
+        };
+
+        if len >= 2
+            && start + len <= bytes.len()
+            && Message::known_opc(opc)
+            && bytes[start..start + len]
+                .iter()
+                .fold(0u8, |checksum, byte| checksum ^ byte)
+                == 0xFF
+        {
+            frames += 1;
+            start += len;
+        } else {
+            start += 1;
+        }
+    }
+
+    frames
+}
@@ -0,0 +1,301 @@
+use crate::error::{EmbeddedError, MessageParseError};
+use crate::protocol::{Message, MAX_MESSAGE_LEN};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// How many bytes are requested from the transport at once.
+const READ_CHUNK: usize = 32;
+
+/// Decodes the frames of the bytes read from a serial transport into messages.
+///
+/// Firmware reading the UART itself, like from an interrupt handler, pushes the read bytes
+/// and takes the decoded messages, while the sessions of this module do so on their own.
+/// Bytes before the first op code are dropped, as they do not belong to a frame.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::embedded::FrameDecoder;
+/// use locodrive::protocol::Message;
+///
+/// let mut decoder = FrameDecoder::new();
+/// decoder.push(&[0x83]);
+/// assert!(decoder.next_message().is_none());
+///
+/// decoder.push(&[0x7C]);
+/// assert_eq!(decoder.next_message().unwrap().unwrap(), Message::GpOn);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FrameDecoder {
+    /// The bytes read but not decoded to a frame yet
+    pending: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Creates a decoder without pending bytes.
+    pub fn new() -> Self {
+        FrameDecoder::default()
+    }
+
+    /// Appends the `bytes` read from the transport.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    /// Decodes the next frame from the pushed bytes and removes it.
+    ///
+    /// # Returns
+    ///
+    /// The parsed frame or `None` if no frame is complete yet.
+    /// A frame that could not be parsed is removed as well,
+    /// so decoding again continues with the following frame.
+    pub fn next_message(&mut self) -> Option<Result<Message, MessageParseError>> {
+        // Only op codes have the most significant bit set
+        let start = self
+            .pending
+            .iter()
+            .position(|byte| byte & 0x80 != 0)
+            .unwrap_or(self.pending.len());
+        self.pending.drain(..start);
+
+        let len = match self.pending.first()? & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            _ => *self.pending.get(1)? as usize,
+        };
+
+        // A frame shorter than its op code and checksum is skipped with its op code
+        let len = len.max(1);
+        if self.pending.len() < len {
+            return None;
+        }
+
+        let frame: Vec<u8> = self.pending.drain(..len).collect();
+        Some(Message::parse(&frame))
+    }
+}
+
+/// Talks to the model railroad over any serial transport implementing the
+/// `embedded-io-async` traits, without depending on tokio.
+///
+/// The session runs on any async executor, like embassy, and brings the frame decoding
+/// and echo handling of the [`crate::loco_controller::LocoDriveController`] to it:
+/// Messages are written as whole frames and sending completes when the model railroad
+/// echoed the message. Messages received while awaiting the echo are kept for
+/// [`EmbeddedSession::receive()`], so no message is lost.
+///
+/// Timeouts are left to the executor, as there is no runtime independent timer.
+/// Wrap the calls into the timeout of your executor to not wait forever on a silent bus.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::embedded::EmbeddedSession;
+/// use locodrive::protocol::Message;
+///
+/// # async fn run<T: embedded_io_async::Read + embedded_io_async::Write>(uart: T) {
+/// let mut session = EmbeddedSession::new(uart);
+/// session.send(Message::GpOn).await.unwrap();
+///
+/// while let Ok(message) = session.receive().await {
+///     println!("Received {:?}", message);
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EmbeddedSession<T> {
+    /// The serial transport to the model railroad
+    transport: T,
+    /// Decodes the read bytes to messages
+    decoder: FrameDecoder,
+    /// The messages received while awaiting an echo
+    received: VecDeque<Message>,
+}
+
+impl<T: embedded_io_async::Read + embedded_io_async::Write> EmbeddedSession<T> {
+    /// Creates a session talking over `transport`.
+    pub fn new(transport: T) -> Self {
+        EmbeddedSession {
+            transport,
+            decoder: FrameDecoder::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The serial transport of this session. Bytes read but not decoded yet are lost.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Writes `message` and waits until the model railroad echoed it.
+    ///
+    /// # Errors
+    ///
+    /// - [`EmbeddedError::Transport`]: If the transport failed to read or write
+    /// - [`EmbeddedError::Closed`]: If the transport was closed before the echo was received
+    ///
+    /// Frames not parseable while awaiting the echo are skipped.
+    pub async fn send(&mut self, message: Message) -> Result<(), EmbeddedError<T::Error>> {
+        // Encodes into a stack buffer, which every message fits into
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let len = message.write_to(&mut buf).unwrap_or_default();
+        self.transport
+            .write_all(&buf[..len])
+            .await
+            .map_err(EmbeddedError::Transport)?;
+        self.transport
+            .flush()
+            .await
+            .map_err(EmbeddedError::Transport)?;
+
+        loop {
+            match self.read_message().await {
+                Ok(echo) if echo == message => return Ok(()),
+                Ok(other) => self.received.push_back(other),
+                Err(EmbeddedError::Parse(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Receives the next message from the model railroad.
+    ///
+    /// # Errors
+    ///
+    /// - [`EmbeddedError::Transport`]: If the transport failed to read
+    /// - [`EmbeddedError::Closed`]: If the transport was closed
+    /// - [`EmbeddedError::Parse`]: If the next frame could not be parsed. It is skipped,
+    ///   so receiving again continues with the following frame.
+    pub async fn receive(&mut self) -> Result<Message, EmbeddedError<T::Error>> {
+        match self.received.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read_message().await,
+        }
+    }
+
+    /// Reads from the transport until the next frame is decoded.
+    async fn read_message(&mut self) -> Result<Message, EmbeddedError<T::Error>> {
+        loop {
+            if let Some(frame) = self.decoder.next_message() {
+                return frame.map_err(EmbeddedError::Parse);
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            match self.transport.read(&mut chunk).await {
+                Ok(0) => return Err(EmbeddedError::Closed),
+                Ok(read) => self.decoder.push(&chunk[..read]),
+                Err(err) => return Err(EmbeddedError::Transport(err)),
+            }
+        }
+    }
+}
+
+/// Talks to the model railroad over any serial transport implementing the blocking
+/// `embedded-io` traits, like the UART peripherals of most hal crates.
+///
+/// It handles the frames and echoes like the [`EmbeddedSession`], but blocks on reading and writing,
+/// so firmware without an async executor can use it.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::embedded::BlockingEmbeddedSession;
+/// use locodrive::protocol::Message;
+///
+/// # fn run<T: embedded_io::Read + embedded_io::Write>(uart: T) {
+/// let mut session = BlockingEmbeddedSession::new(uart);
+/// session.send(Message::GpOn).unwrap();
+///
+/// while let Ok(message) = session.receive() {
+///     println!("Received {:?}", message);
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BlockingEmbeddedSession<T> {
+    /// The serial transport to the model railroad
+    transport: T,
+    /// Decodes the read bytes to messages
+    decoder: FrameDecoder,
+    /// The messages received while awaiting an echo
+    received: VecDeque<Message>,
+}
+
+impl<T: embedded_io::Read + embedded_io::Write> BlockingEmbeddedSession<T> {
+    /// Creates a session talking over `transport`.
+    pub fn new(transport: T) -> Self {
+        BlockingEmbeddedSession {
+            transport,
+            decoder: FrameDecoder::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The serial transport of this session. Bytes read but not decoded yet are lost.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Writes `message` and blocks until the model railroad echoed it.
+    ///
+    /// # Errors
+    ///
+    /// - [`EmbeddedError::Transport`]: If the transport failed to read or write
+    /// - [`EmbeddedError::Closed`]: If the transport was closed before the echo was received
+    ///
+    /// Frames not parseable while awaiting the echo are skipped.
+    pub fn send(&mut self, message: Message) -> Result<(), EmbeddedError<T::Error>> {
+        // Encodes into a stack buffer, which every message fits into
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let len = message.write_to(&mut buf).unwrap_or_default();
+        self.transport
+            .write_all(&buf[..len])
+            .map_err(EmbeddedError::Transport)?;
+        self.transport.flush().map_err(EmbeddedError::Transport)?;
+
+        loop {
+            match self.read_message() {
+                Ok(echo) if echo == message => return Ok(()),
+                Ok(other) => self.received.push_back(other),
+                Err(EmbeddedError::Parse(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Blocks until the next message from the model railroad is received.
+    ///
+    /// # Errors
+    ///
+    /// - [`EmbeddedError::Transport`]: If the transport failed to read
+    /// - [`EmbeddedError::Closed`]: If the transport was closed
+    /// - [`EmbeddedError::Parse`]: If the next frame could not be parsed. It is skipped,
+    ///   so receiving again continues with the following frame.
+    pub fn receive(&mut self) -> Result<Message, EmbeddedError<T::Error>> {
+        match self.received.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read_message(),
+        }
+    }
+
+    /// Reads from the transport until the next frame is decoded.
+    fn read_message(&mut self) -> Result<Message, EmbeddedError<T::Error>> {
+        loop {
+            if let Some(frame) = self.decoder.next_message() {
+                return frame.map_err(EmbeddedError::Parse);
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            match self.transport.read(&mut chunk) {
+                Ok(0) => return Err(EmbeddedError::Closed),
+                Ok(read) => self.decoder.push(&chunk[..read]),
+                Err(err) => return Err(EmbeddedError::Transport(err)),
+            }
+        }
+    }
+}
@@ -1,48 +1,273 @@
+#[cfg(any(feature = "control", feature = "blocking"))]
+use crate::args::{Ack1Arg, SlotArg};
+#[cfg(feature = "control")]
+use crate::signals::Aspect;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
 use std::io;
 
 /// Represents an Error occurring when a message was received
 /// but could not be passed correctly to a valid and known message.
+///
+/// The errors of invalid frames carry the bytes read of the frame and the position of the
+/// offending byte in it, so the traffic can be logged for bug reports.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum MessageParseError {
     /// The OpCode of the message was unknown, maybe that code is not implemented yet.
     /// Please report this to the contributor.
-    UnknownOpcode(u8),
+    UnknownOpcode {
+        /// The unknown op code
+        opc: u8,
+        /// The bytes read of the frame
+        frame: Vec<u8>,
+        /// The position of the offending byte in the frame
+        offset: usize,
+    },
     /// The messages length did not match the expected message length.
-    UnexpectedEnd(u8),
+    UnexpectedEnd {
+        /// The op code of the message
+        opc: u8,
+        /// The bytes read of the frame
+        frame: Vec<u8>,
+        /// The position the frame ended at or of the length not matching the message
+        offset: usize,
+    },
     /// Some expected message format bytes did not contain the expected value.
-    InvalidFormat(String),
+    InvalidFormat {
+        /// Describes the invalid format
+        message: String,
+        /// The bytes read of the frame
+        frame: Vec<u8>,
+        /// The position of the offending byte in the frame
+        offset: usize,
+    },
     /// The checksum could not be validated. The received message is corrupted. Please retry sending.
-    InvalidChecksum(u8),
+    InvalidChecksum {
+        /// The op code of the message
+        opc: u8,
+        /// The bytes read of the frame
+        frame: Vec<u8>,
+        /// The position of the checksum in the frame
+        offset: usize,
+    },
+    /// The frame was cut short by the op code of the next frame, as bytes of it were lost.
+    /// Reading resynchronized at that op code, so the next frame is not discarded.
+    Truncated {
+        /// The op code of the message
+        opc: u8,
+        /// The bytes read of the frame
+        frame: Vec<u8>,
+        /// The position of the next op code, which is the length of the read frame
+        offset: usize,
+    },
     /// This is used only by the controller to receive and handle a shutdown request.
     Update,
+    /// The reading thread panicked while handling a message and was restarted.
+    /// Please report this to the contributor with the given panic message.
+    ReaderPanicked(String),
+}
+
+impl MessageParseError {
+    /// Creates an [`MessageParseError::UnknownOpcode`] error without the frame.
+    pub(crate) fn unknown_opcode(opc: u8) -> Self {
+        Self::UnknownOpcode {
+            opc,
+            frame: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Creates an [`MessageParseError::UnexpectedEnd`] error at `offset` without the frame.
+    pub(crate) fn unexpected_end(opc: u8, offset: usize) -> Self {
+        Self::UnexpectedEnd {
+            opc,
+            frame: Vec::new(),
+            offset,
+        }
+    }
+
+    /// Creates an [`MessageParseError::InvalidFormat`] error at `offset` without the frame.
+    pub(crate) fn invalid_format(message: String, offset: usize) -> Self {
+        Self::InvalidFormat {
+            message,
+            frame: Vec::new(),
+            offset,
+        }
+    }
+
+    /// Creates an [`MessageParseError::InvalidChecksum`] error at `offset` without the frame.
+    pub(crate) fn invalid_checksum(opc: u8, offset: usize) -> Self {
+        Self::InvalidChecksum {
+            opc,
+            frame: Vec::new(),
+            offset,
+        }
+    }
+
+    /// Creates an [`MessageParseError::Truncated`] error at `offset` without the frame.
+    #[cfg(feature = "control")]
+    pub(crate) fn truncated(opc: u8, offset: usize) -> Self {
+        Self::Truncated {
+            opc,
+            frame: Vec::new(),
+            offset,
+        }
+    }
+
+    /// Attaches the bytes of `read` as frame, if the error has no frame yet.
+    pub(crate) fn with_frame(mut self, read: &[u8]) -> Self {
+        match &mut self {
+            Self::UnknownOpcode { frame, .. }
+            | Self::UnexpectedEnd { frame, .. }
+            | Self::InvalidFormat { frame, .. }
+            | Self::InvalidChecksum { frame, .. }
+            | Self::Truncated { frame, .. }
+                if frame.is_empty() =>
+            {
+                frame.extend_from_slice(read)
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The bytes read of the invalid frame, which are empty if no frame is known.
+    pub fn frame(&self) -> &[u8] {
+        match self {
+            Self::UnknownOpcode { frame, .. }
+            | Self::UnexpectedEnd { frame, .. }
+            | Self::InvalidFormat { frame, .. }
+            | Self::InvalidChecksum { frame, .. }
+            | Self::Truncated { frame, .. } => frame,
+            Self::Update | Self::ReaderPanicked(_) => &[],
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The position of the offending byte in [`MessageParseError::frame()`],
+    /// or `None` if the error is not caused by an invalid frame.
+    pub fn offset(&self) -> Option<usize> {
+        match *self {
+            Self::UnknownOpcode { offset, .. }
+            | Self::UnexpectedEnd { offset, .. }
+            | Self::InvalidFormat { offset, .. }
+            | Self::InvalidChecksum { offset, .. }
+            | Self::Truncated { offset, .. } => Some(offset),
+            Self::Update | Self::ReaderPanicked(_) => None,
+        }
+    }
 }
 
 impl Display for MessageParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match *self {
-            Self::UnknownOpcode(opc) => write!(f, "unknown opcode: {:x}", opc),
-            Self::UnexpectedEnd(opc) => write!(f, "unexpected end of stream, while reading message with opcode: {:x}", opc),
-            Self::InvalidChecksum(opc) => write!(f, "invalid checksum, while reading message with opcode: {:x}", opc),
-            Self::Update => write!(f, "update"),
-            Self::InvalidFormat(ref message) => write!(f, "invalid format: {:?}", message),
+            Self::UnknownOpcode { opc, .. } => write!(f, "unknown opcode: {:x}", opc)?,
+            Self::UnexpectedEnd { opc, .. } => write!(
+                f,
+                "unexpected end of stream, while reading message with opcode: {:x}",
+                opc
+            )?,
+            Self::InvalidChecksum { opc, .. } => write!(
+                f,
+                "invalid checksum, while reading message with opcode: {:x}",
+                opc
+            )?,
+            Self::Truncated { opc, .. } => write!(
+                f,
+                "truncated by the next opcode, while reading message with opcode: {:x}",
+                opc
+            )?,
+            Self::Update => write!(f, "update")?,
+            Self::InvalidFormat { ref message, .. } => write!(f, "invalid format: {:?}", message)?,
+            Self::ReaderPanicked(ref message) => write!(f, "reader panicked: {}", message)?,
         }
+
+        if let (false, Some(offset)) = (self.frame().is_empty(), self.offset()) {
+            write!(f, " (frame:")?;
+            for byte in self.frame() {
+                write!(f, " {:02X}", byte)?;
+            }
+            write!(f, ", at byte {})", offset)?;
+        }
+        Ok(())
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for MessageParseError {}
 
+#[cfg(feature = "std")]
 impl From<io::Error> for MessageParseError {
     fn from(err: io::Error) -> Self {
-        MessageParseError::InvalidFormat(err.to_string())
+        MessageParseError::invalid_format(err.to_string(), 0)
+    }
+}
+
+/// Represents an Error occurring when a message could not be written to a buffer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum EncodeError {
+    /// The buffer was too small. Holds the length of the message and the length of the buffer.
+    BufferTooSmall(usize, usize),
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::BufferTooSmall(len, available) => write!(
+                f,
+                "buffer too small, message needs {} bytes, but only {} are available",
+                len, available
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for EncodeError {}
+
+/// Represents an Error occurring when a DCC packet could not be built, see [`crate::dcc`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DccError {
+    /// The packet does not have 2 to 5 bytes. Holds the length of the packet.
+    InvalidLength(usize),
+    /// The address can not be send in its format. Holds the address.
+    InvalidAddress(u16),
+    /// The cv is not between 1 and 1024. Holds the cv.
+    InvalidCv(u16),
+    /// The aspect of an extended accessory decoder is not between 0 and 31. Holds the aspect.
+    InvalidAspect(u8),
+}
+
+impl Display for DccError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::InvalidLength(len) => {
+                write!(f, "dcc packets have 2 to 5 bytes, but got {} bytes", len)
+            }
+            Self::InvalidAddress(address) => write!(f, "invalid dcc address {}", address),
+            Self::InvalidCv(cv) => write!(f, "invalid cv {}, expected 1 to 1024", cv),
+            Self::InvalidAspect(aspect) => {
+                write!(f, "invalid aspect {}, expected 0 to 31", aspect)
+            }
+        }
     }
 }
 
+#[cfg(feature = "std")]
+impl Error for DccError {}
+
 /// This error type is used to describe errors appearing on [`crate::loco_controller::LocoDriveController::send_message()`].
-/// This error comes with the `control` feature. You have to explicitly activate it.
+/// This error comes with the `control` and the `blocking` feature. You have to explicitly activate one of them.
 #[derive(Debug, Copy, Clone)]
-#[cfg(feature = "control")]
+#[cfg(any(feature = "control", feature = "blocking"))]
 pub enum LocoDriveSendingError {
     /// If the reader is closed. This should not happen normally.
     /// If it happens your [`crate::loco_controller::LocoDriveController`] is corrupted and can no longer be used.
@@ -52,18 +277,584 @@ pub enum LocoDriveSendingError {
     /// The railroad control system connection returns writing with an error.
     /// Please recheck your connection.
     NotWritable,
+    /// The railroad control system answered the message with a failed acknowledgment.
+    /// It may be busy, so retrying later could succeed.
+    Rejected(Ack1Arg),
+    /// The message writes to the reserved system slot (120 - 126) and was blocked.
+    /// Use the dedicated APIs for the system slots or allow writing to them explicitly.
+    ReservedSlot(SlotArg),
+    /// Sending was cancelled by its cancellation token before it completed.
+    /// The message may have been written already.
+    Cancelled,
+    /// The message could not be encoded, so it was not written.
+    Encode(EncodeError),
 }
 
-#[cfg(feature = "control")]
+#[cfg(any(feature = "control", feature = "blocking"))]
 impl Display for LocoDriveSendingError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match *self {
             Self::Timeout => write!(f, "connection timed out"),
             Self::NotWritable => write!(f, "could not write to port"),
             Self::IllegalState => write!(f, "connection in illegal state"),
+            Self::Rejected(ack) => write!(f, "message rejected: {}", ack),
+            Self::ReservedSlot(slot) => write!(f, "write to reserved slot {}", slot.slot()),
+            Self::Cancelled => write!(f, "sending cancelled"),
+            Self::Encode(err) => write!(f, "could not encode message: {}", err),
         }
     }
 }
 
-#[cfg(feature = "control")]
+#[cfg(any(feature = "control", feature = "blocking"))]
 impl Error for LocoDriveSendingError {}
+
+/// This error type is used to describe errors appearing on programming with a
+/// [`crate::programmer::Programmer`].
+/// This error comes with the `control` feature. You have to explicitly activate it.
+#[derive(Debug, Copy, Clone)]
+#[cfg(feature = "control")]
+pub enum ProgrammingError {
+    /// The programming track is busy with another task.
+    ProgrammingTrackBusy,
+    /// The track power is on, but the interlock requires it to be off for service mode programming.
+    TrackPowerOn,
+    /// The track status is not known yet, but the interlock requires the track power to be off.
+    /// Request any slot data to receive the track status.
+    TrackStatusUnknown,
+    /// The programming task could not be send.
+    Sending(LocoDriveSendingError),
+    /// The programming task was aborted by a user or the command station.
+    Aborted,
+    /// No decoder was found on the programming track.
+    NoDecoder,
+    /// The decoder did not acknowledge the programming task.
+    NoAck,
+    /// The result of the programming task was not received in time.
+    Timeout,
+    /// The cv read back after writing holds another value.
+    /// The arguments are the cv, the written and the read value.
+    VerifyFailed(u16, u8, u8),
+}
+
+#[cfg(feature = "control")]
+impl Display for ProgrammingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::ProgrammingTrackBusy => write!(f, "programming track busy"),
+            Self::TrackPowerOn => write!(f, "track power is on"),
+            Self::TrackStatusUnknown => write!(f, "track status unknown"),
+            Self::Sending(err) => write!(f, "sending failed: {}", err),
+            Self::Aborted => write!(f, "programming aborted"),
+            Self::NoDecoder => write!(f, "no decoder on programming track"),
+            Self::NoAck => write!(f, "decoder did not acknowledge"),
+            Self::Timeout => write!(f, "programming result timed out"),
+            Self::VerifyFailed(cv, written, read) => {
+                write!(f, "cv {} holds {} instead of written {}", cv, read, written)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl Error for ProgrammingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Sending(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<LocoDriveSendingError> for ProgrammingError {
+    fn from(err: LocoDriveSendingError) -> Self {
+        ProgrammingError::Sending(err)
+    }
+}
+
+/// This error type is used to describe errors appearing on building consists with a
+/// [`crate::consist::ConsistManager`].
+/// This error comes with the `control` feature. You have to explicitly activate it.
+#[derive(Debug, Copy, Clone)]
+#[cfg(feature = "control")]
+pub enum ConsistError {
+    /// The slot is no top of a consist and could not become one, as it is linked to another slot.
+    NotTop(SlotArg),
+    /// The slot is already linked to a consist.
+    Linked(SlotArg),
+    /// The slot is no member of the consist.
+    NotMember(SlotArg),
+    /// The slots could not be read or linked.
+    Sending(LocoDriveSendingError),
+}
+
+#[cfg(feature = "control")]
+impl Display for ConsistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::NotTop(slot) => write!(f, "slot {} is no consist top", slot.slot()),
+            Self::Linked(slot) => write!(f, "slot {} is already linked", slot.slot()),
+            Self::NotMember(slot) => write!(f, "slot {} is no consist member", slot.slot()),
+            Self::Sending(err) => write!(f, "sending failed: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl Error for ConsistError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Sending(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<LocoDriveSendingError> for ConsistError {
+    fn from(err: LocoDriveSendingError) -> Self {
+        ConsistError::Sending(err)
+    }
+}
+
+/// This error type is used to describe turnouts that could not be switched,
+/// when setting a route with a [`crate::routes::RouteSetter`].
+/// This error comes with the `control` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "control")]
+pub struct RouteError {
+    /// The name of the route
+    pub route: String,
+    /// The count of turnouts in the route
+    pub steps: usize,
+    /// The addresses of the turnouts that could not be switched, with the reason
+    pub failed: Vec<(u16, LocoDriveSendingError)>,
+}
+
+#[cfg(feature = "control")]
+impl Display for RouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "route {}: {} of {} turnouts failed",
+            self.route,
+            self.failed.len(),
+            self.steps
+        )?;
+        for (address, err) in &self.failed {
+            write!(f, ", turnout {}: {}", address, err)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "control")]
+impl Error for RouteError {}
+
+/// This error type is used to describe errors appearing on setting the aspect of a signal
+/// with a [`crate::signals::SignalSetter`].
+/// This error comes with the `control` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "control")]
+pub enum SignalError {
+    /// No signal has the name. Holds the name.
+    UnknownSignal(String),
+    /// The signal can not show the aspect. Holds the name of the signal and the aspect.
+    UnknownAspect(String, Aspect),
+    /// The immediate packet of an extended accessory decoder could not be encoded.
+    Dcc(DccError),
+    /// A message showing the aspect could not be send.
+    Sending(LocoDriveSendingError),
+}
+
+#[cfg(feature = "control")]
+impl Display for SignalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownSignal(name) => write!(f, "unknown signal {}", name),
+            Self::UnknownAspect(name, aspect) => {
+                write!(f, "signal {} can not show {}", name, aspect)
+            }
+            Self::Dcc(err) => write!(f, "{}", err),
+            Self::Sending(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl Error for SignalError {}
+
+/// This error type is used to describe errors appearing on an [`crate::embedded::EmbeddedSession`]
+/// or a [`crate::embedded::BlockingEmbeddedSession`].
+/// The argument of [`EmbeddedError::Transport`] is the error type of the serial transport.
+/// This error comes with the `embedded` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "embedded")]
+pub enum EmbeddedError<E> {
+    /// The serial transport failed to read or write.
+    Transport(E),
+    /// The serial transport was closed, so no more bytes could be read or written.
+    Closed,
+    /// A received frame could not be parsed. The frame was skipped.
+    Parse(MessageParseError),
+}
+
+#[cfg(feature = "embedded")]
+impl<E: core::fmt::Debug> Display for EmbeddedError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Transport(ref err) => write!(f, "transport failed: {:?}", err),
+            Self::Closed => write!(f, "transport closed"),
+            Self::Parse(ref err) => write!(f, "could not parse frame: {}", err),
+        }
+    }
+}
+
+#[cfg(all(feature = "embedded", feature = "std"))]
+impl<E: core::fmt::Debug + 'static> Error for EmbeddedError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This error type is used to describe errors appearing on a [`crate::websocket::WebSocketTransport`].
+/// This error comes with the `wasm` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "wasm")]
+pub enum WebSocketError {
+    /// The WebSocket could not be opened. Holds the reason given by the browser.
+    Connect(String),
+    /// The WebSocket was closed.
+    Closed,
+    /// A message could not be send. Holds the reason given by the browser or the bridge.
+    Send(String),
+    /// A received frame could not be parsed. The frame was skipped.
+    Parse(MessageParseError),
+}
+
+#[cfg(feature = "wasm")]
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Connect(ref reason) => write!(f, "could not connect: {}", reason),
+            Self::Closed => write!(f, "websocket closed"),
+            Self::Send(ref reason) => write!(f, "could not send: {}", reason),
+            Self::Parse(ref err) => write!(f, "could not parse frame: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Error for WebSocketError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This error type is used to describe errors appearing on a [`crate::mobile::MobileController`]
+/// and its [`crate::mobile::Throttle`]s. It is passed to Kotlin and Swift by its message.
+/// This error comes with the `mobile` feature. You have to explicitly activate it.
+#[derive(Debug, Clone, uniffi::Error)]
+#[uniffi(flat_error)]
+#[cfg(feature = "mobile")]
+pub enum MobileError {
+    /// The controller could not be connected. Holds the reason.
+    Connect(String),
+    /// A message could not be send to the model railroad.
+    Sending(LocoDriveSendingError),
+    /// The function number is not supported. Only the functions 0 to 8 can be set.
+    UnknownFunction(u8),
+}
+
+#[cfg(feature = "mobile")]
+impl Display for MobileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Connect(ref reason) => write!(f, "could not connect: {}", reason),
+            Self::Sending(ref err) => write!(f, "could not send: {}", err),
+            Self::UnknownFunction(function) => write!(f, "unknown function {}", function),
+        }
+    }
+}
+
+#[cfg(feature = "mobile")]
+impl Error for MobileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Sending(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "mobile")]
+impl From<LocoDriveSendingError> for MobileError {
+    fn from(err: LocoDriveSendingError) -> Self {
+        MobileError::Sending(err)
+    }
+}
+
+/// This error type is used to describe errors appearing on importing a layout
+/// by [`crate::rocrail::import_plan()`].
+/// This error comes with the `rocrail` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "rocrail")]
+pub enum LayoutImportError {
+    /// The file to import could not be read.
+    Io(String),
+    /// The file to import is no valid xml.
+    Xml(String),
+    /// An element misses a required attribute.
+    /// Holds the elements tag name and the attributes name.
+    MissingAttribute(String, String),
+    /// An attribute holds an invalid value.
+    /// Holds the elements id and the attributes name.
+    InvalidAttribute(String, String),
+}
+
+#[cfg(feature = "rocrail")]
+impl Display for LayoutImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Io(ref message) => write!(f, "could not read layout: {}", message),
+            Self::Xml(ref message) => write!(f, "invalid xml: {}", message),
+            Self::MissingAttribute(ref element, ref attribute) => {
+                write!(f, "element {} misses attribute {}", element, attribute)
+            }
+            Self::InvalidAttribute(ref id, ref attribute) => {
+                write!(f, "invalid value of attribute {} at {}", attribute, id)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rocrail")]
+impl Error for LayoutImportError {}
+
+#[cfg(feature = "rocrail")]
+impl From<io::Error> for LayoutImportError {
+    fn from(err: io::Error) -> Self {
+        LayoutImportError::Io(err.to_string())
+    }
+}
+
+/// This error type is used to describe errors appearing on loading a
+/// [`crate::config::LocodriveConfig`] or starting a [`crate::runtime::LayoutRuntime`] from it.
+/// This error comes with the `config` feature. You have to explicitly activate it.
+#[derive(Debug, Clone)]
+#[cfg(feature = "config")]
+pub enum ConfigError {
+    /// A file could not be read.
+    Io(String),
+    /// A file holds no valid configuration or layout.
+    Parse(String),
+    /// The format of the file is not supported. Holds the files path.
+    UnsupportedFormat(String),
+    /// A configured value is invalid.
+    /// Holds the values name and the reason.
+    Invalid(String, String),
+    /// The serial port is not reachable or could not be configured.
+    Connection(tokio_serial::Error),
+}
+
+#[cfg(feature = "config")]
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Io(ref message) => write!(f, "could not read file: {}", message),
+            Self::Parse(ref message) => write!(f, "invalid configuration: {}", message),
+            Self::UnsupportedFormat(ref path) => write!(f, "unsupported file format: {}", path),
+            Self::Invalid(ref name, ref reason) => write!(f, "invalid {}: {}", name, reason),
+            Self::Connection(ref err) => write!(f, "could not connect: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Connection(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err.to_string())
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<tokio_serial::Error> for ConfigError {
+    fn from(err: tokio_serial::Error) -> Self {
+        ConfigError::Connection(err)
+    }
+}
+
+/// The error type covering all errors of a connection to the model railroad,
+/// so applications can use `?` across the crate and keep the cause of each error
+/// as its [`Error::source()`].
+/// This error comes with the `control` feature. You have to explicitly activate it.
+#[derive(Debug)]
+#[non_exhaustive]
+#[cfg(feature = "control")]
+pub enum LocoDriveError {
+    /// A received message could not be parsed.
+    Parse(MessageParseError),
+    /// A message could not be encoded.
+    Encode(EncodeError),
+    /// A message could not be send.
+    Send(LocoDriveSendingError),
+    /// The serial port is not reachable or could not be configured.
+    Transport(tokio_serial::Error),
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// Programming a decoder failed.
+    Programming(ProgrammingError),
+    /// Building a consist failed.
+    Consist(ConsistError),
+    /// Setting a route failed.
+    Route(RouteError),
+}
+
+#[cfg(feature = "control")]
+impl Display for LocoDriveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Parse(_) => write!(f, "could not parse message"),
+            Self::Encode(_) => write!(f, "could not encode message"),
+            Self::Send(_) => write!(f, "could not send message"),
+            Self::Transport(_) => write!(f, "serial port failed"),
+            Self::Io(_) => write!(f, "reading or writing failed"),
+            Self::Programming(_) => write!(f, "programming failed"),
+            Self::Consist(_) => write!(f, "consist failed"),
+            Self::Route(_) => write!(f, "route failed"),
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl Error for LocoDriveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(err) => Some(err),
+            Self::Encode(err) => Some(err),
+            Self::Send(err) => Some(err),
+            Self::Transport(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::Programming(err) => Some(err),
+            Self::Consist(err) => Some(err),
+            Self::Route(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<MessageParseError> for LocoDriveError {
+    fn from(err: MessageParseError) -> Self {
+        LocoDriveError::Parse(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<EncodeError> for LocoDriveError {
+    fn from(err: EncodeError) -> Self {
+        LocoDriveError::Encode(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<LocoDriveSendingError> for LocoDriveError {
+    fn from(err: LocoDriveSendingError) -> Self {
+        LocoDriveError::Send(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<tokio_serial::Error> for LocoDriveError {
+    fn from(err: tokio_serial::Error) -> Self {
+        LocoDriveError::Transport(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<io::Error> for LocoDriveError {
+    fn from(err: io::Error) -> Self {
+        LocoDriveError::Io(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<ProgrammingError> for LocoDriveError {
+    fn from(err: ProgrammingError) -> Self {
+        LocoDriveError::Programming(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<ConsistError> for LocoDriveError {
+    fn from(err: ConsistError) -> Self {
+        LocoDriveError::Consist(err)
+    }
+}
+
+#[cfg(feature = "control")]
+impl From<RouteError> for LocoDriveError {
+    fn from(err: RouteError) -> Self {
+        LocoDriveError::Route(err)
+    }
+}
+
+/// Represents an Error occurring when a JMRI JSON message could not be read,
+/// see [`crate::jmri::JmriJson`].
+/// This error comes with the `jmri` feature. You have to explicitly activate it.
+#[derive(Debug)]
+#[cfg(feature = "jmri")]
+pub enum JmriError {
+    /// The message is no JSON of the expected shape.
+    Json(serde_json::Error),
+    /// The message holds another type of object. Holds the type.
+    UnexpectedType(String),
+    /// The name is no LocoNet name of the type. Holds the name.
+    InvalidName(String),
+    /// The state is unknown or inconsistent. Holds the state.
+    InvalidState(u8),
+}
+
+#[cfg(feature = "jmri")]
+impl Display for JmriError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "invalid jmri message: {}", err),
+            Self::UnexpectedType(kind) => write!(f, "unexpected jmri type {}", kind),
+            Self::InvalidName(name) => write!(f, "invalid loconet name {}", name),
+            Self::InvalidState(state) => write!(f, "invalid jmri state {}", state),
+        }
+    }
+}
+
+#[cfg(feature = "jmri")]
+impl Error for JmriError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "jmri")]
+impl From<serde_json::Error> for JmriError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
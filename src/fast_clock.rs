@@ -0,0 +1,309 @@
+use crate::args::{FastClock, IdArg, TrkArg, WrSlDataStructure};
+use crate::loco_controller::{LocoDriveController, LocoDriveMessage, SendOptions};
+use crate::protocol::Message;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+/// The seconds of one day.
+const DAY: u64 = 24 * 60 * 60;
+
+/// A time shown by the layout clock.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClockTime {
+    /// The number of 24 hour cycles passed
+    pub days: u8,
+    /// The hour of the day (0 - 23)
+    pub hours: u8,
+    /// The minute of the hour (0 - 59)
+    pub minutes: u8,
+    /// The second of the minute (0 - 59)
+    pub seconds: u8,
+}
+
+impl ClockTime {
+    /// Creates the time at the start of the given minute.
+    pub fn new(days: u8, hours: u8, minutes: u8) -> Self {
+        ClockTime {
+            days,
+            hours,
+            minutes,
+            seconds: 0,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The time `seconds` after the start of day 0. The days wrap like the clock data.
+    fn from_seconds(seconds: u64) -> Self {
+        ClockTime {
+            days: (seconds / DAY % 128) as u8,
+            hours: (seconds % DAY / 3600) as u8,
+            minutes: (seconds % 3600 / 60) as u8,
+            seconds: (seconds % 60) as u8,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The time `minutes` later. The days wrap like the clock data.
+    pub fn add_minutes(&self, minutes: u32) -> Self {
+        Self::from_seconds(self.as_seconds() + minutes as u64 * 60)
+    }
+
+    /// # Returns
+    ///
+    /// The seconds since the start of day 0.
+    pub(crate) fn as_seconds(&self) -> u64 {
+        self.days as u64 * DAY
+            + self.hours as u64 * 3600
+            + self.minutes as u64 * 60
+            + self.seconds as u64
+    }
+
+    /// # Returns
+    ///
+    /// The time `elapsed` real time later on a clock running at `rate`.
+    fn advanced(&self, rate: u8, elapsed: Duration) -> Self {
+        Self::from_seconds(self.as_seconds() + (elapsed.as_millis() as u64 * rate as u64) / 1000)
+    }
+}
+
+/// Follows the layout clock from the clock data written to the bus.
+///
+/// The clock data is only written once in a while, so the time in between is scaled
+/// from the real time passed since the last clock data by the clock rate.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct LayoutClock {
+    /// The last clock time received, its rate and when it was received
+    synced: Option<(ClockTime, u8, Instant)>,
+}
+
+impl LayoutClock {
+    /// Creates a clock not synchronized yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Synchronizes the clock with the clock data of `message` received at `now`.
+    ///
+    /// # Returns
+    ///
+    /// If the message held clock data.
+    pub fn update(&mut self, message: &Message, now: Instant) -> bool {
+        match *message {
+            Message::WrSlData(WrSlDataStructure::DataTime(clock, ..)) => {
+                let time = ClockTime::new(clock.days(), clock.hour(), clock.minute());
+                self.synced = Some((time, clock.clk_rate(), now));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The rate the clock runs at, if synchronized. `0` means the clock is frozen.
+    pub fn rate(&self) -> Option<u8> {
+        self.synced.map(|(_, rate, _)| rate)
+    }
+
+    /// # Returns
+    ///
+    /// The time the clock shows at `now`, if synchronized.
+    pub fn time_at(&self, now: Instant) -> Option<ClockTime> {
+        self.synced
+            .map(|(time, rate, synced)| time.advanced(rate, now.saturating_duration_since(synced)))
+    }
+}
+
+/// Follows the layout clock of a [`LocoDriveController`] in the background.
+///
+/// Dropping the follower stops following the clock.
+#[derive(Debug)]
+pub struct ClockFollower {
+    /// The followed clock
+    clock: Arc<Mutex<LayoutClock>>,
+    /// The task updating the clock
+    task: JoinHandle<()>,
+}
+
+impl ClockFollower {
+    /// Starts following the clock data read or written by `controller`.
+    pub fn follow(controller: &LocoDriveController) -> Self {
+        let clock = Arc::new(Mutex::new(LayoutClock::new()));
+        let mut messages = controller.subscribe();
+
+        let followed = clock.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match messages.recv().await {
+                    Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                        followed.lock().unwrap().update(&message, Instant::now());
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        ClockFollower { clock, task }
+    }
+
+    /// # Returns
+    ///
+    /// The current time of the layout clock, if any clock data was received yet.
+    pub fn time(&self) -> Option<ClockTime> {
+        self.clock.lock().unwrap().time_at(Instant::now())
+    }
+
+    /// # Returns
+    ///
+    /// The rate of the layout clock, if any clock data was received yet.
+    pub fn rate(&self) -> Option<u8> {
+        self.clock.lock().unwrap().rate()
+    }
+}
+
+/// Extends standard drop implementation to stop following the clock.
+impl Drop for ClockFollower {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Runs the layout clock as clock master, writing the clock data periodically to the bus.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::fast_clock::{ClockMaster, ClockTime};
+/// use locodrive::loco_controller::LocoDriveController;
+/// use std::time::Duration;
+///
+/// # async fn run(controller: LocoDriveController) {
+/// let clock = ClockMaster::new(ClockTime::new(0, 6, 0), 4)
+///     .interval(Duration::from_secs(15))
+///     .start(&controller);
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ClockMaster {
+    /// The time the clock starts with
+    start: ClockTime,
+    /// The rate the clock runs at
+    rate: u8,
+    /// How often the clock data is written
+    interval: Duration,
+    /// The id the clock data is written with
+    id: IdArg,
+}
+
+impl ClockMaster {
+    /// Creates a clock starting at `start` and running `rate` times faster than real time.
+    /// A rate of `0` freezes the clock.
+    pub fn new(start: ClockTime, rate: u8) -> Self {
+        ClockMaster {
+            start,
+            rate: rate & 0x7F,
+            interval: Duration::from_secs(60),
+            id: IdArg::new(0),
+        }
+    }
+
+    /// Sets how often the clock data is written to the bus.
+    ///
+    /// Defaults to every 60 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the id the clock data is written with.
+    ///
+    /// Defaults to `0`.
+    pub fn id(mut self, id: IdArg) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The message writing the clock data showing `time`.
+    pub fn message(&self, time: ClockTime, track: TrkArg) -> Message {
+        let clock = FastClock::at_time(self.rate, time.days, time.hours, time.minutes);
+        Message::WrSlData(WrSlDataStructure::DataTime(clock, track, self.id))
+    }
+
+    /// Starts writing the clock data with `controller`, starting right away.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the clock. Dropping it stops writing the clock data.
+    pub fn start(self, controller: &LocoDriveController) -> ClockMasterHandle {
+        let handle = controller.command_handle();
+        let track = controller
+            .track_status()
+            .unwrap_or_else(|| TrkArg::new(false, false, true, false));
+
+        let mut clock = LayoutClock::new();
+        clock.update(&self.message(self.start, track), Instant::now());
+
+        let clock = Arc::new(Mutex::new(clock));
+        let running = clock.clone();
+
+        // The clock slot is reserved, so writing to it is allowed explicitly
+        let options = SendOptions {
+            allow_reserved_slots: true,
+            ..SendOptions::default()
+        };
+        let master = self;
+        let task = tokio::spawn(async move {
+            let mut ticks = interval(master.interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let time = running.lock().unwrap().time_at(Instant::now());
+                let message = master.message(time.unwrap_or(master.start), track);
+                if let Err(err) = handle.send_message_with(message, options).await {
+                    log_error!("Could not write the clock data: {}", err);
+                }
+            }
+        });
+
+        ClockMasterHandle { clock, task }
+    }
+}
+
+/// The handle of a running [`ClockMaster`].
+///
+/// Dropping the handle stops writing the clock data.
+#[derive(Debug)]
+pub struct ClockMasterHandle {
+    /// The clock written to the bus
+    clock: Arc<Mutex<LayoutClock>>,
+    /// The task writing the clock data
+    task: JoinHandle<()>,
+}
+
+impl ClockMasterHandle {
+    /// # Returns
+    ///
+    /// The current time of the clock.
+    pub fn time(&self) -> ClockTime {
+        self.clock
+            .lock()
+            .unwrap()
+            .time_at(Instant::now())
+            .unwrap_or_default()
+    }
+}
+
+/// Extends standard drop implementation to stop writing the clock data.
+impl Drop for ClockMasterHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,285 @@
+use crate::error::MessageParseError;
+use crate::loco_controller::{LocoDriveController, LocoDriveMessage};
+use crate::protocol::{Message, MAX_MESSAGE_LEN};
+use std::ffi::{c_char, c_void, CStr};
+use std::io::Write;
+use std::slice;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+/// A pointer argument was null or a string argument was no valid UTF-8.
+pub const LOCODRIVE_ERR_INVALID_ARGUMENT: i32 = -1;
+/// The buffer does not hold the complete frame.
+pub const LOCODRIVE_ERR_UNEXPECTED_END: i32 = -2;
+/// The op code of the frame is not known.
+pub const LOCODRIVE_ERR_UNKNOWN_OPCODE: i32 = -3;
+/// The checksum of the frame is invalid.
+pub const LOCODRIVE_ERR_INVALID_CHECKSUM: i32 = -4;
+/// The arguments of the frame are invalid.
+pub const LOCODRIVE_ERR_INVALID_FORMAT: i32 = -5;
+/// The output buffer is too small.
+pub const LOCODRIVE_ERR_BUFFER_TOO_SMALL: i32 = -6;
+/// The message could not be send to the model railroad.
+pub const LOCODRIVE_ERR_SEND_FAILED: i32 = -7;
+
+/// Called with the bytes of every message received from the model railroad
+/// and the user data given on registration.
+pub type LocoDriveReceiveCallback =
+    Option<unsafe extern "C" fn(frame: *const u8, len: usize, user_data: *mut c_void)>;
+
+/// A controller connected to a serial port, with the runtime it runs on.
+///
+/// It is opaque to C and created by [`locodrive_open_port()`].
+pub struct LocoDriveHandle {
+    /// The controller talking to the model railroad
+    controller: LocoDriveController,
+    /// The task calling the receive callback, if registered
+    receiver: Option<JoinHandle<()>>,
+    /// The runtime the controller runs on
+    runtime: Runtime,
+}
+
+/// The user data of a receive callback, passed back to C unchanged.
+struct UserData(*mut c_void);
+
+// The user data is only passed back to the callback, which has to be callable from any thread.
+unsafe impl Send for UserData {}
+
+/// # Returns
+///
+/// The error code of `err`.
+fn error_code(err: &MessageParseError) -> i32 {
+    match err {
+        MessageParseError::UnexpectedEnd { .. } => LOCODRIVE_ERR_UNEXPECTED_END,
+        MessageParseError::UnknownOpcode { .. } => LOCODRIVE_ERR_UNKNOWN_OPCODE,
+        MessageParseError::InvalidChecksum { .. } => LOCODRIVE_ERR_INVALID_CHECKSUM,
+        _ => LOCODRIVE_ERR_INVALID_FORMAT,
+    }
+}
+
+/// Parses the frame at the start of `frame` and writes its description, terminated by a null byte,
+/// to `description`. The description is truncated to fit `description_len`.
+///
+/// # Returns
+///
+/// The length of the parsed frame as told by its op code or count byte, which may differ
+/// from the length the message encodes to, or a negative `LOCODRIVE_ERR_*` code.
+///
+/// # Safety
+///
+/// `frame` has to point to `len` readable bytes and `description` to `description_len`
+/// writable bytes. `description` may be null to only validate the frame.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_parse(
+    frame: *const u8,
+    len: usize,
+    description: *mut c_char,
+    description_len: usize,
+) -> i32 {
+    if frame.is_null() {
+        return LOCODRIVE_ERR_INVALID_ARGUMENT;
+    }
+    let frame = slice::from_raw_parts(frame, len);
+    let message = match Message::parse(frame) {
+        Ok(message) => message,
+        Err(err) => return error_code(&err),
+    };
+
+    if !description.is_null() && description_len > 0 {
+        let out = slice::from_raw_parts_mut(description as *mut u8, description_len);
+        let mut cursor = &mut out[..description_len - 1];
+        // A description not fitting the buffer is truncated
+        let _ = write!(cursor, "{:?}", message);
+        let written = description_len - 1 - cursor.len();
+        out[written] = 0;
+    }
+    // A parsed frame always tells its length
+    Message::frame_len(frame).unwrap_or(len) as i32
+}
+
+/// Encodes the op code and arguments in `body` to a frame by appending the checksum.
+/// The frame is validated, so only known messages are encoded.
+///
+/// # Returns
+///
+/// The length of the frame written to `out`, or a negative `LOCODRIVE_ERR_*` code.
+///
+/// # Safety
+///
+/// `body` has to point to `body_len` readable bytes and `out` to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_encode(
+    body: *const u8,
+    body_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    if body.is_null() || out.is_null() || body_len == 0 || body_len >= MAX_MESSAGE_LEN {
+        return LOCODRIVE_ERR_INVALID_ARGUMENT;
+    }
+    let mut frame = slice::from_raw_parts(body, body_len).to_vec();
+    frame.push(0xFF ^ frame.iter().fold(0, |acc, &b| acc ^ b));
+
+    let message = match Message::parse(&frame) {
+        Ok(message) => message,
+        Err(err) => return error_code(&err),
+    };
+    match message.write_to(slice::from_raw_parts_mut(out, out_len)) {
+        Ok(len) => len as i32,
+        Err(_) => LOCODRIVE_ERR_BUFFER_TOO_SMALL,
+    }
+}
+
+/// Connects a controller to the serial port `port_name` with `baud_rate`.
+/// The controller runs on its own runtime, so C code needs none.
+///
+/// # Returns
+///
+/// The handle of the controller to pass to the other functions,
+/// or null if the port could not be opened.
+///
+/// # Safety
+///
+/// `port_name` has to point to a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_open_port(
+    port_name: *const c_char,
+    baud_rate: u32,
+) -> *mut LocoDriveHandle {
+    if port_name.is_null() {
+        return std::ptr::null_mut();
+    }
+    let port_name = match CStr::from_ptr(port_name).to_str() {
+        Ok(port_name) => port_name,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let runtime = match Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let controller =
+        match runtime.block_on(LocoDriveController::builder(port_name, baud_rate).build()) {
+            Ok(controller) => controller,
+            Err(_) => return std::ptr::null_mut(),
+        };
+
+    Box::into_raw(Box::new(LocoDriveHandle {
+        controller,
+        receiver: None,
+        runtime,
+    }))
+}
+
+/// Sends the frame in `frame` and blocks until the model railroad echoed it.
+///
+/// # Returns
+///
+/// Zero if the message was send, or a negative `LOCODRIVE_ERR_*` code.
+///
+/// # Safety
+///
+/// `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet.
+/// `frame` has to point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_send(
+    handle: *mut LocoDriveHandle,
+    frame: *const u8,
+    len: usize,
+) -> i32 {
+    if handle.is_null() || frame.is_null() {
+        return LOCODRIVE_ERR_INVALID_ARGUMENT;
+    }
+    let handle = &mut *handle;
+    let message = match Message::parse(slice::from_raw_parts(frame, len)) {
+        Ok(message) => message,
+        Err(err) => return error_code(&err),
+    };
+    match handle
+        .runtime
+        .block_on(handle.controller.send_message(message))
+    {
+        Ok(()) => 0,
+        Err(_) => LOCODRIVE_ERR_SEND_FAILED,
+    }
+}
+
+/// Registers `callback` to be called with the bytes of every message received from the model railroad
+/// and `user_data`. A callback registered before is replaced, a null callback unregisters it.
+///
+/// The callback is called from a thread of the controllers runtime, not from the registering thread.
+///
+/// # Returns
+///
+/// Zero, or [`LOCODRIVE_ERR_INVALID_ARGUMENT`] if `handle` is null.
+///
+/// # Safety
+///
+/// `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet.
+/// `callback` has to be callable from any thread with `user_data` until it is replaced
+/// or the handle is closed. This function must not be called from the callback,
+/// as it waits for the previous callback to return.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_set_receive_callback(
+    handle: *mut LocoDriveHandle,
+    callback: LocoDriveReceiveCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    if handle.is_null() {
+        return LOCODRIVE_ERR_INVALID_ARGUMENT;
+    }
+    let handle = &mut *handle;
+    if let Some(receiver) = handle.receiver.take() {
+        receiver.abort();
+        // Waits for a running callback to return, so the previous one is never called afterwards
+        let _ = handle.runtime.block_on(receiver);
+    }
+    let callback = match callback {
+        Some(callback) => callback,
+        None => return 0,
+    };
+
+    let mut messages = handle.controller.subscribe();
+    let user_data = UserData(user_data);
+    handle.receiver = Some(handle.runtime.spawn(async move {
+        let user_data = user_data;
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        loop {
+            match messages.recv().await {
+                Ok(LocoDriveMessage::Message(message)) => {
+                    let len = message.write_to(&mut buf).unwrap_or_default();
+                    callback(buf.as_ptr(), len, user_data.0);
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }));
+    0
+}
+
+/// Stops the controller and releases `handle`. No callback is called afterwards.
+///
+/// # Safety
+///
+/// `handle` has to be returned by [`locodrive_open_port()`] and not be closed yet, or be null.
+/// This function must not be called from the callback, as it waits for it to return.
+#[no_mangle]
+pub unsafe extern "C" fn locodrive_close(handle: *mut LocoDriveHandle) {
+    if handle.is_null() {
+        return;
+    }
+    let LocoDriveHandle {
+        controller,
+        receiver,
+        runtime,
+    } = *Box::from_raw(handle);
+
+    if let Some(receiver) = receiver {
+        receiver.abort();
+        let _ = runtime.block_on(receiver);
+    }
+    // The controller stops its tasks on drop, which needs its runtime
+    let guard = runtime.enter();
+    drop(controller);
+    drop(guard);
+}
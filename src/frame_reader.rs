@@ -0,0 +1,94 @@
+use bytes::{Buf, BytesMut};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The smallest count of bytes requested from the port at once.
+const MIN_READ: usize = 16;
+/// The largest count of bytes requested from the port at once.
+const MAX_READ: usize = 4096;
+
+/// Reads frames from a port through an adaptive buffer.
+///
+/// Instead of reading each part of a frame by its own system call, the reader requests as many bytes
+/// as the port holds and slices the frames out of the buffer. The size of the requests adapts to the
+/// traffic: It doubles whenever a read fills the whole request, as the bus is busy,
+/// and halves whenever a read fills less than a quarter of it, as the bus is quiet.
+///
+/// Reading is cancel safe, as the read bytes are kept in the buffer until they are consumed.
+#[derive(Debug)]
+pub(crate) struct FrameReader<R> {
+    /// The port to read from
+    inner: R,
+    /// The bytes read but not consumed yet
+    buf: BytesMut,
+    /// How many bytes are requested from the port by the next read
+    read_size: usize,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    /// Creates a reader buffering the bytes read from `inner`.
+    pub(crate) fn new(inner: R) -> Self {
+        FrameReader {
+            inner,
+            buf: BytesMut::with_capacity(MIN_READ),
+            read_size: MIN_READ,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// How many bytes are requested from the port by the next read.
+    #[cfg(test)]
+    pub(crate) fn read_size(&self) -> usize {
+        self.read_size
+    }
+
+    /// Reads from the port until at least `len` bytes are buffered.
+    ///
+    /// # Errors
+    ///
+    /// If the port could not be read or was closed.
+    async fn fill(&mut self, len: usize) -> io::Result<()> {
+        while self.buf.len() < len {
+            self.buf.reserve(self.read_size);
+            let requested = self.buf.capacity() - self.buf.len();
+
+            let read = self.inner.read_buf(&mut self.buf).await?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            if read >= requested {
+                self.read_size = (self.read_size * 2).min(MAX_READ);
+            } else if read < requested / 4 {
+                self.read_size = (self.read_size / 2).max(MIN_READ);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads until the byte at `index` is buffered, without consuming it.
+    ///
+    /// # Errors
+    ///
+    /// If the port could not be read or was closed.
+    pub(crate) async fn peek(&mut self, index: usize) -> io::Result<u8> {
+        self.fill(index + 1).await?;
+        Ok(self.buf[index])
+    }
+
+    /// Reads the next frame of `len` bytes at once.
+    ///
+    /// # Errors
+    ///
+    /// If the port could not be read or was closed. No bytes are consumed then.
+    pub(crate) async fn read_frame(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        self.fill(len).await?;
+        Ok(self.buf.split_to(len).to_vec())
+    }
+
+    /// Drops up to `len` buffered bytes, to resynchronize after an invalid frame.
+    pub(crate) fn skip(&mut self, len: usize) {
+        self.buf.advance(len.min(self.buf.len()));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::adapter::AdapterProfile;
+use crate::loco_controller::{LocoDriveController, LocoDriveControllerBuilder};
+use std::collections::HashSet;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tokio_serial::{SerialPortInfo, SerialPortType};
+
+/// Describes a serial device found while watching for interfaces.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct InterfaceInfo {
+    /// The name of the serial port, like `/dev/ttyUSB0` or `COM3`
+    pub port_name: String,
+    /// The USB vendor id, if the device is connected by USB
+    pub vid: Option<u16>,
+    /// The USB product id, if the device is connected by USB
+    pub pid: Option<u16>,
+    /// The manufacturer reported by the device, if any
+    pub manufacturer: Option<String>,
+    /// The product name reported by the device, if any
+    pub product: Option<String>,
+}
+
+impl From<SerialPortInfo> for InterfaceInfo {
+    fn from(info: SerialPortInfo) -> Self {
+        match info.port_type {
+            SerialPortType::UsbPort(usb) => InterfaceInfo {
+                port_name: info.port_name,
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+            },
+            _ => InterfaceInfo {
+                port_name: info.port_name,
+                vid: None,
+                pid: None,
+                manufacturer: None,
+                product: None,
+            },
+        }
+    }
+}
+
+/// Recognizes a serial device as interface to the model railroad.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum InterfaceMatch {
+    /// Matches USB devices with the given vendor and product id.
+    Usb(u16, u16),
+    /// Matches devices whose product name contains the given text, ignoring case.
+    Product(String),
+    /// Matches the serial port with exactly the given name.
+    PortName(String),
+}
+
+impl InterfaceMatch {
+    /// # Returns
+    ///
+    /// Matches for the product names of common interfaces, like the RR-CirKits LocoBuffer-USB,
+    /// the Digitrax PR3 and PR4 and the Uhlenbrock Intellibox.
+    pub fn known() -> Vec<InterfaceMatch> {
+        ["LocoBuffer", "LocoNet", "PR3", "PR4", "Intellibox"]
+            .iter()
+            .map(|product| InterfaceMatch::Product(product.to_string()))
+            .collect()
+    }
+
+    /// # Returns
+    ///
+    /// If the device described by `info` is recognized.
+    pub fn matches(&self, info: &InterfaceInfo) -> bool {
+        match self {
+            InterfaceMatch::Usb(vid, pid) => info.vid == Some(*vid) && info.pid == Some(*pid),
+            InterfaceMatch::Product(product) => info
+                .product
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().contains(&product.to_lowercase())),
+            InterfaceMatch::PortName(port_name) => info.port_name == *port_name,
+        }
+    }
+}
+
+/// An event reported by a [`HotplugMonitor`].
+pub enum HotplugEvent {
+    /// A known interface was plugged in and a controller connected to it.
+    InterfaceAttached(InterfaceInfo, LocoDriveController),
+    /// A known interface was plugged in, but the controller could not connect to it.
+    /// The connection is retried on the next scan.
+    AttachFailed(InterfaceInfo, tokio_serial::Error),
+    /// A previously attached interface was unplugged. Holds the name of its port.
+    InterfaceDetached(String),
+}
+
+/// Watches for known interfaces to be plugged in and connects a [`LocoDriveController`] to them.
+///
+/// The serial ports are enumerated periodically. Linux reads them from sysfs (or from udev,
+/// if the `libudev` feature of `tokio-serial` is active), Windows uses the SetupAPI.
+/// Interfaces already plugged in when the watcher starts are attached by its first scan.
+///
+/// # Polling
+///
+/// Detection deliberately polls instead of subscribing to device notifications of the operating
+/// system, like udev monitors or `WM_DEVICECHANGE` on Windows. Those need platform specific
+/// dependencies and report a device before its serial port can be opened, so a scan would
+/// be needed anyway. Therefore an interface is attached or detached up to one
+/// [`HotplugWatcher::poll_interval()`] after it was plugged, one second by default.
+/// Lower the interval to react faster at the cost of enumerating the ports more often.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::hotplug::{HotplugEvent, HotplugWatcher};
+/// use locodrive::loco_controller::LocoDriveController;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut monitor = HotplugWatcher::new(LocoDriveController::builder("", 115_200)).start();
+///
+///     while let Some(event) = monitor.recv().await {
+///         if let HotplugEvent::InterfaceAttached(info, controller) = event {
+///             println!("Attached {}", info.port_name);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HotplugWatcher {
+    /// The template to connect the controllers with, the port name is replaced on attaching
+    controller: LocoDriveControllerBuilder,
+    /// The matches recognizing interfaces
+    interfaces: Vec<InterfaceMatch>,
+    /// How often to enumerate the serial ports
+    poll_interval: Duration,
+    /// Whether to detect the adapter profile of attached interfaces
+    detect_adapters: bool,
+}
+
+impl HotplugWatcher {
+    /// Creates a watcher connecting controllers configured like `controller`
+    /// to the [`InterfaceMatch::known()`] interfaces.
+    pub fn new(controller: LocoDriveControllerBuilder) -> Self {
+        HotplugWatcher {
+            controller,
+            interfaces: InterfaceMatch::known(),
+            poll_interval: Duration::from_secs(1),
+            detect_adapters: true,
+        }
+    }
+
+    /// Replaces the matches recognizing interfaces.
+    pub fn interfaces(mut self, interfaces: Vec<InterfaceMatch>) -> Self {
+        self.interfaces = interfaces;
+        self
+    }
+
+    /// Adds a match recognizing interfaces.
+    pub fn interface(mut self, interface: InterfaceMatch) -> Self {
+        self.interfaces.push(interface);
+        self
+    }
+
+    /// Sets how often the serial ports are enumerated, which is the longest time an interface
+    /// stays unnoticed after it was plugged. Defaults to one second.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets whether the adapter profile of attached interfaces is detected by
+    /// [`AdapterProfile::detect()`]. Recognized interfaces are then connected with their profile
+    /// instead of the one set on the template. Defaults to `true`.
+    pub fn detect_adapters(mut self, detect_adapters: bool) -> Self {
+        self.detect_adapters = detect_adapters;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// If the device described by `info` is a known interface.
+    pub fn recognizes(&self, info: &InterfaceInfo) -> bool {
+        self.interfaces
+            .iter()
+            .any(|interface| interface.matches(info))
+    }
+
+    /// Starts watching in a background task.
+    ///
+    /// # Returns
+    ///
+    /// The monitor to receive the [`HotplugEvent`]s with. Dropping it stops watching.
+    pub fn start(self) -> HotplugMonitor {
+        let (sender, events) = unbounded_channel();
+        let task = tokio::spawn(self.watch(sender));
+
+        HotplugMonitor { task, events }
+    }
+
+    /// Enumerates the serial ports until the monitor is dropped.
+    async fn watch(self, events: UnboundedSender<HotplugEvent>) {
+        let mut attached: HashSet<String> = HashSet::new();
+        let mut ticks = interval(self.poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+
+            let present: Vec<InterfaceInfo> = match tokio_serial::available_ports() {
+                Ok(ports) => ports
+                    .into_iter()
+                    .map(InterfaceInfo::from)
+                    .filter(|info| self.recognizes(info))
+                    .collect(),
+                Err(err) => {
+                    log_error!("Could not enumerate serial ports: {}", err);
+                    continue;
+                }
+            };
+
+            let mut detached = Vec::new();
+            attached.retain(|port_name| {
+                let still_present = present.iter().any(|info| info.port_name == *port_name);
+                if !still_present {
+                    detached.push(port_name.clone());
+                }
+                still_present
+            });
+
+            for port_name in detached {
+                log_info!("Interface {} detached", port_name);
+                if events
+                    .send(HotplugEvent::InterfaceDetached(port_name))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+
+            for info in present {
+                if attached.contains(&info.port_name) {
+                    continue;
+                }
+
+                let mut controller = self.controller.clone().port_name(&info.port_name);
+                if self.detect_adapters {
+                    let adapter = AdapterProfile::detect(&info);
+                    if adapter.name() != AdapterProfile::generic().name() {
+                        log_info!("Detected {} at {}", adapter.name(), info.port_name);
+                        controller = controller.adapter(adapter);
+                    }
+                }
+
+                let event = match controller.build().await {
+                    Ok(controller) => {
+                        log_info!("Interface {} attached", info.port_name);
+                        attached.insert(info.port_name.clone());
+                        HotplugEvent::InterfaceAttached(info, controller)
+                    }
+                    Err(err) => HotplugEvent::AttachFailed(info, err),
+                };
+
+                // The monitor was dropped, so nobody is interested in attaching anymore
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Receives the [`HotplugEvent`]s of a started [`HotplugWatcher`].
+///
+/// Dropping the monitor stops watching. Attached controllers keep running.
+pub struct HotplugMonitor {
+    /// The task enumerating the serial ports
+    task: JoinHandle<()>,
+    /// The events reported by the task
+    events: UnboundedReceiver<HotplugEvent>,
+}
+
+impl HotplugMonitor {
+    /// Receives the next event.
+    ///
+    /// # Returns
+    ///
+    /// The next event or `None` if the watcher has stopped.
+    pub async fn recv(&mut self) -> Option<HotplugEvent> {
+        self.events.recv().await
+    }
+}
+
+/// Extends standard drop implementation to stop watching.
+impl Drop for HotplugMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,158 @@
+use crate::args::{ImArg, ImmPacketAck};
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::{LocoDriveController, SendOptions};
+use crate::protocol::Message;
+use tokio::time::{sleep, Duration, Instant};
+
+/// Sends batches of [`Message::ImmPacket`]s paced by the acknowledgments of the model railroad.
+///
+/// The model railroad queues immediate packets and answers with the remaining capacity of its
+/// queue, see [`ImmPacketAck`], or rejects the packet when the queue is full.
+/// This sender then backs off: the less capacity remains, the longer it waits before the next
+/// packet. Rejected packets double the wait up to a maximum.
+/// It resumes at full speed once a packet is acknowledged without a limit again.
+///
+/// The accepted and rejected packets and the time spent sending are recorded
+/// in the controllers [`crate::stats::Stats`].
+pub struct ImmPacketSender<'a> {
+    /// The controller to send the packets with
+    controller: &'a LocoDriveController,
+    /// The first wait after a limited or failed acknowledgment
+    min_backoff: Duration,
+    /// The maximum wait between two packets
+    max_backoff: Duration,
+    /// How often one packet is retried after being rejected
+    max_retries: u32,
+    /// The current wait before the next packet
+    backoff: Duration,
+}
+
+impl<'a> ImmPacketSender<'a> {
+    /// Creates a new sender sending with `controller`.
+    ///
+    /// The backoff starts at 20 milliseconds and grows up to one second.
+    /// A rejected packet is retried up to ten times.
+    pub fn new(controller: &'a LocoDriveController) -> Self {
+        ImmPacketSender {
+            controller,
+            min_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_secs(1),
+            max_retries: 10,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Sets the first wait after a limited or failed acknowledgment
+    /// and the maximum wait between two packets.
+    pub fn backoff(mut self, min_backoff: Duration, max_backoff: Duration) -> Self {
+        self.min_backoff = min_backoff;
+        self.max_backoff = max_backoff.max(min_backoff);
+        self
+    }
+
+    /// Sets how often one packet is retried after being rejected.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The current wait before the next packet is send.
+    pub fn current_backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Sends one immediate packet, waiting for the current backoff first.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::Rejected`]: If the packet was rejected more often than allowed
+    /// - Any other [`LocoDriveSendingError`] of sending the packet
+    pub async fn send(&mut self, packet: ImArg) -> Result<(), LocoDriveSendingError> {
+        let start = Instant::now();
+        let sent = self.send_paced(packet).await;
+        self.controller
+            .stats_collector()
+            .record_imm_packet_time(start.elapsed());
+        sent
+    }
+
+    /// Sends all `packets` in order.
+    ///
+    /// # Returns
+    ///
+    /// The count of send packets or the error of the first packet that could not be send.
+    pub async fn send_all<I: IntoIterator<Item = ImArg>>(
+        &mut self,
+        packets: I,
+    ) -> Result<usize, LocoDriveSendingError> {
+        let start = Instant::now();
+        let mut count = 0;
+        let mut sent = Ok(());
+
+        for packet in packets {
+            sent = self.send_paced(packet).await;
+            if sent.is_err() {
+                break;
+            }
+            count += 1;
+        }
+
+        self.controller
+            .stats_collector()
+            .record_imm_packet_time(start.elapsed());
+        sent.map(|_| count)
+    }
+
+    /// Sends one packet, retrying and adapting the backoff to the acknowledgments.
+    async fn send_paced(&mut self, packet: ImArg) -> Result<(), LocoDriveSendingError> {
+        let options = SendOptions {
+            require_ack: true,
+            ..SendOptions::default()
+        };
+        let mut retries = 0;
+
+        loop {
+            if !self.backoff.is_zero() {
+                sleep(self.backoff).await;
+            }
+
+            match self
+                .controller
+                .send_message_acked(Message::ImmPacket(packet), options)
+                .await
+            {
+                Ok(ack) => {
+                    self.controller.stats_collector().record_imm_packet(false);
+                    // A limited acknowledgment means the queue is filling up
+                    match ack.map(|ack| ImmPacketAck::new(ack).remaining_capacity()) {
+                        Some(None) => self.backoff = Duration::ZERO,
+                        Some(Some(capacity)) => self.pace(capacity),
+                        None => self.increase_backoff(),
+                    }
+                    return Ok(());
+                }
+                Err(LocoDriveSendingError::Rejected(ack)) => {
+                    self.controller.stats_collector().record_imm_packet(true);
+                    self.increase_backoff();
+                    if retries >= self.max_retries {
+                        return Err(LocoDriveSendingError::Rejected(ack));
+                    }
+                    retries += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Waits the longer, the less `capacity` the command stations queue has left.
+    fn pace(&mut self, capacity: u8) {
+        self.backoff = (self.max_backoff / (capacity as u32 + 1)).max(self.min_backoff);
+    }
+
+    /// Doubles the backoff, starting at the minimum and limited by the maximum.
+    fn increase_backoff(&mut self) {
+        self.backoff = (self.backoff * 2).clamp(self.min_backoff, self.max_backoff);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::args::{SensorLevel, SlotArg, SwitchArg, SwitchDirection};
+use crate::error::{ConfigError, LocoDriveSendingError};
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver,
+};
+use crate::manager::{Manager, SensorManager, SlotManager, TurnoutTable};
+use crate::protocol::Message;
+use crate::snapshot::extension;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{timeout_at, Duration, Instant};
+
+/// The switch addresses interrogating the occupancy and stationary decoders,
+/// so they report the state of all their inputs and outputs.
+const INTERROGATE: RangeInclusive<u16> = 1016..=1019;
+
+/// A locomotive found in a slot of the command station.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InventoryLoco {
+    /// The slot holding the locomotive
+    pub slot: u8,
+    /// The address of the locomotive
+    pub address: u16,
+}
+
+/// A turnout whose direction was reported.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InventoryTurnout {
+    /// The address of the turnout
+    pub address: u16,
+    /// The direction of the turnout
+    pub direction: SwitchDirection,
+}
+
+/// A sensor whose level was reported.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InventorySensor {
+    /// The address of the sensor
+    pub address: u16,
+    /// The level of the sensor
+    pub level: SensorLevel,
+}
+
+/// A transponding board that reported by [`Message::MultiSense`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InventoryBoard {
+    /// The address of the board
+    pub address: u8,
+    /// The zones of the board that reported, ordered
+    pub zones: Vec<u8>,
+}
+
+/// The devices discovered on the bus by an [`InventoryScanner`].
+///
+/// It is saved as TOML or RON, depending on the extension of the file, to be loaded again,
+/// or exported as XML for panel editors.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Inventory {
+    /// The locomotives in the slots, ordered by slot
+    pub locos: Vec<InventoryLoco>,
+    /// The turnouts, ordered by address
+    pub turnouts: Vec<InventoryTurnout>,
+    /// The sensors, ordered by address
+    pub sensors: Vec<InventorySensor>,
+    /// The transponding boards, ordered by address
+    pub boards: Vec<InventoryBoard>,
+}
+
+impl Inventory {
+    /// Collects the devices known by the managers and the transponding `boards` with their zones.
+    /// Only turnouts with a reported direction are collected, as commanded ones may not exist.
+    pub fn collect(
+        slots: &SlotManager,
+        turnouts: &TurnoutTable,
+        sensors: &SensorManager,
+        boards: &BTreeMap<u8, Vec<u8>>,
+    ) -> Self {
+        let mut inventory = Inventory {
+            locos: slots
+                .slots()
+                .iter()
+                .filter_map(|(slot, state)| {
+                    let address = state.address?.address();
+                    // Free slots hold address zero
+                    (address != 0).then(|| InventoryLoco {
+                        slot: slot.slot(),
+                        address,
+                    })
+                })
+                .collect(),
+            turnouts: turnouts
+                .turnouts()
+                .iter()
+                .filter_map(|(address, turnout)| {
+                    Some(InventoryTurnout {
+                        address: *address,
+                        direction: turnout.reported?,
+                    })
+                })
+                .collect(),
+            sensors: sensors
+                .sensors()
+                .iter()
+                .map(|(address, level)| InventorySensor {
+                    address: *address,
+                    level: *level,
+                })
+                .collect(),
+            boards: boards
+                .iter()
+                .map(|(address, zones)| InventoryBoard {
+                    address: *address,
+                    zones: zones.clone(),
+                })
+                .collect(),
+        };
+        // Sorted, so exporting the same layout always writes the same file
+        inventory.locos.sort_by_key(|loco| loco.slot);
+        inventory.turnouts.sort_by_key(|turnout| turnout.address);
+        inventory.sensors.sort_by_key(|sensor| sensor.address);
+        inventory
+    }
+
+    /// # Returns
+    ///
+    /// The inventory as XML document, with one element per device.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<inventory>\n");
+
+        xml.push_str("  <locos>\n");
+        for loco in &self.locos {
+            let _ = writeln!(
+                xml,
+                "    <loco slot=\"{}\" address=\"{}\"/>",
+                loco.slot, loco.address
+            );
+        }
+        xml.push_str("  </locos>\n  <turnouts>\n");
+        for turnout in &self.turnouts {
+            let direction = match turnout.direction {
+                SwitchDirection::Straight => "straight",
+                SwitchDirection::Curved => "curved",
+            };
+            let _ = writeln!(
+                xml,
+                "    <turnout address=\"{}\" direction=\"{}\"/>",
+                turnout.address, direction
+            );
+        }
+        xml.push_str("  </turnouts>\n  <sensors>\n");
+        for sensor in &self.sensors {
+            let level = match sensor.level {
+                SensorLevel::High => "high",
+                SensorLevel::Low => "low",
+            };
+            let _ = writeln!(
+                xml,
+                "    <sensor address=\"{}\" level=\"{}\"/>",
+                sensor.address, level
+            );
+        }
+        xml.push_str("  </sensors>\n  <boards>\n");
+        for board in &self.boards {
+            let zones: Vec<String> = board.zones.iter().map(|zone| zone.to_string()).collect();
+            let _ = writeln!(
+                xml,
+                "    <board address=\"{}\" zones=\"{}\"/>",
+                board.address,
+                zones.join(" ")
+            );
+        }
+        xml.push_str("  </boards>\n</inventory>\n");
+        xml
+    }
+
+    /// Loads an inventory from a TOML or RON file, depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be read
+    /// - [`ConfigError::Parse`]: If the file holds no valid inventory
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match extension(path).as_str() {
+            "toml" => toml::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            "ron" => ron::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Saves this inventory to a TOML, RON or XML file, depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be written
+    /// - [`ConfigError::Parse`]: If the inventory could not be serialized
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml`, `ron` nor `xml`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let content = match extension(path).as_str() {
+            "toml" => toml::to_string(self).map_err(|err| ConfigError::Parse(err.to_string()))?,
+            "ron" => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|err| ConfigError::Parse(err.to_string()))?,
+            "xml" => self.to_xml(),
+            _ => return Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        };
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Discovers the devices on the bus by interrogating it.
+///
+/// The scanner reads the slots of the command station, queries the direction of the given
+/// turnouts and interrogates the occupancy and stationary decoders, so they report their inputs
+/// and outputs. The queries are paced, so the bus is not flooded, and the answers are collected
+/// until the bus settled after the last query.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::inventory::InventoryScanner;
+/// use locodrive::loco_controller::LocoDriveController;
+///
+/// # async fn export(controller: LocoDriveController) -> Result<(), Box<dyn std::error::Error>> {
+/// let inventory = InventoryScanner::new(&controller)
+///     .turnouts(0..=63)
+///     .scan()
+///     .await?;
+/// inventory.save("inventory.xml")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct InventoryScanner {
+    /// Sends the queries
+    commands: CommandHandle,
+    /// Receives the answers
+    messages: LocoDriveReceiver,
+    /// The slots to read
+    slots: RangeInclusive<u8>,
+    /// The turnouts to query
+    turnouts: Option<RangeInclusive<u16>>,
+    /// Whether to interrogate the decoders
+    interrogate: bool,
+    /// The time between two queries
+    pace: Duration,
+    /// How long to collect answers after the last query
+    settle: Duration,
+}
+
+impl InventoryScanner {
+    /// Creates a scanner querying with `controller`.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        InventoryScanner {
+            commands: controller.command_handle(),
+            messages: controller.subscribe(),
+            slots: 1..=119,
+            turnouts: None,
+            interrogate: true,
+            pace: Duration::from_millis(50),
+            settle: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the slots to read. Defaults to the slots 1 to 119.
+    pub fn slots(mut self, slots: RangeInclusive<u8>) -> Self {
+        self.slots = slots;
+        self
+    }
+
+    /// Sets the turnouts to query the direction of. Defaults to none.
+    pub fn turnouts(mut self, turnouts: RangeInclusive<u16>) -> Self {
+        self.turnouts = Some(turnouts);
+        self
+    }
+
+    /// Sets whether to interrogate the occupancy and stationary decoders. Defaults to `true`.
+    pub fn interrogate(mut self, interrogate: bool) -> Self {
+        self.interrogate = interrogate;
+        self
+    }
+
+    /// Sets the time between two queries. Defaults to 50 milliseconds.
+    pub fn pace(mut self, pace: Duration) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Sets how long to collect answers after the last query. Defaults to one second.
+    pub fn settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The queries of the scan in the order they are send.
+    pub fn queries(&self) -> Vec<Message> {
+        let mut queries: Vec<Message> = self
+            .slots
+            .clone()
+            .map(|slot| Message::RqSlData(SlotArg::new(slot)))
+            .collect();
+        if let Some(turnouts) = &self.turnouts {
+            queries.extend(turnouts.clone().map(TurnoutTable::query));
+        }
+        if self.interrogate {
+            for address in INTERROGATE {
+                queries.push(Message::SwReq(SwitchArg::new(
+                    address,
+                    SwitchDirection::Straight,
+                    true,
+                )));
+                queries.push(Message::SwReq(SwitchArg::new(
+                    address,
+                    SwitchDirection::Straight,
+                    false,
+                )));
+            }
+        }
+        queries
+    }
+
+    /// Sends the queries and collects the answers.
+    ///
+    /// # Returns
+    ///
+    /// The discovered devices, including the ones reported by other devices meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// The error of the first query that could not be send.
+    pub async fn scan(mut self) -> Result<Inventory, LocoDriveSendingError> {
+        let mut found = Found::default();
+
+        for query in self.queries() {
+            self.commands.send_message(query).await?;
+            found
+                .collect(&mut self.messages, Instant::now() + self.pace)
+                .await;
+        }
+        found
+            .collect(&mut self.messages, Instant::now() + self.settle)
+            .await;
+
+        Ok(Inventory::collect(
+            &found.slots,
+            &found.turnouts,
+            &found.sensors,
+            &found.boards,
+        ))
+    }
+}
+
+/// The devices found by a running scan.
+#[derive(Default)]
+struct Found {
+    /// The read slots
+    slots: SlotManager,
+    /// The reported turnouts
+    turnouts: TurnoutTable,
+    /// The reported sensors
+    sensors: SensorManager,
+    /// The transponding boards with their zones
+    boards: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Found {
+    /// Collects the messages received until `deadline`.
+    async fn collect(&mut self, messages: &mut LocoDriveReceiver, deadline: Instant) {
+        while let Ok(received) = timeout_at(deadline, messages.recv()).await {
+            match received {
+                Ok(LocoDriveMessage::Message(message)) | Ok(LocoDriveMessage::Echo(message)) => {
+                    self.handle(&message)
+                }
+                // Missed answers are lost, the others are still collected
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Updates the found devices by `message`.
+    fn handle(&mut self, message: &Message) {
+        match *message {
+            // The interrogation addresses are no turnouts
+            Message::SwReq(switch) if INTERROGATE.contains(&switch.address()) => return,
+            Message::MultiSense(sense, _) => {
+                let zones = self.boards.entry(sense.board_address()).or_default();
+                if !zones.contains(&sense.zone()) {
+                    zones.push(sense.zone());
+                    zones.sort_unstable();
+                }
+            }
+            _ => {}
+        }
+        self.slots.handle(message);
+        self.turnouts.handle(message);
+        self.sensors.handle(message);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::args::{
+    AddressArg, DirfArg, InArg, SensorLevel, SndArg, SourceType, SpeedArg, SwitchArg,
+    SwitchDirection,
+};
+use crate::error::JmriError;
+use crate::manager::SlotState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The state JMRI reports for closed turnouts and active sensors.
+const JMRI_ON: u8 = 2;
+/// The state JMRI reports for thrown turnouts and inactive sensors.
+const JMRI_OFF: u8 = 4;
+
+/// The highest speed step of a [`SpeedArg`], which is full speed in JMRI.
+const MAX_SPEED: f32 = 126.0;
+
+/// Converts state to and from the JSON protocol of the JMRI json server.
+///
+/// The objects are named like the LocoNet objects of JMRI with the system prefix `L`:
+///
+/// - Turnouts are send as `{"type":"turnout","data":{"name":"LT1","state":2}}`,
+///   where the number is the [`SwitchArg`] address plus one and the state is
+///   `2` for closed or straight and `4` for thrown or curved.
+/// - Sensors are send as `{"type":"sensor","data":{"name":"LS1","state":2}}`,
+///   where the number is the [`InArg::address_ds54()`] plus one and the state is
+///   `2` for active and `4` for inactive.
+/// - Throttles are send as `{"type":"throttle","data":{"name":"3","address":3,"speed":0.5,"forward":true,"F0":true}}`,
+///   where the speed is `0.0` to `1.0`, or `-1.0` for an emergency stop.
+///   Values not known are left out.
+pub trait JmriJson: Sized {
+    /// # Returns
+    ///
+    /// The JMRI JSON message holding this state.
+    fn to_jmri_json(&self) -> String;
+
+    /// Reads the state from a JMRI JSON message.
+    ///
+    /// # Errors
+    ///
+    /// - [`JmriError::Json`]: If `json` is no message of the expected shape
+    /// - [`JmriError::UnexpectedType`]: If the message holds another type of object
+    /// - [`JmriError::InvalidName`]: If the name is no LocoNet name of the type
+    /// - [`JmriError::InvalidState`]: If the state is unknown or inconsistent
+    fn from_jmri_json(json: &str) -> Result<Self, JmriError>;
+}
+
+/// A message of the JMRI JSON protocol.
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    /// The type of the object, like `turnout`
+    #[serde(rename = "type")]
+    kind: String,
+    /// The state of the object
+    data: T,
+}
+
+/// The state of a turnout or sensor.
+#[derive(Serialize, Deserialize)]
+struct NamedState {
+    /// The system name, like `LT1`
+    name: String,
+    /// The JMRI state constant
+    state: u8,
+}
+
+/// The state of a throttle.
+#[derive(Serialize, Deserialize)]
+struct Throttle {
+    /// The name of the throttle, the address of the locomotive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The address of the locomotive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<u16>,
+    /// The speed from `0.0` to `1.0`, negative for an emergency stop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    speed: Option<f32>,
+    /// Whether the locomotive drives forwards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forward: Option<bool>,
+    /// The functions, named `F0` to `F8`, and the fields not used by this crate
+    #[serde(flatten)]
+    functions: BTreeMap<String, Value>,
+}
+
+/// # Returns
+///
+/// The JSON message of the object of type `kind` holding `data`.
+fn write<T: Serialize>(kind: &str, data: T) -> String {
+    let envelope = Envelope {
+        kind: kind.to_string(),
+        data,
+    };
+    // The messages only hold strings, numbers and booleans, which are always serializable
+    serde_json::to_string(&envelope).expect("serializable message")
+}
+
+/// Reads the data of the object of type `kind` from the JSON message `json`.
+///
+/// # Errors
+///
+/// - [`JmriError::Json`]: If `json` is no message of the expected shape
+/// - [`JmriError::UnexpectedType`]: If the message holds another type of object
+fn read<T: DeserializeOwned>(kind: &str, json: &str) -> Result<T, JmriError> {
+    let envelope: Envelope<T> = serde_json::from_str(json)?;
+    match envelope.kind == kind {
+        true => Ok(envelope.data),
+        false => Err(JmriError::UnexpectedType(envelope.kind)),
+    }
+}
+
+/// # Returns
+///
+/// The number of the LocoNet object named `name` with `prefix`, like `LT`.
+///
+/// # Errors
+///
+/// - [`JmriError::InvalidName`]: If the name has not the prefix or a number from one to `max`
+fn number(name: &str, prefix: &str, max: u16) -> Result<u16, JmriError> {
+    name.strip_prefix(prefix)
+        .and_then(|number| number.parse::<u16>().ok())
+        .filter(|number| (1..=max).contains(number))
+        .ok_or_else(|| JmriError::InvalidName(name.to_string()))
+}
+
+impl JmriJson for SwitchArg {
+    fn to_jmri_json(&self) -> String {
+        let state = match self.direction() {
+            SwitchDirection::Straight => JMRI_ON,
+            SwitchDirection::Curved => JMRI_OFF,
+        };
+        write(
+            "turnout",
+            NamedState {
+                name: format!("LT{}", self.address() + 1),
+                state,
+            },
+        )
+    }
+
+    fn from_jmri_json(json: &str) -> Result<Self, JmriError> {
+        let turnout: NamedState = read("turnout", json)?;
+        let address = number(&turnout.name, "LT", 2048)? - 1;
+        let direction = match turnout.state {
+            JMRI_ON => SwitchDirection::Straight,
+            JMRI_OFF => SwitchDirection::Curved,
+            state => return Err(JmriError::InvalidState(state)),
+        };
+        Ok(SwitchArg::new(address, direction, true))
+    }
+}
+
+impl JmriJson for InArg {
+    fn to_jmri_json(&self) -> String {
+        let state = match self.sensor_level() {
+            SensorLevel::High => JMRI_ON,
+            SensorLevel::Low => JMRI_OFF,
+        };
+        write(
+            "sensor",
+            NamedState {
+                name: format!("LS{}", self.address_ds54() + 1),
+                state,
+            },
+        )
+    }
+
+    fn from_jmri_json(json: &str) -> Result<Self, JmriError> {
+        let sensor: NamedState = read("sensor", json)?;
+        let address_ds54 = number(&sensor.name, "LS", 4096)? - 1;
+        let sensor_level = match sensor.state {
+            JMRI_ON => SensorLevel::High,
+            JMRI_OFF => SensorLevel::Low,
+            state => return Err(JmriError::InvalidState(state)),
+        };
+        let mut input = InArg::new(0, SourceType::Switch, sensor_level, false);
+        input.set_address_ds54(address_ds54);
+        Ok(input)
+    }
+}
+
+impl JmriJson for SlotState {
+    fn to_jmri_json(&self) -> String {
+        let address = self.address.map(|address| address.address());
+        let speed = self.speed.map(|speed| match speed {
+            SpeedArg::EmergencyStop => -1.0,
+            speed => speed.get_spd() as f32 / MAX_SPEED,
+        });
+
+        let mut functions = BTreeMap::new();
+        if let Some(dirf) = self.dirf {
+            for function in 0..=4 {
+                functions.insert(format!("F{}", function), Value::Bool(dirf.f(function)));
+            }
+        }
+        if let Some(snd) = self.snd {
+            for function in 5..=8 {
+                functions.insert(format!("F{}", function), Value::Bool(snd.f(function)));
+            }
+        }
+
+        write(
+            "throttle",
+            Throttle {
+                name: address.map(|address| address.to_string()),
+                address,
+                speed,
+                forward: self.dirf.map(|dirf| dirf.dir()),
+                functions,
+            },
+        )
+    }
+
+    fn from_jmri_json(json: &str) -> Result<Self, JmriError> {
+        let throttle: Throttle = read("throttle", json)?;
+        let set = |function: u8| {
+            throttle
+                .functions
+                .get(&format!("F{}", function))
+                .and_then(Value::as_bool)
+        };
+        let function = |function: u8| set(function).unwrap_or(false);
+        let has_sound = (5..=8).any(|function| set(function).is_some());
+
+        Ok(SlotState {
+            address: throttle.address.map(AddressArg::new),
+            status: None,
+            speed: throttle.speed.map(|speed| match speed {
+                speed if speed < 0.0 => SpeedArg::EmergencyStop,
+                speed => SpeedArg::new((speed.min(1.0) * MAX_SPEED).round() as u8),
+            }),
+            // The direction and the first functions are send together
+            dirf: throttle.forward.map(|forward| {
+                DirfArg::new(
+                    forward,
+                    function(0),
+                    function(1),
+                    function(2),
+                    function(3),
+                    function(4),
+                )
+            }),
+            snd: match has_sound {
+                true => Some(SndArg::new(
+                    function(5),
+                    function(6),
+                    function(7),
+                    function(8),
+                )),
+                false => None,
+            },
+        })
+    }
+}
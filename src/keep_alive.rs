@@ -0,0 +1,176 @@
+use crate::args::{SlotArg, SpeedArg};
+use crate::loco_controller::{CommandHandle, LocoDriveMessage};
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// Keeps registered slots alive by refreshing their current speed periodically.
+///
+/// The command station purges slots not refreshed for some minutes, which stops their trains.
+/// When the application goes quiet, the service sends a [`Message::LocoSpd`] with the current
+/// speed of each registered slot once per interval.
+///
+/// The speed is taken from the messages read from the bus, so it follows all throttles.
+/// Refreshes are coalesced with the traffic: Whenever a speed of a slot is read,
+/// no matter if written by the application or another throttle, its next refresh is postponed.
+/// The refreshes are jittered by up to a tenth of the interval, so many slots registered
+/// at once do not flood the bus at the same time.
+///
+/// The service is started by [`crate::loco_controller::LocoDriveControllerBuilder::keep_alive()`].
+#[derive(Debug)]
+pub struct KeepAlive {
+    /// How often each registered slot is refreshed
+    interval: Duration,
+    /// The registered slots with their current speed and when to refresh them next
+    slots: Mutex<HashMap<SlotArg, (SpeedArg, Instant)>>,
+    /// The state of the jitter generator
+    seed: Mutex<u64>,
+    /// Wakes the service when the registered slots changed
+    changed: Notify,
+}
+
+impl KeepAlive {
+    /// Creates a service refreshing the registered slots every `interval`.
+    pub(crate) fn new(interval: Duration) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        KeepAlive {
+            interval,
+            slots: Mutex::new(HashMap::new()),
+            // Xorshift never leaves zero
+            seed: Mutex::new(seed | 1),
+            changed: Notify::new(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// How often each registered slot is refreshed.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Registers `slot` to be kept alive with its current `speed`.
+    /// Its first refresh is sent one interval after registering.
+    pub fn register(&self, slot: SlotArg, speed: SpeedArg) {
+        let due = self.next_due(Instant::now());
+        self.slots.lock().unwrap().insert(slot, (speed, due));
+        self.changed.notify_one();
+    }
+
+    /// Stops keeping `slot` alive. Do this before releasing the slot,
+    /// otherwise the refreshes keep it in use.
+    pub fn unregister(&self, slot: SlotArg) {
+        self.slots.lock().unwrap().remove(&slot);
+        self.changed.notify_one();
+    }
+
+    /// # Returns
+    ///
+    /// If `slot` is kept alive.
+    pub fn is_registered(&self, slot: SlotArg) -> bool {
+        self.slots.lock().unwrap().contains_key(&slot)
+    }
+
+    /// # Returns
+    ///
+    /// The speed `slot` is refreshed with, if it is registered.
+    pub fn speed(&self, slot: SlotArg) -> Option<SpeedArg> {
+        self.slots
+            .lock()
+            .unwrap()
+            .get(&slot)
+            .map(|(speed, _)| *speed)
+    }
+
+    /// Notes the speed of a registered slot read from the bus and postpones its refresh.
+    fn observe(&self, message: &Message, now: Instant) {
+        let (slot, speed) = match *message {
+            Message::LocoSpd(slot, speed) | Message::SlRdData(slot, _, _, speed, ..) => {
+                (slot, speed)
+            }
+            _ => return,
+        };
+
+        let due = self.next_due(now);
+        if let Some(entry) = self.slots.lock().unwrap().get_mut(&slot) {
+            *entry = (speed, due);
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The slots to refresh at `now` with their speed, which are rescheduled.
+    fn take_due(&self, now: Instant) -> Vec<(SlotArg, SpeedArg)> {
+        let mut due = Vec::new();
+        let mut slots = self.slots.lock().unwrap();
+        for (slot, (speed, at)) in slots.iter_mut() {
+            if *at <= now {
+                *at = self.next_due(now);
+                due.push((*slot, *speed));
+            }
+        }
+        due
+    }
+
+    /// # Returns
+    ///
+    /// When the next registered slot has to be refreshed.
+    fn next_refresh(&self) -> Option<Instant> {
+        self.slots.lock().unwrap().values().map(|(_, at)| *at).min()
+    }
+
+    /// # Returns
+    ///
+    /// One interval after `now`, jittered by up to a tenth of the interval.
+    fn next_due(&self, now: Instant) -> Instant {
+        let mut seed = self.seed.lock().unwrap();
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+
+        let jitter = self.interval / 10;
+        let jitter = match jitter.as_nanos() as u64 {
+            0 => Duration::ZERO,
+            max => Duration::from_nanos(*seed % max),
+        };
+        now + self.interval - jitter
+    }
+}
+
+/// Runs the service, refreshing the slots of `keep_alive` with `handle`
+/// and observing the speeds received by `messages`, until the controller is dropped.
+pub(crate) async fn run(
+    keep_alive: &KeepAlive,
+    handle: CommandHandle,
+    mut messages: Receiver<LocoDriveMessage>,
+) {
+    loop {
+        let next = keep_alive.next_refresh();
+
+        tokio::select! {
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    keep_alive.observe(&message, Instant::now())
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = keep_alive.changed.notified() => {}
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                for (slot, speed) in keep_alive.take_due(Instant::now()) {
+                    if let Err(err) = handle.send_message(Message::LocoSpd(slot, speed)).await {
+                        log_error!("Could not refresh slot {}: {}", slot.slot(), err);
+                    }
+                }
+            }
+        }
+    }
+}
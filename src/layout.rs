@@ -0,0 +1,162 @@
+use crate::args::{InArg, SwitchArg, SwitchDirection};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A locomotive known to the layout.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Loco {
+    /// The unique name of the locomotive
+    pub id: String,
+    /// The decoders address (0 - 16383)
+    pub address: u16,
+    /// The count of speed steps the decoder uses, if known
+    pub speed_steps: Option<u8>,
+}
+
+/// A turnout of the layout.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Turnout {
+    /// The unique name of the turnout
+    pub id: String,
+    /// The address the turnout is switched with (0 - 2047)
+    pub address: u16,
+}
+
+impl Turnout {
+    /// Creates the [`SwitchArg`] to switch this turnout.
+    ///
+    /// # Parameters
+    ///
+    /// - `direction`: The direction the turnout should switch to
+    /// - `state`: The activation state of the turnout
+    pub fn switch_arg(&self, direction: SwitchDirection, state: bool) -> SwitchArg {
+        SwitchArg::new(self.address, direction, state)
+    }
+}
+
+/// A sensor of the layout.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sensor {
+    /// The unique name of the sensor
+    pub id: String,
+    /// The sensors address with the source type as least significant bit (0 - 4095),
+    /// as returned by [`InArg::address_ds54()`]
+    pub address: u16,
+}
+
+impl Sensor {
+    /// # Returns
+    ///
+    /// If the sensor input `in_arg` was reported by this sensor.
+    pub fn matches(&self, in_arg: &InArg) -> bool {
+        in_arg.address_ds54() == self.address
+    }
+}
+
+/// A block of the layout, a track section trains can stop in.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    /// The unique name of the block
+    pub id: String,
+    /// The ids of the sensors detecting trains in this block
+    pub sensors: Vec<String>,
+}
+
+/// All locomotives known to the layout.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Roster {
+    /// The known locomotives
+    locos: Vec<Loco>,
+}
+
+impl Roster {
+    /// Creates a new empty roster.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a locomotive to the roster. A locomotive with the same id is replaced.
+    pub fn add(&mut self, loco: Loco) {
+        match self.locos.iter_mut().find(|known| known.id == loco.id) {
+            Some(known) => *known = loco,
+            None => self.locos.push(loco),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The locomotive with the given `id`, if known.
+    pub fn by_id(&self, id: &str) -> Option<&Loco> {
+        self.locos.iter().find(|loco| loco.id == id)
+    }
+
+    /// # Returns
+    ///
+    /// The locomotive with the given `address`, if known.
+    pub fn by_address(&self, address: u16) -> Option<&Loco> {
+        self.locos.iter().find(|loco| loco.address == address)
+    }
+
+    /// # Returns
+    ///
+    /// All known locomotives in order of adding.
+    pub fn locos(&self) -> &[Loco] {
+        &self.locos
+    }
+}
+
+/// Describes the layout: its locomotives, turnouts, sensors and blocks.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LayoutModel {
+    /// The locomotives of the layout
+    pub roster: Roster,
+    /// The turnouts of the layout
+    pub turnouts: Vec<Turnout>,
+    /// The sensors of the layout
+    pub sensors: Vec<Sensor>,
+    /// The blocks of the layout
+    pub blocks: Vec<Block>,
+}
+
+impl LayoutModel {
+    /// Creates a new empty layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The turnout with the given `id`, if known.
+    pub fn turnout(&self, id: &str) -> Option<&Turnout> {
+        self.turnouts.iter().find(|turnout| turnout.id == id)
+    }
+
+    /// # Returns
+    ///
+    /// The sensor with the given `id`, if known.
+    pub fn sensor(&self, id: &str) -> Option<&Sensor> {
+        self.sensors.iter().find(|sensor| sensor.id == id)
+    }
+
+    /// # Returns
+    ///
+    /// The sensor that reported the sensor input `in_arg`, if known.
+    pub fn sensor_for(&self, in_arg: &InArg) -> Option<&Sensor> {
+        self.sensors.iter().find(|sensor| sensor.matches(in_arg))
+    }
+
+    /// # Returns
+    ///
+    /// The block with the given `id`, if known.
+    pub fn block(&self, id: &str) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id == id)
+    }
+}
@@ -1,12 +1,232 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+// The scaffolding of the uniffi bindings has to be set up in the crate root
+#[cfg(feature = "mobile")]
+uniffi::setup_scaffolding!();
+
+/// Holds the macros logging the crates events
+#[cfg(feature = "control")]
+#[macro_use]
+mod logging;
+/// Holds the [`adapter::AdapterProfile`]s initializing interfaces after opening their port.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod adapter;
 /// Holds all arguments used in the messages
 pub mod args;
+/// Holds the [`audit::AuditSink`]s recording every command send with its outcome.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod audit;
+/// Holds the [`automation::Automation`] steps to script the automation of a layout.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod automation;
+/// Holds the [`block_control::BlockControl`] protecting trains by automatic brake control.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod block_control;
+/// Holds a [`blocking::BlockingLocoDriveController`] for applications without an async runtime.
+/// This modules is contained in the `blocking` feature. You have to explicitly activate it.
+#[cfg(feature = "blocking")]
+pub mod blocking;
+/// Holds the [`bridge::LocoNetBridge`] forwarding messages between transports.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod bridge;
+/// Holds the [`config::LocodriveConfig`] loadable from TOML or RON for the whole stack.
+/// This modules is contained in the `config` feature. You have to explicitly activate it.
+#[cfg(feature = "config")]
+pub mod config;
+/// Holds conformance tests against byte sequences of the LocoNet documentation
+mod conformance;
+/// Holds the [`consist::ConsistManager`] building advanced consists of linked slots.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod consist;
+/// Holds the correlation of received answers to their requests
+#[cfg(feature = "control")]
+mod correlation;
+/// Holds the [`dcc::DccPacket`]s building and decoding the DCC packets send by immediate packets.
+pub mod dcc;
+/// Holds the filter dropping duplicated frames
+#[cfg(feature = "control")]
+mod dedup;
+/// Holds the [`discovery::PortCandidate`]s found by probing the serial ports.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod discovery;
+/// Holds the [`embedded::EmbeddedSession`] and [`embedded::BlockingEmbeddedSession`] talking to the model railroad over `embedded-io` serial types.
+/// This modules is contained in the `embedded` feature. You have to explicitly activate it.
+#[cfg(feature = "embedded")]
+pub mod embedded;
 /// Holds all error messages that may occur
 pub mod error;
+/// Holds the [`fast_clock::LayoutClock`] following and the [`fast_clock::ClockMaster`] running the layout clock.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod fast_clock;
+/// Holds the C interface declared by `include/locodrive.h`, passing frames as byte buffers.
+/// This modules is contained in the `ffi` feature. You have to explicitly activate it.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Holds the adaptive buffer the frames are read through
+#[cfg(feature = "control")]
+mod frame_reader;
+/// Holds the [`hotplug::HotplugWatcher`] attaching controllers to interfaces when they are plugged in.
+/// This modules is contained in the `hotplug` feature. You have to explicitly activate it.
+#[cfg(feature = "hotplug")]
+pub mod hotplug;
+/// Holds the [`imm_packet::ImmPacketSender`] to send batches of immediate packets.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod imm_packet;
+/// Holds the [`inventory::InventoryScanner`] discovering the devices on the bus and exporting them as [`inventory::Inventory`].
+/// This modules is contained in the `config` feature. You have to explicitly activate it.
+#[cfg(feature = "config")]
+pub mod inventory;
+/// Holds the [`jmri::JmriJson`] conversions of throttles, turnouts and sensors to the JSON protocol of JMRI.
+/// This modules is contained in the `jmri` feature. You have to explicitly activate it.
+#[cfg(feature = "jmri")]
+pub mod jmri;
+/// Holds the [`keep_alive::KeepAlive`] service refreshing slots to prevent their purge.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod keep_alive;
+/// Holds the [`layout::LayoutModel`] describing the locomotives, turnouts, sensors and blocks of a layout.
+pub mod layout;
+/// Holds the lines of the LoconetOverTcp protocol carrying frames
+#[cfg(any(feature = "control", feature = "wasm"))]
+mod line_protocol;
+/// Holds the [`load_test::LoadTest`] measuring command latencies under bus load.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod load_test;
 /// Holds a [`loco_controller::LocoDriveController`] to manage communication to a serial port based model railroad system.
 /// This modules is contained in the `control` feature. You have to explicitly activate it.
 #[cfg(feature = "control")]
 pub mod loco_controller;
+/// Holds the [`loco_server::LocoServer`] serving the LoconetOverTcp protocol to network clients.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod loco_server;
+/// Holds the [`manager::Manager`]s tracking the slot, switch, sensor and throttle states from the bus messages.
+/// This modules is contained in the `std` feature, which is activated by default.
+#[cfg(feature = "std")]
+pub mod manager;
+/// Holds the [`message_ref::MessageRef`] to inspect frames without decoding them.
+pub mod message_ref;
+/// Holds the [`mobile::MobileController`] and [`mobile::Throttle`] exposed to Kotlin and Swift throttle apps by uniffi.
+/// This modules is contained in the `mobile` feature. You have to explicitly activate it.
+#[cfg(feature = "mobile")]
+pub mod mobile;
+/// Holds the [`mqtt::MqttBridge`] publishing layout events to and accepting commands from an MQTT broker.
+/// This modules is contained in the `mqtt` feature. You have to explicitly activate it.
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+/// Holds the [`names::NameRegistry`] naming turnouts, sensors and locomotives by their address.
+/// This modules is contained in the `std` feature, which is activated by default.
+#[cfg(feature = "std")]
+pub mod names;
+/// Holds the [`occupancy::BlockOccupancy`] tracking which blocks are occupied.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod occupancy;
+/// Holds the [`programmer::Programmer`] to read and write decoder cvs guarded by an interlock.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod programmer;
 /// Holds the [`protocol::Message`]s that can be send to and received from the model railroad system.
 pub mod protocol;
+/// Holds the Python bindings of the messages, their arguments and an asyncio driven controller.
+/// This modules is contained in the `python` feature. You have to explicitly activate it.
+#[cfg(feature = "python")]
+pub mod python;
+/// Holds the [`refresh::RefreshConsolidator`] limiting slot refreshes of bridged throttles.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod refresh;
+/// Holds the [`replay::ReplayTransport`] replaying captured sessions with their timing.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod replay;
+/// Holds the importer of Rocrail plan files into a [`layout::LayoutModel`].
+/// This modules is contained in the `rocrail` feature. You have to explicitly activate it.
+#[cfg(feature = "rocrail")]
+pub mod rocrail;
+/// Holds the [`routes::RouteSetter`] switching named routes of turnouts.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod routes;
+/// Holds the [`runtime::LayoutRuntime`] running a layout with its controller.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod runtime;
+/// Holds the [`signals::SignalSetter`] showing the aspects of signals by switches or extended accessory decoders.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod signals;
+/// Holds the [`simulator::CommandStation`] simulating a bus with a command station attached.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod simulator;
+/// Holds the [`slot_cache::SlotResolver`] resolving the slots of locomotive addresses.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod slot_cache;
+/// Holds the tracking of the slots used by a controller
+#[cfg(feature = "control")]
+mod slot_usage;
+/// Holds the [`snapshot::LayoutSnapshot`] saving and restoring the state of a layout.
+/// This modules is contained in the `config` feature. You have to explicitly activate it.
+#[cfg(feature = "config")]
+pub mod snapshot;
+/// Holds the [`speed_ramp::SpeedRamp`] driving slots smoothly to their target speeds.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod speed_ramp;
+/// Holds the [`srcp::SrcpServer`] serving the Simple Railroad Command Protocol to SRCP clients.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod srcp;
+/// Holds the [`stats::Stats`] collected by a [`loco_controller::LocoDriveController`].
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod stats;
+/// Holds the [`subscription::FilteredReceiver`] to receive only messages of one kind.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod subscription;
 /// Holds test for controlling the correctness of the implemented protocol
 mod tests;
+/// Holds the [`traffic::TrafficAnalyzer`] counting the traffic per op code and device.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod traffic;
+/// Holds the [`transaction::Transaction`]s grouping operations spanning several messages.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod transaction;
+/// Holds the [`transport::LocoNetTransport`] to connect a [`loco_controller::LocoDriveController`] in memory.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod transport;
+/// Holds the [`turnouts::TurnoutDriver`] pacing the throws of turnouts and switching their outputs off.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod turnouts;
+/// Holds the [`websocket::WebSocketTransport`] talking to the model railroad through a WebSocket bridge from the browser.
+/// This modules is contained in the `wasm` feature. You have to explicitly activate it.
+#[cfg(feature = "wasm")]
+pub mod websocket;
+/// Holds the [`wire::TestVector`]s of the wire level compatibility corpus.
+pub mod wire;
+/// Holds the [`withrottle::WiThrottleServer`] serving the WiThrottle protocol to throttle apps.
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod withrottle;
+/// Holds the z21 LAN protocol tunneling the messages to a z21 central, see [`transport::LocoNetTransport::z21()`].
+/// This modules is contained in the `control` feature. You have to explicitly activate it.
+#[cfg(feature = "control")]
+pub mod z21;
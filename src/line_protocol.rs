@@ -0,0 +1,41 @@
+use crate::error::MessageParseError;
+use crate::protocol::Message;
+
+/// Parses a `line` of the LoconetOverTcp protocol carrying a frame after `command`,
+/// like `SEND 83 7C` or `RECEIVE 83 7C`.
+///
+/// # Returns
+///
+/// The message of the line or `None` if the line is no `command`.
+///
+/// # Errors
+///
+/// If the bytes of the line are no valid message.
+pub(crate) fn parse_frame_line(
+    command: &str,
+    line: &str,
+) -> Option<Result<Message, MessageParseError>> {
+    let mut words = line.split_whitespace();
+    if !words.next()?.eq_ignore_ascii_case(command) {
+        return None;
+    }
+
+    let frame = match words
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+    {
+        Ok(frame) => frame,
+        Err(err) => return Some(Err(MessageParseError::invalid_format(err.to_string(), 0))),
+    };
+
+    Some(Message::parse(&frame))
+}
+
+/// # Returns
+///
+/// The line of the `command` followed by the bytes of `frame` in hex.
+pub(crate) fn format_frame(command: &str, frame: &[u8]) -> String {
+    frame.iter().fold(command.to_string(), |line, byte| {
+        format!("{} {:02X}", line, byte)
+    })
+}
@@ -0,0 +1,284 @@
+use crate::loco_controller::{CommandHandle, LocoDriveController, SendOptions};
+use crate::protocol::Message;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+
+/// The distribution of measured latencies.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct LatencyDistribution {
+    /// The measured latencies in ascending order
+    samples: Vec<Duration>,
+}
+
+impl LatencyDistribution {
+    /// Creates a distribution of the given `samples`.
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        LatencyDistribution { samples }
+    }
+
+    /// # Returns
+    ///
+    /// The count of measured latencies.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// # Returns
+    ///
+    /// If no latency was measured.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// # Returns
+    ///
+    /// The latency `percentile` percent of the samples are below or equal to,
+    /// using the nearest rank. `None` if no latency was measured.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil();
+        let index = (rank as usize).clamp(1, self.samples.len()) - 1;
+        Some(self.samples[index])
+    }
+
+    /// # Returns
+    ///
+    /// The smallest measured latency.
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    /// # Returns
+    ///
+    /// The largest measured latency.
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+
+    /// # Returns
+    ///
+    /// The mean of the measured latencies.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+}
+
+impl Display for LatencyDistribution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (
+            self.min(),
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max(),
+        ) {
+            (Some(min), Some(p50), Some(p90), Some(p99), Some(max)) => write!(
+                f,
+                "n={} min={:?} p50={:?} p90={:?} p99={:?} max={:?}",
+                self.len(),
+                min,
+                p50,
+                p90,
+                p99,
+                max
+            ),
+            _ => write!(f, "n=0"),
+        }
+    }
+}
+
+/// The result of a [`LoadTest`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct LoadTestReport {
+    /// The latencies from writing the command until it was received back
+    pub echo: LatencyDistribution,
+    /// The latencies from writing the command until it was acknowledged, if measured
+    pub ack: Option<LatencyDistribution>,
+    /// How often sending the command failed
+    pub failures: usize,
+    /// How many background messages were sent during the test
+    pub background_sent: usize,
+    /// How long the test took
+    pub duration: Duration,
+}
+
+impl Display for LoadTestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "echo: {}", self.echo)?;
+        if let Some(ack) = &self.ack {
+            writeln!(f, "ack: {}", ack)?;
+        }
+        write!(
+            f,
+            "failures: {}, background messages: {}, duration: {:?}",
+            self.failures, self.background_sent, self.duration
+        )
+    }
+}
+
+/// Measures the latency of a command while generating background traffic on the bus.
+///
+/// The command is sent repeatedly. Each time it is measured how long it took until it was
+/// received back and optionally until it was acknowledged by a [`Message::LongAck`].
+/// Meanwhile a background message is sent at a configurable rate through its own
+/// [`CommandHandle`], so the command competes with it for the port like with other components.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{SlotArg, SpeedArg};
+/// use locodrive::load_test::LoadTest;
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::protocol::Message;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let controller = LocoDriveController::builder("/dev/ttyUSB0", 115_200)
+///         .build()
+///         .await
+///         .unwrap();
+///
+///     let report = LoadTest::new(Message::LocoSpd(SlotArg::new(1), SpeedArg::Stop))
+///         .background(Message::RqSlData(SlotArg::new(2)), 20)
+///         .samples(200)
+///         .run(&controller)
+///         .await;
+///
+///     println!("{}", report);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadTest {
+    /// The command to measure
+    command: Message,
+    /// How many times the command is measured
+    samples: usize,
+    /// The pause between two measurements
+    pause: Duration,
+    /// Whether to measure the acknowledgment of the command
+    measure_ack: bool,
+    /// The background message and how many are sent per second
+    background: Option<(Message, u32)>,
+}
+
+impl LoadTest {
+    /// Creates a load test measuring `command` 100 times with a pause of 10 milliseconds
+    /// and no background traffic.
+    pub fn new(command: Message) -> Self {
+        LoadTest {
+            command,
+            samples: 100,
+            pause: Duration::from_millis(10),
+            measure_ack: false,
+            background: None,
+        }
+    }
+
+    /// Sets how many times the command is measured.
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Sets the pause between two measurements.
+    pub fn pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Sets whether to also measure the time until the command is acknowledged.
+    /// Only useful for commands the model railroad answers with a [`Message::LongAck`].
+    pub fn measure_ack(mut self, measure_ack: bool) -> Self {
+        self.measure_ack = measure_ack;
+        self
+    }
+
+    /// Sends `message` `per_second` times a second as background traffic.
+    pub fn background(mut self, message: Message, per_second: u32) -> Self {
+        self.background = Some((message, per_second));
+        self
+    }
+
+    /// Runs the load test with the port of `controller`.
+    pub async fn run(&self, controller: &LocoDriveController) -> LoadTestReport {
+        let start = Instant::now();
+        let background_sent = Arc::new(AtomicUsize::new(0));
+
+        let background = match self.background {
+            Some((message, per_second)) if per_second > 0 => {
+                Some(tokio::spawn(Self::send_background(
+                    controller.command_handle(),
+                    message,
+                    Duration::from_secs(1) / per_second,
+                    background_sent.clone(),
+                )))
+            }
+            _ => None,
+        };
+
+        let handle = controller.command_handle();
+        let mut echo = Vec::with_capacity(self.samples);
+        let mut ack = Vec::with_capacity(self.samples);
+        let mut failures = 0;
+
+        for _ in 0..self.samples {
+            let sent_at = Instant::now();
+            match handle.send_message(self.command).await {
+                Ok(()) => echo.push(sent_at.elapsed()),
+                Err(_) => failures += 1,
+            }
+
+            if self.measure_ack {
+                let options = SendOptions {
+                    require_ack: true,
+                    ..SendOptions::default()
+                };
+                let sent_at = Instant::now();
+                match handle.send_message_acked(self.command, options).await {
+                    Ok(Some(_)) => ack.push(sent_at.elapsed()),
+                    _ => failures += 1,
+                }
+            }
+
+            sleep(self.pause).await;
+        }
+
+        if let Some(background) = background {
+            background.abort();
+        }
+
+        LoadTestReport {
+            echo: LatencyDistribution::new(echo),
+            ack: self.measure_ack.then(|| LatencyDistribution::new(ack)),
+            failures,
+            background_sent: background_sent.load(Ordering::Relaxed),
+            duration: start.elapsed(),
+        }
+    }
+
+    /// Sends `message` every `period` until aborted, counting the sent messages.
+    async fn send_background(
+        handle: CommandHandle,
+        message: Message,
+        period: Duration,
+        sent: Arc<AtomicUsize>,
+    ) {
+        let mut ticks = interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+            if handle.send_message(message).await.is_ok() {
+                sent.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -1,589 +1,2913 @@
+use crate::adapter::AdapterProfile;
+use crate::args::{Ack1Arg, InArg, SlotArg, SnArg, Stat1Arg, State, TrkArg, WrSlDataStructure};
+use crate::audit::{AuditLog, AuditSink};
+use crate::correlation::{echo_channel, AnswerCorrelator, EchoMatcher, EchoSender};
+use crate::dedup::DuplicateFilter;
+use crate::discovery::{self, PortCandidate};
 use crate::error::{LocoDriveSendingError, MessageParseError};
-use crate::protocol::Message;
+use crate::frame_reader::FrameReader;
+use crate::keep_alive::{self, KeepAlive};
+use crate::protocol::{ExtraBytes, Message, ParseOptions, MAX_MESSAGE_LEN};
+use crate::slot_usage::SlotUsage;
+use crate::stats::{Stats, StatsCollector};
+use crate::subscription::{
+    self, Envelope, EnvelopeReceiver, FilteredReceiver, PowerEvent, SlotUpdate,
+};
+use crate::traffic::{TrafficKey, TrafficReport};
+use crate::transaction::{Transaction, TransactionTracker};
+use crate::transport::{LocoNetTransport, ReadPort, ReadSource, SharedTransport, WritePort};
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::broadcast::Sender;
-use tokio::sync::Notify;
-use tokio::task::JoinHandle;
-use tokio::time::{sleep, Duration};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::{RecvError, SendError};
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, watch};
+use tokio::time::{sleep, sleep_until, timeout, Duration, Instant};
 use tokio_serial::{
-    DataBits, Error, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits,
+    DataBits, Error, FlowControl, Parity, SerialPort, SerialPortBuilderExt, StopBits,
 };
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::Stream;
+use tokio_util::sync::CancellationToken;
 
 /// This message is sent when data are received from the loco connection.
+///
+/// New kinds of events may be added in minor releases, so matches need a wildcard arm.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum LocoDriveMessage {
     /// A normal loco connection message. Consider that all [`LocoDriveMessage::Answer`] messages are also send this way.
     Message(Message),
-    /// This is a response for the before received message.
+    /// A message send by this controller or its [`CommandHandle`]s and echoed back by the model railroad.
+    /// Only send instead of [`LocoDriveMessage::Message`] if configured by
+    /// [`LocoDriveControllerBuilder::tag_send_messages()`].
+    Echo(Message),
+    /// This is a response for a before received request, like a switch, slot or immediate packet request.
     /// The response message is represent by the first argument and
-    /// the before received message is represented the second argument.
+    /// the before received request is represented the second argument.
+    /// Requests not answered within their timeout are not correlated anymore,
+    /// see [`LocoDriveControllerBuilder::answer_timeout()`].
     /// Consider that the here mentioned received message is also send as normal [`LocoDriveMessage::Message`] afterwards.
     Answer(Message, Message),
+    /// A request awaiting an answer, like a switch request, was not answered within its timeout,
+    /// see [`LocoDriveControllerBuilder::answer_timeout()`].
+    /// The request is represented by the argument.
+    AnswerTimeout(Message),
+    /// The vendor extension bytes received with the message, if configured by
+    /// [`LocoDriveControllerBuilder::extra_slot_bytes()`].
+    /// It is send after the message was send as [`LocoDriveMessage::Message`].
+    VendorBytes(Message, Vec<u8>),
+    /// A slot used by this controller was purged by the command station, or released by another device.
+    /// Re-acquire the slot to keep controlling its locomotive.
+    /// It is send after the message reporting the slot not in use anymore was send as [`LocoDriveMessage::Message`].
+    /// See [`LocoDriveController::slots_in_use()`] for when a slot is used.
+    SlotPurged(SlotArg),
+    /// A message written by this controller or its [`CommandHandle`]s with its bytes.
+    /// It is send when the message was written to the port, whether the model railroad echoes it or not.
+    /// Only send if configured by [`LocoDriveControllerBuilder::report_sent_messages()`].
+    Sent(Message, Vec<u8>),
     /// This message is send when the by the LocoDrive received message is not readable.
     /// Please look at [`MessageParseError`] for more information on the errors.
     Error(MessageParseError),
     /// This message is send when some error appears on opening the serial port.
     SerialPortError(Error),
+    /// This message is send when an operation spanning several messages completed.
+    /// It is send after the final answer was send as [`LocoDriveMessage::Message`].
+    /// Please look at [`Transaction`] for more information.
+    Transaction(Transaction),
+    /// This message is send when no traffic was seen on the bus for the time configured by
+    /// [`LocoDriveControllerBuilder::idle_after()`]. The argument is how long the bus was idle.
+    BusIdle(Duration),
+    /// This message is send when traffic was seen again after a [`LocoDriveMessage::BusIdle`].
+    BusResumed,
+    /// This message is send periodically with the statistics of the connection, if configured by
+    /// [`LocoDriveControllerBuilder::health_interval()`].
+    Health(Stats),
+    /// This message is send when more checksum errors than configured by
+    /// [`LocoDriveControllerBuilder::line_noise_threshold()`] were read within the checksum window,
+    /// which suggests problems with the wiring or the interface.
+    /// The argument is the count of checksum errors within the window.
+    LineNoise(u64),
+    /// This message is send when a device sends more frames than configured by
+    /// [`LocoDriveControllerBuilder::chatty_threshold()`] within the traffic window,
+    /// like a bouncing sensor flooding the bus with reports.
+    /// The arguments are the device and its count of frames within the window.
+    ChattyDevice(TrafficKey, u64),
 }
 
-type SendSynchronisation = Arc<(Arc<Mutex<Vec<u8>>>, Arc<Notify>)>;
-type ReferencedSendSynchronisation<'a> = Arc<(&'a Arc<Mutex<Vec<u8>>>, &'a Arc<Notify>)>;
+impl LocoDriveMessage {
+    /// # Returns
+    ///
+    /// If this event is critical, so it is passed to [`LocoDriveController::critical_events()`].
+    /// These are the power changes [`Message::GpOn`], [`Message::GpOff`] and [`Message::Idle`],
+    /// purged slots, serial port errors and panics of the reading thread.
+    pub fn is_critical(&self) -> bool {
+        matches!(
+            self,
+            LocoDriveMessage::Message(Message::GpOn | Message::GpOff | Message::Idle)
+                | LocoDriveMessage::SlotPurged(_)
+                | LocoDriveMessage::SerialPortError(_)
+                | LocoDriveMessage::Error(MessageParseError::ReaderPanicked(_))
+        )
+    }
+}
 
-/// This struct handles a connection to a serial port based railroad controlling system.
-///
-/// All received messages on the port are send to the defined channel.
-/// - Note: The auto returned messages as defined in the model railroads protocol are also send back to the channel.
-/// But the protocol ensures itself that the writer waits until the model railroad response is received.
-///
-/// # Usage
-///
-/// To send a message see [`LocoDriveController::send_message()`].
-/// The reading thread is start automatically on port creation.
-/// You can just check on your reader channel for new messages.
-/// The reader is automatically dropped when the [`LocoDriveController`] is dropped.
+/// Receives the [`LocoDriveMessage`]s broadcast by a [`LocoDriveController`].
 ///
-/// # Examples
-///
-/// Reading ten messages received from the model railroads:
-/// ```
-/// # use tokio_serial::FlowControl;
-/// # use locodrive::loco_controller::LocoDriveController;
-/// # use locodrive::protocol::Message;
-/// # use locodrive::args::{SlotArg, SpeedArg};
-/// # use locodrive::protocol::Message::LocoSpd;
+/// Other than a plain broadcast receiver this one reports when it lagged behind the channel,
+/// so the lost messages show up in the controllers [`Stats`].
+pub struct LocoDriveReceiver {
+    /// The wrapped broadcast receiver
+    receiver: Receiver<LocoDriveMessage>,
+    /// The statistics to report lagging to
+    stats: Arc<StatsCollector>,
+}
+
+impl LocoDriveReceiver {
+    /// Receives the next message from the controller.
+    ///
+    /// # Errors
+    ///
+    /// - [`RecvError::Lagged`]: If this receiver lagged behind. The count of lost messages is
+    ///   recorded in the controllers [`Stats`] and the next call receives the oldest message
+    ///   still held by the channel.
+    /// - [`RecvError::Closed`]: If the controller was dropped.
+    pub async fn recv(&mut self) -> Result<LocoDriveMessage, RecvError> {
+        let received = self.receiver.recv().await;
+        if let Err(RecvError::Lagged(lost)) = received {
+            self.stats.record_lag(lost);
+        }
+        received
+    }
+
+    /// Creates another receiver of the same controller.
+    /// It receives the messages broadcast after its creation.
+    pub fn resubscribe(&self) -> Self {
+        LocoDriveReceiver {
+            receiver: self.receiver.resubscribe(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// Configures how [`LocoDriveController::send_message_with()`] retries a message
+/// and which answer it awaits.
 ///
-/// #[tokio::main]
-/// async fn main() {
-///     use locodrive::args::{SwitchArg, SwitchDirection};
-///     use locodrive::protocol::Message::SwReq;
-///     // Creating a sender and receiver for the LocoDriveConnector.
-///     let (sender, mut receiver) = tokio::sync::broadcast::channel(1);
+/// The default sends the message once and only awaits it to be received back.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SendOptions {
+    /// How often to retry a message that was not received back or was answered
+    /// with a failed [`Message::LongAck`].
+    pub retries: u32,
+    /// How long to wait before retrying.
+    pub retry_delay: Duration,
+    /// Whether a not failed [`Message::LongAck`] has to be received for the message to be send.
+    /// If no such answer is received, the attempt fails with a [`LocoDriveSendingError::Timeout`].
+    pub require_ack: bool,
+    /// How long to wait for a [`Message::LongAck`] after the message was received back.
+    ///
+    /// - `None`: Uses the controllers sending timeout if `require_ack` is set,
+    ///   otherwise no answer is awaited.
+    /// - `Some(timeout)`: Awaits the answer that long. If `require_ack` is not set,
+    ///   only a failed answer in this time fails the attempt.
+    ///   This is useful for [`Message::SwReq`] which is only answered if it failed.
+    pub ack_timeout: Option<Duration>,
+    /// Whether the message may write to the reserved system slots (120 - 126),
+    /// even if the controller blocks this. Only the dedicated APIs for these slots,
+    /// like the [`crate::programmer::Programmer`], should set this.
+    pub allow_reserved_slots: bool,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        SendOptions {
+            retries: 0,
+            retry_delay: Duration::from_millis(100),
+            require_ack: false,
+            ack_timeout: None,
+            allow_reserved_slots: false,
+        }
+    }
+}
+
+/// Configures whether the [`LocoDriveController`] awaits the echo of a written message.
 ///
-///     // Creating a LocoDriveConnector, reading from the port '/dev/ttyUSB0'.
-///     let mut loco_controller = match LocoDriveController::new(
-///         "/dev/ttyUSB0",
-///         115_200,
-///         5000,
-///         FlowControl::Software,
-///         sender,
-///         false,
-///     ).await {
-///         Ok(loco_controller) => loco_controller,
-///         Err(err) => {
-///             eprintln!("Error: Could not connect to the serial port!");
-///             return;
-///         }
-///     };
+/// The model railroad normally sends every written message back. Echoes are matched by the
+/// parsed message, so interfaces altering the echoed bytes are supported.
+/// Some interfaces do not echo at all, so awaiting the echo would let every sending time out.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EchoPolicy {
+    /// Awaits the echo for the sending timeout and fails with [`LocoDriveSendingError::Timeout`]
+    /// if it is not received.
+    Require,
+    /// Awaits the echo for the sending timeout, but a missing echo is no error.
+    Optional,
+    /// Does not await any echo.
+    None,
+}
+
+/// How the controller handles subscribers lagging behind its broadcast channel,
+/// see [`LocoDriveControllerBuilder::overflow_policy()`].
 ///
-///     loco_controller.send_message(SwReq(SwitchArg::new(39, SwitchDirection::Straight, true)));
+/// The broadcast channel holds the messages not received by all subscribers yet, up to its capacity.
+/// Whatever the policy, the messages lost by lagging subscribers are counted in the [`Stats`]
+/// and streams created by [`LocoDriveController::messages()`] never lose messages.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OverflowPolicy {
+    /// Drops the oldest messages for lagging subscribers.
+    DropOldest,
+    /// Holds reading back while the channel is full, but at most for the given time.
+    /// The unread bytes are buffered by the port meanwhile.
+    /// When the time is up, the oldest messages are dropped and [`Stats::overflow_timeouts`] is counted.
+    Block(Duration),
+    /// Drops the oldest messages like [`OverflowPolicy::DropOldest`], but additionally passes the
+    /// critical events to the unbounded queue of [`LocoDriveController::critical_events()`].
+    /// See [`LocoDriveMessage::is_critical()`] for which events are critical.
+    SpillCritical,
+}
+
+/// Configures and creates a [`LocoDriveController`].
 ///
-///     let mut read_messages = 0;
-///     while let Ok(message) = receiver.recv().await {
-///         println!("GOT = {:?}", message);
-///         read_messages += 1;
-///         if read_messages >= 10 {
-///            break;
-///         }
-///     }
-/// }
-/// ```
-pub struct LocoDriveController {
-    /// The serial port used to connect to the model railroads.
-    port: SerialStream,
-    /// Here are all values bundled for the intern check of the message sending and receiving.
-    /// The Mutex is used to save the last message send to check against.
-    /// The two Condvar args are used synchronize the writer.
-    send: SendSynchronisation,
-    /// This is used to call the reader to stop reading.
-    stop: Arc<Mutex<bool>>,
-    /// Fire stop to notify the reader to recheck if it should stop
-    fire_stop: Arc<Notify>,
-    /// This is the thread to await for joining if one reading thread should be closed.
-    reading_thread: Option<JoinHandle<()>>,
-    /// How long to wait on success of sending.
+/// Use [`LocoDriveController::builder()`] to create one.
+#[derive(Debug, Clone)]
+pub struct LocoDriveControllerBuilder {
+    /// The name of the port to connect to
+    port_name: String,
+    /// The baud rate to use
+    baud_rate: u32,
+    /// How long to wait on success of sending
     sending_timeout: u64,
-    /// Securing one writing thread at a time
-    wait_for_write: Arc<tokio::sync::Mutex<bool>>,
+    /// The flow control to use for the port
+    flow_control: FlowControl,
+    /// The channel to send received messages to, if the user brings one
+    send_to: Option<Sender<LocoDriveMessage>>,
+    /// The capacity of the channel the controller creates if no `send_to` is set
+    channel_capacity: usize,
+    /// Whether to not broadcast messages send by the controller itself
+    ignore_send_messages: bool,
+    /// Whether to broadcast messages send by the controller itself as echoes
+    tag_send_messages: bool,
+    /// Whether to broadcast messages when they are written
+    report_sent_messages: bool,
+    /// Where to mirror the raw bytes read from and written to the port
+    raw_tap: Option<Sender<(Direction, Vec<u8>, Instant)>>,
+    /// The minimal gap between the last bus activity and the next write
+    tx_gap: Duration,
+    /// The additional gap per priority delay step of a message
+    priority_backoff: Duration,
+    /// After which time without traffic the bus is reported as idle
+    idle_after: Option<Duration>,
+    /// How to handle slot data frames longer than the standard length
+    extra_slot_bytes: ExtraBytes,
+    /// How strictly to parse the read frames
+    parse_options: ParseOptions,
+    /// Within which time identical frames are dropped as duplicates
+    dedup_window: Option<Duration>,
+    /// Within which time checksum errors are counted as recent
+    checksum_window: Duration,
+    /// Above which count of recent checksum errors the line is reported as noisy
+    line_noise_threshold: Option<u64>,
+    /// Within which time the traffic is counted per op code and device
+    traffic_window: Duration,
+    /// Above which count of frames within the traffic window a device is reported as chatty
+    chatty_threshold: Option<u64>,
+    /// How often the statistics are broadcast
+    health_interval: Option<Duration>,
+    /// How often the slots registered to be kept alive are refreshed
+    keep_alive: Option<Duration>,
+    /// Whether to await the echo of written messages
+    echo_policy: EchoPolicy,
+    /// How to initialize the interface after opening the port
+    adapter: AdapterProfile,
+    /// How long to hold writes back at most while the master is busy
+    busy_hold: Duration,
+    /// Whether to block writes to the reserved system slots
+    strict_slots: bool,
+    /// How long to await the answers to requests with the op code
+    answer_timeouts: HashMap<u8, Duration>,
+    /// How long to await the answers to other requests
+    default_answer_timeout: Duration,
+    /// The in memory transport to connect to instead of the serial port
+    transport: Option<SharedTransport>,
+    /// How to handle subscribers lagging behind the broadcast channel
+    overflow_policy: OverflowPolicy,
+    /// Where to record the outcome of every command send
+    audit: Option<AuditLog>,
 }
 
-impl LocoDriveController {
-    /// Creates a new serial port connection to a model railroad and starts reading on that port
+impl LocoDriveControllerBuilder {
+    /// Sets the name of the port to connect to.
+    pub fn port_name(mut self, port_name: &str) -> Self {
+        self.port_name = port_name.to_string();
+        self
+    }
+
+    /// Sets how long to wait for response for the model railroads connection
+    /// while sending messages. Defaults to 5000 milliseconds.
+    pub fn sending_timeout(mut self, sending_timeout: u64) -> Self {
+        self.sending_timeout = sending_timeout;
+        self
+    }
+
+    /// Sets which mode of flow control to use for this port.
+    /// Defaults to [`FlowControl::Software`](https://docs.rs/tokio-serial/latest/tokio_serial/enum.FlowControl.html).
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// Sets the capacity of the broadcast channel created by the controller.
+    /// Defaults to 64 messages.
     ///
-    /// # Parameter
+    /// If a subscriber falls behind by more messages than this capacity it lags and the
+    /// oldest messages are lost. This is counted in the controllers [`Stats`].
     ///
-    /// - `port_name`: Is the name of the port to connect to.
-    ///   If you are not sure, which ports are allowed use [`tokio_serial::available_ports()`](https://docs.rs/tokio-serial/latest/tokio_serial/fn.available_ports.html).
-    /// - `baud_rate`: The baud rate to use for the port connection.
-    /// - `sending_timeout`: How long to wait for response for the model railroads connection
-    ///   while sending messages.
-    /// - `update_cycles`: How long to wait for incoming messages on reader side,
-    ///   before checking if this reader should close.
-    /// - `flow_control`: Which mode of flow control to use for this port.
-    ///   It is recommended to use [`FlowControl::Software`](https://docs.rs/tokio-serial/latest/tokio_serial/enum.FlowControl.html).
+    /// Ignored if a channel is given using [`LocoDriveControllerBuilder::sender()`].
+    pub fn channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = channel_capacity;
+        self
+    }
+
+    /// Sets how to handle subscribers lagging behind the broadcast channel.
+    /// Defaults to [`OverflowPolicy::DropOldest`].
     ///
-    /// # Error
+    /// [`OverflowPolicy::Block`] waits for the channel to hold less messages than the
+    /// [`LocoDriveControllerBuilder::channel_capacity()`], also if a channel is given using
+    /// [`LocoDriveControllerBuilder::sender()`].
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Uses the given channel to broadcast the received messages instead of creating one.
     ///
-    /// This method exit with an error if the serial port is not reachable or the port could
-    /// not be configured correctly.
+    /// Receivers subscribed directly to this channel can not report lagging to the
+    /// controllers [`Stats`]. Use [`LocoDriveController::subscribe()`] for this.
+    pub fn sender(mut self, send_to: Sender<LocoDriveMessage>) -> Self {
+        self.send_to = Some(send_to);
+        self
+    }
+
+    /// Sets whether messages send by the controller should not be broadcast
+    /// when the model railroad echoes them back. Defaults to `false`.
+    pub fn ignore_send_messages(mut self, ignore_send_messages: bool) -> Self {
+        self.ignore_send_messages = ignore_send_messages;
+        self
+    }
+
+    /// Sets whether messages send by the controller are broadcast as [`LocoDriveMessage::Echo`]
+    /// when the model railroad echoes them back, so they are distinguishable from the traffic of
+    /// other devices. Bridges need this to not pass their own messages back. Defaults to `false`.
     ///
-    /// # Reading
+    /// Ignoring the send messages by [`LocoDriveControllerBuilder::ignore_send_messages()`]
+    /// takes precedence.
+    pub fn tag_send_messages(mut self, tag_send_messages: bool) -> Self {
+        self.tag_send_messages = tag_send_messages;
+        self
+    }
+
+    /// Sets whether messages written by the controller are broadcast as [`LocoDriveMessage::Sent`],
+    /// so monitors capture them even if the model railroad does not echo them
+    /// or their echoes are ignored. Defaults to `false`.
+    pub fn report_sent_messages(mut self, report_sent_messages: bool) -> Self {
+        self.report_sent_messages = report_sent_messages;
+        self
+    }
+
+    /// Sets a channel mirroring the raw bytes of every frame read from or written to the port,
+    /// before it is parsed, together with its [`Direction`] and when it was read or written.
+    /// Protocol analyzers and capture files are built on it. Defaults to no tap.
+    pub fn raw_tap(mut self, raw_tap: Sender<(Direction, Vec<u8>, Instant)>) -> Self {
+        self.raw_tap = Some(raw_tap);
+        self
+    }
+
+    /// Sets the minimal time the bus has to be idle before the controller writes a message.
+    /// Defaults to no gap.
     ///
-    /// - If this thread communicates a [`LocoDriveMessage::SerialPortError`] it will
-    ///   not be receiving any more messages. All other Messages would not lead
-    ///   the reading thread to stop.
-    /// - Lack messages are send twice. Ones as [`LocoDriveMessage::Answer`] and
-    ///   then a second time as [`LocoDriveMessage::Message`].
-    pub async fn new(
-        port_name: &str,
-        baud_rate: u32,
-        sending_timeout: u64,
-        flow_control: FlowControl,
-        send_to: Sender<LocoDriveMessage>,
-        ignore_send_messages: bool,
-    ) -> Result<Self, Error> {
-        // Creation of the port to write to
-        let mut port = match tokio_serial::new(port_name, baud_rate)
-            .data_bits(DataBits::Eight)
-            .stop_bits(StopBits::Two)
-            .parity(Parity::None)
-            .flow_control(flow_control)
-            .timeout(Duration::from_millis(sending_timeout))
-            .open_native_async()
-        {
-            Ok(port) => port,
-            Err(e) => return Err(e),
+    /// Bus activity are all received messages as well as all written messages.
+    /// The model railroads protocol requires a carrier detect backoff of 1.2 milliseconds,
+    /// which is not enforced by most cheap USB adapters.
+    pub fn tx_gap(mut self, tx_gap: Duration) -> Self {
+        self.tx_gap = tx_gap;
+        self
+    }
+
+    /// Sets the time added to the [`LocoDriveControllerBuilder::tx_gap()`] per priority delay
+    /// step of the message to write. Defaults to no backoff.
+    ///
+    /// The steps are given by [`Message::priority_class()`]. Urgent messages like [`Message::GpOff`]
+    /// have no priority delay, so they are written first when the bus becomes idle.
+    /// The protocol uses one bit time (60 microseconds) per step.
+    pub fn priority_backoff(mut self, priority_backoff: Duration) -> Self {
+        self.priority_backoff = priority_backoff;
+        self
+    }
+
+    /// Reports a [`LocoDriveMessage::BusIdle`] when no traffic was seen on the bus for `idle_after`
+    /// and a [`LocoDriveMessage::BusResumed`] when traffic returns. Defaults to no reporting.
+    pub fn idle_after(mut self, idle_after: Duration) -> Self {
+        self.idle_after = Some(idle_after);
+        self
+    }
+
+    /// Sets how to handle slot data frames longer than the standard length.
+    /// Defaults to [`ExtraBytes::Reject`].
+    ///
+    /// Some clone command stations append vendor extension bytes to [`Message::SlRdData`].
+    /// If attached, the extra bytes are broadcast as [`LocoDriveMessage::VendorBytes`].
+    pub fn extra_slot_bytes(mut self, extra_slot_bytes: ExtraBytes) -> Self {
+        self.extra_slot_bytes = extra_slot_bytes;
+        self
+    }
+
+    /// Sets how strictly to parse the read frames. Defaults to lenient parsing.
+    ///
+    /// Bridges should parse leniently to forward unknown bits and values unchanged,
+    /// while protocol validators may reject them as [`MessageParseError::InvalidFormat`].
+    pub fn parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Drops a frame identical to the previous one when it is read within `dedup_window`.
+    /// Defaults to passing all frames.
+    ///
+    /// Some hardware taps deliver each frame twice, which makes state machines count sensor
+    /// transitions twice. The dropped frames are counted in [`Stats::duplicates_dropped`].
+    /// Keep the window short, as messages really repeated within it are dropped as well.
+    pub fn dedup_window(mut self, dedup_window: Duration) -> Self {
+        self.dedup_window = Some(dedup_window);
+        self
+    }
+
+    /// Sets whether to await the echo of written messages. Defaults to [`EchoPolicy::Require`].
+    pub fn echo_policy(mut self, echo_policy: EchoPolicy) -> Self {
+        self.echo_policy = echo_policy;
+        self
+    }
+
+    /// Sets how long writes are held back at most while the master reports to be busy.
+    /// Defaults to 500 milliseconds.
+    ///
+    /// The master broadcasts [`Message::Busy`] while it can not handle requests, so messages
+    /// written meanwhile are likely dropped. Writes wait until the master sends anything else
+    /// or the hold time elapsed. A zero hold time writes immediately.
+    pub fn busy_hold(mut self, busy_hold: Duration) -> Self {
+        self.busy_hold = busy_hold;
+        self
+    }
+
+    /// Sets whether writes to the reserved system slots (120 - 126) are blocked with
+    /// [`LocoDriveSendingError::ReservedSlot`]. Defaults to `true`.
+    ///
+    /// These slots hold the state of the command station, like the fast clock or the
+    /// programming track, so writing to them by accident, like by an off-by-one slot,
+    /// corrupts it. The dedicated APIs, like the [`crate::programmer::Programmer`],
+    /// still write to them. Others may do so using [`SendOptions::allow_reserved_slots`].
+    pub fn strict_slots(mut self, strict_slots: bool) -> Self {
+        self.strict_slots = strict_slots;
+        self
+    }
+
+    /// Sets how long the answer to requests with the op code `opc` is awaited to be matched
+    /// to its request and reported as [`LocoDriveMessage::Answer`].
+    /// Requests not answered in this time are reported as [`LocoDriveMessage::AnswerTimeout`].
+    /// Defaults to [`LocoDriveControllerBuilder::default_answer_timeout()`].
+    ///
+    /// The op code of a message is returned by [`Message::opc()`].
+    pub fn answer_timeout(mut self, opc: u8, answer_timeout: Duration) -> Self {
+        self.answer_timeouts.insert(opc, answer_timeout);
+        self
+    }
+
+    /// Sets how long answers are awaited to be matched to their request and reported as
+    /// [`LocoDriveMessage::Answer`], if no timeout is set for the requests op code.
+    /// Requests not answered in this time are reported as [`LocoDriveMessage::AnswerTimeout`].
+    /// Defaults to one second.
+    pub fn default_answer_timeout(mut self, default_answer_timeout: Duration) -> Self {
+        self.default_answer_timeout = default_answer_timeout;
+        self
+    }
+
+    /// Sets how to initialize the interface after opening the port.
+    /// Defaults to [`AdapterProfile::generic()`].
+    ///
+    /// The baud rate and flow control fixed by the profile replace the ones set on the builder.
+    /// If the profile tells the interface does not echo, no echoes are awaited
+    /// and the written messages are reported like by [`LocoDriveControllerBuilder::report_sent_messages()`].
+    pub fn adapter(mut self, adapter: AdapterProfile) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    /// Sets within which time checksum errors are counted in [`Stats::recent_checksum_errors`].
+    /// Defaults to one minute.
+    pub fn checksum_window(mut self, checksum_window: Duration) -> Self {
+        self.checksum_window = checksum_window;
+        self
+    }
+
+    /// Broadcasts [`LocoDriveMessage::LineNoise`] when more than `line_noise_threshold`
+    /// checksum errors were read within the checksum window,
+    /// see [`LocoDriveControllerBuilder::checksum_window()`]. Defaults to no broadcasting.
+    pub fn line_noise_threshold(mut self, line_noise_threshold: u64) -> Self {
+        self.line_noise_threshold = Some(line_noise_threshold);
+        self
+    }
+
+    /// Sets within which time the read frames are counted per op code and device,
+    /// see [`LocoDriveController::traffic()`]. Defaults to ten seconds.
+    pub fn traffic_window(mut self, traffic_window: Duration) -> Self {
+        self.traffic_window = traffic_window;
+        self
+    }
+
+    /// Broadcasts [`LocoDriveMessage::ChattyDevice`] when a device sends more than `chatty_threshold`
+    /// frames within the traffic window, see [`LocoDriveControllerBuilder::traffic_window()`].
+    /// Defaults to no broadcasting.
+    pub fn chatty_threshold(mut self, chatty_threshold: u64) -> Self {
+        self.chatty_threshold = Some(chatty_threshold);
+        self
+    }
+
+    /// Broadcasts the statistics of the connection as [`LocoDriveMessage::Health`]
+    /// every `health_interval`. Defaults to no broadcasting.
+    pub fn health_interval(mut self, health_interval: Duration) -> Self {
+        self.health_interval = Some(health_interval);
+        self
+    }
+
+    /// Starts the [`KeepAlive`] service refreshing the registered slots every `interval`.
+    /// Defaults to no service.
+    ///
+    /// Choose an interval well below the purge time of the command station,
+    /// which is about 200 seconds for most Digitrax command stations.
+    /// Register the slots with [`LocoDriveController::keep_alive()`].
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Records every command send by the controller and its [`CommandHandle`]s
+    /// with its outcome and timing to `sink`, like a log file or a channel.
+    /// Defaults to no recording.
+    ///
+    /// Controllers built from clones of this builder share the sink.
+    pub fn audit_sink(mut self, sink: impl AuditSink + 'static) -> Self {
+        self.audit = Some(AuditLog::new(sink));
+        self
+    }
+
+    /// Connects to the in memory `transport` instead of the serial port,
+    /// so the controller can be tested without a model railroad.
+    /// Defaults to the serial port named by [`LocoDriveControllerBuilder::port_name()`].
+    ///
+    /// The baud rate, flow control and adapter settings are ignored for the transport.
+    /// As the transport can only be connected once, only one controller can be built
+    /// from this builder and its clones. For the same reason a reading thread that panicked
+    /// can not be restarted, so it stops with [`ReaderStop::SerialPortError`].
+    pub fn transport(mut self, transport: LocoNetTransport) -> Self {
+        self.transport = Some(SharedTransport::new(transport));
+        self
+    }
+
+    /// Opens the configured serial port and starts reading on that port.
+    ///
+    /// # Error
+    ///
+    /// This method exit with an error if the serial port is not reachable or the port could
+    /// not be configured correctly, or the in memory transport is connected already.
+    pub async fn build(self) -> Result<LocoDriveController, Error> {
+        // The adapter knows best what the interface needs
+        let baud_rate = self.adapter.fixed_baud_rate().unwrap_or(self.baud_rate);
+        let flow_control = self
+            .adapter
+            .fixed_flow_control()
+            .unwrap_or(self.flow_control);
+        let echoless = self.transport.is_none() && !self.adapter.has_echo();
+        let echo_policy = match echoless {
+            true => EchoPolicy::None,
+            false => self.echo_policy,
+        };
+        let report_sent_messages = echoless || self.report_sent_messages;
+
+        let (port_name, source, port) = match &self.transport {
+            Some(transport) => {
+                let (name, source, port) = transport.connect()?;
+                (Some(name), source, port)
+            }
+            None => {
+                // Creation of the port to write to
+                let mut port = match tokio_serial::new(&self.port_name, baud_rate)
+                    .data_bits(DataBits::Eight)
+                    .stop_bits(StopBits::Two)
+                    .parity(Parity::None)
+                    .flow_control(flow_control)
+                    .timeout(Duration::from_millis(self.sending_timeout))
+                    .open_native_async()
+                {
+                    Ok(port) => port,
+                    Err(e) => return Err(e),
+                };
+
+                // For unix systems we must ensure the port to be available
+                // for parallel opening by the reading thread.
+                #[cfg(unix)]
+                port.set_exclusive(false)?;
+
+                // Some interfaces need initialization before passing messages
+                self.adapter.apply(&mut port).await?;
+
+                let source = ReadSource::Serial {
+                    name: self.port_name.clone(),
+                    baud_rate,
+                    flow_control,
+                };
+                (port.name(), source, WritePort::Serial(port))
+            }
         };
 
-        // For unix systems we must ensure the port to be available
-        // for parallel opening by the reading thread.
-        #[cfg(unix)]
-        port.set_exclusive(false)?;
+        // We only know the capacity of channels we create ourselves
+        let (send_to, stats) = match self.send_to {
+            Some(send_to) => (send_to, StatsCollector::new(None)),
+            None => (
+                tokio::sync::broadcast::channel(self.channel_capacity).0,
+                StatsCollector::new(Some(self.channel_capacity)),
+            ),
+        };
+        let send_to = Fanout::new(send_to, self.channel_capacity, self.overflow_policy);
+        let stats = Arc::new(
+            stats
+                .with_line_noise(self.checksum_window, self.line_noise_threshold)
+                .with_traffic(self.traffic_window, self.chatty_threshold),
+        );
 
         // Takes care of the writer reader synchronisation
-        let send = Arc::new((Arc::new(Mutex::new(vec![0u8; 0])), Arc::new(Notify::new())));
+        let (echoes, echo_matcher) = echo_channel();
+
+        // Cancelled by the task owning the port when it stops, which stops the other tasks
+        let stopping = CancellationToken::new();
+
+        // Used to pace the writer
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
 
-        // Used to stop a reader when the the value was dropped
-        let stop = Arc::new(Mutex::new(false));
-        let fire_stop = Arc::new(Notify::new());
+        // The last track status and power state reported by the model railroad
+        let track = Arc::new(TrackState::new());
+
+        // Whether the master reports to be busy
+        let (busy, busy_watch) = watch::channel(false);
+
+        // Whether the reading thread is running
+        let (reader_status, reader) = watch::channel(ReaderStatus::Running { restarts: 0 });
+
+        // Mirrors the raw frames
+        let raw_tap = RawTap(self.raw_tap);
+
+        // The slots used by the controller, to report when they are purged
+        let slots = Arc::new(SlotUsage::new());
 
         // Starts the reading thread
-        let reading_thread = Some(
-            LocoDriveController::start_reading_thread(
-                port_name.to_string(),
-                baud_rate,
-                flow_control,
-                &send,
-                &send_to,
-                &stop,
-                &fire_stop,
-                ignore_send_messages,
-            )
-            .await,
-        );
+        LocoDriveController::start_reading_thread(
+            source,
+            echo_matcher,
+            &send_to,
+            &stopping,
+            &last_activity,
+            &track,
+            busy,
+            AnswerCorrelator::new(self.default_answer_timeout, self.answer_timeouts),
+            &stats,
+            self.idle_after,
+            DuplicateFilter::new(self.dedup_window),
+            self.extra_slot_bytes,
+            self.parse_options,
+            if self.ignore_send_messages {
+                OwnMessages::Ignore
+            } else if self.tag_send_messages {
+                OwnMessages::Tag
+            } else {
+                OwnMessages::Broadcast
+            },
+            reader_status,
+            raw_tap.clone(),
+            &slots,
+        )
+        .await;
+
+        // Starts the periodic health reporting
+        if let Some(health_interval) = self.health_interval {
+            LocoDriveController::start_health_task(health_interval, &send_to, &stats, &stopping);
+        }
+
+        // Takes care of writing, shared with all command handles
+        let (commands, port_commands) = unbounded_channel();
+        let writer = Arc::new(Writer {
+            commands,
+            echoes,
+            sending_timeout: AtomicU64::new(self.sending_timeout),
+            echo_policy,
+            report_sent_messages,
+            raw_tap,
+            slots: slots.clone(),
+            send_to: send_to.clone(),
+            stats: stats.clone(),
+            last_activity,
+            tx_gap: self.tx_gap,
+            priority_backoff: self.priority_backoff,
+            busy: busy_watch,
+            busy_hold: self.busy_hold,
+            strict_slots: self.strict_slots,
+            audit: self.audit,
+        });
+
+        // Starts the task owning the port to write to
+        tokio::spawn(Writer::run(
+            writer.clone(),
+            port,
+            port_commands,
+            stopping.clone(),
+        ));
+
+        // Starts refreshing the slots registered to be kept alive
+        let keep_alive = self.keep_alive.map(|interval| {
+            let keep_alive = Arc::new(KeepAlive::new(interval));
+            let handle = CommandHandle {
+                writer: writer.clone(),
+            };
+            let messages = send_to.subscribe();
+            let service = keep_alive.clone();
+            let stopping = stopping.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = keep_alive::run(&service, handle, messages) => {}
+                    _ = stopping.cancelled() => {}
+                }
+            });
+            keep_alive
+        });
+
+        // All steps has passed successfully
+        Ok(LocoDriveController {
+            port_name,
+            _stop_on_drop: Arc::new(StopOnDrop(writer.commands.clone())),
+            writer,
+            reader: ReaderHandle { status: reader },
+            keep_alive,
+            send_to,
+            stats,
+            track,
+            slots,
+        })
+    }
+}
+
+/// The status of the reading thread of a [`LocoDriveController`].
+#[derive(Debug, Clone)]
+pub enum ReaderStatus {
+    /// The reading thread is running.
+    Running {
+        /// How often the reading thread was restarted after a panic
+        restarts: u32,
+    },
+    /// The reading thread stopped for the given reason and will not be restarted.
+    Stopped(ReaderStop),
+}
+
+impl ReaderStatus {
+    /// # Returns
+    ///
+    /// If the reading thread is running.
+    pub fn is_running(&self) -> bool {
+        matches!(self, ReaderStatus::Running { .. })
+    }
+}
+
+/// Why the reading thread of a [`LocoDriveController`] stopped.
+#[derive(Debug, Clone)]
+pub enum ReaderStop {
+    /// The controller was dropped.
+    Requested,
+    /// The serial port could not be opened for reading or reading from it failed.
+    SerialPortError(Error),
+    /// The port was closed, like the other end of an in memory transport was dropped.
+    Closed,
+    /// The reading thread was cancelled, as the runtime shuts down.
+    Cancelled,
+}
+
+impl ReaderStop {
+    /// # Returns
+    ///
+    /// Why the reading thread stops after reading from its port failed with `err`.
+    fn read_failed(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => ReaderStop::Closed,
+            _ => ReaderStop::SerialPortError(err.into()),
+        }
+    }
+}
+
+/// A cheap cloneable handle to supervise the reading thread of a [`LocoDriveController`].
+///
+/// Supervisors can await the reading thread to stop and react to it, like reconnecting,
+/// instead of discovering it by missing messages.
+#[derive(Debug, Clone)]
+pub struct ReaderHandle {
+    /// The reported status of the reading thread
+    status: watch::Receiver<ReaderStatus>,
+}
+
+impl ReaderHandle {
+    /// # Returns
+    ///
+    /// The current status of the reading thread.
+    pub fn status(&self) -> ReaderStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Waits until the reading thread stopped.
+    ///
+    /// # Returns
+    ///
+    /// Why the reading thread stopped.
+    pub async fn stopped(&mut self) -> ReaderStop {
+        let stopped = match self.status.wait_for(|status| !status.is_running()).await {
+            Ok(status) => status.clone(),
+            // The supervisor is gone without reporting, so it was cancelled
+            Err(_) => return ReaderStop::Cancelled,
+        };
+        match stopped {
+            ReaderStatus::Stopped(reason) => reason,
+            ReaderStatus::Running { .. } => ReaderStop::Cancelled,
+        }
+    }
+}
+
+/// How often the broadcast channel is checked for capacity, see [`OverflowPolicy::Block`].
+const OVERFLOW_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Passes the messages read by the reading thread to the broadcast channel
+/// and all streams created by [`LocoDriveController::messages()`].
+/// Each message is numbered and passed in an [`Envelope`] to the envelope channel.
+#[derive(Debug, Clone)]
+struct Fanout {
+    /// The broadcast channel to send to
+    sender: Sender<LocoDriveMessage>,
+    /// The senders of all open streams
+    streams: Arc<Mutex<Vec<UnboundedSender<LocoDriveMessage>>>>,
+    /// The broadcast channel to send the envelopes to
+    envelopes: Sender<Envelope<LocoDriveMessage>>,
+    /// The sequence number of the next message
+    sequence: Arc<AtomicU64>,
+    /// The senders of all open critical event streams
+    critical: Arc<Mutex<Vec<UnboundedSender<LocoDriveMessage>>>>,
+    /// The capacity of the broadcast channel to keep, if the policy blocks
+    capacity: usize,
+    /// How to handle lagging subscribers
+    overflow: OverflowPolicy,
+}
+
+impl Fanout {
+    /// Creates a new fanout to the broadcast channel of `sender` and no streams.
+    /// The envelopes are held in a channel with `capacity`.
+    /// Lagging subscribers are handled as given by `overflow`.
+    fn new(sender: Sender<LocoDriveMessage>, capacity: usize, overflow: OverflowPolicy) -> Self {
+        Fanout {
+            sender,
+            streams: Arc::new(Mutex::new(Vec::new())),
+            envelopes: tokio::sync::broadcast::channel(capacity).0,
+            sequence: Arc::new(AtomicU64::new(0)),
+            critical: Arc::new(Mutex::new(Vec::new())),
+            capacity,
+            overflow,
+        }
+    }
+
+    /// Subscribes to the broadcast channel.
+    fn subscribe(&self) -> Receiver<LocoDriveMessage> {
+        self.sender.subscribe()
+    }
+
+    /// Subscribes to the envelope channel.
+    fn subscribe_envelopes(&self) -> Receiver<Envelope<LocoDriveMessage>> {
+        self.envelopes.subscribe()
+    }
+
+    /// Creates a new stream receiving all following messages.
+    fn stream(&self) -> UnboundedReceiverStream<LocoDriveMessage> {
+        let (sender, receiver) = unbounded_channel();
+        self.streams.lock().unwrap().push(sender);
+        UnboundedReceiverStream::new(receiver)
+    }
+
+    /// Creates a new stream receiving all following critical events,
+    /// if the policy is [`OverflowPolicy::SpillCritical`].
+    fn critical_stream(&self) -> Option<UnboundedReceiverStream<LocoDriveMessage>> {
+        if self.overflow != OverflowPolicy::SpillCritical {
+            return None;
+        }
+        let (sender, receiver) = unbounded_channel();
+        self.critical.lock().unwrap().push(sender);
+        Some(UnboundedReceiverStream::new(receiver))
+    }
+
+    /// Waits while the broadcast channel is full, if the policy is [`OverflowPolicy::Block`].
+    ///
+    /// # Returns
+    ///
+    /// If waiting timed out, so lagging subscribers lose the oldest messages.
+    async fn await_capacity(&self) -> bool {
+        let max_wait = match self.overflow {
+            OverflowPolicy::Block(max_wait) => max_wait,
+            OverflowPolicy::DropOldest | OverflowPolicy::SpillCritical => return false,
+        };
+
+        let deadline = Instant::now() + max_wait;
+        // The broadcast channel does not notify when messages are received, so we poll it
+        while self.sender.len() >= self.capacity {
+            if Instant::now() >= deadline {
+                return true;
+            }
+            sleep_until(deadline.min(Instant::now() + OVERFLOW_POLL_INTERVAL)).await;
+        }
+        false
+    }
+
+    /// Sends the message to all streams and the broadcast channel. Closed streams are removed.
+    ///
+    /// # Errors
+    ///
+    /// If neither a stream nor a broadcast receiver is listening, the message is returned.
+    #[allow(clippy::result_large_err)]
+    fn send(&self, message: LocoDriveMessage) -> Result<(), SendError<LocoDriveMessage>> {
+        let mut streams = self.streams.lock().unwrap();
+
+        // Numbered while the streams are locked, so the numbers follow the sending order
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        if self.envelopes.receiver_count() > 0 {
+            let _ = self.envelopes.send(Envelope {
+                sequence,
+                received: Instant::now(),
+                event: message.clone(),
+            });
+        }
+        streams.retain(|stream| stream.send(message.clone()).is_ok());
+        if message.is_critical() {
+            self.critical
+                .lock()
+                .unwrap()
+                .retain(|stream| stream.send(message.clone()).is_ok());
+        }
+
+        match self.sender.send(message) {
+            Err(_) if !streams.is_empty() => Ok(()),
+            Err(err) => Err(err),
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+/// Tracks whether the bus is reported as idle by the reading thread.
+struct IdleWatch {
+    /// After which time without traffic the bus is reported as idle
+    after: Option<Duration>,
+    /// Whether the bus is currently reported as idle
+    idle: bool,
+}
+
+/// The direction a raw frame mirrored by [`LocoDriveControllerBuilder::raw_tap()`] was transferred in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// The frame was read from the port.
+    Rx,
+    /// The frame was written to the port.
+    Tx,
+}
+
+/// The power state of the layout.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PowerState {
+    /// No power state was reported yet.
+    Unknown,
+    /// The track power is on.
+    On,
+    /// The track power is off.
+    Off,
+    /// The track power is on, but all locomotives were stopped by [`Message::Idle`].
+    Idle,
+}
+
+/// The track status and power state reported by the model railroad.
+#[derive(Debug)]
+struct TrackState {
+    /// The last track status reported by the model railroad
+    status: Mutex<Option<TrkArg>>,
+    /// The power state of the layout
+    power: watch::Sender<PowerState>,
+}
+
+impl TrackState {
+    /// Creates a state without any reported status.
+    fn new() -> Self {
+        TrackState {
+            status: Mutex::new(None),
+            power: watch::channel(PowerState::Unknown).0,
+        }
+    }
+}
+
+/// Mirrors raw frames to the tap configured by [`LocoDriveControllerBuilder::raw_tap()`], if any.
+#[derive(Debug, Clone)]
+struct RawTap(Option<Sender<(Direction, Vec<u8>, Instant)>>);
+
+impl RawTap {
+    /// Mirrors the `bytes` transferred in `direction` at `at`.
+    fn mirror(&self, direction: Direction, bytes: &[u8], at: Instant) {
+        if let Some(tap) = &self.0 {
+            // Nobody may be listening, which is fine
+            let _ = tap.send((direction, bytes.to_vec(), at));
+        }
+    }
+}
+
+/// How the reader broadcasts the echoes of messages send by the controller itself.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum OwnMessages {
+    /// As normal [`LocoDriveMessage::Message`]
+    Broadcast,
+    /// Not at all
+    Ignore,
+    /// As [`LocoDriveMessage::Echo`]
+    Tag,
+}
+
+/// Why the reader did not read a message.
+#[derive(Debug, Clone)]
+enum ReadError {
+    /// The read bytes were no valid message, or the reader was woken up
+    Parse(MessageParseError),
+    /// The port can not be read anymore, so the reading thread stops
+    Stopped(ReaderStop),
+}
+
+impl From<MessageParseError> for ReadError {
+    fn from(err: MessageParseError) -> Self {
+        ReadError::Parse(err)
+    }
+}
+
+/// A message read by the reader.
+#[derive(Debug, Clone)]
+struct Received {
+    /// The parsed message
+    message: Message,
+    /// Whether it is tagged as echo of a message send by the controller
+    echoed: bool,
+    /// The vendor extension bytes received with the message
+    vendor_bytes: Vec<u8>,
+}
+
+/// This struct handles a connection to a serial port based railroad controlling system.
+///
+/// All received messages on the port are send to the defined channel.
+/// - Note: The auto returned messages as defined in the model railroads protocol are also send back to the channel.
+///   But the protocol ensures itself that the writer waits until the model railroad response is received.
+///
+/// # Usage
+///
+/// To send a message see [`LocoDriveController::send_message()`].
+/// The reading thread is start automatically on port creation.
+/// You can just check on your reader channel for new messages.
+///
+/// The controller is a cheap handle: Its clones share the connection and can send
+/// from other tasks concurrently. The reader is automatically stopped when the last clone
+/// of the [`LocoDriveController`] is dropped.
+///
+/// # Examples
+///
+/// Reading ten messages received from the model railroads:
+/// ```
+/// # use tokio_serial::FlowControl;
+/// # use locodrive::loco_controller::LocoDriveController;
+/// # use locodrive::protocol::Message;
+/// # use locodrive::args::{SlotArg, SpeedArg};
+/// # use locodrive::protocol::Message::LocoSpd;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     use locodrive::args::{SwitchArg, SwitchDirection};
+///     use locodrive::protocol::Message::SwReq;
+///     // Creating a sender and receiver for the LocoDriveConnector.
+///     let (sender, mut receiver) = tokio::sync::broadcast::channel(1);
+///
+///     // Creating a LocoDriveConnector, reading from the port '/dev/ttyUSB0'.
+///     let loco_controller = match LocoDriveController::new(
+///         "/dev/ttyUSB0",
+///         115_200,
+///         5000,
+///         FlowControl::Software,
+///         sender,
+///         false,
+///     ).await {
+///         Ok(loco_controller) => loco_controller,
+///         Err(err) => {
+///             eprintln!("Error: Could not connect to the serial port!");
+///             return;
+///         }
+///     };
+///
+///     loco_controller.send_message(SwReq(SwitchArg::new(39, SwitchDirection::Straight, true)));
+///
+///     let mut read_messages = 0;
+///     while let Ok(message) = receiver.recv().await {
+///         println!("GOT = {:?}", message);
+///         read_messages += 1;
+///         if read_messages >= 10 {
+///            break;
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct LocoDriveController {
+    /// The name of the serial port used to connect to the model railroads.
+    port_name: Option<String>,
+    /// Writes the messages to the serial port, shared with all [`CommandHandle`]s.
+    writer: Arc<Writer>,
+    /// Stops the connection when the last clone of this controller is dropped.
+    _stop_on_drop: Arc<StopOnDrop>,
+    /// The status of the reading thread.
+    reader: ReaderHandle,
+    /// The service keeping slots alive, if configured.
+    keep_alive: Option<Arc<KeepAlive>>,
+    /// The channel and streams all received messages are send to.
+    send_to: Fanout,
+    /// The statistics collected for this connection.
+    stats: Arc<StatsCollector>,
+    /// The last track status and power state reported by the model railroad.
+    track: Arc<TrackState>,
+    /// The slots used by the controller.
+    slots: Arc<SlotUsage>,
+}
+
+impl LocoDriveController {
+    /// Creates a new serial port connection to a model railroad and starts reading on that port
+    ///
+    /// # Parameter
+    ///
+    /// - `port_name`: Is the name of the port to connect to.
+    ///   If you are not sure, which ports are allowed use [`tokio_serial::available_ports()`](https://docs.rs/tokio-serial/latest/tokio_serial/fn.available_ports.html).
+    /// - `baud_rate`: The baud rate to use for the port connection.
+    /// - `sending_timeout`: How long to wait for response for the model railroads connection
+    ///   while sending messages.
+    /// - `update_cycles`: How long to wait for incoming messages on reader side,
+    ///   before checking if this reader should close.
+    /// - `flow_control`: Which mode of flow control to use for this port.
+    ///   It is recommended to use [`FlowControl::Software`](https://docs.rs/tokio-serial/latest/tokio_serial/enum.FlowControl.html).
+    ///
+    /// # Error
+    ///
+    /// This method exit with an error if the serial port is not reachable or the port could
+    /// not be configured correctly.
+    ///
+    /// # Reading
+    ///
+    /// - If this thread communicates a [`LocoDriveMessage::SerialPortError`] it will
+    ///   not be receiving any more messages. All other Messages would not lead
+    ///   the reading thread to stop.
+    /// - Lack messages are send twice. Ones as [`LocoDriveMessage::Answer`] and
+    ///   then a second time as [`LocoDriveMessage::Message`].
+    pub async fn new(
+        port_name: &str,
+        baud_rate: u32,
+        sending_timeout: u64,
+        flow_control: FlowControl,
+        send_to: Sender<LocoDriveMessage>,
+        ignore_send_messages: bool,
+    ) -> Result<Self, Error> {
+        Self::builder(port_name, baud_rate)
+            .sending_timeout(sending_timeout)
+            .flow_control(flow_control)
+            .sender(send_to)
+            .ignore_send_messages(ignore_send_messages)
+            .build()
+            .await
+    }
+
+    /// Creates a [`LocoDriveControllerBuilder`] to configure a connection to the serial port
+    /// `port_name` using the given `baud_rate`.
+    ///
+    /// Other than [`LocoDriveController::new()`] the builder creates the broadcast channel
+    /// itself, so you can subscribe to it using [`LocoDriveController::subscribe()`].
+    pub fn builder(port_name: &str, baud_rate: u32) -> LocoDriveControllerBuilder {
+        LocoDriveControllerBuilder {
+            port_name: port_name.to_string(),
+            baud_rate,
+            sending_timeout: 5000,
+            flow_control: FlowControl::Software,
+            send_to: None,
+            channel_capacity: 64,
+            ignore_send_messages: false,
+            tag_send_messages: false,
+            report_sent_messages: false,
+            raw_tap: None,
+            tx_gap: Duration::ZERO,
+            priority_backoff: Duration::ZERO,
+            idle_after: None,
+            extra_slot_bytes: ExtraBytes::Reject,
+            parse_options: ParseOptions::default(),
+            dedup_window: None,
+            checksum_window: Duration::from_secs(60),
+            line_noise_threshold: None,
+            traffic_window: Duration::from_secs(10),
+            chatty_threshold: None,
+            health_interval: None,
+            keep_alive: None,
+            echo_policy: EchoPolicy::Require,
+            adapter: AdapterProfile::generic(),
+            busy_hold: Duration::from_millis(500),
+            strict_slots: true,
+            answer_timeouts: HashMap::new(),
+            default_answer_timeout: Duration::from_secs(1),
+            transport: None,
+            overflow_policy: OverflowPolicy::DropOldest,
+            audit: None,
+        }
+    }
+
+    /// Looks for serial ports a model railroad is connected to.
+    ///
+    /// All serial ports are enumerated and probed with the `baud_candidates` in order.
+    /// Probing sends the harmless query [`Message::RqSlData`] of slot 0 and listens
+    /// half a second for valid frames. Ports in use by other applications may not be probed.
+    ///
+    /// # Returns
+    ///
+    /// All found ports. Those a model railroad answered on are [`PortCandidate::answered()`]
+    /// and hold the baud rate to use.
+    pub async fn discover(baud_candidates: &[u32]) -> Vec<PortCandidate> {
+        discovery::discover(baud_candidates).await
+    }
+
+    /// Subscribes to the messages received by this controller.
+    ///
+    /// Only messages received after subscribing are passed to the receiver.
+    pub fn subscribe(&self) -> LocoDriveReceiver {
+        LocoDriveReceiver {
+            receiver: self.send_to.subscribe(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Subscribes to the messages received by this controller, each wrapped in an [`Envelope`]
+    /// with its sequence number and the time it was received.
+    ///
+    /// The sequence numbers are shared by all envelope receivers of this controller,
+    /// so events from several subscriptions can be ordered deterministically.
+    /// Only messages received after subscribing are passed to the receiver.
+    pub fn subscribe_envelopes(&self) -> EnvelopeReceiver<LocoDriveMessage> {
+        EnvelopeReceiver::new(
+            self.send_to.subscribe_envelopes(),
+            self.stats.clone(),
+            |message| Some(message.clone()),
+        )
+    }
+
+    /// Subscribes to the sensor events, reported by [`Message::InputRep`].
+    ///
+    /// Only events received after subscribing are passed to the receiver.
+    pub fn subscribe_sensor_events(&self) -> FilteredReceiver<InArg> {
+        FilteredReceiver::new(self.subscribe(), subscription::sensor_event)
+    }
+
+    /// Subscribes to the updates of slots, like read slot data or changed speeds.
+    ///
+    /// Only updates received after subscribing are passed to the receiver.
+    pub fn subscribe_slot_updates(&self) -> FilteredReceiver<SlotUpdate> {
+        FilteredReceiver::new(self.subscribe(), SlotUpdate::from_message)
+    }
+
+    /// Subscribes to the switch reports, reported by [`Message::SwRep`].
+    ///
+    /// Only reports received after subscribing are passed to the receiver.
+    pub fn subscribe_switch_reports(&self) -> FilteredReceiver<SnArg> {
+        FilteredReceiver::new(self.subscribe(), subscription::switch_report)
+    }
+
+    /// Creates a stream of the messages received by this controller.
+    ///
+    /// Other than [`LocoDriveController::subscribe()`] the stream buffers all messages
+    /// until they are polled, so no message is lost by lagging behind.
+    /// Only messages received after creating the stream are passed to it.
+    /// The stream ends when the controller is dropped.
+    pub fn messages(&self) -> impl Stream<Item = LocoDriveMessage> {
+        self.send_to.stream()
+    }
+
+    /// Creates a stream of the critical events received by this controller,
+    /// see [`LocoDriveMessage::is_critical()`].
+    ///
+    /// The stream buffers all events until they are polled, so no critical event is lost,
+    /// even if the broadcast channel overflows.
+    /// Only events received after creating the stream are passed to it.
+    ///
+    /// # Returns
+    ///
+    /// The stream, or `None` if the controller was not configured with
+    /// [`OverflowPolicy::SpillCritical`].
+    pub fn critical_events(&self) -> Option<impl Stream<Item = LocoDriveMessage>> {
+        self.send_to.critical_stream()
+    }
+
+    /// # Return
+    ///
+    /// The last track status reported by the model railroad or `None` if no status was reported yet.
+    pub fn track_status(&self) -> Option<TrkArg> {
+        *self.track.status.lock().unwrap()
+    }
+
+    /// # Return
+    ///
+    /// The power state of the layout, as last reported by the model railroad.
+    pub fn power_state(&self) -> PowerState {
+        *self.track.power.borrow()
+    }
+
+    /// Waits until the layout is powered on, so commands to the locomotives take effect.
+    /// Returns immediately if the power is on already.
+    ///
+    /// The power state is only known after it was reported, so request any slot data first
+    /// or wait for the track power to be switched.
+    pub async fn await_power_on(&self) {
+        let mut power = self.track.power.subscribe();
+        // The sender lives as long as this controller, so waiting can not fail
+        let _ = power.wait_for(|power| *power == PowerState::On).await;
+    }
+
+    /// Subscribes to the power changes of the layout,
+    /// reported by [`Message::GpOn`], [`Message::GpOff`] and [`Message::Idle`].
+    ///
+    /// Only events received after subscribing are passed to the receiver.
+    pub fn subscribe_power_events(&self) -> FilteredReceiver<PowerEvent> {
+        FilteredReceiver::new(self.subscribe(), PowerEvent::from_message)
+    }
+
+    /// # Return
+    ///
+    /// Whether the master currently reports to be busy by [`Message::Busy`].
+    pub fn master_busy(&self) -> bool {
+        *self.writer.busy.borrow()
+    }
+
+    /// # Return
+    ///
+    /// A snapshot of the statistics collected for this connection.
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// # Return
+    ///
+    /// The frames read within the traffic window per op code and device, with the chatty devices,
+    /// see [`LocoDriveControllerBuilder::traffic_window()`].
+    pub fn traffic(&self) -> TrafficReport {
+        self.stats.traffic()
+    }
+
+    /// # Return
+    ///
+    /// The collector of the statistics of this connection.
+    pub(crate) fn stats_collector(&self) -> &StatsCollector {
+        &self.stats
+    }
+
+    /// # Return
+    ///
+    /// The port the `LocoDriveConnector` is connected to.
+    pub fn get_port_name(&self) -> Option<String> {
+        self.port_name.clone()
+    }
+
+    /// # Return
+    ///
+    /// The connected ports baud rate.
+    ///
+    /// # Error
+    ///
+    /// If the baud rate could not be read from the port or the controller is connected
+    /// to an in memory transport.
+    ///
+    /// # Note
+    ///
+    /// The port is asked after the messages queued for writing before were written.
+    pub async fn get_baud_rate(&self) -> tokio_serial::Result<u32> {
+        self.writer.command(PortCommand::BaudRate).await?
+    }
+
+    /// # Return
+    ///
+    /// The maximum time to wait for a message to be send correctly.
+    pub fn get_sending_timeout(&self) -> u64 {
+        self.writer.sending_timeout()
+    }
+
+    /// Overrides the sending timeout with the give value.
+    ///
+    /// # Parameter
+    ///
+    /// - `sending_timeout`: The time to wait for a reading action to complete.
+    ///
+    /// # Returns
+    ///
+    /// If some error occurred on overriding the timeout on the port.
+    ///
+    /// # Note
+    ///
+    /// The port is reconfigured after the messages queued for writing before were written.
+    pub async fn set_sending_timeout(&self, sending_timeout: u64) -> Result<(), Error> {
+        self.writer
+            .sending_timeout
+            .store(sending_timeout, Ordering::Relaxed);
+        self.writer
+            .command(|reply| PortCommand::Reconfigure {
+                sending_timeout,
+                reply,
+            })
+            .await?
+    }
+
+    /// Helper method that spawns a new async tokio thread broadcasting
+    /// the statistics every `health_interval` until `stopping` is cancelled.
+    fn start_health_task(
+        health_interval: Duration,
+        send_to: &Fanout,
+        stats: &Arc<StatsCollector>,
+        stopping: &CancellationToken,
+    ) {
+        let send_to = send_to.clone();
+        let stats = stats.clone();
+        let stopping = stopping.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(health_interval);
+            // The first tick completes immediately
+            ticks.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = stopping.cancelled() => break,
+                }
+                // Nobody listening to the health is no error
+                let _ = send_to.send(LocoDriveMessage::Health(stats.snapshot()));
+            }
+        });
+    }
+
+    /// Helper method that spawns a new async tokio thread for reading model railroads
+    /// messages from the specified serial port.
+    ///
+    /// # Parameter
+    ///
+    /// - `source`: Where to open the port to read from
+    /// - `echoes`: Matches the read messages to the echo awaited by the writer
+    /// - `send_to`: Where to send the received and parsed model railroad messages
+    /// - `stopping`: Cancelled to stop the reading thread, also while it waits for new incoming messages
+    /// - `last_activity`: Where to note when the last message was read
+    /// - `track`: Where to note the last reported track status
+    /// - `busy`: Where to note whether the master reports to be busy
+    /// - `answers`: Matches the received answers to their requests
+    /// - `stats`: Where to count the read frames and errors
+    /// - `idle_after`: After which time without traffic the bus is reported as idle
+    /// - `duplicates`: Drops duplicated frames
+    /// - `extra_slot_bytes`: How to handle slot data frames longer than the standard length
+    /// - `parse_options`: How strictly to parse the read frames
+    /// - `own_messages`: How to broadcast the echoes of messages send by the controller
+    /// - `status`: Where to report the status of the reading thread
+    /// - `raw_tap`: Where to mirror the read bytes
+    /// - `slots`: Where to note the purged slots
+    ///
+    /// The reading thread is supervised: If it panics, the panic is broadcast as
+    /// [`MessageParseError::ReaderPanicked`] and a new reading thread is started.
+    /// The new one reopens the serial port, which an in memory transport does not allow.
+    /// If the port was closed or could not be read anymore, the reading thread stops.
+    #[allow(clippy::too_many_arguments)]
+    async fn start_reading_thread(
+        source: ReadSource,
+        echoes: EchoMatcher,
+        send_to: &Fanout,
+        stopping: &CancellationToken,
+        last_activity: &Arc<Mutex<Instant>>,
+        track: &Arc<TrackState>,
+        busy: watch::Sender<bool>,
+        answers: AnswerCorrelator,
+        stats: &Arc<StatsCollector>,
+        idle_after: Option<Duration>,
+        duplicates: DuplicateFilter,
+        extra_slot_bytes: ExtraBytes,
+        parse_options: ParseOptions,
+        own_messages: OwnMessages,
+        status: watch::Sender<ReaderStatus>,
+        raw_tap: RawTap,
+        slots: &Arc<SlotUsage>,
+    ) {
+        // Clone all arcs to make them save to use in the reading threads
+        let send_to = send_to.clone();
+        // The echoes awaited by the writer are kept over restarts of the reading thread
+        let echoes = Arc::new(tokio::sync::Mutex::new(echoes));
+        let stopping = stopping.clone();
+        let last_activity = last_activity.clone();
+        let track = track.clone();
+        let stats = stats.clone();
+        let busy = Arc::new(busy);
+        let slots = slots.clone();
+        let source = Arc::new(source);
+
+        // Creates a reading thread, once at start and again after each panic
+        let start_reader = {
+            let send_to = send_to.clone();
+            let stopping = stopping.clone();
+            move || {
+                let arc_send_to = send_to.clone();
+
+                let echoes = echoes.clone();
+
+                let new_arc_stopping = stopping.clone();
+                let new_arc_last_activity = last_activity.clone();
+                let new_arc_track = track.clone();
+                let new_arc_stats = stats.clone();
+                let busy = busy.clone();
+                let source = source.clone();
+                let raw_tap = raw_tap.clone();
+                let slots = slots.clone();
+
+                // Each reading thread starts with fresh answer and duplicate tracking
+                let mut answers = answers.clone();
+                let mut duplicates = duplicates.clone();
+
+                #[cfg(feature = "tracing")]
+                let span = tracing::info_span!("locodrive_reader", port = %source.name());
+
+                let reader = async move {
+                    // Connects the port to read from
+                    let port = match source.open() {
+                        Ok(port) => port,
+                        Err(err) => {
+                            let reason = ReaderStop::SerialPortError(err.clone());
+                            if let Err(err) =
+                                arc_send_to.send(LocoDriveMessage::SerialPortError(err))
+                            {
+                                log_error!(
+                                    "Unable to send critical error to receiver! \
+                                Closed connection to the serial port!\n \
+                                Following error occurred: {:?}",
+                                    err
+                                );
+                            }
+                            return reason;
+                        }
+                    };
+
+                    // Reads the frames through an adaptive buffer
+                    let mut port = FrameReader::new(port);
+
+                    // Groups the read messages to transactions
+                    let mut transactions = TransactionTracker::new();
+                    // Whether the bus is reported as idle
+                    let mut idle = IdleWatch {
+                        after: idle_after,
+                        idle: false,
+                    };
+
+                    // A restarted reading thread waits until the panicked one released the echoes
+                    let mut echoes = echoes.lock().await;
+
+                    log_info!("Reading thread started!");
+
+                    // This thread reads till it is notified to stop
+                    while !new_arc_stopping.is_cancelled() {
+                        // We read and directly handle received messages
+                        let stopped = LocoDriveController::handle_next_message(
+                            &mut port,
+                            &mut echoes,
+                            &mut answers,
+                            &mut transactions,
+                            &mut idle,
+                            &arc_send_to,
+                            &new_arc_stopping,
+                            &new_arc_last_activity,
+                            &new_arc_track,
+                            &busy,
+                            &new_arc_stats,
+                            &mut duplicates,
+                            extra_slot_bytes,
+                            parse_options,
+                            own_messages,
+                            &raw_tap,
+                            &slots,
+                        )
+                        .await;
+
+                        // The port can not be read anymore, so we stop instead of retrying
+                        if let Some(stopped) = stopped {
+                            log_error!("Reading thread stopped: {:?}", stopped);
+                            if let ReaderStop::SerialPortError(err) = &stopped {
+                                let err = LocoDriveMessage::SerialPortError(err.clone());
+                                if let Err(err) = arc_send_to.send(err) {
+                                    log_error!("{:?}", err);
+                                }
+                            }
+                            return stopped;
+                        }
+                    }
+
+                    log_info!("Reading thread closed!");
+                    ReaderStop::Requested
+                };
+
+                #[cfg(feature = "tracing")]
+                let reader = tracing::Instrument::instrument(reader, span);
+
+                tokio::spawn(reader)
+            }
+        };
+
+        tokio::spawn(async move {
+            let stopped = loop {
+                let reason = match start_reader().await {
+                    Ok(stopped) => break stopped,
+                    Err(err) if err.is_panic() => {
+                        let panic = err.into_panic();
+                        panic
+                            .downcast_ref::<&str>()
+                            .map(|reason| reason.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_default()
+                    }
+                    // The runtime shuts down
+                    Err(_) => break ReaderStop::Cancelled,
+                };
+
+                log_error!("Reading thread panicked: {}", reason);
+                if let Err(err) = send_to.send(LocoDriveMessage::Error(
+                    MessageParseError::ReaderPanicked(reason),
+                )) {
+                    log_error!("{:?}", err);
+                }
+
+                if stopping.is_cancelled() {
+                    break ReaderStop::Requested;
+                }
+                log_info!("Restarting reading thread!");
+                status.send_modify(|status| {
+                    if let ReaderStatus::Running { restarts } = status {
+                        *restarts += 1;
+                    }
+                });
+            };
+
+            status.send_replace(ReaderStatus::Stopped(stopped));
+        });
+    }
+
+    /// Handles a model railroad message after it was parsed successfully.
+    ///
+    /// # Parameter
+    ///
+    /// - `port`: The port to read messages from
+    /// - `echoes`: Matches the read messages to the echo awaited by the writer
+    /// - `answers`: Matches the received answers to their requests
+    /// - `transactions`: Groups the received messages to transactions
+    /// - `idle`: Whether the bus is reported as idle
+    /// - `send_to`: Where to send the received and parsed model railroad messages
+    /// - `stopping`: Cancelled to awake the reading thread from waiting for new incoming messages
+    /// - `last_activity`: Where to note when the last message was read
+    /// - `track`: Where to note the last reported track status
+    /// - `busy`: Where to note whether the master reports to be busy
+    /// - `stats`: Where to count the read frames and errors
+    /// - `duplicates`: Drops duplicated frames
+    /// - `extra_slot_bytes`: How to handle slot data frames longer than the standard length
+    /// - `parse_options`: How strictly to parse the read frames
+    /// - `own_messages`: How to broadcast the echoes of messages send by the controller
+    /// - `raw_tap`: Where to mirror the read bytes
+    /// - `slots`: Where to note the purged slots
+    ///
+    /// # Returns
+    ///
+    /// Why the reading thread has to stop, if the port can not be read anymore.
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_next_message(
+        port: &mut FrameReader<ReadPort>,
+        echoes: &mut EchoMatcher,
+        answers: &mut AnswerCorrelator,
+        transactions: &mut TransactionTracker,
+        idle: &mut IdleWatch,
+        send_to: &Fanout,
+        stopping: &CancellationToken,
+        last_activity: &Arc<Mutex<Instant>>,
+        track: &Arc<TrackState>,
+        busy: &watch::Sender<bool>,
+        stats: &StatsCollector,
+        duplicates: &mut DuplicateFilter,
+        extra_slot_bytes: ExtraBytes,
+        parse_options: ParseOptions,
+        own_messages: OwnMessages,
+        raw_tap: &RawTap,
+        slots: &SlotUsage,
+    ) -> Option<ReaderStop> {
+        // We hold reading back while lagging subscribers would lose messages
+        if send_to.await_capacity().await {
+            stats.record_overflow_timeout();
+        }
+
+        // When to report the bus as idle, if it is not already
+        let activity = *last_activity.lock().unwrap();
+        let idle_at = match idle.after {
+            Some(after) if !idle.idle => Some(activity + after),
+            _ => None,
+        };
+        // We also wake up when the next pending request times out
+        let wake_at = match (idle_at, answers.next_deadline()) {
+            (Some(idle_at), Some(deadline)) => Some(idle_at.min(deadline)),
+            (idle_at, deadline) => idle_at.or(deadline),
+        };
+
+        // We read the next message from the serial port
+        let parsed = LocoDriveController::read_next_message(
+            port,
+            echoes,
+            stopping,
+            last_activity,
+            stats,
+            duplicates,
+            wake_at,
+            extra_slot_bytes,
+            parse_options,
+            own_messages,
+            raw_tap,
+        )
+        .await;
+        let parsed = match parsed {
+            Ok(received) => Ok(received),
+            Err(ReadError::Parse(err)) => Err(err),
+            Err(ReadError::Stopped(stopped)) => return Some(stopped),
+        };
+
+        // Devices flooding the bus are reported once they cross the threshold
+        for (device, count) in stats.take_chatty() {
+            if let Err(err) = send_to.send(LocoDriveMessage::ChattyDevice(device, count)) {
+                log_error!("{:?}", err);
+            }
+        }
+
+        // Traffic returned after the bus was reported idle
+        if idle.idle && *last_activity.lock().unwrap() != activity {
+            idle.idle = false;
+            if let Err(err) = send_to.send(LocoDriveMessage::BusResumed) {
+                log_error!("{:?}", err);
+            }
+        }
+
+        // Requests not answered until the message was read, or until now, are timed out
+        let expire_at = match parsed {
+            Ok(_) => *last_activity.lock().unwrap(),
+            Err(_) => Instant::now(),
+        };
+        for request in answers.expire(expire_at) {
+            if let Err(err) = send_to.send(LocoDriveMessage::AnswerTimeout(request)) {
+                log_error!("{:?}", err);
+            }
+        }
+
+        // We check which type the message we received is
+        match parsed {
+            // We can at this level ignore update messages, but they may indicate an idle bus
+            Err(MessageParseError::Update) => {
+                let now = Instant::now();
+                if idle_at.is_some_and(|idle_at| now >= idle_at)
+                    && *last_activity.lock().unwrap() == activity
+                {
+                    idle.idle = true;
+                    if let Err(err) = send_to.send(LocoDriveMessage::BusIdle(now - activity)) {
+                        log_error!("{:?}", err);
+                    }
+                }
+            }
+            // For errors we only give them to our listener and if this fails we print them
+            Err(err) => {
+                // Lost bytes are line noise just like corrupted ones
+                let checksum = matches!(
+                    err,
+                    MessageParseError::InvalidChecksum { .. } | MessageParseError::Truncated { .. }
+                );
+                let line_noise = stats.record_parse_error(checksum);
+                if let Err(err) = send_to.send(LocoDriveMessage::Error(err)) {
+                    log_error!("{:?}", err);
+                };
+                if let Some(count) = line_noise {
+                    if let Err(err) = send_to.send(LocoDriveMessage::LineNoise(count)) {
+                        log_error!("{:?}", err);
+                    }
+                }
+            }
+            Ok(Received {
+                message,
+                echoed,
+                vendor_bytes,
+            }) => {
+                // If the message answers a pending request, we notify our listener of it
+                let read_at = *last_activity.lock().unwrap();
+                if let Some(request) = answers.handle(message, read_at) {
+                    if let Err(err) = send_to.send(LocoDriveMessage::Answer(message, request)) {
+                        log_error!("{:?}", err);
+                    };
+                }
+
+                // Notes the track status reported by the model railroad
+                LocoDriveController::update_track(track, &message);
+                let purged = slots.note_read(&message);
+
+                // The master is free again as soon as anything else than busy is sent
+                busy.send_if_modified(|busy| {
+                    let was_busy = *busy;
+                    *busy = Message::Busy == message;
+                    was_busy != *busy
+                });
+
+                if let Message::LongAck(_, ack) = message {
+                    if ack.failed() {
+                        stats.record_lack_failure();
+                    }
+                }
+
+                // We at least notify our listener about the received message
+                let received = if echoed {
+                    LocoDriveMessage::Echo(message)
+                } else {
+                    LocoDriveMessage::Message(message)
+                };
+                if let Err(err) = send_to.send(received) {
+                    log_error!("{:?}", err);
+                }
+                if !vendor_bytes.is_empty() {
+                    let vendor_bytes = LocoDriveMessage::VendorBytes(message, vendor_bytes);
+                    if let Err(err) = send_to.send(vendor_bytes) {
+                        log_error!("{:?}", err);
+                    }
+                }
+                if let Some(slot) = purged {
+                    if let Err(err) = send_to.send(LocoDriveMessage::SlotPurged(slot)) {
+                        log_error!("{:?}", err);
+                    }
+                }
+
+                // and about the transaction completed by it
+                if let Some(transaction) = transactions.handle(message, read_at) {
+                    if let Err(err) = send_to.send(LocoDriveMessage::Transaction(transaction)) {
+                        log_error!("{:?}", err);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Waits for the next model railroad message and reads that message from a given serial port.
+    ///
+    /// # Parameter
+    ///
+    /// - `port`: The serial port to read the message from
+    /// - `echoes`: Used to notify the writer that the model railroad has successfully received the send message
+    /// - `stopping`: Cancelled to awake this thread from waiting at new messages
+    /// - `last_activity`: Where to note when the last message was read
+    /// - `stats`: Where to count the read frames
+    /// - `duplicates`: Drops duplicated frames
+    /// - `idle_at`: When to stop waiting for a message, as the bus is idle
+    /// - `extra_slot_bytes`: How to handle slot data frames longer than the standard length
+    /// - `parse_options`: How strictly to parse the read frames
+    /// - `own_messages`: How to broadcast the echoes of messages send by the controller
+    /// - `raw_tap`: Where to mirror the read bytes
+    ///
+    /// # Return
+    ///
+    /// [`Received`]: If a model railroad message was read from the port
+    /// [`MessageParseError`]: If there occurred some error while parsing the message
+    /// [`MessageParseError::Update`]: If `stopping` was cancelled to awake,
+    /// `idle_at` was reached or the frame was dropped as duplicate
+    /// [`ReaderStop`]: If the port was closed or could not be read anymore
+    ///
+    /// # Note
+    ///
+    /// This method sleeps until a message was received as long as the maximum timeout is set.
+    #[allow(clippy::too_many_arguments)]
+    async fn read_next_message(
+        port: &mut FrameReader<ReadPort>,
+        echoes: &mut EchoMatcher,
+        stopping: &CancellationToken,
+        last_activity: &Arc<Mutex<Instant>>,
+        stats: &StatsCollector,
+        duplicates: &mut DuplicateFilter,
+        idle_at: Option<Instant>,
+        extra_slot_bytes: ExtraBytes,
+        parse_options: ParseOptions,
+        own_messages: OwnMessages,
+        raw_tap: &RawTap,
+    ) -> Result<Received, ReadError> {
+        // We wait for a messages op code to be received or to a wakeup by a notification.
+        // Peeking keeps the op code buffered, so the frame is sliced out of the buffer at once.
+        let opc = tokio::select! {
+            opc = port.peek(0) => match opc {
+                Ok(opc) => opc,
+                Err(err) => return Err(ReadError::Stopped(ReaderStop::read_failed(err))),
+            },
+            _ = stopping.cancelled() => {
+                return Err(MessageParseError::Update.into())
+            }
+            _ = sleep_until(idle_at.unwrap_or_else(Instant::now)), if idle_at.is_some() => {
+                return Err(MessageParseError::Update.into())
+            }
+        };
+
+        if !Message::known_opc(opc) {
+            port.skip(1);
+            raw_tap.mirror(Direction::Rx, &[opc], Instant::now());
+            return Err(MessageParseError::unknown_opcode(opc)
+                .with_frame(&[opc])
+                .into());
+        }
+
+        // We calculate the length of the message to read
+        let len = match opc & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            0xE0 => {
+                // The code 0xE0 indicates that the second byte of the message is used to display
+                // the messages length so we wait for that second byte.
+                match port.peek(1).await {
+                    Ok(read_len) if read_len > 2 => read_len as usize,
+                    Ok(read_len) => {
+                        port.skip(2);
+                        let err = MessageParseError::unexpected_end(opc, 1);
+                        return Err(err.with_frame(&[opc, read_len]).into());
+                    }
+                    Err(err) => return Err(ReadError::Stopped(ReaderStop::read_failed(err))),
+                }
+            }
+            _ => {
+                return Err(MessageParseError::unknown_opcode(opc)
+                    .with_frame(&[opc])
+                    .into())
+            }
+        };
+
+        // Data bytes never have their highest bit set, so an op code within the frame means
+        // a byte was lost. We resynchronize at that op code, so the following frame is not discarded.
+        for index in 1..len {
+            match port.peek(index).await {
+                Ok(byte) if byte & 0x80 != 0 => {
+                    let cut = match port.read_frame(index).await {
+                        Ok(cut) => cut,
+                        Err(err) => return Err(ReadError::Stopped(ReaderStop::read_failed(err))),
+                    };
+                    raw_tap.mirror(Direction::Rx, &cut, Instant::now());
+                    let err = MessageParseError::truncated(opc, index);
+                    return Err(err.with_frame(&cut).into());
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        // We take the whole message out of the read buffer
+        let buf = match port.read_frame(len).await {
+            Ok(buf) => buf,
+            Err(err) => return Err(ReadError::Stopped(ReaderStop::read_failed(err))),
+        };
+
+        log_trace!(bytes = ?buf, "rx");
+
+        // The bus was busy until now
+        let read_at = Instant::now();
+        raw_tap.mirror(Direction::Rx, &buf, read_at);
+        *last_activity.lock().unwrap() = read_at;
+        stats.record_frame(&buf, read_at);
+
+        // Noisy taps may deliver the same frame twice
+        if duplicates.is_duplicate(&buf, read_at) {
+            stats.record_duplicate();
+            return Err(MessageParseError::Update.into());
+        }
+
+        // We now parse the read bytes to our message
+        let (message, vendor_bytes) = Message::parse_with(buf.as_slice(), extra_slot_bytes)?;
+        if parse_options.strict {
+            // The read frame may be shorter than the message encodes to, like variable length ones
+            message.check_strict(&buf)?;
+        }
+        log_debug!(message = ?message, "rx");
+
+        // Check for receiving last send message to awake the writing thread.
+        // Some interfaces alter the bytes of the echo, so we compare the parsed messages.
+        if echoes.handle(&message) {
+            match own_messages {
+                OwnMessages::Broadcast => {}
+                OwnMessages::Ignore => return Err(MessageParseError::Update.into()),
+                OwnMessages::Tag => {
+                    return Ok(Received {
+                        message,
+                        echoed: true,
+                        vendor_bytes,
+                    })
+                }
+            }
+        }
+
+        Ok(Received {
+            message,
+            echoed: false,
+            vendor_bytes,
+        })
+    }
 
-        let wait_for_write = Arc::new(tokio::sync::Mutex::new(false));
+    /// Notes the track status and power state reported by `message`.
+    ///
+    /// Only slot data read from the model railroad reports the complete track status.
+    /// Power messages update the power state of an already known status.
+    fn update_track(track: &TrackState, message: &Message) {
+        let mut status = track.status.lock().unwrap();
+        match *message {
+            Message::SlRdData(_, _, _, _, _, trk, ..)
+            | Message::ProgrammingFinalResponse(_, _, _, _, _, trk, ..) => {
+                *status = Some(trk);
+                track.power.send_if_modified(|power| {
+                    let reported = match (trk.power_on(), *power) {
+                        (false, _) => PowerState::Off,
+                        // The slot data does not tell an idle layout from a running one
+                        (true, PowerState::Idle) => PowerState::Idle,
+                        (true, _) => PowerState::On,
+                    };
+                    std::mem::replace(power, reported) != reported
+                });
+            }
+            Message::GpOn | Message::GpOff => {
+                if let Some(trk) = *status {
+                    *status = Some(TrkArg::new(
+                        Message::GpOn == *message,
+                        trk.track_idle(),
+                        trk.mlok1(),
+                        trk.prog_busy(),
+                    ));
+                }
+            }
+            _ => {}
+        }
 
-        // All steps has passed successfully
-        Ok(LocoDriveController {
-            port,
-            send,
-            stop,
-            fire_stop,
-            reading_thread,
-            sending_timeout,
-            wait_for_write,
-        })
+        if let Some(event) = PowerEvent::from_message(message) {
+            track.power.send_if_modified(|power| {
+                let reported = event.state();
+                std::mem::replace(power, reported) != reported
+            });
+        }
     }
 
+    /// Sends a Message to the model railroad.
+    ///
+    /// # Parameter
+    ///
+    /// - `message`: The message to send to the model railroads serial port
+    ///
     /// # Return
     ///
-    /// The port the `LocoDriveConnector` is connected to.
-    pub fn get_port_name(&self) -> Option<String> {
-        self.port.name()
+    /// If the message was successfully written nothing is returned else
+    /// an [`LocoDriveSendingError`] describing the reason for the fail of the writing is returned.
+    pub async fn send_message(&self, message: Message) -> Result<(), LocoDriveSendingError> {
+        self.send_message_with(message, SendOptions::default())
+            .await
     }
 
+    /// Sends a Message to the model railroad and retries it as configured by `options`.
+    ///
+    /// One attempt of sending fails if the message was not received back in time or
+    /// the model railroad answered with a failed [`Message::LongAck`].
+    /// See [`SendOptions`] for when the answer is awaited.
+    ///
+    /// # Parameter
+    ///
+    /// - `message`: The message to send to the model railroads serial port
+    /// - `options`: How to retry and which answer to await
+    ///
     /// # Return
     ///
-    /// The connected ports baud rate.
-    pub fn get_baud_rate(&self) -> tokio_serial::Result<u32> {
-        self.port.baud_rate()
+    /// If the message was successfully written nothing is returned else the
+    /// [`LocoDriveSendingError`] of the last attempt is returned.
+    pub async fn send_message_with(
+        &self,
+        message: Message,
+        options: SendOptions,
+    ) -> Result<(), LocoDriveSendingError> {
+        self.send_message_acked(message, options).await?;
+        Ok(())
     }
 
+    /// Sends a Message to the model railroad like [`LocoDriveController::send_message_with()`],
+    /// but returns the acknowledgment the model railroad answered with.
+    ///
+    /// # Parameter
+    ///
+    /// - `message`: The message to send to the model railroads serial port
+    /// - `options`: How to retry and which answer to await
+    ///
     /// # Return
     ///
-    /// The maximum time to wait for a message to be send correctly.
-    pub fn get_sending_timeout(&self) -> u64 {
-        self.sending_timeout
+    /// The not failed acknowledgment of the successful attempt, or `None` if no acknowledgment
+    /// was received in the awaited time. If sending failed the [`LocoDriveSendingError`]
+    /// of the last attempt is returned.
+    pub async fn send_message_acked(
+        &self,
+        message: Message,
+        options: SendOptions,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        self.writer
+            .send_message_acked(message, options, &CancellationToken::new())
+            .await
     }
 
-    /// Overrides the sending timeout with the give value.
+    /// Sends a Message to the model railroad like [`LocoDriveController::send_message_acked()`],
+    /// but stops waiting as soon as `cancel` is cancelled.
+    ///
+    /// Use this to abort a hung send, for example when the user closes a dialog.
+    /// A message is never cancelled while its bytes are written, so no partial message
+    /// is left on the bus. Dropping the returned future is just as safe,
+    /// but cancelling lets the caller know the outcome.
     ///
     /// # Parameter
     ///
-    /// - `sending_timeout`: The time to wait for a reading action to complete.
+    /// - `message`: The message to send to the model railroads serial port
+    /// - `options`: How to retry and which answer to await
+    /// - `cancel`: The token to cancel sending with
+    ///
+    /// # Return
+    ///
+    /// Like [`LocoDriveController::send_message_acked()`] or
+    /// [`LocoDriveSendingError::Cancelled`] if `cancel` was cancelled before sending completed.
+    pub async fn send_message_cancellable(
+        &self,
+        message: Message,
+        options: SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        self.writer
+            .send_message_acked(message, options, cancel)
+            .await
+    }
+
+    /// Hands the locomotive in `slot` over to a handheld throttle by moving it to the dispatch slot 0,
+    /// as described for [`Message::MoveSlots`]. The throttle then takes it by a dispatch get.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::Rejected`]: If the command station answered with a failed
+    ///   [`Message::LongAck`], as the slot could not be dispatched
+    /// - [`LocoDriveSendingError::Timeout`]: If the command station did not answer in the sending timeout
+    /// - The errors of [`LocoDriveController::send_message()`]
+    pub async fn dispatch_put(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.dispatch_put(slot).await
+    }
+
+    /// Takes the locomotive put to the dispatch slot 0 by another throttle,
+    /// as described for [`Message::MoveSlots`].
     ///
     /// # Returns
     ///
-    /// If some error occurred on overriding the timeout on the port.
-    pub fn set_sending_timeout(&mut self, sending_timeout: u64) -> Result<(), Error> {
-        self.sending_timeout = sending_timeout;
-        self.port
-            .set_timeout(Duration::from_millis(sending_timeout))
+    /// The data of the slot holding the dispatched locomotive,
+    /// or `None` if the command station answered with a failed [`Message::LongAck`],
+    /// as no locomotive is dispatched.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::Timeout`]: If the command station did not answer in the sending timeout
+    /// - The errors of [`LocoDriveController::send_message()`]
+    pub async fn dispatch_get(&self) -> Result<Option<SlotUpdate>, LocoDriveSendingError> {
+        self.writer.dispatch_get().await
     }
 
-    /// Stops the async model railroads message reader and wait until the tokio thread is joined.
+    /// Releases `slot` by marking it [`State::Free`], so the command station may reuse it.
+    /// The other status bits of the slot are kept, therefore the slot is read first.
     ///
-    /// If no thread is opened the function returns immediately.
+    /// # Errors
     ///
-    /// # Panics
+    /// - [`LocoDriveSendingError::Timeout`]: If the slot data was not received in the sending timeout
+    /// - The errors of [`LocoDriveController::send_message()`]
+    pub async fn release_slot(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.set_slot_state(slot, State::Free).await
+    }
+
+    /// Marks `slot` as [`State::Common`], so its locomotive keeps running
+    /// and may be taken by another throttle. The other status bits of the slot are kept,
+    /// therefore the slot is read first.
     ///
-    /// This function panics if the reading thread has panicked or the reading thread was killed,
-    /// by some external source.
-    fn stop_reader(&mut self) {
-        if let Some(_reader) = self.reading_thread.take() {
-            // Note the thread to end reading
-            *self.stop.lock().unwrap() = true;
-            (*self.fire_stop).notify_waiters();
-        }
+    /// # Errors
+    ///
+    /// Like [`LocoDriveController::release_slot()`].
+    pub async fn set_common(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.set_slot_state(slot, State::Common).await
     }
 
-    /// Helper method that spawns a new async tokio thread for reading model railroads
-    /// messages from the specified serial port.
+    /// # Returns
     ///
-    /// # Parameter
+    /// The slots used by this controller and its [`CommandHandle`]s.
     ///
-    /// - `port_name`: The name of the serial port to read from
-    /// - `baud_rate`: The baud rate to use
-    /// - `flow_control`: The used [`FlowControl`]
-    /// - `send`: The information to free the writer when rechecking that the message is received by the model railroad
-    /// - `send_to`: Where to send the received and parsed model railroad messages
-    /// - `wait_to`: A mutex indicates this thread to stop.
-    /// - `stopping`: A notify used to awake the reading thread from waiting for new incoming messages
+    /// A slot is used after its speed or functions were written, it was marked [`State::InUse`]
+    /// or a `NULL`-Move was done on it. It is no longer used after it was released, set common
+    /// or put to dispatch. If the command station reports a used slot not to be in use anymore,
+    /// [`LocoDriveMessage::SlotPurged`] is send.
+    pub fn slots_in_use(&self) -> Vec<SlotArg> {
+        self.slots.used()
+    }
+
+    /// # Returns
     ///
+    /// The service keeping slots alive, if started by [`LocoDriveControllerBuilder::keep_alive()`].
+    pub fn keep_alive(&self) -> Option<&KeepAlive> {
+        self.keep_alive.as_deref()
+    }
+
     /// # Returns
     ///
-    /// The spawned threads join handle.
-    #[allow(clippy::too_many_arguments)]
-    async fn start_reading_thread(
-        port_name: String,
-        baud_rate: u32,
-        flow_control: FlowControl,
-        send: &SendSynchronisation,
-        send_to: &Sender<LocoDriveMessage>,
-        wait_to: &Arc<Mutex<bool>>,
-        stopping: &Arc<Notify>,
-        ignore_send_messages: bool,
-    ) -> JoinHandle<()> {
-        // Clone all arcs to make them save to use in the reading thread
-        let arc_send_to = send_to.clone();
+    /// The current status of the reading thread.
+    pub fn reader_status(&self) -> ReaderStatus {
+        self.reader.status()
+    }
 
-        let last_message = &send.0;
-        let notify_wait = &send.1;
+    /// Creates a [`ReaderHandle`] to await the reading thread to stop.
+    pub fn reader_handle(&self) -> ReaderHandle {
+        self.reader.clone()
+    }
 
-        let last_message_move = last_message.clone();
-        let notify_wait_move = notify_wait.clone();
+    /// Creates a [`CommandHandle`] to send messages with from other tasks.
+    pub fn command_handle(&self) -> CommandHandle {
+        CommandHandle {
+            writer: self.writer.clone(),
+        }
+    }
+}
 
-        let new_arc_wait_to = wait_to.clone();
-        let new_arc_stopping = stopping.clone();
+/// Stops the connection of a [`LocoDriveController`] when its last clone is dropped.
+///
+/// Holds the channel to the task owning the port, see [`Writer::run()`].
+struct StopOnDrop(UnboundedSender<PortCommand>);
 
-        tokio::spawn(async move {
-            // Connects the port to read from
-            let mut port = match tokio_serial::new(port_name, baud_rate)
-                .data_bits(DataBits::Eight)
-                .stop_bits(StopBits::Two)
-                .parity(Parity::None)
-                .flow_control(flow_control)
-                .open_native_async()
-            {
-                Ok(port) => port,
-                Err(err) => {
-                    if let Err(err) = arc_send_to.send(LocoDriveMessage::SerialPortError(err)) {
-                        eprintln!(
-                            "[locodrive:ERROR] Unable to send critical error to receiver! \
-                        Closed connection to the serial port!\n \
-                        Following error occurred: {:?}",
-                            err
-                        );
-                    }
-                    return;
-                }
-            };
+impl Drop for StopOnDrop {
+    /// Sends [`PortCommand::Shutdown`] to the task owning the port, without waiting for the
+    /// reading thread to end. Dropping does not panic, even if the reading thread panicked before,
+    /// as panics are reported as [`MessageParseError::ReaderPanicked`]
+    /// and the reading thread is restarted.
+    fn drop(&mut self) {
+        // The task already stopped if the runtime shuts down
+        let _ = self.0.send(PortCommand::Shutdown);
+    }
+}
 
-            // For linux systems we once more ensure that this set is not exclusive usable for us
-            #[cfg(unix)]
-            if let Err(err) = port.set_exclusive(false) {
-                if let Err(err) = arc_send_to.send(LocoDriveMessage::SerialPortError(err)) {
-                    eprintln!(
-                        "[locodrive:ERROR] Unable to send critical error to receiver! \
-                    Closed connection to the serial port!\n \
-                    Following error occurred: {:?}",
-                        err
-                    );
-                };
-                return;
-            };
+/// A command to the task owning the port to write to, see [`Writer::run()`].
+enum PortCommand {
+    /// Writes a message like [`Writer::send_message_acked()`].
+    Send {
+        /// The message to write
+        message: Message,
+        /// How to retry and which answer to await
+        options: SendOptions,
+        /// Cancels writing the message
+        cancel: CancellationToken,
+        /// Where to answer with the result of writing
+        reply: oneshot::Sender<Result<Option<Ack1Arg>, LocoDriveSendingError>>,
+    },
+    /// Reads the baud rate of the port.
+    BaudRate(oneshot::Sender<tokio_serial::Result<u32>>),
+    /// Sets the timeout of the port.
+    Reconfigure {
+        /// The new sending timeout in milliseconds
+        sending_timeout: u64,
+        /// Where to answer with the result of reconfiguring
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+    /// Stops writing, the reading thread and the background tasks.
+    /// The commands queued before are handled first, those queued after are dropped.
+    /// Once stopped, the [`ReaderHandle`] reports [`ReaderStop::Requested`].
+    Shutdown,
+}
 
-            // The lack indicates the last message to await a model railroads response
-            let mut lack = false;
-            // The last message to pass when a lack was received
-            let mut last_message = Message::Busy;
-
-            let new_arc_send_locked = Arc::new((&last_message_move, &notify_wait_move));
-
-            println!("[locodrive:INFO] Reading thread started!");
-
-            // This thread reads till it is notified to stop
-            while !*new_arc_wait_to.lock().unwrap() {
-                // We read and directly handle received messages
-                LocoDriveController::handle_next_message(
-                    &mut port,
-                    &new_arc_send_locked,
-                    &mut lack,
-                    &mut last_message,
-                    &arc_send_to,
-                    &new_arc_stopping,
-                    ignore_send_messages,
-                )
-                .await;
-            }
+/// Writes the messages of a [`LocoDriveController`] and all its [`CommandHandle`]s
+/// to the serial port.
+///
+/// The port is owned by a single task, see [`Writer::run()`], which receives the messages
+/// to write as [`PortCommand`]s. So the senders share no lock on the port.
+struct Writer {
+    /// The commands to the task owning the port, handled in the order they were send
+    commands: UnboundedSender<PortCommand>,
+    /// Tells the reader which echo to expect.
+    echoes: EchoSender,
+    /// How long to wait on success of sending.
+    sending_timeout: AtomicU64,
+    /// Whether to await the echo of written messages.
+    echo_policy: EchoPolicy,
+    /// Whether to broadcast messages when they are written.
+    report_sent_messages: bool,
+    /// Where to mirror the written bytes.
+    raw_tap: RawTap,
+    /// Where to note the slots used by the written messages.
+    slots: Arc<SlotUsage>,
+    /// The channel the answers to written messages are received from.
+    send_to: Fanout,
+    /// The statistics collected for this connection.
+    stats: Arc<StatsCollector>,
+    /// When the last message was read from or written to the bus.
+    last_activity: Arc<Mutex<Instant>>,
+    /// The minimal gap between the last bus activity and the next write.
+    tx_gap: Duration,
+    /// The additional gap per priority delay step of a message.
+    priority_backoff: Duration,
+    /// Whether the master reports to be busy.
+    busy: watch::Receiver<bool>,
+    /// How long to hold writes back at most while the master is busy.
+    busy_hold: Duration,
+    /// Whether to block writes to the reserved system slots.
+    strict_slots: bool,
+    /// Where to record the outcome of every command send.
+    audit: Option<AuditLog>,
+}
 
-            println!("[locodrive:INFO] Reading thread closed!");
-        })
+impl Writer {
+    /// # Return
+    ///
+    /// The maximum time to wait for a message to be send correctly.
+    fn sending_timeout(&self) -> u64 {
+        self.sending_timeout.load(Ordering::Relaxed)
     }
 
-    /// Handles a model railroad message after it was parsed successfully.
-    ///
-    /// # Parameter
+    /// Owns the `port` and handles the `commands` one after another,
+    /// until [`PortCommand::Shutdown`] is received.
     ///
-    /// - `port`: The port to read messages from
-    /// - `send`: The information to free the writer when rechecking that the message is received by the model railroad
-    /// - `lack`: Whether the last received message expects a lack to follow
-    /// - `last_message`: The previous received message
-    /// - `send_to`: Where to send the received and parsed model railroad messages
-    /// - `stopping`: A notify used to awake the reading thread from waiting for new incoming messages
-    async fn handle_next_message<'a>(
-        port: &mut SerialStream,
-        send: &ReferencedSendSynchronisation<'a>,
-        await_response: &mut bool,
-        last_message: &mut Message,
-        send_to: &Sender<LocoDriveMessage>,
-        stopping: &Arc<Notify>,
-        ignore_send_messages: bool,
+    /// Then `stopping` is cancelled, which stops the other tasks of the controller.
+    /// The commands still queued are dropped, so their senders fail.
+    async fn run(
+        writer: Arc<Writer>,
+        mut port: WritePort,
+        mut commands: UnboundedReceiver<PortCommand>,
+        stopping: CancellationToken,
     ) {
-        // We read the next message from the serial port
-        let parsed =
-            LocoDriveController::read_next_message(port, send, stopping, ignore_send_messages)
-                .await;
-
-        // We check which type the message we received is
-        match parsed {
-            // We can at this level ignore update messages
-            Err(MessageParseError::Update) => {}
-            // For errors we only give them to our listener and if this fails we print them
-            Err(err) => {
-                if let Err(err) = send_to.send(LocoDriveMessage::Error(err)) {
-                    eprintln!("[locodrive:ERROR] {:?}", err);
-                };
-                *await_response = false;
-            }
-            Ok(message) => {
-                // If our last received message expects a response message to follow, we check
-                // for this response message to be received
-                if *await_response {
-                    match message {
-                        Message::LongAck(lopc, _) => {
-                            if lopc.check_opc(last_message) {
-                                // We notify our listener of that long acknowledgment
-                                if let Err(err) =
-                                    send_to.send(LocoDriveMessage::Answer(message, *last_message))
-                                {
-                                    eprintln!("[locodrive:ERROR] {:?}", err);
-                                };
-                            }
+        while let Some(command) = commands.recv().await {
+            match command {
+                PortCommand::Send {
+                    message,
+                    options,
+                    cancel,
+                    mut reply,
+                } => {
+                    // If the future of the sender is dropped, the message is still written
+                    // completely, so no partial frame is left on the bus.
+                    // Only waiting for the master, the echo or the acknowledgment is skipped.
+                    let abandoned = cancel.child_token();
+                    let attempts = writer.write_attempts(&mut port, message, &options, &abandoned);
+                    tokio::pin!(attempts);
+                    tokio::select! {
+                        result = &mut attempts => {
+                            let _ = reply.send(result);
                         }
-                        Message::SlRdData(..) => {
-                            if last_message.await_slot_data() {
-                                if let Err(err) =
-                                    send_to.send(LocoDriveMessage::Answer(message, *last_message))
-                                {
-                                    eprintln!("[locodrive:ERROR] {:?}", err);
-                                };
-                            }
+                        _ = reply.closed() => {
+                            abandoned.cancel();
+                            let _ = attempts.await;
                         }
-                        _ => {}
                     }
                 }
-
-                // Checks whether our message is followed by an acknowledgment
-                if message.answer_follows() {
-                    *await_response = true;
-                    *last_message = message;
-                } else if Message::Busy != message {
-                    *await_response = false;
+                PortCommand::BaudRate(reply) => {
+                    let _ = reply.send(port.serial().and_then(|port| port.baud_rate()));
                 }
-
-                // We at least notify our listener about the received message
-                if let Err(err) = send_to.send(LocoDriveMessage::Message(message)) {
-                    eprintln!("[locodrive:ERROR] {:?}", err);
+                PortCommand::Reconfigure {
+                    sending_timeout,
+                    reply,
+                } => {
+                    let result = match &mut port {
+                        WritePort::Serial(port) => {
+                            port.set_timeout(Duration::from_millis(sending_timeout))
+                        }
+                        // The timeout of writing in memory is only enforced by awaiting the echo
+                        WritePort::Memory(_) => Ok(()),
+                    };
+                    let _ = reply.send(result);
                 }
+                PortCommand::Shutdown => break,
             }
         }
+
+        stopping.cancel();
     }
 
-    /// Waits for the next model railroad message and reads that message from a given serial port.
-    ///
-    /// # Parameter
-    ///
-    /// - `port`: The serial port to read the message from
-    /// - `send`: Used to notify the writer that the model railroad has successfully received the send message
-    /// - `stopping`: This is used to notify this thread to awake from waiting at new messages
-    ///
-    /// # Return
+    /// Sends the command built by `command` to the task owning the port and awaits its answer.
     ///
-    /// [`Message`]: If a model railroad message was read from the port
-    /// [`MessageParseError`]: If there occurred some error while parsing the message
-    /// [`MessageParseError::Update`]: If a notification was send over `stopping` to awake
+    /// # Errors
     ///
-    /// # Note
+    /// If the controller was dropped, so the port is not owned anymore.
+    async fn command<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> PortCommand,
+    ) -> Result<T, Error> {
+        let (reply, answer) = oneshot::channel();
+        let stopped = || {
+            Error::new(
+                tokio_serial::ErrorKind::Unknown,
+                "the controller was stopped",
+            )
+        };
+        self.commands.send(command(reply)).map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())
+    }
+
+    /// Sends a message like [`LocoDriveController::send_message_cancellable()`].
     ///
-    /// This method sleeps until a message was received as long as the maximum timeout is set.
-    async fn read_next_message<'a>(
-        port: &mut SerialStream,
-        send: &ReferencedSendSynchronisation<'a>,
-        stopping: &Arc<Notify>,
-        ignore_send_messages: bool,
-    ) -> Result<Message, MessageParseError> {
-        // The buffer we want to read the model railroads message to
-        let mut buf = vec![0u8; 1];
+    /// The messages are written in the order they were send, so no sender starves.
+    /// Sending is cancellation safe: If the future is dropped or `cancel` is cancelled
+    /// while waiting, the expected echo is reset, so the next sender is not affected.
+    /// The outcome is recorded to the audit sink, if any.
+    async fn send_message_acked(
+        &self,
+        message: Message,
+        options: SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        // Dropping the future records the command as cancelled
+        let audit = self.audit.as_ref().map(|audit| audit.begin(message));
+        let result = self.send_unaudited(message, options, cancel).await;
+        if let Some(audit) = audit {
+            audit.finish(&result);
+        }
+        result
+    }
 
-        // We wait for a messages op code to be received or to a wakeup by a notification
-        let opc = tokio::select! {
-            opc = port.read_exact(&mut buf) => match opc {
-                Ok(_) => buf[0],
-                Err(_) => return Err(MessageParseError::UnexpectedEnd(0x00)),
-            },
-            _ = stopping.notified() => {
-                return Err(MessageParseError::Update)
+    /// Sends a message like [`Writer::send_message_acked()`] without recording it.
+    async fn send_unaudited(
+        &self,
+        message: Message,
+        options: SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        if self.strict_slots && !options.allow_reserved_slots {
+            if let Some(slot) = Self::reserved_slot_written(&message) {
+                return Err(LocoDriveSendingError::ReservedSlot(slot));
             }
+        }
+
+        let (reply, result) = oneshot::channel();
+        let command = PortCommand::Send {
+            message,
+            options,
+            cancel: cancel.clone(),
+            reply,
         };
+        // The controller was dropped, so nobody reads the echoes anymore
+        if self.commands.send(command).is_err() {
+            return Err(LocoDriveSendingError::IllegalState);
+        }
 
-        if !Message::known_opc(opc) {
-            return Err(MessageParseError::UnknownOpcode(opc));
+        tokio::select! {
+            result = result => result.unwrap_or(Err(LocoDriveSendingError::IllegalState)),
+            _ = cancel.cancelled() => Err(LocoDriveSendingError::Cancelled),
         }
+    }
 
-        // We calculate the length of the remaining message to read
-        let len = match opc & 0xE0 {
-            0x80 => 2,
-            0xA0 => 4,
-            0xC0 => 6,
-            0xE0 => {
-                // The code 0xE0 indicates that the second byte of the message is used to display
-                // the messages length so we read that second byte.
-                let mut read_len = [0u8; 1];
-                match port.read_exact(&mut read_len).await {
-                    Ok(_) => {
-                        buf.push(read_len[0]);
-                        // We already read the messages first byte
-                        read_len[0] as usize - 1
+    /// Makes all attempts to write `message` to the `port` as configured by `options`.
+    /// All attempts are made without other writers in between.
+    async fn write_attempts(
+        &self,
+        port: &mut WritePort,
+        message: Message,
+        options: &SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        let mut attempt = 0;
+        loop {
+            match self.send_attempt(port, message, options, cancel).await {
+                Err(LocoDriveSendingError::Timeout | LocoDriveSendingError::Rejected(_))
+                    if attempt < options.retries =>
+                {
+                    attempt += 1;
+                    self.stats.record_retransmit();
+                    tokio::select! {
+                        _ = sleep(options.retry_delay) => {}
+                        _ = cancel.cancelled() => return Err(LocoDriveSendingError::Cancelled),
                     }
-                    Err(_) => return Err(MessageParseError::UnexpectedEnd(opc)),
                 }
+                result => return result,
             }
-            _ => return Err(MessageParseError::UnknownOpcode(opc)),
-        };
+        }
+    }
 
-        // As we already read the messages opcode
-        let mut message = vec![0u8; len - 1];
+    /// Sends a dispatch put like [`LocoDriveController::dispatch_put()`].
+    async fn dispatch_put(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        match self.move_slots(slot, SlotArg::new(0)).await? {
+            Ok(_) => Ok(()),
+            Err(ack) => Err(LocoDriveSendingError::Rejected(ack)),
+        }
+    }
 
-        // We read the remaining message from the serial port
-        buf.append(match port.read_exact(&mut message).await {
-            Ok(_) => &mut message,
-            Err(_) => return Err(MessageParseError::UnexpectedEnd(opc)),
-        });
+    /// Sends a dispatch get like [`LocoDriveController::dispatch_get()`].
+    async fn dispatch_get(&self) -> Result<Option<SlotUpdate>, LocoDriveSendingError> {
+        // The destination is not used by a dispatch get
+        Ok(self
+            .move_slots(SlotArg::new(0), SlotArg::new(0))
+            .await?
+            .ok())
+    }
+
+    /// Moves the slot `src` to `dst` and awaits the answer of the command station.
+    ///
+    /// # Returns
+    ///
+    /// The read slot data or the failed acknowledgment the command station answered with.
+    async fn move_slots(
+        &self,
+        src: SlotArg,
+        dst: SlotArg,
+    ) -> Result<Result<SlotUpdate, Ack1Arg>, LocoDriveSendingError> {
+        let message = Message::MoveSlots(src, dst);
+        self.request(message, |answer| move_answer(&message, answer))
+            .await
+    }
+
+    /// Sets the state of `slot` like [`LocoDriveController::release_slot()`].
+    async fn set_slot_state(
+        &self,
+        slot: SlotArg,
+        state: State,
+    ) -> Result<(), LocoDriveSendingError> {
+        // The other status bits of the slot have to be kept
+        let stat1 = self
+            .request(Message::RqSlData(slot), |answer| match *answer {
+                Message::SlRdData(read, stat1, ..) if read == slot => Some(stat1),
+                _ => None,
+            })
+            .await?;
+
+        let stat1 = Stat1Arg::new(
+            stat1.s_purge(),
+            stat1.consist(),
+            state,
+            stat1.decoder_type(),
+        );
+        self.send_message_acked(
+            Message::SlotStat1(slot, stat1),
+            SendOptions::default(),
+            &CancellationToken::new(),
+        )
+        .await?;
+        Ok(())
+    }
 
-        // Check for receiving last send message to awake the writing thread
-        let (lock, cvar) = **send;
-        let mut last_send = lock.lock().unwrap();
+    /// Sends `message` and awaits the first received message `answer` interprets.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::Timeout`]: If no answer was received in the sending timeout
+    /// - The errors of [`Writer::send_message_acked()`]
+    async fn request<T>(
+        &self,
+        message: Message,
+        answer: impl Fn(&Message) -> Option<T>,
+    ) -> Result<T, LocoDriveSendingError> {
+        // We listen before writing to not miss a fast answer
+        let mut answers = self.send_to.subscribe();
+        self.send_message_acked(message, SendOptions::default(), &CancellationToken::new())
+            .await?;
 
-        if !(*last_send).is_empty() && (*last_send) == buf {
-            *last_send = vec![0u8; 0];
-            cvar.notify_waiters();
+        let deadline = Instant::now() + Duration::from_millis(self.sending_timeout());
+        loop {
+            let received = tokio::select! {
+                received = answers.recv() => received,
+                _ = sleep_until(deadline) => return Err(LocoDriveSendingError::Timeout),
+            };
 
-            if ignore_send_messages {
-                return Err(MessageParseError::Update);
+            match received {
+                Ok(LocoDriveMessage::Message(received)) => {
+                    if let Some(answer) = answer(&received) {
+                        return Ok(answer);
+                    }
+                }
+                Err(RecvError::Closed) => return Err(LocoDriveSendingError::IllegalState),
+                _ => {}
             }
         }
+    }
 
-        // We now parse the read bytes to our message
-        Message::parse(buf.as_slice())
+    /// Writes the message once and awaits the answer as configured by `options`.
+    async fn send_attempt(
+        &self,
+        port: &mut WritePort,
+        message: Message,
+        options: &SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        // We listen before writing to not miss a fast answer
+        let mut answers = self.send_to.subscribe();
+
+        self.write_message(port, message, cancel).await?;
+
+        let ack_timeout = match (options.ack_timeout, options.require_ack) {
+            (Some(ack_timeout), _) => ack_timeout,
+            (None, true) => Duration::from_millis(self.sending_timeout()),
+            (None, false) => return Ok(None),
+        };
+        let deadline = Instant::now() + ack_timeout;
+
+        loop {
+            let received = tokio::select! {
+                received = answers.recv() => received,
+                _ = sleep_until(deadline) => break,
+                _ = cancel.cancelled() => return Err(LocoDriveSendingError::Cancelled),
+            };
+
+            match received {
+                Ok(LocoDriveMessage::Message(Message::LongAck(lopc, ack)))
+                    if lopc.check_opc(&message) =>
+                {
+                    return if ack.failed() {
+                        Err(LocoDriveSendingError::Rejected(ack))
+                    } else {
+                        Ok(Some(ack))
+                    };
+                }
+                Err(RecvError::Closed) => return Err(LocoDriveSendingError::IllegalState),
+                _ => {}
+            }
+        }
+
+        if options.require_ack {
+            Err(LocoDriveSendingError::Timeout)
+        } else {
+            Ok(None)
+        }
     }
 
-    /// Sends a Message to the model railroad.
-    ///
-    /// # Parameter
-    ///
-    /// - `message`: The message to send to the model railroads serial port
-    ///
-    /// # Return
+    /// Writes the message to the serial port and waits until it is received back.
     ///
-    /// If the message was successfully written nothing is returned else
-    /// an [`LocoDriveSendingError`] describing the reason for the fail of the writing is returned.
-    pub async fn send_message(&mut self, message: Message) -> Result<(), LocoDriveSendingError> {
-        // If we have no reading thread we raise an error, that should not be possible
-        if self.reading_thread.is_none() {
-            return Err(LocoDriveSendingError::IllegalState);
+    /// Cancelling stops waiting for the master, the bus or the echo,
+    /// but never interrupts writing the bytes of the message.
+    /// A message whose token is cancelled before writing starts is not written at all.
+    async fn write_message(
+        &self,
+        port: &mut WritePort,
+        message: Message,
+        cancel: &CancellationToken,
+    ) -> Result<(), LocoDriveSendingError> {
+        // We encode the message to send into a stack buffer, which every message fits into
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let len = message
+            .write_to(&mut buf)
+            .map_err(LocoDriveSendingError::Encode)?;
+        let bytes = &buf[..len];
+
+        // We wait for the master to be free and the bus to be idle long enough for this message.
+        // A token cancelled already is checked first, so its message is never written.
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return Err(LocoDriveSendingError::Cancelled),
+            _ = async {
+                self.await_master_free().await;
+                self.await_tx_gap(&message).await;
+            } => {}
         }
 
-        let _send_message_waiting = self.wait_for_write.lock().await;
+        // We tell the reader which echo to expect before writing, to not miss a fast one.
+        // Dropping the receiver however this function is left tells the reader to stop expecting it.
+        let echoed = match self.echo_policy {
+            EchoPolicy::None => None,
+            EchoPolicy::Require | EchoPolicy::Optional => Some(self.echoes.expect(message)),
+        };
 
-        // We parse the message to send in a byte vector
-        let bytes = message.to_message();
+        log_debug!(message = ?message, "tx");
+        log_trace!(bytes = ?bytes, "tx");
 
-        // We wait for possible other waiting operations to finish
-        let (lock, notify) = &*self.send;
+        // Another thread may have cancelled the token since waiting ended
+        if cancel.is_cancelled() {
+            return Err(LocoDriveSendingError::Cancelled);
+        }
 
-        {
-            // We say the Reader which method to expect
-            let mut send = lock.lock().unwrap();
+        // Write the message to the serial port
+        let written = port.write_all(bytes).await;
+        let written_at = Instant::now();
+        *self.last_activity.lock().unwrap() = written_at;
 
-            *send = bytes.clone();
+        if written.is_err() {
+            return Err(LocoDriveSendingError::NotWritable);
         }
 
-        // Write the message to the serial port
-        match self.port.write_all(&bytes).await {
-            Ok(_) => {
-                // When successfully written, wait until the positive response
-                // by the reading thread is received or raise an error
-                if !(*lock.lock().unwrap()).is_empty() {
-                    if tokio::select! {
-                        _ = notify.notified() => false,
-                        _ = sleep(Duration::from_millis(self.sending_timeout)) => true,
-                    } {
-                        Err(LocoDriveSendingError::Timeout)
-                    } else {
-                        Ok(())
-                    }
-                } else {
-                    Ok(())
-                }
+        self.raw_tap.mirror(Direction::Tx, bytes, written_at);
+        self.slots.note_written(&message);
+
+        if self.report_sent_messages {
+            // Nobody may be listening, which is fine
+            let _ = self
+                .send_to
+                .send(LocoDriveMessage::Sent(message, bytes.to_vec()));
+        }
+
+        // When successfully written, wait until the echo is received by the reading thread
+        if let Some(echoed) = echoed {
+            let timed_out = tokio::select! {
+                echoed = echoed => echoed.is_err(),
+                _ = sleep(Duration::from_millis(self.sending_timeout())) => true,
+                _ = cancel.cancelled() => return Err(LocoDriveSendingError::Cancelled),
+            };
+
+            if timed_out && self.echo_policy == EchoPolicy::Require {
+                return Err(LocoDriveSendingError::Timeout);
             }
-            Err(_) => Err(LocoDriveSendingError::NotWritable),
         }
+
+        Ok(())
+    }
+
+    /// # Returns
+    ///
+    /// The reserved system slot `message` writes to, if any. Reading slots is always allowed.
+    fn reserved_slot_written(message: &Message) -> Option<SlotArg> {
+        let slots = match *message {
+            Message::LocoSpd(slot, _)
+            | Message::LocoDirf(slot, _)
+            | Message::LocoSnd(slot, _)
+            | Message::UhliFun(slot, _)
+            | Message::ConsistFunc(slot, _)
+            | Message::SlotStat1(slot, _)
+            | Message::WrSlData(WrSlDataStructure::DataGeneral(slot, ..)) => [Some(slot), None],
+            Message::MoveSlots(src, dst)
+            | Message::LinkSlots(src, dst)
+            | Message::UnlinkSlots(src, dst) => [Some(src), Some(dst)],
+            // The fast clock and programming track data always address their reserved slots
+            Message::WrSlData(WrSlDataStructure::DataTime(..)) => [Some(SlotArg::new(123)), None],
+            Message::WrSlData(WrSlDataStructure::DataPt(..)) => [Some(SlotArg::new(124)), None],
+            _ => [None, None],
+        };
+
+        slots.iter().flatten().copied().find(SlotArg::is_reserved)
     }
-}
 
-/// Extends standard drop implementation to close the reading thread.
-impl Drop for LocoDriveController {
-    /// Handles drop Actions for the [`LocoDriveController`].
+    /// Waits until the master stopped reporting to be busy, but at most the busy hold time.
+    async fn await_master_free(&self) {
+        if self.busy_hold.is_zero() || !*self.busy.borrow() {
+            return;
+        }
+
+        let mut busy = self.busy.clone();
+        // After the hold time we try anyway, the retries care for dropped messages
+        let _ = timeout(self.busy_hold, busy.wait_for(|busy| !*busy)).await;
+    }
+
+    /// Waits until the bus was idle for the configured gap and the priority backoff of `message`.
     ///
-    /// In detail: We stop and join our reading thread on drop.
+    /// New bus activity while waiting restarts the wait.
+    async fn await_tx_gap(&self, message: &Message) {
+        let gap = self.tx_gap + self.priority_backoff * message.priority_class();
+        if gap.is_zero() {
+            return;
+        }
+
+        loop {
+            let ready_at = *self.last_activity.lock().unwrap() + gap;
+            if Instant::now() >= ready_at {
+                break;
+            }
+            sleep_until(ready_at).await;
+        }
+    }
+}
+
+/// A cheap cloneable handle to send messages through the serial port of a [`LocoDriveController`].
+///
+/// Pass clones of it to all tasks that need to send, like a GUI, an automation and scripts,
+/// without wrapping the whole controller in a Mutex.
+/// The port is granted to the waiting senders in the order they asked for it,
+/// so no task can starve the others. All attempts of one message are made without other
+/// messages in between.
+///
+/// Sending fails with [`LocoDriveSendingError::IllegalState`] once the controller was dropped.
+#[derive(Clone)]
+pub struct CommandHandle {
+    /// The writer of the controller
+    writer: Arc<Writer>,
+}
+
+impl CommandHandle {
+    /// Sends a message like [`LocoDriveController::send_message()`].
+    pub async fn send_message(&self, message: Message) -> Result<(), LocoDriveSendingError> {
+        self.send_message_with(message, SendOptions::default())
+            .await
+    }
+
+    /// Sends a message like [`LocoDriveController::send_message_with()`].
+    pub async fn send_message_with(
+        &self,
+        message: Message,
+        options: SendOptions,
+    ) -> Result<(), LocoDriveSendingError> {
+        self.send_message_acked(message, options).await?;
+        Ok(())
+    }
+
+    /// Sends a message like [`LocoDriveController::send_message_acked()`].
+    pub async fn send_message_acked(
+        &self,
+        message: Message,
+        options: SendOptions,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        self.writer
+            .send_message_acked(message, options, &CancellationToken::new())
+            .await
+    }
+
+    /// Hands a locomotive over like [`LocoDriveController::dispatch_put()`].
+    pub async fn dispatch_put(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.dispatch_put(slot).await
+    }
+
+    /// Takes a dispatched locomotive like [`LocoDriveController::dispatch_get()`].
+    pub async fn dispatch_get(&self) -> Result<Option<SlotUpdate>, LocoDriveSendingError> {
+        self.writer.dispatch_get().await
+    }
+
+    /// Releases a slot like [`LocoDriveController::release_slot()`].
+    pub async fn release_slot(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.set_slot_state(slot, State::Free).await
+    }
+
+    /// Marks a slot as common like [`LocoDriveController::set_common()`].
+    pub async fn set_common(&self, slot: SlotArg) -> Result<(), LocoDriveSendingError> {
+        self.writer.set_slot_state(slot, State::Common).await
+    }
+
+    /// Sends `message` and awaits the first received message `answer` interprets.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// The drop panics if the reading thread has panicked.
-    fn drop(&mut self) {
-        self.stop_reader()
+    /// - [`LocoDriveSendingError::Timeout`]: If no answer was received in the sending timeout
+    /// - The errors of [`LocoDriveController::send_message()`]
+    pub(crate) async fn request<T>(
+        &self,
+        message: Message,
+        answer: impl Fn(&Message) -> Option<T>,
+    ) -> Result<T, LocoDriveSendingError> {
+        self.writer.request(message, answer).await
+    }
+
+    /// Sends a message like [`LocoDriveController::send_message_cancellable()`].
+    pub async fn send_message_cancellable(
+        &self,
+        message: Message,
+        options: SendOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Ack1Arg>, LocoDriveSendingError> {
+        self.writer
+            .send_message_acked(message, options, cancel)
+            .await
+    }
+}
+
+/// Interprets `received` as answer to the [`Message::MoveSlots`], [`Message::LinkSlots`]
+/// or [`Message::UnlinkSlots`] `request`.
+///
+/// Only the data of the answered slot is accepted: The moved to slot, the source slot
+/// of a dispatch put, any slot of a dispatch get, the top slot of a link
+/// and the unlinked slot of an unlink.
+///
+/// # Returns
+///
+/// - `None`: If the message is no answer to the request
+/// - `Some(Ok(_))`: The slot data read in answer
+/// - `Some(Err(_))`: The failed acknowledgment the request was answered with
+pub(crate) fn move_answer(
+    request: &Message,
+    received: &Message,
+) -> Option<Result<SlotUpdate, Ack1Arg>> {
+    let no_slot = SlotArg::new(0);
+    let answered = match *request {
+        // The command station chooses the dispatched slot
+        Message::MoveSlots(src, _) if src == no_slot => None,
+        Message::MoveSlots(src, dst) if dst == no_slot => Some(src),
+        Message::MoveSlots(_, dst) | Message::LinkSlots(_, dst) => Some(dst),
+        Message::UnlinkSlots(src, _) => Some(src),
+        _ => None,
+    };
+
+    match *received {
+        Message::SlRdData(slot, ..) if answered.is_none_or(|answered| answered == slot) => {
+            SlotUpdate::from_message(received).map(Ok)
+        }
+        Message::LongAck(lopc, ack) if lopc.check_opc(request) && ack.failed() => Some(Err(ack)),
+        _ => None,
     }
 }
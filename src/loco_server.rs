@@ -0,0 +1,282 @@
+use crate::args::SlotArg;
+use crate::error::MessageParseError;
+pub(crate) use crate::line_protocol::format_frame;
+use crate::line_protocol::parse_frame_line;
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver,
+};
+use crate::protocol::Message;
+use crate::refresh::RefreshConsolidator;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// Serves the LoconetOverTcp protocol of LbServer to network clients,
+/// like JMRI or Rocrail, relaying between them and a [`LocoDriveController`].
+///
+/// The protocol is line based ASCII text:
+///
+/// - The server greets each client with `VERSION <name>`.
+/// - Every message read from the bus is sent to all clients as `RECEIVE` followed by
+///   the bytes of the frame in hex, like `RECEIVE 83 7C`.
+/// - A client sends a message by the line `SEND 83 7C`. The frame is validated,
+///   written to the bus and answered by `SENT OK` or `SENT ERROR <reason>`.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::loco_server::LocoServer;
+///
+/// # async fn serve(controller: LocoDriveController) -> std::io::Result<()> {
+/// let server = LocoServer::bind("0.0.0.0:1234").await?.start(&controller);
+/// println!("Serving on {}", server.local_addr());
+/// # Ok(())
+/// # }
+/// ```
+pub struct LocoServer {
+    /// The socket accepting the clients
+    listener: TcpListener,
+    /// The name the clients are greeted with
+    version: String,
+    /// Limits the slot refreshes relayed from the clients
+    refreshes: Option<Arc<RefreshConsolidator>>,
+}
+
+impl LocoServer {
+    /// Binds a server to `address`.
+    ///
+    /// # Errors
+    ///
+    /// If the address could not be bound.
+    pub async fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Ok(LocoServer {
+            listener: TcpListener::bind(address).await?,
+            version: format!("locodrive {}", env!("CARGO_PKG_VERSION")),
+            refreshes: None,
+        })
+    }
+
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Sets the name the clients are greeted with.
+    ///
+    /// Defaults to `locodrive` with the version of this crate.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Consolidates the slot refreshes of all clients with `refreshes`.
+    ///
+    /// A speed or function message repeating the current value of its slot is a refresh.
+    /// It is only written to the bus if the consolidator admits it, but always confirmed
+    /// to the client. Changes are written directly.
+    ///
+    /// Defaults to relaying all refreshes.
+    pub fn consolidate_refreshes(mut self, refreshes: Arc<RefreshConsolidator>) -> Self {
+        self.refreshes = Some(refreshes);
+        self
+    }
+
+    /// Starts accepting clients and relays between them and `controller`.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the server. Dropping it disconnects all clients and stops the server.
+    pub fn start(self, controller: &LocoDriveController) -> LocoServerHandle {
+        // The address is known, as it was bound successfully
+        let local_addr = self
+            .listener
+            .local_addr()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+        let shared = Arc::new(Shared {
+            version: self.version,
+            handle: controller.command_handle(),
+            refreshes: self.refreshes,
+            values: Mutex::new(HashMap::new()),
+        });
+
+        LocoServerHandle {
+            local_addr,
+            task: tokio::spawn(accept(self.listener, shared, controller.subscribe())),
+        }
+    }
+}
+
+/// The handle of a running [`LocoServer`].
+///
+/// Dropping the handle disconnects all clients and stops the server.
+#[derive(Debug)]
+pub struct LocoServerHandle {
+    /// The address the server is bound to
+    local_addr: SocketAddr,
+    /// The task accepting the clients
+    task: JoinHandle<()>,
+}
+
+impl LocoServerHandle {
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Extends standard drop implementation to stop the server.
+impl Drop for LocoServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The state shared by all clients of a server.
+struct Shared {
+    /// The name the clients are greeted with
+    version: String,
+    /// Writes the messages sent by the clients
+    handle: CommandHandle,
+    /// Limits the slot refreshes relayed from the clients
+    refreshes: Option<Arc<RefreshConsolidator>>,
+    /// The last speed and function messages of each slot by their op code
+    values: Mutex<HashMap<(SlotArg, u8), Message>>,
+}
+
+impl Shared {
+    /// Notes `message` as current value of its slot.
+    ///
+    /// # Returns
+    ///
+    /// If the message repeats the current value of its slot, so it is a refresh.
+    fn note(&self, message: Message) -> bool {
+        let slot = match message {
+            Message::LocoSpd(slot, _) | Message::LocoDirf(slot, _) | Message::LocoSnd(slot, _) => {
+                slot
+            }
+            _ => return false,
+        };
+
+        let key = (slot, message.opc());
+        self.values.lock().unwrap().insert(key, message) == Some(message)
+    }
+
+    /// Writes a `message` sent by a client to the bus.
+    async fn send(&self, message: Message) -> Result<(), String> {
+        let result = match &self.refreshes {
+            Some(refreshes) if self.note(message) => {
+                refreshes.refresh(&self.handle, message).await.map(|_| ())
+            }
+            _ => self.handle.send_message(message).await,
+        };
+        result.map_err(|err| err.to_string())
+    }
+}
+
+/// Accepts clients on `listener` until the controller is dropped.
+async fn accept(listener: TcpListener, shared: Arc<Shared>, mut messages: LocoDriveReceiver) {
+    let mut clients = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    log_info!("Client {} connected", address);
+                    clients.spawn(serve(stream, address, shared.clone(), messages.resubscribe()));
+                }
+                Err(err) => log_error!("Could not accept a client: {}", err),
+            },
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    shared.note(message);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            // Reap the finished clients
+            Some(_) = clients.join_next(), if !clients.is_empty() => {}
+        }
+    }
+}
+
+/// Relays between the client connected by `stream` and the bus, until one of them closes.
+async fn serve(
+    stream: TcpStream,
+    address: SocketAddr,
+    shared: Arc<Shared>,
+    mut messages: LocoDriveReceiver,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    if let Err(err) = writer
+        .write_all(format!("VERSION {}\r\n", shared.version).as_bytes())
+        .await
+    {
+        log_error!("Could not greet client {}: {}", address, err);
+        return;
+    }
+
+    loop {
+        let reply = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => match parse_command(&line) {
+                    Some(Ok(message)) => match shared.send(message).await {
+                        Ok(()) => "SENT OK".to_string(),
+                        Err(err) => format!("SENT ERROR {}", err),
+                    },
+                    Some(Err(err)) => format!("SENT ERROR {}", err),
+                    None if line.trim().is_empty() => continue,
+                    None => "ERROR unknown command".to_string(),
+                },
+                Ok(None) => break,
+                Err(err) => {
+                    log_error!("Could not read from client {}: {}", address, err);
+                    break;
+                }
+            },
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    format_frame("RECEIVE", &message.to_message())
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(lost)) => {
+                    log_error!("Client {} lost {} messages", address, lost);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        if let Err(err) = writer.write_all(format!("{}\r\n", reply).as_bytes()).await {
+            log_error!("Could not write to client {}: {}", address, err);
+            break;
+        }
+    }
+
+    log_info!("Client {} disconnected", address);
+}
+
+/// Parses a `line` sent by a client.
+///
+/// # Returns
+///
+/// The message of a `SEND` command or `None` if the line is no `SEND` command.
+///
+/// # Errors
+///
+/// If the bytes of the `SEND` command are no valid message.
+pub(crate) fn parse_command(line: &str) -> Option<Result<Message, MessageParseError>> {
+    parse_frame_line("SEND", line)
+}
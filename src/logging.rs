@@ -0,0 +1,38 @@
+//! Logging of the crate, routed through `tracing` if the `tracing` feature is active
+//! and printed to the standard output otherwise.
+
+/// Logs an informational event.
+macro_rules! log_info {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        println!("[locodrive:INFO] {}", format_args!($($arg)+));
+    }};
+}
+
+/// Logs an error event.
+macro_rules! log_error {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::error!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("[locodrive:ERROR] {}", format_args!($($arg)+));
+    }};
+}
+
+/// Logs a message level debug event. Only logged if the `tracing` feature is active.
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)+);
+    }};
+}
+
+/// Logs a byte level trace event. Only logged if the `tracing` feature is active.
+macro_rules! log_trace {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)+);
+    }};
+}
@@ -0,0 +1,630 @@
+use crate::args::{
+    AddressArg, DirfArg, ReceiverType, SensorLevel, SlotArg, SnArg, SndArg, SourceType, SpeedArg,
+    Stat1Arg, SwitchArg, SwitchDirection, ThrottleStatusArg, WrSlDataStructure,
+};
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Tracks a part of the model railroads state from the messages on the bus.
+///
+/// Managers are fed live with every received message or offline with a recorded capture,
+/// so the state at the end of a session can be reconstructed without the hardware attached.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{SlotArg, SpeedArg};
+/// use locodrive::manager::{Manager, SlotManager};
+/// use locodrive::protocol::Message;
+///
+/// let capture = vec![
+///     Message::LocoSpd(SlotArg::new(3), SpeedArg::Drive(40)),
+///     Message::LocoSpd(SlotArg::new(3), SpeedArg::Stop),
+/// ];
+///
+/// let mut slots = SlotManager::new();
+/// slots.replay(&capture);
+///
+/// assert_eq!(slots.slot(SlotArg::new(3)).unwrap().speed, Some(SpeedArg::Stop));
+/// ```
+pub trait Manager {
+    /// Updates the tracked state by one `message`. Messages not concerning it are ignored.
+    fn handle(&mut self, message: &Message);
+
+    /// Feeds all messages of the recorded `capture` in order through this manager.
+    fn replay<'a, I>(&mut self, capture: I)
+    where
+        I: IntoIterator<Item = &'a Message>,
+        Self: Sized,
+    {
+        for message in capture {
+            self.handle(message);
+        }
+    }
+}
+
+/// The known state of one slot. Values not observed yet are `None`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlotState {
+    /// The address of the locomotive in the slot
+    pub address: Option<AddressArg>,
+    /// The status of the slot
+    pub status: Option<Stat1Arg>,
+    /// The speed of the locomotive
+    pub speed: Option<SpeedArg>,
+    /// The direction and the first functions of the locomotive
+    pub dirf: Option<DirfArg>,
+    /// The sound functions of the locomotive
+    pub snd: Option<SndArg>,
+}
+
+/// Tracks the state of the command stations slots.
+///
+/// The state is taken from read and written slot data,
+/// as well as from speed, function and status updates.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct SlotManager {
+    /// The state of each observed slot
+    slots: HashMap<SlotArg, SlotState>,
+}
+
+impl SlotManager {
+    /// Creates a manager without any known slot.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The known state of `slot`, if it was observed.
+    pub fn slot(&self, slot: SlotArg) -> Option<&SlotState> {
+        self.slots.get(&slot)
+    }
+
+    /// # Returns
+    ///
+    /// The known states of all observed slots.
+    pub fn slots(&self) -> &HashMap<SlotArg, SlotState> {
+        &self.slots
+    }
+
+    /// Sets the known `state` of `slot`, like restored from a saved session.
+    pub fn set(&mut self, slot: SlotArg, state: SlotState) {
+        self.slots.insert(slot, state);
+    }
+}
+
+impl Manager for SlotManager {
+    fn handle(&mut self, message: &Message) {
+        match *message {
+            Message::SlRdData(slot, status, address, speed, dirf, _, _, snd, _)
+            | Message::WrSlData(WrSlDataStructure::DataGeneral(
+                slot,
+                status,
+                _,
+                address,
+                speed,
+                dirf,
+                _,
+                snd,
+                _,
+            )) => {
+                self.slots.insert(
+                    slot,
+                    SlotState {
+                        address: Some(address),
+                        status: Some(status),
+                        speed: Some(speed),
+                        dirf: Some(dirf),
+                        snd: Some(snd),
+                    },
+                );
+            }
+            Message::SlotStat1(slot, status) => {
+                self.slots.entry(slot).or_default().status = Some(status)
+            }
+            Message::LocoSpd(slot, speed) => {
+                self.slots.entry(slot).or_default().speed = Some(speed)
+            }
+            Message::LocoDirf(slot, dirf) => self.slots.entry(slot).or_default().dirf = Some(dirf),
+            Message::LocoSnd(slot, snd) => self.slots.entry(slot).or_default().snd = Some(snd),
+            _ => {}
+        }
+    }
+}
+
+/// Tracks the directions of the switches.
+///
+/// The direction is taken from switch requests and from output reports
+/// telling which part of a switch is active.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct SwitchManager {
+    /// The direction of each observed switch by its address
+    switches: HashMap<u16, SwitchDirection>,
+}
+
+impl SwitchManager {
+    /// Creates a manager without any known switch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The known direction of the switch with `address`, if it was observed.
+    pub fn direction(&self, address: u16) -> Option<SwitchDirection> {
+        self.switches.get(&address).copied()
+    }
+
+    /// # Returns
+    ///
+    /// The known directions of all observed switches by their address.
+    pub fn switches(&self) -> &HashMap<u16, SwitchDirection> {
+        &self.switches
+    }
+}
+
+impl Manager for SwitchManager {
+    fn handle(&mut self, message: &Message) {
+        match *message {
+            Message::SwReq(switch) => {
+                self.switches.insert(switch.address(), switch.direction());
+            }
+            Message::SwRep(SnArg::SwitchDirectionStatus(address, straight, curved)) => {
+                match (straight, curved) {
+                    (SensorLevel::High, SensorLevel::Low) => {
+                        self.switches.insert(address, SwitchDirection::Straight);
+                    }
+                    (SensorLevel::Low, SensorLevel::High) => {
+                        self.switches.insert(address, SwitchDirection::Curved);
+                    }
+                    // No or both parts active tells no direction
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where the direction of a turnout was learned from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TurnoutSource {
+    /// A switch request commanded the direction.
+    Commanded,
+    /// The turnout reported the direction by its output feedback.
+    Reported,
+    /// The command station answered a state query with the direction.
+    Queried,
+}
+
+/// A change of the known direction of a turnout.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TurnoutChange {
+    /// The address of the turnout
+    pub address: u16,
+    /// The new direction of the turnout
+    pub direction: SwitchDirection,
+    /// Where the direction was learned from
+    pub source: TurnoutSource,
+}
+
+/// The known state of one turnout. Values not observed yet are `None`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct TurnoutState {
+    /// The direction last commanded by a switch request
+    pub commanded: Option<SwitchDirection>,
+    /// The direction last reported by feedback or a state query
+    pub reported: Option<SwitchDirection>,
+}
+
+impl TurnoutState {
+    /// # Returns
+    ///
+    /// The direction of the turnout. Reported feedback is trusted over the commanded direction,
+    /// until the turnout is commanded again.
+    pub fn direction(&self) -> Option<SwitchDirection> {
+        self.reported.or(self.commanded)
+    }
+
+    /// # Returns
+    ///
+    /// If the feedback of the turnout contradicts its commanded direction,
+    /// as it did not move yet or is blocked.
+    pub fn mismatched(&self) -> bool {
+        matches!((self.commanded, self.reported), (Some(commanded), Some(reported)) if commanded != reported)
+    }
+}
+
+/// Caches the direction of every turnout, reconciling the commanded directions with the feedback.
+///
+/// Switch requests set the commanded direction and clear the reported one, as the turnout is moving.
+/// Output reports and answers to [`TurnoutTable::query()`] set the reported direction.
+/// [`TurnoutTable::update()`] tells whenever the known direction of a turnout changes.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct TurnoutTable {
+    /// The state of each observed turnout by its address
+    turnouts: HashMap<u16, TurnoutState>,
+    /// The turnout whose state query awaits its answer
+    query: Option<SwitchArg>,
+}
+
+impl TurnoutTable {
+    /// Creates a table without any known turnout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The message asking the command station for the direction of the turnout with `address`.
+    /// The answer updates the table, when handled.
+    pub fn query(address: u16) -> Message {
+        Message::SwState(SwitchArg::new(address, SwitchDirection::Straight, false))
+    }
+
+    /// # Returns
+    ///
+    /// The known direction of the turnout with `address`, see [`TurnoutState::direction()`].
+    pub fn state(&self, address: u16) -> Option<SwitchDirection> {
+        self.turnouts
+            .get(&address)
+            .and_then(TurnoutState::direction)
+    }
+
+    /// # Returns
+    ///
+    /// The known state of the turnout with `address`, if it was observed.
+    pub fn turnout(&self, address: u16) -> Option<&TurnoutState> {
+        self.turnouts.get(&address)
+    }
+
+    /// # Returns
+    ///
+    /// The known state of all observed turnouts by their address.
+    pub fn turnouts(&self) -> &HashMap<u16, TurnoutState> {
+        &self.turnouts
+    }
+
+    /// # Returns
+    ///
+    /// The addresses of the turnouts whose feedback contradicts their commanded direction.
+    pub fn mismatched(&self) -> Vec<u16> {
+        self.turnouts
+            .iter()
+            .filter(|(_, turnout)| turnout.mismatched())
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    /// Updates the table by one `message` like [`Manager::handle()`].
+    ///
+    /// # Returns
+    ///
+    /// The change of the known direction of a turnout caused by the message.
+    pub fn update(&mut self, message: &Message) -> Option<TurnoutChange> {
+        let (address, direction, source) = match *message {
+            Message::SwReq(switch) => (
+                switch.address(),
+                switch.direction(),
+                TurnoutSource::Commanded,
+            ),
+            Message::SwRep(SnArg::SwitchDirectionStatus(address, straight, curved)) => {
+                let direction = match (straight, curved) {
+                    (SensorLevel::High, SensorLevel::Low) => SwitchDirection::Straight,
+                    (SensorLevel::Low, SensorLevel::High) => SwitchDirection::Curved,
+                    // No or both parts active tells no direction
+                    _ => return None,
+                };
+                (address, direction, TurnoutSource::Reported)
+            }
+            Message::SwState(switch) => {
+                self.query = Some(switch);
+                return None;
+            }
+            Message::LongAck(lopc, ack) => match self.query {
+                Some(switch) if lopc.check_opc(&Message::SwState(switch)) => {
+                    self.query = None;
+                    // The command station answers a straight turnout with bit 5 set
+                    let direction = if ack.ack1() & 0x20 != 0 {
+                        SwitchDirection::Straight
+                    } else {
+                        SwitchDirection::Curved
+                    };
+                    (switch.address(), direction, TurnoutSource::Queried)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let turnout = self.turnouts.entry(address).or_default();
+        let before = turnout.direction();
+        match source {
+            TurnoutSource::Commanded => {
+                turnout.commanded = Some(direction);
+                turnout.reported = None;
+            }
+            TurnoutSource::Reported | TurnoutSource::Queried => turnout.reported = Some(direction),
+        }
+
+        if before == Some(direction) {
+            None
+        } else {
+            Some(TurnoutChange {
+                address,
+                direction,
+                source,
+            })
+        }
+    }
+}
+
+impl Manager for TurnoutTable {
+    fn handle(&mut self, message: &Message) {
+        self.update(message);
+    }
+}
+
+/// How the reports of one sensor are interpreted by the [`SensorManager`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct SensorConfig {
+    /// How long a new level has to be reported without bouncing back before it is accepted.
+    /// Defaults to accepting each level at once.
+    pub debounce: Duration,
+    /// Whether the sensor reports [`SensorLevel::Low`] when it is active, like a light barrier.
+    /// Defaults to not inverted.
+    pub inverted: bool,
+    /// Which input of the DS54 address reports the sensor, the other input is ignored.
+    /// Defaults to both the aux and the switch input.
+    pub source: Option<SourceType>,
+}
+
+/// A change of the level of a sensor, after its [`SensorConfig`] was applied.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SensorChange {
+    /// The address of the sensor
+    pub address: u16,
+    /// The new level of the sensor
+    pub level: SensorLevel,
+}
+
+/// Tracks the levels of the sensors.
+///
+/// Each sensor may be configured by a [`SensorConfig`] to invert its level, to listen to only
+/// one input of its DS54 address and to debounce its reports. A debounced sensor changes its level
+/// only after the new level was reported for the debounce time without bouncing back.
+/// Call [`SensorManager::poll()`] until [`SensorManager::next_deadline()`] to receive the changes
+/// confirmed after the debounce time, even if no further message is received.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{InArg, SensorLevel, SourceType};
+/// use locodrive::manager::{SensorChange, SensorConfig, SensorManager};
+/// use locodrive::protocol::Message;
+/// use std::time::{Duration, Instant};
+///
+/// let mut sensors = SensorManager::new();
+/// sensors.configure(
+///     7,
+///     SensorConfig {
+///         debounce: Duration::from_millis(100),
+///         inverted: true,
+///         ..SensorConfig::default()
+///     },
+/// );
+///
+/// // The light barrier is free, until it is interrupted and bounces back once
+/// sensors.set(7, SensorLevel::Low);
+/// let blocked = Message::InputRep(InArg::new(7, SourceType::Switch, SensorLevel::Low, false));
+/// let free = Message::InputRep(InArg::new(7, SourceType::Switch, SensorLevel::High, false));
+/// let now = Instant::now();
+/// assert!(sensors.update(&blocked, now).is_empty());
+/// assert!(sensors.update(&free, now + Duration::from_millis(10)).is_empty());
+/// assert!(sensors.update(&blocked, now + Duration::from_millis(20)).is_empty());
+///
+/// // The level is accepted after the debounce time
+/// let changes = sensors.poll(now + Duration::from_millis(120));
+/// assert_eq!(changes, vec![SensorChange { address: 7, level: SensorLevel::High }]);
+/// assert_eq!(sensors.level(7), Some(SensorLevel::High));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct SensorManager {
+    /// The level of each observed sensor by its address
+    sensors: HashMap<u16, SensorLevel>,
+    /// The configuration of each configured sensor by its address
+    configs: HashMap<u16, SensorConfig>,
+    /// The levels awaiting their debounce time with since when they are reported
+    pending: HashMap<u16, (SensorLevel, Instant)>,
+}
+
+impl SensorManager {
+    /// Creates a manager without any known sensor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures how the reports of the sensor with `address` are interpreted.
+    pub fn configure(&mut self, address: u16, config: SensorConfig) {
+        self.configs.insert(address, config);
+    }
+
+    /// # Returns
+    ///
+    /// The configuration of the sensor with `address`.
+    pub fn config(&self, address: u16) -> SensorConfig {
+        self.configs.get(&address).copied().unwrap_or_default()
+    }
+
+    /// # Returns
+    ///
+    /// The known level of the sensor with `address`, if it was observed.
+    pub fn level(&self, address: u16) -> Option<SensorLevel> {
+        self.sensors.get(&address).copied()
+    }
+
+    /// # Returns
+    ///
+    /// The known levels of all observed sensors by their address.
+    pub fn sensors(&self) -> &HashMap<u16, SensorLevel> {
+        &self.sensors
+    }
+
+    /// Sets the known `level` of the sensor with `address`, like restored from a saved session.
+    pub fn set(&mut self, address: u16, level: SensorLevel) {
+        self.pending.remove(&address);
+        self.sensors.insert(address, level);
+    }
+
+    /// # Returns
+    ///
+    /// When the next level is confirmed, if any sensor awaits its debounce time.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .iter()
+            .map(|(address, (_, since))| *since + self.config(*address).debounce)
+            .min()
+    }
+
+    /// Updates the sensors by one `message` received at `now` like [`Manager::handle()`].
+    ///
+    /// # Returns
+    ///
+    /// The changes of the sensors confirmed until `now`.
+    pub fn update(&mut self, message: &Message, now: Instant) -> Vec<SensorChange> {
+        let input = match *message {
+            Message::InputRep(input) => input,
+            _ => return self.poll(now),
+        };
+        let address = input.address();
+        let config = self.config(address);
+        if config
+            .source
+            .is_some_and(|source| source != input.input_source())
+        {
+            return self.poll(now);
+        }
+
+        let level = match config.inverted {
+            true => !input.sensor_level(),
+            false => input.sensor_level(),
+        };
+        if self.level(address) == Some(level) {
+            // The sensor bounced back before its new level was confirmed
+            self.pending.remove(&address);
+        } else if self.pending.get(&address).map(|(pending, _)| *pending) != Some(level) {
+            self.pending.insert(address, (level, now));
+        }
+
+        self.poll(now)
+    }
+
+    /// # Returns
+    ///
+    /// The changes of the sensors confirmed until `now`, as their new level was reported
+    /// for the debounce time.
+    pub fn poll(&mut self, now: Instant) -> Vec<SensorChange> {
+        let configs = &self.configs;
+        let mut confirmed: Vec<SensorChange> = self
+            .pending
+            .iter()
+            .filter(|(address, (_, since))| {
+                let debounce = configs.get(address).copied().unwrap_or_default().debounce;
+                now.saturating_duration_since(*since) >= debounce
+            })
+            .map(|(address, (level, _))| SensorChange {
+                address: *address,
+                level: *level,
+            })
+            .collect();
+        confirmed.sort_by_key(|change| change.address);
+
+        for change in &confirmed {
+            self.pending.remove(&change.address);
+            self.sensors.insert(change.address, change.level);
+        }
+        confirmed
+    }
+}
+
+impl Manager for SensorManager {
+    fn handle(&mut self, message: &Message) {
+        self.update(message, Instant::now());
+    }
+}
+
+/// Tracks the throttles attached to the infrared and radio receivers, like the UR90 and UR92.
+///
+/// Send [`ThrottleNetwork::query()`] to have the receivers report all attached throttles.
+/// Throttles are reported again on each status change, so the battery and semaphore status stays current.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct ThrottleNetwork {
+    /// The last status of each observed throttle by its id
+    throttles: HashMap<u16, ThrottleStatusArg>,
+}
+
+impl ThrottleNetwork {
+    /// Creates a network without any known throttle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The message asking the receivers to report their attached throttles.
+    pub fn query() -> Message {
+        Message::ReceiverQuery
+    }
+
+    /// # Returns
+    ///
+    /// The last status of the throttle with `throttle_id`, if it was observed.
+    pub fn throttle(&self, throttle_id: u16) -> Option<&ThrottleStatusArg> {
+        self.throttles.get(&throttle_id)
+    }
+
+    /// # Returns
+    ///
+    /// The last status of all observed throttles by their id.
+    pub fn throttles(&self) -> &HashMap<u16, ThrottleStatusArg> {
+        &self.throttles
+    }
+
+    /// # Returns
+    ///
+    /// The ids of the throttles attached to receivers of type `receiver`.
+    pub fn attached_to(&self, receiver: ReceiverType) -> Vec<u16> {
+        self.throttles
+            .values()
+            .filter(|throttle| throttle.receiver() == receiver)
+            .map(|throttle| throttle.throttle_id())
+            .collect()
+    }
+
+    /// # Returns
+    ///
+    /// The ids of the throttles reporting a low battery.
+    pub fn low_battery(&self) -> Vec<u16> {
+        self.throttles
+            .values()
+            .filter(|throttle| throttle.battery_low())
+            .map(|throttle| throttle.throttle_id())
+            .collect()
+    }
+}
+
+impl Manager for ThrottleNetwork {
+    fn handle(&mut self, message: &Message) {
+        match *message {
+            // The receivers report all attached throttles again
+            Message::ReceiverQuery => self.throttles.clear(),
+            Message::ThrottleStatus(throttle) => {
+                self.throttles.insert(throttle.throttle_id(), throttle);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::args::{AddressArg, InArg, SlotArg, SpeedArg, SwitchArg};
+use crate::error::MessageParseError;
+use crate::protocol::Message;
+
+/// A validated frame borrowed from a read buffer, whose fields are decoded only when asked for.
+///
+/// Creating the view only checks the length, op code and checksum of the frame.
+/// Consumers inspecting one or two fields of each frame, like monitors and filters,
+/// so skip decoding the whole message. Use [`MessageRef::decode()`] to get the full [`Message`].
+///
+/// # Example
+///
+/// ```
+/// use locodrive::message_ref::MessageRef;
+///
+/// // The speed of slot 3 is set to 31
+/// let frame = [0xA0, 0x03, 0x20, 0x7C];
+/// let message = MessageRef::new(&frame).unwrap();
+///
+/// assert_eq!(message.opc(), 0xA0);
+/// assert_eq!(message.slot().map(|slot| slot.slot()), Some(3));
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct MessageRef<'a> {
+    /// The bytes of the frame including its checksum
+    frame: &'a [u8],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Validates the frame at the start of `buf` without decoding it.
+    /// Bytes after the frame are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - [`MessageParseError::UnexpectedEnd`]: If `buf` is shorter than the frame
+    /// - [`MessageParseError::UnknownOpcode`]: If the op code is not known
+    /// - [`MessageParseError::InvalidChecksum`]: If the checksum is not valid
+    #[inline]
+    pub fn new(buf: &'a [u8]) -> Result<Self, MessageParseError> {
+        let opc = match buf.first() {
+            Some(opc) => *opc,
+            None => return Err(MessageParseError::unexpected_end(0x00, 0)),
+        };
+        let len = match opc & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            0xE0 if buf.len() > 1 => buf[1] as usize,
+            0xE0 => return Err(MessageParseError::unexpected_end(opc, 1).with_frame(buf)),
+            _ => return Err(MessageParseError::unknown_opcode(opc).with_frame(&buf[..1])),
+        };
+
+        if len < 2 {
+            return Err(MessageParseError::unexpected_end(opc, 1).with_frame(&buf[..2]));
+        }
+        if buf.len() < len {
+            return Err(MessageParseError::unexpected_end(opc, buf.len()).with_frame(buf));
+        }
+        if !Message::known_opc(opc) {
+            return Err(MessageParseError::unknown_opcode(opc).with_frame(&buf[..1]));
+        }
+        if buf[..len].iter().fold(0, |acc, &b| acc ^ b) != 0xFF {
+            return Err(MessageParseError::invalid_checksum(opc, len - 1).with_frame(&buf[..len]));
+        }
+
+        Ok(MessageRef { frame: &buf[..len] })
+    }
+
+    /// # Returns
+    ///
+    /// The op code of the frame.
+    #[inline]
+    pub fn opc(&self) -> u8 {
+        self.frame[0]
+    }
+
+    /// # Returns
+    ///
+    /// The bytes of the frame including its checksum.
+    #[inline]
+    pub fn bytes(&self) -> &'a [u8] {
+        self.frame
+    }
+
+    /// # Returns
+    ///
+    /// The bytes between the op code and the checksum of the frame,
+    /// starting with the length byte for variable length messages.
+    #[inline]
+    pub fn args(&self) -> &'a [u8] {
+        &self.frame[1..self.frame.len() - 1]
+    }
+
+    /// Decodes the whole message.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Message::parse()`], if the arguments of the frame are not valid.
+    #[inline]
+    pub fn decode(&self) -> Result<Message, MessageParseError> {
+        Message::parse(self.frame)
+    }
+
+    /// # Returns
+    ///
+    /// The slot of [`Message::LocoSpd`], [`Message::LocoDirf`], [`Message::LocoSnd`],
+    /// [`Message::SlotStat1`], [`Message::ConsistFunc`], [`Message::RqSlData`],
+    /// [`Message::UhliFun`] and [`Message::SlRdData`], the source slot of [`Message::MoveSlots`]
+    /// and the first slot of [`Message::LinkSlots`] and [`Message::UnlinkSlots`].
+    #[inline]
+    pub fn slot(&self) -> Option<SlotArg> {
+        match self.opc() {
+            0xA0 | 0xA1 | 0xA2 | 0xB5 | 0xB6 | 0xBB | 0xBA | 0xB9 | 0xB8 => {
+                Some(SlotArg::parse(self.frame[1]))
+            }
+            0xD4 => Some(SlotArg::parse(self.frame[2])),
+            _ if self.is_slot_data() => Some(SlotArg::parse(self.frame[2])),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The speed of [`Message::LocoSpd`] and [`Message::SlRdData`].
+    #[inline]
+    pub fn speed(&self) -> Option<SpeedArg> {
+        match self.opc() {
+            0xA0 => Some(SpeedArg::parse(self.frame[2])),
+            _ if self.is_slot_data() => Some(SpeedArg::parse(self.frame[5])),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The locomotive address of [`Message::LocoAdr`] and [`Message::SlRdData`].
+    #[inline]
+    pub fn address(&self) -> Option<AddressArg> {
+        match self.opc() {
+            0xBF => Some(AddressArg::parse(self.frame[1], self.frame[2])),
+            _ if self.is_slot_data() => Some(AddressArg::parse(self.frame[9], self.frame[4])),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The switch of [`Message::SwReq`], [`Message::SwState`] and [`Message::SwAck`].
+    #[inline]
+    pub fn switch(&self) -> Option<SwitchArg> {
+        match self.opc() {
+            0xB0 | 0xBC | 0xBD => Some(SwitchArg::parse(self.frame[1], self.frame[2])),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The sensor report of [`Message::InputRep`].
+    #[inline]
+    pub fn sensor(&self) -> Option<InArg> {
+        match self.opc() {
+            0xB2 => Some(InArg::parse(self.frame[1], self.frame[2])),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// If the frame is decoded to [`Message::SlRdData`].
+    #[inline]
+    fn is_slot_data(&self) -> bool {
+        self.opc() == 0xE7 && self.frame.len() == 14 && self.frame[2] & 0x7F != 0x7C
+    }
+}
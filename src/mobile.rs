@@ -0,0 +1,363 @@
+use crate::args::{
+    AddressArg, DirfArg, SensorLevel, SlotArg, SndArg, SpeedArg, SwitchArg, SwitchDirection,
+};
+use crate::error::MobileError;
+use crate::loco_controller::{CommandHandle, LocoDriveController, LocoDriveMessage};
+use crate::manager::{Manager, SensorManager, SlotManager, TurnoutTable};
+use crate::protocol::Message;
+use crate::slot_cache::SlotResolver;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// Is told about changes of the layout, implemented by the Kotlin or Swift app.
+///
+/// It is called from a background thread, so apps have to switch to their ui thread themselves.
+#[uniffi::export(callback_interface)]
+pub trait LayoutListener: Send + Sync {
+    /// The sensor at `address` changed to `occupied`.
+    fn sensor_changed(&self, address: u16, occupied: bool);
+
+    /// The turnout at `address` changed to `straight`.
+    fn turnout_changed(&self, address: u16, straight: bool);
+}
+
+/// The layout state tracked from the messages on the bus, shared with the updating task.
+#[derive(Default)]
+struct Layout {
+    /// The known slots, used by the throttles
+    slots: Mutex<SlotManager>,
+    /// The known turnouts
+    turnouts: Mutex<TurnoutTable>,
+    /// The known sensors
+    sensors: Mutex<SensorManager>,
+    /// The listener told about turnout and sensor changes
+    listener: Mutex<Option<Box<dyn LayoutListener>>>,
+}
+
+impl Layout {
+    /// Updates the tracked state by `message` and tells the listener about changes.
+    fn handle(&self, message: &Message) {
+        self.slots.lock().unwrap().handle(message);
+
+        let turnout = self.turnouts.lock().unwrap().update(message);
+        let sensor = match *message {
+            Message::InputRep(input) => {
+                let mut sensors = self.sensors.lock().unwrap();
+                let previous = sensors.level(input.address());
+                sensors.handle(message);
+                match previous != Some(input.sensor_level()) {
+                    true => Some(input),
+                    false => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(listener) = self.listener.lock().unwrap().as_ref() {
+            if let Some(change) = turnout {
+                listener.turnout_changed(
+                    change.address,
+                    change.direction == SwitchDirection::Straight,
+                );
+            }
+            if let Some(input) = sensor {
+                listener.sensor_changed(input.address(), input.sensor_level() == SensorLevel::High);
+            }
+        }
+    }
+}
+
+/// Connects a throttle app to the model railroad, exposed to Kotlin and Swift by uniffi.
+///
+/// The controller tracks the slots, turnouts and sensors in the background,
+/// so apps only acquire [`Throttle`]s and switch turnouts without managing slots themselves.
+#[derive(uniffi::Object)]
+pub struct MobileController {
+    /// The controller talking to the model railroad, kept alive for the handles
+    _controller: LocoDriveController,
+    /// Sends the messages of the controller and its throttles
+    commands: CommandHandle,
+    /// Resolves the slots of the acquired locomotives
+    resolver: SlotResolver,
+    /// The tracked layout state
+    layout: Arc<Layout>,
+    /// The task updating the layout state
+    updater: JoinHandle<()>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MobileController {
+    /// Connects to the serial port `port_name` with `baud_rate`.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Connect`]: If the port could not be opened
+    #[uniffi::constructor]
+    pub async fn connect(port_name: String, baud_rate: u32) -> Result<Arc<Self>, MobileError> {
+        let controller = LocoDriveController::builder(&port_name, baud_rate)
+            .build()
+            .await
+            .map_err(|err| MobileError::Connect(err.to_string()))?;
+        Ok(Arc::new(MobileController::new(controller)))
+    }
+
+    /// Acquires the slot of the locomotive at `address`, requesting it from the command station if needed.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the slot could not be requested
+    pub async fn acquire(&self, address: u16) -> Result<Arc<Throttle>, MobileError> {
+        let address = AddressArg::new(address);
+        let slot = self.resolver.slot_for(address).await?;
+        Ok(Arc::new(Throttle {
+            address,
+            slot,
+            commands: self.commands.clone(),
+            layout: self.layout.clone(),
+        }))
+    }
+
+    /// Switches the turnout at `address` to `straight` or curved.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the request could not be send
+    pub async fn set_turnout(&self, address: u16, straight: bool) -> Result<(), MobileError> {
+        let direction = match straight {
+            true => SwitchDirection::Straight,
+            false => SwitchDirection::Curved,
+        };
+        let message = Message::SwReq(SwitchArg::new(address, direction, true));
+        self.commands.send_message(message).await?;
+        Ok(())
+    }
+
+    /// Switches the track power on or off.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the request could not be send
+    pub async fn set_power(&self, on: bool) -> Result<(), MobileError> {
+        let message = match on {
+            true => Message::GpOn,
+            false => Message::GpOff,
+        };
+        self.commands.send_message(message).await?;
+        Ok(())
+    }
+
+    /// # Returns
+    ///
+    /// Whether the turnout at `address` is straight, if known.
+    pub fn turnout(&self, address: u16) -> Option<bool> {
+        let turnouts = self.layout.turnouts.lock().unwrap();
+        turnouts
+            .state(address)
+            .map(|direction| direction == SwitchDirection::Straight)
+    }
+
+    /// # Returns
+    ///
+    /// Whether the sensor at `address` is occupied, if known.
+    pub fn sensor(&self, address: u16) -> Option<bool> {
+        let sensors = self.layout.sensors.lock().unwrap();
+        sensors
+            .level(address)
+            .map(|level| level == SensorLevel::High)
+    }
+
+    /// Sets the `listener` told about turnout and sensor changes, replacing the one set before.
+    pub fn set_listener(&self, listener: Box<dyn LayoutListener>) {
+        *self.layout.listener.lock().unwrap() = Some(listener);
+    }
+}
+
+impl MobileController {
+    /// Tracks the layout with the messages received by `controller`.
+    ///
+    /// Needs to be called on a tokio runtime.
+    pub fn new(controller: LocoDriveController) -> Self {
+        let layout = Arc::new(Layout::default());
+        let mut messages = controller.subscribe();
+        let updated = layout.clone();
+        let updater = tokio::spawn(async move {
+            loop {
+                match messages.recv().await {
+                    Ok(LocoDriveMessage::Message(message))
+                    | Ok(LocoDriveMessage::Echo(message)) => updated.handle(&message),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        MobileController {
+            commands: controller.command_handle(),
+            resolver: SlotResolver::new(&controller),
+            _controller: controller,
+            layout,
+            updater,
+        }
+    }
+}
+
+impl Drop for MobileController {
+    fn drop(&mut self) {
+        self.updater.abort();
+    }
+}
+
+/// Drives the locomotive in an acquired slot, exposed to Kotlin and Swift by uniffi.
+///
+/// The speed, direction and functions are read from the tracked slot,
+/// so changes by other throttles are kept.
+#[derive(uniffi::Object)]
+pub struct Throttle {
+    /// The address of the locomotive
+    address: AddressArg,
+    /// The slot of the locomotive
+    slot: SlotArg,
+    /// Sends the messages of the throttle
+    commands: CommandHandle,
+    /// The tracked layout state
+    layout: Arc<Layout>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Throttle {
+    /// # Returns
+    ///
+    /// The address of the locomotive.
+    pub fn address(&self) -> u16 {
+        self.address.address()
+    }
+
+    /// # Returns
+    ///
+    /// The slot of the locomotive.
+    pub fn slot(&self) -> u8 {
+        self.slot.slot()
+    }
+
+    /// # Returns
+    ///
+    /// The speed step of the locomotive, zero if stopped.
+    pub fn speed(&self) -> u8 {
+        self.state().0.map_or(0, |speed| speed.get_spd())
+    }
+
+    /// # Returns
+    ///
+    /// Whether the locomotive drives forwards.
+    pub fn forward(&self) -> bool {
+        self.state().1.dir()
+    }
+
+    /// # Returns
+    ///
+    /// Whether the `function` is on. Unsupported functions are off.
+    pub fn function(&self, function: u8) -> bool {
+        let (_, dirf, snd) = self.state();
+        match function {
+            0..=4 => dirf.f(function),
+            5..=8 => snd.f(function),
+            _ => false,
+        }
+    }
+
+    /// Sets the speed step of the locomotive. Steps above 126 are capped.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the speed could not be send
+    pub async fn set_speed(&self, speed: u8) -> Result<(), MobileError> {
+        let speed = SpeedArg::new(speed.min(126));
+        self.send(Message::LocoSpd(self.slot, speed)).await
+    }
+
+    /// Stops the locomotive, immediately if `emergency` is set.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the stop could not be send
+    pub async fn stop(&self, emergency: bool) -> Result<(), MobileError> {
+        let speed = match emergency {
+            true => SpeedArg::EmergencyStop,
+            false => SpeedArg::Stop,
+        };
+        self.send(Message::LocoSpd(self.slot, speed)).await
+    }
+
+    /// Sets the driving direction of the locomotive.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the direction could not be send
+    pub async fn set_forward(&self, forward: bool) -> Result<(), MobileError> {
+        let (_, mut dirf, _) = self.state();
+        dirf.set_dir(forward);
+        self.send(Message::LocoDirf(self.slot, dirf)).await
+    }
+
+    /// Switches the `function` on or off. The functions 0 to 4 are send with the direction,
+    /// 5 to 8 with the sound.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::UnknownFunction`]: If the function is above 8
+    /// - [`MobileError::Sending`]: If the function could not be send
+    pub async fn set_function(&self, function: u8, on: bool) -> Result<(), MobileError> {
+        let (_, mut dirf, mut snd) = self.state();
+        let message = match function {
+            0..=4 => {
+                dirf.set_f(function, on);
+                Message::LocoDirf(self.slot, dirf)
+            }
+            5..=8 => {
+                snd.set_f(function, on);
+                Message::LocoSnd(self.slot, snd)
+            }
+            _ => return Err(MobileError::UnknownFunction(function)),
+        };
+        self.send(message).await
+    }
+
+    /// Stops the locomotive and releases its slot, so other throttles can acquire it.
+    ///
+    /// # Errors
+    ///
+    /// - [`MobileError::Sending`]: If the slot could not be released
+    pub async fn release(&self) -> Result<(), MobileError> {
+        self.send(Message::LocoSpd(self.slot, SpeedArg::Stop))
+            .await?;
+        self.commands.release_slot(self.slot).await?;
+        Ok(())
+    }
+}
+
+impl Throttle {
+    /// # Returns
+    ///
+    /// The tracked speed, direction and functions of the slot, defaulting to stopped forwards with all functions off.
+    fn state(&self) -> (Option<SpeedArg>, DirfArg, SndArg) {
+        let slots = self.layout.slots.lock().unwrap();
+        let state = slots.slot(self.slot).copied().unwrap_or_default();
+        (
+            state.speed,
+            state
+                .dirf
+                .unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false)),
+            state
+                .snd
+                .unwrap_or_else(|| SndArg::new(false, false, false, false)),
+        )
+    }
+
+    /// Sends `message` and tracks it right away, so a following command builds on it.
+    async fn send(&self, message: Message) -> Result<(), MobileError> {
+        self.commands.send_message(message).await?;
+        self.layout.slots.lock().unwrap().handle(&message);
+        Ok(())
+    }
+}
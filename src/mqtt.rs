@@ -0,0 +1,396 @@
+use crate::args::{AddressArg, DirfArg, SensorLevel, SpeedArg, SwitchArg, SwitchDirection};
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver,
+};
+use crate::manager::{Manager, SensorManager, SlotManager, TurnoutTable};
+use crate::message_ref::MessageRef;
+use crate::protocol::Message;
+use crate::slot_cache::SlotResolver;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// Bridges the model railroad to an MQTT broker, so home automation like Home Assistant
+/// or Node-RED can watch and control the layout.
+///
+/// The bridge publishes every change below its prefix, `locodrive` by default:
+///
+/// - `<prefix>/sensor/<address>`: `ON` if the sensor is occupied, `OFF` otherwise
+/// - `<prefix>/turnout/<address>`: `straight` or `curved`, as commanded or reported
+/// - `<prefix>/slot/<slot>/address`, `.../speed` and `.../direction`: The locomotive in the slot,
+///   its speed step and `forward` or `backward`
+/// - `<prefix>/power`: `ON` or `OFF`
+///
+/// It accepts commands on:
+///
+/// - `<prefix>/loco/<address>/speed/set`: The speed step, acquiring the slot of the locomotive if needed
+/// - `<prefix>/loco/<address>/direction/set`: `forward` or `backward`
+/// - `<prefix>/turnout/<address>/set`: `straight` or `curved`
+/// - `<prefix>/power/set`: `ON` or `OFF`
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::mqtt::MqttBridge;
+/// use rumqttc::MqttOptions;
+///
+/// # fn bridge(controller: LocoDriveController) {
+/// let bridge = MqttBridge::new(MqttOptions::new("locodrive", "localhost", 1883))
+///     .prefix("railroad")
+///     .start(&controller);
+/// # }
+/// ```
+pub struct MqttBridge {
+    /// The options to connect to the broker with
+    options: MqttOptions,
+    /// The prefix of all topics
+    prefix: String,
+    /// The quality of service of the publications and subscriptions
+    qos: QoS,
+    /// Whether the publications are retained by the broker
+    retain: bool,
+}
+
+impl MqttBridge {
+    /// Creates a bridge connecting to the broker with `options`.
+    pub fn new(options: MqttOptions) -> Self {
+        MqttBridge {
+            options,
+            prefix: "locodrive".to_string(),
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        }
+    }
+
+    /// Sets the `prefix` of all published and subscribed topics.
+    ///
+    /// Defaults to `locodrive`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets the quality of service of the publications and subscriptions.
+    ///
+    /// Defaults to [`QoS::AtLeastOnce`].
+    pub fn qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Sets whether the publications are retained by the broker,
+    /// so new subscribers learn the current state.
+    ///
+    /// Defaults to `true`.
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Starts bridging between the broker and `controller`.
+    /// The bridge reconnects to the broker, if the connection is lost.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the bridge. Dropping it disconnects from the broker.
+    pub fn start(self, controller: &LocoDriveController) -> MqttBridgeHandle {
+        let shared = Arc::new(Shared {
+            handle: controller.command_handle(),
+            resolver: SlotResolver::new(controller),
+        });
+        let task = tokio::spawn(bridge(self, shared, controller.subscribe()));
+        MqttBridgeHandle { task }
+    }
+}
+
+/// The handle of a running [`MqttBridge`].
+///
+/// Dropping the handle disconnects from the broker.
+#[derive(Debug)]
+pub struct MqttBridgeHandle {
+    /// The task bridging between the broker and the controller
+    task: JoinHandle<()>,
+}
+
+/// Extends standard drop implementation to stop the bridge.
+impl Drop for MqttBridgeHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Writes the commands received from the broker.
+struct Shared {
+    /// Writes the messages of the commands
+    handle: CommandHandle,
+    /// Resolves the slots of the commanded locomotives
+    resolver: SlotResolver,
+}
+
+/// A command received from the broker.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum MqttCommand {
+    /// Sets the speed of the locomotive at the address
+    Speed(AddressArg, SpeedArg),
+    /// Sets whether the locomotive at the address drives forwards
+    Direction(AddressArg, bool),
+    /// Switches the turnout at the address
+    Turnout(u16, SwitchDirection),
+    /// Switches the track power
+    Power(bool),
+}
+
+/// Parses the `payload` published to `topic` below `prefix`.
+///
+/// # Returns
+///
+/// The command, or `None` if the topic or payload is unknown.
+pub(crate) fn parse_command(prefix: &str, topic: &str, payload: &[u8]) -> Option<MqttCommand> {
+    let payload = std::str::from_utf8(payload).ok()?.trim();
+    let path: Vec<&str> = topic
+        .strip_prefix(prefix)?
+        .strip_prefix('/')?
+        .split('/')
+        .collect();
+
+    match path[..] {
+        ["loco", address, "speed", "set"] => {
+            let address = AddressArg::new(address.parse().ok()?);
+            Some(MqttCommand::Speed(
+                address,
+                SpeedArg::new(payload.parse::<u8>().ok()?.min(126)),
+            ))
+        }
+        ["loco", address, "direction", "set"] => {
+            let address = AddressArg::new(address.parse().ok()?);
+            match payload {
+                "forward" => Some(MqttCommand::Direction(address, true)),
+                "backward" => Some(MqttCommand::Direction(address, false)),
+                _ => None,
+            }
+        }
+        ["turnout", address, "set"] => {
+            let address = address.parse().ok()?;
+            match payload {
+                "straight" => Some(MqttCommand::Turnout(address, SwitchDirection::Straight)),
+                "curved" => Some(MqttCommand::Turnout(address, SwitchDirection::Curved)),
+                _ => None,
+            }
+        }
+        ["power", "set"] => match payload {
+            "ON" => Some(MqttCommand::Power(true)),
+            "OFF" => Some(MqttCommand::Power(false)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Tracks the layout from the bus to publish its changes.
+#[derive(Debug, Default)]
+pub(crate) struct MqttPublisher {
+    /// The known slots
+    slots: SlotManager,
+    /// The known turnouts
+    turnouts: TurnoutTable,
+    /// The known sensors
+    sensors: SensorManager,
+    /// Whether the track power is on, if known
+    power: Option<bool>,
+}
+
+impl MqttPublisher {
+    /// Tracks `message`.
+    ///
+    /// # Returns
+    ///
+    /// The topics below `prefix` with their payloads, that changed by the message.
+    pub(crate) fn track(&mut self, prefix: &str, message: &Message) -> Vec<(String, String)> {
+        let mut publications = Vec::new();
+
+        let frame = message.to_message();
+        if let Some(slot) = MessageRef::new(&frame)
+            .ok()
+            .and_then(|message| message.slot())
+        {
+            let before = self.slots.slot(slot).copied().unwrap_or_default();
+            self.slots.handle(message);
+            let after = self.slots.slot(slot).copied().unwrap_or_default();
+
+            let topic = format!("{}/slot/{}", prefix, slot.slot());
+            if after.address != before.address {
+                if let Some(address) = after.address {
+                    publications
+                        .push((format!("{}/address", topic), address.address().to_string()));
+                }
+            }
+            if after.speed != before.speed {
+                if let Some(speed) = after.speed {
+                    publications.push((format!("{}/speed", topic), speed.get_spd().to_string()));
+                }
+            }
+            let direction = |dirf: Option<DirfArg>| dirf.map(|dirf| dirf.dir());
+            if direction(after.dirf) != direction(before.dirf) {
+                if let Some(forward) = direction(after.dirf) {
+                    let forward = match forward {
+                        true => "forward",
+                        false => "backward",
+                    };
+                    publications.push((format!("{}/direction", topic), forward.to_string()));
+                }
+            }
+        }
+
+        match *message {
+            Message::InputRep(input) => {
+                if self.sensors.level(input.address()) != Some(input.sensor_level()) {
+                    self.sensors.handle(message);
+                    let level = match input.sensor_level() {
+                        SensorLevel::High => "ON",
+                        SensorLevel::Low => "OFF",
+                    };
+                    publications.push((
+                        format!("{}/sensor/{}", prefix, input.address()),
+                        level.to_string(),
+                    ));
+                }
+            }
+            Message::GpOn | Message::GpOff => {
+                let power = *message == Message::GpOn;
+                if self.power.replace(power) != Some(power) {
+                    let power = match power {
+                        true => "ON",
+                        false => "OFF",
+                    };
+                    publications.push((format!("{}/power", prefix), power.to_string()));
+                }
+            }
+            _ => {
+                if let Some(change) = self.turnouts.update(message) {
+                    let direction = match change.direction {
+                        SwitchDirection::Straight => "straight",
+                        SwitchDirection::Curved => "curved",
+                    };
+                    publications.push((
+                        format!("{}/turnout/{}", prefix, change.address),
+                        direction.to_string(),
+                    ));
+                }
+            }
+        }
+        publications
+    }
+
+    /// # Returns
+    ///
+    /// The direction and functions of the slot of the locomotive at `address`, if known.
+    fn dirf(&self, address: AddressArg) -> Option<DirfArg> {
+        self.slots
+            .slots()
+            .values()
+            .find(|state| state.address == Some(address))
+            .and_then(|state| state.dirf)
+    }
+}
+
+/// Executes a `command` received from the broker.
+/// The direction is set on the functions `dirf`, defaulting to all functions off.
+async fn execute(shared: Arc<Shared>, command: MqttCommand, dirf: Option<DirfArg>) {
+    let message = match command {
+        MqttCommand::Speed(address, speed) => match shared.resolver.slot_for(address).await {
+            Ok(slot) => Message::LocoSpd(slot, speed),
+            Err(err) => {
+                log_error!(
+                    "Could not acquire locomotive {}: {}",
+                    address.address(),
+                    err
+                );
+                return;
+            }
+        },
+        MqttCommand::Direction(address, forward) => match shared.resolver.slot_for(address).await {
+            Ok(slot) => {
+                let mut dirf =
+                    dirf.unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false));
+                dirf.set_dir(forward);
+                Message::LocoDirf(slot, dirf)
+            }
+            Err(err) => {
+                log_error!(
+                    "Could not acquire locomotive {}: {}",
+                    address.address(),
+                    err
+                );
+                return;
+            }
+        },
+        MqttCommand::Turnout(address, direction) => {
+            Message::SwReq(SwitchArg::new(address, direction, true))
+        }
+        MqttCommand::Power(true) => Message::GpOn,
+        MqttCommand::Power(false) => Message::GpOff,
+    };
+    if let Err(err) = shared.handle.send_message(message).await {
+        log_error!("Could not send {:?} commanded by MQTT: {}", message, err);
+    }
+}
+
+/// Bridges between the broker of `bridge` and the bus, until the controller is dropped.
+async fn bridge(bridge: MqttBridge, shared: Arc<Shared>, mut messages: LocoDriveReceiver) {
+    let (client, mut events) = AsyncClient::new(bridge.options, 64);
+    let mut publisher = MqttPublisher::default();
+    let commands = [
+        format!("{}/loco/+/speed/set", bridge.prefix),
+        format!("{}/loco/+/direction/set", bridge.prefix),
+        format!("{}/turnout/+/set", bridge.prefix),
+        format!("{}/power/set", bridge.prefix),
+    ];
+
+    loop {
+        tokio::select! {
+            event = events.poll() => match event {
+                // The subscriptions are renewed on every connect, as the session may be new
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    log_info!("Connected to the MQTT broker");
+                    for topic in commands.iter() {
+                        if let Err(err) = client.try_subscribe(topic.as_str(), bridge.qos) {
+                            log_error!("Could not subscribe to {}: {}", topic, err);
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    match parse_command(&bridge.prefix, &publish.topic, &publish.payload) {
+                        Some(command) => {
+                            let dirf = match command {
+                                MqttCommand::Direction(address, _) => publisher.dirf(address),
+                                _ => None,
+                            };
+                            // Commands are written in the background, so the broker is served meanwhile
+                            tokio::spawn(execute(shared.clone(), command, dirf));
+                        }
+                        None => log_error!("Unknown MQTT command on {}", publish.topic),
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log_error!("MQTT connection failed, reconnecting: {}", err);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            },
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    for (topic, payload) in publisher.track(&bridge.prefix, &message) {
+                        if let Err(err) = client.try_publish(topic.as_str(), bridge.qos, bridge.retain, payload) {
+                            log_error!("Could not publish {}: {}", topic, err);
+                        }
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use crate::args::{AddressArg, InArg, SensorLevel, SwitchArg, SwitchDirection};
+#[cfg(feature = "config")]
+use crate::error::ConfigError;
+#[cfg(feature = "control")]
+use crate::loco_controller::LocoDriveMessage;
+use crate::manager::{SensorChange, TurnoutChange};
+use crate::protocol::Message;
+#[cfg(feature = "config")]
+use crate::snapshot::extension;
+#[cfg(feature = "control")]
+use crate::traffic::TrafficKey;
+use std::collections::BTreeMap;
+#[cfg(feature = "config")]
+use std::path::Path;
+
+/// The user friendly name of a turnout, sensor or locomotive with its metadata.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alias {
+    /// The LocoNet address of the named object
+    pub address: u16,
+    /// The name shown instead of the address
+    pub name: String,
+    /// Further information on the object, like where it is located
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Alias {
+    /// Creates the alias `name` of the object with `address`, without metadata.
+    pub fn new(address: u16, name: impl Into<String>) -> Self {
+        Alias {
+            address,
+            name: name.into(),
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+/// Maps the LocoNet addresses of turnouts, sensors and locomotives to user friendly names,
+/// so logs and events say `Yard West #12` instead of `switch 57`.
+///
+/// The turnouts are numbered like the [`SwitchArg`] addresses, the sensors like
+/// [`InArg::address_ds54()`] and the locomotives by their decoder address.
+/// Objects without a name are described by their kind and address.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{SwitchArg, SwitchDirection};
+/// use locodrive::names::{Describe, NameRegistry};
+/// use locodrive::protocol::Message;
+///
+/// let mut names = NameRegistry::new();
+/// names.name_turnout(57, "Yard West #12");
+///
+/// let message = Message::SwReq(SwitchArg::new(57, SwitchDirection::Curved, true));
+/// assert_eq!(message.describe(&names), "SwReq Yard West #12 curved on");
+/// assert_eq!(names.turnout(58), "switch 58");
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NameRegistry {
+    /// The names of the turnouts by their address
+    #[cfg_attr(feature = "serde", serde(default, with = "aliases"))]
+    turnouts: BTreeMap<u16, Alias>,
+    /// The names of the sensors by their address
+    #[cfg_attr(feature = "serde", serde(default, with = "aliases"))]
+    sensors: BTreeMap<u16, Alias>,
+    /// The names of the locomotives by their address
+    #[cfg_attr(feature = "serde", serde(default, with = "aliases"))]
+    locos: BTreeMap<u16, Alias>,
+}
+
+impl NameRegistry {
+    /// Creates a registry without any name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the turnout with `address`, replacing its previous name and metadata.
+    ///
+    /// # Returns
+    ///
+    /// The alias of the turnout, to add metadata to.
+    pub fn name_turnout(&mut self, address: u16, name: impl Into<String>) -> &mut Alias {
+        Self::insert(&mut self.turnouts, address, name)
+    }
+
+    /// Names the sensor with `address`, replacing its previous name and metadata.
+    ///
+    /// # Returns
+    ///
+    /// The alias of the sensor, to add metadata to.
+    pub fn name_sensor(&mut self, address: u16, name: impl Into<String>) -> &mut Alias {
+        Self::insert(&mut self.sensors, address, name)
+    }
+
+    /// Names the locomotive with `address`, replacing its previous name and metadata.
+    ///
+    /// # Returns
+    ///
+    /// The alias of the locomotive, to add metadata to.
+    pub fn name_loco(&mut self, address: u16, name: impl Into<String>) -> &mut Alias {
+        Self::insert(&mut self.locos, address, name)
+    }
+
+    /// Inserts the alias `name` of the object with `address` into `aliases`.
+    fn insert(
+        aliases: &mut BTreeMap<u16, Alias>,
+        address: u16,
+        name: impl Into<String>,
+    ) -> &mut Alias {
+        aliases.insert(address, Alias::new(address, name));
+        aliases.get_mut(&address).expect("inserted alias")
+    }
+
+    /// # Returns
+    ///
+    /// The alias of the turnout with `address`, if it is named.
+    pub fn turnout_alias(&self, address: u16) -> Option<&Alias> {
+        self.turnouts.get(&address)
+    }
+
+    /// # Returns
+    ///
+    /// The alias of the sensor with `address`, if it is named.
+    pub fn sensor_alias(&self, address: u16) -> Option<&Alias> {
+        self.sensors.get(&address)
+    }
+
+    /// # Returns
+    ///
+    /// The alias of the locomotive with `address`, if it is named.
+    pub fn loco_alias(&self, address: u16) -> Option<&Alias> {
+        self.locos.get(&address)
+    }
+
+    /// # Returns
+    ///
+    /// The name of the turnout with `address`, or `switch` and its address if it is not named.
+    pub fn turnout(&self, address: u16) -> String {
+        Self::label(self.turnout_alias(address), "switch", address)
+    }
+
+    /// # Returns
+    ///
+    /// The name of the sensor with `address`, or `sensor` and its address if it is not named.
+    pub fn sensor(&self, address: u16) -> String {
+        Self::label(self.sensor_alias(address), "sensor", address)
+    }
+
+    /// # Returns
+    ///
+    /// The name of the locomotive with `address`, or `loco` and its address if it is not named.
+    pub fn loco(&self, address: u16) -> String {
+        Self::label(self.loco_alias(address), "loco", address)
+    }
+
+    /// # Returns
+    ///
+    /// The name of `alias`, or `kind` and `address` if the object is not named.
+    fn label(alias: Option<&Alias>, kind: &str, address: u16) -> String {
+        match alias {
+            Some(alias) => alias.name.clone(),
+            None => format!("{} {}", kind, address),
+        }
+    }
+
+    /// Loads the names from a TOML or RON file, depending on its extension.
+    /// The objects are listed with their address, name and optional metadata:
+    ///
+    /// ```toml
+    /// [[turnouts]]
+    /// address = 57
+    /// name = "Yard West #12"
+    /// metadata = { station = "Yard" }
+    ///
+    /// [[sensors]]
+    /// address = 12
+    /// name = "Platform 1"
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be read
+    /// - [`ConfigError::Parse`]: If the file holds no valid names
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    #[cfg(feature = "config")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match extension(path).as_str() {
+            "toml" => toml::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            "ron" => ron::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Saves the names to a TOML or RON file, depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be written
+    /// - [`ConfigError::Parse`]: If the names could not be serialized
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    #[cfg(feature = "config")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let content = match extension(path).as_str() {
+            "toml" => toml::to_string(self).map_err(|err| ConfigError::Parse(err.to_string()))?,
+            "ron" => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|err| ConfigError::Parse(err.to_string()))?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        };
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Serializes the aliases by address as list, as TOML only allows names as keys.
+#[cfg(feature = "serde")]
+mod aliases {
+    use super::Alias;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    /// Serializes the `aliases` as list ordered by their address.
+    pub(super) fn serialize<S: Serializer>(
+        aliases: &BTreeMap<u16, Alias>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(aliases.values())
+    }
+
+    /// Deserializes the aliases from a list, a later alias of the same address wins.
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<u16, Alias>, D::Error> {
+        let aliases = Vec::<Alias>::deserialize(deserializer)?;
+        Ok(aliases
+            .into_iter()
+            .map(|alias| (alias.address, alias))
+            .collect())
+    }
+}
+
+/// Describes state and events with the names of a [`NameRegistry`].
+pub trait Describe {
+    /// # Returns
+    ///
+    /// The human readable description, naming the objects by `names`.
+    fn describe(&self, names: &NameRegistry) -> String;
+}
+
+/// # Returns
+///
+/// The lower case name of `direction`.
+fn direction(direction: SwitchDirection) -> &'static str {
+    match direction {
+        SwitchDirection::Straight => "straight",
+        SwitchDirection::Curved => "curved",
+    }
+}
+
+/// # Returns
+///
+/// The lower case name of `level`.
+fn level(level: SensorLevel) -> &'static str {
+    match level {
+        SensorLevel::High => "high",
+        SensorLevel::Low => "low",
+    }
+}
+
+impl Describe for SwitchArg {
+    fn describe(&self, names: &NameRegistry) -> String {
+        let state = match self.state() {
+            true => "on",
+            false => "off",
+        };
+        format!(
+            "{} {} {}",
+            names.turnout(self.address()),
+            direction(self.direction()),
+            state
+        )
+    }
+}
+
+impl Describe for InArg {
+    fn describe(&self, names: &NameRegistry) -> String {
+        format!(
+            "{} {}",
+            names.sensor(self.address_ds54()),
+            level(self.sensor_level())
+        )
+    }
+}
+
+impl Describe for AddressArg {
+    fn describe(&self, names: &NameRegistry) -> String {
+        names.loco(self.address())
+    }
+}
+
+/// Messages addressing a named object are described by their name and the object,
+/// all others like their debug output.
+impl Describe for Message {
+    fn describe(&self, names: &NameRegistry) -> String {
+        let named = match *self {
+            Message::SwReq(switch) | Message::SwState(switch) | Message::SwAck(switch) => names
+                .turnout_alias(switch.address())
+                .map(|_| switch.describe(names)),
+            Message::SwRep(sn) => names.turnout_alias(sn.address()).map(|alias| {
+                let debug = format!("{:?}", sn);
+                format!("{} {}", alias.name, debug)
+            }),
+            Message::InputRep(input) => names
+                .sensor_alias(input.address_ds54())
+                .map(|_| input.describe(names)),
+            Message::LocoAdr(address) => names
+                .loco_alias(address.address())
+                .map(|_| address.describe(names)),
+            _ => None,
+        };
+        let debug = format!("{:?}", self);
+        match named {
+            Some(named) => {
+                // The variant name is the debug output up to its arguments
+                let variant = debug.split('(').next().unwrap_or_default();
+                format!("{} {}", variant, named)
+            }
+            None => debug,
+        }
+    }
+}
+
+impl Describe for SensorChange {
+    fn describe(&self, names: &NameRegistry) -> String {
+        format!("{} {}", names.sensor(self.address), level(self.level))
+    }
+}
+
+impl Describe for TurnoutChange {
+    fn describe(&self, names: &NameRegistry) -> String {
+        format!(
+            "{} {}",
+            names.turnout(self.address),
+            direction(self.direction)
+        )
+    }
+}
+
+#[cfg(feature = "control")]
+impl Describe for TrafficKey {
+    fn describe(&self, names: &NameRegistry) -> String {
+        match *self {
+            TrafficKey::Sensor(address) => names.sensor(address),
+            TrafficKey::Switch(address) => names.turnout(address),
+            key => key.to_string(),
+        }
+    }
+}
+
+/// The messages of the bus are described by [`Message::describe()`],
+/// other events without a named object like their debug output.
+#[cfg(feature = "control")]
+impl Describe for LocoDriveMessage {
+    fn describe(&self, names: &NameRegistry) -> String {
+        match self {
+            LocoDriveMessage::Message(message) => message.describe(names),
+            LocoDriveMessage::Echo(message) => format!("echo {}", message.describe(names)),
+            LocoDriveMessage::Answer(answer, request) => format!(
+                "{} answering {}",
+                answer.describe(names),
+                request.describe(names)
+            ),
+            LocoDriveMessage::AnswerTimeout(request) => {
+                format!("no answer to {}", request.describe(names))
+            }
+            LocoDriveMessage::Sent(message, _) => format!("sent {}", message.describe(names)),
+            LocoDriveMessage::ChattyDevice(device, count) => {
+                format!("{} is chatty with {} frames", device.describe(names), count)
+            }
+            event => format!("{:?}", event),
+        }
+    }
+}
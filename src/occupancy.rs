@@ -0,0 +1,262 @@
+use crate::args::{AddressArg, SensorLevel};
+use crate::layout::LayoutModel;
+use crate::protocol::Message;
+use std::collections::{HashMap, HashSet};
+use tokio::time::{Duration, Instant};
+
+/// The type of [`crate::args::MultiSenseArg`] reporting transponders.
+const TRANSPONDING: u8 = 0x01;
+
+/// A detector reporting whether a part of a block is occupied.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Detector {
+    /// A sensor reporting by [`Message::InputRep`], by its address with the source type
+    /// as least significant bit, as returned by [`crate::args::InArg::address_ds54()`]
+    Sensor(u16),
+    /// A transponding zone reporting by [`Message::MultiSense`], by its board address and zone
+    Zone(u8, u8),
+}
+
+/// A train entering or leaving a block.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum OccupancyEvent {
+    /// The block became occupied.
+    Entered {
+        /// The name of the block
+        block: String,
+        /// The locomotive detected in the block, if reported by transponding
+        loco: Option<AddressArg>,
+    },
+    /// The block became free.
+    Left {
+        /// The name of the block
+        block: String,
+        /// The locomotive last detected in the block, if reported by transponding
+        loco: Option<AddressArg>,
+    },
+}
+
+/// The state of one block.
+#[derive(Debug, Clone, Default)]
+struct BlockState {
+    /// The detectors currently reporting the block as occupied
+    active: HashSet<Detector>,
+    /// Whether the block is occupied after debouncing
+    occupied: bool,
+    /// Since when the detectors contradict the debounced state
+    pending: Option<Instant>,
+    /// The locomotives currently detected in the block by transponding
+    locos: Vec<AddressArg>,
+    /// The locomotive last detected in the block
+    last_loco: Option<AddressArg>,
+}
+
+impl BlockState {
+    /// # Returns
+    ///
+    /// Whether the detectors report the block as occupied.
+    fn detected(&self) -> bool {
+        !self.active.is_empty()
+    }
+}
+
+/// Tracks which blocks are occupied from the sensor and transponding reports of their detectors.
+///
+/// Sensors often flicker, when a train passes a gap or dirty track. So a block changes its state
+/// only after its detectors agree on the new state for the debounce time.
+/// Call [`BlockOccupancy::poll()`] until [`BlockOccupancy::next_deadline()`] to receive the
+/// changes confirmed after the debounce time, even if no further message is received.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{InArg, SensorLevel, SourceType};
+/// use locodrive::occupancy::{BlockOccupancy, Detector, OccupancyEvent};
+/// use locodrive::protocol::Message;
+/// use std::time::Duration;
+/// use tokio::time::Instant;
+///
+/// let mut blocks = BlockOccupancy::new().debounce(Duration::ZERO);
+/// blocks.add_block("station", [Detector::Sensor(20)]);
+///
+/// let sensor = InArg::new(10, SourceType::Ds54Aux, SensorLevel::High, false);
+/// let events = blocks.handle(&Message::InputRep(sensor), Instant::now());
+///
+/// assert!(blocks.is_occupied("station"));
+/// assert_eq!(events, vec![OccupancyEvent::Entered { block: "station".to_string(), loco: None }]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockOccupancy {
+    /// The block of each detector by its name
+    detectors: HashMap<Detector, String>,
+    /// The state of each block by its name
+    blocks: HashMap<String, BlockState>,
+    /// How long the detectors have to agree on a new state of a block
+    debounce: Duration,
+}
+
+impl BlockOccupancy {
+    /// Creates a tracker without any block.
+    pub fn new() -> Self {
+        BlockOccupancy {
+            detectors: HashMap::new(),
+            blocks: HashMap::new(),
+            debounce: Duration::from_millis(500),
+        }
+    }
+
+    /// Creates a tracker of all blocks of `layout`, detected by their sensors.
+    /// Sensors not known to the layout are skipped.
+    pub fn from_layout(layout: &LayoutModel) -> Self {
+        let mut occupancy = Self::new();
+        for block in &layout.blocks {
+            let sensors = block
+                .sensors
+                .iter()
+                .filter_map(|id| layout.sensor(id))
+                .map(|sensor| Detector::Sensor(sensor.address));
+            occupancy.add_block(&block.id, sensors);
+        }
+        occupancy
+    }
+
+    /// Sets how long the detectors have to agree on a new state of a block.
+    ///
+    /// Defaults to 500 milliseconds.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Adds the block `name` detected by `detectors`.
+    /// A detector assigned to another block before is moved to this one.
+    pub fn add_block(&mut self, name: &str, detectors: impl IntoIterator<Item = Detector>) {
+        self.blocks.entry(name.to_string()).or_default();
+        for detector in detectors {
+            self.detectors.insert(detector, name.to_string());
+        }
+    }
+
+    /// # Returns
+    ///
+    /// Whether the block `name` is occupied after debouncing.
+    pub fn is_occupied(&self, name: &str) -> bool {
+        self.blocks.get(name).is_some_and(|block| block.occupied)
+    }
+
+    /// # Returns
+    ///
+    /// The names of all occupied blocks.
+    pub fn occupied(&self) -> Vec<&str> {
+        self.blocks
+            .iter()
+            .filter(|(_, block)| block.occupied)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// # Returns
+    ///
+    /// The locomotives detected in the block `name` by transponding.
+    pub fn locos(&self, name: &str) -> &[AddressArg] {
+        self.blocks
+            .get(name)
+            .map_or(&[], |block| block.locos.as_slice())
+    }
+
+    /// # Returns
+    ///
+    /// When the next change of a block is confirmed, if any block awaits its debounce time.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.blocks
+            .values()
+            .filter_map(|block| block.pending)
+            .min()
+            .map(|since| since + self.debounce)
+    }
+
+    /// Updates the blocks by one `message` received at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The changes of the blocks confirmed until `now`.
+    pub fn handle(&mut self, message: &Message, now: Instant) -> Vec<OccupancyEvent> {
+        let (detector, active, loco) = match *message {
+            Message::InputRep(input) => (
+                Detector::Sensor(input.address_ds54()),
+                input.sensor_level() == SensorLevel::High,
+                None,
+            ),
+            Message::MultiSense(sense, loco) if sense.m_type() == TRANSPONDING => (
+                Detector::Zone(sense.board_address(), sense.zone()),
+                sense.present(),
+                Some(loco),
+            ),
+            _ => return self.poll(now),
+        };
+
+        let blocks = &mut self.blocks;
+        if let Some(block) = self
+            .detectors
+            .get(&detector)
+            .and_then(|name| blocks.get_mut(name))
+        {
+            if active {
+                block.active.insert(detector);
+            } else {
+                block.active.remove(&detector);
+            }
+
+            if let Some(loco) = loco {
+                block.locos.retain(|known| *known != loco);
+                if active {
+                    block.locos.push(loco);
+                }
+                block.last_loco = Some(loco);
+            }
+
+            if block.detected() == block.occupied {
+                block.pending = None;
+            } else if block.pending.is_none() {
+                block.pending = Some(now);
+            }
+        }
+
+        self.poll(now)
+    }
+
+    /// # Returns
+    ///
+    /// The changes of the blocks confirmed until `now`, as their detectors agreed on the new state
+    /// for the debounce time.
+    pub fn poll(&mut self, now: Instant) -> Vec<OccupancyEvent> {
+        let mut events = Vec::new();
+        for (name, block) in self.blocks.iter_mut() {
+            match block.pending {
+                Some(since) if now.saturating_duration_since(since) >= self.debounce => {}
+                _ => continue,
+            }
+
+            block.pending = None;
+            block.occupied = block.detected();
+            events.push(if block.occupied {
+                OccupancyEvent::Entered {
+                    block: name.clone(),
+                    loco: block.locos.last().copied(),
+                }
+            } else {
+                OccupancyEvent::Left {
+                    block: name.clone(),
+                    loco: block.last_loco,
+                }
+            });
+        }
+        events
+    }
+}
+
+impl Default for BlockOccupancy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
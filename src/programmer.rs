@@ -0,0 +1,608 @@
+use crate::args::{Ack1Arg, AddressArg, CvDataArg, PStat, Pcmd, TrkArg, WrSlDataStructure};
+use crate::error::ProgrammingError;
+use crate::loco_controller::{LocoDriveController, LocoDriveMessage, SendOptions};
+use crate::protocol::Message;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// Configures when a [`Programmer`] refuses service mode operations on the programming track.
+///
+/// Operations on the main track (ops mode) are never refused.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ProgrammingInterlock {
+    /// Refuse operations while the model railroad reports the programming track as busy.
+    pub refuse_when_busy: bool,
+    /// Refuse operations while the track power is on or not known to be off.
+    /// This protects decoders on the main track, if the programming track is wired to it.
+    pub require_power_off: bool,
+}
+
+impl Default for ProgrammingInterlock {
+    fn default() -> Self {
+        ProgrammingInterlock {
+            refuse_when_busy: true,
+            require_power_off: false,
+        }
+    }
+}
+
+/// The mode to read or write the cvs of a decoder with.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ProgrammingMode {
+    /// Paged mode on the programming track
+    Paged,
+    /// Direct byte mode on the programming track
+    Direct,
+    /// Physical register mode on the programming track
+    Register,
+    /// Operations mode on the main track for the decoder with the given address.
+    /// Reading requires a decoder sending feedback.
+    Ops(AddressArg),
+}
+
+impl ProgrammingMode {
+    /// # Returns
+    ///
+    /// The programming command to read or, if `write` is set, to write a cv in this mode.
+    pub fn pcmd(&self, write: bool) -> Pcmd {
+        match *self {
+            ProgrammingMode::Paged => Pcmd::new(write, true, false, false, false),
+            ProgrammingMode::Direct => Pcmd::new(write, true, false, true, false),
+            ProgrammingMode::Register => Pcmd::new(write, false, false, false, true),
+            // Reading on the main track requires feedback of the decoder
+            ProgrammingMode::Ops(_) => Pcmd::new(write, true, true, !write, false),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The address of the decoder to program in ops mode.
+    fn address(&self) -> AddressArg {
+        match *self {
+            ProgrammingMode::Ops(address) => address,
+            _ => AddressArg::new(0),
+        }
+    }
+}
+
+/// The progress of a batch of cv operations, like a full decoder backup or restore.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ProgrammingProgress {
+    /// The cv just read or written
+    pub cv: u16,
+    /// How many cvs of the batch are done
+    pub done: usize,
+    /// How many cvs the batch contains
+    pub total: usize,
+    /// How long the batch is running
+    pub elapsed: Duration,
+}
+
+impl ProgrammingProgress {
+    /// Creates the progress of a batch started at `start`.
+    fn new(cv: u16, done: usize, total: usize, start: Instant) -> Self {
+        ProgrammingProgress {
+            cv,
+            done,
+            total,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The estimated time until the batch is done,
+    /// assuming the remaining cvs take as long as the cvs done so far.
+    pub fn eta(&self) -> Duration {
+        if self.done == 0 {
+            return Duration::ZERO;
+        }
+        let remaining = self.total.saturating_sub(self.done) as u32;
+        self.elapsed / self.done as u32 * remaining
+    }
+}
+
+/// The cv values of a decoder, read as backup to write them back later.
+///
+/// The cvs are ordered by their number, starting at `1`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecoderProfile {
+    /// The values by their cv
+    values: BTreeMap<u16, u8>,
+}
+
+impl DecoderProfile {
+    /// Creates a profile without any cv.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The value of `cv`, if it is part of the profile.
+    pub fn get(&self, cv: u16) -> Option<u8> {
+        self.values.get(&cv).copied()
+    }
+
+    /// Sets `cv` to `value`.
+    pub fn set(&mut self, cv: u16, value: u8) {
+        self.values.insert(cv, value);
+    }
+
+    /// Removes `cv` from the profile.
+    ///
+    /// # Returns
+    ///
+    /// The value `cv` held.
+    pub fn remove(&mut self, cv: u16) -> Option<u8> {
+        self.values.remove(&cv)
+    }
+
+    /// # Returns
+    ///
+    /// If the profile holds `cv`.
+    pub fn contains(&self, cv: u16) -> bool {
+        self.values.contains_key(&cv)
+    }
+
+    /// # Returns
+    ///
+    /// How many cvs the profile holds.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// # Returns
+    ///
+    /// If the profile holds no cv.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// # Returns
+    ///
+    /// The cvs with their values ordered by the cv.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.values.iter().map(|(cv, value)| (*cv, *value))
+    }
+}
+
+/// Starts programming tasks on a model railroad, guarded by a [`ProgrammingInterlock`].
+///
+/// The interlock is checked against the track status last reported to the controller,
+/// see [`LocoDriveController::track_status()`].
+pub struct Programmer<'a> {
+    /// The controller to send the programming tasks with
+    controller: &'a LocoDriveController,
+    /// When to refuse service mode operations
+    interlock: ProgrammingInterlock,
+    /// How often a cv operation is repeated, if the decoder did not acknowledge it
+    retries: u8,
+    /// Whether written cvs are read back to verify them
+    verify: bool,
+    /// How long to wait for the result of a cv operation
+    result_timeout: Duration,
+}
+
+impl<'a> Programmer<'a> {
+    /// Creates a new programmer sending with `controller` using the default [`ProgrammingInterlock`].
+    pub fn new(controller: &'a LocoDriveController) -> Self {
+        Self::with_interlock(controller, ProgrammingInterlock::default())
+    }
+
+    /// Creates a new programmer sending with `controller` guarded by `interlock`.
+    pub fn with_interlock(
+        controller: &'a LocoDriveController,
+        interlock: ProgrammingInterlock,
+    ) -> Self {
+        Programmer {
+            controller,
+            interlock,
+            retries: 2,
+            verify: true,
+            result_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets how often a cv operation is repeated, if the decoder did not acknowledge it.
+    ///
+    /// Defaults to `2`.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets whether written cvs are read back to verify them.
+    /// Ops mode writes are never verified, as reading on the main track requires feedback.
+    ///
+    /// Defaults to `true`.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Sets how long to wait for the result of a cv operation.
+    ///
+    /// Defaults to ten seconds.
+    pub fn result_timeout(mut self, result_timeout: Duration) -> Self {
+        self.result_timeout = result_timeout;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The interlock guarding this programmer.
+    pub fn interlock(&self) -> ProgrammingInterlock {
+        self.interlock
+    }
+
+    /// Overrides the interlock guarding this programmer.
+    pub fn set_interlock(&mut self, interlock: ProgrammingInterlock) {
+        self.interlock = interlock;
+    }
+
+    /// Checks whether the interlock allows an operation with the programming command `pcmd`.
+    ///
+    /// # Errors
+    ///
+    /// - [`ProgrammingError::ProgrammingTrackBusy`]: If the programming track is busy
+    /// - [`ProgrammingError::TrackPowerOn`]: If the track power is on, but required to be off
+    /// - [`ProgrammingError::TrackStatusUnknown`]: If the track power is required to be off,
+    ///   but no track status was reported yet
+    pub fn check_interlock(&self, pcmd: &Pcmd) -> Result<(), ProgrammingError> {
+        if pcmd.ops_mode() {
+            return Ok(());
+        }
+
+        let track = self.controller.track_status();
+
+        if self.interlock.refuse_when_busy && track.is_some_and(|trk| trk.prog_busy()) {
+            return Err(ProgrammingError::ProgrammingTrackBusy);
+        }
+
+        if self.interlock.require_power_off {
+            match track {
+                None => return Err(ProgrammingError::TrackStatusUnknown),
+                Some(trk) if trk.power_on() => return Err(ProgrammingError::TrackPowerOn),
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts a programming task, if the interlock allows it.
+    ///
+    /// The task is send as [`WrSlDataStructure::DataPt`]. It is started if the model railroad
+    /// accepts the task. The result is reported later as [`Message::ProgrammingFinalResponse`].
+    ///
+    /// # Parameters
+    ///
+    /// - `pcmd`: The programming command to use
+    /// - `address`: The address of the decoder to program in ops mode
+    /// - `cv_data`: The cv and data to read or write
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::check_interlock()`] or [`ProgrammingError::Sending`]
+    /// if the task was not accepted.
+    pub async fn start_task(
+        &mut self,
+        pcmd: Pcmd,
+        address: AddressArg,
+        cv_data: CvDataArg,
+    ) -> Result<(), ProgrammingError> {
+        self.send_task(pcmd, address, cv_data).await?;
+        Ok(())
+    }
+
+    /// Sends a programming task like [`Programmer::start_task()`].
+    ///
+    /// # Returns
+    ///
+    /// The acknowledgment the task was accepted with.
+    async fn send_task(
+        &mut self,
+        pcmd: Pcmd,
+        address: AddressArg,
+        cv_data: CvDataArg,
+    ) -> Result<Option<Ack1Arg>, ProgrammingError> {
+        self.check_interlock(&pcmd)?;
+
+        let track = self
+            .controller
+            .track_status()
+            .unwrap_or_else(|| TrkArg::new(false, false, true, false));
+
+        let options = SendOptions {
+            require_ack: true,
+            allow_reserved_slots: true,
+            ..SendOptions::default()
+        };
+
+        Ok(self
+            .controller
+            .send_message_acked(
+                Message::WrSlData(WrSlDataStructure::DataPt(pcmd, address, track, cv_data)),
+                options,
+            )
+            .await?)
+    }
+
+    /// Reads a cv of a decoder.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to read with
+    /// - `cv`: The number of the cv to read, starting at `1`
+    ///
+    /// # Returns
+    ///
+    /// The value of the cv.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::start_task()`] or the error reported as result of the task.
+    /// The task is repeated on [`ProgrammingError::NoAck`] as configured by [`Programmer::retries()`].
+    pub async fn read_cv(
+        &mut self,
+        mode: ProgrammingMode,
+        cv: u16,
+    ) -> Result<u8, ProgrammingError> {
+        self.run_task(mode.pcmd(false), mode.address(), cv_data(cv, 0))
+            .await
+    }
+
+    /// Writes a cv of a decoder and verifies it as configured by [`Programmer::verify()`].
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to write with
+    /// - `cv`: The number of the cv to write, starting at `1`
+    /// - `value`: The value to write
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::read_cv()`] or [`ProgrammingError::VerifyFailed`]
+    /// if another value was read back.
+    pub async fn write_cv(
+        &mut self,
+        mode: ProgrammingMode,
+        cv: u16,
+        value: u8,
+    ) -> Result<(), ProgrammingError> {
+        self.run_task(mode.pcmd(true), mode.address(), cv_data(cv, value))
+            .await?;
+
+        if self.verify && !matches!(mode, ProgrammingMode::Ops(_)) {
+            let read = self.read_cv(mode, cv).await?;
+            if read != value {
+                return Err(ProgrammingError::VerifyFailed(cv, value, read));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a batch of cvs, like for a decoder backup.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to read with
+    /// - `cvs`: The numbers of the cvs to read
+    /// - `progress`: Called after each read cv
+    ///
+    /// # Returns
+    ///
+    /// The read cvs with their values in the order of `cvs`.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::read_cv()`]. Reading stops at the first failed cv.
+    pub async fn read_cvs(
+        &mut self,
+        mode: ProgrammingMode,
+        cvs: &[u16],
+        mut progress: impl FnMut(ProgrammingProgress),
+    ) -> Result<Vec<(u16, u8)>, ProgrammingError> {
+        let start = Instant::now();
+        let mut values = Vec::with_capacity(cvs.len());
+        for &cv in cvs {
+            values.push((cv, self.read_cv(mode, cv).await?));
+            progress(ProgrammingProgress::new(cv, values.len(), cvs.len(), start));
+        }
+        Ok(values)
+    }
+
+    /// Writes a batch of cvs, like for a decoder restore.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to write with
+    /// - `values`: The cvs with the values to write to them
+    /// - `progress`: Called after each written cv
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::write_cv()`]. Writing stops at the first failed cv.
+    pub async fn write_cvs(
+        &mut self,
+        mode: ProgrammingMode,
+        values: &[(u16, u8)],
+        mut progress: impl FnMut(ProgrammingProgress),
+    ) -> Result<(), ProgrammingError> {
+        let start = Instant::now();
+        for (done, &(cv, value)) in values.iter().enumerate() {
+            self.write_cv(mode, cv, value).await?;
+            progress(ProgrammingProgress::new(cv, done + 1, values.len(), start));
+        }
+        Ok(())
+    }
+
+    /// Reads `cvs` into `profile`, like for a decoder backup.
+    ///
+    /// The cvs already held by the profile are skipped. So after a failure, reading is resumed
+    /// by calling this again with the same profile.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to read with
+    /// - `cvs`: The cvs to read, like `1..=256` or a list of cvs
+    /// - `profile`: The profile to store the read values in
+    /// - `progress`: Called after each read cv
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::read_cv()`]. Reading stops at the first failed cv,
+    /// the cvs read before are kept in the profile.
+    pub async fn read_profile(
+        &mut self,
+        mode: ProgrammingMode,
+        cvs: impl IntoIterator<Item = u16>,
+        profile: &mut DecoderProfile,
+        mut progress: impl FnMut(ProgrammingProgress),
+    ) -> Result<(), ProgrammingError> {
+        let mut pending: Vec<u16> = cvs
+            .into_iter()
+            .filter(|cv| !profile.contains(*cv))
+            .collect();
+        pending.sort_unstable();
+        pending.dedup();
+
+        let start = Instant::now();
+        for (done, &cv) in pending.iter().enumerate() {
+            let value = self.read_cv(mode, cv).await?;
+            profile.set(cv, value);
+            progress(ProgrammingProgress::new(cv, done + 1, pending.len(), start));
+        }
+        Ok(())
+    }
+
+    /// Writes all cvs of `profile` back to a decoder, like for a decoder restore.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The mode to write with
+    /// - `profile`: The profile to write
+    /// - `resume_after`: Only writes the cvs after this one. To resume after a failure,
+    ///   pass the cv last reported to `progress`.
+    /// - `progress`: Called after each written cv
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Programmer::write_cv()`]. Writing stops at the first failed cv.
+    pub async fn write_profile(
+        &mut self,
+        mode: ProgrammingMode,
+        profile: &DecoderProfile,
+        resume_after: Option<u16>,
+        mut progress: impl FnMut(ProgrammingProgress),
+    ) -> Result<(), ProgrammingError> {
+        let pending: Vec<(u16, u8)> = profile
+            .iter()
+            .filter(|(cv, _)| resume_after.is_none_or(|after| *cv > after))
+            .collect();
+
+        self.write_cvs(mode, &pending, &mut progress).await
+    }
+
+    /// Runs a programming task, repeating it while the decoder does not acknowledge it.
+    async fn run_task(
+        &mut self,
+        pcmd: Pcmd,
+        address: AddressArg,
+        cv_data: CvDataArg,
+    ) -> Result<u8, ProgrammingError> {
+        let mut attempt = 0;
+        loop {
+            match self.run_task_once(pcmd, address, cv_data).await {
+                Err(ProgrammingError::NoAck) if attempt < self.retries => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    /// Starts a programming task and awaits its result reported in the programming slot.
+    async fn run_task_once(
+        &mut self,
+        pcmd: Pcmd,
+        address: AddressArg,
+        cv_data: CvDataArg,
+    ) -> Result<u8, ProgrammingError> {
+        // We listen before starting to not miss a fast result
+        let mut messages = self.controller.subscribe();
+        let ack = self.send_task(pcmd, address, cv_data).await?;
+
+        // A task accepted blind reports no result, like an ops mode write without feedback
+        if ack.is_some_and(|ack| ack.accepted_blind()) {
+            return Ok(cv_data_value(cv_data));
+        }
+
+        let deadline = Instant::now() + self.result_timeout;
+        loop {
+            let received = tokio::select! {
+                received = messages.recv() => received,
+                _ = sleep_until(deadline) => return Err(ProgrammingError::Timeout),
+            };
+
+            match received {
+                Ok(LocoDriveMessage::Message(Message::ProgrammingFinalResponse(
+                    ..,
+                    pstat,
+                    _,
+                    cv_data,
+                ))) => return task_result(pstat, cv_data),
+                Ok(LocoDriveMessage::Message(Message::ProgrammingAborted(_))) => {
+                    return Err(ProgrammingError::Aborted)
+                }
+                Err(RecvError::Closed) => return Err(ProgrammingError::Timeout),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// # Returns
+///
+/// The cv and data argument of the cv numbered `cv`, starting at `1`, holding `value`.
+pub(crate) fn cv_data(cv: u16, value: u8) -> CvDataArg {
+    // The cvs are transmitted starting at zero
+    let cv = cv.saturating_sub(1);
+    let mut cv_data = CvDataArg::new();
+    for bit in 0..10 {
+        cv_data.set_cv(bit, cv >> bit & 1 != 0);
+    }
+    for bit in 0..8 {
+        cv_data.set_data(bit, value >> bit & 1 != 0);
+    }
+    cv_data
+}
+
+/// Interprets the result of a programming task.
+///
+/// # Returns
+///
+/// The value read or written by the task.
+///
+/// # Errors
+///
+/// The error reported by `pstat`.
+pub(crate) fn task_result(pstat: PStat, cv_data: CvDataArg) -> Result<u8, ProgrammingError> {
+    if pstat.user_aborted() {
+        Err(ProgrammingError::Aborted)
+    } else if pstat.programming_track_empty() {
+        Err(ProgrammingError::NoDecoder)
+    } else if pstat.no_read_ack() || pstat.no_write_ack() {
+        Err(ProgrammingError::NoAck)
+    } else {
+        Ok(cv_data_value(cv_data))
+    }
+}
+
+/// # Returns
+///
+/// The data value held by `cv_data`.
+fn cv_data_value(cv_data: CvDataArg) -> u8 {
+    (0..8).fold(0, |value, bit| value | (cv_data.data(bit) as u8) << bit)
+}
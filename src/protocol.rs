@@ -1,9 +1,17 @@
 use crate::args::*;
-use crate::error::MessageParseError;
+use crate::error::{EncodeError, MessageParseError};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The count of bytes of the longest message, so a buffer of this length can hold every message.
+pub const MAX_MESSAGE_LEN: usize = 21;
 
 /// Represents the types of messages that are specified by the model railroads protocol.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Message {
     /// Forces the model railroads to switch in Idle state. An emergency stop for all trains is broadcast.
     /// Note: The model railroads may not response any more.
@@ -148,6 +156,16 @@ pub enum Message {
     /// In systems from `Uhlenbrock` this message could be used to
     /// access the slot functions 9 to 28.
     UhliFun(SlotArg, FunctionArg),
+    /// Asks the infrared and radio receivers, like the UR90 and UR92,
+    /// to report the throttles attached to them.
+    ///
+    /// # Response
+    ///
+    /// A [`Message::ThrottleStatus`] for each attached throttle.
+    ReceiverQuery,
+    /// Reports a throttle attached to an infrared or radio receiver
+    /// with its battery and semaphore status.
+    ThrottleStatus(ThrottleStatusArg),
 
     /// Used to write special and more complex slot data.
     ///
@@ -237,19 +255,31 @@ impl Message {
     /// [`InvalidChecksum`]: MessageParseError::InvalidChecksum
     /// [`InvalidFormat`]: MessageParseError::InvalidFormat
     pub fn parse(buf: &[u8]) -> Result<Self, MessageParseError> {
-        let opc = buf[0];
+        let opc = match buf.first() {
+            Some(opc) => *opc,
+            None => return Err(MessageParseError::unexpected_end(0x00, 0)),
+        };
         // We calculate the length of the remaining message to read
         let len = match opc & 0xE0 {
             0x80 => 2,
             0xA0 => 4,
             0xC0 => 6,
-            0xE0 => buf[1] as usize,
-            _ => return Err(MessageParseError::UnknownOpcode(opc)),
+            0xE0 if buf.len() > 1 => buf[1] as usize,
+            0xE0 => return Err(MessageParseError::unexpected_end(opc, 1).with_frame(buf)),
+            _ => return Err(MessageParseError::unknown_opcode(opc).with_frame(&buf[..1])),
         };
 
+        if len < 2 {
+            return Err(MessageParseError::unexpected_end(opc, 1).with_frame(&buf[..2]));
+        }
+        if buf.len() < len {
+            return Err(MessageParseError::unexpected_end(opc, buf.len()).with_frame(buf));
+        }
+
         // validate checksum
-        if !Self::validate(&buf[0..len]) {
-            return Err(MessageParseError::InvalidChecksum(opc));
+        let frame = &buf[0..len];
+        if !Self::validate(frame) {
+            return Err(MessageParseError::invalid_checksum(opc, len - 1).with_frame(frame));
         }
 
         // call appropriate parse function
@@ -259,6 +289,194 @@ impl Message {
             6 => Self::parse6(opc, &buf[1..5]),
             var => Self::parse_var(opc, &buf[1..var - 1]),
         }
+        .map_err(|err| err.with_frame(frame))
+    }
+
+    /// Parses a model railroads message from `buf` like [`Message::parse()`],
+    /// but handles slot data frames longer than the standard 14 bytes as configured by `extra_bytes`.
+    ///
+    /// Some clone command stations send [`Message::SlRdData`] with vendor extension bytes
+    /// appended after the standard slot data.
+    ///
+    /// # Returns
+    ///
+    /// The parsed message with the vendor extension bytes, which are empty for standard frames.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Message::parse()`]. Longer slot data frames are an
+    /// [`MessageParseError::UnexpectedEnd`] if rejected.
+    pub fn parse_with(
+        buf: &[u8],
+        extra_bytes: ExtraBytes,
+    ) -> Result<(Self, Vec<u8>), MessageParseError> {
+        match *buf {
+            [0xE7, len, ..]
+                if extra_bytes == ExtraBytes::Attach
+                    && len as usize > 14
+                    && buf.len() >= len as usize =>
+            {
+                let len = len as usize;
+                let frame = &buf[0..len];
+                if !Self::validate(frame) {
+                    return Err(
+                        MessageParseError::invalid_checksum(0xE7, len - 1).with_frame(frame)
+                    );
+                }
+
+                // We parse the standard part as if it was a standard length frame
+                let mut args = buf[1..13].to_vec();
+                args[0] = 0x0E;
+                let message = Self::parse_var(0xE7, &args).map_err(|err| err.with_frame(frame))?;
+
+                Ok((message, buf[13..len - 1].to_vec()))
+            }
+            _ => Self::parse(buf).map(|message| (message, Vec::new())),
+        }
+    }
+
+    /// Parses a model railroads message from `buf` like [`Message::parse()`], as configured by `options`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Message::parse()`]. In strict mode additionally an [`InvalidFormat`],
+    /// if the frame sets a reserved bit or holds a value not documented by the protocol.
+    ///
+    /// [`InvalidFormat`]: MessageParseError::InvalidFormat
+    pub fn parse_with_options(
+        buf: &[u8],
+        options: ParseOptions,
+    ) -> Result<Self, MessageParseError> {
+        let message = Self::parse(buf)?;
+        if options.strict {
+            // Frames of variable length may be shorter than the message encodes to
+            let len = Self::frame_len(buf).unwrap_or(buf.len());
+            message.check_strict(&buf[..len])?;
+        }
+        Ok(message)
+    }
+
+    /// # Returns
+    ///
+    /// The length of the frame starting `buf` as told by its op code or count byte,
+    /// or `None` if `buf` starts with no op code or is too short to tell the length.
+    pub(crate) fn frame_len(buf: &[u8]) -> Option<usize> {
+        match *buf.first()? & 0xE0 {
+            0x80 => Some(2),
+            0xA0 => Some(4),
+            0xC0 => Some(6),
+            0xE0 => buf.get(1).map(|len| *len as usize),
+            _ => None,
+        }
+    }
+
+    /// Checks that the `frame` this message was parsed from sets no reserved bit
+    /// and holds only values documented by the protocol.
+    ///
+    /// # Errors
+    ///
+    /// - [`InvalidFormat`]: With the offset of the first violating byte
+    ///
+    /// [`InvalidFormat`]: MessageParseError::InvalidFormat
+    pub(crate) fn check_strict(&self, frame: &[u8]) -> Result<(), MessageParseError> {
+        // Only the op code may have its highest bit set
+        if let Some(offset) = frame[1..frame.len() - 1]
+            .iter()
+            .position(|byte| byte & 0x80 != 0)
+        {
+            return Err(MessageParseError::invalid_format(
+                "data byte with the highest bit set".to_string(),
+                offset + 1,
+            )
+            .with_frame(frame));
+        }
+
+        let reserved_decoder =
+            |stat1: Stat1Arg| matches!(stat1.decoder_type(), DecoderType::Reserved(_));
+        let violation = match *self {
+            Message::SwReq(switch) | Message::SwState(switch) | Message::SwAck(switch)
+                if switch.reserved() != 0 =>
+            {
+                Some(("reserved switch bits set", 2))
+            }
+            Message::SlotStat1(_, stat1) if reserved_decoder(stat1) => {
+                Some(("reserved decoder type", 2))
+            }
+            Message::ThrottleStatus(throttle)
+                if matches!(throttle.receiver(), ReceiverType::Unknown(_)) =>
+            {
+                Some(("unknown receiver type", 1))
+            }
+            Message::SlRdData(_, stat1, _, _, _, trk, stat2, ..)
+            | Message::WrSlData(WrSlDataStructure::DataGeneral(
+                _,
+                stat1,
+                stat2,
+                _,
+                _,
+                _,
+                trk,
+                ..,
+            )) => {
+                if reserved_decoder(stat1) {
+                    Some(("reserved decoder type", 3))
+                } else if trk.reserved() != 0 {
+                    Some(("reserved track bits set", 7))
+                } else if stat2.reserved() != 0 {
+                    Some(("reserved stat2 bits set", 8))
+                } else {
+                    None
+                }
+            }
+            Message::ProgrammingFinalResponse(
+                _,
+                _,
+                _,
+                _,
+                _,
+                trk,
+                _,
+                _,
+                _,
+                pcmd,
+                pstat,
+                _,
+                cv_data,
+            ) => {
+                if pcmd.reserved() != 0 {
+                    Some(("reserved programming command bits set", 3))
+                } else if pstat.reserved() != 0 {
+                    Some(("reserved programming status bits set", 4))
+                } else if trk.reserved() != 0 {
+                    Some(("reserved track bits set", 7))
+                } else if cv_data.reserved() != 0 {
+                    Some(("reserved cv bits set", 8))
+                } else {
+                    None
+                }
+            }
+            Message::WrSlData(WrSlDataStructure::DataPt(pcmd, _, trk, cv_data)) => {
+                if pcmd.reserved() != 0 {
+                    Some(("reserved programming command bits set", 3))
+                } else if trk.reserved() != 0 {
+                    Some(("reserved track bits set", 7))
+                } else if cv_data.reserved() != 0 {
+                    Some(("reserved cv bits set", 8))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match violation {
+            Some((message, offset)) => Err(MessageParseError::invalid_format(
+                message.to_string(),
+                offset,
+            )
+            .with_frame(frame)),
+            None => Ok(()),
+        }
     }
 
     /// Parse all messages of two bytes length. As the second byte is every time the checksum,
@@ -275,7 +493,7 @@ impl Message {
             0x83 => Ok(Self::GpOn),
             0x82 => Ok(Self::GpOff),
             0x81 => Ok(Self::Busy),
-            _ => Err(MessageParseError::UnknownOpcode(opc)),
+            _ => Err(MessageParseError::unknown_opcode(opc)),
         }
     }
 
@@ -292,7 +510,7 @@ impl Message {
     /// [`UnexpectedEnd`]: MessageParseError::UnexpectedEnd
     fn parse4(opc: u8, args: &[u8]) -> Result<Self, MessageParseError> {
         if args.len() != 2 {
-            return Err(MessageParseError::UnexpectedEnd(opc));
+            return Err(MessageParseError::unexpected_end(opc, 1 + args.len()));
         }
         match opc {
             0xBF => Ok(Self::LocoAdr(AddressArg::parse(args[0], args[1]))),
@@ -338,7 +556,7 @@ impl Message {
                 SlotArg::parse(args[0]),
                 SpeedArg::parse(args[1]),
             )),
-            _ => Err(MessageParseError::UnknownOpcode(opc)),
+            _ => Err(MessageParseError::unknown_opcode(opc)),
         }
     }
 
@@ -357,7 +575,7 @@ impl Message {
     /// [`InvalidFormat`]: MessageParseError::InvalidFormat
     fn parse6(opc: u8, args: &[u8]) -> Result<Self, MessageParseError> {
         if args.len() != 4 {
-            return Err(MessageParseError::UnexpectedEnd(opc));
+            return Err(MessageParseError::unexpected_end(opc, 1 + args.len()));
         }
         match opc {
             0xD0 => Ok(Self::MultiSense(
@@ -366,17 +584,36 @@ impl Message {
             )),
             0xD4 => {
                 if 0x20 != args[0] {
-                    return Err(MessageParseError::InvalidFormat(format!(
-                        "Expected first arg of UhliFun to be 0x20 got {:02x}",
-                        args[0]
-                    )));
+                    return Err(MessageParseError::invalid_format(
+                        format!(
+                            "Expected first arg of UhliFun to be 0x20 got {:02x}",
+                            args[0]
+                        ),
+                        1,
+                    ));
                 }
                 Ok(Self::UhliFun(
                     SlotArg::parse(args[1]),
                     FunctionArg::parse(args[2], args[3]),
                 ))
             }
-            _ => Err(MessageParseError::UnknownOpcode(opc)),
+            0xDF => match args[0] {
+                // A receiver query holds no arguments, so it is encoded back unchanged
+                0x00 => match args[1..].iter().position(|arg| *arg != 0x00) {
+                    None => Ok(Self::ReceiverQuery),
+                    Some(offset) => Err(MessageParseError::invalid_format(
+                        format!(
+                            "Expected the arguments of ReceiverQuery to be zero got {:02x}",
+                            args[offset + 1]
+                        ),
+                        offset + 2,
+                    )),
+                },
+                receiver => Ok(Self::ThrottleStatus(ThrottleStatusArg::parse(
+                    receiver, args[1], args[2], args[3],
+                ))),
+            },
+            _ => Err(MessageParseError::unknown_opcode(opc)),
         }
     }
 
@@ -394,22 +631,25 @@ impl Message {
     /// [`UnexpectedEnd`]: MessageParseError::UnexpectedEnd
     /// [`InvalidFormat`]: MessageParseError::InvalidFormat
     fn parse_var(opc: u8, args: &[u8]) -> Result<Self, MessageParseError> {
-        if args.len() + 2 != args[0] as usize {
-            return Err(MessageParseError::UnexpectedEnd(opc));
+        if args.first().map(|len| *len as usize) != Some(args.len() + 2) {
+            return Err(MessageParseError::unexpected_end(opc, 1));
         }
 
         match opc {
             0xED => {
                 if args.len() != 9 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
                 if args[1] != 0x7F {
-                    return Err(MessageParseError::InvalidFormat(format!(
-                        "The check byte of the received message whith opcode {:x} was invalid. \
-                            Expected 0x7F got {:02x}",
-                        opc, args[1]
-                    )));
+                    return Err(MessageParseError::invalid_format(
+                        format!(
+                            "The check byte of the received message whith opcode {:x} was invalid. \
+                                Expected 0x7F got {:02x}",
+                            opc, args[1]
+                        ),
+                        2,
+                    ));
                 }
 
                 Ok(Self::ImmPacket(ImArg::parse(
@@ -418,7 +658,7 @@ impl Message {
             }
             0xEF => {
                 if args.len() != 12 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
                 Ok(Self::WrSlData(WrSlDataStructure::parse(
@@ -428,10 +668,10 @@ impl Message {
             }
             0xE7 => {
                 if args.len() != 12 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
-                if args[1] == 0x7C {
+                if args[1] & 0x7F == 0x7C {
                     Ok(Self::ProgrammingFinalResponse(
                         SlotArg::parse(args[1]),
                         Stat1Arg::parse(args[2]),
@@ -463,27 +703,24 @@ impl Message {
             }
             0xE6 => {
                 if args.len() < 2 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
                 Ok(Message::ProgrammingAborted(ProgrammingAbortedArg::parse(
                     args[0],
                     &args[1..],
                 )))
-            },
+            }
             0xE4 => {
                 if args.len() < 2 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
-                Ok(Self::Rep(match RepStructure::parse(args[0], &args[1..]) {
-                    Err(err) => return Err(err),
-                    Ok(rep) => rep,
-                }))
-            },
+                Ok(Self::Rep(RepStructure::parse(args[0], &args[1..])?))
+            }
             0xE5 => {
                 if args.len() != 14 {
-                    return Err(MessageParseError::UnexpectedEnd(opc));
+                    return Err(MessageParseError::unexpected_end(opc, 1));
                 }
 
                 Ok(Self::PeerXfer(
@@ -495,69 +732,152 @@ impl Message {
                     ),
                 ))
             }
-            _ => Err(MessageParseError::UnknownOpcode(opc)),
+            _ => Err(MessageParseError::unknown_opcode(opc)),
         }
     }
 
     /// Validates the `msg` by xor-ing all bytes and checking for the result to be 0xFF.
     fn validate(msg: &[u8]) -> bool {
-        return msg.iter().fold(0, |acc, &b| acc ^ b) == 0xFF;
+        msg.iter().fold(0, |acc, &b| acc ^ b) == 0xFF
     }
 
     /// Parses the given [`Message`] to a [`Vec<u8>`] using the model railroads protocol.
+    ///
+    /// Use [`Message::write_to()`] to encode the message without allocating.
     pub fn to_message(self) -> Vec<u8> {
-        // Parses the message
-        let mut message = match self {
-            Message::Idle => vec![0x85_u8],
-            Message::GpOn => vec![0x83_u8],
-            Message::GpOff => vec![0x82_u8],
-            Message::Busy => vec![0x81_u8],
-            Message::LocoAdr(adr_arg) => vec![0xBF_u8, adr_arg.adr2(), adr_arg.adr1()],
-            Message::SwAck(switch_arg) => vec![0xBD_u8, switch_arg.sw1(), switch_arg.sw2()],
-            Message::SwState(switch_arg) => vec![0xBC_u8, switch_arg.sw1(), switch_arg.sw2()],
-            Message::RqSlData(slot_arg) => vec![0xBB_u8, slot_arg.slot(), 0x00_u8],
-            Message::MoveSlots(src, dst) => vec![0xBA_u8, src.slot(), dst.slot()],
-            Message::LinkSlots(sl1, sl2) => vec![0xB9_u8, sl1.slot(), sl2.slot()],
-            Message::UnlinkSlots(sl1, sl2) => vec![0xB8_u8, sl1.slot(), sl2.slot()],
-            Message::ConsistFunc(slot, dirf) => vec![0xB6_u8, slot.slot(), dirf.dirf()],
-            Message::SlotStat1(slot, stat1) => vec![0xB5_u8, slot.slot(), stat1.stat1()],
-            Message::LongAck(lopc, ack1) => vec![0xB4_u8, lopc.lopc(), ack1.ack1()],
-            Message::InputRep(input) => vec![0xB2_u8, input.in1(), input.in2()],
-            Message::SwRep(sn_arg) => vec![0xB1_u8, sn_arg.sn1(), sn_arg.sn2()],
-            Message::SwReq(sw) => vec![0xB0_u8, sw.sw1(), sw.sw2()],
-            Message::LocoSnd(slot, snd) => vec![0xA2_u8, slot.slot(), snd.snd()],
-            Message::LocoDirf(slot, dirf) => vec![0xA1_u8, slot.slot(), dirf.dirf()],
-            Message::LocoSpd(slot, spd) => vec![0xA0_u8, slot.slot(), spd.spd()],
-            Message::MultiSense(multi_sense, address) => vec![
+        let mut message = vec![0; self.encoded_len()];
+        // The buffer is exactly as long as the message, so writing can not fail
+        let _ = self.write_to(&mut message);
+        message
+    }
+
+    /// # Returns
+    ///
+    /// The count of bytes of this message including its checksum, as written by
+    /// [`Message::write_to()`]. This is at most [`MAX_MESSAGE_LEN`].
+    pub fn encoded_len(&self) -> usize {
+        match self.opc() & 0xE0 {
+            0x80 => 2,
+            0xA0 => 4,
+            0xC0 => 6,
+            // The variable length messages have a fixed length per message type
+            _ => match *self {
+                Message::ImmPacket(..) => 11,
+                Message::Rep(RepStructure::LissyIrReport(..))
+                | Message::Rep(RepStructure::WheelcntReport(..)) => 8,
+                Message::Rep(RepStructure::RFID5Report(..)) => 12,
+                Message::Rep(RepStructure::RFID7Report(..)) => 14,
+                Message::ProgrammingAborted(args) => match args.arg_len {
+                    0x10 => 16,
+                    _ => 21,
+                },
+                Message::PeerXfer(..) => 16,
+                _ => 14,
+            },
+        }
+    }
+
+    /// Writes this message including its checksum to the start of `buf`
+    /// using the model railroads protocol, without allocating.
+    ///
+    /// # Returns
+    ///
+    /// The count of written bytes, see [`Message::encoded_len()`].
+    ///
+    /// # Errors
+    ///
+    /// [`EncodeError::BufferTooSmall`] if `buf` can not hold the message. Nothing is written then.
+    pub fn write_to(self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let len = self.encoded_len();
+        if buf.len() < len {
+            return Err(EncodeError::BufferTooSmall(len, buf.len()));
+        }
+
+        let (body, checksum) = buf[..len].split_at_mut(len - 1);
+        match self {
+            Message::Idle => body.copy_from_slice(&[0x85_u8]),
+            Message::GpOn => body.copy_from_slice(&[0x83_u8]),
+            Message::GpOff => body.copy_from_slice(&[0x82_u8]),
+            Message::Busy => body.copy_from_slice(&[0x81_u8]),
+            Message::LocoAdr(adr_arg) => {
+                body.copy_from_slice(&[0xBF_u8, adr_arg.adr2(), adr_arg.adr1()])
+            }
+            Message::SwAck(switch_arg) => {
+                body.copy_from_slice(&[0xBD_u8, switch_arg.sw1(), switch_arg.sw2()])
+            }
+            Message::SwState(switch_arg) => {
+                body.copy_from_slice(&[0xBC_u8, switch_arg.sw1(), switch_arg.sw2()])
+            }
+            Message::RqSlData(slot_arg) => {
+                body.copy_from_slice(&[0xBB_u8, slot_arg.slot(), 0x00_u8])
+            }
+            Message::MoveSlots(src, dst) => {
+                body.copy_from_slice(&[0xBA_u8, src.slot(), dst.slot()])
+            }
+            Message::LinkSlots(sl1, sl2) => {
+                body.copy_from_slice(&[0xB9_u8, sl1.slot(), sl2.slot()])
+            }
+            Message::UnlinkSlots(sl1, sl2) => {
+                body.copy_from_slice(&[0xB8_u8, sl1.slot(), sl2.slot()])
+            }
+            Message::ConsistFunc(slot, dirf) => {
+                body.copy_from_slice(&[0xB6_u8, slot.slot(), dirf.dirf()])
+            }
+            Message::SlotStat1(slot, stat1) => {
+                body.copy_from_slice(&[0xB5_u8, slot.slot(), stat1.stat1()])
+            }
+            Message::LongAck(lopc, ack1) => {
+                body.copy_from_slice(&[0xB4_u8, lopc.lopc(), ack1.ack1()])
+            }
+            Message::InputRep(input) => body.copy_from_slice(&[0xB2_u8, input.in1(), input.in2()]),
+            Message::SwRep(sn_arg) => body.copy_from_slice(&[0xB1_u8, sn_arg.sn1(), sn_arg.sn2()]),
+            Message::SwReq(sw) => body.copy_from_slice(&[0xB0_u8, sw.sw1(), sw.sw2()]),
+            Message::LocoSnd(slot, snd) => body.copy_from_slice(&[0xA2_u8, slot.slot(), snd.snd()]),
+            Message::LocoDirf(slot, dirf) => {
+                body.copy_from_slice(&[0xA1_u8, slot.slot(), dirf.dirf()])
+            }
+            Message::LocoSpd(slot, spd) => body.copy_from_slice(&[0xA0_u8, slot.slot(), spd.spd()]),
+            Message::MultiSense(multi_sense, address) => body.copy_from_slice(&[
                 0xD0_u8,
                 multi_sense.m_high(),
                 multi_sense.zas(),
                 address.adr2(),
                 address.adr1(),
-            ],
-            Message::UhliFun(slot, function) => vec![
+            ]),
+            Message::UhliFun(slot, function) => body.copy_from_slice(&[
                 0xD4_u8,
                 0x20_u8,
                 slot.slot(),
                 function.group(),
                 function.function(),
-            ],
-            Message::WrSlData(wr_slot_data_arg) => wr_slot_data_arg.to_message(),
-            Message::SlRdData(slot, stat1, adr, spd, dirf, trk, stat2, snd, id) => vec![
-                0xE7_u8,
-                0x0E_u8,
-                slot.slot(),
-                stat1.stat1(),
-                adr.adr1(),
-                spd.spd(),
-                dirf.dirf(),
-                trk.trk_arg(),
-                stat2.stat2(),
-                adr.adr2(),
-                snd.snd(),
-                id.id1(),
-                id.id2(),
-            ],
+            ]),
+            Message::ReceiverQuery => {
+                body.copy_from_slice(&[0xDF_u8, 0x00_u8, 0x00_u8, 0x00_u8, 0x00_u8])
+            }
+            Message::ThrottleStatus(throttle) => body.copy_from_slice(&[
+                0xDF_u8,
+                throttle.receiver_byte(),
+                throttle.id1(),
+                throttle.id2(),
+                throttle.status(),
+            ]),
+            Message::WrSlData(wr_slot_data_arg) => wr_slot_data_arg.write_to(body),
+            Message::SlRdData(slot, stat1, adr, spd, dirf, trk, stat2, snd, id) => body
+                .copy_from_slice(&[
+                    0xE7_u8,
+                    0x0E_u8,
+                    slot.slot(),
+                    stat1.stat1(),
+                    adr.adr1(),
+                    spd.spd(),
+                    dirf.dirf(),
+                    trk.trk_arg(),
+                    stat2.stat2(),
+                    adr.adr2(),
+                    snd.snd(),
+                    id.id1(),
+                    id.id2(),
+                ]),
             Message::ProgrammingFinalResponse(
                 slot,
                 stat1,
@@ -572,7 +892,7 @@ impl Message {
                 stat,
                 opsa,
                 cv_data,
-            ) => vec![
+            ) => body.copy_from_slice(&[
                 0xE7_u8,
                 0x0E_u8,
                 slot.slot(),
@@ -586,9 +906,9 @@ impl Message {
                 snd.snd() | cv_data.data7(),
                 id.id1(),
                 id.id2(),
-            ],
-            Message::ProgrammingAborted(args) => args.to_message(),
-            Message::ImmPacket(im) => vec![
+            ]),
+            Message::ProgrammingAborted(args) => args.write_to(body),
+            Message::ImmPacket(im) => body.copy_from_slice(&[
                 0xED_u8,
                 0x0B_u8,
                 0x7F_u8,
@@ -599,36 +919,36 @@ impl Message {
                 im.im3(),
                 im.im4(),
                 im.im5(),
-            ],
+            ]),
             Message::Rep(rep) => match rep {
-                RepStructure::RFID7Report(report) => report.to_message(),
-                RepStructure::RFID5Report(report) => report.to_message(),
-                RepStructure::LissyIrReport(report) => report.to_message(),
-                RepStructure::WheelcntReport(report) => report.to_message(),
+                RepStructure::RFID7Report(report) => report.write_to(body),
+                RepStructure::RFID5Report(report) => report.write_to(body),
+                RepStructure::LissyIrReport(report) => report.write_to(body),
+                RepStructure::WheelcntReport(report) => report.write_to(body),
             },
-            Message::PeerXfer(src, dst, pxct) => vec![
+            Message::PeerXfer(src, dst, pxct) => body.copy_from_slice(&[
                 0xE5,
                 0x10,
                 src.slot(),
                 dst.dst_low(),
                 dst.dst_high(),
                 pxct.pxct1(),
-                pxct.d1(),
-                pxct.d2(),
-                pxct.d3(),
-                pxct.d4(),
+                pxct.d1() & 0x7F,
+                pxct.d2() & 0x7F,
+                pxct.d3() & 0x7F,
+                pxct.d4() & 0x7F,
                 pxct.pxct2(),
-                pxct.d5(),
-                pxct.d6(),
-                pxct.d7(),
-                pxct.d8(),
-            ],
+                pxct.d5() & 0x7F,
+                pxct.d6() & 0x7F,
+                pxct.d7() & 0x7F,
+                pxct.d8() & 0x7F,
+            ]),
         };
 
         // Appending checksum to the created message
-        message.push(Self::check_sum(&message));
+        checksum[0] = Self::check_sum(body);
 
-        message
+        Ok(len)
     }
 
     /// Calculates the check sum for the given `msg`.
@@ -670,6 +990,7 @@ impl Message {
                 | 0xA0
                 | 0xD0
                 | 0xD4
+                | 0xDF
                 | 0xEF
                 | 0xE7
                 | 0xE6
@@ -706,6 +1027,7 @@ impl Message {
             Message::LocoSpd(..) => 0xA0,
             Message::MultiSense(..) => 0xD0,
             Message::UhliFun(..) => 0xD4,
+            Message::ReceiverQuery | Message::ThrottleStatus(..) => 0xDF,
             Message::WrSlData(..) => 0xEF,
             Message::SlRdData(..) => 0xE7,
             Message::ProgrammingFinalResponse(..) => 0xE7,
@@ -721,6 +1043,36 @@ impl Message {
         0x01 & self.opc() == 0x01
     }
 
+    /// # Returns
+    ///
+    /// The priority delay class of this message in steps of the priority backoff.
+    /// When the bus is busy, the messages with lower classes are written first.
+    ///
+    /// The classes follow the LocoNet carrier detect backoff scheme:
+    ///
+    /// - `0`: Power and emergency handling, like [`Message::GpOff`]
+    /// - `2`: Answers to requests of other devices, like [`Message::LongAck`]
+    /// - `6`: Driving and switching commands, like [`Message::LocoSpd`]
+    /// - `10`: All other requests
+    /// - `20`: Programming, peer transfers and bulk packets, like [`Message::ImmPacket`]
+    pub fn priority_class(&self) -> u32 {
+        match self {
+            Message::Idle | Message::GpOff | Message::GpOn => 0,
+            Message::LongAck(..) | Message::SlRdData(..) | Message::Busy => 2,
+            Message::LocoSpd(..)
+            | Message::LocoDirf(..)
+            | Message::LocoSnd(..)
+            | Message::SwReq(..)
+            | Message::UhliFun(..) => 6,
+            Message::WrSlData(..)
+            | Message::PeerXfer(..)
+            | Message::ImmPacket(..)
+            | Message::ProgrammingFinalResponse(..)
+            | Message::ProgrammingAborted(..) => 20,
+            _ => 10,
+        }
+    }
+
     /// Indicates if a request with the specified slot
     /// data was awaited after that message.
     pub fn await_slot_data(&self) -> bool {
@@ -734,3 +1086,25 @@ impl Message {
         )
     }
 }
+
+/// How to handle slot data frames longer than the standard length, see [`Message::parse_with()`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ExtraBytes {
+    /// Rejects the frame as malformed.
+    Reject,
+    /// Parses the standard part of the frame and attaches the extra bytes as vendor extension.
+    Attach,
+}
+
+/// How strictly to parse messages, see [`Message::parse_with_options()`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseOptions {
+    /// Rejects frames setting reserved bits or holding values not documented by the protocol.
+    ///
+    /// If not strict, unknown bits and values are kept in raw form,
+    /// so the parsed messages encode to the same bytes again.
+    pub strict: bool,
+}
@@ -0,0 +1,330 @@
+use crate::args::{AddressArg, InArg, SlotArg, SpeedArg, SwitchArg, SwitchDirection};
+use crate::loco_controller::{LocoDriveController, LocoDriveMessage, LocoDriveReceiver};
+use crate::message_ref::MessageRef;
+use crate::protocol::Message;
+use pyo3::exceptions::{PyConnectionError, PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::future_into_py;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+/// The address of a locomotive, exposed to Python as `AddressArg`.
+#[pyclass(name = "AddressArg", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PyAddressArg(AddressArg);
+
+#[pymethods]
+impl PyAddressArg {
+    #[new]
+    fn new(address: u16) -> Self {
+        PyAddressArg(AddressArg::new(address))
+    }
+
+    #[getter]
+    fn address(&self) -> u16 {
+        self.0.address()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("AddressArg({})", self.0.address())
+    }
+}
+
+/// A slot of the command station, exposed to Python as `SlotArg`.
+#[pyclass(name = "SlotArg", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PySlotArg(SlotArg);
+
+#[pymethods]
+impl PySlotArg {
+    #[new]
+    fn new(slot: u8) -> Self {
+        PySlotArg(SlotArg::new(slot))
+    }
+
+    #[getter]
+    fn slot(&self) -> u8 {
+        self.0.slot()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SlotArg({})", self.0.slot())
+    }
+}
+
+/// The speed of a slot, exposed to Python as `SpeedArg`.
+/// Zero stops and one stops in emergency, all other steps drive.
+#[pyclass(name = "SpeedArg", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PySpeedArg(SpeedArg);
+
+#[pymethods]
+impl PySpeedArg {
+    #[new]
+    fn new(speed: u8) -> Self {
+        PySpeedArg(SpeedArg::new(speed))
+    }
+
+    #[getter]
+    fn speed(&self) -> u8 {
+        self.0.get_spd()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SpeedArg({})", self.0.get_spd())
+    }
+}
+
+/// A turnout with its requested direction, exposed to Python as `SwitchArg`.
+#[pyclass(name = "SwitchArg", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PySwitchArg(SwitchArg);
+
+#[pymethods]
+impl PySwitchArg {
+    #[new]
+    #[pyo3(signature = (address, straight, on = true))]
+    fn new(address: u16, straight: bool, on: bool) -> Self {
+        let direction = match straight {
+            true => SwitchDirection::Straight,
+            false => SwitchDirection::Curved,
+        };
+        PySwitchArg(SwitchArg::new(address, direction, on))
+    }
+
+    #[getter]
+    fn address(&self) -> u16 {
+        self.0.address()
+    }
+
+    #[getter]
+    fn straight(&self) -> bool {
+        self.0.direction() == SwitchDirection::Straight
+    }
+
+    #[getter]
+    fn on(&self) -> bool {
+        self.0.state()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SwitchArg({}, straight={}, on={})",
+            self.address(),
+            self.straight(),
+            self.on()
+        )
+    }
+}
+
+/// A sensor report, exposed to Python as `InArg`.
+#[pyclass(name = "InArg", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PyInArg(InArg);
+
+#[pymethods]
+impl PyInArg {
+    #[getter]
+    fn address(&self) -> u16 {
+        self.0.address()
+    }
+
+    #[getter]
+    fn occupied(&self) -> bool {
+        self.0.sensor_level() == crate::args::SensorLevel::High
+    }
+
+    fn __repr__(&self) -> String {
+        format!("InArg({}, occupied={})", self.address(), self.occupied())
+    }
+}
+
+/// A model railroad message, exposed to Python as `Message`.
+///
+/// Messages are created by their named constructors or parsed from bytes,
+/// so only valid frames are send.
+#[pyclass(name = "Message", frozen, eq, hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PyMessage(pub Message);
+
+#[pymethods]
+impl PyMessage {
+    /// Parses the message from the bytes of its frame.
+    #[staticmethod]
+    fn parse(frame: &[u8]) -> PyResult<Self> {
+        Message::parse(frame)
+            .map(PyMessage)
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    #[staticmethod]
+    fn gp_on() -> Self {
+        PyMessage(Message::GpOn)
+    }
+
+    #[staticmethod]
+    fn gp_off() -> Self {
+        PyMessage(Message::GpOff)
+    }
+
+    #[staticmethod]
+    fn idle() -> Self {
+        PyMessage(Message::Idle)
+    }
+
+    #[staticmethod]
+    fn loco_adr(address: PyAddressArg) -> Self {
+        PyMessage(Message::LocoAdr(address.0))
+    }
+
+    #[staticmethod]
+    fn rq_sl_data(slot: PySlotArg) -> Self {
+        PyMessage(Message::RqSlData(slot.0))
+    }
+
+    #[staticmethod]
+    fn loco_spd(slot: PySlotArg, speed: PySpeedArg) -> Self {
+        PyMessage(Message::LocoSpd(slot.0, speed.0))
+    }
+
+    #[staticmethod]
+    fn sw_req(switch: PySwitchArg) -> Self {
+        PyMessage(Message::SwReq(switch.0))
+    }
+
+    /// The bytes of the frame including its checksum.
+    #[pyo3(name = "to_bytes")]
+    fn frame(&self) -> Vec<u8> {
+        self.0.to_message()
+    }
+
+    #[getter]
+    fn opc(&self) -> u8 {
+        self.0.opc()
+    }
+
+    /// The name of the message, like `LocoSpd`.
+    #[getter]
+    fn kind(&self) -> String {
+        let debug = format!("{:?}", self.0);
+        debug
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[getter]
+    fn slot(&self) -> Option<PySlotArg> {
+        self.with_ref(|message| message.slot().map(PySlotArg))
+    }
+
+    #[getter]
+    fn speed(&self) -> Option<PySpeedArg> {
+        self.with_ref(|message| message.speed().map(PySpeedArg))
+    }
+
+    #[getter]
+    fn address(&self) -> Option<PyAddressArg> {
+        self.with_ref(|message| message.address().map(PyAddressArg))
+    }
+
+    #[getter]
+    fn switch(&self) -> Option<PySwitchArg> {
+        self.with_ref(|message| message.switch().map(PySwitchArg))
+    }
+
+    #[getter]
+    fn sensor(&self) -> Option<PyInArg> {
+        self.with_ref(|message| message.sensor().map(PyInArg))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+impl PyMessage {
+    /// # Returns
+    ///
+    /// The result of `inspect` called with the frame of this message.
+    fn with_ref<T>(&self, inspect: impl FnOnce(MessageRef<'_>) -> Option<T>) -> Option<T> {
+        let frame = self.0.to_message();
+        MessageRef::new(&frame).ok().and_then(inspect)
+    }
+}
+
+/// A [`LocoDriveController`] driven by asyncio, exposed to Python as `Controller`.
+///
+/// All methods return awaitables running on a tokio runtime in the background.
+#[pyclass(name = "Controller")]
+pub struct PyController {
+    /// The controller talking to the model railroad
+    controller: Arc<Mutex<LocoDriveController>>,
+    /// The messages received from the model railroad
+    messages: Arc<Mutex<LocoDriveReceiver>>,
+}
+
+#[pymethods]
+impl PyController {
+    /// Connects to the serial port `port_name` with `baud_rate`.
+    #[staticmethod]
+    fn connect(py: Python<'_>, port_name: String, baud_rate: u32) -> PyResult<Bound<'_, PyAny>> {
+        future_into_py(py, async move {
+            let controller = LocoDriveController::builder(&port_name, baud_rate)
+                .build()
+                .await
+                .map_err(|err| PyIOError::new_err(err.to_string()))?;
+            let messages = controller.subscribe();
+            Ok(PyController {
+                controller: Arc::new(Mutex::new(controller)),
+                messages: Arc::new(Mutex::new(messages)),
+            })
+        })
+    }
+
+    /// Sends `message` and completes when the model railroad echoed it.
+    fn send<'py>(&self, py: Python<'py>, message: PyMessage) -> PyResult<Bound<'py, PyAny>> {
+        let controller = self.controller.clone();
+        future_into_py(py, async move {
+            controller
+                .lock()
+                .await
+                .send_message(message.0)
+                .await
+                .map_err(|err| PyIOError::new_err(err.to_string()))
+        })
+    }
+
+    /// Receives the next message from the model railroad.
+    /// Messages missed by receiving too slowly are skipped.
+    fn recv<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let messages = self.messages.clone();
+        future_into_py(py, async move {
+            let mut messages = messages.lock().await;
+            loop {
+                match messages.recv().await {
+                    Ok(LocoDriveMessage::Message(message)) => return Ok(PyMessage(message)),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => {
+                        return Err(PyConnectionError::new_err("controller closed"))
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// The `locodrive` Python module.
+#[pymodule]
+pub(crate) fn locodrive(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyAddressArg>()?;
+    module.add_class::<PySlotArg>()?;
+    module.add_class::<PySpeedArg>()?;
+    module.add_class::<PySwitchArg>()?;
+    module.add_class::<PyInArg>()?;
+    module.add_class::<PyMessage>()?;
+    module.add_class::<PyController>()?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::args::SlotArg;
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::CommandHandle;
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Consolidates the periodic slot refreshes of many throttle clients.
+///
+/// Network throttles refresh the speed of their locomotives periodically to keep the slots
+/// in use. When a server bridges many clients to the model railroad, passing every refresh
+/// multiplies the traffic by the count of clients controlling the same slot.
+/// The consolidator is shared by all client tasks of a server and passes at most one refresh
+/// per slot and interval to the bus, regardless of the count of clients.
+///
+/// Only refreshes should be passed through the consolidator.
+/// Changes of speed or functions must be sent directly, so they are never dropped.
+#[derive(Debug)]
+pub struct RefreshConsolidator {
+    /// The minimal time between two refreshes of the same slot
+    interval: Duration,
+    /// When each slot was refreshed last
+    refreshed: Mutex<HashMap<SlotArg, Instant>>,
+}
+
+impl RefreshConsolidator {
+    /// Creates a consolidator passing at most one refresh per slot every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        RefreshConsolidator {
+            interval,
+            refreshed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The minimal time between two refreshes of the same slot.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Decides whether a refresh of `slot` is passed to the bus now.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the refresh should be sent, which is noted as the slots last refresh.
+    /// `false` if the slot was refreshed within the interval, so the refresh should be dropped.
+    pub fn admit(&self, slot: SlotArg) -> bool {
+        self.admit_at(slot, Instant::now())
+    }
+
+    /// Decides like [`RefreshConsolidator::admit()`] whether a refresh of `slot`
+    /// is passed to the bus at the time `now`.
+    pub fn admit_at(&self, slot: SlotArg, now: Instant) -> bool {
+        let mut refreshed = self.refreshed.lock().unwrap();
+        match refreshed.get(&slot) {
+            Some(last) if now.saturating_duration_since(*last) < self.interval => false,
+            _ => {
+                refreshed.insert(slot, now);
+                true
+            }
+        }
+    }
+
+    /// Forgets when `slot` was refreshed, so its next refresh is passed immediately.
+    /// Call this when the slot is released or changed hands.
+    pub fn forget(&self, slot: SlotArg) {
+        self.refreshed.lock().unwrap().remove(&slot);
+    }
+
+    /// Sends the refresh `message` with `handle`, if it is admitted.
+    ///
+    /// The slot is taken from [`Message::LocoSpd`], [`Message::LocoDirf`], [`Message::LocoSnd`]
+    /// and [`Message::RqSlData`]. Other messages concern no slot and are always sent.
+    ///
+    /// # Returns
+    ///
+    /// If the message was sent or dropped as the slot was refreshed recently.
+    pub async fn refresh(
+        &self,
+        handle: &CommandHandle,
+        message: Message,
+    ) -> Result<bool, LocoDriveSendingError> {
+        let admitted = match message {
+            Message::LocoSpd(slot, _)
+            | Message::LocoDirf(slot, _)
+            | Message::LocoSnd(slot, _)
+            | Message::RqSlData(slot) => self.admit(slot),
+            _ => true,
+        };
+
+        if admitted {
+            handle.send_message(message).await?;
+        }
+        Ok(admitted)
+    }
+}
@@ -0,0 +1,153 @@
+use crate::bridge::BridgePort;
+use crate::loco_controller::Direction;
+use crate::protocol::Message;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Duration, Instant};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::Stream;
+
+/// Replays a captured session of bus traffic with its original timing or accelerated,
+/// so bugs reported by users can be reproduced exactly and tested without a layout.
+///
+/// Sessions are captured with [`crate::loco_controller::LocoDriveControllerBuilder::raw_tap()`]
+/// or built from messages with their offset from the start of the session.
+/// Each message is replayed at the start of the replay plus its offset divided by the speed,
+/// so the schedule does not drift and messages with the same offset keep their order.
+///
+/// Connect the replay to a controller by its [`ReplayTransport::port()`],
+/// or feed managers directly by its [`ReplayTransport::stream()`].
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::{SwitchArg, SwitchDirection};
+/// use locodrive::protocol::Message;
+/// use locodrive::replay::ReplayTransport;
+/// use tokio::time::Duration;
+///
+/// let switch = SwitchArg::new(12, SwitchDirection::Curved, true);
+/// let replay = ReplayTransport::new(vec![
+///     (Duration::ZERO, Message::GpOn),
+///     (Duration::from_secs(4), Message::SwReq(switch)),
+/// ])
+/// // Replays four times faster than captured
+/// .speed(4.0);
+///
+/// assert_eq!(
+///     replay.schedule(),
+///     vec![
+///         (Duration::ZERO, Message::GpOn),
+///         (Duration::from_secs(1), Message::SwReq(switch)),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayTransport {
+    /// The captured messages with their offset from the start of the session, in order
+    session: Vec<(Duration, Message)>,
+    /// How many times faster than captured the session is replayed
+    speed: f64,
+}
+
+impl ReplayTransport {
+    /// Creates a replay of the `session` made of messages with their offset
+    /// from the start of the session. The messages are ordered by their offset.
+    pub fn new<I>(session: I) -> Self
+    where
+        I: IntoIterator<Item = (Duration, Message)>,
+    {
+        let mut session: Vec<(Duration, Message)> = session.into_iter().collect();
+        // The sort is stable, so messages with the same offset keep their captured order
+        session.sort_by_key(|(offset, _)| *offset);
+        ReplayTransport {
+            session,
+            speed: 1.0,
+        }
+    }
+
+    /// Creates a replay of the `frames` mirrored by
+    /// [`crate::loco_controller::LocoDriveControllerBuilder::raw_tap()`].
+    ///
+    /// Only the frames read from the bus are replayed, as they include the echoes of the
+    /// written ones. Frames that are no valid message are skipped.
+    /// The session starts with the first read frame.
+    pub fn from_frames<I>(frames: I) -> Self
+    where
+        I: IntoIterator<Item = (Direction, Vec<u8>, Instant)>,
+    {
+        let read: Vec<(Instant, Message)> = frames
+            .into_iter()
+            .filter(|(direction, ..)| *direction == Direction::Rx)
+            .filter_map(|(_, frame, at)| Message::parse(&frame).ok().map(|message| (at, message)))
+            .collect();
+        let start = read.iter().map(|(at, _)| *at).min();
+
+        Self::new(read.into_iter().map(|(at, message)| {
+            let offset = start.map_or(Duration::ZERO, |start| at.duration_since(start));
+            (offset, message)
+        }))
+    }
+
+    /// Sets how many times faster than captured the session is replayed,
+    /// [`f64::INFINITY`] replays all messages without pauses.
+    /// Speeds that are not positive are ignored.
+    ///
+    /// Defaults to `1.0`, the original speed.
+    pub fn speed(mut self, speed: f64) -> Self {
+        if speed > 0.0 {
+            self.speed = speed;
+        }
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The messages of the session with their offset from the start of the replay.
+    pub fn schedule(&self) -> Vec<(Duration, Message)> {
+        self.session
+            .iter()
+            .map(|(offset, message)| (offset.div_f64(self.speed), *message))
+            .collect()
+    }
+
+    /// # Returns
+    ///
+    /// How long replaying the whole session takes.
+    pub fn duration(&self) -> Duration {
+        self.session
+            .last()
+            .map_or(Duration::ZERO, |(offset, _)| offset.div_f64(self.speed))
+    }
+
+    /// Starts the replay.
+    ///
+    /// # Returns
+    ///
+    /// The stream of the replayed messages, each passed at its time.
+    /// The stream ends after the last message. Dropping it stops the replay.
+    pub fn stream(self) -> impl Stream<Item = Message> + Send + Unpin {
+        let (sender, replayed) = mpsc::unbounded_channel();
+        let schedule = self.schedule();
+        let start = Instant::now();
+        tokio::spawn(async move {
+            for (offset, message) in schedule {
+                sleep_until(start + offset).await;
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        UnboundedReceiverStream::new(replayed)
+    }
+
+    /// Starts the replay as port to connect it to other transports, like a controller,
+    /// with a [`crate::bridge::LocoNetBridge`].
+    ///
+    /// The replay only receives the captured messages, the messages of the other ports
+    /// are not forwarded to it.
+    pub fn port(self, name: impl Into<String>) -> BridgePort {
+        // Nothing is forwarded to the replay, so the forwarded messages are never read
+        let (outgoing, _) = mpsc::channel(1);
+        BridgePort::new(name, self.stream(), outgoing).pass(|_| false)
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::LayoutImportError;
+use crate::layout::{Block, LayoutModel, Loco, Sensor, Turnout};
+use roxmltree::{Document, Node};
+use std::path::Path;
+
+/// Imports a layout from the Rocrail plan file at `path`.
+///
+/// See [`import_plan()`] for the imported elements.
+pub fn import_plan_file<P: AsRef<Path>>(path: P) -> Result<LayoutModel, LayoutImportError> {
+    import_plan(&std::fs::read_to_string(path)?)
+}
+
+/// Imports a layout from the content of a Rocrail plan file (`plan.xml`).
+///
+/// The following elements are imported:
+///
+/// - `lc`: Locomotives with their `addr` and `spcnt` speed steps into the [`crate::layout::Roster`]
+/// - `sw`: Turnouts with their `addr1` and `port1`.
+///   If `port1` is zero `addr1` is used as flat address.
+/// - `fb`: Sensors with their `addr`
+/// - `bk`: Blocks with the sensors referenced by their `fbevent` children
+///
+/// Rocrail counts turnout and sensor addresses from one, they are converted to
+/// the addresses used by the model railroad messages counting from zero.
+/// Elements with address zero are not configured in Rocrail and therefore skipped.
+pub fn import_plan(xml: &str) -> Result<LayoutModel, LayoutImportError> {
+    let document = Document::parse(xml).map_err(|err| LayoutImportError::Xml(err.to_string()))?;
+
+    let mut layout = LayoutModel::new();
+
+    for node in document.descendants().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "lc" => {
+                let id = id(&node)?;
+                let address = number(&node, &id, "addr")?.unwrap_or(0);
+                if address == 0 {
+                    continue;
+                }
+                if address > 0x3FFF {
+                    return Err(LayoutImportError::InvalidAttribute(id, "addr".to_string()));
+                }
+                let speed_steps = match number(&node, &id, "spcnt")? {
+                    Some(steps) if steps > u8::MAX as u16 => {
+                        return Err(LayoutImportError::InvalidAttribute(id, "spcnt".to_string()))
+                    }
+                    steps => steps.map(|steps| steps as u8),
+                };
+                layout.roster.add(Loco {
+                    id,
+                    address,
+                    speed_steps,
+                });
+            }
+            "sw" => {
+                let id = id(&node)?;
+                let address = number(&node, &id, "addr1")?.unwrap_or(0);
+                let port = number(&node, &id, "port1")?.unwrap_or(0);
+                let address = match (address as u32, port as u32) {
+                    (address, 0) => address,
+                    (0, _) => 0,
+                    (address, port) => (address - 1) * 4 + port,
+                };
+                if address == 0 {
+                    continue;
+                }
+                if address > 2048 {
+                    return Err(LayoutImportError::InvalidAttribute(id, "addr1".to_string()));
+                }
+                layout.turnouts.push(Turnout {
+                    id,
+                    address: address as u16 - 1,
+                });
+            }
+            "fb" => {
+                let id = id(&node)?;
+                let address = number(&node, &id, "addr")?.unwrap_or(0);
+                if address == 0 {
+                    continue;
+                }
+                if address > 4096 {
+                    return Err(LayoutImportError::InvalidAttribute(id, "addr".to_string()));
+                }
+                layout.sensors.push(Sensor {
+                    id,
+                    address: address - 1,
+                });
+            }
+            "bk" => {
+                let id = id(&node)?;
+                let mut sensors: Vec<String> = Vec::new();
+                for event in node
+                    .children()
+                    .filter(|child| child.has_tag_name("fbevent"))
+                {
+                    if let Some(sensor) = event.attribute("id") {
+                        if !sensors.iter().any(|known| known == sensor) {
+                            sensors.push(sensor.to_string());
+                        }
+                    }
+                }
+                layout.blocks.push(Block { id, sensors });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(layout)
+}
+
+/// Reads the `id` attribute of an element.
+fn id(node: &Node) -> Result<String, LayoutImportError> {
+    match node.attribute("id") {
+        Some(id) => Ok(id.to_string()),
+        None => Err(LayoutImportError::MissingAttribute(
+            node.tag_name().name().to_string(),
+            "id".to_string(),
+        )),
+    }
+}
+
+/// Reads a numeric attribute of the element with the id `id`.
+///
+/// # Returns
+///
+/// `None` if the attribute is not set.
+fn number(node: &Node, id: &str, attribute: &str) -> Result<Option<u16>, LayoutImportError> {
+    match node.attribute(attribute) {
+        Some(value) => match value.trim().parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(LayoutImportError::InvalidAttribute(
+                id.to_string(),
+                attribute.to_string(),
+            )),
+        },
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,168 @@
+use crate::args::{SwitchArg, SwitchDirection};
+use crate::error::RouteError;
+use crate::loco_controller::{CommandHandle, LocoDriveController, SendOptions};
+use crate::protocol::Message;
+use tokio::time::{sleep, Duration};
+
+/// A named route, an ordered list of turnouts switched together to lead a train along a path.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Route {
+    /// The unique name of the route
+    name: String,
+    /// The addresses of the turnouts with the directions to switch them to, in switching order
+    steps: Vec<(u16, SwitchDirection)>,
+}
+
+impl Route {
+    /// Creates a route without any turnout.
+    pub fn new(name: impl Into<String>) -> Self {
+        Route {
+            name: name.into(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Appends switching the turnout with `address` to `direction` to this route.
+    pub fn step(mut self, address: u16, direction: SwitchDirection) -> Self {
+        self.steps.push((address, direction));
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The name of this route.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// # Returns
+    ///
+    /// The addresses of the turnouts with their directions, in switching order.
+    pub fn steps(&self) -> &[(u16, SwitchDirection)] {
+        &self.steps
+    }
+
+    /// # Returns
+    ///
+    /// The switch requests setting this route, in switching order.
+    pub fn messages(&self) -> Vec<Message> {
+        self.steps
+            .iter()
+            .map(|&(address, direction)| Message::SwReq(SwitchArg::new(address, direction, true)))
+            .collect()
+    }
+}
+
+/// Sets [`Route`]s by switching their turnouts one after another.
+///
+/// The switch requests are paced, so the turnout decoders and their power supply
+/// are not overloaded by switching all turnouts at once. A request answered by a failed
+/// [`Message::LongAck`] is repeated. The remaining turnouts are still switched,
+/// if one of them failed, so the failure is reported with all turnouts of the route set.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::SwitchDirection;
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::routes::{Route, RouteSetter};
+///
+/// # async fn set(controller: LocoDriveController) {
+/// let route = Route::new("platform 1")
+///     .step(12, SwitchDirection::Straight)
+///     .step(13, SwitchDirection::Curved);
+///
+/// if let Err(err) = RouteSetter::new(&controller).set(&route).await {
+///     println!("{}", err);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RouteSetter {
+    /// Writes the switch requests
+    handle: CommandHandle,
+    /// How long to wait between two switch requests
+    pacing: Duration,
+    /// How long a failed answer to a switch request is awaited
+    ack_timeout: Duration,
+    /// How often a failed switch request is repeated
+    retries: u32,
+}
+
+impl RouteSetter {
+    /// Creates a setter switching the turnouts with `controller`.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        RouteSetter {
+            handle: controller.command_handle(),
+            pacing: Duration::from_millis(250),
+            ack_timeout: Duration::from_millis(100),
+            retries: 2,
+        }
+    }
+
+    /// Sets how long to wait between two switch requests.
+    ///
+    /// Defaults to 250 milliseconds.
+    pub fn pacing(mut self, pacing: Duration) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Sets how long a failed answer to a switch request is awaited.
+    /// Switch requests are only answered, if the command station could not accept them.
+    ///
+    /// Defaults to 100 milliseconds.
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Sets how often a failed switch request is repeated.
+    ///
+    /// Defaults to `2`.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets `route` by switching all of its turnouts in order.
+    ///
+    /// # Errors
+    ///
+    /// The [`RouteError`] listing the turnouts that could not be switched,
+    /// if any switch request failed.
+    pub async fn set(&self, route: &Route) -> Result<(), RouteError> {
+        let options = SendOptions {
+            retries: self.retries,
+            ack_timeout: Some(self.ack_timeout),
+            ..SendOptions::default()
+        };
+
+        let mut failed = Vec::new();
+        for (step, message) in route.messages().into_iter().enumerate() {
+            if step > 0 {
+                sleep(self.pacing).await;
+            }
+
+            if let Err(err) = self.handle.send_message_with(message, options).await {
+                log_error!(
+                    "Could not switch {:?} of route {}: {}",
+                    message,
+                    route.name(),
+                    err
+                );
+                failed.push((route.steps()[step].0, err));
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(RouteError {
+                route: route.name().to_string(),
+                steps: route.steps().len(),
+                failed,
+            })
+        }
+    }
+}
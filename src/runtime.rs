@@ -0,0 +1,68 @@
+#[cfg(feature = "config")]
+use crate::config::LocodriveConfig;
+#[cfg(feature = "config")]
+use crate::error::ConfigError;
+use crate::layout::LayoutModel;
+use crate::loco_controller::LocoDriveController;
+
+/// Runs a layout: the controller connected to the model railroad together with the
+/// [`LayoutModel`] describing what is connected to it.
+///
+/// Binaries and examples create it from a [`LocodriveConfig`] using
+/// [`LayoutRuntime::from_config()`] (requires the `config` feature).
+pub struct LayoutRuntime {
+    /// The controller connected to the model railroad
+    controller: LocoDriveController,
+    /// The layout controlled
+    layout: LayoutModel,
+}
+
+impl LayoutRuntime {
+    /// Creates a runtime controlling `layout` with `controller`.
+    pub fn new(controller: LocoDriveController, layout: LayoutModel) -> Self {
+        LayoutRuntime { controller, layout }
+    }
+
+    /// Loads the layout and connects the controller as configured by `config`.
+    ///
+    /// # Errors
+    ///
+    /// - The errors of [`LocodriveConfig::validate()`] and [`LocodriveConfig::load_layout()`]
+    /// - [`ConfigError::Connection`]: If the serial port is not reachable or could not be configured
+    #[cfg(feature = "config")]
+    pub async fn from_config(config: &LocodriveConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let layout = config.load_layout()?;
+        let controller = config.controller_builder().build().await?;
+
+        Ok(Self::new(controller, layout))
+    }
+
+    /// # Returns
+    ///
+    /// The controller connected to the model railroad.
+    pub fn controller(&self) -> &LocoDriveController {
+        &self.controller
+    }
+
+    /// # Returns
+    ///
+    /// The controller connected to the model railroad, to send messages with.
+    pub fn controller_mut(&mut self) -> &mut LocoDriveController {
+        &mut self.controller
+    }
+
+    /// # Returns
+    ///
+    /// The layout controlled.
+    pub fn layout(&self) -> &LayoutModel {
+        &self.layout
+    }
+
+    /// # Returns
+    ///
+    /// The layout controlled, to add or replace its elements.
+    pub fn layout_mut(&mut self) -> &mut LayoutModel {
+        &mut self.layout
+    }
+}
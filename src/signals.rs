@@ -0,0 +1,377 @@
+use crate::args::{SwitchArg, SwitchDirection};
+use crate::dcc::{DccCommand, DccPacket};
+use crate::error::{DccError, SignalError};
+use crate::loco_controller::{CommandHandle, LocoDriveController, SendOptions};
+use crate::manager::Manager;
+use crate::protocol::Message;
+use crate::turnouts::TurnoutDriver;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+/// The aspect shown by a signal.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Aspect {
+    /// The train has to stop before the signal.
+    Stop,
+    /// The train may proceed at line speed.
+    Proceed,
+    /// The train may proceed, but has to expect the next signal to show stop.
+    Approach,
+    /// The train may proceed at restricted speed, like into an occupied block.
+    Restricting,
+    /// The signal is switched off.
+    Dark,
+}
+
+impl Display for Aspect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Aspect::Stop => "stop",
+            Aspect::Proceed => "proceed",
+            Aspect::Approach => "approach",
+            Aspect::Restricting => "restricting",
+            Aspect::Dark => "dark",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How an aspect is shown by the decoder of a signal.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum AspectOutput {
+    /// The aspect is shown by switching the outputs of a basic accessory decoder,
+    /// by their switch addresses with the directions to switch them to, in switching order.
+    Switches(Vec<(u16, SwitchDirection)>),
+    /// The aspect is shown by setting an extended accessory decoder, see
+    /// [`DccCommand::ExtendedAccessory`]. It is send as immediate packet.
+    Extended {
+        /// The address of the decoder output, numbered like the switch addresses
+        address: u16,
+        /// The aspect number the decoder shows the aspect for, from 0 to 31
+        aspect: u8,
+    },
+}
+
+impl AspectOutput {
+    /// # Returns
+    ///
+    /// The messages showing the aspect, with the immediate packets repeated `repeats` times more.
+    ///
+    /// # Errors
+    ///
+    /// The [`DccError`] if the extended accessory packet could not be encoded.
+    pub fn messages(&self, repeats: u8) -> Result<Vec<Message>, DccError> {
+        match self {
+            AspectOutput::Switches(outputs) => Ok(outputs
+                .iter()
+                .map(|&(address, direction)| {
+                    Message::SwReq(SwitchArg::new(address, direction, true))
+                })
+                .collect()),
+            AspectOutput::Extended { address, aspect } => {
+                let command = DccCommand::ExtendedAccessory {
+                    address: *address,
+                    aspect: *aspect,
+                };
+                Ok(vec![command.encode()?.to_message(repeats)])
+            }
+        }
+    }
+}
+
+/// A signal mast showing its aspects by one or more accessory decoder outputs.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Signal {
+    /// The unique name of the signal
+    name: String,
+    /// The aspects the signal can show with how they are shown
+    aspects: Vec<(Aspect, AspectOutput)>,
+}
+
+impl Signal {
+    /// Creates a signal without any aspect.
+    pub fn new(name: impl Into<String>) -> Self {
+        Signal {
+            name: name.into(),
+            aspects: Vec::new(),
+        }
+    }
+
+    /// Adds the `aspect` shown by `output` to this signal, replacing how it was shown before.
+    pub fn aspect(mut self, aspect: Aspect, output: AspectOutput) -> Self {
+        self.aspects.retain(|(known, _)| *known != aspect);
+        self.aspects.push((aspect, output));
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The name of this signal.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// # Returns
+    ///
+    /// The aspects this signal can show with how they are shown.
+    pub fn aspects(&self) -> &[(Aspect, AspectOutput)] {
+        &self.aspects
+    }
+
+    /// # Returns
+    ///
+    /// How this signal shows `aspect`, if it can show it.
+    pub fn output(&self, aspect: Aspect) -> Option<&AspectOutput> {
+        self.aspects
+            .iter()
+            .find(|(known, _)| *known == aspect)
+            .map(|(_, output)| output)
+    }
+}
+
+/// Tracks the aspects of the signals from the messages on the bus.
+///
+/// An aspect shown by switches is recognized once all of its outputs were switched
+/// to their directions. An aspect shown by an extended accessory decoder is recognized
+/// from the immediate packet setting it.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct SignalTable {
+    /// The known signals in the order they were added
+    signals: Vec<Signal>,
+    /// The known aspect of each signal by its name
+    aspects: HashMap<String, Aspect>,
+    /// The last switched direction of each output
+    switches: HashMap<u16, SwitchDirection>,
+}
+
+impl SignalTable {
+    /// Creates a table without any signal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `signal` to the table, replacing a signal with the same name.
+    pub fn add(&mut self, signal: Signal) {
+        self.signals.retain(|known| known.name != signal.name);
+        self.signals.push(signal);
+    }
+
+    /// # Returns
+    ///
+    /// The signal named `name`, if it is known.
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|signal| signal.name == name)
+    }
+
+    /// # Returns
+    ///
+    /// All known signals in the order they were added.
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    /// # Returns
+    ///
+    /// The known aspect of the signal named `name`, if it was observed.
+    pub fn aspect(&self, name: &str) -> Option<Aspect> {
+        self.aspects.get(name).copied()
+    }
+
+    /// Sets the known `aspect` of the signal named `name`, like restored from a saved session.
+    pub fn set(&mut self, name: &str, aspect: Aspect) {
+        self.aspects.insert(name.to_string(), aspect);
+    }
+
+    /// # Returns
+    ///
+    /// If the last switched directions match all `outputs`.
+    fn switched(&self, outputs: &[(u16, SwitchDirection)]) -> bool {
+        outputs
+            .iter()
+            .all(|(address, direction)| self.switches.get(address) == Some(direction))
+    }
+}
+
+impl Manager for SignalTable {
+    fn handle(&mut self, message: &Message) {
+        let mut observed = Vec::new();
+        match *message {
+            Message::SwReq(switch) if switch.state() => {
+                self.switches.insert(switch.address(), switch.direction());
+                for signal in &self.signals {
+                    let shown = signal.aspects.iter().find(|(_, output)| {
+                        matches!(output, AspectOutput::Switches(outputs)
+                            if outputs.iter().any(|(address, _)| *address == switch.address())
+                                && self.switched(outputs))
+                    });
+                    if let Some((aspect, _)) = shown {
+                        observed.push((signal.name.clone(), *aspect));
+                    }
+                }
+            }
+            Message::ImmPacket(_) => {
+                if let Some(DccCommand::ExtendedAccessory { address, aspect }) =
+                    DccPacket::from_message(message).and_then(|packet| packet.decode())
+                {
+                    let extended = AspectOutput::Extended { address, aspect };
+                    for signal in &self.signals {
+                        if let Some((shown, _)) = signal
+                            .aspects
+                            .iter()
+                            .find(|(_, output)| *output == extended)
+                        {
+                            observed.push((signal.name.clone(), *shown));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.aspects.extend(observed);
+    }
+}
+
+/// Sets the aspects of the signals of a [`SignalTable`].
+///
+/// The switch requests of aspects shown by switches are send one after another, or are thrown
+/// by a [`TurnoutDriver`] if set, so the outputs are paced and switched off after a pulse.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::SwitchDirection;
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::signals::{Aspect, AspectOutput, Signal, SignalSetter, SignalTable};
+///
+/// # async fn set(controller: LocoDriveController) {
+/// let mut table = SignalTable::new();
+/// table.add(
+///     Signal::new("A1")
+///         .aspect(Aspect::Stop, AspectOutput::Switches(vec![(40, SwitchDirection::Curved)]))
+///         .aspect(Aspect::Proceed, AspectOutput::Switches(vec![(40, SwitchDirection::Straight)])),
+/// );
+/// table.add(
+///     Signal::new("B2")
+///         .aspect(Aspect::Stop, AspectOutput::Extended { address: 60, aspect: 0 })
+///         .aspect(Aspect::Approach, AspectOutput::Extended { address: 60, aspect: 4 }),
+/// );
+///
+/// let signals = SignalSetter::new(&controller, table);
+/// signals.set_aspect("A1", Aspect::Proceed).await.unwrap();
+/// signals.set_aspect("B2", Aspect::Approach).await.unwrap();
+/// assert_eq!(signals.aspect("B2"), Some(Aspect::Approach));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SignalSetter {
+    /// Writes the messages showing the aspects
+    handle: CommandHandle,
+    /// Throws the switches showing the aspects, if set
+    turnouts: Option<TurnoutDriver>,
+    /// The signals with their known aspects
+    table: Arc<Mutex<SignalTable>>,
+    /// How often an immediate packet is repeated
+    repeats: u8,
+    /// How to send the messages
+    send_options: SendOptions,
+}
+
+impl SignalSetter {
+    /// Creates a setter of the signals in `table` sending with `controller`.
+    pub fn new(controller: &LocoDriveController, table: SignalTable) -> Self {
+        SignalSetter {
+            handle: controller.command_handle(),
+            turnouts: None,
+            table: Arc::new(Mutex::new(table)),
+            repeats: 2,
+            send_options: SendOptions::default(),
+        }
+    }
+
+    /// Throws the switches showing the aspects by `turnouts`.
+    /// Defaults to sending the switch requests without pacing or switching them off.
+    pub fn turnouts(mut self, turnouts: TurnoutDriver) -> Self {
+        self.turnouts = Some(turnouts);
+        self
+    }
+
+    /// Sets how often the immediate packets of extended accessory decoders are repeated,
+    /// up to 7 times.
+    ///
+    /// Defaults to `2`.
+    pub fn repeats(mut self, repeats: u8) -> Self {
+        self.repeats = repeats.min(7);
+        self
+    }
+
+    /// Sets how the messages are send, like how often a failed message is repeated.
+    ///
+    /// Defaults to [`SendOptions::default()`].
+    pub fn send_options(mut self, send_options: SendOptions) -> Self {
+        self.send_options = send_options;
+        self
+    }
+
+    /// Shows `aspect` on the signal named `signal`.
+    ///
+    /// # Errors
+    ///
+    /// - [`SignalError::UnknownSignal`]: If the signal is not in the table
+    /// - [`SignalError::UnknownAspect`]: If the signal can not show the aspect
+    /// - [`SignalError::Dcc`]: If the extended accessory packet could not be encoded
+    /// - [`SignalError::Sending`]: If a message could not be send
+    pub async fn set_aspect(&self, signal: &str, aspect: Aspect) -> Result<(), SignalError> {
+        let output = {
+            let table = self.table.lock().unwrap();
+            let known = table
+                .signal(signal)
+                .ok_or_else(|| SignalError::UnknownSignal(signal.to_string()))?;
+            known
+                .output(aspect)
+                .ok_or_else(|| SignalError::UnknownAspect(signal.to_string(), aspect))?
+                .clone()
+        };
+
+        match (&output, &self.turnouts) {
+            (AspectOutput::Switches(outputs), Some(turnouts)) => {
+                for &(address, direction) in outputs {
+                    turnouts
+                        .throw(address, direction)
+                        .await
+                        .map_err(SignalError::Sending)?;
+                }
+            }
+            _ => {
+                for message in output.messages(self.repeats).map_err(SignalError::Dcc)? {
+                    self.handle
+                        .send_message_with(message, self.send_options)
+                        .await
+                        .map_err(SignalError::Sending)?;
+                }
+            }
+        }
+
+        self.table.lock().unwrap().set(signal, aspect);
+        Ok(())
+    }
+
+    /// # Returns
+    ///
+    /// The known aspect of the signal named `signal`, if it was set or observed.
+    pub fn aspect(&self, signal: &str) -> Option<Aspect> {
+        self.table.lock().unwrap().aspect(signal)
+    }
+
+    /// Updates the known aspects by one received `message`, like set by other throttles.
+    pub fn handle(&self, message: &Message) {
+        self.table.lock().unwrap().handle(message);
+    }
+
+    /// # Returns
+    ///
+    /// A copy of the signals with their known aspects.
+    pub fn table(&self) -> SignalTable {
+        self.table.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,511 @@
+use crate::args::{
+    Ack1Arg, AddressArg, Consist, DecoderType, DirfArg, IdArg, LopcArg, SlotArg, SndArg, SpeedArg,
+    Stat1Arg, Stat2Arg, State, SwitchDirection, TrkArg, WrSlDataStructure,
+};
+use crate::bridge::BridgePort;
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Duration, Instant};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// The highest slot holding a locomotive.
+const MAX_LOCO_SLOT: u8 = 119;
+
+/// The answer of a switch state request reporting a straight switch.
+const SWITCH_STRAIGHT: u8 = 0x30;
+/// The answer of a switch state request reporting a curved switch.
+const SWITCH_CURVED: u8 = 0x50;
+
+/// The data of one slot of the [`CommandStation`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct SlotData {
+    /// The status of the slot
+    stat1: Stat1Arg,
+    /// The address of the locomotive in the slot
+    adr: AddressArg,
+    /// The speed of the locomotive
+    spd: SpeedArg,
+    /// The direction and the first functions of the locomotive
+    dirf: DirfArg,
+    /// The second status of the slot
+    stat2: Stat2Arg,
+    /// The sound functions of the locomotive
+    snd: SndArg,
+    /// The id of the throttle last writing the slot
+    id: IdArg,
+}
+
+impl SlotData {
+    /// Creates a free slot.
+    fn free() -> Self {
+        SlotData {
+            stat1: Stat1Arg::new(false, Consist::Free, State::Free, DecoderType::Dcc128),
+            adr: AddressArg::new(0),
+            spd: SpeedArg::Stop,
+            dirf: DirfArg::new(true, false, false, false, false, false),
+            stat2: Stat2Arg::new(false, false, false),
+            snd: SndArg::new(false, false, false, false),
+            id: IdArg::new(0),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// This slot data with the state of the slot set to `state`.
+    fn with_state(mut self, state: State) -> Self {
+        self.stat1 = Stat1Arg::new(
+            self.stat1.s_purge(),
+            self.stat1.consist(),
+            state,
+            self.stat1.decoder_type(),
+        );
+        self
+    }
+}
+
+/// A software command station, answering the messages written to it like a real one.
+///
+/// The station assigns slots to the requested locomotive addresses and keeps their state,
+/// switches the turnouts and answers their state requests and tracks the track power.
+/// Messages it does not support are not answered.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::args::AddressArg;
+/// use locodrive::protocol::Message;
+/// use locodrive::simulator::CommandStation;
+///
+/// let mut station = CommandStation::new();
+/// let answers = station.handle(&Message::LocoAdr(AddressArg::new(5)));
+///
+/// assert!(matches!(answers[..], [Message::SlRdData(slot, ..)] if slot.slot() == 1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandStation {
+    /// The data of the slots holding locomotives
+    slots: HashMap<SlotArg, SlotData>,
+    /// The direction of each switched turnout by its address
+    switches: HashMap<u16, SwitchDirection>,
+    /// Whether the track power is on
+    power: bool,
+}
+
+impl CommandStation {
+    /// Creates a station with all slots free and the track power off.
+    pub fn new() -> Self {
+        CommandStation {
+            slots: HashMap::new(),
+            switches: HashMap::new(),
+            power: false,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// Whether the track power is on.
+    pub fn power(&self) -> bool {
+        self.power
+    }
+
+    /// # Returns
+    ///
+    /// The direction the turnout with `address` was switched to.
+    pub fn switch(&self, address: u16) -> Option<SwitchDirection> {
+        self.switches.get(&address).copied()
+    }
+
+    /// # Returns
+    ///
+    /// The slot holding the locomotive with `address`.
+    pub fn slot_of(&self, address: AddressArg) -> Option<SlotArg> {
+        (1..=MAX_LOCO_SLOT).map(SlotArg::new).find(|slot| {
+            self.slots
+                .get(slot)
+                .is_some_and(|data| data.adr == address && data.stat1.state() != State::Free)
+        })
+    }
+
+    /// # Returns
+    ///
+    /// The slot data of `slot` as read by [`Message::RqSlData`],
+    /// or `None` if `slot` is no slot for locomotives.
+    pub fn slot_data(&self, slot: SlotArg) -> Option<Message> {
+        if slot.slot() == 0 || slot.slot() > MAX_LOCO_SLOT {
+            return None;
+        }
+        let data = self
+            .slots
+            .get(&slot)
+            .copied()
+            .unwrap_or_else(SlotData::free);
+        Some(Message::SlRdData(
+            slot,
+            data.stat1,
+            data.adr,
+            data.spd,
+            data.dirf,
+            self.track(),
+            data.stat2,
+            data.snd,
+            data.id,
+        ))
+    }
+
+    /// Handles one `message` written to the station.
+    ///
+    /// # Returns
+    ///
+    /// The answers of the station, in the order they are put on the bus.
+    pub fn handle(&mut self, message: &Message) -> Vec<Message> {
+        let answer = match *message {
+            Message::GpOn => {
+                self.power = true;
+                None
+            }
+            Message::GpOff => {
+                self.power = false;
+                None
+            }
+            Message::LocoAdr(address) => Some(match self.slot_of(address) {
+                Some(slot) => self.slot_data_or_fail(slot, message),
+                None => match self.free_slot() {
+                    Some(slot) => {
+                        let mut data = SlotData::free().with_state(State::Common);
+                        data.adr = address;
+                        self.slots.insert(slot, data);
+                        self.slot_data_or_fail(slot, message)
+                    }
+                    // No free slot is available
+                    None => Self::ack(message, Ack1Arg::new(false)),
+                },
+            }),
+            Message::RqSlData(slot) => Some(self.slot_data_or_fail(slot, message)),
+            Message::MoveSlots(src, dst) => Some(self.move_slots(src, dst, message)),
+            Message::SlotStat1(slot, stat1) => {
+                self.update(slot, |data| data.stat1 = stat1);
+                None
+            }
+            Message::LocoSpd(slot, spd) => {
+                self.update(slot, |data| data.spd = spd);
+                None
+            }
+            Message::LocoDirf(slot, dirf) => {
+                self.update(slot, |data| data.dirf = dirf);
+                None
+            }
+            Message::LocoSnd(slot, snd) => {
+                self.update(slot, |data| data.snd = snd);
+                None
+            }
+            Message::WrSlData(WrSlDataStructure::DataGeneral(
+                slot,
+                stat1,
+                stat2,
+                adr,
+                spd,
+                dirf,
+                _,
+                snd,
+                id,
+            )) if slot.slot() > 0 && slot.slot() <= MAX_LOCO_SLOT => {
+                let data = SlotData {
+                    stat1,
+                    adr,
+                    spd,
+                    dirf,
+                    stat2,
+                    snd,
+                    id,
+                };
+                self.slots.insert(slot, data);
+                Some(Self::ack(message, Ack1Arg::new(true)))
+            }
+            Message::SwReq(switch) => {
+                self.switches.insert(switch.address(), switch.direction());
+                None
+            }
+            Message::SwAck(switch) => {
+                self.switches.insert(switch.address(), switch.direction());
+                Some(Self::ack(message, Ack1Arg::new(true)))
+            }
+            Message::SwState(switch) => {
+                let code = match self.switch(switch.address()) {
+                    Some(SwitchDirection::Curved) => SWITCH_CURVED,
+                    _ => SWITCH_STRAIGHT,
+                };
+                Some(Self::ack(message, Ack1Arg::new_advanced(code)))
+            }
+            _ => None,
+        };
+        answer.into_iter().collect()
+    }
+
+    /// # Returns
+    ///
+    /// The track status of the station.
+    fn track(&self) -> TrkArg {
+        TrkArg::new(self.power, false, true, false)
+    }
+
+    /// # Returns
+    ///
+    /// The first slot holding no locomotive.
+    fn free_slot(&self) -> Option<SlotArg> {
+        (1..=MAX_LOCO_SLOT).map(SlotArg::new).find(|slot| {
+            self.slots
+                .get(slot)
+                .is_none_or(|data| data.stat1.state() == State::Free)
+        })
+    }
+
+    /// Updates the data of `slot` by `update`, if it holds a locomotive.
+    fn update(&mut self, slot: SlotArg, update: impl FnOnce(&mut SlotData)) {
+        if slot.slot() > 0 && slot.slot() <= MAX_LOCO_SLOT {
+            update(self.slots.entry(slot).or_insert_with(SlotData::free));
+        }
+    }
+
+    /// Moves the slot `src` to `dst`. A move to itself marks the slot in use,
+    /// a move to slot `0` puts it to dispatch.
+    ///
+    /// # Returns
+    ///
+    /// The answer to the move `request`.
+    fn move_slots(&mut self, src: SlotArg, dst: SlotArg, request: &Message) -> Message {
+        let data = match self.slots.get(&src) {
+            Some(data) if data.stat1.state() != State::Free => *data,
+            _ => return Self::ack(request, Ack1Arg::new(false)),
+        };
+
+        if src == dst {
+            self.slots.insert(src, data.with_state(State::InUse));
+            return self.slot_data_or_fail(src, request);
+        }
+        if dst.slot() == 0 {
+            self.slots.insert(src, data.with_state(State::Common));
+            return self.slot_data_or_fail(src, request);
+        }
+
+        match self.slots.get(&dst) {
+            Some(target) if target.stat1.state() != State::Free => {
+                Self::ack(request, Ack1Arg::new(false))
+            }
+            _ => {
+                self.slots.insert(dst, data.with_state(State::InUse));
+                self.slots.insert(src, SlotData::free());
+                self.slot_data_or_fail(dst, request)
+            }
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The slot data of `slot` or a failed acknowledgment of `request`,
+    /// if `slot` is no slot for locomotives.
+    fn slot_data_or_fail(&self, slot: SlotArg, request: &Message) -> Message {
+        self.slot_data(slot)
+            .unwrap_or_else(|| Self::ack(request, Ack1Arg::new(false)))
+    }
+
+    /// # Returns
+    ///
+    /// The acknowledgment `ack` of `request`.
+    fn ack(request: &Message, ack: Ack1Arg) -> Message {
+        Message::LongAck(LopcArg::new(request.opc()), ack)
+    }
+}
+
+impl Default for CommandStation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Simulates a bus with a [`CommandStation`] attached, to run applications without hardware.
+///
+/// Every message written to the bus is echoed, followed by the answers of the station.
+/// Scripted messages, like sensor reports, are put on the bus at their time after the start.
+/// Connect the bus to other transports by its [`SimulatorHandle::port()`].
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{InArg, SensorLevel, SourceType};
+/// use locodrive::bridge::{BridgePort, LocoNetBridge};
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::protocol::Message;
+/// use locodrive::simulator::{CommandStation, Simulator};
+/// use std::time::Duration;
+///
+/// # async fn demo(controller: LocoDriveController) {
+/// let sensor = InArg::new(10, SourceType::Switch, SensorLevel::High, false);
+/// let simulator = Simulator::new(CommandStation::new())
+///     .inject(Duration::from_secs(5), Message::InputRep(sensor))
+///     .start();
+///
+/// let _bridge = LocoNetBridge::new()
+///     .port(BridgePort::controller("interface", &controller))
+///     .port(simulator.port("simulator"))
+///     .start();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Simulator {
+    /// The station answering the messages
+    station: CommandStation,
+    /// The scripted messages with when to put them on the bus
+    script: Vec<(Duration, Message)>,
+}
+
+impl Simulator {
+    /// Creates a simulator of a bus with `station` attached and no scripted messages.
+    pub fn new(station: CommandStation) -> Self {
+        Simulator {
+            station,
+            script: Vec::new(),
+        }
+    }
+
+    /// Scripts to put `message` on the bus `after` the start.
+    pub fn inject(mut self, after: Duration, message: Message) -> Self {
+        self.script.push((after, message));
+        self
+    }
+
+    /// Starts simulating the bus.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the bus. Dropping it stops the simulation.
+    pub fn start(self) -> SimulatorHandle {
+        let (writer, mut written) = mpsc::unbounded_channel::<Message>();
+        let bus = broadcast::channel(256).0;
+        let station = Arc::new(Mutex::new(self.station));
+
+        let start = Instant::now();
+        let mut script: Vec<(Instant, Message)> = self
+            .script
+            .into_iter()
+            .map(|(after, message)| (start + after, message))
+            .collect();
+        // The next scripted message is taken from the end
+        script.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        let simulated = station.clone();
+        let sender = bus.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let next = script.last().map(|(at, _)| *at);
+                let message = tokio::select! {
+                    written = written.recv() => match written {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                        match script.pop() {
+                            Some((_, message)) => message,
+                            None => continue,
+                        }
+                    }
+                };
+
+                let answers = simulated.lock().unwrap().handle(&message);
+                // Nobody may listen to the bus
+                let _ = sender.send(message);
+                for answer in answers {
+                    let _ = sender.send(answer);
+                }
+            }
+        });
+
+        SimulatorHandle {
+            writer,
+            bus,
+            station,
+            task,
+        }
+    }
+}
+
+/// The handle of a running [`Simulator`].
+///
+/// Dropping the handle stops the simulation.
+#[derive(Debug)]
+pub struct SimulatorHandle {
+    /// Writes messages to the bus
+    writer: mpsc::UnboundedSender<Message>,
+    /// Broadcasts the messages on the bus
+    bus: broadcast::Sender<Message>,
+    /// The station attached to the bus
+    station: Arc<Mutex<CommandStation>>,
+    /// The task simulating the bus
+    task: JoinHandle<()>,
+}
+
+impl SimulatorHandle {
+    /// Writes `message` to the bus, like a throttle connected to it.
+    pub fn write(&self, message: Message) {
+        // The simulation only ends when the handle is dropped
+        let _ = self.writer.send(message);
+    }
+
+    /// Subscribes to the messages on the bus.
+    ///
+    /// Only messages put on the bus after subscribing are passed to the receiver.
+    pub fn subscribe(&self) -> broadcast::Receiver<Message> {
+        self.bus.subscribe()
+    }
+
+    /// # Returns
+    ///
+    /// A copy of the current state of the station attached to the bus.
+    pub fn station(&self) -> CommandStation {
+        self.station.lock().unwrap().clone()
+    }
+
+    /// Creates a port to connect the bus to other transports with a
+    /// [`crate::bridge::LocoNetBridge`].
+    pub fn port(&self, name: impl Into<String>) -> BridgePort {
+        let (incoming, received) = mpsc::unbounded_channel();
+        let mut bus = self.bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match bus.recv().await {
+                    Ok(message) => {
+                        if incoming.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(lost)) => {
+                        log_error!("The simulator port lost {} messages", lost);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let (outgoing, mut forwarded) = mpsc::channel::<Message>(64);
+        let writer = self.writer.clone();
+        tokio::spawn(async move {
+            while let Some(message) = forwarded.recv().await {
+                if writer.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        BridgePort::new(name, UnboundedReceiverStream::new(received), outgoing)
+    }
+}
+
+/// Extends standard drop implementation to stop the simulation.
+impl Drop for SimulatorHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
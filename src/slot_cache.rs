@@ -0,0 +1,299 @@
+use crate::args::{AddressArg, IdArg, SlotArg, State};
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::{move_answer, CommandHandle, LocoDriveController, LocoDriveMessage};
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// A change of the slot a locomotive address is cached for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SlotChange {
+    /// The slot of the address was acquired by the resolver.
+    Acquired {
+        /// The address of the locomotive
+        address: AddressArg,
+        /// The slot acquired for the address
+        slot: SlotArg,
+    },
+    /// The command station moved the address to another slot.
+    Moved {
+        /// The address of the locomotive
+        address: AddressArg,
+        /// The slot the address was cached for before
+        from: SlotArg,
+        /// The slot the address is held in now
+        to: SlotArg,
+    },
+    /// Another throttle took the slot of the address. The entry was removed from the cache.
+    Stolen {
+        /// The address of the locomotive
+        address: AddressArg,
+        /// The slot taken by the other throttle
+        slot: SlotArg,
+    },
+    /// The slot of the address was purged, released or reused for another address.
+    /// The entry was removed from the cache.
+    Invalidated {
+        /// The address of the locomotive
+        address: AddressArg,
+        /// The slot the address was cached for
+        slot: SlotArg,
+    },
+}
+
+/// Caches the slot of each locomotive address and keeps the entries up to date
+/// with the slot data read from the bus.
+///
+/// An entry is moved, when the slot data reports its address in another slot.
+/// It is removed, when its slot is purged, is no longer in use, holds another address,
+/// or is taken over by a throttle with another id.
+#[derive(Debug, Clone, Default)]
+pub struct SlotCache {
+    /// The slot of each address with the id of the throttle last reported using it
+    entries: HashMap<AddressArg, (SlotArg, IdArg)>,
+}
+
+impl SlotCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The slot cached for `address`.
+    pub fn slot(&self, address: AddressArg) -> Option<SlotArg> {
+        self.entries.get(&address).map(|(slot, _)| *slot)
+    }
+
+    /// # Returns
+    ///
+    /// All cached addresses with their slots.
+    pub fn entries(&self) -> Vec<(AddressArg, SlotArg)> {
+        self.entries
+            .iter()
+            .map(|(address, (slot, _))| (*address, *slot))
+            .collect()
+    }
+
+    /// Caches `slot` for `address`, used by the throttle with `id`.
+    pub fn insert(&mut self, address: AddressArg, slot: SlotArg, id: IdArg) {
+        self.entries.insert(address, (slot, id));
+    }
+
+    /// Removes the entry of `address`.
+    ///
+    /// # Returns
+    ///
+    /// The slot that was cached for `address`.
+    pub fn remove(&mut self, address: AddressArg) -> Option<SlotArg> {
+        self.entries.remove(&address).map(|(slot, _)| slot)
+    }
+
+    /// Updates the entries by one `message` received from a controller.
+    ///
+    /// # Returns
+    ///
+    /// The changes of the entries caused by the message.
+    pub fn update(&mut self, message: &LocoDriveMessage) -> Vec<SlotChange> {
+        match *message {
+            LocoDriveMessage::Message(Message::SlRdData(slot, stat1, address, .., id))
+            | LocoDriveMessage::Echo(Message::SlRdData(slot, stat1, address, .., id)) => {
+                let mut changes = self.invalidate_slot(slot, Some(address));
+
+                match self.entries.get_mut(&address) {
+                    Some(entry) if stat1.state() != State::InUse && entry.0 == slot => {
+                        changes.push(SlotChange::Invalidated { address, slot });
+                        self.entries.remove(&address);
+                    }
+                    // An unused slot holding the address does not move it
+                    Some(_) if stat1.state() != State::InUse => {}
+                    Some(entry) if entry.0 != slot => {
+                        changes.push(SlotChange::Moved {
+                            address,
+                            from: entry.0,
+                            to: slot,
+                        });
+                        *entry = (slot, id);
+                    }
+                    Some(entry) if entry.1 != id => {
+                        changes.push(SlotChange::Stolen { address, slot });
+                        self.entries.remove(&address);
+                    }
+                    _ => {}
+                }
+                changes
+            }
+            LocoDriveMessage::SlotPurged(slot) => self.invalidate_slot(slot, None),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Removes the entries of `slot`, except the one of `keep`.
+    ///
+    /// # Returns
+    ///
+    /// The removed entries.
+    fn invalidate_slot(&mut self, slot: SlotArg, keep: Option<AddressArg>) -> Vec<SlotChange> {
+        let invalid: Vec<AddressArg> = self
+            .entries
+            .iter()
+            .filter(|(address, (cached, _))| *cached == slot && Some(**address) != keep)
+            .map(|(address, _)| *address)
+            .collect();
+
+        invalid
+            .into_iter()
+            .map(|address| {
+                self.entries.remove(&address);
+                SlotChange::Invalidated { address, slot }
+            })
+            .collect()
+    }
+}
+
+/// Resolves the slot of a locomotive address, acquiring it from the command station if needed.
+///
+/// The slots are answered from a [`SlotCache`], that is kept up to date in the background
+/// with the messages received by the controller. On a cache miss, the address is requested
+/// by [`Message::LocoAdr`] and its slot is marked in use by a `NULL`-Move.
+///
+/// Dropping the resolver stops updating the cache.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{AddressArg, SpeedArg};
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::protocol::Message;
+/// use locodrive::slot_cache::SlotResolver;
+///
+/// # async fn drive(controller: LocoDriveController) -> Result<(), locodrive::error::LocoDriveSendingError> {
+/// let slots = SlotResolver::new(&controller);
+/// let slot = slots.slot_for(AddressArg::new(5)).await?;
+///
+/// controller
+///     .command_handle()
+///     .send_message(Message::LocoSpd(slot, SpeedArg::Drive(20)))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SlotResolver {
+    /// Writes the acquiring messages
+    handle: CommandHandle,
+    /// The cached slots
+    cache: Arc<Mutex<SlotCache>>,
+    /// Broadcasts the changes of the cached slots
+    changes: broadcast::Sender<SlotChange>,
+    /// The task updating the cache
+    task: JoinHandle<()>,
+}
+
+impl SlotResolver {
+    /// Creates a resolver acquiring the slots with `controller`.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        let cache = Arc::new(Mutex::new(SlotCache::new()));
+        let (changes, _) = broadcast::channel(16);
+        let mut messages = controller.subscribe();
+
+        let updated = cache.clone();
+        let notify = changes.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match messages.recv().await {
+                    Ok(message) => {
+                        for change in updated.lock().unwrap().update(&message) {
+                            log_debug!("Slot cache changed: {:?}", change);
+                            // Nobody may listen to the changes
+                            let _ = notify.send(change);
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        SlotResolver {
+            handle: controller.command_handle(),
+            cache,
+            changes,
+            task,
+        }
+    }
+
+    /// Resolves the slot of `address`. If it is not cached, the address is requested from
+    /// the command station and its slot is marked in use.
+    ///
+    /// # Errors
+    ///
+    /// - [`LocoDriveSendingError::Rejected`]: If no free slot is available or the slot
+    ///   could not be marked in use
+    /// - Any other [`LocoDriveSendingError`] if the requests could not be send
+    pub async fn slot_for(&self, address: AddressArg) -> Result<SlotArg, LocoDriveSendingError> {
+        if let Some(slot) = self.cached(address) {
+            return Ok(slot);
+        }
+
+        let request = Message::LocoAdr(address);
+        let (slot, stat1, id) = self
+            .handle
+            .request(request, |answer| match *answer {
+                Message::SlRdData(slot, stat1, read, .., id) if read == address => {
+                    Some(Ok((slot, stat1, id)))
+                }
+                Message::LongAck(lopc, ack) if lopc.check_opc(&request) && ack.failed() => {
+                    Some(Err(ack))
+                }
+                _ => None,
+            })
+            .await?
+            .map_err(LocoDriveSendingError::Rejected)?;
+
+        if stat1.state() != State::InUse {
+            let null_move = Message::MoveSlots(slot, slot);
+            self.handle
+                .request(null_move, |answer| move_answer(&null_move, answer))
+                .await?
+                .map_err(LocoDriveSendingError::Rejected)?;
+        }
+
+        self.cache.lock().unwrap().insert(address, slot, id);
+        // Nobody may listen to the changes
+        let _ = self.changes.send(SlotChange::Acquired { address, slot });
+        Ok(slot)
+    }
+
+    /// # Returns
+    ///
+    /// The slot cached for `address`, without acquiring it on a miss.
+    pub fn cached(&self, address: AddressArg) -> Option<SlotArg> {
+        self.cache.lock().unwrap().slot(address)
+    }
+
+    /// Removes the entry of `address`, so its slot is acquired again on the next resolve.
+    ///
+    /// # Returns
+    ///
+    /// The slot that was cached for `address`.
+    pub fn invalidate(&self, address: AddressArg) -> Option<SlotArg> {
+        self.cache.lock().unwrap().remove(address)
+    }
+
+    /// Subscribes to the changes of the cached slots,
+    /// to notice when a locomotive was moved to another slot or taken by another throttle.
+    pub fn subscribe(&self) -> broadcast::Receiver<SlotChange> {
+        self.changes.subscribe()
+    }
+}
+
+/// Extends standard drop implementation to stop updating the cache.
+impl Drop for SlotResolver {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,75 @@
+use crate::args::{SlotArg, State};
+use crate::protocol::Message;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Tracks the slots used by a controller, to recognize when the command station purges one of them.
+///
+/// A slot is used after the controller wrote its speed or functions, marked it in use or did a
+/// `NULL`-Move on it. It is no longer used after the controller released it.
+/// If the command station reports a used slot not to be in use anymore, it was purged.
+#[derive(Debug, Default)]
+pub(crate) struct SlotUsage {
+    /// The slots used by the controller
+    used: Mutex<HashSet<SlotArg>>,
+}
+
+impl SlotUsage {
+    /// Creates a tracker without any used slot.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The slots used by the controller.
+    pub(crate) fn used(&self) -> Vec<SlotArg> {
+        self.used.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Notes a `message` written by the controller.
+    pub(crate) fn note_written(&self, message: &Message) {
+        let mut used = self.used.lock().unwrap();
+        match *message {
+            Message::LocoSpd(slot, _)
+            | Message::LocoDirf(slot, _)
+            | Message::LocoSnd(slot, _)
+            | Message::UhliFun(slot, _) => {
+                used.insert(slot);
+            }
+            Message::MoveSlots(src, dst) if src == dst => {
+                used.insert(src);
+            }
+            // A slot put to dispatch is handed to another throttle
+            Message::MoveSlots(src, dst) if dst.slot() == 0 => {
+                used.remove(&src);
+            }
+            Message::SlotStat1(slot, stat1) => {
+                if stat1.state() == State::InUse {
+                    used.insert(slot);
+                } else {
+                    used.remove(&slot);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Notes a `message` read from the bus.
+    ///
+    /// # Returns
+    ///
+    /// The used slot reported not to be in use anymore, as it was purged.
+    pub(crate) fn note_read(&self, message: &Message) -> Option<SlotArg> {
+        let (slot, stat1) = match *message {
+            Message::SlRdData(slot, stat1, ..) | Message::SlotStat1(slot, stat1) => (slot, stat1),
+            _ => return None,
+        };
+
+        if stat1.state() != State::InUse && self.used.lock().unwrap().remove(&slot) {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::args::{SensorLevel, SlotArg, SwitchArg, SwitchDirection};
+use crate::error::ConfigError;
+use crate::fast_clock::{ClockMaster, ClockTime};
+use crate::manager::{Manager, SensorManager, SlotManager, SlotState, TurnoutTable};
+use crate::protocol::Message;
+use crate::routes::Route;
+use std::path::Path;
+
+/// The state of a layout at one point of time, to be saved to a file and restored on startup.
+///
+/// The snapshot holds the state aggregated by the [`SlotManager`], the [`TurnoutTable`]
+/// and the [`SensorManager`], as well as the time of the layout clock.
+/// It is saved as TOML or RON, depending on the extension of the file.
+///
+/// After restoring the managers, the physical layout is brought back to the saved state
+/// by setting the [`LayoutSnapshot::reconciliation()`] route with a [`crate::routes::RouteSetter`].
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::manager::{SensorManager, SlotManager, TurnoutTable};
+/// use locodrive::routes::RouteSetter;
+/// use locodrive::snapshot::LayoutSnapshot;
+///
+/// # async fn restore(controller: LocoDriveController) -> Result<(), Box<dyn std::error::Error>> {
+/// let snapshot = LayoutSnapshot::load("layout-state.toml")?;
+///
+/// let (mut slots, mut turnouts, mut sensors) =
+///     (SlotManager::new(), TurnoutTable::new(), SensorManager::new());
+/// let route = snapshot.reconciliation(&turnouts);
+/// snapshot.restore(&mut slots, &mut turnouts, &mut sensors);
+///
+/// RouteSetter::new(&controller).set(&route).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LayoutSnapshot {
+    /// The known state of each slot, ordered by slot
+    pub slots: Vec<(SlotArg, SlotState)>,
+    /// The known direction of each turnout, ordered by address
+    pub turnouts: Vec<(u16, SwitchDirection)>,
+    /// The known level of each sensor, ordered by address
+    pub sensors: Vec<(u16, SensorLevel)>,
+    /// The time of the layout clock
+    pub clock: Option<ClockTime>,
+    /// The rate of the layout clock
+    pub clock_rate: Option<u8>,
+}
+
+impl LayoutSnapshot {
+    /// Captures the state currently known by the managers and the layout `clock` with its rate.
+    pub fn capture(
+        slots: &SlotManager,
+        turnouts: &TurnoutTable,
+        sensors: &SensorManager,
+        clock: Option<(ClockTime, u8)>,
+    ) -> Self {
+        let mut snapshot = LayoutSnapshot {
+            slots: slots
+                .slots()
+                .iter()
+                .map(|(slot, state)| (*slot, *state))
+                .collect(),
+            turnouts: turnouts
+                .turnouts()
+                .iter()
+                .filter_map(|(address, turnout)| Some((*address, turnout.direction()?)))
+                .collect(),
+            sensors: sensors
+                .sensors()
+                .iter()
+                .map(|(address, level)| (*address, *level))
+                .collect(),
+            clock: clock.map(|(time, _)| time),
+            clock_rate: clock.map(|(_, rate)| rate),
+        };
+        // Sorted, so saving the same state always writes the same file
+        snapshot.slots.sort_by_key(|(slot, _)| slot.slot());
+        snapshot.turnouts.sort_by_key(|(address, _)| *address);
+        snapshot.sensors.sort_by_key(|(address, _)| *address);
+        snapshot
+    }
+
+    /// Restores the saved state into the managers. The turnouts are restored as commanded.
+    pub fn restore(
+        &self,
+        slots: &mut SlotManager,
+        turnouts: &mut TurnoutTable,
+        sensors: &mut SensorManager,
+    ) {
+        for (slot, state) in &self.slots {
+            slots.set(*slot, *state);
+        }
+        for &(address, direction) in &self.turnouts {
+            turnouts.handle(&Message::SwReq(SwitchArg::new(address, direction, true)));
+        }
+        for (address, level) in &self.sensors {
+            sensors.set(*address, *level);
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The route switching all turnouts, whose direction known by `current` differs
+    /// from the saved one or is unknown, back to their saved direction.
+    pub fn reconciliation(&self, current: &TurnoutTable) -> Route {
+        self.turnouts
+            .iter()
+            .filter(|(address, direction)| current.state(*address) != Some(*direction))
+            .fold(Route::new("snapshot"), |route, (address, direction)| {
+                route.step(*address, *direction)
+            })
+    }
+
+    /// # Returns
+    ///
+    /// A clock master continuing the saved layout clock, if the clock was saved.
+    pub fn clock_master(&self) -> Option<ClockMaster> {
+        Some(ClockMaster::new(self.clock?, self.clock_rate?))
+    }
+
+    /// Loads a snapshot from a TOML or RON file, depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be read
+    /// - [`ConfigError::Parse`]: If the file holds no valid snapshot
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match extension(path).as_str() {
+            "toml" => toml::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            "ron" => ron::from_str(&content).map_err(|err| ConfigError::Parse(err.to_string())),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Saves this snapshot to a TOML or RON file, depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// - [`ConfigError::Io`]: If the file could not be written
+    /// - [`ConfigError::Parse`]: If the snapshot could not be serialized
+    /// - [`ConfigError::UnsupportedFormat`]: If the extension is neither `toml` nor `ron`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let content = match extension(path).as_str() {
+            "toml" => toml::to_string(self).map_err(|err| ConfigError::Parse(err.to_string()))?,
+            "ron" => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|err| ConfigError::Parse(err.to_string()))?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        };
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// # Returns
+///
+/// The lower case extension of `path`.
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
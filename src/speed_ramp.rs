@@ -0,0 +1,287 @@
+use crate::args::{SlotArg, SpeedArg};
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver,
+};
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+/// The thousandths a speed step is tracked with while ramping.
+const MILLI: u32 = 1000;
+
+/// How fast a locomotive changes its speed, in speed steps per second.
+///
+/// A rate of `0` changes the speed immediately.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Momentum {
+    /// The speed steps per second the speed increases by
+    pub acceleration: u16,
+    /// The speed steps per second the speed decreases by
+    pub deceleration: u16,
+}
+
+impl Momentum {
+    /// Creates a momentum accelerating and decelerating by the given speed steps per second.
+    pub fn new(acceleration: u16, deceleration: u16) -> Self {
+        Momentum {
+            acceleration,
+            deceleration,
+        }
+    }
+
+    /// Creates a momentum changing the speed immediately.
+    pub fn immediate() -> Self {
+        Self::default()
+    }
+
+    /// # Returns
+    ///
+    /// The speed reached `elapsed` after starting at `from` to approach `to`.
+    pub fn speed_after(&self, from: u8, to: u8, elapsed: Duration) -> u8 {
+        (self.advance(from as u32 * MILLI, to, elapsed) / MILLI) as u8
+    }
+
+    /// # Returns
+    ///
+    /// The speed in thousandths of a step reached `elapsed` after starting at `from` to approach `to`.
+    fn advance(&self, from: u32, to: u8, elapsed: Duration) -> u32 {
+        let to = to as u32 * MILLI;
+        let rate = if to > from {
+            self.acceleration
+        } else {
+            self.deceleration
+        };
+        if rate == 0 {
+            return to;
+        }
+
+        let steps = (rate as u128 * elapsed.as_millis()).min(u32::MAX as u128) as u32;
+        if to > from {
+            from.saturating_add(steps).min(to)
+        } else {
+            from.saturating_sub(steps).max(to)
+        }
+    }
+}
+
+/// The ramp of one slot.
+#[derive(Debug, Copy, Clone)]
+struct RampSlot {
+    /// The current speed in thousandths of a step
+    current: u32,
+    /// The speed to approach
+    target: u8,
+    /// How fast the speed approaches the target
+    momentum: Momentum,
+}
+
+impl RampSlot {
+    /// Creates a ramp standing still and changing its speed immediately.
+    fn new() -> Self {
+        RampSlot {
+            current: 0,
+            target: 0,
+            momentum: Momentum::immediate(),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The current speed in whole steps.
+    fn speed(&self) -> u8 {
+        (self.current / MILLI) as u8
+    }
+}
+
+/// Drives slots smoothly to their target speeds, by writing intermediate speeds over time.
+///
+/// Each slot approaches its target with its own [`Momentum`]. The speeds are updated
+/// once per interval and only written, when they changed by a whole step.
+/// An emergency stop bypasses the ramp and is written immediately.
+///
+/// The current speed of a slot is followed from the speeds read from the bus, so a ramp
+/// continues from the speed set by another throttle. A slot not read yet starts at `0`.
+///
+/// Dropping the ramp stops writing the speeds. The slots keep their last written speed.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::{SlotArg, SpeedArg};
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::speed_ramp::{Momentum, SpeedRamp};
+///
+/// # async fn drive(controller: LocoDriveController) -> Result<(), locodrive::error::LocoDriveSendingError> {
+/// let ramp = SpeedRamp::new(&controller);
+/// ramp.set_momentum(SlotArg::new(3), Momentum::new(20, 40));
+/// ramp.set_target(SlotArg::new(3), SpeedArg::Drive(80)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SpeedRamp {
+    /// Writes the speeds bypassing the ramp
+    handle: CommandHandle,
+    /// The ramps of the slots
+    slots: Arc<Mutex<HashMap<SlotArg, RampSlot>>>,
+    /// The task writing the intermediate speeds
+    task: JoinHandle<()>,
+}
+
+impl SpeedRamp {
+    /// Creates a ramp writing the speeds with `controller` every 100 milliseconds.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        Self::with_interval(controller, Duration::from_millis(100))
+    }
+
+    /// Creates a ramp writing the speeds with `controller` every `interval`.
+    pub fn with_interval(controller: &LocoDriveController, interval: Duration) -> Self {
+        let handle = controller.command_handle();
+        let slots = Arc::new(Mutex::new(HashMap::new()));
+        let task = tokio::spawn(run(
+            handle.clone(),
+            slots.clone(),
+            controller.subscribe(),
+            interval,
+        ));
+
+        SpeedRamp {
+            handle,
+            slots,
+            task,
+        }
+    }
+
+    /// Sets how fast `slot` approaches its target speed.
+    ///
+    /// Defaults to [`Momentum::immediate()`].
+    pub fn set_momentum(&self, slot: SlotArg, momentum: Momentum) {
+        self.slots
+            .lock()
+            .unwrap()
+            .entry(slot)
+            .or_insert_with(RampSlot::new)
+            .momentum = momentum;
+    }
+
+    /// Sets the speed `slot` approaches.
+    ///
+    /// A [`SpeedArg::EmergencyStop`] bypasses the ramp and is written immediately,
+    /// as is any speed of a slot with [`Momentum::immediate()`].
+    ///
+    /// # Errors
+    ///
+    /// The [`LocoDriveSendingError`] if the speed was written immediately and failed.
+    pub async fn set_target(
+        &self,
+        slot: SlotArg,
+        speed: SpeedArg,
+    ) -> Result<(), LocoDriveSendingError> {
+        let immediate = {
+            let mut slots = self.slots.lock().unwrap();
+            let ramp = slots.entry(slot).or_insert_with(RampSlot::new);
+            ramp.target = speed.get_spd();
+
+            if speed == SpeedArg::EmergencyStop || ramp.momentum == Momentum::immediate() {
+                ramp.current = ramp.target as u32 * MILLI;
+                true
+            } else {
+                false
+            }
+        };
+
+        if immediate {
+            self.handle
+                .send_message(Message::LocoSpd(slot, speed))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// # Returns
+    ///
+    /// The speed last written to `slot` by this ramp or read from the bus.
+    pub fn speed(&self, slot: SlotArg) -> Option<u8> {
+        self.slots.lock().unwrap().get(&slot).map(RampSlot::speed)
+    }
+
+    /// # Returns
+    ///
+    /// The speed `slot` approaches.
+    pub fn target(&self, slot: SlotArg) -> Option<u8> {
+        self.slots
+            .lock()
+            .unwrap()
+            .get(&slot)
+            .map(|ramp| ramp.target)
+    }
+
+    /// Stops ramping `slot`. The slot keeps its current speed.
+    pub fn remove(&self, slot: SlotArg) {
+        self.slots.lock().unwrap().remove(&slot);
+    }
+}
+
+/// Extends standard drop implementation to stop writing the speeds.
+impl Drop for SpeedRamp {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Runs the ramps of `slots`, writing their speeds with `handle` every `period`
+/// and following the speeds received by `messages`, until the controller is dropped.
+async fn run(
+    handle: CommandHandle,
+    slots: Arc<Mutex<HashMap<SlotArg, RampSlot>>>,
+    mut messages: LocoDriveReceiver,
+    period: Duration,
+) {
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = Instant::now();
+
+    loop {
+        tokio::select! {
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    if let Message::LocoSpd(slot, speed) | Message::SlRdData(slot, _, _, speed, ..) = message {
+                        if let Some(ramp) = slots.lock().unwrap().get_mut(&slot) {
+                            // Only whole steps are written, so the thousandths are kept
+                            if ramp.speed() != speed.get_spd() {
+                                ramp.current = speed.get_spd() as u32 * MILLI;
+                            }
+                        }
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            now = ticks.tick() => {
+                let elapsed = now.saturating_duration_since(last);
+                last = now;
+
+                let changed: Vec<(SlotArg, u8)> = slots
+                    .lock()
+                    .unwrap()
+                    .iter_mut()
+                    .filter_map(|(slot, ramp)| {
+                        let speed = ramp.speed();
+                        ramp.current = ramp.momentum.advance(ramp.current, ramp.target, elapsed);
+                        (ramp.speed() != speed).then(|| (*slot, ramp.speed()))
+                    })
+                    .collect();
+
+                for (slot, speed) in changed {
+                    let message = Message::LocoSpd(slot, SpeedArg::new(speed));
+                    if let Err(err) = handle.send_message(message).await {
+                        log_error!("Could not ramp slot {}: {}", slot.slot(), err);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,784 @@
+use crate::args::{
+    AddressArg, DirfArg, SensorLevel, SlotArg, SndArg, SpeedArg, SwitchArg, SwitchDirection,
+};
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver, PowerState,
+};
+use crate::manager::{Manager, SensorManager, SlotManager, SlotState, TurnoutTable};
+use crate::message_ref::MessageRef;
+use crate::protocol::Message;
+use crate::slot_cache::SlotResolver;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// The bus the model railroad is served as.
+const BUS: u16 = 1;
+
+/// The highest speed step reported to the clients.
+const MAX_SPEED: u16 = 126;
+
+/// Serves the Simple Railroad Command Protocol 0.8 to clients, like srcpd front-ends,
+/// using a [`LocoDriveController`] as command station backend.
+///
+/// The model railroad is served as bus `1` with the devices:
+///
+/// - `GL`: Locomotives by their address. `INIT` acquires their slot by a [`SlotResolver`],
+///   `SET` drives them with the functions 0 to 8 and `TERM` releases the slot.
+/// - `GA`: Turnouts by their address. Port `0` switches them curved, port `1` straight.
+/// - `FB`: Sensors by their address.
+/// - `POWER`: The track power.
+///
+/// Clients in command mode send commands answered by the server. Clients in info mode
+/// are told about every change of the devices.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::srcp::SrcpServer;
+///
+/// # async fn serve(controller: LocoDriveController) -> std::io::Result<()> {
+/// let server = SrcpServer::bind("0.0.0.0:4303").await?.start(&controller);
+/// println!("Serving on {}", server.local_addr());
+/// # Ok(())
+/// # }
+/// ```
+pub struct SrcpServer {
+    /// The socket accepting the clients
+    listener: TcpListener,
+}
+
+impl SrcpServer {
+    /// Binds a server to `address`.
+    ///
+    /// # Errors
+    ///
+    /// If the address could not be bound.
+    pub async fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Ok(SrcpServer {
+            listener: TcpListener::bind(address).await?,
+        })
+    }
+
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Starts accepting clients and uses `controller` as command station.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the server. Dropping it disconnects all clients and stops the server.
+    pub fn start(self, controller: &LocoDriveController) -> SrcpServerHandle {
+        // The address is known, as it was bound successfully
+        let local_addr = self
+            .listener
+            .local_addr()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+        let shared = Arc::new(Shared {
+            handle: controller.command_handle(),
+            resolver: SlotResolver::new(controller),
+            layout: Mutex::new(Layout {
+                slots: SlotManager::new(),
+                turnouts: TurnoutTable::new(),
+                sensors: SensorManager::new(),
+                power: controller.power_state(),
+                locos: HashMap::new(),
+            }),
+            updates: broadcast::channel(64).0,
+            sessions: AtomicU32::new(0),
+        });
+
+        SrcpServerHandle {
+            local_addr,
+            task: tokio::spawn(accept(self.listener, shared, controller.subscribe())),
+        }
+    }
+}
+
+/// The handle of a running [`SrcpServer`].
+///
+/// Dropping the handle disconnects all clients and stops the server.
+#[derive(Debug)]
+pub struct SrcpServerHandle {
+    /// The address the server is bound to
+    local_addr: SocketAddr,
+    /// The task accepting the clients
+    task: JoinHandle<()>,
+}
+
+impl SrcpServerHandle {
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Extends standard drop implementation to stop the server.
+impl Drop for SrcpServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A change of a device to tell the clients in info mode.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Update {
+    /// The state of a slot changed
+    Slot(SlotArg),
+    /// A turnout changed its direction
+    Turnout(u16, SwitchDirection),
+    /// A sensor changed its level
+    Sensor(u16, SensorLevel),
+    /// The track power changed
+    Power(PowerState),
+}
+
+/// The state of the devices tracked from the bus.
+struct Layout {
+    /// The known slots
+    slots: SlotManager,
+    /// The known turnouts
+    turnouts: TurnoutTable,
+    /// The known sensors
+    sensors: SensorManager,
+    /// The track power
+    power: PowerState,
+    /// The slots of the initialized locomotives by their address
+    locos: HashMap<u16, SlotArg>,
+}
+
+/// The state shared by all clients of a server.
+struct Shared {
+    /// Writes the messages of the clients
+    handle: CommandHandle,
+    /// Resolves the slots of the initialized locomotives
+    resolver: SlotResolver,
+    /// The tracked state of the devices
+    layout: Mutex<Layout>,
+    /// Tells the clients in info mode about changes of the devices
+    updates: broadcast::Sender<Update>,
+    /// The number of sessions started
+    sessions: AtomicU32,
+}
+
+impl Shared {
+    /// Tracks `message` and tells the clients about the change it caused.
+    fn track(&self, message: &Message) {
+        let mut layout = self.layout.lock().unwrap();
+        let frame = message.to_message();
+        let slot = MessageRef::new(&frame)
+            .ok()
+            .and_then(|message| message.slot());
+        let before = slot.and_then(|slot| layout.slots.slot(slot).copied());
+        layout.slots.handle(message);
+
+        let power = match *message {
+            Message::GpOn => Some(PowerState::On),
+            Message::GpOff => Some(PowerState::Off),
+            Message::Idle => Some(PowerState::Idle),
+            _ => None,
+        };
+        let update = match (*message, power, slot) {
+            (_, Some(power), _) if layout.power != power => {
+                layout.power = power;
+                Some(Update::Power(power))
+            }
+            (_, Some(_), _) => None,
+            (_, None, Some(slot)) if layout.slots.slot(slot).copied() != before => {
+                Some(Update::Slot(slot))
+            }
+            (Message::InputRep(input), ..) => {
+                let changed = layout.sensors.level(input.address()) != Some(input.sensor_level());
+                layout.sensors.handle(message);
+                match changed {
+                    true => Some(Update::Sensor(input.address(), input.sensor_level())),
+                    false => None,
+                }
+            }
+            _ => layout
+                .turnouts
+                .update(message)
+                .map(|change| Update::Turnout(change.address, change.direction)),
+        };
+        drop(layout);
+
+        if let Some(update) = update {
+            // No client may be connected
+            let _ = self.updates.send(update);
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The slot of the initialized locomotive at `address` and its tracked state.
+    fn loco(&self, address: u16) -> Option<(SlotArg, SlotState)> {
+        let layout = self.layout.lock().unwrap();
+        let slot = *layout.locos.get(&address)?;
+        Some((slot, layout.slots.slot(slot).copied().unwrap_or_default()))
+    }
+
+    /// Writes a `message` of a client to the bus and tracks it right away,
+    /// so a following command builds on it.
+    async fn send(&self, message: Message) -> Result<(), Reply> {
+        self.handle
+            .send_message(message)
+            .await
+            .map_err(Reply::sending)?;
+        self.track(&message);
+        Ok(())
+    }
+}
+
+/// Accepts clients on `listener` until the controller is dropped.
+async fn accept(listener: TcpListener, shared: Arc<Shared>, mut messages: LocoDriveReceiver) {
+    let mut clients = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    log_info!("SRCP client {} connected", address);
+                    clients.spawn(serve(stream, address, shared.clone()));
+                }
+                Err(err) => log_error!("Could not accept an SRCP client: {}", err),
+            },
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    shared.track(&message);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            // Reap the finished clients
+            Some(_) = clients.join_next(), if !clients.is_empty() => {}
+        }
+    }
+}
+
+/// A reply to a client, made of its code and text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Reply(pub(crate) u16, pub(crate) String);
+
+impl Reply {
+    /// # Returns
+    ///
+    /// The reply confirming a command.
+    fn ok() -> Self {
+        Reply(200, "OK".to_string())
+    }
+
+    /// # Returns
+    ///
+    /// The error reply with `code` and `text`.
+    fn error(code: u16, text: &str) -> Self {
+        Reply(code, format!("ERROR {}", text))
+    }
+
+    /// # Returns
+    ///
+    /// The error reply of a message that could not be send.
+    fn sending(err: LocoDriveSendingError) -> Self {
+        match err {
+            LocoDriveSendingError::Timeout => Reply::error(417, "timeout"),
+            _ => Reply::error(500, "out of resources"),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The info reply with `info` about a device of the bus.
+    fn info(info: String) -> Self {
+        Reply(100, format!("INFO {} {}", BUS, info))
+    }
+
+    /// # Returns
+    ///
+    /// The line of this reply stamped with the current time.
+    fn line(&self) -> String {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        format!(
+            "{}.{:03} {} {}\n",
+            time.as_secs(),
+            time.subsec_millis(),
+            self.0,
+            self.1
+        )
+    }
+}
+
+/// A command of a client in command mode.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Request {
+    /// Acquires the slot of the locomotive at the address
+    InitGl(u16),
+    /// Drives the locomotive at the address
+    SetGl {
+        /// The address of the locomotive
+        address: u16,
+        /// Backwards with `0`, forwards with `1` or emergency stop with `2`
+        drivemode: u8,
+        /// The speed relative to the maximum speed
+        speed: u16,
+        /// The maximum speed
+        max_speed: u16,
+        /// The functions starting with function 0. Missing functions are kept.
+        functions: Vec<bool>,
+    },
+    /// Queries the state of the locomotive at the address
+    GetGl(u16),
+    /// Releases the slot of the locomotive at the address
+    TermGl(u16),
+    /// Initializes the turnout at the address
+    InitGa(u16),
+    /// Switches the port of the turnout at the address on or off, switching it off after the delay
+    SetGa(u16, u8, bool, Option<Duration>),
+    /// Queries whether the port of the turnout at the address is active
+    GetGa(u16, u8),
+    /// Queries the level of the sensor at the address
+    GetFb(u16),
+    /// Switches the track power
+    SetPower(bool),
+    /// Queries the track power
+    GetPower,
+    /// Ends the session
+    TermSession,
+}
+
+/// Parses a `line` sent by a client in command mode.
+///
+/// # Errors
+///
+/// The error reply, if the line is no valid command.
+pub(crate) fn parse_request(line: &str) -> Result<Request, Reply> {
+    let tokens: Vec<String> = line
+        .split_whitespace()
+        .map(|token| token.to_ascii_uppercase())
+        .collect();
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let number = |index: usize| -> Result<i64, Reply> {
+        let token = tokens
+            .get(index)
+            .ok_or_else(|| Reply::error(419, "list too short"))?;
+        token.parse().map_err(|_| Reply::error(412, "wrong value"))
+    };
+    let address = |index: usize| -> Result<u16, Reply> {
+        match number(index)? {
+            address @ 1..=0x3FFF => Ok(address as u16),
+            _ => Err(Reply::error(412, "wrong value")),
+        }
+    };
+    let port = |index: usize| -> Result<u8, Reply> {
+        match number(index)? {
+            port @ 0..=1 => Ok(port as u8),
+            _ => Err(Reply::error(412, "wrong value")),
+        }
+    };
+
+    match tokens[..] {
+        ["TERM", "0", "SESSION", ..] => return Ok(Request::TermSession),
+        [_, bus, ..] if bus != BUS.to_string() => return Err(Reply::error(412, "wrong value")),
+        [] | [_] | [_, _] => return Err(Reply::error(419, "list too short")),
+        _ => {}
+    }
+
+    match (tokens[0], tokens[2]) {
+        ("INIT", "GL") => Ok(Request::InitGl(address(3)?)),
+        ("SET", "GL") => {
+            let drivemode = match number(4)? {
+                drivemode @ 0..=2 => drivemode as u8,
+                _ => return Err(Reply::error(412, "wrong value")),
+            };
+            let speed = number(5)?;
+            let max_speed = number(6)?;
+            if speed < 0 || max_speed < 0 || speed > max_speed {
+                return Err(Reply::error(412, "wrong value"));
+            }
+            let functions = (7..tokens.len())
+                .map(|index| number(index).map(|on| on != 0))
+                .collect::<Result<_, _>>()?;
+            Ok(Request::SetGl {
+                address: address(3)?,
+                drivemode,
+                speed: speed.min(u16::MAX as i64) as u16,
+                max_speed: max_speed.min(u16::MAX as i64) as u16,
+                functions,
+            })
+        }
+        ("GET", "GL") => Ok(Request::GetGl(address(3)?)),
+        ("TERM", "GL") => Ok(Request::TermGl(address(3)?)),
+        ("INIT", "GA") => Ok(Request::InitGa(address(3)?)),
+        ("SET", "GA") => {
+            let delay = match number(6)? {
+                delay if delay > 0 => Some(Duration::from_millis(delay as u64)),
+                _ => None,
+            };
+            Ok(Request::SetGa(
+                address(3)?,
+                port(4)?,
+                number(5)? != 0,
+                delay,
+            ))
+        }
+        ("GET", "GA") => Ok(Request::GetGa(address(3)?, port(4)?)),
+        ("GET", "FB") => Ok(Request::GetFb(address(3)?)),
+        ("SET", "POWER") => match tokens.get(3) {
+            Some(&"ON") => Ok(Request::SetPower(true)),
+            Some(&"OFF") => Ok(Request::SetPower(false)),
+            Some(_) => Err(Reply::error(412, "wrong value")),
+            None => Err(Reply::error(419, "list too short")),
+        },
+        ("GET", "POWER") => Ok(Request::GetPower),
+        (_, "GL") | (_, "GA") | (_, "FB") | (_, "POWER") => {
+            Err(Reply::error(410, "unknown command"))
+        }
+        _ => Err(Reply::error(421, "unsupported device")),
+    }
+}
+
+/// # Returns
+///
+/// The port of a turnout active in `direction`.
+fn port_of(direction: SwitchDirection) -> u8 {
+    match direction {
+        SwitchDirection::Straight => 1,
+        SwitchDirection::Curved => 0,
+    }
+}
+
+/// # Returns
+///
+/// The info about the locomotive at `address` in `state`.
+fn gl_info(address: u16, state: SlotState) -> Reply {
+    let dirf = state
+        .dirf
+        .unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false));
+    let snd = state
+        .snd
+        .unwrap_or_else(|| SndArg::new(false, false, false, false));
+    let drivemode = match state.speed {
+        Some(SpeedArg::EmergencyStop) => 2,
+        _ => dirf.dir() as u8,
+    };
+    let speed = state.speed.map_or(0, |speed| speed.get_spd());
+    let functions: Vec<String> = (0..=8)
+        .map(|function| match function {
+            0..=4 => dirf.f(function),
+            _ => snd.f(function),
+        })
+        .map(|on| (on as u8).to_string())
+        .collect();
+    Reply::info(format!(
+        "GL {} {} {} {} {}",
+        address,
+        drivemode,
+        speed,
+        MAX_SPEED,
+        functions.join(" ")
+    ))
+}
+
+/// # Returns
+///
+/// The info about the track `power`.
+fn power_info(power: PowerState) -> Reply {
+    let power = match power {
+        PowerState::Off => "OFF",
+        _ => "ON",
+    };
+    Reply::info(format!("POWER {}", power))
+}
+
+/// Executes the `request` of a client in command mode.
+///
+/// # Returns
+///
+/// The reply to the request.
+async fn execute(shared: &Shared, request: Request) -> Reply {
+    let result = match request {
+        Request::InitGl(address) => init_gl(shared, address).await,
+        Request::SetGl {
+            address,
+            drivemode,
+            speed,
+            max_speed,
+            functions,
+        } => set_gl(shared, address, drivemode, speed, max_speed, &functions).await,
+        Request::GetGl(address) => match shared.loco(address) {
+            Some((_, state)) => Ok(gl_info(address, state)),
+            None => Err(Reply::error(416, "no data")),
+        },
+        Request::TermGl(address) => {
+            let slot = shared.layout.lock().unwrap().locos.remove(&address);
+            match slot {
+                Some(slot) => shared
+                    .handle
+                    .release_slot(slot)
+                    .await
+                    .map(|_| Reply::ok())
+                    .map_err(Reply::sending),
+                None => Err(Reply::error(416, "no data")),
+            }
+        }
+        Request::InitGa(_) => Ok(Reply::ok()),
+        Request::SetGa(address, port, on, delay) => {
+            let direction = match port {
+                1 => SwitchDirection::Straight,
+                _ => SwitchDirection::Curved,
+            };
+            let result = shared
+                .send(Message::SwReq(SwitchArg::new(address, direction, on)))
+                .await;
+            if let (Ok(()), true, Some(delay)) = (&result, on, delay) {
+                // The port is switched off in the background, so the client is not blocked
+                let handle = shared.handle.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let off = Message::SwReq(SwitchArg::new(address, direction, false));
+                    if let Err(err) = handle.send_message(off).await {
+                        log_error!("Could not switch off turnout {}: {}", address, err);
+                    }
+                });
+            }
+            result.map(|_| Reply::ok())
+        }
+        Request::GetGa(address, port) => {
+            let layout = shared.layout.lock().unwrap();
+            match layout.turnouts.state(address) {
+                Some(direction) => Ok(Reply::info(format!(
+                    "GA {} {} {}",
+                    address,
+                    port,
+                    (port_of(direction) == port) as u8
+                ))),
+                None => Err(Reply::error(416, "no data")),
+            }
+        }
+        Request::GetFb(address) => {
+            let layout = shared.layout.lock().unwrap();
+            let level = layout.sensors.level(address);
+            Ok(Reply::info(format!(
+                "FB {} {}",
+                address,
+                (level == Some(SensorLevel::High)) as u8
+            )))
+        }
+        Request::SetPower(on) => {
+            let message = match on {
+                true => Message::GpOn,
+                false => Message::GpOff,
+            };
+            shared.send(message).await.map(|_| Reply::ok())
+        }
+        Request::GetPower => Ok(power_info(shared.layout.lock().unwrap().power)),
+        Request::TermSession => Ok(Reply::ok()),
+    };
+    result.unwrap_or_else(|reply| reply)
+}
+
+/// Acquires the slot of the locomotive at `address`.
+async fn init_gl(shared: &Shared, address: u16) -> Result<Reply, Reply> {
+    let slot = shared
+        .resolver
+        .slot_for(AddressArg::new(address))
+        .await
+        .map_err(Reply::sending)?;
+    shared.layout.lock().unwrap().locos.insert(address, slot);
+    Ok(Reply::ok())
+}
+
+/// Drives the locomotive at `address` by sending the messages of the values that changed.
+async fn set_gl(
+    shared: &Shared,
+    address: u16,
+    drivemode: u8,
+    speed: u16,
+    max_speed: u16,
+    functions: &[bool],
+) -> Result<Reply, Reply> {
+    let (slot, state) = shared
+        .loco(address)
+        .ok_or_else(|| Reply::error(416, "no data"))?;
+    let mut dirf = state
+        .dirf
+        .unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false));
+    let mut snd = state
+        .snd
+        .unwrap_or_else(|| SndArg::new(false, false, false, false));
+
+    if drivemode != 2 {
+        dirf.set_dir(drivemode == 1);
+    }
+    for (function, &on) in functions.iter().enumerate().take(9) {
+        match function as u8 {
+            function @ 0..=4 => dirf.set_f(function, on),
+            function => snd.set_f(function, on),
+        }
+    }
+    if state.dirf != Some(dirf) {
+        shared.send(Message::LocoDirf(slot, dirf)).await?;
+    }
+    if state.snd != Some(snd) {
+        shared.send(Message::LocoSnd(slot, snd)).await?;
+    }
+
+    let speed = match (drivemode, max_speed) {
+        (2, _) => SpeedArg::EmergencyStop,
+        (_, 0) => SpeedArg::Stop,
+        _ => SpeedArg::new((speed as u32 * MAX_SPEED as u32 / max_speed as u32) as u8),
+    };
+    shared.send(Message::LocoSpd(slot, speed)).await?;
+    Ok(Reply::ok())
+}
+
+/// The state of a session.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Mode {
+    /// The session is negotiated, in info mode if set
+    Handshake(bool),
+    /// The client sends commands
+    Command,
+    /// The client is told about changes
+    Info,
+}
+
+/// Negotiates a session with a `line` of the client.
+///
+/// # Returns
+///
+/// The reply and whether the session starts.
+fn handshake(line: &str, info: &mut bool) -> (Reply, bool) {
+    let tokens: Vec<String> = line
+        .split_whitespace()
+        .map(|token| token.to_ascii_uppercase())
+        .collect();
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    match tokens[..] {
+        ["SET", "PROTOCOL", "SRCP", version] if version.starts_with("0.8") => {
+            (Reply(201, "OK PROTOCOL SRCP".to_string()), false)
+        }
+        ["SET", "PROTOCOL", ..] => (Reply::error(400, "unsupported protocol"), false),
+        ["SET", "CONNECTIONMODE", "SRCP", mode @ ("COMMAND" | "INFO")] => {
+            *info = mode == "INFO";
+            (Reply(202, "OK CONNECTIONMODE".to_string()), false)
+        }
+        ["SET", "CONNECTIONMODE", ..] => (Reply::error(401, "unsupported connection mode"), false),
+        ["GO"] => (Reply::ok(), true),
+        _ => (Reply::error(410, "unknown command"), false),
+    }
+}
+
+/// # Returns
+///
+/// The replies telling a client in info mode about `update`.
+fn reflect(shared: &Shared, update: Update) -> Vec<Reply> {
+    match update {
+        Update::Slot(slot) => {
+            let layout = shared.layout.lock().unwrap();
+            let state = layout.slots.slot(slot).copied().unwrap_or_default();
+            layout
+                .locos
+                .iter()
+                .filter(|(_, &loco)| loco == slot)
+                .map(|(&address, _)| gl_info(address, state))
+                .collect()
+        }
+        Update::Turnout(address, direction) => (0..=1)
+            .map(|port| {
+                let active = (port_of(direction) == port) as u8;
+                Reply::info(format!("GA {} {} {}", address, port, active))
+            })
+            .collect(),
+        Update::Sensor(address, level) => vec![Reply::info(format!(
+            "FB {} {}",
+            address,
+            (level == SensorLevel::High) as u8
+        ))],
+        Update::Power(power) => vec![power_info(power)],
+    }
+}
+
+/// Serves the client connected by `stream`, until it disconnects or the server stops.
+async fn serve(stream: TcpStream, address: SocketAddr, shared: Arc<Shared>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut updates = shared.updates.subscribe();
+    let mut mode = Mode::Handshake(false);
+
+    let greeting = format!("locodrive {}; SRCP 0.8.4\n", env!("CARGO_PKG_VERSION"));
+    if let Err(err) = writer.write_all(greeting.as_bytes()).await {
+        log_error!("Could not greet SRCP client {}: {}", address, err);
+        return;
+    }
+
+    loop {
+        let (replies, end) = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => match mode {
+                    Mode::Handshake(mut info) => {
+                        let (mut reply, go) = handshake(&line, &mut info);
+                        mode = Mode::Handshake(info);
+                        if go {
+                            let session = shared.sessions.fetch_add(1, Ordering::Relaxed) + 1;
+                            reply.1 = format!("OK GO {}", session);
+                            mode = if info { Mode::Info } else { Mode::Command };
+                        }
+                        (vec![reply], false)
+                    }
+                    Mode::Command => match parse_request(&line) {
+                        Ok(request) => {
+                            let end = request == Request::TermSession;
+                            (vec![execute(&shared, request).await], end)
+                        }
+                        Err(reply) => (vec![reply], false),
+                    },
+                    Mode::Info => (vec![Reply::error(410, "unknown command")], false),
+                },
+                Ok(None) => break,
+                Err(err) => {
+                    log_error!("Could not read from SRCP client {}: {}", address, err);
+                    break;
+                }
+            },
+            update = updates.recv() => match update {
+                Ok(update) if mode == Mode::Info => (reflect(&shared, update), false),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(lost)) => {
+                    log_error!("SRCP client {} lost {} updates", address, lost);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        let answer: String = replies.iter().map(Reply::line).collect();
+        if let Err(err) = writer.write_all(answer.as_bytes()).await {
+            log_error!("Could not write to SRCP client {}: {}", address, err);
+            break;
+        }
+        if end {
+            break;
+        }
+    }
+
+    log_info!("SRCP client {} disconnected", address);
+}
@@ -0,0 +1,320 @@
+use crate::traffic::{TrafficAnalyzer, TrafficKey, TrafficReport};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// A snapshot of the statistics collected by a [`crate::loco_controller::LocoDriveController`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Stats {
+    /// How long the controller is running.
+    pub uptime: Duration,
+    /// How many frames were read from the bus, including the echoes of send messages.
+    pub frames_received: u64,
+    /// How many bytes were read from the bus in complete frames.
+    pub bytes_received: u64,
+    /// How many read frames could not be parsed, not counting checksum errors.
+    pub parse_errors: u64,
+    /// How many read frames had an invalid checksum or were truncated by lost bytes.
+    pub checksum_errors: u64,
+    /// How many read frames had an invalid checksum within the last checksum window,
+    /// see [`crate::loco_controller::LocoDriveControllerBuilder::checksum_window()`].
+    pub recent_checksum_errors: u64,
+    /// How many read frames were dropped as duplicates,
+    /// see [`crate::loco_controller::LocoDriveControllerBuilder::dedup_window()`].
+    pub duplicates_dropped: u64,
+    /// How often a message was send again after a failed attempt.
+    pub retransmits: u64,
+    /// How many failed [`crate::protocol::Message::LongAck`]s were read from the bus.
+    pub lack_failures: u64,
+    /// When the last frame was read from the bus, or `None` if no frame was read yet.
+    pub last_activity: Option<Instant>,
+    /// The capacity of the broadcast channel.
+    /// `None` if the channel was not created by the controller.
+    pub channel_capacity: Option<usize>,
+    /// How often a subscriber lagged behind the broadcast channel.
+    pub lag_events: u64,
+    /// How many messages were lost by lagging subscribers in total.
+    pub lagged_messages: u64,
+    /// How often the reader stopped waiting for lagging subscribers,
+    /// see [`crate::loco_controller::OverflowPolicy::Block`].
+    pub overflow_timeouts: u64,
+    /// How many immediate packets were accepted by the model railroad
+    /// when send by a [`crate::imm_packet::ImmPacketSender`].
+    pub imm_packets_sent: u64,
+    /// How often an immediate packet was rejected, as the model railroads packet queue was full.
+    pub imm_packets_rejected: u64,
+    /// How long immediate packet batches were sending in total.
+    pub imm_packet_time: Duration,
+    /// How many devices send more frames within the traffic window than the chatty threshold,
+    /// see [`crate::loco_controller::LocoDriveControllerBuilder::chatty_threshold()`].
+    pub chatty_devices: u64,
+}
+
+impl Stats {
+    /// # Returns
+    ///
+    /// The average count of bytes read from the bus per second since the controller started.
+    pub fn bytes_per_second(&self) -> f64 {
+        if self.uptime.is_zero() {
+            0.0
+        } else {
+            self.bytes_received as f64 / self.uptime.as_secs_f64()
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The effective throughput of immediate packets in packets per second,
+    /// or `None` if no batch was send yet.
+    pub fn imm_packet_rate(&self) -> Option<f64> {
+        if self.imm_packet_time.is_zero() {
+            None
+        } else {
+            Some(self.imm_packets_sent as f64 / self.imm_packet_time.as_secs_f64())
+        }
+    }
+}
+
+/// Collects the statistics of one controller.
+///
+/// All counters are atomic, so they can be updated from the reader, the writer and all subscribers.
+#[derive(Debug)]
+pub(crate) struct StatsCollector {
+    /// When the controller started
+    started: Instant,
+    /// How many frames were read
+    frames_received: AtomicU64,
+    /// How many bytes were read
+    bytes_received: AtomicU64,
+    /// How many frames could not be parsed
+    parse_errors: AtomicU64,
+    /// How many frames had an invalid checksum
+    checksum_errors: AtomicU64,
+    /// When the frames with an invalid checksum within the checksum window were read
+    recent_checksum_errors: Mutex<VecDeque<Instant>>,
+    /// Within which time the checksum errors are counted as recent
+    checksum_window: Duration,
+    /// Above which count of recent checksum errors the line is reported as noisy
+    line_noise_threshold: Option<u64>,
+    /// How many frames were dropped as duplicates
+    duplicates_dropped: AtomicU64,
+    /// How often a message was send again
+    retransmits: AtomicU64,
+    /// How many failed long acknowledgments were read
+    lack_failures: AtomicU64,
+    /// When the last frame was read
+    last_activity: Mutex<Option<Instant>>,
+    /// The capacity of the broadcast channel, if known
+    channel_capacity: Option<usize>,
+    /// How often a subscriber lagged
+    lag_events: AtomicU64,
+    /// How many messages were lost by lagging
+    lagged_messages: AtomicU64,
+    /// How often the reader stopped waiting for lagging subscribers
+    overflow_timeouts: AtomicU64,
+    /// How many immediate packets were accepted
+    imm_packets_sent: AtomicU64,
+    /// How many immediate packets were rejected
+    imm_packets_rejected: AtomicU64,
+    /// How long immediate packet batches were sending in nanoseconds
+    imm_packet_nanos: AtomicU64,
+    /// Counts the read frames per op code and device
+    traffic: Mutex<TrafficAnalyzer>,
+    /// The devices that became chatty, until they are broadcasted
+    new_chatty: Mutex<Vec<(TrafficKey, u64)>>,
+}
+
+impl StatsCollector {
+    /// Creates a new collector with all counters set to zero.
+    ///
+    /// # Parameters
+    ///
+    /// - `channel_capacity`: The capacity of the broadcast channel, if known
+    pub(crate) fn new(channel_capacity: Option<usize>) -> Self {
+        StatsCollector {
+            started: Instant::now(),
+            frames_received: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            parse_errors: AtomicU64::new(0),
+            checksum_errors: AtomicU64::new(0),
+            recent_checksum_errors: Mutex::new(VecDeque::new()),
+            checksum_window: Duration::from_secs(60),
+            line_noise_threshold: None,
+            duplicates_dropped: AtomicU64::new(0),
+            retransmits: AtomicU64::new(0),
+            lack_failures: AtomicU64::new(0),
+            last_activity: Mutex::new(None),
+            channel_capacity,
+            lag_events: AtomicU64::new(0),
+            lagged_messages: AtomicU64::new(0),
+            overflow_timeouts: AtomicU64::new(0),
+            imm_packets_sent: AtomicU64::new(0),
+            imm_packets_rejected: AtomicU64::new(0),
+            imm_packet_nanos: AtomicU64::new(0),
+            traffic: Mutex::new(TrafficAnalyzer::new(Duration::from_secs(10))),
+            new_chatty: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Counts checksum errors within `checksum_window` as recent and reports the line as noisy
+    /// if more than `line_noise_threshold` of them are recent.
+    pub(crate) fn with_line_noise(
+        mut self,
+        checksum_window: Duration,
+        line_noise_threshold: Option<u64>,
+    ) -> Self {
+        self.checksum_window = checksum_window;
+        self.line_noise_threshold = line_noise_threshold;
+        self
+    }
+
+    /// Counts the traffic within `traffic_window` and flags devices as chatty
+    /// if they send more than `chatty_threshold` frames within it.
+    pub(crate) fn with_traffic(
+        mut self,
+        traffic_window: Duration,
+        chatty_threshold: Option<u64>,
+    ) -> Self {
+        let mut traffic = TrafficAnalyzer::new(traffic_window);
+        if let Some(chatty_threshold) = chatty_threshold {
+            traffic = traffic.chatty_threshold(chatty_threshold);
+        }
+        self.traffic = Mutex::new(traffic);
+        self
+    }
+
+    /// Records that the `frame` was read at `at`.
+    pub(crate) fn record_frame(&self, frame: &[u8], at: Instant) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(frame.len() as u64, Ordering::Relaxed);
+        *self.last_activity.lock().unwrap() = Some(at);
+
+        let chatty = self.traffic.lock().unwrap().record(frame, at);
+        if !chatty.is_empty() {
+            self.new_chatty.lock().unwrap().extend(chatty);
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The devices that became chatty since the last call with their count of frames in the window.
+    pub(crate) fn take_chatty(&self) -> Vec<(TrafficKey, u64)> {
+        std::mem::take(&mut *self.new_chatty.lock().unwrap())
+    }
+
+    /// # Returns
+    ///
+    /// The traffic within the traffic window ending now.
+    pub(crate) fn traffic(&self) -> TrafficReport {
+        self.traffic.lock().unwrap().report(Instant::now())
+    }
+
+    /// Records that a read frame could not be parsed, as its checksum was invalid or
+    /// it was otherwise malformed.
+    ///
+    /// # Returns
+    ///
+    /// The count of recent checksum errors, if it just exceeded the line noise threshold.
+    pub(crate) fn record_parse_error(&self, checksum: bool) -> Option<u64> {
+        if !checksum {
+            self.parse_errors.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.checksum_errors.fetch_add(1, Ordering::Relaxed);
+
+        let now = Instant::now();
+        let mut recent = self.recent_checksum_errors.lock().unwrap();
+        recent.push_back(now);
+        Self::forget_old(&mut recent, now, self.checksum_window);
+
+        // We report the line only once when the threshold is crossed
+        let count = recent.len() as u64;
+        match self.line_noise_threshold {
+            Some(threshold) if count == threshold + 1 => Some(count),
+            _ => None,
+        }
+    }
+
+    /// Removes the checksum errors read before the `checksum_window` ending `now` from `recent`.
+    fn forget_old(recent: &mut VecDeque<Instant>, now: Instant, checksum_window: Duration) {
+        while recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > checksum_window)
+        {
+            recent.pop_front();
+        }
+    }
+
+    /// Records that a read frame was dropped as duplicate.
+    pub(crate) fn record_duplicate(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a message was send again.
+    pub(crate) fn record_retransmit(&self) {
+        self.retransmits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a failed long acknowledgment was read.
+    pub(crate) fn record_lack_failure(&self) {
+        self.lack_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a subscriber lagged behind and lost `lost` messages.
+    pub(crate) fn record_lag(&self, lost: u64) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.lagged_messages.fetch_add(lost, Ordering::Relaxed);
+    }
+
+    /// Records that the reader stopped waiting for lagging subscribers.
+    pub(crate) fn record_overflow_timeout(&self) {
+        self.overflow_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that an immediate packet was accepted or `rejected`.
+    pub(crate) fn record_imm_packet(&self, rejected: bool) {
+        if rejected {
+            self.imm_packets_rejected.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.imm_packets_sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records that an immediate packet batch was sending for `duration`.
+    pub(crate) fn record_imm_packet_time(&self, duration: Duration) {
+        self.imm_packet_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// # Returns
+    ///
+    /// The current values of all counters.
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            uptime: self.started.elapsed(),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            checksum_errors: self.checksum_errors.load(Ordering::Relaxed),
+            recent_checksum_errors: {
+                let mut recent = self.recent_checksum_errors.lock().unwrap();
+                Self::forget_old(&mut recent, Instant::now(), self.checksum_window);
+                recent.len() as u64
+            },
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+            retransmits: self.retransmits.load(Ordering::Relaxed),
+            lack_failures: self.lack_failures.load(Ordering::Relaxed),
+            last_activity: *self.last_activity.lock().unwrap(),
+            channel_capacity: self.channel_capacity,
+            lag_events: self.lag_events.load(Ordering::Relaxed),
+            lagged_messages: self.lagged_messages.load(Ordering::Relaxed),
+            overflow_timeouts: self.overflow_timeouts.load(Ordering::Relaxed),
+            imm_packets_sent: self.imm_packets_sent.load(Ordering::Relaxed),
+            imm_packets_rejected: self.imm_packets_rejected.load(Ordering::Relaxed),
+            imm_packet_time: Duration::from_nanos(self.imm_packet_nanos.load(Ordering::Relaxed)),
+            chatty_devices: self.traffic.lock().unwrap().chatty_count(Instant::now()),
+        }
+    }
+}
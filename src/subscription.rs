@@ -0,0 +1,257 @@
+use crate::args::{
+    AddressArg, DirfArg, IdArg, InArg, SlotArg, SnArg, SndArg, SpeedArg, Stat1Arg, Stat2Arg, TrkArg,
+};
+use crate::loco_controller::{LocoDriveMessage, LocoDriveReceiver, PowerState};
+use crate::protocol::Message;
+use crate::stats::StatsCollector;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::time::Instant;
+
+/// Receives only the messages of one kind from a [`crate::loco_controller::LocoDriveController`].
+///
+/// Tagged echoes of the controllers own messages are passed like other messages.
+/// All other messages, errors and answers are skipped, so the receiver only
+/// returns the typed events it was created for.
+pub struct FilteredReceiver<T> {
+    /// The receiver to read all messages from
+    receiver: LocoDriveReceiver,
+    /// Converts the matching messages to the typed event
+    filter: fn(&Message) -> Option<T>,
+}
+
+impl<T> FilteredReceiver<T> {
+    /// Creates a new receiver passing only the messages converted by `filter`.
+    pub(crate) fn new(receiver: LocoDriveReceiver, filter: fn(&Message) -> Option<T>) -> Self {
+        FilteredReceiver { receiver, filter }
+    }
+
+    /// Receives the next matching event from the controller.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`LocoDriveReceiver::recv()`]. A lag is reported even if none of the
+    /// lost messages would have matched.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        loop {
+            if let LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message) =
+                self.receiver.recv().await?
+            {
+                if let Some(event) = (self.filter)(&message) {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+}
+
+/// An event emitted by a [`crate::loco_controller::LocoDriveController`]
+/// with its sequence number and the time it was received.
+#[derive(Debug, Clone)]
+pub struct Envelope<T> {
+    /// The number of the message the event was emitted for. The numbers are increasing
+    /// without gaps over all messages emitted by a controller.
+    pub sequence: u64,
+    /// When the message was emitted by the controller
+    pub received: Instant,
+    /// The wrapped event
+    pub event: T,
+}
+
+impl<T> Envelope<T> {
+    /// # Returns
+    ///
+    /// The envelope of the event converted by `convert`, with the same sequence number and time.
+    pub fn map<U>(self, convert: impl FnOnce(T) -> U) -> Envelope<U> {
+        Envelope {
+            sequence: self.sequence,
+            received: self.received,
+            event: convert(self.event),
+        }
+    }
+}
+
+/// Receives the events of a [`crate::loco_controller::LocoDriveController`] in [`Envelope`]s.
+///
+/// The sequence numbers of the envelopes tell the order of the events over all subscriptions.
+/// Other than the lag reported by the channel, a receiver counts the messages it missed from
+/// the gaps in the sequence numbers. Messages skipped by the filter of the receiver are not
+/// counted as missed.
+pub struct EnvelopeReceiver<T> {
+    /// The receiver to read all envelopes from
+    receiver: Receiver<Envelope<LocoDriveMessage>>,
+    /// The statistics to report lagging to
+    stats: Arc<StatsCollector>,
+    /// Converts the matching messages to the typed event
+    filter: fn(&LocoDriveMessage) -> Option<T>,
+    /// The sequence number expected next
+    next: Option<u64>,
+    /// The count of messages missed
+    missed: u64,
+}
+
+impl<T> EnvelopeReceiver<T> {
+    /// Creates a new receiver passing only the messages converted by `filter`.
+    pub(crate) fn new(
+        receiver: Receiver<Envelope<LocoDriveMessage>>,
+        stats: Arc<StatsCollector>,
+        filter: fn(&LocoDriveMessage) -> Option<T>,
+    ) -> Self {
+        EnvelopeReceiver {
+            receiver,
+            stats,
+            filter,
+            next: None,
+            missed: 0,
+        }
+    }
+
+    /// Converts this receiver to one passing only the messages converted by `filter`.
+    /// The count of missed messages is kept.
+    pub fn filter<U>(self, filter: fn(&LocoDriveMessage) -> Option<U>) -> EnvelopeReceiver<U> {
+        EnvelopeReceiver {
+            receiver: self.receiver,
+            stats: self.stats,
+            filter,
+            next: self.next,
+            missed: self.missed,
+        }
+    }
+
+    /// Receives the next matching event from the controller.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`LocoDriveReceiver::recv()`]. The lost messages are counted as missed.
+    pub async fn recv(&mut self) -> Result<Envelope<T>, RecvError> {
+        loop {
+            let envelope = match self.receiver.recv().await {
+                Ok(envelope) => envelope,
+                Err(RecvError::Lagged(lost)) => {
+                    self.stats.record_lag(lost);
+                    return Err(RecvError::Lagged(lost));
+                }
+                Err(err) => return Err(err),
+            };
+
+            if let Some(next) = self.next {
+                self.missed += envelope.sequence.saturating_sub(next);
+            }
+            self.next = Some(envelope.sequence + 1);
+
+            if let Some(event) = (self.filter)(&envelope.event) {
+                return Ok(envelope.map(|_| event));
+            }
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The count of messages this receiver missed, as it lagged behind.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+/// An update of a slot observed on the model railroad.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SlotUpdate {
+    /// All data of a slot was read by a [`Message::SlRdData`].
+    Data(
+        SlotArg,
+        Stat1Arg,
+        AddressArg,
+        SpeedArg,
+        DirfArg,
+        TrkArg,
+        Stat2Arg,
+        SndArg,
+        IdArg,
+    ),
+    /// The speed of a slot was set by a [`Message::LocoSpd`].
+    Speed(SlotArg, SpeedArg),
+    /// The direction and functions of a slot were set by a [`Message::LocoDirf`].
+    Dirf(SlotArg, DirfArg),
+    /// The sound functions of a slot were set by a [`Message::LocoSnd`].
+    Snd(SlotArg, SndArg),
+}
+
+impl SlotUpdate {
+    /// # Returns
+    ///
+    /// The slot this update is for.
+    pub fn slot(&self) -> SlotArg {
+        match *self {
+            SlotUpdate::Data(slot, ..)
+            | SlotUpdate::Speed(slot, _)
+            | SlotUpdate::Dirf(slot, _)
+            | SlotUpdate::Snd(slot, _) => slot,
+        }
+    }
+
+    /// Converts a message to the slot update it represents.
+    pub(crate) fn from_message(message: &Message) -> Option<Self> {
+        match *message {
+            Message::SlRdData(slot, stat1, adr, spd, dirf, trk, stat2, snd, id) => Some(
+                SlotUpdate::Data(slot, stat1, adr, spd, dirf, trk, stat2, snd, id),
+            ),
+            Message::LocoSpd(slot, spd) => Some(SlotUpdate::Speed(slot, spd)),
+            Message::LocoDirf(slot, dirf) => Some(SlotUpdate::Dirf(slot, dirf)),
+            Message::LocoSnd(slot, snd) => Some(SlotUpdate::Snd(slot, snd)),
+            _ => None,
+        }
+    }
+}
+
+/// A change of the track power reported on the model railroad.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PowerEvent {
+    /// The track power was switched on by a [`Message::GpOn`].
+    On,
+    /// The track power was switched off by a [`Message::GpOff`].
+    Off,
+    /// All locomotives were stopped by a [`Message::Idle`], while the track power stays on.
+    Idle,
+}
+
+impl PowerEvent {
+    /// # Returns
+    ///
+    /// The power event represented by `message`, if it is a power message.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        match *message {
+            Message::GpOn => Some(PowerEvent::On),
+            Message::GpOff => Some(PowerEvent::Off),
+            Message::Idle => Some(PowerEvent::Idle),
+            _ => None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The power state of the layout after this event.
+    pub fn state(&self) -> PowerState {
+        match *self {
+            PowerEvent::On => PowerState::On,
+            PowerEvent::Off => PowerState::Off,
+            PowerEvent::Idle => PowerState::Idle,
+        }
+    }
+}
+
+/// Converts a message to the sensor event it represents.
+pub(crate) fn sensor_event(message: &Message) -> Option<InArg> {
+    match *message {
+        Message::InputRep(in_arg) => Some(in_arg),
+        _ => None,
+    }
+}
+
+/// Converts a message to the switch report it represents.
+pub(crate) fn switch_report(message: &Message) -> Option<SnArg> {
+    match *message {
+        Message::SwRep(sn_arg) => Some(sn_arg),
+        _ => None,
+    }
+}
@@ -1,24 +1,30 @@
 /// Tests all testable core functions of this module
 #[cfg(test)]
 #[cfg(feature = "control")]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::args::{
         Ack1Arg, AddressArg, Consist, CvDataArg, DecoderType, DirfArg, DstArg, FastClock,
-        FunctionArg, FunctionGroup, IdArg, ImAddress, ImArg, ImFunctionType, InArg, LissyIrReport,
-        LopcArg, MultiSenseArg, PStat, Pcmd, ProgrammingAbortedArg, PxctData, RFID5Report,
-        RFID7Report, RepStructure, SensorLevel, SlotArg, SnArg, SndArg, SourceType, SpeedArg,
-        Stat1Arg, Stat2Arg, State, SwitchArg, SwitchDirection, TrkArg, WheelcntReport,
+        FunctionArg, FunctionGroup, IdArg, ImAddress, ImArg, ImFunctionType, ImmPacketAck, InArg,
+        LissyIrReport, LopcArg, MultiSenseArg, PStat, Pcmd, ProgrammingAbortedArg, PxctData,
+        RFID5Report, RFID7Report, RepStructure, SensorLevel, SlotArg, SnArg, SndArg, SourceType,
+        SpeedArg, Stat1Arg, Stat2Arg, State, SwitchArg, SwitchDirection, TrkArg, WheelcntReport,
         WrSlDataStructure,
     };
-    use crate::loco_controller::{LocoDriveController, LocoDriveMessage};
+    use crate::loco_controller::{
+        LocoDriveController, LocoDriveControllerBuilder, LocoDriveMessage,
+    };
     use crate::protocol::Message;
     use crate::protocol::Message::{GpOn, LocoSpd};
+    use crate::slot_cache::SlotResolver;
+    use crate::transaction::TransactionTracker;
+    use crate::transport::LocoNetTransport;
     use std::collections::HashMap;
     use std::io::{stdout, Write};
     use std::process::exit;
     use std::time::Duration;
     use tokio::io::AsyncReadExt;
-    use tokio::time::sleep;
+    use tokio::time::{sleep, Instant};
     use tokio_serial::{DataBits, FlowControl, Parity, SerialPortBuilderExt, StopBits};
 
     /// Tests if the message parsing is reliable
@@ -131,12 +137,13 @@ mod tests {
             Stat1Arg::new(
                 false,
                 Consist::LogicalSubMember,
-                State::Idle,
-                DecoderType::AdrMobile28,
+                State::InUse,
+                DecoderType::Regular28,
             ),
             AddressArg::new(0),
             SpeedArg::Stop,
-            DirfArg::new(false, false, false, false, false, false),
+            // The direction is read from the low ops address byte, so it is forward
+            DirfArg::new(true, false, false, false, false, false),
             TrkArg::new(false, false, false, false),
             Stat2Arg::new(false, false, false),
             SndArg::new(false, false, false, false),
@@ -203,183 +210,4257 @@ mod tests {
         );
     }
 
-    /// Reads bytewise from port. This is for testing purposes only.
-    #[allow(dead_code)]
-    async fn test_reading() {
-        let mut port = match tokio_serial::new("/dev/ttyUSB0", 115_200)
-            .data_bits(DataBits::Eight)
-            .stop_bits(StopBits::Two)
-            .parity(Parity::None)
-            .flow_control(FlowControl::None)
-            .open_native_async()
-        {
-            Ok(port) => port,
-            Err(_) => {
-                return;
-            }
-        };
+    /// Tests that reserved bits survive parsing, so re-encoding received frames is bit exact.
+    #[test]
+    fn reserved_bits() {
+        // LocoDirf with the reserved dirf bit set
+        test_one_frame(&[0xA1, 0x0A, 0x7F]);
+        // SwReq with the reserved sw2 bit set
+        test_one_frame(&[0xB0, 0x0A, 0x75]);
+        // SlotStat1 with the reserved decoder types
+        test_one_frame(&[0xB5, 0x0A, 0x35]);
+        test_one_frame(&[0xB5, 0x0A, 0x06]);
+        // SlRdData with reserved dirf, trk, stat2 and snd bits set
+        test_one_frame(&[
+            0xE7, 0x0E, 0x0C, 0x33, 0x03, 0x00, 0x60, 0x77, 0x72, 0x00, 0x7F, 0x0C, 0x00,
+        ]);
+        // WrSlData programming task with reserved pcmd and cvh bits and a high cv
+        test_one_frame(&[
+            0xEF, 0x0E, 0x7C, 0x7F, 0x00, 0x00, 0x00, 0x07, 0x7F, 0x12, 0x01, 0x00, 0x00,
+        ]);
+    }
 
-        loop {
-            let mut buf = vec![0u8; 1];
+    /// Tests if the message parsed from `frame` re-encodes to exactly `frame`.
+    ///
+    /// The checksum is appended by this function.
+    fn test_one_frame(frame: &[u8]) {
+        let mut frame = frame.to_vec();
+        frame.push(0xFF ^ frame.iter().fold(0, |acc, &b| acc ^ b));
+        assert_eq!(Message::parse(&frame).unwrap().to_message(), frame);
+    }
 
-            let opc = port.read_exact(&mut buf).await;
-            let byte = match opc {
-                Ok(_) => buf[0],
-                Err(_) => {
-                    return;
-                }
-            };
+    /// Tests the wire level compatibility corpus parses and re-encodes bit exact.
+    #[test]
+    fn wire_vectors() {
+        let vectors: Vec<_> = crate::wire::test_vectors().collect();
+        assert_eq!(
+            vectors.len(),
+            crate::wire::fixture().matches("\"name\"").count()
+        );
 
-            print!("{:02x?} ", byte);
-            stdout().flush().unwrap();
+        for vector in vectors {
+            match vector.message() {
+                Ok(message) if vector.valid => {
+                    assert_eq!(message.to_message(), vector.frame, "{}", vector.name)
+                }
+                Err(_) if !vector.valid => {}
+                parsed => panic!("{}: {:?}", vector.name, parsed),
+            }
         }
     }
 
+    /// Tests slot data with vendor extension bytes is rejected or parsed as configured.
+    #[test]
+    fn extra_slot_bytes() {
+        use crate::protocol::ExtraBytes;
+
+        let mut frame = vec![
+            0xE7, 0x10, 0x0C, 0x33, 0x03, 0x00, 0x20, 0x07, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x12,
+            0x34,
+        ];
+        frame.push(0xFF ^ frame.iter().fold(0, |acc, &b| acc ^ b));
+
+        assert!(Message::parse_with(&frame, ExtraBytes::Reject).is_err());
+
+        let (message, vendor_bytes) = Message::parse_with(&frame, ExtraBytes::Attach).unwrap();
+        assert!(matches!(message, Message::SlRdData(slot, ..) if slot.slot() == 12));
+        assert_eq!(vendor_bytes, vec![0x12, 0x34]);
+
+        // Standard frames have no vendor bytes
+        let standard = LocoSpd(SlotArg::new(1), SpeedArg::Stop).to_message();
+        assert!(Message::parse_with(&standard, ExtraBytes::Attach)
+            .unwrap()
+            .1
+            .is_empty());
+    }
+
+    /// Tests strict parsing rejects reserved bits, while lenient parsing keeps them.
+    #[test]
+    fn strict_parsing() {
+        use crate::protocol::ParseOptions;
+
+        let strict = ParseOptions { strict: true };
+        // A switch request with the reserved sw2 bit set
+        let mut frame = vec![0xB0, 0x05, 0x70];
+        frame.push(0xFF ^ frame.iter().fold(0, |acc, &b| acc ^ b));
+
+        let lenient = Message::parse_with_options(&frame, ParseOptions::default()).unwrap();
+        assert_eq!(lenient.to_message(), frame);
+        let err = Message::parse_with_options(&frame, strict).unwrap_err();
+        assert_eq!(err.offset(), Some(2));
+
+        let valid = LocoSpd(SlotArg::new(1), SpeedArg::Stop).to_message();
+        assert!(Message::parse_with_options(&valid, strict).is_ok());
+
+        // A programming aborted frame shorter than the message encodes to
+        let mut short = vec![0xE6, 0x07, 0x01, 0x02, 0x03, 0x04];
+        short.push(0xFF ^ short.iter().fold(0, |acc, &b| acc ^ b));
+        let aborted = Message::parse(&short).unwrap();
+        assert!(aborted.encoded_len() > short.len());
+        assert!(Message::parse_with_options(&short, strict).is_ok());
+    }
+
+    /// Tests the strict reader accepts frames shorter than their message encodes to.
     #[tokio::test]
-    async fn test_message_sending() {
-        println!("Start test!");
+    async fn strict_reader_short_frame() {
+        use crate::protocol::ParseOptions;
+        use tokio::io::AsyncWriteExt;
 
-        for port in tokio_serial::available_ports().unwrap() {
-            println!("Port: {:?}", port)
-        }
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.parse_options(ParseOptions { strict: true })).await;
+        let mut messages = controller.subscribe();
 
-        let (sender, mut receiver) = tokio::sync::broadcast::channel(10);
+        let mut short = vec![0xE6, 0x07, 0x01, 0x02, 0x03, 0x04];
+        short.push(0xFF ^ short.iter().fold(0, |acc, &b| acc ^ b));
+        bus.write_all(&short).await.unwrap();
 
-        println!("Try to connect to port!");
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::Message(Message::ProgrammingAborted(..))
+        ));
+        assert!(controller.reader_status().is_running());
+    }
 
-        let mut loco_controller = match LocoDriveController::new(
-            "/dev/ttyUSB0",
-            115_200,
-            50000,
-            FlowControl::None,
-            sender,
-            false,
-        )
-        .await
-        {
-            Ok(loco_controller) => loco_controller,
-            Err(err) => {
-                eprintln!("Error: Could not connect to the serial port! {:?}", err);
-                println!();
-                return;
-            }
-        };
+    /// Connects a controller configured by `configure` to an in memory bus.
+    ///
+    /// # Returns
+    ///
+    /// The controller and the other end of the bus, to play the model railroad with.
+    async fn memory_controller(
+        configure: impl FnOnce(LocoDriveControllerBuilder) -> LocoDriveControllerBuilder,
+    ) -> (LocoDriveController, LocoNetTransport) {
+        let (controller_end, bus) = LocoNetTransport::pair();
+        let builder = LocoDriveController::builder("unused", 0).transport(controller_end);
+        (configure(builder).build().await.unwrap(), bus)
+    }
 
-        let _m = Message::parse(
-            Message::SwReq(SwitchArg::new(15, SwitchDirection::Curved, true))
-                .to_message()
-                .as_slice(),
-        )
+    /// Tests the interpretation of immediate packet acknowledgments.
+    #[test]
+    fn imm_packet_ack() {
+        assert_eq!(
+            ImmPacketAck::new(Ack1Arg::new(true)).remaining_capacity(),
+            None
+        );
+        assert_eq!(
+            ImmPacketAck::new(Ack1Arg::new(false)).remaining_capacity(),
+            Some(0)
+        );
+        assert!(!ImmPacketAck::new(Ack1Arg::new(false)).accepted());
+        let limited = ImmPacketAck::from(Ack1Arg::new_advanced(3));
+        assert!(limited.accepted());
+        assert_eq!(limited.remaining_capacity(), Some(3));
+    }
+
+    /// Tests that multi frame operations are grouped to transactions.
+    #[test]
+    fn transactions() {
+        let mut tracker = TransactionTracker::new();
+        let start = Instant::now();
+        let slot_data = Message::parse(&[
+            0xE7, 0x0E, 0x0C, 0x33, 0x03, 0x00, 0x60, 0x07, 0x02, 0x00, 0x7F, 0x0C, 0x00, 0x3C,
+        ])
         .unwrap();
 
-        println!(
-            "Message: {:?}",
-            Message::SwReq(SwitchArg::new(15, SwitchDirection::Curved, true)).to_message()
+        // A slot request answered after one busy frame
+        let request = Message::LocoAdr(AddressArg::new(3));
+        assert!(tracker.handle(request, start).is_none());
+        assert!(tracker.handle(Message::Busy, start).is_none());
+        let transaction = tracker
+            .handle(slot_data, start + Duration::from_millis(5))
+            .unwrap();
+        assert_eq!(transaction.request(), request);
+        assert_eq!(transaction.answer(), slot_data);
+        assert_eq!(transaction.busy_count(), 1);
+        assert_eq!(transaction.duration(), Duration::from_millis(5));
+
+        // A slot request rejected by the command station
+        let request = Message::LocoAdr(AddressArg::new(4));
+        let answer = Message::LongAck(LopcArg::new(request.opc()), Ack1Arg::new(false));
+        assert!(tracker.handle(request, start).is_none());
+        assert_eq!(tracker.handle(answer, start).unwrap().frames(), &[answer]);
+
+        // An answer without its request is no transaction
+        assert!(tracker.handle(slot_data, start).is_none());
+    }
+
+    /// Tests matching answers to their requests with other traffic in between.
+    #[test]
+    fn answer_correlation() {
+        use crate::correlation::AnswerCorrelator;
+
+        let mut timeouts = HashMap::new();
+        timeouts.insert(0xED, Duration::from_millis(50));
+        let mut answers = AnswerCorrelator::new(Duration::from_secs(1), timeouts);
+        let start = Instant::now();
+
+        let switch = Message::SwState(SwitchArg::new(12, SwitchDirection::Straight, false));
+        let packet = Message::ImmPacket(ImArg::new(
+            32,
+            ImAddress::Short(3),
+            ImFunctionType::F9to12,
+            0,
+        ));
+
+        assert!(answers.handle(switch, start).is_none());
+        assert!(answers.handle(packet, start).is_none());
+        assert!(answers.handle(GpOn, start).is_none());
+
+        // The immediate packet times out first and is reported once
+        assert_eq!(
+            answers.next_deadline(),
+            Some(start + Duration::from_millis(50))
         );
+        assert_eq!(
+            answers.expire(start + Duration::from_millis(60)),
+            vec![packet]
+        );
+        assert!(answers.expire(start + Duration::from_millis(60)).is_empty());
 
-        loco_controller.send_message(GpOn).await.unwrap();
+        // The switch state is answered after other traffic, also with a limited acknowledgment
+        let state = Message::LongAck(LopcArg::new(switch.opc()), Ack1Arg::new(true));
+        assert_eq!(
+            answers.handle(state, start + Duration::from_millis(60)),
+            Some(switch)
+        );
 
-        println!("Setup test train");
+        // The immediate packet timed out
+        let accepted = Message::LongAck(LopcArg::new(packet.opc()), Ack1Arg::new(true));
+        assert!(answers
+            .handle(accepted, start + Duration::from_millis(60))
+            .is_none());
+    }
 
-        let adr = AddressArg::new(5);
+    /// Tests the priority classes order urgent messages before bulk traffic.
+    #[test]
+    fn priority_classes() {
+        let speed = LocoSpd(SlotArg::new(1), SpeedArg::Stop);
+        let packet = Message::ImmPacket(ImArg::new(
+            32,
+            ImAddress::Short(3),
+            ImFunctionType::F9to12,
+            0,
+        ));
 
-        let mut slot_adr_map: HashMap<AddressArg, SlotArg> = HashMap::new();
+        assert_eq!(Message::GpOff.priority_class(), 0);
+        assert!(Message::GpOff.priority_class() < speed.priority_class());
+        assert!(speed.priority_class() < Message::RqSlData(SlotArg::new(1)).priority_class());
+        assert!(Message::RqSlData(SlotArg::new(1)).priority_class() < packet.priority_class());
+    }
 
-        match loco_controller.send_message(Message::LocoAdr(adr)).await {
-            Ok(()) => {}
-            Err(err) => {
-                eprintln!("Message was not send! {:?}", err);
-                println!();
-                exit(1)
-            }
+    /// Tests reconstructing the switch and sensor states from a capture.
+    #[test]
+    fn manager_replay() {
+        use crate::manager::{Manager, SensorManager, SwitchManager};
+
+        let capture = vec![
+            Message::SwReq(SwitchArg::new(5, SwitchDirection::Curved, true)),
+            Message::InputRep(InArg::new(8, SourceType::Switch, SensorLevel::High, false)),
+            Message::SwRep(SnArg::SwitchDirectionStatus(
+                5,
+                SensorLevel::High,
+                SensorLevel::Low,
+            )),
+            Message::InputRep(InArg::new(8, SourceType::Switch, SensorLevel::Low, false)),
+        ];
+
+        let mut switches = SwitchManager::new();
+        switches.replay(&capture);
+        assert_eq!(switches.direction(5), Some(SwitchDirection::Straight));
+        assert_eq!(switches.direction(6), None);
+
+        let mut sensors = SensorManager::new();
+        sensors.replay(&capture);
+        assert_eq!(sensors.level(8), Some(SensorLevel::Low));
+    }
+
+    /// Tests tracking the throttles attached to the receivers.
+    #[test]
+    fn throttle_network() {
+        use crate::args::{ReceiverType, ThrottleStatusArg};
+        use crate::manager::{Manager, ThrottleNetwork};
+
+        let status = ThrottleStatusArg::new(ReceiverType::Ur92, 149, true, true);
+        assert_eq!(
+            Message::parse(&[0xDF, 0x40, 0x15, 0x01, 0x03, 0x77]).unwrap(),
+            Message::ThrottleStatus(status)
+        );
+        assert_eq!(
+            Message::parse(&[0xDF, 0x00, 0x00, 0x00, 0x00, 0x20]).unwrap(),
+            Message::ReceiverQuery
+        );
+        // A query with arguments would lose them when encoded back
+        assert!(matches!(
+            Message::parse(&[0xDF, 0x00, 0x00, 0x05, 0x00, 0x25]),
+            Err(crate::error::MessageParseError::InvalidFormat { offset: 3, .. })
+        ));
+
+        let mut network = ThrottleNetwork::new();
+        network.replay(&[
+            Message::ThrottleStatus(ThrottleStatusArg::new(ReceiverType::Ur90, 7, false, false)),
+            ThrottleNetwork::query(),
+            Message::ThrottleStatus(status),
+        ]);
+        assert_eq!(network.throttles().len(), 1);
+        assert_eq!(network.attached_to(ReceiverType::Ur92), vec![149]);
+        assert_eq!(network.low_battery(), vec![149]);
+        assert!(network.throttle(149).unwrap().semaphore());
+    }
+
+    /// Tests interpreting the answers to a dispatch.
+    #[test]
+    fn dispatch_answers() {
+        use crate::loco_controller::move_answer;
+
+        let get = Message::MoveSlots(SlotArg::new(0), SlotArg::new(0));
+        let failed = Ack1Arg::new(false);
+        assert_eq!(
+            move_answer(&get, &Message::LongAck(LopcArg::new(get.opc()), failed)),
+            Some(Err(failed))
+        );
+        assert_eq!(
+            move_answer(&get, &Message::LongAck(LopcArg::new(0x3F), failed)),
+            None
+        );
+        assert_eq!(move_answer(&get, &GpOn), None);
+
+        let data = |slot| {
+            Message::SlRdData(
+                SlotArg::new(slot),
+                Stat1Arg::new(false, Consist::Free, State::InUse, DecoderType::Dcc128),
+                AddressArg::new(3),
+                SpeedArg::Stop,
+                DirfArg::new(false, false, false, false, false, false),
+                TrkArg::new(true, false, true, false),
+                Stat2Arg::new(false, false, false),
+                SndArg::new(false, false, false, false),
+                IdArg::new(0),
+            )
         };
+        let answered = |request: &Message, slot| move_answer(request, &data(slot)).is_some();
+        // Any slot may be dispatched
+        assert!(answered(&get, 5));
+        // A dispatch put is answered with the data of the put slot
+        let put = Message::MoveSlots(SlotArg::new(5), SlotArg::new(0));
+        assert!(answered(&put, 5));
+        assert!(!answered(&put, 6));
+        // A move is answered with the data of the destination
+        let moved = Message::MoveSlots(SlotArg::new(5), SlotArg::new(6));
+        assert!(answered(&moved, 6));
+        assert!(!answered(&moved, 5));
+        let null_move = Message::MoveSlots(SlotArg::new(5), SlotArg::new(5));
+        assert!(answered(&null_move, 5));
+        assert!(!answered(&null_move, 7));
+    }
 
-        loop {
-            match receiver.recv().await {
-                Ok(message) => match message {
-                    LocoDriveMessage::Message(message) => match message {
-                        Message::SlRdData(slot, _, address, ..) => {
-                            slot_adr_map.insert(address, slot);
-                            println!("Added {:?}, {:?} to {:?}", address, slot, slot_adr_map);
-                            break;
-                        }
-                        _ => {}
-                    },
-                    LocoDriveMessage::Answer(_, _) => {}
-                    LocoDriveMessage::Error(err) => {
-                        eprintln!("Message could not be read! {:?}", err);
-                        exit(1)
-                    }
-                    LocoDriveMessage::SerialPortError(err) => {
-                        eprintln!("Connection refused! {:?}", err);
-                        exit(1)
-                    }
-                },
-                Err(err) => {
-                    println!("WHAT? {:?}", err);
-                }
-            }
+    /// Tests recognizing purged slots among the used slots.
+    #[test]
+    fn slot_purge() {
+        use crate::slot_usage::SlotUsage;
+
+        let slots = SlotUsage::new();
+        let stat1 = |state| Stat1Arg::new(false, Consist::Free, state, DecoderType::Dcc128);
+
+        slots.note_written(&LocoSpd(SlotArg::new(3), SpeedArg::Drive(20)));
+        slots.note_written(&LocoSpd(SlotArg::new(4), SpeedArg::Drive(20)));
+        slots.note_written(&Message::SlotStat1(SlotArg::new(4), stat1(State::Free)));
+        assert_eq!(slots.used(), vec![SlotArg::new(3)]);
+
+        assert_eq!(
+            slots.note_read(&Message::SlotStat1(SlotArg::new(3), stat1(State::InUse))),
+            None
+        );
+        assert_eq!(
+            slots.note_read(&Message::SlotStat1(SlotArg::new(3), stat1(State::Common))),
+            Some(SlotArg::new(3))
+        );
+        assert_eq!(
+            slots.note_read(&Message::SlotStat1(SlotArg::new(4), stat1(State::Free))),
+            None
+        );
+        assert!(slots.used().is_empty());
+    }
+
+    /// Tests validating the topology of slots to link.
+    #[test]
+    fn consist_topology() {
+        use crate::consist::validate_link;
+        use crate::error::ConsistError;
+
+        let stat1 = |consist| Stat1Arg::new(false, consist, State::InUse, DecoderType::Dcc128);
+        let (top, member) = (SlotArg::new(1), SlotArg::new(2));
+
+        assert!(validate_link(top, stat1(Consist::Free), member, stat1(Consist::Free)).is_ok());
+        assert!(validate_link(
+            top,
+            stat1(Consist::LogicalTop),
+            member,
+            stat1(Consist::Free)
+        )
+        .is_ok());
+        assert!(matches!(
+            validate_link(top, stat1(Consist::LogicalSubMember), member, stat1(Consist::Free)),
+            Err(ConsistError::NotTop(slot)) if slot == top
+        ));
+        assert!(matches!(
+            validate_link(top, stat1(Consist::Free), member, stat1(Consist::LogicalMid)),
+            Err(ConsistError::Linked(slot)) if slot == member
+        ));
+    }
+
+    /// Tests encoding cvs and interpreting the results of programming tasks.
+    #[test]
+    fn programming_results() {
+        use crate::error::ProgrammingError;
+        use crate::programmer::{cv_data, task_result, ProgrammingMode};
+
+        let value = cv_data(29, 0x86);
+        assert!(value.cv(2) && value.cv(3) && value.cv(4) && !value.cv(0));
+        assert_eq!(
+            CvDataArg::parse(value.cvh(), value.cvl(), value.data7()),
+            value
+        );
+
+        let ok = PStat::new(false, false, false, false);
+        assert_eq!(task_result(ok, value).unwrap(), 0x86);
+        assert!(matches!(
+            task_result(PStat::new(false, true, false, false), value),
+            Err(ProgrammingError::NoAck)
+        ));
+        assert!(matches!(
+            task_result(PStat::new(false, false, false, true), value),
+            Err(ProgrammingError::NoDecoder)
+        ));
+
+        // Every mode encodes to a valid frame, whose data bytes have no highest bit set
+        let modes = [
+            ProgrammingMode::Paged,
+            ProgrammingMode::Direct,
+            ProgrammingMode::Register,
+            ProgrammingMode::Ops(AddressArg::new(1234)),
+        ];
+        for (mode, write) in modes.iter().flat_map(|mode| [(mode, false), (mode, true)]) {
+            let pcmd = mode.pcmd(write);
+            let task = WrSlDataStructure::DataPt(
+                pcmd,
+                AddressArg::new(1234),
+                TrkArg::new(true, false, true, false),
+                cv_data(1024, 0xFF),
+            );
+            let frame = Message::WrSlData(task).to_message();
+            assert!(
+                frame[1..].iter().all(|byte| *byte < 0x80),
+                "{:?} encodes to {:02X?}",
+                mode,
+                frame
+            );
+            assert!(matches!(
+                Message::parse(&frame).unwrap(),
+                Message::WrSlData(WrSlDataStructure::DataPt(parsed, ..)) if parsed == pcmd
+            ));
         }
+    }
 
-        println!("Known Trains: {:?}", slot_adr_map);
+    /// Tests holding decoder profiles and estimating the progress of a backup.
+    #[test]
+    fn decoder_profile() {
+        use crate::programmer::{DecoderProfile, ProgrammingProgress};
 
-        for i in 1..3 {
-            println!("Drive round {}", i);
+        let mut profile = DecoderProfile::new();
+        profile.set(29, 0x06);
+        profile.set(1, 3);
+        profile.set(29, 0x26);
+        assert_eq!(profile.iter().collect::<Vec<_>>(), vec![(1, 3), (29, 0x26)]);
+        assert!(profile.contains(1) && !profile.contains(2));
 
-            if i % 2 == 0 {
-                loco_controller
-                    .send_message(Message::SwReq(SwitchArg::new(
-                        15,
-                        SwitchDirection::Straight,
-                        true,
-                    )))
-                    .await
-                    .unwrap();
-                loco_controller
-                    .send_message(Message::SwReq(SwitchArg::new(
-                        18,
-                        SwitchDirection::Straight,
-                        true,
-                    )))
-                    .await
-                    .unwrap();
-            } else {
-                loco_controller
-                    .send_message(Message::SwReq(SwitchArg::new(
-                        15,
-                        SwitchDirection::Curved,
-                        true,
-                    )))
-                    .await
-                    .unwrap();
-                loco_controller
-                    .send_message(Message::SwReq(SwitchArg::new(
-                        18,
-                        SwitchDirection::Curved,
-                        true,
-                    )))
-                    .await
-                    .unwrap();
-            }
+        let progress = ProgrammingProgress {
+            cv: 8,
+            done: 4,
+            total: 10,
+            elapsed: Duration::from_secs(8),
+        };
+        assert_eq!(progress.eta(), Duration::from_secs(12));
+    }
 
-            loco_controller
-                .send_message(LocoSpd(
-                    *slot_adr_map.get(&adr).unwrap(),
-                    SpeedArg::Drive(100),
-                ))
-                .await
-                .unwrap();
+    /// Tests reconciling commanded turnout directions with their feedback.
+    #[test]
+    fn turnout_table() {
+        use crate::manager::{TurnoutSource, TurnoutTable};
 
-            let mut waiting = true;
+        let mut turnouts = TurnoutTable::new();
+        let request = Message::SwReq(SwitchArg::new(12, SwitchDirection::Curved, true));
+        assert_eq!(
+            turnouts.update(&request).unwrap().source,
+            TurnoutSource::Commanded
+        );
+        assert_eq!(turnouts.update(&request), None);
+
+        let report = SnArg::SwitchDirectionStatus(12, SensorLevel::High, SensorLevel::Low);
+        assert_eq!(
+            turnouts.update(&Message::SwRep(report)).unwrap().direction,
+            SwitchDirection::Straight
+        );
+        assert_eq!(turnouts.mismatched(), vec![12]);
+
+        turnouts.update(&TurnoutTable::query(12));
+        let answer = Message::LongAck(LopcArg::new(0xBC), Ack1Arg::new_advanced(0x50));
+        let change = turnouts.update(&answer).unwrap();
+        assert_eq!(
+            (change.direction, change.source),
+            (SwitchDirection::Curved, TurnoutSource::Queried)
+        );
+        assert_eq!(turnouts.state(12), Some(SwitchDirection::Curved));
+        assert!(turnouts.mismatched().is_empty());
+    }
+
+    /// Tests building routes and reporting their partly failed setting.
+    #[test]
+    fn route_steps() {
+        use crate::error::{LocoDriveSendingError, RouteError};
+        use crate::routes::Route;
+
+        let route = Route::new("platform 1")
+            .step(12, SwitchDirection::Straight)
+            .step(13, SwitchDirection::Curved);
+        assert_eq!(
+            route.messages(),
+            vec![
+                Message::SwReq(SwitchArg::new(12, SwitchDirection::Straight, true)),
+                Message::SwReq(SwitchArg::new(13, SwitchDirection::Curved, true)),
+            ]
+        );
+
+        let err = RouteError {
+            route: route.name().to_string(),
+            steps: route.steps().len(),
+            failed: vec![(13, LocoDriveSendingError::Timeout)],
+        };
+        assert_eq!(
+            err.to_string(),
+            "route platform 1: 1 of 2 turnouts failed, turnout 13: connection timed out"
+        );
+    }
+
+    /// Tests debouncing the occupancy of blocks and reporting transponded locomotives.
+    #[test]
+    fn block_occupancy() {
+        use crate::occupancy::{BlockOccupancy, Detector, OccupancyEvent};
+
+        let mut blocks = BlockOccupancy::new().debounce(Duration::from_millis(100));
+        blocks.add_block("station", [Detector::Sensor(20), Detector::Zone(2, 3)]);
+        let start = Instant::now();
+        let sensor = |level| Message::InputRep(InArg::new(10, SourceType::Ds54Aux, level, false));
+
+        // A flickering sensor does not change the block
+        assert!(blocks.handle(&sensor(SensorLevel::High), start).is_empty());
+        assert!(blocks
+            .handle(&sensor(SensorLevel::Low), start + Duration::from_millis(50))
+            .is_empty());
+        assert!(blocks.poll(start + Duration::from_millis(200)).is_empty());
+
+        let loco = AddressArg::new(1234);
+        let present = Message::MultiSense(MultiSenseArg::new(1, true, 2, 3), loco);
+        blocks.handle(&present, start);
+        assert_eq!(
+            blocks.next_deadline(),
+            Some(start + Duration::from_millis(100))
+        );
+        assert_eq!(
+            blocks.poll(start + Duration::from_millis(100)),
+            vec![OccupancyEvent::Entered {
+                block: "station".to_string(),
+                loco: Some(loco)
+            }]
+        );
+        assert_eq!(blocks.locos("station"), &[loco]);
+    }
+
+    /// Tests encoding the clock data and scaling the layout time by the clock rate.
+    #[test]
+    fn layout_clock() {
+        use crate::fast_clock::{ClockMaster, ClockTime, LayoutClock};
+
+        for (hour, minute) in [(0, 0), (6, 30), (23, 59)] {
+            let clock = FastClock::at_time(4, 1, hour, minute);
+            assert_eq!((clock.hour(), clock.minute()), (hour, minute));
+        }
+
+        let master = ClockMaster::new(ClockTime::new(2, 23, 58), 10);
+        let message = master.message(
+            ClockTime::new(2, 23, 58),
+            TrkArg::new(true, false, true, false),
+        );
+        let start = Instant::now();
+        let mut clock = LayoutClock::new();
+        assert!(clock.update(&Message::parse(&message.to_message()).unwrap(), start));
+
+        assert_eq!(clock.rate(), Some(10));
+        let later = clock.time_at(start + Duration::from_secs(15)).unwrap();
+        assert_eq!(
+            later,
+            ClockTime {
+                days: 3,
+                hours: 0,
+                minutes: 0,
+                seconds: 30
+            }
+        );
+    }
+
+    /// Tests moving and invalidating the cached slots of locomotive addresses.
+    #[test]
+    fn slot_cache() {
+        use crate::slot_cache::{SlotCache, SlotChange};
+
+        let data = |slot, state, address, id| {
+            LocoDriveMessage::Message(Message::SlRdData(
+                SlotArg::new(slot),
+                Stat1Arg::new(false, Consist::Free, state, DecoderType::Dcc128),
+                AddressArg::new(address),
+                SpeedArg::Stop,
+                DirfArg::new(false, false, false, false, false, false),
+                TrkArg::new(true, false, true, false),
+                Stat2Arg::new(false, false, false),
+                SndArg::new(false, false, false, false),
+                IdArg::new(id),
+            ))
+        };
+        let (adr, slot) = (AddressArg::new(5), SlotArg::new(3));
+
+        let mut cache = SlotCache::new();
+        cache.insert(adr, slot, IdArg::new(7));
+        assert!(cache.update(&data(3, State::InUse, 5, 7)).is_empty());
+
+        let moved = SlotChange::Moved {
+            address: adr,
+            from: slot,
+            to: SlotArg::new(4),
+        };
+        assert_eq!(cache.update(&data(4, State::InUse, 5, 7)), vec![moved]);
+        assert_eq!(cache.slot(adr), Some(SlotArg::new(4)));
+
+        let stolen = SlotChange::Stolen {
+            address: adr,
+            slot: SlotArg::new(4),
+        };
+        assert_eq!(cache.update(&data(4, State::InUse, 5, 9)), vec![stolen]);
+        assert_eq!(cache.slot(adr), None);
+
+        cache.insert(adr, slot, IdArg::new(7));
+        let purged = SlotChange::Invalidated { address: adr, slot };
+        assert_eq!(
+            cache.update(&LocoDriveMessage::SlotPurged(slot)),
+            vec![purged]
+        );
+        assert!(cache.entries().is_empty());
+    }
+
+    /// Tests approaching target speeds with the momentum of a locomotive.
+    #[test]
+    fn speed_momentum() {
+        use crate::speed_ramp::Momentum;
+
+        let momentum = Momentum::new(20, 40);
+        assert_eq!(momentum.speed_after(0, 80, Duration::from_millis(500)), 10);
+        assert_eq!(momentum.speed_after(0, 80, Duration::from_secs(10)), 80);
+        assert_eq!(momentum.speed_after(80, 0, Duration::from_millis(500)), 60);
+        assert_eq!(momentum.speed_after(30, 0, Duration::from_secs(1)), 0);
+        assert_eq!(Momentum::immediate().speed_after(0, 80, Duration::ZERO), 80);
+    }
+
+    /// Tests advancing the layout clock time by scale minutes.
+    #[test]
+    fn clock_minutes() {
+        use crate::fast_clock::ClockTime;
+
+        assert_eq!(
+            ClockTime::new(0, 6, 50).add_minutes(15),
+            ClockTime::new(0, 7, 5)
+        );
+        assert_eq!(
+            ClockTime::new(2, 23, 30).add_minutes(45),
+            ClockTime::new(3, 0, 15)
+        );
+    }
+
+    /// Tests saving a layout snapshot and reconciling the turnouts after restoring it.
+    #[test]
+    #[cfg(feature = "config")]
+    fn layout_snapshot() {
+        use crate::fast_clock::ClockTime;
+        use crate::manager::{Manager, SensorManager, SlotManager, TurnoutTable};
+        use crate::snapshot::LayoutSnapshot;
+
+        let mut slots = SlotManager::new();
+        let mut turnouts = TurnoutTable::new();
+        slots.handle(&LocoSpd(SlotArg::new(3), SpeedArg::Drive(40)));
+        turnouts.handle(&Message::SwReq(SwitchArg::new(
+            12,
+            SwitchDirection::Curved,
+            true,
+        )));
+        turnouts.handle(&Message::SwReq(SwitchArg::new(
+            13,
+            SwitchDirection::Straight,
+            true,
+        )));
+        let clock = Some((ClockTime::new(1, 6, 30), 4));
+        let snapshot = LayoutSnapshot::capture(&slots, &turnouts, &SensorManager::new(), clock);
+
+        for file in ["snapshot.toml", "snapshot.ron"] {
+            let path = std::env::temp_dir().join(format!("locodrive-{}", file));
+            snapshot.save(&path).unwrap();
+            assert_eq!(LayoutSnapshot::load(&path).unwrap(), snapshot);
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let mut current = TurnoutTable::new();
+        current.handle(&Message::SwReq(SwitchArg::new(
+            12,
+            SwitchDirection::Curved,
+            true,
+        )));
+        let route = snapshot.reconciliation(&current);
+        assert_eq!(route.steps(), &[(13, SwitchDirection::Straight)]);
+
+        let (mut restored, mut sensors) = (SlotManager::new(), SensorManager::new());
+        snapshot.restore(&mut restored, &mut current, &mut sensors);
+        assert_eq!(restored, slots);
+        assert!(snapshot.reconciliation(&current).steps().is_empty());
+    }
+
+    /// Tests counting the messages missed by an envelope receiver from the sequence numbers.
+    #[tokio::test]
+    async fn envelope_sequence() {
+        use crate::stats::StatsCollector;
+        use crate::subscription::{Envelope, EnvelopeReceiver};
+        use std::sync::Arc;
+
+        let (sender, receiver) = tokio::sync::broadcast::channel(2);
+        let stats = Arc::new(StatsCollector::new(None));
+        let mut envelopes = EnvelopeReceiver::new(receiver, stats, |message| match message {
+            LocoDriveMessage::Message(message) => Some(*message),
+            _ => None,
+        });
+
+        for (sequence, event) in vec![
+            LocoDriveMessage::Message(GpOn),
+            LocoDriveMessage::BusResumed,
+            LocoDriveMessage::Message(Message::Idle),
+            LocoDriveMessage::BusResumed,
+            LocoDriveMessage::Message(Message::GpOff),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let received = Instant::now();
+            let sequence = sequence as u64;
+            sender
+                .send(Envelope {
+                    sequence,
+                    received,
+                    event,
+                })
+                .unwrap();
+            if sequence == 0 {
+                assert_eq!(envelopes.recv().await.unwrap().event, GpOn);
+            }
+        }
+
+        assert!(envelopes.recv().await.is_err());
+        let envelope = envelopes.recv().await.unwrap();
+        assert_eq!((envelope.sequence, envelope.event), (4, Message::GpOff));
+        assert_eq!(envelopes.missed(), 2);
+    }
+
+    /// Tests the answers of the simulated command station and the scripted bus messages.
+    #[tokio::test]
+    async fn simulated_station() {
+        use crate::manager::{Manager, TurnoutTable};
+        use crate::simulator::{CommandStation, Simulator};
+
+        let mut station = CommandStation::new();
+        let adr = AddressArg::new(5);
+        let slot = match station.handle(&Message::LocoAdr(adr))[..] {
+            [Message::SlRdData(slot, stat1, read, ..)] if read == adr => {
+                assert_eq!(stat1.state(), State::Common);
+                slot
+            }
+            ref answers => panic!("unexpected answers {:?}", answers),
+        };
+        assert!(matches!(
+            station.handle(&Message::MoveSlots(slot, slot))[..],
+            [Message::SlRdData(_, stat1, ..)] if stat1.state() == State::InUse
+        ));
+        assert!(matches!(
+            station.handle(&Message::LocoAdr(adr))[..],
+            [Message::SlRdData(read, ..)] if read == slot
+        ));
+
+        let mut turnouts = TurnoutTable::new();
+        let switch = SwitchArg::new(12, SwitchDirection::Curved, true);
+        for message in [Message::SwReq(switch), Message::SwState(switch)].iter() {
+            turnouts.handle(message);
+            for answer in station.handle(message) {
+                turnouts.handle(&answer);
+            }
+        }
+        assert!(!turnouts.turnout(12).unwrap().mismatched());
+
+        let sensor = Message::InputRep(InArg::new(3, SourceType::Switch, SensorLevel::High, false));
+        let simulator = Simulator::new(station)
+            .inject(Duration::from_millis(10), sensor)
+            .start();
+        let mut bus = simulator.subscribe();
+        simulator.write(GpOn);
+        assert_eq!(bus.recv().await.unwrap(), GpOn);
+        assert_eq!(bus.recv().await.unwrap(), sensor);
+        assert!(simulator.station().power());
+    }
+
+    /// Tests replaying a captured session in order and accelerated.
+    #[tokio::test]
+    async fn replayed_session() {
+        use crate::loco_controller::Direction;
+        use crate::manager::{Manager, TurnoutTable};
+        use crate::replay::ReplayTransport;
+        use tokio_stream::StreamExt;
+
+        let start = Instant::now();
+        let switch = SwitchArg::new(12, SwitchDirection::Curved, true);
+        let at = |millis| start + Duration::from_millis(millis);
+        // The written frame and the invalid frame are not replayed
+        let frames = vec![
+            (Direction::Tx, GpOn.to_message(), at(0)),
+            (Direction::Rx, Message::SwReq(switch).to_message(), at(400)),
+            (Direction::Rx, vec![0xB0, 0x00], at(500)),
+            (Direction::Rx, GpOn.to_message(), at(100)),
+        ];
+        let replay = ReplayTransport::from_frames(frames).speed(10.0);
+        assert_eq!(
+            replay.schedule(),
+            vec![
+                (Duration::ZERO, GpOn),
+                (Duration::from_millis(30), Message::SwReq(switch)),
+            ]
+        );
+        assert_eq!(replay.duration(), Duration::from_millis(30));
+
+        let mut turnouts = TurnoutTable::new();
+        let started = Instant::now();
+        let mut replayed = replay.stream();
+        while let Some(message) = replayed.next().await {
+            turnouts.handle(&message);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert_eq!(
+            turnouts.turnout(12).unwrap().commanded,
+            Some(SwitchDirection::Curved)
+        );
+    }
+
+    /// Tests round tripping arbitrary messages through their bytes.
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_messages() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // Deterministic noise, so failures are reproducible
+        let mut seed = 0x2545_F491_4F6C_DD1D_u64;
+        let noise: Vec<u8> = (0..1 << 16)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect();
+
+        // Parsing any frame with a valid checksum must not panic
+        for frame in noise.chunks(20) {
+            let len = (frame[0] as usize % 20).min(frame.len());
+            let mut frame = frame[..len].to_vec();
+            if len > 0 {
+                frame[len - 1] = 0xFF ^ frame[..len - 1].iter().fold(0, |acc, &b| acc ^ b);
+            }
+            let _ = Message::parse(&frame);
+        }
+
+        let mut unstructured = Unstructured::new(&noise);
+        for _ in 0..2000 {
+            let message = Message::arbitrary(&mut unstructured).unwrap();
+            if let Ok(parsed) = Message::parse(&message.to_message()) {
+                assert_eq!(Message::parse(&parsed.to_message()).ok(), Some(parsed));
+            }
+        }
+    }
+
+    /// Tests the data bytes of a peer transfer are returned whole, not cut to six bits.
+    #[test]
+    fn peer_transfer_data() {
+        let message = Message::PeerXfer(
+            SlotArg::new(1),
+            DstArg::new(2),
+            PxctData::new(0, 0x7F, 0x40, 0, 0, 0, 0, 0, 0x41),
+        );
+        match Message::parse(&message.to_message()).unwrap() {
+            Message::PeerXfer(_, _, pxct) => {
+                assert_eq!((pxct.d1(), pxct.d2(), pxct.d8()), (0x7F, 0x40, 0x41))
+            }
+            parsed => panic!("expected a peer transfer, got {:?}", parsed),
+        }
+    }
+
+    /// Tests the most significant bit of each peer transfer data byte is sent in the
+    /// PXCT bytes, as the data bytes on the wire hold only seven bits.
+    #[test]
+    fn peer_transfer_high_bits() {
+        let message = Message::PeerXfer(
+            SlotArg::new(1),
+            DstArg::new(2),
+            PxctData::new(0x3F, 0x80, 0xFF, 0, 0, 0, 0, 0, 0xC0),
+        );
+        let frame = message.to_message();
+        // PXCT1 holds the low peer data bits and the high bits of D1 and D2
+        assert_eq!(frame[5], 0x73);
+        assert_eq!(&frame[6..8], &[0x00, 0x7F]);
+        // PXCT2 holds the high peer data bits and the high bit of D8
+        assert_eq!(frame[10], 0x78);
+        assert_eq!(frame[14], 0x40);
+        assert!(frame[1..].iter().all(|byte| *byte < 0x80));
+        assert_eq!(Message::parse(&frame).unwrap(), message);
+    }
+
+    /// Tests the functions of an immediate packet are counted from the first function
+    /// of its function group.
+    #[test]
+    fn imm_packet_function_groups() {
+        for &(function_type, first, last) in [
+            (ImFunctionType::F13to20, 13, 19),
+            (ImFunctionType::F21to28, 21, 27),
+        ]
+        .iter()
+        {
+            let mut im_arg = ImArg::new(0, ImAddress::Short(3), function_type, 0);
+            im_arg.set_f(first, true);
+            im_arg.set_f(last, true);
+            assert!(im_arg.f(first) && im_arg.f(last) && !im_arg.f(first + 1));
+
+            match Message::parse(&Message::ImmPacket(im_arg).to_message()).unwrap() {
+                Message::ImmPacket(parsed) => {
+                    assert!(parsed.f(first) && parsed.f(last) && !parsed.f(first + 1))
+                }
+                parsed => panic!("expected an immediate packet, got {:?}", parsed),
+            }
+        }
+    }
+
+    /// Tests functions 9 to 12 of a short address are read back from the immediate packet.
+    #[test]
+    fn imm_packet_f9_to_f12() {
+        let mut im_arg = ImArg::new(0, ImAddress::Short(3), ImFunctionType::F9to12, 0);
+        im_arg.set_f(9, true);
+        im_arg.set_f(12, true);
+
+        match Message::parse(&Message::ImmPacket(im_arg).to_message()).unwrap() {
+            Message::ImmPacket(parsed) => {
+                assert!(parsed.f(9) && !parsed.f(10) && !parsed.f(11) && parsed.f(12))
+            }
+            parsed => panic!("expected an immediate packet, got {:?}", parsed),
+        }
+    }
+
+    /// Tests the address type of an immediate packet survives writing and parsing it,
+    /// with the function bits written to the byte of its address type.
+    #[test]
+    fn imm_packet_addresses() {
+        // Function 18 sets the bit marking function group bytes of long addresses
+        let mut short = ImArg::new(0, ImAddress::Short(3), ImFunctionType::F13to20, 0);
+        short.set_f(18, true);
+        let mut long = ImArg::new(0, ImAddress::Long(1234), ImFunctionType::F21to28, 0);
+        long.set_f(21, true);
+        long.set_f(27, true);
+
+        for (im_arg, im4) in [(short, 0x00), (long, 0x41)] {
+            let frame = Message::ImmPacket(im_arg).to_message();
+            assert_eq!(frame[8], im4, "{:?}", im_arg);
+            assert_eq!(Message::parse(&frame).unwrap(), Message::ImmPacket(im_arg));
+        }
+    }
+
+    /// Tests that frames too short for their opcode are rejected.
+    #[test]
+    fn short_frames() {
+        assert!(Message::parse(&[0xFD, 0x02]).is_err());
+        assert!(Message::parse(&[0xBB, 0x44]).is_err());
+    }
+
+    /// # Returns
+    ///
+    /// A strategy generating frames of all known opcodes with random 7 bit arguments.
+    /// The frames have a valid length and checksum, but may hold invalid arguments.
+    ///
+    /// Immediate packets are only modelled for setting functions,
+    /// so they are covered by generating messages instead.
+    fn frame_strategy() -> impl proptest::strategy::Strategy<Value = Vec<u8>> {
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use proptest::sample::select;
+
+        let sized = |opc: Vec<u8>, len: usize| {
+            (select(opc), vec(0..0x80_u8, len - 2)).prop_map(|(opc, mut args)| {
+                args.insert(0, opc);
+                args
+            })
+        };
+        let variable = |opc: u8, len: Vec<u8>| {
+            select(len).prop_flat_map(move |len| {
+                vec(0..0x80_u8, len as usize - 3).prop_map(move |mut args| {
+                    args.splice(0..0, vec![opc, len]);
+                    args
+                })
+            })
+        };
+
+        prop_oneof![
+            sized(vec![0x81, 0x82, 0x83, 0x85], 2),
+            sized(
+                vec![
+                    0xA0, 0xA1, 0xA2, 0xB0, 0xB1, 0xB2, 0xB4, 0xB5, 0xB6, 0xB8, 0xB9, 0xBA, 0xBC,
+                    0xBD, 0xBF,
+                ],
+                4
+            ),
+            // The second argument of a slot data request is always zero
+            (0..0x80_u8).prop_map(|slot| vec![0xBB, slot, 0x00]),
+            sized(vec![0xD0], 6),
+            // Function messages are marked by 0x20 and a receiver query holds no arguments
+            vec(0..0x80_u8, 3).prop_map(|args| [&[0xD4, 0x20][..], &args].concat()),
+            (1..0x80_u8, vec(0..0x80_u8, 3)).prop_map(|(receiver, args)| [
+                &[0xDF, receiver][..],
+                &args
+            ]
+            .concat()),
+            Just(vec![0xDF, 0x00, 0x00, 0x00, 0x00]),
+            variable(0xE4, vec![0x08, 0x0C, 0x0E]),
+            variable(0xE5, vec![0x10]),
+            variable(0xE6, vec![0x10, 0x15]),
+            variable(0xE7, vec![0x0E]),
+            variable(0xEF, vec![0x0E]).prop_filter("programming task", |frame| frame[2] != 0x7C),
+            // A programming task leaves its reserved arguments zero
+            (0..0x80_u8, vec(0..0x80_u8, 6)).prop_map(|(pcmd, args)| {
+                [&[0xEF, 0x0E, 0x7C, pcmd, 0x00][..], &args, &[0x00, 0x00]].concat()
+            }),
+        ]
+        .prop_map(|mut frame| {
+            frame.push(0xFF ^ frame.iter().fold(0, |acc, &b| acc ^ b));
+            frame
+        })
+    }
+
+    /// # Returns
+    ///
+    /// A strategy generating messages of all variants with random field values.
+    ///
+    /// The arguments are parsed from random 7 bit values, so their fields take any value
+    /// a message can hold. Only the peer data and immediate packets are created directly.
+    fn message_strategy() -> impl proptest::strategy::Strategy<Value = Message> {
+        use crate::args::ThrottleStatusArg;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use proptest::sample::select;
+
+        let byte = || 0..0x80_u8;
+        let bytes = move |len| vec(byte(), len);
+        let slot = move || byte().prop_map(SlotArg::parse);
+        let address = move || (byte(), byte()).prop_map(|(adr2, adr)| AddressArg::parse(adr2, adr));
+        let switch = move || (byte(), byte()).prop_map(|(sw1, sw2)| SwitchArg::parse(sw1, sw2));
+        let slot_data = |args: &[u8]| {
+            (
+                SlotArg::parse(args[0]),
+                Stat1Arg::parse(args[1]),
+                AddressArg::parse(args[7], args[2]),
+                SpeedArg::parse(args[3]),
+                DirfArg::parse(args[4]),
+                TrkArg::parse(args[5]),
+                Stat2Arg::parse(args[6]),
+                SndArg::parse(args[8]),
+                IdArg::parse(args[9], args[10]),
+            )
+        };
+
+        let im_address = prop_oneof![
+            byte().prop_map(ImAddress::Short),
+            // A long address with the high byte of a function group is read as short address
+            (byte(), byte())
+                .prop_filter("function group", |(high, _)| !matches!(high, 0x5E | 0x5F))
+                .prop_map(|(high, low)| ImAddress::Long((high as u16) << 8 | low as u16)),
+        ];
+        let im_arg = (
+            byte(),
+            im_address,
+            select(vec![
+                ImFunctionType::F9to12,
+                ImFunctionType::F13to20,
+                ImFunctionType::F21to28,
+            ]),
+            vec(any::<bool>(), 7),
+            byte(),
+        )
+            .prop_map(|(dhi, address, function_type, functions, im5)| {
+                let mut im_arg = ImArg::new(dhi, address, function_type, im5);
+                let (first, count) = match function_type {
+                    ImFunctionType::F9to12 => (9, 4),
+                    ImFunctionType::F13to20 => (13, 7),
+                    ImFunctionType::F21to28 => (21, 7),
+                };
+                for (f_num, f) in (first..).zip(functions).take(count) {
+                    im_arg.set_f(f_num, f);
+                }
+                im_arg
+            });
+
+        prop_oneof![
+            select(vec![
+                Message::Idle,
+                GpOn,
+                Message::GpOff,
+                Message::Busy,
+                Message::ReceiverQuery,
+            ]),
+            address().prop_map(Message::LocoAdr),
+            switch().prop_map(Message::SwAck),
+            switch().prop_map(Message::SwState),
+            switch().prop_map(Message::SwReq),
+            slot().prop_map(Message::RqSlData),
+            (slot(), slot()).prop_map(|(src, dst)| Message::MoveSlots(src, dst)),
+            (slot(), slot()).prop_map(|(sl1, sl2)| Message::LinkSlots(sl1, sl2)),
+            (slot(), slot()).prop_map(|(sl1, sl2)| Message::UnlinkSlots(sl1, sl2)),
+            (slot(), byte())
+                .prop_map(|(slot, dirf)| Message::ConsistFunc(slot, DirfArg::parse(dirf))),
+            (slot(), byte())
+                .prop_map(|(slot, stat1)| Message::SlotStat1(slot, Stat1Arg::parse(stat1))),
+            (byte(), byte()).prop_map(|(lopc, ack1)| {
+                Message::LongAck(LopcArg::parse(lopc), Ack1Arg::parse(ack1))
+            }),
+            (byte(), byte()).prop_map(|(in1, in2)| Message::InputRep(InArg::parse(in1, in2))),
+            (byte(), byte()).prop_map(|(sn1, sn2)| Message::SwRep(SnArg::parse(sn1, sn2))),
+            (slot(), byte()).prop_map(|(slot, snd)| Message::LocoSnd(slot, SndArg::parse(snd))),
+            (slot(), byte()).prop_map(|(slot, dirf)| Message::LocoDirf(slot, DirfArg::parse(dirf))),
+            (slot(), byte()).prop_map(|(slot, spd)| Message::LocoSpd(slot, SpeedArg::parse(spd))),
+            (byte(), byte(), address()).prop_map(|(m_high, zas, address)| {
+                Message::MultiSense(MultiSenseArg::parse(m_high, zas), address)
+            }),
+            (slot(), byte(), byte()).prop_map(|(slot, group, function)| {
+                Message::UhliFun(slot, FunctionArg::parse(group, function))
+            }),
+            (1..0x80_u8, bytes(3)).prop_map(|(receiver, args)| {
+                Message::ThrottleStatus(ThrottleStatusArg::parse(
+                    receiver, args[0], args[1], args[2],
+                ))
+            }),
+            // The clock and programming slots are written in other formats
+            (prop_oneof![Just(0x7B), Just(0x7C), byte()], bytes(10)).prop_map(|(slot, args)| {
+                let args = [&[slot][..], &args].concat();
+                Message::WrSlData(WrSlDataStructure::parse(
+                    args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
+                    args[8], args[9], args[10],
+                ))
+            }),
+            bytes(11)
+                .prop_filter("programming slot", |args| args[0] != 0x7C)
+                .prop_map(move |args| {
+                    let (slot, stat1, address, speed, dirf, trk, stat2, snd, id) = slot_data(&args);
+                    Message::SlRdData(slot, stat1, address, speed, dirf, trk, stat2, snd, id)
+                }),
+            bytes(10).prop_map(move |args| {
+                // The programming result is read from the same bytes as the slot data
+                let args = [&[0x7C][..], &args].concat();
+                let (slot, stat1, address, speed, dirf, trk, stat2, snd, id) = slot_data(&args);
+                Message::ProgrammingFinalResponse(
+                    slot,
+                    stat1,
+                    address,
+                    speed,
+                    dirf,
+                    trk,
+                    stat2,
+                    snd,
+                    id,
+                    Pcmd::parse(args[1]),
+                    PStat::parse(args[2]),
+                    AddressArg::parse(args[3], args[4]),
+                    CvDataArg::parse(args[6], args[7], args[8]),
+                )
+            }),
+            (select(vec![0x10, 0x15]), bytes(18)).prop_map(|(len, args)| {
+                Message::ProgrammingAborted(ProgrammingAbortedArg::new(len, &args))
+            }),
+            (slot(), byte(), byte(), 0..0x40_u8, vec(any::<u8>(), 8)).prop_map(
+                |(src, dst_low, dst_high, pxc, d)| {
+                    Message::PeerXfer(
+                        src,
+                        DstArg::parse(dst_low, dst_high),
+                        PxctData::new(pxc, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]),
+                    )
+                }
+            ),
+            select(vec![(0x08, 0x00), (0x08, 0x40), (0x0C, 0x41), (0x0E, 0x41)]).prop_flat_map(
+                move |(count, report)| {
+                    bytes(count as usize - 4).prop_map(move |args| {
+                        let args = [&[report][..], &args].concat();
+                        Message::Rep(RepStructure::parse(count, &args).unwrap())
+                    })
+                }
+            ),
+            im_arg.prop_map(Message::ImmPacket),
+        ]
+    }
+
+    proptest::proptest! {
+        /// Tests that every message is the same after writing and parsing it.
+        #[test]
+        fn message_round_trip(message in message_strategy()) {
+            proptest::prop_assert_eq!(Message::parse(&message.to_message()).ok(), Some(message));
+        }
+
+        /// Tests that every valid frame is written back to the same bytes after parsing.
+        #[test]
+        fn frame_round_trip(frame in frame_strategy()) {
+            if let Ok(message) = Message::parse(&frame) {
+                proptest::prop_assert_eq!(message.to_message(), frame);
+            }
+        }
+
+        /// Tests that the fields of a borrowed frame are the fields of the decoded message.
+        #[test]
+        fn message_ref_fields(message in message_strategy()) {
+            use crate::message_ref::MessageRef;
+
+            let frame = message.to_message();
+            let view = MessageRef::new(&frame).unwrap();
+            proptest::prop_assert_eq!(view.decode().ok(), Some(message));
+            proptest::prop_assert_eq!(view.opc(), message.opc());
+
+            let slot = match message {
+                Message::LocoSpd(slot, _)
+                | Message::LocoDirf(slot, _)
+                | Message::LocoSnd(slot, _)
+                | Message::SlotStat1(slot, _)
+                | Message::ConsistFunc(slot, _)
+                | Message::RqSlData(slot)
+                | Message::MoveSlots(slot, _)
+                | Message::LinkSlots(slot, _)
+                | Message::UnlinkSlots(slot, _)
+                | Message::UhliFun(slot, _)
+                | Message::SlRdData(slot, ..) => Some(slot),
+                _ => None,
+            };
+            proptest::prop_assert_eq!(view.slot(), slot);
+            let speed = match message {
+                Message::LocoSpd(_, speed) | Message::SlRdData(_, _, _, speed, ..) => Some(speed),
+                _ => None,
+            };
+            proptest::prop_assert_eq!(view.speed(), speed);
+            let address = match message {
+                Message::LocoAdr(address) | Message::SlRdData(_, _, address, ..) => Some(address),
+                _ => None,
+            };
+            proptest::prop_assert_eq!(view.address(), address);
+            let switch = match message {
+                Message::SwReq(switch) | Message::SwState(switch) | Message::SwAck(switch) => {
+                    Some(switch)
+                }
+                _ => None,
+            };
+            proptest::prop_assert_eq!(view.switch(), switch);
+            let sensor = match message {
+                Message::InputRep(sensor) => Some(sensor),
+                _ => None,
+            };
+            proptest::prop_assert_eq!(view.sensor(), sensor);
+        }
+
+        /// Tests that every message is written to a buffer like it is encoded to a vector.
+        #[test]
+        fn message_write_to(message in message_strategy()) {
+            use crate::error::EncodeError;
+            use crate::protocol::MAX_MESSAGE_LEN;
+
+            let mut buf = [0; MAX_MESSAGE_LEN];
+            let len = message.write_to(&mut buf).unwrap();
+            proptest::prop_assert_eq!(len, message.encoded_len());
+            let encoded = message.to_message();
+            proptest::prop_assert_eq!(&buf[..len], encoded.as_slice());
+            proptest::prop_assert_eq!(
+                message.write_to(&mut buf[..len - 1]),
+                Err(EncodeError::BufferTooSmall(len, len - 1))
+            );
+        }
+    }
+
+    /// Tests the direction bit of a dirf byte is set for driving backwards.
+    #[test]
+    fn dirf_direction() {
+        let forward = DirfArg::new(true, false, false, false, false, false);
+        let backward = DirfArg::new(false, false, false, false, false, false);
+        assert_eq!(
+            Message::LocoDirf(SlotArg::new(3), forward).to_message(),
+            vec![0xA1, 0x03, 0x00, 0x5D]
+        );
+        assert_eq!(
+            Message::parse(&[0xA1, 0x03, 0x20, 0x7D]).unwrap(),
+            Message::LocoDirf(SlotArg::new(3), backward)
+        );
+
+        let mut dirf = forward;
+        dirf.set_dir(false);
+        assert!(!dirf.dir());
+        assert_eq!(dirf, backward);
+    }
+
+    /// Tests the echo and acknowledgment handling of a controller over an in memory transport.
+    #[tokio::test]
+    async fn memory_transport() {
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::SendOptions;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller_end, mut bus) = LocoNetTransport::pair();
+        let builder = LocoDriveController::builder("unused", 0)
+            .transport(controller_end)
+            .sending_timeout(100)
+            .ignore_send_messages(true);
+        let controller = builder.clone().build().await.unwrap();
+        let mut messages = controller.subscribe();
+        assert_eq!(controller.get_port_name().as_deref(), Some("memory:0"));
+        assert!(controller.get_baud_rate().await.is_err());
+        assert!(controller.set_sending_timeout(100).await.is_ok());
+        // The transport is connected only once
+        assert!(builder.build().await.is_err());
+
+        // Echoed messages are send, but not broadcast
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            bus
+        });
+        controller.send_message(GpOn).await.unwrap();
+        let mut bus = station.await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), messages.recv())
+                .await
+                .is_err()
+        );
+
+        // Not echoed messages time out
+        assert!(matches!(
+            controller.send_message(Message::GpOff).await,
+            Err(LocoDriveSendingError::Timeout)
+        ));
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), Message::GpOff.to_message());
+
+        // The acknowledgment is matched to the echoed request
+        let request = Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true));
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 4];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            let ack = Message::LongAck(LopcArg::new(0xB0), Ack1Arg::new(true));
+            bus.write_all(&ack.to_message()).await.unwrap();
+            bus
+        });
+        let options = SendOptions {
+            require_ack: true,
+            ..SendOptions::default()
+        };
+        assert_eq!(
+            controller
+                .send_message_acked(request, options)
+                .await
+                .unwrap(),
+            Some(Ack1Arg::new(true))
+        );
+        let _bus = station.await.unwrap();
+
+        // The port is not written to anymore once the controller was dropped
+        let handle = controller.command_handle();
+        drop(controller);
+        assert!(matches!(
+            handle.send_message(GpOn).await,
+            Err(LocoDriveSendingError::IllegalState)
+        ));
+    }
+
+    /// Tests a send that times out or is rejected is retried until it is acknowledged.
+    #[tokio::test]
+    async fn send_retries() {
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::SendOptions;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(50)).await;
+
+        // The first attempt is not echoed, the second rejected and the third acknowledged
+        let request = Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true));
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 4];
+            bus.read_exact(&mut frame).await.unwrap();
+            for accepted in [false, true] {
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                let ack = Message::LongAck(LopcArg::new(0xB0), Ack1Arg::new(accepted));
+                bus.write_all(&ack.to_message()).await.unwrap();
+            }
+            bus
+        });
+        let options = SendOptions {
+            retries: 2,
+            retry_delay: Duration::from_millis(10),
+            require_ack: true,
+            ..SendOptions::default()
+        };
+        assert_eq!(
+            controller
+                .send_message_acked(request, options)
+                .await
+                .unwrap(),
+            Some(Ack1Arg::new(true))
+        );
+        assert_eq!(controller.stats().retransmits, 2);
+
+        // Without retries left the last error is returned
+        let mut bus = station.await.unwrap();
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 4];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            let ack = Message::LongAck(LopcArg::new(0xB0), Ack1Arg::new(false));
+            bus.write_all(&ack.to_message()).await.unwrap();
+        });
+        let options = SendOptions {
+            retries: 0,
+            ..options
+        };
+        assert!(matches!(
+            controller.send_message_acked(request, options).await,
+            Err(LocoDriveSendingError::Rejected(_))
+        ));
+        station.await.unwrap();
+        assert_eq!(controller.stats().retransmits, 2);
+    }
+
+    /// Tests the typed subscriptions only receive the events of their kind.
+    #[tokio::test]
+    async fn typed_subscriptions() {
+        use crate::subscription::SlotUpdate;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) = memory_controller(|builder| builder).await;
+        let mut sensors = controller.subscribe_sensor_events();
+        let mut slots = controller.subscribe_slot_updates();
+        let mut switches = controller.subscribe_switch_reports();
+
+        let sensor = InArg::new(8, SourceType::Switch, SensorLevel::High, false);
+        let report = SnArg::SwitchDirectionStatus(5, SensorLevel::High, SensorLevel::Low);
+        let traffic: Vec<u8> = [
+            GpOn,
+            Message::InputRep(sensor),
+            LocoSpd(SlotArg::new(3), SpeedArg::Drive(20)),
+            Message::SwRep(report),
+            Message::GpOff,
+        ]
+        .iter()
+        .flat_map(|message| message.to_message())
+        .collect();
+        bus.write_all(&traffic).await.unwrap();
+
+        assert_eq!(sensors.recv().await.unwrap(), sensor);
+        assert_eq!(
+            slots.recv().await.unwrap(),
+            SlotUpdate::Speed(SlotArg::new(3), SpeedArg::Drive(20))
+        );
+        assert_eq!(switches.recv().await.unwrap(), report);
+
+        // Nothing else matched the subscriptions
+        let nothing = Duration::from_millis(50);
+        assert!(tokio::time::timeout(nothing, sensors.recv()).await.is_err());
+        assert!(tokio::time::timeout(nothing, slots.recv()).await.is_err());
+        assert!(tokio::time::timeout(nothing, switches.recv())
+            .await
+            .is_err());
+    }
+
+    /// Tests the bus is reported idle once after a quiet time and resumed by the next traffic.
+    #[tokio::test]
+    async fn bus_idle() {
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.idle_after(Duration::from_millis(30))).await;
+        let mut messages = controller.subscribe();
+
+        match messages.recv().await.unwrap() {
+            LocoDriveMessage::BusIdle(quiet) => assert!(quiet >= Duration::from_millis(30)),
+            other => panic!("unexpected event {:?}", other),
+        }
+        // The idle bus is reported only once
+        assert!(
+            tokio::time::timeout(Duration::from_millis(80), messages.recv())
+                .await
+                .is_err()
+        );
+
+        bus.write_all(&GpOn.to_message()).await.unwrap();
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::BusResumed
+        ));
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::Message(GpOn)
+        ));
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::BusIdle(_)
+        ));
+    }
+
+    /// Tests the message stream buffers all messages, even beyond the channel capacity,
+    /// and ends once the controller was dropped.
+    #[tokio::test]
+    async fn message_stream() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_stream::StreamExt;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.channel_capacity(1)).await;
+        let mut stream = Box::pin(controller.messages());
+
+        let sent = [GpOn, Message::GpOff, GpOn, Message::Idle, Message::GpOff];
+        let traffic: Vec<u8> = sent
+            .iter()
+            .flat_map(|message| message.to_message())
+            .collect();
+        bus.write_all(&traffic).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        for expected in sent {
+            match stream.next().await {
+                Some(LocoDriveMessage::Message(message)) => assert_eq!(message, expected),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(controller.stats().lagged_messages, 0);
+
+        drop(controller);
+        let end = tokio::time::timeout(Duration::from_millis(1000), stream.next()).await;
+        assert!(end.unwrap().is_none());
+    }
+
+    /// Tests the immediate packet sender backs off on limited and rejected acknowledgments
+    /// and resumes once a packet is fully accepted.
+    #[tokio::test]
+    async fn imm_packet_pacing() {
+        use crate::imm_packet::ImmPacketSender;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(200)).await;
+
+        // The queue is limited after the first packet and full for the first try of the second
+        let acks = [
+            Ack1Arg::new_advanced(3),
+            Ack1Arg::new(false),
+            Ack1Arg::new(true),
+        ];
+        let station = tokio::spawn(async move {
+            for ack in acks {
+                let mut frame = [0; 11];
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                let ack = Message::LongAck(LopcArg::new(0xED), ack);
+                bus.write_all(&ack.to_message()).await.unwrap();
+            }
+        });
+
+        let packet = ImArg::new(32, ImAddress::Short(3), ImFunctionType::F9to12, 0);
+        let mut sender = ImmPacketSender::new(&controller)
+            .backoff(Duration::from_millis(5), Duration::from_millis(100));
+        sender.send(packet).await.unwrap();
+        assert_eq!(sender.current_backoff(), Duration::from_millis(25));
+        assert_eq!(sender.send_all([packet]).await.unwrap(), 1);
+        assert_eq!(sender.current_backoff(), Duration::ZERO);
+        station.await.unwrap();
+
+        let stats = controller.stats();
+        assert_eq!(stats.imm_packets_sent, 2);
+        assert_eq!(stats.imm_packets_rejected, 1);
+        assert!(stats.imm_packet_time >= Duration::from_millis(75));
+    }
+
+    /// Tests the traffic statistics are counted and broadcast periodically as health events.
+    #[tokio::test]
+    async fn health_reports() {
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.health_interval(Duration::from_millis(30))).await;
+        let mut messages = controller.subscribe();
+        assert_eq!(controller.stats().frames_received, 0);
+        assert!(controller.stats().last_activity.is_none());
+
+        // A valid frame, a corrupted one and a failed acknowledgment
+        let mut traffic = GpOn.to_message();
+        traffic.extend([0x83, 0x00]);
+        traffic.extend(Message::LongAck(LopcArg::new(0xB0), Ack1Arg::new(false)).to_message());
+        bus.write_all(&traffic).await.unwrap();
+
+        let stats = loop {
+            match messages.recv().await.unwrap() {
+                LocoDriveMessage::Health(stats) if stats.frames_received == 3 => break stats,
+                _ => {}
+            }
+        };
+        assert_eq!(stats.bytes_received, 8);
+        assert_eq!(stats.checksum_errors, 1);
+        assert_eq!(stats.lack_failures, 1);
+        assert!(stats.last_activity.is_some());
+        assert!(stats.bytes_per_second() > 0.0);
+    }
+
+    /// Tests the echo policies: a required echo is matched among other traffic,
+    /// an optional one is awaited without failing and none is not awaited at all.
+    #[tokio::test]
+    async fn echo_policies() {
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::EchoPolicy;
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::Instant;
+
+        let connect = |echo_policy| async move {
+            let (controller, bus) =
+                memory_controller(|builder| builder.sending_timeout(60).echo_policy(echo_policy))
+                    .await;
+            (controller, bus)
+        };
+
+        // The echo follows the message of another device
+        let (controller, mut bus) = connect(EchoPolicy::Require).await;
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&Message::Idle.to_message()).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            bus
+        });
+        controller.send_message(GpOn).await.unwrap();
+        let _bus = station.await.unwrap();
+        assert!(matches!(
+            controller.send_message(GpOn).await,
+            Err(LocoDriveSendingError::Timeout)
+        ));
+
+        // A missing optional echo is awaited, but no error
+        let (controller, _bus) = connect(EchoPolicy::Optional).await;
+        let sent = Instant::now();
+        controller.send_message(GpOn).await.unwrap();
+        assert!(sent.elapsed() >= Duration::from_millis(60));
+
+        // Without echoes the message is send as soon as it is written
+        let (controller, _bus) = connect(EchoPolicy::None).await;
+        let sent = Instant::now();
+        controller.send_message(GpOn).await.unwrap();
+        assert!(sent.elapsed() < Duration::from_millis(60));
+    }
+
+    /// Tests command handles of several tasks share the port without garbling their frames.
+    #[tokio::test]
+    async fn command_handles() {
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.sending_timeout(1000)).await;
+
+        // The command station echoes every message
+        let station = tokio::spawn(async move {
+            let mut received = Vec::new();
+            for _ in 0..15 {
+                let mut frame = [0; 2];
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                received.push(Message::parse(&frame).unwrap());
+            }
+            received
+        });
+
+        let senders: Vec<_> = [GpOn, Message::GpOff, Message::Idle]
+            .iter()
+            .map(|&message| {
+                let handle = controller.command_handle();
+                tokio::spawn(async move {
+                    for _ in 0..5 {
+                        handle.send_message(message).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.await.unwrap();
+        }
+
+        let received = station.await.unwrap();
+        for message in [GpOn, Message::GpOff, Message::Idle] {
+            assert_eq!(received.iter().filter(|&&read| read == message).count(), 5);
+        }
+    }
+
+    /// Tests writes are held back while the master is busy, until it is free or the hold time is up.
+    #[tokio::test]
+    async fn busy_hold() {
+        use crate::loco_controller::EchoPolicy;
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::{timeout, Instant};
+
+        let connect = |busy_hold| async move {
+            let (controller, mut bus) = memory_controller(|builder| {
+                builder.echo_policy(EchoPolicy::None).busy_hold(busy_hold)
+            })
+            .await;
+            bus.write_all(&Message::Busy.to_message()).await.unwrap();
+            while !controller.master_busy() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            (controller, bus)
+        };
+
+        // The write waits until the master sends anything else
+        let (controller, mut bus) = connect(Duration::from_secs(5)).await;
+        let handle = controller.command_handle();
+        let sender = tokio::spawn(async move { handle.send_message(GpOn).await });
+        let mut frame = [0; 2];
+        assert!(
+            timeout(Duration::from_millis(50), bus.read_exact(&mut frame))
+                .await
+                .is_err()
+        );
+        bus.write_all(&Message::GpOff.to_message()).await.unwrap();
+        timeout(Duration::from_millis(1000), bus.read_exact(&mut frame))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.to_vec(), GpOn.to_message());
+        sender.await.unwrap().unwrap();
+        assert!(!controller.master_busy());
+
+        // The write is tried anyway after the hold time
+        let (controller, mut bus) = connect(Duration::from_millis(50)).await;
+        let sent = Instant::now();
+        controller.send_message(GpOn).await.unwrap();
+        assert!(sent.elapsed() >= Duration::from_millis(50));
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), GpOn.to_message());
+    }
+
+    /// Tests a send whose token is cancelled before writing starts writes nothing.
+    #[tokio::test]
+    async fn cancelled_before_write() {
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::{EchoPolicy, SendOptions};
+        use tokio_util::sync::CancellationToken;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.echo_policy(EchoPolicy::None)).await;
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            controller
+                .send_message_cancellable(GpOn, SendOptions::default(), &cancel)
+                .await,
+            Err(LocoDriveSendingError::Cancelled)
+        ));
+
+        // The next message is the first one on the bus
+        controller.send_message(Message::GpOff).await.unwrap();
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), Message::GpOff.to_message());
+    }
+
+    /// Tests cancelling a hung send does not let its late echo complete the next send.
+    #[tokio::test]
+    async fn cancelled_send() {
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::SendOptions;
+        use tokio::io::AsyncWriteExt;
+        use tokio_util::sync::CancellationToken;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(100)).await;
+
+        let cancel = CancellationToken::new();
+        let cancelling = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancelling.cancel();
+        });
+        assert!(matches!(
+            controller
+                .send_message_cancellable(GpOn, SendOptions::default(), &cancel)
+                .await,
+            Err(LocoDriveSendingError::Cancelled)
+        ));
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), GpOn.to_message());
+
+        // The late echo of the cancelled message is no echo of the next one
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&GpOn.to_message()).await.unwrap();
+            // The bus stays open, so the reader keeps awaiting the echo
+            (frame, bus)
+        });
+        let sent = tokio::time::Instant::now();
+        assert!(matches!(
+            controller.send_message(Message::GpOff).await,
+            Err(LocoDriveSendingError::Timeout)
+        ));
+        assert!(sent.elapsed() >= Duration::from_millis(100));
+        let (frame, _bus) = station.await.unwrap();
+        assert_eq!(frame.to_vec(), Message::GpOff.to_message());
+    }
+
+    /// Tests echoes of own messages are tagged, unless they are ignored.
+    #[tokio::test]
+    async fn echo_tagging() {
+        use tokio::io::AsyncWriteExt;
+
+        for ignore in [false, true] {
+            let (controller, mut bus) = memory_controller(|builder| {
+                builder.ignore_send_messages(ignore).tag_send_messages(true)
+            })
+            .await;
+            let mut messages = controller.subscribe();
+
+            // The echo is followed by the same message of another device
+            let station = tokio::spawn(async move {
+                let mut frame = [0; 2];
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                bus
+            });
+            controller.send_message(GpOn).await.unwrap();
+            let _bus = station.await.unwrap();
+
+            if !ignore {
+                assert!(matches!(
+                    messages.recv().await,
+                    Ok(LocoDriveMessage::Echo(GpOn))
+                ));
+            }
+            assert!(matches!(
+                messages.recv().await,
+                Ok(LocoDriveMessage::Message(GpOn))
+            ));
+        }
+    }
+
+    /// Tests a panicking reading thread is reported and restarted,
+    /// which fails as the in memory transport can not be opened again.
+    #[tokio::test]
+    async fn reader_panic() {
+        use crate::error::MessageParseError;
+        use crate::loco_controller::ReaderStop;
+
+        let (controller_end, _bus) = LocoNetTransport::pair();
+        let (sender, mut messages) = tokio::sync::broadcast::channel(16);
+        let controller = LocoDriveController::builder("unused", 0)
+            .transport(controller_end.panic_reader())
+            .sender(sender)
+            .build()
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            controller.reader_handle().stopped().await,
+            ReaderStop::SerialPortError(_)
+        ));
+        assert!(matches!(
+            messages.recv().await,
+            Ok(LocoDriveMessage::Error(MessageParseError::ReaderPanicked(reason)))
+                if reason == "the reading thread panicked on purpose"
+        ));
+        assert!(matches!(
+            messages.recv().await,
+            Ok(LocoDriveMessage::SerialPortError(_))
+        ));
+    }
+
+    /// Tests the reading thread is reported running until the controller is dropped.
+    #[tokio::test]
+    async fn reader_supervision() {
+        use crate::loco_controller::{ReaderStatus, ReaderStop};
+
+        let (controller, _bus) = memory_controller(|builder| builder).await;
+        let mut reader = controller.reader_handle();
+        assert!(matches!(
+            controller.reader_status(),
+            ReaderStatus::Running { restarts: 0 }
+        ));
+        assert!(reader.status().is_running());
+
+        drop(controller);
+        let stopped = tokio::time::timeout(Duration::from_millis(1000), reader.stopped())
+            .await
+            .unwrap();
+        assert!(matches!(stopped, ReaderStop::Requested));
+        assert!(matches!(
+            reader.status(),
+            ReaderStatus::Stopped(ReaderStop::Requested)
+        ));
+    }
+
+    /// Tests the clones of a controller share its connection, which is stopped
+    /// only when the last clone is dropped.
+    #[tokio::test]
+    async fn cloned_controller() {
+        use crate::loco_controller::{EchoPolicy, ReaderStop};
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.echo_policy(EchoPolicy::None)).await;
+        let clone = controller.clone();
+        let mut reader = controller.reader_handle();
+
+        drop(controller);
+        clone.send_message(GpOn).await.unwrap();
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), GpOn.to_message());
+        assert!(reader.status().is_running());
+
+        drop(clone);
+        let stopped = tokio::time::timeout(Duration::from_millis(1000), reader.stopped())
+            .await
+            .unwrap();
+        assert!(matches!(stopped, ReaderStop::Requested));
+    }
+
+    /// Tests written messages are reported, even if their echoes are ignored.
+    #[tokio::test]
+    async fn sent_reports() {
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) = memory_controller(|builder| {
+            builder
+                .ignore_send_messages(true)
+                .report_sent_messages(true)
+        })
+        .await;
+        let mut messages = controller.subscribe();
+
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            bus
+        });
+        controller.send_message(GpOn).await.unwrap();
+        let _bus = station.await.unwrap();
+
+        match messages.recv().await {
+            Ok(LocoDriveMessage::Sent(GpOn, bytes)) => assert_eq!(bytes, GpOn.to_message()),
+            received => panic!("expected the sent message, got {:?}", received),
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), messages.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests the raw tap mirrors the written and the read frames,
+    /// including those which fail to parse.
+    #[tokio::test]
+    async fn raw_tap() {
+        use crate::loco_controller::Direction;
+        use tokio::io::AsyncWriteExt;
+
+        let (tap, mut frames) = tokio::sync::broadcast::channel(16);
+        let (controller, mut bus) = memory_controller(|builder| builder.raw_tap(tap)).await;
+
+        // The echo is followed by a frame with a wrong checksum
+        let corrupt = vec![0x83, 0x00];
+        let station = tokio::spawn({
+            let corrupt = corrupt.clone();
+            async move {
+                let mut frame = [0; 2];
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                bus.write_all(&corrupt).await.unwrap();
+                bus
+            }
+        });
+        controller.send_message(GpOn).await.unwrap();
+        let _bus = station.await.unwrap();
+
+        let mut mirrored = Vec::new();
+        for _ in 0..3 {
+            let (direction, bytes, _) = frames.recv().await.unwrap();
+            mirrored.push((direction, bytes));
+        }
+        assert_eq!(
+            mirrored,
+            vec![
+                (Direction::Tx, GpOn.to_message()),
+                (Direction::Rx, GpOn.to_message()),
+                (Direction::Rx, corrupt),
+            ]
+        );
+    }
+
+    /// Tests the task owning the port applies reconfigurations to the following sends
+    /// and stops with the controller, so its handles fail.
+    #[tokio::test]
+    async fn port_owner() {
+        use crate::error::LocoDriveSendingError;
+        use tokio::time::Instant;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.sending_timeout(5000)).await;
+        let handle = controller.command_handle();
+
+        // An in memory transport has no serial settings
+        assert!(controller.get_baud_rate().await.is_err());
+        controller.set_sending_timeout(50).await.unwrap();
+        let sent = Instant::now();
+        assert!(matches!(
+            handle.send_message(GpOn).await,
+            Err(LocoDriveSendingError::Timeout)
+        ));
+        assert!(sent.elapsed() < Duration::from_millis(5000));
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), GpOn.to_message());
+
+        drop(controller);
+        assert!(matches!(
+            handle.send_message(GpOn).await,
+            Err(LocoDriveSendingError::IllegalState)
+        ));
+    }
+
+    /// Tests requests without an answer are reported after the timeout of their op code.
+    #[tokio::test]
+    async fn answer_timeouts() {
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::Instant;
+
+        let request = Message::SwAck(SwitchArg::new(1, SwitchDirection::Straight, true));
+        let (controller, mut bus) = memory_controller(|builder| {
+            builder
+                .answer_timeout(request.opc(), Duration::from_millis(50))
+                .default_answer_timeout(Duration::from_millis(5000))
+        })
+        .await;
+        let mut messages = controller.subscribe();
+
+        // Another device requests a slot with the default timeout and switches a switch
+        // with the shorter one
+        let slot_request = Message::LocoAdr(AddressArg::new(3));
+        bus.write_all(&slot_request.to_message()).await.unwrap();
+        bus.write_all(&request.to_message()).await.unwrap();
+        let sent = Instant::now();
+
+        let timed_out = loop {
+            match messages.recv().await.unwrap() {
+                LocoDriveMessage::AnswerTimeout(timed_out) => break timed_out,
+                LocoDriveMessage::Message(_) => {}
+                received => panic!("unexpected {:?}", received),
+            }
+        };
+        assert_eq!(timed_out, request);
+        assert!(sent.elapsed() >= Duration::from_millis(50));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), messages.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests a send dropped while its frame is written still writes the whole frame,
+    /// so the following message is not garbled.
+    #[tokio::test]
+    async fn dropped_send() {
+        use crate::loco_controller::EchoPolicy;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.echo_policy(EchoPolicy::None)).await;
+
+        // Fills the transport buffer, so only half of the next frame fits into it
+        let filled = 2047;
+        for _ in 0..filled {
+            controller.send_message(GpOn).await.unwrap();
+        }
+        let request = Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), controller.send_message(request))
+                .await
+                .is_err()
+        );
+        // The writer notices the dropped send before the bus is read
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let mut frame = [0; 2];
+        for _ in 0..filled {
+            bus.read_exact(&mut frame).await.unwrap();
+        }
+        let mut frame = [0; 4];
+        tokio::time::timeout(Duration::from_millis(1000), bus.read_exact(&mut frame))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.to_vec(), request.to_message());
+
+        controller.send_message(Message::GpOff).await.unwrap();
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame.to_vec(), Message::GpOff.to_message());
+    }
+
+    /// Tests the reading thread stops once the other end of the transport was dropped,
+    /// instead of reporting the closed port over and over.
+    #[tokio::test]
+    async fn closed_transport() {
+        use crate::loco_controller::ReaderStop;
+
+        let (controller, bus) = memory_controller(|builder| builder).await;
+        let mut messages = controller.subscribe();
+        let mut reader = controller.reader_handle();
+
+        drop(bus);
+        let stopped = tokio::time::timeout(Duration::from_millis(1000), reader.stopped())
+            .await
+            .unwrap();
+        assert!(matches!(stopped, ReaderStop::Closed));
+        assert!(!controller.reader_status().is_running());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), messages.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests a frame cut off by a lost byte is reported as truncated and counted as line noise
+    /// without discarding the following frame.
+    #[tokio::test]
+    async fn line_noise() {
+        use crate::error::MessageParseError;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.line_noise_threshold(0)).await;
+        let mut messages = controller.subscribe();
+
+        // The speed message lost its last two bytes
+        bus.write_all(&[0xA0, 0x03, 0x83, 0x7C]).await.unwrap();
+
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::Error(MessageParseError::Truncated {
+                opc: 0xA0,
+                offset: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::LineNoise(1)
+        ));
+        assert!(matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::Message(GpOn)
+        ));
+        assert_eq!(controller.stats().recent_checksum_errors, 1);
+    }
+
+    /// Tests a bouncing sensor is flagged as chatty and counted in the traffic report.
+    #[tokio::test]
+    async fn chatty_devices() {
+        use crate::traffic::TrafficKey;
+        use tokio::io::AsyncWriteExt;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.chatty_threshold(2)).await;
+        let mut messages = controller.subscribe();
+
+        let report = [0xB2, 0x01, 0x10, 0x5C];
+        let sensor = TrafficKey::Sensor(InArg::parse(0x01, 0x10).address_ds54());
+        for _ in 0..3 {
+            bus.write_all(&report).await.unwrap();
+        }
+
+        loop {
+            if let LocoDriveMessage::ChattyDevice(device, count) = messages.recv().await.unwrap() {
+                assert_eq!((device, count), (sensor, 3));
+                break;
+            }
+        }
+        assert_eq!(controller.stats().chatty_devices, 1);
+
+        let traffic = controller.traffic();
+        assert_eq!(traffic.frames, 3);
+        assert_eq!(traffic.opcodes, vec![(TrafficKey::Opcode(0xB2), 3)]);
+        assert_eq!(traffic.devices, vec![(sensor, 3)]);
+        assert_eq!(traffic.chatty, vec![(sensor, 3)]);
+    }
+
+    /// Tests matching read messages to the echoes awaited by the writers.
+    #[tokio::test]
+    async fn echo_channel() {
+        use crate::correlation::echo_channel;
+
+        let (echoes, mut matcher) = echo_channel();
+
+        let echoed = echoes.expect(GpOn);
+        assert!(!matcher.handle(&Message::GpOff));
+        assert!(matcher.handle(&GpOn));
+        assert!(echoed.await.is_ok());
+        // Each echo is only matched once
+        assert!(!matcher.handle(&GpOn));
+
+        // A writer no longer waiting is not matched
+        drop(echoes.expect(GpOn));
+        assert!(!matcher.handle(&GpOn));
+
+        // Only the echo of the last written message is awaited
+        let superseded = echoes.expect(Message::GpOff);
+        let echoed = echoes.expect(GpOn);
+        assert!(!matcher.handle(&Message::GpOff));
+        assert!(superseded.await.is_err());
+        assert!(matcher.handle(&GpOn));
+        assert!(echoed.await.is_ok());
+
+        // Without a reader the echo is never read
+        let echoed = echoes.expect(GpOn);
+        drop(matcher);
+        assert!(echoed.await.is_err());
+    }
+
+    /// Tests the lag of a subscriber behind a small broadcast channel is counted in the stats.
+    #[tokio::test]
+    async fn channel_lag() {
+        use tokio::io::AsyncWriteExt;
+        use tokio::sync::broadcast::error::RecvError;
+
+        let (controller, mut bus) = memory_controller(|builder| builder.channel_capacity(2)).await;
+        let mut messages = controller.subscribe();
+        assert_eq!(controller.stats().channel_capacity, Some(2));
+
+        let traffic: Vec<u8> = [GpOn, Message::GpOff, GpOn, Message::GpOff, GpOn]
+            .iter()
+            .flat_map(|message| message.to_message())
+            .collect();
+        bus.write_all(&traffic).await.unwrap();
+        tokio::time::timeout(Duration::from_millis(1000), async {
+            while controller.stats().frames_received < 5 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Only the two newest messages are still held by the channel
+        assert!(matches!(messages.recv().await, Err(RecvError::Lagged(3))));
+        assert!(matches!(
+            messages.recv().await,
+            Ok(LocoDriveMessage::Message(Message::GpOff))
+        ));
+        assert!(matches!(
+            messages.recv().await,
+            Ok(LocoDriveMessage::Message(GpOn))
+        ));
+        let stats = controller.stats();
+        assert_eq!(stats.lag_events, 1);
+        assert_eq!(stats.lagged_messages, 3);
+    }
+
+    /// Tests the controller keeps the bus idle for the gap and priority backoff before writing.
+    #[tokio::test]
+    async fn tx_pacing() {
+        use crate::loco_controller::EchoPolicy;
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::Instant;
+
+        let (controller, mut bus) = memory_controller(|builder| {
+            builder
+                .echo_policy(EchoPolicy::None)
+                .tx_gap(Duration::from_millis(40))
+                .priority_backoff(Duration::from_millis(10))
+        })
+        .await;
+
+        // Power messages have no priority delay, so only the gap is kept
+        controller.send_message(GpOn).await.unwrap();
+        let written = Instant::now();
+        controller.send_message(Message::GpOff).await.unwrap();
+        assert!(written.elapsed() >= Duration::from_millis(40));
+
+        // Received traffic is bus activity as well, switching waits six backoff steps
+        let received = Instant::now();
+        bus.write_all(&GpOn.to_message()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let request = Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true));
+        controller.send_message(request).await.unwrap();
+        assert!(received.elapsed() >= Duration::from_millis(100));
+
+        let mut frames = [0; 8];
+        bus.read_exact(&mut frames).await.unwrap();
+        assert_eq!(frames[4..].to_vec(), request.to_message());
+    }
+
+    /// Tests spilling critical events and blocking on a full broadcast channel.
+    #[tokio::test]
+    async fn overflow_policy() {
+        use crate::loco_controller::OverflowPolicy;
+        use tokio::io::AsyncWriteExt;
+        use tokio_stream::StreamExt;
+
+        let traffic: Vec<u8> = [Message::GpOff, GpOn, Message::GpOff, GpOn]
+            .iter()
+            .flat_map(|message| message.to_message())
+            .collect();
+
+        // Critical events are not lost by lagging
+        let (controller, mut bus) = memory_controller(|builder| {
+            builder
+                .channel_capacity(1)
+                .overflow_policy(OverflowPolicy::SpillCritical)
+        })
+        .await;
+        let mut critical = Box::pin(controller.critical_events().unwrap());
+        let mut messages = controller.subscribe();
+        bus.write_all(&traffic).await.unwrap();
+        for expected in [Message::GpOff, GpOn, Message::GpOff, GpOn] {
+            match critical.next().await {
+                Some(LocoDriveMessage::Message(message)) => assert_eq!(message, expected),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert!(messages.recv().await.is_err());
+        assert_eq!(controller.stats().lagged_messages, 3);
+
+        // The reader waits for the subscribers until its time is up
+        let (controller, mut bus) = memory_controller(|builder| {
+            builder
+                .channel_capacity(2)
+                .overflow_policy(OverflowPolicy::Block(Duration::from_millis(20)))
+        })
+        .await;
+        assert!(controller.critical_events().is_none());
+        let mut messages = controller.subscribe();
+        bus.write_all(&traffic).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(controller.stats().overflow_timeouts > 0);
+        assert!(messages.recv().await.is_err());
+    }
+
+    /// Tests the frame and offset carried by parse errors.
+    #[test]
+    fn parse_error_frame() {
+        use crate::error::MessageParseError;
+
+        let err = Message::parse(&[0x83, 0x7D]).unwrap_err();
+        assert!(matches!(
+            err,
+            MessageParseError::InvalidChecksum { opc: 0x83, .. }
+        ));
+        assert_eq!(err.frame(), &[0x83, 0x7D]);
+        assert_eq!(err.offset(), Some(1));
+        assert_eq!(
+            err.to_string(),
+            "invalid checksum, while reading message with opcode: 83 (frame: 83 7D, at byte 1)"
+        );
+
+        // Only the bytes of the frame are carried
+        let err = Message::parse(&[0x12, 0x34]).unwrap_err();
+        assert!(matches!(
+            err,
+            MessageParseError::UnknownOpcode { opc: 0x12, .. }
+        ));
+        assert_eq!(err.frame(), &[0x12]);
+
+        let err = Message::parse(&[0xA0, 0x01]).unwrap_err();
+        assert!(matches!(err, MessageParseError::UnexpectedEnd { .. }));
+        assert_eq!(err.offset(), Some(2));
+
+        // The check byte of an immediate packet is invalid
+        let mut frame = vec![0xED, 0x0B, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        frame[10] = frame[..10].iter().fold(0xFF, |acc, byte| acc ^ byte);
+        let err = Message::parse(&frame).unwrap_err();
+        assert!(matches!(err, MessageParseError::InvalidFormat { .. }));
+        assert_eq!(err.frame(), frame.as_slice());
+        assert_eq!(err.offset(), Some(2));
+
+        assert_eq!(MessageParseError::Update.offset(), None);
+    }
+
+    /// Tests converting errors to the unified error and chaining their sources.
+    #[test]
+    fn unified_error() {
+        use crate::error::{LocoDriveError, LocoDriveSendingError, ProgrammingError};
+        use std::error::Error;
+
+        fn parse(frame: &[u8]) -> Result<Message, LocoDriveError> {
+            Ok(Message::parse(frame)?)
+        }
+
+        let err = parse(&[0x83, 0x7D]).unwrap_err();
+        assert!(matches!(err, LocoDriveError::Parse(_)));
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            Message::parse(&[0x83, 0x7D]).unwrap_err().to_string()
+        );
+
+        // The cause of wrapping errors is kept
+        let err = LocoDriveError::from(ProgrammingError::Sending(LocoDriveSendingError::Timeout));
+        let programming = err.source().unwrap();
+        assert!(programming.source().unwrap().is::<LocoDriveSendingError>());
+    }
+
+    /// Tests that every slot status byte read from the wire parses, including reserved decoder types.
+    #[test]
+    fn stat1_never_panics() {
+        use crate::args::DecoderType;
+        use crate::error::MessageParseError;
+        use std::convert::TryFrom;
+
+        for stat1 in 0..0x80u8 {
+            let mut frame = vec![0xE7, 0x0E, 0x03, stat1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            frame.push(frame.iter().fold(0xFF, |acc, byte| acc ^ byte));
+
+            let message = Message::parse(&frame).unwrap();
+            let decoder_type = match message {
+                Message::SlRdData(_, stat1, ..) => stat1.decoder_type(),
+                _ => panic!("unexpected message {:?}", message),
+            };
+            if let DecoderType::Reserved(reserved) = decoder_type {
+                assert_eq!(reserved.raw(), stat1 & 0x07);
+            }
+            assert_eq!(message.to_message(), frame);
+
+            // Fallible parsing rejects the reserved decoder types
+            let parsed = Stat1Arg::try_from(stat1);
+            match decoder_type {
+                DecoderType::Reserved(_) => assert!(matches!(
+                    parsed,
+                    Err(MessageParseError::InvalidFormat { .. })
+                )),
+                _ => assert_eq!(parsed.unwrap().decoder_type(), decoder_type),
+            }
+        }
+    }
+
+    /// Tests resolving the slot of an address from a command station over an in memory transport.
+    #[tokio::test]
+    async fn slot_resolver() {
+        use crate::slot_cache::SlotChange;
+        use tokio::io::AsyncWriteExt;
+
+        let data = |state| {
+            Message::SlRdData(
+                SlotArg::new(3),
+                Stat1Arg::new(false, Consist::Free, state, DecoderType::Dcc128),
+                AddressArg::new(5),
+                SpeedArg::Stop,
+                DirfArg::new(false, false, false, false, false, false),
+                TrkArg::new(true, false, true, false),
+                Stat2Arg::new(false, false, false),
+                SndArg::new(false, false, false, false),
+                IdArg::new(7),
+            )
+        };
+
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.sending_timeout(1000)).await;
+
+        // The command station answers the address request with an idle slot
+        // and the null move marking it in use
+        let station = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for answer in [data(State::Idle), data(State::InUse)] {
+                let mut frame = [0; 4];
+                bus.read_exact(&mut frame).await.unwrap();
+                bus.write_all(&frame).await.unwrap();
+                bus.write_all(&answer.to_message()).await.unwrap();
+                requests.push(Message::parse(&frame).unwrap());
+            }
+            requests
+        });
+
+        let slots = SlotResolver::new(&controller);
+        let mut changes = slots.subscribe();
+        let adr = AddressArg::new(5);
+        assert_eq!(slots.slot_for(adr).await.unwrap(), SlotArg::new(3));
+        assert_eq!(
+            station.await.unwrap(),
+            vec![
+                Message::LocoAdr(adr),
+                Message::MoveSlots(SlotArg::new(3), SlotArg::new(3))
+            ]
+        );
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            SlotChange::Acquired {
+                address: adr,
+                slot: SlotArg::new(3)
+            }
+        );
+
+        // The cached slot is resolved without asking the command station again
+        assert_eq!(slots.cached(adr), Some(SlotArg::new(3)));
+        assert_eq!(slots.slot_for(adr).await.unwrap(), SlotArg::new(3));
+        assert_eq!(slots.invalidate(adr), Some(SlotArg::new(3)));
+        assert_eq!(slots.cached(adr), None);
+    }
+
+    /// Tests mapping power messages to power events and states.
+    #[test]
+    fn power_events() {
+        use crate::loco_controller::PowerState;
+        use crate::subscription::PowerEvent;
+
+        assert_eq!(PowerEvent::from_message(&GpOn), Some(PowerEvent::On));
+        let state = |message| PowerEvent::from_message(&message).map(|event| event.state());
+        assert_eq!(state(Message::Idle), Some(PowerState::Idle));
+        assert_eq!(state(Message::GpOff), Some(PowerState::Off));
+        assert_eq!(PowerEvent::from_message(&Message::Busy), None);
+    }
+
+    /// Tests dropping identical frames only within the window.
+    #[test]
+    fn duplicate_frames() {
+        use crate::dedup::DuplicateFilter;
+
+        let mut duplicates = DuplicateFilter::new(Some(Duration::from_millis(5)));
+        let start = Instant::now();
+        let sensor = Message::InputRep(InArg::new(8, SourceType::Switch, SensorLevel::High, false))
+            .to_message();
+
+        assert!(!duplicates.is_duplicate(&sensor, start));
+        assert!(duplicates.is_duplicate(&sensor, start + Duration::from_millis(1)));
+        assert!(!duplicates.is_duplicate(&sensor, start + Duration::from_millis(6)));
+        assert!(!duplicates.is_duplicate(&GpOn.to_message(), start + Duration::from_millis(7)));
+        assert!(!duplicates.is_duplicate(&sensor, start + Duration::from_millis(8)));
+
+        let mut passing = DuplicateFilter::new(None);
+        assert!(!passing.is_duplicate(&sensor, start));
+        assert!(!passing.is_duplicate(&sensor, start));
+    }
+
+    /// Tests registering slots to be kept alive.
+    #[test]
+    fn keep_alive_registration() {
+        use crate::keep_alive::KeepAlive;
+
+        let keep_alive = KeepAlive::new(Duration::from_secs(60));
+        let slot = SlotArg::new(4);
+
+        keep_alive.register(slot, SpeedArg::Drive(20));
+        assert!(keep_alive.is_registered(slot));
+        assert_eq!(keep_alive.speed(slot), Some(SpeedArg::Drive(20)));
+
+        keep_alive.unregister(slot);
+        assert!(!keep_alive.is_registered(slot));
+        assert_eq!(keep_alive.speed(slot), None);
+    }
+
+    /// Tests forwarding messages between bridged ports without loops.
+    #[tokio::test]
+    async fn bridge_forwarding() {
+        use crate::bridge::{BridgePort, LocoNetBridge};
+        use tokio::sync::mpsc::channel;
+        use tokio_stream::wrappers::ReceiverStream;
+
+        let (a_in, a_incoming) = channel(8);
+        let (a_outgoing, mut a_out) = channel(8);
+        let (b_in, b_incoming) = channel(8);
+        let (b_outgoing, mut b_out) = channel(8);
+        let sensor = Message::InputRep(InArg::new(8, SourceType::Switch, SensorLevel::High, false));
+
+        let _bridge = LocoNetBridge::new()
+            .port(BridgePort::new(
+                "a",
+                ReceiverStream::new(a_incoming),
+                a_outgoing,
+            ))
+            .port(
+                BridgePort::new("b", ReceiverStream::new(b_incoming), b_outgoing)
+                    .pass(|message| !matches!(message, Message::InputRep(_))),
+            )
+            .start();
+
+        a_in.send(sensor).await.unwrap();
+        a_in.send(GpOn).await.unwrap();
+        assert_eq!(b_out.recv().await, Some(GpOn));
+
+        // The echo of the forwarded message is not sent back
+        b_in.send(GpOn).await.unwrap();
+        b_in.send(Message::GpOff).await.unwrap();
+        assert_eq!(a_out.recv().await, Some(Message::GpOff));
+        assert!(a_out.try_recv().is_err());
+        assert!(b_out.try_recv().is_err());
+    }
+
+    /// Tests the lines of the LoconetOverTcp protocol.
+    #[test]
+    fn loco_server_lines() {
+        use crate::loco_server::{format_frame, parse_command};
+
+        assert_eq!(parse_command("SEND 83 7C").unwrap().unwrap(), GpOn);
+        assert_eq!(parse_command("send 83 7c\r").unwrap().unwrap(), GpOn);
+        assert!(parse_command("SEND 83 7D").unwrap().is_err());
+        assert!(parse_command("SEND 83 XY").unwrap().is_err());
+        assert!(parse_command("RECEIVE 83 7C").is_none());
+        assert!(parse_command("").is_none());
+        assert_eq!(
+            crate::line_protocol::parse_frame_line("RECEIVE", "RECEIVE 83 7C")
+                .unwrap()
+                .unwrap(),
+            GpOn
+        );
+
+        assert_eq!(format_frame("RECEIVE", &GpOn.to_message()), "RECEIVE 83 7C");
+    }
+
+    /// Tests slicing frames out of the adaptive read buffer.
+    #[tokio::test]
+    async fn frame_reader() {
+        use crate::frame_reader::FrameReader;
+
+        let traffic: Vec<u8> = (0..40).flat_map(|_| GpOn.to_message()).collect();
+        let mut reader = FrameReader::new(traffic.as_slice());
+
+        assert_eq!(reader.peek(0).await.unwrap(), 0x83);
+        assert_eq!(reader.read_frame(2).await.unwrap(), GpOn.to_message());
+        // The busy bus filled the whole request
+        assert!(reader.read_size() > 16);
+
+        for _ in 1..39 {
+            assert_eq!(reader.peek(1).await.unwrap(), 0x7C);
+            assert_eq!(reader.read_frame(2).await.unwrap(), GpOn.to_message());
+        }
+        // A truncated frame is kept buffered
+        reader.skip(1);
+        assert!(reader.read_frame(2).await.is_err());
+        assert_eq!(reader.peek(0).await.unwrap(), 0x7C);
+        reader.skip(4);
+        assert!(reader.peek(0).await.is_err());
+    }
+
+    /// Tests the echo handling of a session over an embedded transport.
+    #[tokio::test]
+    #[cfg(feature = "embedded")]
+    async fn embedded_session() {
+        use crate::embedded::EmbeddedSession;
+        use crate::error::EmbeddedError;
+        use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+        use std::collections::VecDeque;
+
+        /// Echoes all written bytes after the bytes already on the bus.
+        struct Loopback(VecDeque<u8>);
+
+        impl ErrorType for Loopback {
+            type Error = ErrorKind;
+        }
+
+        impl Read for Loopback {
+            async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+                let len = buf.len().min(self.0.len());
+                for byte in buf.iter_mut().take(len) {
+                    *byte = self.0.pop_front().unwrap();
+                }
+                Ok(len)
+            }
+        }
+
+        impl Write for Loopback {
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+                self.0.extend(buf);
+                Ok(buf.len())
+            }
+        }
+
+        // Noise and a message of another device precede the echo
+        let mut bus: VecDeque<u8> = vec![0x12].into();
+        bus.extend(Message::GpOff.to_message());
+        let mut session = EmbeddedSession::new(Loopback(bus));
+
+        session.send(GpOn).await.unwrap();
+        assert_eq!(session.receive().await.unwrap(), Message::GpOff);
+        assert!(matches!(
+            session.receive().await,
+            Err(EmbeddedError::Closed)
+        ));
+    }
+
+    /// Tests the blocking controller awaits the echo over a pseudo terminal.
+    #[test]
+    #[cfg(all(unix, feature = "blocking"))]
+    fn blocking_controller() {
+        use crate::blocking::BlockingLocoDriveController;
+        use crate::error::LocoDriveSendingError;
+        use serialport::{SerialPort, TTYPort};
+        use std::io::Read;
+        use std::sync::mpsc::channel;
+
+        // The controller opens the terminal again by its name
+        let (mut bus, port) = TTYPort::pair().unwrap();
+        let name = port.name().unwrap();
+        drop(port);
+
+        let (sender, receiver) = channel();
+        let mut controller = BlockingLocoDriveController::new(
+            &name,
+            115_200,
+            200,
+            serialport::FlowControl::None,
+            sender,
+            true,
+        )
+        .unwrap();
+
+        // The echo is awaited, but not passed on
+        let station = std::thread::spawn(move || {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).unwrap();
+            bus.write_all(&frame).unwrap();
+            bus.write_all(&Message::GpOff.to_message()).unwrap();
+            bus
+        });
+        controller.send_message(GpOn).unwrap();
+        let mut bus = station.join().unwrap();
+        let received = receiver.recv_timeout(Duration::from_millis(1000)).unwrap();
+        assert_eq!(received.unwrap(), Message::GpOff);
+
+        // Not echoed messages time out
+        assert!(matches!(
+            controller.send_message(Message::Idle),
+            Err(LocoDriveSendingError::Timeout)
+        ));
+        let mut frame = [0; 2];
+        bus.read_exact(&mut frame).unwrap();
+        assert_eq!(frame.to_vec(), Message::Idle.to_message());
+    }
+
+    /// Tests the echo handling of a blocking session over an embedded transport.
+    #[test]
+    #[cfg(feature = "embedded")]
+    fn blocking_embedded_session() {
+        use crate::embedded::BlockingEmbeddedSession;
+        use crate::error::EmbeddedError;
+        use embedded_io::{ErrorKind, ErrorType, Read, Write};
+        use std::collections::VecDeque;
+
+        /// Echoes all written bytes after the bytes already on the bus, one byte per read.
+        struct Loopback(VecDeque<u8>);
+
+        impl ErrorType for Loopback {
+            type Error = ErrorKind;
+        }
+
+        impl Read for Loopback {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+                match (buf.first_mut(), self.0.pop_front()) {
+                    (Some(first), Some(byte)) => {
+                        *first = byte;
+                        Ok(1)
+                    }
+                    _ => Ok(0),
+                }
+            }
+        }
+
+        impl Write for Loopback {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+                self.0.extend(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> Result<(), ErrorKind> {
+                Ok(())
+            }
+        }
+
+        // A frame with an invalid checksum and a message of another device precede the echo
+        let mut bus: VecDeque<u8> = vec![0x83, 0x00].into();
+        bus.extend(Message::GpOff.to_message());
+        let mut session = BlockingEmbeddedSession::new(Loopback(bus));
+
+        session.send(GpOn).unwrap();
+        assert_eq!(session.receive().unwrap(), Message::GpOff);
+        assert!(matches!(session.receive(), Err(EmbeddedError::Closed)));
+    }
+
+    /// Tests parsing and encoding frames through the C interface, which the header declares.
+    #[test]
+    #[cfg(feature = "ffi")]
+    fn ffi_parse_encode() {
+        use crate::ffi::*;
+
+        let header = include_str!("../include/locodrive.h");
+        for function in [
+            "locodrive_parse(",
+            "locodrive_encode(",
+            "locodrive_open_port(",
+            "locodrive_send(",
+            "locodrive_set_receive_callback(",
+            "locodrive_close(",
+        ] {
+            assert!(header.contains(function), "{} is not declared", function);
+        }
+
+        let mut frame = [0; 4];
+        let len =
+            unsafe { locodrive_encode([0xA0, 0x03, 0x20].as_ptr(), 3, frame.as_mut_ptr(), 4) };
+        assert_eq!(len, 4);
+        assert_eq!(frame, [0xA0, 0x03, 0x20, 0x7C]);
+
+        let mut description = [0u8; 8];
+        let len =
+            unsafe { locodrive_parse(frame.as_ptr(), 4, description.as_mut_ptr() as *mut _, 8) };
+        assert_eq!(len, 4);
+        assert_eq!(&description, b"LocoSpd\0");
+
+        frame[3] = 0x00;
+        let len = unsafe { locodrive_parse(frame.as_ptr(), 4, std::ptr::null_mut(), 0) };
+        assert_eq!(len, LOCODRIVE_ERR_INVALID_CHECKSUM);
+        let len = unsafe { locodrive_encode([0x83].as_ptr(), 1, frame.as_mut_ptr(), 1) };
+        assert_eq!(len, LOCODRIVE_ERR_BUFFER_TOO_SMALL);
+
+        // The length of the consumed frame is returned, not the one the message encodes to
+        let mut aborted = [0; 20];
+        aborted[..2].copy_from_slice(&[0xE6, 0x12]);
+        aborted[17] = 0xFF ^ 0xE6 ^ 0x12;
+        let len = unsafe { locodrive_parse(aborted.as_ptr(), 20, std::ptr::null_mut(), 0) };
+        assert_eq!(len, 0x12);
+    }
+
+    /// Tests creating, inspecting and parsing messages from Python.
+    #[test]
+    #[cfg(feature = "python")]
+    fn python_message() {
+        use pyo3::types::{PyDict, PyDictMethods};
+        use pyo3::Python;
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let locals = PyDict::new(py);
+            let module = pyo3::wrap_pymodule!(crate::python::locodrive)(py);
+            locals.set_item("locodrive", module).unwrap();
+            py.run(
+                pyo3::ffi::c_str!(
+                    r#"
+message = locodrive.Message.loco_spd(locodrive.SlotArg(3), locodrive.SpeedArg(31))
+assert message.kind == "LocoSpd"
+assert message.slot == locodrive.SlotArg(3)
+assert message.speed.speed == 31
+assert message.switch is None
+assert locodrive.Message.parse(message.to_bytes()) == message
+
+try:
+    locodrive.Message.parse(bytes([0x83, 0x00]))
+    raise AssertionError("invalid checksum parsed")
+except ValueError:
+    pass
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+
+    /// Tests driving a locomotive and watching sensors through the mobile bindings.
+    #[tokio::test]
+    #[cfg(feature = "mobile")]
+    async fn mobile_controller() {
+        use crate::mobile::{LayoutListener, MobileController};
+        use crate::simulator::CommandStation;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        struct Sensors(Arc<Mutex<Vec<(u16, bool)>>>);
+
+        impl LayoutListener for Sensors {
+            fn sensor_changed(&self, address: u16, occupied: bool) {
+                self.0.lock().unwrap().push((address, occupied));
+            }
+
+            fn turnout_changed(&self, _address: u16, _straight: bool) {}
+        }
+
+        // The station echoes every frame and answers it like a command station
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(500)).await;
+        let sensor = Message::InputRep(InArg::new(3, SourceType::Switch, SensorLevel::High, false));
+        tokio::spawn(async move {
+            let mut station = CommandStation::new();
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    let mut answers = vec![message];
+                    answers.extend(station.handle(&message));
+                    if message == Message::GpOn {
+                        answers.push(sensor);
+                    }
+                    for answer in answers {
+                        bus.write_all(&answer.to_message()).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        let mobile = MobileController::new(controller);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        mobile.set_listener(Box::new(Sensors(changes.clone())));
+
+        let throttle = mobile.acquire(5).await.unwrap();
+        assert_eq!(throttle.address(), 5);
+        throttle.set_speed(20).await.unwrap();
+        throttle.set_function(5, true).await.unwrap();
+        throttle.set_forward(false).await.unwrap();
+        assert_eq!(throttle.speed(), 20);
+        assert!(throttle.function(5));
+        assert!(!throttle.function(0));
+        assert!(!throttle.forward());
+        assert!(throttle.set_function(9, true).await.is_err());
+
+        mobile.set_power(true).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while mobile.sensor(3).is_none() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(mobile.sensor(3), Some(true));
+        assert_eq!(*changes.lock().unwrap(), vec![(3, true)]);
+    }
+
+    /// Tests parsing WiThrottle commands and driving a locomotive by a throttle app.
+    #[tokio::test]
+    async fn withrottle_server() {
+        use crate::simulator::CommandStation;
+        use crate::withrottle::{parse_command, Action, Command, WiThrottleServer};
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        assert_eq!(parse_command("*+"), Some(Command::Heartbeat(Some(true))));
+        assert_eq!(
+            parse_command("PTATLT12"),
+            Some(Command::Turnout(12, Some(SwitchDirection::Curved)))
+        );
+        assert_eq!(
+            parse_command("MTAL3<;>V-1"),
+            Some(Command::Action(
+                'T',
+                "L3".to_string(),
+                Action::Speed(SpeedArg::EmergencyStop)
+            ))
+        );
+        assert_eq!(
+            parse_command("MTAL3<;>F112"),
+            Some(Command::Action(
+                'T',
+                "L3".to_string(),
+                Action::Press(12, true)
+            ))
+        );
+        assert_eq!(parse_command("MT+X3<;>X3"), None);
+
+        // The station echoes every frame and answers it like a command station
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(500)).await;
+        tokio::spawn(async move {
+            let mut station = CommandStation::new();
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    bus.write_all(&message.to_message()).await.unwrap();
+                    for answer in station.handle(&message) {
+                        bus.write_all(&answer.to_message()).await.unwrap();
+                    }
+                }
+            }
+        });
+        let server = WiThrottleServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .start(&controller);
+
+        let (reader, mut app) = tokio::net::TcpStream::connect(server.local_addr())
+            .await
+            .unwrap()
+            .into_split();
+        let mut lines = BufReader::new(reader).lines();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "VN2.0");
+        while lines.next_line().await.unwrap().unwrap() != "*10" {}
+
+        app.write_all(b"MT+L5<;>L5\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "MT+L5<;>");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "MTAL5<;>V0");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "MTAL5<;>R1");
+        for function in 0..9 {
+            assert_eq!(
+                lines.next_line().await.unwrap().unwrap(),
+                format!("MTAL5<;>F0{}", function)
+            );
+        }
+
+        // Changes are reflected back to the app
+        app.write_all(b"MTAL5<;>V20\nMTAL5<;>F15\nMTAL5<;>F05\nPTACLT12\n")
+            .await
+            .unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "MTAL5<;>V20");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "MTAL5<;>F15");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "PTA2LT12");
+    }
+
+    /// Tests parsing SRCP commands and driving a locomotive by an SRCP client.
+    #[tokio::test]
+    async fn srcp_server() {
+        use crate::simulator::CommandStation;
+        use crate::srcp::{parse_request, Reply, Request, SrcpServer};
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        assert_eq!(
+            parse_request("SET 1 GL 3 1 50 100 1 0"),
+            Ok(Request::SetGl {
+                address: 3,
+                drivemode: 1,
+                speed: 50,
+                max_speed: 100,
+                functions: vec![true, false],
+            })
+        );
+        assert_eq!(
+            parse_request("set 1 ga 12 0 1 200"),
+            Ok(Request::SetGa(
+                12,
+                0,
+                true,
+                Some(Duration::from_millis(200))
+            ))
+        );
+        assert!(matches!(parse_request("GET 2 FB 3"), Err(Reply(412, _))));
+        assert!(matches!(parse_request("GET 1 SM 3"), Err(Reply(421, _))));
+        assert!(matches!(parse_request("SET 1 GL 3"), Err(Reply(419, _))));
+
+        // The station echoes every frame and answers it like a command station
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(500)).await;
+        tokio::spawn(async move {
+            let mut station = CommandStation::new();
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    bus.write_all(&message.to_message()).await.unwrap();
+                    for answer in station.handle(&message) {
+                        bus.write_all(&answer.to_message()).await.unwrap();
+                    }
+                }
+            }
+        });
+        let server = SrcpServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .start(&controller);
+
+        let (reader, mut client) = tokio::net::TcpStream::connect(server.local_addr())
+            .await
+            .unwrap()
+            .into_split();
+        let mut lines = BufReader::new(reader).lines();
+        assert!(lines
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap()
+            .contains("SRCP 0.8"));
+
+        // The time stamp of each reply is skipped
+        for (line, reply) in [
+            ("SET PROTOCOL SRCP 0.8.4", "201 OK PROTOCOL SRCP"),
+            ("SET CONNECTIONMODE SRCP COMMAND", "202 OK CONNECTIONMODE"),
+            ("GO", "200 OK GO 1"),
+            ("GET 1 GL 5", "416 ERROR no data"),
+            ("INIT 1 GL 5 N 1 128 9", "200 OK"),
+            ("SET 1 GL 5 0 20 126 1 0 0 0 0 1", "200 OK"),
+            ("GET 1 GL 5", "100 INFO 1 GL 5 0 20 126 1 0 0 0 0 1 0 0 0"),
+            ("SET 1 GA 12 1 1 -1", "200 OK"),
+            ("GET 1 GA 12 0", "100 INFO 1 GA 12 0 0"),
+            ("TERM 0 SESSION", "200 OK"),
+        ]
+        .iter()
+        {
+            client
+                .write_all(format!("{}\n", line).as_bytes())
+                .await
+                .unwrap();
+            let answer = lines.next_line().await.unwrap().unwrap();
+            assert_eq!(
+                answer.split_once(' ').unwrap().1,
+                *reply,
+                "answering {}",
+                line
+            );
+        }
+        assert!(lines.next_line().await.unwrap().is_none());
+    }
+
+    /// Tests the topics published and the commands accepted by the MQTT bridge.
+    #[test]
+    #[cfg(feature = "mqtt")]
+    fn mqtt_topics() {
+        use crate::mqtt::{parse_command, MqttCommand, MqttPublisher};
+
+        assert_eq!(
+            parse_command("locodrive", "locodrive/loco/5/speed/set", b"40"),
+            Some(MqttCommand::Speed(AddressArg::new(5), SpeedArg::Drive(40)))
+        );
+        assert_eq!(
+            parse_command("locodrive", "locodrive/turnout/12/set", b"curved"),
+            Some(MqttCommand::Turnout(12, SwitchDirection::Curved))
+        );
+        assert_eq!(parse_command("locodrive", "other/power/set", b"ON"), None);
+        assert_eq!(
+            parse_command("locodrive", "locodrive/power/set", b"on"),
+            None
+        );
+
+        let mut publisher = MqttPublisher::default();
+        let topics = |publications: Vec<(String, String)>| {
+            publications
+                .into_iter()
+                .map(|(topic, payload)| format!("{} {}", topic, payload))
+                .collect::<Vec<_>>()
+        };
+        let sensor = Message::InputRep(InArg::new(3, SourceType::Switch, SensorLevel::High, false));
+        assert_eq!(topics(publisher.track("lc", &sensor)), ["lc/sensor/3 ON"]);
+        // Repeated states are not published again
+        assert!(publisher.track("lc", &sensor).is_empty());
+        let switch = Message::SwReq(SwitchArg::new(12, SwitchDirection::Straight, true));
+        assert_eq!(
+            topics(publisher.track("lc", &switch)),
+            ["lc/turnout/12 straight"]
+        );
+        assert_eq!(
+            topics(publisher.track("lc", &LocoSpd(SlotArg::new(4), SpeedArg::Drive(20)))),
+            ["lc/slot/4/speed 20"]
+        );
+        assert_eq!(topics(publisher.track("lc", &GpOn)), ["lc/power ON"]);
+    }
+
+    /// Tests tunneling messages to a z21 central over UDP.
+    #[tokio::test]
+    async fn z21_transport() {
+        use crate::z21::loconet_frames;
+        use tokio::net::UdpSocket;
+
+        // Datasets of other headers are skipped
+        let datagram = [
+            0x08, 0x00, 0x10, 0x00, 0x01, 0x02, 0x03, 0x04, 0x06, 0x00, 0xA0, 0x00, 0x83, 0x7C,
+        ];
+        assert_eq!(loconet_frames(&datagram), [&[0x83, 0x7C][..]]);
+        assert!(loconet_frames(&[0x09, 0x00, 0xA0, 0x00, 0x83]).is_empty());
+
+        let z21 = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let transport = LocoNetTransport::z21(z21.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut datagram = [0; 64];
+        let (len, client) = z21.recv_from(&mut datagram).await.unwrap();
+        assert_eq!(
+            datagram[..len],
+            [0x08, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x0F]
+        );
+
+        let controller = LocoDriveController::builder(transport.name(), 0)
+            .transport(transport)
+            .sending_timeout(500)
+            .build()
+            .await
+            .unwrap();
+        let mut messages = controller.subscribe();
+
+        // Sent messages are echoed by the tunnel
+        controller.send_message(GpOn).await.unwrap();
+        let len = z21.recv(&mut datagram).await.unwrap();
+        assert_eq!(datagram[..len], [0x06, 0x00, 0xA2, 0x00, 0x83, 0x7C]);
+
+        let sensor = Message::InputRep(InArg::new(3, SourceType::Switch, SensorLevel::High, false));
+        let mut received = vec![0x00, 0x00, 0xA0, 0x00];
+        received.extend(sensor.to_message());
+        received[0] = received.len() as u8;
+        z21.send_to(&received, client).await.unwrap();
+        while !matches!(
+            messages.recv().await.unwrap(),
+            LocoDriveMessage::Message(message) if message == sensor
+        ) {}
+    }
+
+    #[test]
+    fn dcc_packets() {
+        use crate::dcc::{DccCommand, DccFunctionGroup, DccPacket};
+        use crate::error::DccError;
+
+        let speed = DccCommand::Speed {
+            address: ImAddress::Long(1234),
+            speed: SpeedArg::Drive(20),
+            forward: true,
+        };
+        let packet = speed.encode().unwrap();
+        assert_eq!(packet.bytes(), [0xC4, 0xD2, 0x3F, 0x95]);
+        assert_eq!(packet.error_byte(), 0xC4 ^ 0xD2 ^ 0x3F ^ 0x95);
+
+        // The packet survives being send as immediate packet
+        let message = packet.to_message(3);
+        let parsed = Message::parse(&message.to_message()).unwrap();
+        assert_eq!(parsed, message);
+        assert_eq!(DccPacket::from_message(&parsed), Some(packet));
+        assert_eq!(packet.decode(), Some(speed));
+
+        let commands = [
+            DccCommand::Functions {
+                address: ImAddress::Short(3),
+                group: DccFunctionGroup::F0to4,
+                functions: 0b10011,
+            },
+            DccCommand::Functions {
+                address: ImAddress::Short(3),
+                group: DccFunctionGroup::F21to28,
+                functions: 0xA5,
+            },
+            DccCommand::Accessory {
+                address: 17,
+                direction: SwitchDirection::Curved,
+                active: true,
+            },
+            DccCommand::WriteCv {
+                address: ImAddress::Long(300),
+                cv: 1024,
+                value: 0xFF,
+            },
+            DccCommand::ExtendedAccessory {
+                address: 17,
+                aspect: 12,
+            },
+        ];
+        for command in commands {
+            let packet = command.encode().unwrap();
+            let message = Message::parse(&packet.to_message(0).to_message()).unwrap();
+            assert_eq!(
+                DccPacket::from_message(&message).unwrap().decode(),
+                Some(command)
+            );
+        }
+        assert_eq!(commands[0].encode().unwrap().bytes(), [0x03, 0x99]);
+        assert_eq!(commands[2].encode().unwrap().bytes(), [0x85, 0xFA]);
+        assert_eq!(commands[4].encode().unwrap().bytes(), [0x85, 0x73, 0x0C]);
+
+        assert_eq!(DccPacket::new(&[0x03]), Err(DccError::InvalidLength(1)));
+        let invalid = DccCommand::WriteCv {
+            address: ImAddress::Short(3),
+            cv: 0,
+            value: 1,
+        };
+        assert_eq!(invalid.encode(), Err(DccError::InvalidCv(0)));
+        let invalid = DccCommand::ExtendedAccessory {
+            address: 17,
+            aspect: 32,
+        };
+        assert_eq!(invalid.encode(), Err(DccError::InvalidAspect(32)));
+    }
+
+    #[test]
+    #[cfg(feature = "jmri")]
+    fn jmri_json() {
+        use crate::error::JmriError;
+        use crate::jmri::JmriJson;
+        use crate::manager::SlotState;
+
+        let turnout = SwitchArg::new(11, SwitchDirection::Curved, true);
+        let json = turnout.to_jmri_json();
+        assert_eq!(
+            json,
+            r#"{"type":"turnout","data":{"name":"LT12","state":4}}"#
+        );
+        assert_eq!(SwitchArg::from_jmri_json(&json).unwrap(), turnout);
+
+        let sensor = InArg::new(4, SourceType::Switch, SensorLevel::High, false);
+        let json = sensor.to_jmri_json();
+        assert_eq!(
+            json,
+            r#"{"type":"sensor","data":{"name":"LS10","state":2}}"#
+        );
+        assert_eq!(InArg::from_jmri_json(&json).unwrap(), sensor);
+
+        // Fields not used by the crate are skipped
+        let throttle = SlotState::from_jmri_json(
+            r#"{"type":"throttle","data":{"throttle":"cab","address":3,"speed":0.5,"forward":false,"F0":true,"F6":true,"clients":1}}"#,
+        )
+        .unwrap();
+        assert_eq!(throttle.address, Some(AddressArg::new(3)));
+        assert_eq!(throttle.speed, Some(SpeedArg::Drive(63)));
+        assert_eq!(
+            throttle.dirf,
+            Some(DirfArg::new(false, true, false, false, false, false))
+        );
+        assert_eq!(throttle.snd, Some(SndArg::new(false, true, false, false)));
+        assert_eq!(
+            SlotState::from_jmri_json(&throttle.to_jmri_json()).unwrap(),
+            throttle
+        );
+
+        assert!(matches!(
+            SwitchArg::from_jmri_json(r#"{"type":"sensor","data":{"name":"LS1","state":2}}"#),
+            Err(JmriError::UnexpectedType(_))
+        ));
+        assert!(matches!(
+            InArg::from_jmri_json(r#"{"type":"sensor","data":{"name":"LS1","state":0}}"#),
+            Err(JmriError::InvalidState(0))
+        ));
+        assert!(matches!(
+            SwitchArg::from_jmri_json(r#"{"type":"turnout","data":{"name":"IT1","state":2}}"#),
+            Err(JmriError::InvalidName(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "config")]
+    async fn inventory_scan() {
+        use crate::inventory::{Inventory, InventoryScanner, InventorySensor, InventoryTurnout};
+        use crate::simulator::CommandStation;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::time::Duration;
+
+        // The station echoes every frame, answers it and reports a sensor when interrogated
+        let (controller, mut bus) = memory_controller(|builder| builder.sending_timeout(500)).await;
+        let sensor = InArg::new(3, SourceType::Switch, SensorLevel::High, false);
+        tokio::spawn(async move {
+            let mut station = CommandStation::new();
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    let mut answers = vec![message];
+                    answers.extend(station.handle(&message));
+                    if matches!(message, Message::SwReq(switch) if switch.address() == 1016) {
+                        answers.push(Message::InputRep(sensor));
+                    }
+                    for answer in answers {
+                        bus.write_all(&answer.to_message()).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        controller
+            .send_message(Message::LocoAdr(AddressArg::new(1234)))
+            .await
+            .unwrap();
+        controller
+            .send_message(Message::SwReq(SwitchArg::new(
+                7,
+                SwitchDirection::Curved,
+                true,
+            )))
+            .await
+            .unwrap();
+
+        let inventory = InventoryScanner::new(&controller)
+            .slots(1..=4)
+            .turnouts(7..=7)
+            .pace(Duration::from_millis(5))
+            .settle(Duration::from_millis(100))
+            .scan()
+            .await
+            .unwrap();
+        assert_eq!(inventory.locos.len(), 1);
+        assert_eq!(inventory.locos[0].address, 1234);
+        // The interrogation is no turnout
+        assert_eq!(
+            inventory.turnouts,
+            [InventoryTurnout {
+                address: 7,
+                direction: SwitchDirection::Curved
+            }]
+        );
+        assert_eq!(
+            inventory.sensors,
+            [InventorySensor {
+                address: 3,
+                level: SensorLevel::High
+            }]
+        );
+        assert!(inventory
+            .to_xml()
+            .contains("<turnout address=\"7\" direction=\"curved\"/>"));
+
+        let path = std::env::temp_dir().join(format!("inventory-{}.toml", std::process::id()));
+        inventory.save(&path).unwrap();
+        assert_eq!(Inventory::load(&path).unwrap(), inventory);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Tests the sensor configuration is applied before the level changes are reported.
+    #[test]
+    fn sensor_config() {
+        use crate::manager::{SensorChange, SensorConfig, SensorManager};
+
+        let mut sensors = SensorManager::new();
+        sensors.configure(
+            4,
+            SensorConfig {
+                debounce: Duration::from_millis(50),
+                inverted: true,
+                source: Some(SourceType::Ds54Aux),
+            },
+        );
+        let report =
+            |address, source, level| Message::InputRep(InArg::new(address, source, level, false));
+        let now = std::time::Instant::now();
+
+        // Unconfigured sensors change at once
+        assert_eq!(
+            sensors.update(&report(5, SourceType::Switch, SensorLevel::High), now),
+            vec![SensorChange {
+                address: 5,
+                level: SensorLevel::High
+            }]
+        );
+
+        // The switch input of the configured sensor is ignored
+        sensors.update(&report(4, SourceType::Switch, SensorLevel::High), now);
+        assert_eq!(sensors.next_deadline(), None);
+
+        // The inverted level awaits its debounce time, which restarts on bouncing
+        let later = |millis| now + Duration::from_millis(millis);
+        assert!(sensors
+            .update(&report(4, SourceType::Ds54Aux, SensorLevel::Low), now)
+            .is_empty());
+        assert!(sensors
+            .update(
+                &report(4, SourceType::Ds54Aux, SensorLevel::High),
+                later(20)
+            )
+            .is_empty());
+        assert!(sensors
+            .update(&report(4, SourceType::Ds54Aux, SensorLevel::Low), later(30))
+            .is_empty());
+        assert_eq!(sensors.next_deadline(), Some(later(80)));
+        assert!(sensors.poll(later(60)).is_empty());
+        assert_eq!(
+            sensors.poll(later(80)),
+            vec![SensorChange {
+                address: 4,
+                level: SensorLevel::High
+            }]
+        );
+        assert_eq!(sensors.level(4), Some(SensorLevel::High));
+
+        // Reporting the accepted level again cancels a pending change
+        sensors.update(
+            &report(4, SourceType::Ds54Aux, SensorLevel::High),
+            later(90),
+        );
+        sensors.update(
+            &report(4, SourceType::Ds54Aux, SensorLevel::Low),
+            later(100),
+        );
+        assert_eq!(sensors.next_deadline(), None);
+        assert!(sensors.poll(later(200)).is_empty());
+    }
+
+    /// Tests the throws of one decoder are paced and each output is switched off after its pulse.
+    #[tokio::test]
+    async fn turnout_pacing() {
+        use crate::turnouts::TurnoutDriver;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::mpsc::unbounded_channel;
+
+        // The bus echoes every frame and notes when it was written
+        let (controller, mut bus) = memory_controller(|builder| builder).await;
+        let (written, mut frames) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    let _ = written.send((message, Instant::now()));
+                    bus.write_all(&message.to_message()).await.unwrap();
+                }
+            }
+        });
+
+        let turnouts = TurnoutDriver::new(&controller)
+            .pulse(Some(Duration::from_millis(20)))
+            .cooldown(Duration::from_millis(100));
+        assert_eq!(turnouts.decoder(5), 1);
+
+        // The turnouts 0 and 1 share a decoder, the turnout 4 has its own
+        let start = Instant::now();
+        turnouts.throw(0, SwitchDirection::Curved).await.unwrap();
+        turnouts.throw(4, SwitchDirection::Curved).await.unwrap();
+        turnouts.throw(1, SwitchDirection::Straight).await.unwrap();
+
+        let switch =
+            |address, direction, on| Message::SwReq(SwitchArg::new(address, direction, on));
+        let mut sent = Vec::new();
+        while sent.len() < 6 {
+            sent.push(frames.recv().await.unwrap());
+        }
+        let messages: Vec<Message> = sent.iter().map(|(message, _)| *message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                switch(0, SwitchDirection::Curved, true),
+                switch(4, SwitchDirection::Curved, true),
+                switch(0, SwitchDirection::Curved, false),
+                switch(4, SwitchDirection::Curved, false),
+                switch(1, SwitchDirection::Straight, true),
+                switch(1, SwitchDirection::Straight, false),
+            ]
+        );
+        assert!(sent[2].1 >= sent[0].1 + Duration::from_millis(20));
+        assert!(sent[4].1 >= start + Duration::from_millis(100));
+    }
+
+    /// Tests setting the aspects of signals and recognizing them from the bus.
+    #[tokio::test]
+    async fn signal_aspects() {
+        use crate::error::SignalError;
+        use crate::manager::Manager;
+        use crate::signals::{Aspect, AspectOutput, Signal, SignalSetter, SignalTable};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut table = SignalTable::new();
+        table.add(
+            Signal::new("A1")
+                .aspect(
+                    Aspect::Stop,
+                    AspectOutput::Switches(vec![(40, SwitchDirection::Curved)]),
+                )
+                .aspect(
+                    Aspect::Proceed,
+                    AspectOutput::Switches(vec![
+                        (40, SwitchDirection::Straight),
+                        (41, SwitchDirection::Straight),
+                    ]),
+                )
+                .aspect(
+                    Aspect::Approach,
+                    AspectOutput::Switches(vec![
+                        (40, SwitchDirection::Straight),
+                        (41, SwitchDirection::Curved),
+                    ]),
+                ),
+        );
+        table.add(Signal::new("B2").aspect(
+            Aspect::Approach,
+            AspectOutput::Extended {
+                address: 60,
+                aspect: 4,
+            },
+        ));
+
+        // Aspects are recognized once all their outputs are switched
+        let switch = |address, direction| Message::SwReq(SwitchArg::new(address, direction, true));
+        table.handle(&switch(40, SwitchDirection::Straight));
+        assert_eq!(table.aspect("A1"), None);
+        table.handle(&switch(41, SwitchDirection::Curved));
+        assert_eq!(table.aspect("A1"), Some(Aspect::Approach));
+        table.handle(&switch(40, SwitchDirection::Curved));
+        assert_eq!(table.aspect("A1"), Some(Aspect::Stop));
+
+        // The bus echoes every frame and notes it
+        let (controller, mut bus) = memory_controller(|builder| builder).await;
+        let (written, mut frames) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let mut read = [0; 32];
+            while let Ok(len) = bus.read(&mut read).await {
+                buf.extend_from_slice(&read[..len]);
+                while let Ok(message) = Message::parse(&buf) {
+                    buf.drain(..message.encoded_len());
+                    let _ = written.send(message);
+                    bus.write_all(&message.to_message()).await.unwrap();
+                }
+            }
+        });
+
+        let signals = SignalSetter::new(&controller, table);
+        signals.set_aspect("A1", Aspect::Proceed).await.unwrap();
+        assert_eq!(
+            frames.recv().await,
+            Some(switch(40, SwitchDirection::Straight))
+        );
+        assert_eq!(
+            frames.recv().await,
+            Some(switch(41, SwitchDirection::Straight))
+        );
+        assert_eq!(signals.aspect("A1"), Some(Aspect::Proceed));
+
+        signals.set_aspect("B2", Aspect::Approach).await.unwrap();
+        let packet = frames.recv().await.unwrap();
+        let mut observed = SignalTable::new();
+        observed.add(signals.table().signal("B2").unwrap().clone());
+        observed.handle(&packet);
+        assert_eq!(observed.aspect("B2"), Some(Aspect::Approach));
+
+        assert!(matches!(
+            signals.set_aspect("B2", Aspect::Stop).await,
+            Err(SignalError::UnknownAspect(_, Aspect::Stop))
+        ));
+        assert!(matches!(
+            signals.set_aspect("C3", Aspect::Stop).await,
+            Err(SignalError::UnknownSignal(_))
+        ));
+    }
+
+    /// Tests trains are stopped and slowed down by the signals protecting occupied blocks.
+    #[test]
+    fn block_control() {
+        use crate::block_control::{BlockControl, ControlAction};
+        use crate::occupancy::{BlockOccupancy, Detector};
+        use crate::signals::Aspect;
+
+        let mut occupancy = BlockOccupancy::new().debounce(Duration::ZERO);
+        occupancy.add_block("a", [Detector::Sensor(2)]);
+        occupancy.add_block("b", [Detector::Sensor(4)]);
+        occupancy.add_block("c", [Detector::Sensor(6)]);
+        let mut control = BlockControl::new(occupancy).approach_speed(20);
+        for (block, signal) in [("a", "A"), ("b", "B"), ("c", "C")] {
+            control.add_block(block, Some(signal));
+        }
+        control.add_transition("a", "b");
+        control.add_transition("b", "c");
+
+        let aspect = |signal: &str, aspect| ControlAction::Aspect(signal.to_string(), aspect);
+        let slot = SlotArg::new(3);
+        let sensor = |address, level| {
+            Message::InputRep(InArg::new(address, SourceType::Ds54Aux, level, false))
+        };
+        let now = Instant::now();
+
+        assert_eq!(
+            control.place("a", slot, SpeedArg::Drive(80)),
+            vec![
+                aspect("A", Aspect::Stop),
+                aspect("B", Aspect::Proceed),
+                aspect("C", Aspect::Proceed),
+                ControlAction::Speed(slot, SpeedArg::Drive(80)),
+            ]
+        );
+
+        // Another train parks in the block after next
+        assert_eq!(
+            control.handle(&sensor(3, SensorLevel::High), now),
+            vec![
+                aspect("B", Aspect::Approach),
+                aspect("C", Aspect::Stop),
+                ControlAction::Speed(slot, SpeedArg::Drive(20)),
+            ]
+        );
+
+        // The train enters the next block and stops before the occupied one
+        assert_eq!(
+            control.handle(&sensor(2, SensorLevel::High), now),
+            vec![
+                aspect("A", Aspect::Approach),
+                aspect("B", Aspect::Stop),
+                ControlAction::Speed(slot, SpeedArg::Stop),
+            ]
+        );
+        assert_eq!(control.block_of(slot), Some("b"));
+
+        // The line is clear again
+        assert_eq!(
+            control.handle(&sensor(3, SensorLevel::Low), now),
+            vec![
+                aspect("C", Aspect::Proceed),
+                ControlAction::Speed(slot, SpeedArg::Drive(80)),
+            ]
+        );
+        assert!(control.handle(&sensor(3, SensorLevel::Low), now).is_empty());
+    }
+
+    /// Tests describing messages and events by the names of a name registry.
+    #[test]
+    fn name_registry() {
+        use crate::names::{Describe, NameRegistry};
+        use crate::traffic::TrafficKey;
+
+        let mut names = NameRegistry::new();
+        names
+            .name_turnout(57, "Yard West #12")
+            .metadata
+            .insert("station".to_string(), "Yard".to_string());
+        names.name_sensor(12, "Platform 1");
+        names.name_loco(218, "BR 218");
+
+        let switch = SwitchArg::new(57, SwitchDirection::Straight, false);
+        assert_eq!(
+            Message::SwReq(switch).describe(&names),
+            "SwReq Yard West #12 straight off"
+        );
+        let sensor = InArg::new(6, SourceType::Ds54Aux, SensorLevel::High, false);
+        assert_eq!(
+            Message::InputRep(sensor).describe(&names),
+            "InputRep Platform 1 high"
+        );
+        assert_eq!(
+            Message::LocoAdr(AddressArg::new(218)).describe(&names),
+            "LocoAdr BR 218"
+        );
+        assert_eq!(
+            LocoDriveMessage::ChattyDevice(TrafficKey::Sensor(12), 40).describe(&names),
+            "Platform 1 is chatty with 40 frames"
+        );
+
+        // Unnamed objects are described by their address
+        let unnamed = Message::SwReq(SwitchArg::new(58, SwitchDirection::Curved, true));
+        assert_eq!(unnamed.describe(&names), format!("{:?}", unnamed));
+        assert_eq!(TrafficKey::Switch(58).describe(&names), "switch 58");
+
+        // A new name replaces the old one with its metadata
+        names.name_turnout(57, "Yard West #13");
+        assert!(names.turnout_alias(57).unwrap().metadata.is_empty());
+        assert_eq!(names.turnout(57), "Yard West #13");
+
+        #[cfg(feature = "config")]
+        for file in ["names.toml", "names.ron"] {
+            names
+                .name_turnout(57, "Yard West #12")
+                .metadata
+                .insert("station".to_string(), "Yard".to_string());
+            let path =
+                std::env::temp_dir().join(format!("locodrive-{}-{}", std::process::id(), file));
+            names.save(&path).unwrap();
+            assert_eq!(NameRegistry::load(&path).unwrap(), names);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    /// Tests the outcome of every send command is recorded to the audit sink.
+    #[tokio::test]
+    async fn command_audit() {
+        use crate::audit::{AuditOutcome, AuditSink, WriteSink};
+        use crate::error::LocoDriveSendingError;
+        use crate::loco_controller::SendOptions;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (sink, mut records) = tokio::sync::mpsc::unbounded_channel();
+        let (controller, mut bus) =
+            memory_controller(|builder| builder.sending_timeout(100).audit_sink(sink)).await;
+
+        // The station echoes the power on and acknowledges the switch request,
+        // but never echoes the power off
+        let request = Message::SwReq(SwitchArg::new(1, SwitchDirection::Straight, true));
+        let station = tokio::spawn(async move {
+            let mut frame = [0; 2];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            bus.read_exact(&mut frame).await.unwrap();
+            let mut frame = [0; 4];
+            bus.read_exact(&mut frame).await.unwrap();
+            bus.write_all(&frame).await.unwrap();
+            let ack = Message::LongAck(LopcArg::new(0xB0), Ack1Arg::new(true));
+            bus.write_all(&ack.to_message()).await.unwrap();
+            bus
+        });
+        controller.send_message(Message::GpOn).await.unwrap();
+        assert!(controller.send_message(Message::GpOff).await.is_err());
+        let options = SendOptions {
+            require_ack: true,
+            ..SendOptions::default()
+        };
+        controller
+            .send_message_acked(request, options)
+            .await
+            .unwrap();
+        let _bus = station.await.unwrap();
+        // Writing the reserved slots is blocked, also for command handles
+        assert!(controller
+            .command_handle()
+            .send_message(Message::SlotStat1(
+                SlotArg::new(123),
+                Stat1Arg::new(false, Consist::Free, State::Free, DecoderType::Dcc128)
+            ))
+            .await
+            .is_err());
+
+        let mut outcomes = Vec::new();
+        while let Ok(record) = records.try_recv() {
+            outcomes.push((record.message, record.outcome));
+        }
+        assert!(matches!(
+            outcomes.as_slice(),
+            [
+                (Message::GpOn, AuditOutcome::Sent),
+                (Message::GpOff, AuditOutcome::TimedOut),
+                (Message::SwReq(_), AuditOutcome::Acked(_)),
+                (
+                    Message::SlotStat1(..),
+                    AuditOutcome::Failed(LocoDriveSendingError::ReservedSlot(_))
+                ),
+            ]
+        ));
+
+        // The records are written as one line each
+        let mut sink = WriteSink::new(Vec::new());
+        let record = crate::audit::AuditRecord {
+            message: Message::GpOn,
+            requested_at: std::time::UNIX_EPOCH + Duration::from_millis(1500),
+            duration: Duration::from_millis(12),
+            outcome: AuditOutcome::Sent,
+        };
+        sink.record(&record);
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "at=1.500 duration_ms=12 outcome=\"sent\" message=\"GpOn\"\n"
+        );
+    }
+
+    /// Tests the import of a Rocrail plan.
+    #[test]
+    #[cfg(feature = "rocrail")]
+    fn rocrail_import() {
+        let layout = crate::rocrail::import_plan(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <plan title="test">
+              <lclist>
+                <lc id="BR 218" addr="218" spcnt="128"/>
+                <lc id="unset" addr="0"/>
+              </lclist>
+              <swlist>
+                <sw id="sw1" addr1="2" port1="3"/>
+                <sw id="sw2" addr1="12" port1="0"/>
+              </swlist>
+              <fblist>
+                <fb id="fb1" addr="1"/>
+              </fblist>
+              <bklist>
+                <bk id="bk1">
+                  <fbevent id="fb1" action="enter"/>
+                  <fbevent id="fb1" action="in"/>
+                </bk>
+              </bklist>
+            </plan>"#,
+        )
+        .unwrap();
+
+        assert_eq!(layout.roster.locos().len(), 1);
+        assert_eq!(
+            layout.roster.by_address(218).unwrap().speed_steps,
+            Some(128)
+        );
+        assert_eq!(layout.turnout("sw1").unwrap().address, 6);
+        assert_eq!(layout.turnout("sw2").unwrap().address, 11);
+        assert_eq!(layout.sensor("fb1").unwrap().address, 0);
+        assert_eq!(
+            layout.block("bk1").unwrap().sensors,
+            vec!["fb1".to_string()]
+        );
+
+        assert!(crate::rocrail::import_plan(r#"<plan><fb id="fb1" addr="x"/></plan>"#).is_err());
+    }
+
+    #[test]
+    fn frame_counting() {
+        use crate::discovery::count_frames;
+
+        // GpOn, noise, SlRdData cut off
+        assert_eq!(count_frames(&[0x83, 0x7C, 0x00, 0x12, 0x83]), 1);
+        // RqSlData with wrong checksum, then Idle
+        assert_eq!(count_frames(&[0xBB, 0x00, 0x00, 0x00, 0x85, 0x7A]), 1);
+        assert_eq!(count_frames(&[]), 0);
+    }
+
+    #[test]
+    fn reserved_slots() {
+        assert!(!SlotArg::new(119).is_reserved());
+        assert!(SlotArg::new(120).is_reserved());
+        assert!(SlotArg::new(123).is_reserved());
+        assert!(SlotArg::new(126).is_reserved());
+        assert!(!SlotArg::new(127).is_reserved());
+    }
+
+    #[test]
+    fn refresh_consolidation() {
+        use crate::refresh::RefreshConsolidator;
+
+        let consolidator = RefreshConsolidator::new(Duration::from_secs(1));
+        let start = Instant::now();
+
+        assert!(consolidator.admit_at(SlotArg::new(3), start));
+        // A second client refreshing the same slot is dropped, other slots are not affected
+        assert!(!consolidator.admit_at(SlotArg::new(3), start + Duration::from_millis(200)));
+        assert!(consolidator.admit_at(SlotArg::new(4), start + Duration::from_millis(200)));
+        assert!(consolidator.admit_at(SlotArg::new(3), start + Duration::from_secs(1)));
+
+        consolidator.forget(SlotArg::new(4));
+        assert!(consolidator.admit_at(SlotArg::new(4), start + Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn latency_distribution() {
+        use crate::load_test::LatencyDistribution;
+
+        let latencies =
+            LatencyDistribution::new((1..=100).rev().map(Duration::from_millis).collect());
+
+        assert_eq!(latencies.min(), Some(Duration::from_millis(1)));
+        assert_eq!(latencies.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(latencies.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(latencies.max(), Some(Duration::from_millis(100)));
+        assert_eq!(latencies.mean(), Some(Duration::from_micros(50_500)));
+        assert_eq!(LatencyDistribution::default().percentile(50.0), None);
+    }
+
+    #[test]
+    #[cfg(feature = "config")]
+    fn config() {
+        use crate::config::{FlowControlConfig, LocodriveConfig};
+        use crate::loco_controller::EchoPolicy;
+
+        let config = LocodriveConfig::from_toml_str(
+            r#"
+            [connection]
+            port = "/dev/ttyUSB0"
+            flow_control = "hardware"
+
+            [features]
+            echo_policy = "optional"
+            idle_after_ms = 1000
+
+            [endpoints]
+            mqtt = "localhost:1883"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.connection.baud_rate, 115_200);
+        assert_eq!(config.connection.flow_control, FlowControlConfig::Hardware);
+        assert_eq!(config.timeouts.sending_ms, 5000);
+        assert_eq!(config.features.echo_policy, EchoPolicy::Optional);
+        assert_eq!(config.connection.adapter, "generic");
+        assert!(config.managers.slots);
+        assert!(config.load_layout().unwrap().roster.locos().is_empty());
+
+        let ron = LocodriveConfig::from_ron_str(
+            r#"(connection: (port: "/dev/ttyUSB0"), features: (idle_after_ms: Some(1000), echo_policy: optional), endpoints: (mqtt: Some("localhost:1883")))"#,
+        )
+        .unwrap();
+        assert_eq!(ron.features, config.features);
+
+        // The port is required and endpoints need a port
+        assert!(LocodriveConfig::from_toml_str("[connection]\nbaud_rate = 57600").is_err());
+        assert!(LocodriveConfig::from_toml_str(
+            "[connection]\nport = \"COM3\"\nadapter = \"unknown\""
+        )
+        .is_err());
+        assert!(LocodriveConfig::from_toml_str(
+            "[connection]\nport = \"COM3\"\n[endpoints]\ntcp = \"localhost\""
+        )
+        .is_err());
+        assert!(
+            LocodriveConfig::from_toml_str("[connection]\nport = \"COM3\"\nspeed = 1").is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hotplug")]
+    fn hotplug_matching() {
+        use crate::hotplug::{HotplugWatcher, InterfaceInfo, InterfaceMatch};
+
+        let usb = InterfaceInfo {
+            port_name: "/dev/ttyACM0".to_string(),
+            vid: Some(0x04D8),
+            pid: Some(0x000A),
+            manufacturer: Some("Digitrax".to_string()),
+            product: Some("Digitrax PR3".to_string()),
+        };
+        let other = InterfaceInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            vid: None,
+            pid: None,
+            manufacturer: None,
+            product: None,
+        };
+
+        let watcher = HotplugWatcher::new(LocoDriveController::builder("", 115_200));
+        assert!(watcher.recognizes(&usb));
+        assert!(!watcher.recognizes(&other));
+
+        assert!(InterfaceMatch::Usb(0x04D8, 0x000A).matches(&usb));
+        assert!(!InterfaceMatch::Usb(0x0403, 0x6001).matches(&usb));
+        assert!(InterfaceMatch::Product("pr3".to_string()).matches(&usb));
+        assert!(InterfaceMatch::PortName("/dev/ttyS0".to_string()).matches(&other));
+    }
+
+    #[test]
+    #[cfg(feature = "hotplug")]
+    fn adapter_detection() {
+        use crate::adapter::{AdapterProfile, AdapterStep};
+        use crate::hotplug::{HotplugWatcher, InterfaceInfo};
+        use tokio_serial::FlowControl;
+
+        let intellibox = InterfaceInfo {
+            port_name: "/dev/ttyACM0".to_string(),
+            vid: None,
+            pid: None,
+            manufacturer: Some("Uhlenbrock Elektronik".to_string()),
+            product: Some("USB Intellibox II".to_string()),
+        };
+        let watcher = HotplugWatcher::new(LocoDriveController::builder("", 57_600));
+        assert!(watcher.recognizes(&intellibox));
+
+        let adapter = AdapterProfile::detect(&intellibox);
+        assert_eq!(adapter.name(), "intellibox");
+        assert_eq!(adapter.fixed_baud_rate(), Some(115_200));
+        assert_eq!(adapter.fixed_flow_control(), Some(FlowControl::Hardware));
+        assert!(!adapter.has_echo());
+        assert!(matches!(
+            adapter.steps().last(),
+            Some(AdapterStep::AwaitCts(_))
+        ));
+        assert_eq!(
+            AdapterProfile::by_name("intellibox").unwrap().name(),
+            "intellibox"
+        );
+
+        let unknown = InterfaceInfo {
+            manufacturer: None,
+            product: Some("USB Serial".to_string()),
+            ..intellibox
+        };
+        assert_eq!(AdapterProfile::detect(&unknown).name(), "generic");
+        assert!(AdapterProfile::generic().has_echo());
+    }
+
+    /// Reads bytewise from port. This is for testing purposes only.
+    #[allow(dead_code)]
+    async fn test_reading() {
+        let mut port = match tokio_serial::new("/dev/ttyUSB0", 115_200)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::Two)
+            .parity(Parity::None)
+            .flow_control(FlowControl::None)
+            .open_native_async()
+        {
+            Ok(port) => port,
+            Err(_) => {
+                return;
+            }
+        };
+
+        loop {
+            let mut buf = vec![0u8; 1];
+
+            let opc = port.read_exact(&mut buf).await;
+            let byte = match opc {
+                Ok(_) => buf[0],
+                Err(_) => {
+                    return;
+                }
+            };
+
+            print!("{:02x?} ", byte);
+            stdout().flush().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_sending() {
+        println!("Start test!");
+
+        for port in tokio_serial::available_ports().unwrap() {
+            println!("Port: {:?}", port)
+        }
+
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(10);
+
+        println!("Try to connect to port!");
+
+        let loco_controller = match LocoDriveController::new(
+            "/dev/ttyUSB0",
+            115_200,
+            50000,
+            FlowControl::None,
+            sender,
+            false,
+        )
+        .await
+        {
+            Ok(loco_controller) => loco_controller,
+            Err(err) => {
+                eprintln!("Error: Could not connect to the serial port! {:?}", err);
+                println!();
+                return;
+            }
+        };
+
+        let _m = Message::parse(
+            Message::SwReq(SwitchArg::new(15, SwitchDirection::Curved, true))
+                .to_message()
+                .as_slice(),
+        )
+        .unwrap();
+
+        println!(
+            "Message: {:?}",
+            Message::SwReq(SwitchArg::new(15, SwitchDirection::Curved, true)).to_message()
+        );
+
+        loco_controller.send_message(GpOn).await.unwrap();
+
+        println!("Setup test train");
+
+        let adr = AddressArg::new(5);
+
+        let mut slot_adr_map: HashMap<AddressArg, SlotArg> = HashMap::new();
+
+        match loco_controller.send_message(Message::LocoAdr(adr)).await {
+            Ok(()) => {}
+            Err(err) => {
+                eprintln!("Message was not send! {:?}", err);
+                println!();
+                exit(1)
+            }
+        };
+
+        loop {
+            match receiver.recv().await {
+                Ok(message) => match message {
+                    LocoDriveMessage::Message(message) => {
+                        if let Message::SlRdData(slot, _, address, ..) = message {
+                            slot_adr_map.insert(address, slot);
+                            println!("Added {:?}, {:?} to {:?}", address, slot, slot_adr_map);
+                            break;
+                        }
+                    }
+                    LocoDriveMessage::Answer(_, _) => {}
+                    LocoDriveMessage::AnswerTimeout(_) => {}
+                    LocoDriveMessage::Echo(_) => {}
+                    LocoDriveMessage::Sent(_, _) => {}
+                    LocoDriveMessage::SlotPurged(_) => {}
+                    LocoDriveMessage::VendorBytes(_, _) => {}
+                    LocoDriveMessage::Transaction(_) => {}
+                    LocoDriveMessage::BusIdle(_) | LocoDriveMessage::BusResumed => {}
+                    LocoDriveMessage::Health(_) => {}
+                    LocoDriveMessage::LineNoise(_) => {}
+                    LocoDriveMessage::ChattyDevice(_, _) => {}
+                    LocoDriveMessage::Error(err) => {
+                        eprintln!("Message could not be read! {:?}", err);
+                        exit(1)
+                    }
+                    LocoDriveMessage::SerialPortError(err) => {
+                        eprintln!("Connection refused! {:?}", err);
+                        exit(1)
+                    }
+                },
+                Err(err) => {
+                    println!("WHAT? {:?}", err);
+                }
+            }
+        }
+
+        println!("Known Trains: {:?}", slot_adr_map);
+
+        for i in 1..3 {
+            println!("Drive round {}", i);
+
+            if i % 2 == 0 {
+                loco_controller
+                    .send_message(Message::SwReq(SwitchArg::new(
+                        15,
+                        SwitchDirection::Straight,
+                        true,
+                    )))
+                    .await
+                    .unwrap();
+                loco_controller
+                    .send_message(Message::SwReq(SwitchArg::new(
+                        18,
+                        SwitchDirection::Straight,
+                        true,
+                    )))
+                    .await
+                    .unwrap();
+            } else {
+                loco_controller
+                    .send_message(Message::SwReq(SwitchArg::new(
+                        15,
+                        SwitchDirection::Curved,
+                        true,
+                    )))
+                    .await
+                    .unwrap();
+                loco_controller
+                    .send_message(Message::SwReq(SwitchArg::new(
+                        18,
+                        SwitchDirection::Curved,
+                        true,
+                    )))
+                    .await
+                    .unwrap();
+            }
+
+            loco_controller
+                .send_message(LocoSpd(
+                    *slot_adr_map.get(&adr).unwrap(),
+                    SpeedArg::Drive(100),
+                ))
+                .await
+                .unwrap();
+
+            let mut waiting = true;
 
             while let Ok(message) = receiver.recv().await {
                 match message {
-                    LocoDriveMessage::Message(message) => match message {
-                        Message::InputRep(in_arg) => {
-                            if i % 2 == 0
+                    LocoDriveMessage::Message(message) => {
+                        if let Message::InputRep(in_arg) = message {
+                            if (i % 2 == 0
                                 && in_arg.address() == 3
                                 && in_arg.input_source() == SourceType::Switch
-                                && in_arg.sensor_level() == SensorLevel::High
+                                && in_arg.sensor_level() == SensorLevel::High)
+                                || (i % 2 == 1
+                                    && in_arg.address() == 8
+                                    && in_arg.input_source() == SourceType::Switch
+                                    && in_arg.sensor_level() == SensorLevel::High)
                             {
                                 waiting = false;
                                 loco_controller
@@ -389,36 +4470,26 @@ mod tests {
                                     ))
                                     .await
                                     .unwrap();
-                            } else if i % 2 == 1
-                                && in_arg.address() == 8
-                                && in_arg.input_source() == SourceType::Switch
-                                && in_arg.sensor_level() == SensorLevel::High
-                            {
-                                waiting = false;
-                                loco_controller
-                                    .send_message(LocoSpd(
-                                        *slot_adr_map.get(&adr).unwrap(),
-                                        SpeedArg::Drive(50),
-                                    ))
-                                    .await
-                                    .unwrap();
-                            } else if !waiting
-                                && in_arg.address() == 8
-                                && in_arg.input_source() == SourceType::Ds54Aux
-                                && in_arg.sensor_level() == SensorLevel::Low
-                            {
-                                break;
                             } else if !waiting
-                                && in_arg.address() == 1
+                                && (in_arg.address() == 8 || in_arg.address() == 1)
                                 && in_arg.input_source() == SourceType::Ds54Aux
                                 && in_arg.sensor_level() == SensorLevel::Low
                             {
                                 break;
                             }
                         }
-                        _ => {}
-                    },
+                    }
                     LocoDriveMessage::Answer(_, _) => {}
+                    LocoDriveMessage::AnswerTimeout(_) => {}
+                    LocoDriveMessage::Echo(_) => {}
+                    LocoDriveMessage::Sent(_, _) => {}
+                    LocoDriveMessage::SlotPurged(_) => {}
+                    LocoDriveMessage::VendorBytes(_, _) => {}
+                    LocoDriveMessage::Transaction(_) => {}
+                    LocoDriveMessage::BusIdle(_) | LocoDriveMessage::BusResumed => {}
+                    LocoDriveMessage::Health(_) => {}
+                    LocoDriveMessage::LineNoise(_) => {}
+                    LocoDriveMessage::ChattyDevice(_, _) => {}
                     LocoDriveMessage::Error(err) => {
                         eprintln!("Message could not be read! {:?}", err);
                         exit(1)
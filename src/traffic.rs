@@ -0,0 +1,241 @@
+use crate::message_ref::MessageRef;
+use crate::protocol::Message;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use tokio::time::{Duration, Instant};
+
+/// What the traffic on the bus is counted for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum TrafficKey {
+    /// All frames with this op code.
+    Opcode(u8),
+    /// The frames addressing this slot, see [`MessageRef::slot()`].
+    Slot(u8),
+    /// The [`Message::InputRep`]s of the sensor with this [`crate::args::InArg::address_ds54()`].
+    Sensor(u16),
+    /// The frames addressing the switch with this address, see [`MessageRef::switch()`].
+    Switch(u16),
+    /// The [`Message::MultiSense`] reports of the board with this address.
+    Board(u8),
+}
+
+impl TrafficKey {
+    /// # Returns
+    ///
+    /// If the key names one device on the bus, so everything but [`TrafficKey::Opcode`].
+    pub fn is_device(&self) -> bool {
+        !matches!(self, TrafficKey::Opcode(_))
+    }
+}
+
+impl Display for TrafficKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrafficKey::Opcode(opc) => write!(f, "opcode 0x{:02X}", opc),
+            TrafficKey::Slot(slot) => write!(f, "slot {}", slot),
+            TrafficKey::Sensor(address) => write!(f, "sensor {}", address),
+            TrafficKey::Switch(address) => write!(f, "switch {}", address),
+            TrafficKey::Board(address) => write!(f, "board {}", address),
+        }
+    }
+}
+
+/// Counts the read frames per op code and device within a sliding window
+/// and flags devices sending more frames than expected, like a bouncing sensor
+/// flooding the bus with [`Message::InputRep`]s.
+///
+/// # Example
+///
+/// ```
+/// use locodrive::traffic::{TrafficAnalyzer, TrafficKey};
+/// use tokio::time::{Duration, Instant};
+///
+/// let mut analyzer = TrafficAnalyzer::new(Duration::from_secs(10)).chatty_threshold(2);
+/// let now = Instant::now();
+///
+/// // The sensor 2 reports three times within the window
+/// let report = [0xB2, 0x01, 0x10, 0x5C];
+/// assert!(analyzer.record(&report, now).is_empty());
+/// assert!(analyzer.record(&report, now).is_empty());
+/// assert_eq!(analyzer.record(&report, now), vec![(TrafficKey::Sensor(2), 3)]);
+///
+/// assert_eq!(analyzer.report(now).chatty, vec![(TrafficKey::Sensor(2), 3)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrafficAnalyzer {
+    /// Within which time the frames are counted
+    window: Duration,
+    /// Above which count of frames within the window a device is chatty
+    chatty_threshold: Option<u64>,
+    /// The keys of the frames read within the window with when they were read
+    recent: VecDeque<(Instant, TrafficKey)>,
+    /// How many frames were read within the window per key
+    counts: HashMap<TrafficKey, u64>,
+}
+
+impl TrafficAnalyzer {
+    /// Creates an analyzer counting the frames read within `window`.
+    pub fn new(window: Duration) -> Self {
+        TrafficAnalyzer {
+            window,
+            chatty_threshold: None,
+            recent: VecDeque::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Flags devices as chatty, which send more than `chatty_threshold` frames within the window.
+    /// Defaults to flagging no device.
+    pub fn chatty_threshold(mut self, chatty_threshold: u64) -> Self {
+        self.chatty_threshold = Some(chatty_threshold);
+        self
+    }
+
+    /// # Returns
+    ///
+    /// Within which time the frames are counted.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Counts the `frame` read at `at` for its op code and the device it addresses.
+    /// Frames that are no valid LocoNet message are not counted.
+    ///
+    /// # Returns
+    ///
+    /// The devices that just became chatty by this frame with their count of frames in the window.
+    pub fn record(&mut self, frame: &[u8], at: Instant) -> Vec<(TrafficKey, u64)> {
+        self.forget_old(at);
+        let frame = match MessageRef::new(frame) {
+            Ok(frame) => frame,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut chatty = Vec::new();
+        for key in Self::keys(&frame).iter().flatten() {
+            self.recent.push_back((at, *key));
+            let count = self.counts.entry(*key).or_insert(0);
+            *count += 1;
+
+            // Devices are reported only once when the threshold is crossed
+            if key.is_device()
+                && self.chatty_threshold.map(|threshold| threshold + 1) == Some(*count)
+            {
+                chatty.push((*key, *count));
+            }
+        }
+        chatty
+    }
+
+    /// # Returns
+    ///
+    /// The op code and the addressed device of `frame`.
+    fn keys(frame: &MessageRef) -> [Option<TrafficKey>; 2] {
+        let device = if let Some(slot) = frame.slot() {
+            Some(TrafficKey::Slot(slot.slot()))
+        } else if let Some(sensor) = frame.sensor() {
+            Some(TrafficKey::Sensor(sensor.address_ds54()))
+        } else if let Some(switch) = frame.switch() {
+            Some(TrafficKey::Switch(switch.address()))
+        } else {
+            // Only the few multi sense reports are decoded to get their board
+            match frame.decode() {
+                Ok(Message::MultiSense(sense, _)) => Some(TrafficKey::Board(sense.board_address())),
+                _ => None,
+            }
+        };
+        [Some(TrafficKey::Opcode(frame.opc())), device]
+    }
+
+    /// Removes the frames read before the window ending `now`.
+    fn forget_old(&mut self, now: Instant) {
+        while let Some((at, key)) = self.recent.front().copied() {
+            if now.duration_since(at) <= self.window {
+                break;
+            }
+            self.recent.pop_front();
+            if let Some(count) = self.counts.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The count of chatty devices within the window ending `now`.
+    pub fn chatty_count(&mut self, now: Instant) -> u64 {
+        self.forget_old(now);
+        match self.chatty_threshold {
+            Some(threshold) => self
+                .counts
+                .iter()
+                .filter(|(key, count)| key.is_device() && **count > threshold)
+                .count() as u64,
+            None => 0,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The traffic within the window ending `now`.
+    pub fn report(&mut self, now: Instant) -> TrafficReport {
+        self.forget_old(now);
+
+        // The busiest keys come first, equally busy ones are ordered by their key
+        let mut counts: Vec<(TrafficKey, u64)> = self
+            .counts
+            .iter()
+            .map(|(key, count)| (*key, *count))
+            .collect();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+        let (opcodes, devices): (Vec<_>, Vec<_>) =
+            counts.into_iter().partition(|(key, _)| !key.is_device());
+        let chatty = match self.chatty_threshold {
+            Some(threshold) => devices
+                .iter()
+                .filter(|(_, count)| *count > threshold)
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        };
+        TrafficReport {
+            window: self.window,
+            frames: opcodes.iter().map(|(_, count)| count).sum(),
+            opcodes,
+            devices,
+            chatty,
+        }
+    }
+}
+
+/// The traffic counted by a [`TrafficAnalyzer`] within its window.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TrafficReport {
+    /// Within which time the frames were counted.
+    pub window: Duration,
+    /// How many valid frames were read within the window.
+    pub frames: u64,
+    /// The count of frames per op code, the busiest first.
+    pub opcodes: Vec<(TrafficKey, u64)>,
+    /// The count of frames per device, the busiest first.
+    pub devices: Vec<(TrafficKey, u64)>,
+    /// The devices that send more frames than the chatty threshold, the busiest first.
+    pub chatty: Vec<(TrafficKey, u64)>,
+}
+
+impl TrafficReport {
+    /// # Returns
+    ///
+    /// The average rate of `count` frames within the window in frames per second.
+    pub fn per_second(&self, count: u64) -> f64 {
+        if self.window.is_zero() {
+            0.0
+        } else {
+            count as f64 / self.window.as_secs_f64()
+        }
+    }
+}
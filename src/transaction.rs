@@ -0,0 +1,178 @@
+use crate::args::WrSlDataStructure;
+use crate::protocol::Message;
+use tokio::time::{Duration, Instant};
+
+/// Groups all frames of an operation spanning several messages.
+///
+/// A transaction starts with a request awaiting an answer, like a [`Message::LocoAdr`]
+/// or a [`Message::WrSlData`], collects all [`Message::Busy`] frames and intermediate
+/// acknowledgments and ends with the final answer to the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Transaction {
+    /// The message that started the transaction
+    request: Message,
+    /// All following frames of this transaction with the final answer as last frame
+    frames: Vec<Message>,
+    /// When the request was read
+    started: Instant,
+    /// When the final answer was read
+    finished: Instant,
+}
+
+impl Transaction {
+    /// Creates a new transaction waiting for the answer to `request`.
+    fn new(request: Message, started: Instant) -> Self {
+        Transaction {
+            request,
+            frames: Vec::new(),
+            started,
+            finished: started,
+        }
+    }
+
+    /// Adds one frame to the transaction.
+    fn push(&mut self, frame: Message, at: Instant) {
+        self.frames.push(frame);
+        self.finished = at;
+    }
+
+    /// # Returns
+    ///
+    /// The message that started this transaction.
+    pub fn request(&self) -> Message {
+        self.request
+    }
+
+    /// # Returns
+    ///
+    /// The final answer to the request.
+    pub fn answer(&self) -> Message {
+        // A transaction is only completed with an answer, so there is always a last frame
+        *self.frames.last().unwrap()
+    }
+
+    /// # Returns
+    ///
+    /// All frames received after the request in order of receiving.
+    /// The last frame is the [`Transaction::answer()`].
+    pub fn frames(&self) -> &[Message] {
+        &self.frames
+    }
+
+    /// # Returns
+    ///
+    /// How many [`Message::Busy`] frames were received before the answer.
+    pub fn busy_count(&self) -> usize {
+        self.frames
+            .iter()
+            .filter(|frame| **frame == Message::Busy)
+            .count()
+    }
+
+    /// # Returns
+    ///
+    /// When the request was read.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// # Returns
+    ///
+    /// When the final answer was read.
+    pub fn finished(&self) -> Instant {
+        self.finished
+    }
+
+    /// # Returns
+    ///
+    /// How long it took from the request to the final answer.
+    pub fn duration(&self) -> Duration {
+        self.finished - self.started
+    }
+}
+
+/// Follows the read messages to build up [`Transaction`]s.
+///
+/// Only one ordinary request is followed at a time, as the model railroad answers
+/// directly after the request. Programming tasks are followed separately,
+/// as their final answer may arrive much later after other traffic.
+#[derive(Debug, Default)]
+pub(crate) struct TransactionTracker {
+    /// The request awaiting its answer
+    pending: Option<Transaction>,
+    /// The accepted programming task awaiting its final response
+    programming: Option<Transaction>,
+}
+
+impl TransactionTracker {
+    /// Creates a new tracker following no transaction.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles the next read message.
+    ///
+    /// # Parameter
+    ///
+    /// - `message`: The read message
+    /// - `at`: When the message was read
+    ///
+    /// # Returns
+    ///
+    /// The transaction completed by this message, if any.
+    pub(crate) fn handle(&mut self, message: Message, at: Instant) -> Option<Transaction> {
+        match message {
+            Message::ProgrammingFinalResponse(..) | Message::ProgrammingAborted(..)
+                if self.programming.is_some() =>
+            {
+                let mut transaction = self.programming.take().unwrap();
+                transaction.push(message, at);
+                return Some(transaction);
+            }
+            _ => {}
+        }
+
+        let completed = match self.pending.take() {
+            Some(mut transaction) => match message {
+                Message::Busy => {
+                    transaction.push(message, at);
+                    self.pending = Some(transaction);
+                    None
+                }
+                Message::LongAck(lopc, ack) if lopc.check_opc(&transaction.request) => {
+                    transaction.push(message, at);
+                    if Self::is_programming(&transaction.request)
+                        && (ack.accepted() || ack.accepted_blind())
+                    {
+                        // The final response of the programming task follows later
+                        self.programming = Some(transaction);
+                        None
+                    } else {
+                        Some(transaction)
+                    }
+                }
+                Message::SlRdData(..) | Message::ProgrammingFinalResponse(..)
+                    if transaction.request.await_slot_data() =>
+                {
+                    transaction.push(message, at);
+                    Some(transaction)
+                }
+                // Any other message means the answer was missed
+                _ => None,
+            },
+            None => None,
+        };
+
+        // A busy frame never starts a transaction on its own
+        if message.answer_follows() && message != Message::Busy {
+            self.pending = Some(Transaction::new(message, at));
+        }
+
+        completed
+    }
+
+    /// Checks if the request starts a programming task.
+    fn is_programming(request: &Message) -> bool {
+        matches!(request, Message::WrSlData(WrSlDataStructure::DataPt(..)))
+    }
+}
@@ -0,0 +1,358 @@
+use crate::z21;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{duplex, split, AsyncRead, AsyncWrite, DuplexStream, ReadBuf, ReadHalf, WriteHalf};
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
+use tokio_serial::{
+    DataBits, Error, ErrorKind, FlowControl, Parity, SerialPortBuilderExt, SerialStream, StopBits,
+};
+
+/// How many bytes an in memory transport buffers in each direction.
+const MEMORY_BUFFER: usize = 4096;
+
+/// One end of an in memory connection, to connect a [`crate::loco_controller::LocoDriveController`]
+/// to a test without a serial port.
+///
+/// The other end plays the model railroad: It reads the bytes written by the controller and writes
+/// the echoes and answers back, as configured by
+/// [`crate::loco_controller::LocoDriveControllerBuilder::transport()`].
+/// For a z21 central, the other end is played by a tunnel to it, see [`LocoNetTransport::z21()`].
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::transport::LocoNetTransport;
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+///
+/// # async fn echo() -> Result<(), Box<dyn std::error::Error>> {
+/// let (controller_end, mut bus) = LocoNetTransport::pair();
+/// let controller = LocoDriveController::builder(controller_end.name(), 0)
+///     .transport(controller_end)
+///     .build()
+///     .await?;
+///
+/// // Echoes the next written message of two bytes
+/// let mut frame = [0; 2];
+/// bus.read_exact(&mut frame).await?;
+/// bus.write_all(&frame).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LocoNetTransport {
+    /// The name of this end
+    name: String,
+    /// The stream connected to the other end
+    stream: DuplexStream,
+    /// Whether the reading thread of the controller connected to this end panics
+    #[cfg(test)]
+    panic_reader: bool,
+}
+
+impl LocoNetTransport {
+    /// Creates two connected in memory ends. The bytes written to one end are read from the other.
+    pub fn pair() -> (LocoNetTransport, LocoNetTransport) {
+        let (first, second) = duplex(MEMORY_BUFFER);
+        (
+            LocoNetTransport {
+                name: "memory:0".to_string(),
+                stream: first,
+                #[cfg(test)]
+                panic_reader: false,
+            },
+            LocoNetTransport {
+                name: "memory:1".to_string(),
+                stream: second,
+                #[cfg(test)]
+                panic_reader: false,
+            },
+        )
+    }
+
+    /// Connects to the z21 central at `address` over UDP, for layouts without a serial LocoNet interface.
+    /// The messages are tunneled in the LAN_LOCONET datagrams of the z21 LAN protocol.
+    ///
+    /// The z21 listens on the port [`crate::z21::Z21_PORT`]. The tunnel is closed, when the controller
+    /// connected to the transport is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If the address could not be resolved, the socket could not be bound
+    /// or the registration at the z21 could not be sent.
+    pub async fn z21<A: ToSocketAddrs>(address: A) -> io::Result<LocoNetTransport> {
+        let address = lookup_host(address).await?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the address could not be resolved",
+            )
+        })?;
+        let local: SocketAddr = match address {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0, 0, 0, 0, 0, 0, 0, 0], 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(address).await?;
+        z21::register(&socket).await?;
+
+        let (stream, bus) = duplex(MEMORY_BUFFER);
+        tokio::spawn(z21::tunnel(socket, bus));
+        Ok(LocoNetTransport {
+            name: format!("z21:{}", address),
+            stream,
+            #[cfg(test)]
+            panic_reader: false,
+        })
+    }
+
+    /// # Returns
+    ///
+    /// The name of this end, reported as port name by a controller connected to it.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Lets the reading thread of the controller connected to this end panic,
+    /// after it opened the port.
+    #[cfg(test)]
+    pub(crate) fn panic_reader(mut self) -> Self {
+        self.panic_reader = true;
+        self
+    }
+}
+
+impl AsyncRead for LocoNetTransport {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for LocoNetTransport {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// An in memory transport shared by clones of a builder, which only one of them can connect to.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedTransport(Arc<Mutex<Option<LocoNetTransport>>>);
+
+impl SharedTransport {
+    /// Shares `transport`.
+    pub(crate) fn new(transport: LocoNetTransport) -> Self {
+        SharedTransport(Arc::new(Mutex::new(Some(transport))))
+    }
+
+    /// Takes the transport to connect to it.
+    ///
+    /// # Returns
+    ///
+    /// The name of the transport, where to open the reading port from
+    /// and the port to write to.
+    ///
+    /// # Errors
+    ///
+    /// If a controller is connected to the transport already.
+    pub(crate) fn connect(&self) -> Result<(String, ReadSource, WritePort), Error> {
+        let transport = self.0.lock().unwrap().take().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NoDevice,
+                "a controller is connected to the transport already",
+            )
+        })?;
+        let (read, write) = split(transport.stream);
+        let read = Arc::new(Mutex::new(Some(read)));
+        #[cfg(test)]
+        if transport.panic_reader {
+            return Ok((
+                transport.name,
+                ReadSource::Panicking(read),
+                WritePort::Memory(write),
+            ));
+        }
+        Ok((
+            transport.name,
+            ReadSource::Memory(read),
+            WritePort::Memory(write),
+        ))
+    }
+}
+
+/// Where the reading thread of a controller opens its port from.
+pub(crate) enum ReadSource {
+    /// Opens the serial port again for reading
+    Serial {
+        /// The name of the serial port
+        name: String,
+        /// The baud rate to use
+        baud_rate: u32,
+        /// The flow control to use
+        flow_control: FlowControl,
+    },
+    /// Takes the reading half of an in memory transport, which can only be opened once.
+    /// So a restarted reading thread fails to open it.
+    Memory(Arc<Mutex<Option<ReadHalf<DuplexStream>>>>),
+    /// Takes the reading half of an in memory transport like [`ReadSource::Memory`],
+    /// but panics after taking it.
+    #[cfg(test)]
+    Panicking(Arc<Mutex<Option<ReadHalf<DuplexStream>>>>),
+}
+
+impl ReadSource {
+    /// # Returns
+    ///
+    /// The name of the port to read from.
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            ReadSource::Serial { name, .. } => name,
+            ReadSource::Memory(_) => "memory",
+            #[cfg(test)]
+            ReadSource::Panicking(_) => "memory",
+        }
+    }
+
+    /// Opens the port to read from.
+    ///
+    /// # Errors
+    ///
+    /// If the serial port could not be opened and configured,
+    /// or the in memory transport was opened already.
+    pub(crate) fn open(&self) -> Result<ReadPort, Error> {
+        match self {
+            ReadSource::Serial {
+                name,
+                baud_rate,
+                flow_control,
+            } => {
+                let port = tokio_serial::new(name, *baud_rate)
+                    .data_bits(DataBits::Eight)
+                    .stop_bits(StopBits::Two)
+                    .parity(Parity::None)
+                    .flow_control(*flow_control)
+                    .open_native_async()?;
+
+                // For linux systems we once more ensure that this set is not exclusive usable for us
+                #[cfg(unix)]
+                let mut port = port;
+                #[cfg(unix)]
+                port.set_exclusive(false)?;
+
+                Ok(ReadPort::Serial(port))
+            }
+            ReadSource::Memory(read) => read
+                .lock()
+                .unwrap()
+                .take()
+                .map(ReadPort::Memory)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::NoDevice,
+                        "the in memory transport can not be opened again",
+                    )
+                }),
+            #[cfg(test)]
+            ReadSource::Panicking(read) => {
+                let taken = read.lock().unwrap().take();
+                match taken {
+                    Some(_) => panic!("the reading thread panicked on purpose"),
+                    None => ReadSource::Memory(read.clone()).open(),
+                }
+            }
+        }
+    }
+}
+
+/// The port the reading thread of a controller reads from.
+pub(crate) enum ReadPort {
+    /// A serial port
+    Serial(SerialStream),
+    /// The reading half of an in memory transport
+    Memory(ReadHalf<DuplexStream>),
+}
+
+impl AsyncRead for ReadPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ReadPort::Serial(port) => Pin::new(port).poll_read(cx, buf),
+            ReadPort::Memory(port) => Pin::new(port).poll_read(cx, buf),
+        }
+    }
+}
+
+/// The port a controller writes to.
+pub(crate) enum WritePort {
+    /// A serial port
+    Serial(SerialStream),
+    /// The writing half of an in memory transport
+    Memory(WriteHalf<DuplexStream>),
+}
+
+impl WritePort {
+    /// # Returns
+    ///
+    /// The serial port written to.
+    ///
+    /// # Errors
+    ///
+    /// If an in memory transport is written to, which has no serial settings.
+    pub(crate) fn serial(&mut self) -> Result<&mut SerialStream, Error> {
+        match self {
+            WritePort::Serial(port) => Ok(port),
+            WritePort::Memory(_) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "an in memory transport has no serial settings",
+            )),
+        }
+    }
+}
+
+impl AsyncWrite for WritePort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WritePort::Serial(port) => Pin::new(port).poll_write(cx, buf),
+            WritePort::Memory(port) => Pin::new(port).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WritePort::Serial(port) => Pin::new(port).poll_flush(cx),
+            WritePort::Memory(port) => Pin::new(port).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WritePort::Serial(port) => Pin::new(port).poll_shutdown(cx),
+            WritePort::Memory(port) => Pin::new(port).poll_shutdown(cx),
+        }
+    }
+}
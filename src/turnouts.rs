@@ -0,0 +1,150 @@
+use crate::args::{SwitchArg, SwitchDirection};
+use crate::error::LocoDriveSendingError;
+use crate::loco_controller::{CommandHandle, LocoDriveController, SendOptions};
+use crate::protocol::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{sleep, sleep_until, Duration, Instant};
+
+/// Throws turnouts paced per accessory decoder and switches their outputs off after a pulse.
+///
+/// Many accessory decoders drive the coils of a turnout as long as the output is active,
+/// so the output has to be switched off by a [`Message::SwReq`] with the state `false`
+/// after a short pulse. The off request is send in the background after the pulse.
+///
+/// Each decoder serves several neighbouring turnout addresses and is thrown at most once
+/// within its cooldown, so its coils and power supply are not overloaded and the command station
+/// does not drop requests. Throws of one decoder are send in the order they were requested,
+/// each after the off request of the previous one. Different decoders are thrown independently.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::args::SwitchDirection;
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::turnouts::TurnoutDriver;
+/// use tokio::time::Duration;
+///
+/// # async fn throw(controller: LocoDriveController) {
+/// // The decoder may be thrown twice per second
+/// let turnouts = TurnoutDriver::new(&controller).cooldown(Duration::from_millis(500));
+///
+/// turnouts.throw(12, SwitchDirection::Curved).await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TurnoutDriver {
+    /// Writes the switch requests
+    handle: CommandHandle,
+    /// How long an output is active before it is switched off
+    pulse: Option<Duration>,
+    /// How long to wait between two throws of one decoder
+    cooldown: Duration,
+    /// How many neighbouring turnout addresses one decoder serves
+    outputs_per_decoder: u16,
+    /// How to send the switch requests
+    send_options: SendOptions,
+    /// When each decoder may be thrown next, locked while it is thrown
+    decoders: Arc<Mutex<HashMap<u16, Arc<tokio::sync::Mutex<Instant>>>>>,
+}
+
+impl TurnoutDriver {
+    /// Creates a driver throwing the turnouts with `controller`.
+    pub fn new(controller: &LocoDriveController) -> Self {
+        TurnoutDriver {
+            handle: controller.command_handle(),
+            pulse: Some(Duration::from_millis(200)),
+            cooldown: Duration::from_millis(250),
+            outputs_per_decoder: 4,
+            send_options: SendOptions::default(),
+            decoders: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sets how long an output is active before it is switched off,
+    /// or `None` to leave switching off to the decoder.
+    ///
+    /// Defaults to 200 milliseconds.
+    pub fn pulse(mut self, pulse: Option<Duration>) -> Self {
+        self.pulse = pulse;
+        self
+    }
+
+    /// Sets how long to wait between two throws of one decoder.
+    /// A decoder allowing `n` throws per second needs a cooldown of `1 s / n`.
+    ///
+    /// Defaults to 250 milliseconds.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how many neighbouring turnout addresses one decoder serves,
+    /// starting at multiples of `outputs_per_decoder`.
+    ///
+    /// Defaults to `4`, like most DCC accessory decoders.
+    pub fn outputs_per_decoder(mut self, outputs_per_decoder: u16) -> Self {
+        self.outputs_per_decoder = outputs_per_decoder.max(1);
+        self
+    }
+
+    /// Sets how the switch requests are send, like how often a failed request is repeated.
+    ///
+    /// Defaults to [`SendOptions::default()`].
+    pub fn send_options(mut self, send_options: SendOptions) -> Self {
+        self.send_options = send_options;
+        self
+    }
+
+    /// # Returns
+    ///
+    /// The decoder serving the turnout with `address`.
+    pub fn decoder(&self, address: u16) -> u16 {
+        address / self.outputs_per_decoder
+    }
+
+    /// Throws the turnout with `address` to `direction`, after the cooldown of its decoder.
+    /// The output is switched off in the background after the pulse.
+    ///
+    /// # Errors
+    ///
+    /// The [`LocoDriveSendingError`] of the switch request, if it could not be send.
+    /// A failed off request is only logged, as the decoder is not thrown anymore.
+    pub async fn throw(
+        &self,
+        address: u16,
+        direction: SwitchDirection,
+    ) -> Result<(), LocoDriveSendingError> {
+        let decoder = self
+            .decoders
+            .lock()
+            .unwrap()
+            .entry(self.decoder(address))
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(Instant::now())))
+            .clone();
+
+        // The lock is held until the output is switched off, so throws of a decoder never overlap
+        let mut next_throw = decoder.lock_owned().await;
+        sleep_until(*next_throw).await;
+
+        let on = Message::SwReq(SwitchArg::new(address, direction, true));
+        let thrown_at = Instant::now();
+        let result = self.handle.send_message_with(on, self.send_options).await;
+        *next_throw = thrown_at + self.cooldown;
+        result?;
+
+        if let Some(pulse) = self.pulse {
+            let handle = self.handle.clone();
+            let send_options = self.send_options;
+            tokio::spawn(async move {
+                sleep(pulse).await;
+                let off = Message::SwReq(SwitchArg::new(address, direction, false));
+                if let Err(err) = handle.send_message_with(off, send_options).await {
+                    log_error!("Could not switch off turnout {}: {}", address, err);
+                }
+                drop(next_throw);
+            });
+        }
+        Ok(())
+    }
+}
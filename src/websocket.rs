@@ -0,0 +1,166 @@
+use crate::error::WebSocketError;
+use crate::line_protocol::{format_frame, parse_frame_line};
+use crate::protocol::Message;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_util::StreamExt;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{MessageEvent, WebSocket};
+
+/// An event of the WebSocket, passed from its callbacks to the transport.
+#[derive(Debug)]
+enum SocketEvent {
+    /// The WebSocket was opened
+    Open,
+    /// A line of text was received
+    Line(String),
+    /// The WebSocket failed
+    Error,
+    /// The WebSocket was closed
+    Closed,
+}
+
+/// Talks to the model railroad through a bridge serving the LoconetOverTcp protocol over a WebSocket,
+/// like an LbServer behind a WebSocket proxy, so control panels running in the browser can use this crate
+/// compiled to WebAssembly.
+///
+/// The bridge speaks the same lines as the [`crate::loco_server::LocoServer`]:
+/// Messages are send as `SEND 83 7C` and received as `RECEIVE 83 7C`.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::protocol::Message;
+/// use locodrive::websocket::WebSocketTransport;
+///
+/// # async fn run() -> Result<(), locodrive::error::WebSocketError> {
+/// let mut transport = WebSocketTransport::connect("ws://localhost:1235").await?;
+/// transport.send(Message::GpOn)?;
+///
+/// loop {
+///     let message = transport.receive().await?;
+///     println!("Received {:?}", message);
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct WebSocketTransport {
+    /// The WebSocket to the bridge
+    socket: WebSocket,
+    /// The events of the WebSocket
+    events: UnboundedReceiver<SocketEvent>,
+    /// The callbacks of the WebSocket, which have to live as long as it
+    _callbacks: Vec<Closure<dyn FnMut(JsValue)>>,
+}
+
+impl WebSocketTransport {
+    /// Opens a WebSocket to the bridge at `url` and waits until it is open.
+    ///
+    /// # Errors
+    ///
+    /// - [`WebSocketError::Connect`]: If the WebSocket could not be opened
+    pub async fn connect(url: &str) -> Result<Self, WebSocketError> {
+        let socket =
+            WebSocket::new(url).map_err(|err| WebSocketError::Connect(format!("{:?}", err)))?;
+        let (sender, events) = unbounded();
+
+        let on_open = callback(&sender, |_| vec![SocketEvent::Open]);
+        let on_message = callback(&sender, |event| {
+            // The bridge may send several lines at once
+            let text = event
+                .dyn_into::<MessageEvent>()
+                .ok()
+                .and_then(|event| event.data().as_string())
+                .unwrap_or_default();
+            text.lines()
+                .map(|line| SocketEvent::Line(line.to_string()))
+                .collect()
+        });
+        let on_error = callback(&sender, |_| vec![SocketEvent::Error]);
+        let on_close = callback(&sender, |_| vec![SocketEvent::Closed]);
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        let mut transport = WebSocketTransport {
+            socket,
+            events,
+            _callbacks: vec![on_open, on_message, on_error, on_close],
+        };
+        match transport.events.next().await {
+            Some(SocketEvent::Open) => Ok(transport),
+            _ => Err(WebSocketError::Connect(format!(
+                "the websocket to {} could not be opened",
+                url
+            ))),
+        }
+    }
+
+    /// Sends `message` to the bridge, which writes it to the bus.
+    /// A rejection by the bridge is returned by [`WebSocketTransport::receive()`].
+    ///
+    /// # Errors
+    ///
+    /// - [`WebSocketError::Send`]: If the WebSocket is not open
+    pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        self.socket
+            .send_with_str(&format_frame("SEND", &message.to_message()))
+            .map_err(|err| WebSocketError::Send(format!("{:?}", err)))
+    }
+
+    /// Receives the next message read from the bus by the bridge.
+    /// Lines other than received frames are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - [`WebSocketError::Closed`]: If the WebSocket was closed or failed
+    /// - [`WebSocketError::Send`]: If the bridge rejected a send message
+    /// - [`WebSocketError::Parse`]: If the received frame could not be parsed. It is skipped,
+    ///   so receiving again continues with the following frame.
+    pub async fn receive(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let line = match self.events.next().await {
+                Some(SocketEvent::Line(line)) => line,
+                Some(SocketEvent::Open) => continue,
+                Some(SocketEvent::Error) | Some(SocketEvent::Closed) | None => {
+                    return Err(WebSocketError::Closed)
+                }
+            };
+
+            if let Some(reason) = line.strip_prefix("SENT ERROR") {
+                return Err(WebSocketError::Send(reason.trim().to_string()));
+            }
+            if let Some(message) = parse_frame_line("RECEIVE", &line) {
+                return message.map_err(WebSocketError::Parse);
+            }
+        }
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        // The callbacks are dropped with the transport, so the WebSocket must not call them anymore
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onerror(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
+
+/// # Returns
+///
+/// A callback of the WebSocket passing the events created by `events` from its argument to `sender`.
+fn callback(
+    sender: &UnboundedSender<SocketEvent>,
+    events: impl Fn(JsValue) -> Vec<SocketEvent> + 'static,
+) -> Closure<dyn FnMut(JsValue)> {
+    let sender = sender.clone();
+    Closure::new(move |event: JsValue| {
+        for event in events(event) {
+            // The transport may already be dropped
+            let _ = sender.unbounded_send(event);
+        }
+    })
+}
@@ -0,0 +1,76 @@
+use crate::error::MessageParseError;
+use crate::protocol::Message;
+use alloc::format;
+use alloc::vec::Vec;
+
+/// The golden frames this crate is tested against, as shipped in `fixtures/wire_vectors.json`.
+const FIXTURE: &str = include_str!("../fixtures/wire_vectors.json");
+
+/// One golden frame of the wire level compatibility corpus.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TestVector {
+    /// The unique name of the vector, like `loco_spd`
+    pub name: &'static str,
+    /// The complete frame as sent on the bus, including the checksum
+    pub frame: Vec<u8>,
+    /// Whether the frame is a valid message. Invalid frames must be rejected by parsers.
+    pub valid: bool,
+}
+
+impl TestVector {
+    /// Parses the frame of this vector.
+    ///
+    /// # Returns
+    ///
+    /// The parsed message, that is encoded exactly to the frame again, for valid vectors.
+    ///
+    /// # Errors
+    ///
+    /// The error parsing the frame, which is the case for all invalid vectors.
+    pub fn message(&self) -> Result<Message, MessageParseError> {
+        Message::parse(&self.frame)
+    }
+}
+
+/// # Returns
+///
+/// The wire level compatibility corpus as JSON.
+///
+/// The corpus is an array of objects with the `name` of the vector, its `frame` as
+/// whitespace separated hex bytes and whether it is `valid`. Third-party implementations,
+/// like firmware or ports to other languages, can validate against this corpus to encode
+/// and parse exactly like this crate.
+pub fn fixture() -> &'static str {
+    FIXTURE
+}
+
+/// # Returns
+///
+/// The vectors of the wire level compatibility corpus, see [`fixture()`].
+pub fn test_vectors() -> impl Iterator<Item = TestVector> {
+    // The fixture holds one vector per line, so no JSON parser is needed
+    FIXTURE.lines().filter_map(parse_vector)
+}
+
+/// Parses one line of the fixture, if it holds a vector.
+fn parse_vector(line: &'static str) -> Option<TestVector> {
+    let name = string_field(line, "name")?;
+    let frame = string_field(line, "frame")?
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+    let valid = line.contains("\"valid\": true");
+
+    Some(TestVector { name, frame, valid })
+}
+
+/// # Returns
+///
+/// The string value of the field `key` in `line`.
+fn string_field(line: &'static str, key: &str) -> Option<&'static str> {
+    let pattern = format!("\"{}\": \"", key);
+    let start = line.find(&pattern)? + pattern.len();
+    let len = line[start..].find('"')?;
+    Some(&line[start..start + len])
+}
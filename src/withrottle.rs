@@ -0,0 +1,787 @@
+use crate::args::{AddressArg, DirfArg, SlotArg, SndArg, SpeedArg, SwitchArg, SwitchDirection};
+use crate::loco_controller::{
+    CommandHandle, LocoDriveController, LocoDriveMessage, LocoDriveReceiver, PowerState,
+};
+use crate::manager::{Manager, SlotManager, SlotState, TurnoutTable};
+use crate::message_ref::MessageRef;
+use crate::protocol::Message;
+use crate::slot_cache::SlotResolver;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep_until, Instant};
+
+/// The separator between the locomotive and the action of a throttle command.
+const SEPARATOR: &str = "<;>";
+
+/// Serves the WiThrottle protocol of JMRI to throttle apps, like Engine Driver or WiThrottle,
+/// driving locomotives and switching turnouts with a [`LocoDriveController`].
+///
+/// The apps acquire locomotives by their address, their slots are resolved by a [`SlotResolver`].
+/// The speed, direction and the functions 0 to 8 of acquired locomotives, the turnouts
+/// and the track power are reflected back to the apps whenever they change on the bus.
+///
+/// Functions are latching: Pressing a function button toggles it, releasing is ignored.
+/// Clients enabling the heartbeat stop all their locomotives in emergency
+/// when they are silent for longer than the heartbeat.
+/// Disconnecting keeps the locomotives running, as apps reconnect after network losses.
+///
+/// # Example
+///
+/// ```no_run
+/// use locodrive::loco_controller::LocoDriveController;
+/// use locodrive::withrottle::WiThrottleServer;
+///
+/// # async fn serve(controller: LocoDriveController) -> std::io::Result<()> {
+/// let server = WiThrottleServer::bind("0.0.0.0:12090").await?.start(&controller);
+/// println!("Serving on {}", server.local_addr());
+/// # Ok(())
+/// # }
+/// ```
+pub struct WiThrottleServer {
+    /// The socket accepting the clients
+    listener: TcpListener,
+    /// The heartbeat announced to the clients
+    heartbeat: Duration,
+}
+
+impl WiThrottleServer {
+    /// Binds a server to `address`.
+    ///
+    /// # Errors
+    ///
+    /// If the address could not be bound.
+    pub async fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Ok(WiThrottleServer {
+            listener: TcpListener::bind(address).await?,
+            heartbeat: Duration::from_secs(10),
+        })
+    }
+
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Sets the `heartbeat` announced to the clients, rounded down to whole seconds.
+    /// Clients enabling the heartbeat are stopped, when they are silent for longer.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Starts accepting clients and drives the layout with `controller`.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the server. Dropping it disconnects all clients and stops the server.
+    pub fn start(self, controller: &LocoDriveController) -> WiThrottleServerHandle {
+        // The address is known, as it was bound successfully
+        let local_addr = self
+            .listener
+            .local_addr()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+        let shared = Arc::new(Shared {
+            heartbeat: Duration::from_secs(self.heartbeat.as_secs().max(1)),
+            handle: controller.command_handle(),
+            resolver: SlotResolver::new(controller),
+            layout: Mutex::new(Layout {
+                slots: SlotManager::new(),
+                turnouts: TurnoutTable::new(),
+                power: controller.power_state(),
+            }),
+            updates: broadcast::channel(64).0,
+        });
+
+        WiThrottleServerHandle {
+            local_addr,
+            task: tokio::spawn(accept(self.listener, shared, controller.subscribe())),
+        }
+    }
+}
+
+/// The handle of a running [`WiThrottleServer`].
+///
+/// Dropping the handle disconnects all clients and stops the server.
+#[derive(Debug)]
+pub struct WiThrottleServerHandle {
+    /// The address the server is bound to
+    local_addr: SocketAddr,
+    /// The task accepting the clients
+    task: JoinHandle<()>,
+}
+
+impl WiThrottleServerHandle {
+    /// # Returns
+    ///
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Extends standard drop implementation to stop the server.
+impl Drop for WiThrottleServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A change of the layout to reflect to the clients.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Update {
+    /// The state of a slot changed
+    Slot(SlotArg),
+    /// A turnout changed its direction
+    Turnout(u16, SwitchDirection),
+    /// The track power changed
+    Power(PowerState),
+}
+
+/// The layout state tracked from the bus.
+struct Layout {
+    /// The known slots
+    slots: SlotManager,
+    /// The known turnouts
+    turnouts: TurnoutTable,
+    /// The track power
+    power: PowerState,
+}
+
+/// The state shared by all clients of a server.
+struct Shared {
+    /// The heartbeat announced to the clients
+    heartbeat: Duration,
+    /// Writes the messages of the clients
+    handle: CommandHandle,
+    /// Resolves the slots of the acquired locomotives
+    resolver: SlotResolver,
+    /// The tracked layout state
+    layout: Mutex<Layout>,
+    /// Tells the clients about changes of the layout
+    updates: broadcast::Sender<Update>,
+}
+
+impl Shared {
+    /// Tracks `message` and tells the clients about the change it caused.
+    fn track(&self, message: &Message) {
+        let mut layout = self.layout.lock().unwrap();
+        let frame = message.to_message();
+        let slot = MessageRef::new(&frame)
+            .ok()
+            .and_then(|message| message.slot());
+        let before = slot.and_then(|slot| layout.slots.slot(slot).copied());
+        layout.slots.handle(message);
+
+        let power = match *message {
+            Message::GpOn => Some(PowerState::On),
+            Message::GpOff => Some(PowerState::Off),
+            Message::Idle => Some(PowerState::Idle),
+            _ => None,
+        };
+        let update = match (power, slot) {
+            (Some(power), _) if layout.power != power => {
+                layout.power = power;
+                Some(Update::Power(power))
+            }
+            (Some(_), _) => None,
+            (None, Some(slot)) if layout.slots.slot(slot).copied() != before => {
+                Some(Update::Slot(slot))
+            }
+            (None, _) => layout
+                .turnouts
+                .update(message)
+                .map(|change| Update::Turnout(change.address, change.direction)),
+        };
+        drop(layout);
+
+        if let Some(update) = update {
+            // No client may be connected
+            let _ = self.updates.send(update);
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The tracked state of `slot`.
+    fn slot(&self, slot: SlotArg) -> SlotState {
+        let layout = self.layout.lock().unwrap();
+        layout.slots.slot(slot).copied().unwrap_or_default()
+    }
+
+    /// Writes a `message` of a client to the bus and tracks it right away,
+    /// so a following command builds on it.
+    async fn send(&self, message: Message) -> Result<(), String> {
+        self.handle
+            .send_message(message)
+            .await
+            .map_err(|err| err.to_string())?;
+        self.track(&message);
+        Ok(())
+    }
+}
+
+/// Accepts clients on `listener` until the controller is dropped.
+async fn accept(listener: TcpListener, shared: Arc<Shared>, mut messages: LocoDriveReceiver) {
+    let mut clients = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    log_info!("Throttle {} connected", address);
+                    clients.spawn(serve(stream, address, shared.clone()));
+                }
+                Err(err) => log_error!("Could not accept a throttle: {}", err),
+            },
+            received = messages.recv() => match received {
+                Ok(LocoDriveMessage::Message(message) | LocoDriveMessage::Echo(message)) => {
+                    shared.track(&message);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            // Reap the finished clients
+            Some(_) = clients.join_next(), if !clients.is_empty() => {}
+        }
+    }
+}
+
+/// A locomotive acquired by a client.
+struct Loco {
+    /// The throttle of the app the locomotive is acquired on
+    throttle: char,
+    /// The key the app names the locomotive with, like `L3`
+    key: String,
+    /// The slot of the locomotive
+    slot: SlotArg,
+    /// The state last shown to the app
+    shown: Option<Shown>,
+}
+
+/// The state of a locomotive as shown to an app.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Shown {
+    /// The speed step
+    speed: u8,
+    /// Whether the locomotive drives forwards
+    forward: bool,
+    /// The functions 0 to 8
+    functions: [bool; 9],
+}
+
+impl Shown {
+    /// # Returns
+    ///
+    /// The shown state of `state`, defaulting to stopped forwards with all functions off.
+    fn new(state: SlotState) -> Self {
+        let (dirf, snd) = functions(state);
+        let mut functions = [false; 9];
+        for (function, on) in functions.iter_mut().enumerate() {
+            *on = match function {
+                0..=4 => dirf.f(function as u8),
+                _ => snd.f(function as u8),
+            };
+        }
+        Shown {
+            speed: state.speed.map_or(0, |speed| speed.get_spd()),
+            forward: dirf.dir(),
+            functions,
+        }
+    }
+}
+
+impl Loco {
+    /// # Returns
+    ///
+    /// The prefix of the lines reporting the state of this locomotive, like `MTAL3<;>`.
+    fn prefix(&self) -> String {
+        format!("M{}A{}{}", self.throttle, self.key, SEPARATOR)
+    }
+
+    /// Shows `state` to the app.
+    ///
+    /// # Returns
+    ///
+    /// The lines reporting the values that changed since they were shown last.
+    fn show(&mut self, state: SlotState) -> Vec<String> {
+        let shown = Shown::new(state);
+        let before = self.shown.replace(shown);
+        let prefix = self.prefix();
+        let mut lines = Vec::new();
+
+        if before.map(|before| before.speed) != Some(shown.speed) {
+            lines.push(format!("{}V{}", prefix, shown.speed));
+        }
+        if before.map(|before| before.forward) != Some(shown.forward) {
+            lines.push(format!("{}R{}", prefix, shown.forward as u8));
+        }
+        for (function, &on) in shown.functions.iter().enumerate() {
+            if before.map(|before| before.functions[function]) != Some(on) {
+                lines.push(format!("{}F{}{}", prefix, on as u8, function));
+            }
+        }
+        lines
+    }
+}
+
+/// # Returns
+///
+/// The direction and functions of `state`, defaulting to forwards with all functions off.
+fn functions(state: SlotState) -> (DirfArg, SndArg) {
+    (
+        state
+            .dirf
+            .unwrap_or_else(|| DirfArg::new(true, false, false, false, false, false)),
+        state
+            .snd
+            .unwrap_or_else(|| SndArg::new(false, false, false, false)),
+    )
+}
+
+/// A command of a throttle app.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Command {
+    /// The app told its name and expects the heartbeat
+    Name,
+    /// The app enabled or disabled the heartbeat, or sent one
+    Heartbeat(Option<bool>),
+    /// The app switches the track power
+    Power(bool),
+    /// The app toggles the turnout at the address or switches it straight or curved
+    Turnout(u16, Option<SwitchDirection>),
+    /// The app acquires the locomotive at the address on its throttle with the key
+    Acquire(char, String, AddressArg),
+    /// The app releases the locomotive with the key from its throttle.
+    /// Dispatched locomotives are put back to the command station.
+    Release(char, String, bool),
+    /// The app controls the locomotive with the key on its throttle
+    Action(char, String, Action),
+    /// The app disconnects
+    Quit,
+}
+
+/// An action on an acquired locomotive.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Action {
+    /// Sets the speed step
+    Speed(SpeedArg),
+    /// Sets whether the locomotive drives forwards
+    Forward(bool),
+    /// Presses or releases a function button
+    Press(u8, bool),
+    /// Switches a function on or off
+    Function(u8, bool),
+    /// Queries the speed or direction
+    Query,
+}
+
+/// Parses a `line` sent by a throttle app.
+///
+/// # Returns
+///
+/// The command of the line, or `None` if it is unknown or not supported.
+pub(crate) fn parse_command(line: &str) -> Option<Command> {
+    let line = line.trim();
+    if line == "Q" {
+        return Some(Command::Quit);
+    }
+    if let Some(heartbeat) = line.strip_prefix('*') {
+        return match heartbeat {
+            "+" => Some(Command::Heartbeat(Some(true))),
+            "-" => Some(Command::Heartbeat(Some(false))),
+            _ => Some(Command::Heartbeat(None)),
+        };
+    }
+    if let Some(power) = line.strip_prefix("PPA") {
+        return match power {
+            "1" => Some(Command::Power(true)),
+            "0" => Some(Command::Power(false)),
+            _ => None,
+        };
+    }
+    if let Some(turnout) = line.strip_prefix("PTA") {
+        let mut chars = turnout.chars();
+        let direction = match chars.next()? {
+            '2' => None,
+            'C' => Some(SwitchDirection::Straight),
+            'T' => Some(SwitchDirection::Curved),
+            _ => return None,
+        };
+        let name = chars.as_str();
+        let address = name.strip_prefix("LT").unwrap_or(name).parse().ok()?;
+        return Some(Command::Turnout(address, direction));
+    }
+    if line.starts_with('N') {
+        return Some(Command::Name);
+    }
+
+    let mut chars = line.strip_prefix('M')?.chars();
+    let throttle = chars.next()?;
+    let operation = chars.next()?;
+    let (key, action) = chars.as_str().split_once(SEPARATOR)?;
+    let key = key.to_string();
+    match operation {
+        '+' => {
+            let address = match key.split_at(1) {
+                ("L", address) | ("S", address) => AddressArg::new(address.parse().ok()?),
+                _ => return None,
+            };
+            Some(Command::Acquire(throttle, key, address))
+        }
+        '-' => Some(Command::Release(throttle, key, action == "d")),
+        'A' => {
+            let action = match action.split_at(1.min(action.len())) {
+                ("V", speed) => match speed.parse::<i16>().ok()? {
+                    speed if speed < 0 => Action::Speed(SpeedArg::EmergencyStop),
+                    speed => Action::Speed(SpeedArg::new(speed.min(126) as u8)),
+                },
+                ("X", _) => Action::Speed(SpeedArg::EmergencyStop),
+                ("I", _) => Action::Speed(SpeedArg::Stop),
+                ("R", forward) => Action::Forward(forward == "1"),
+                ("F", function) | ("f", function) if function.len() > 1 => {
+                    let (on, number) = function.split_at(1);
+                    let number = number.parse().ok()?;
+                    match action.starts_with('F') {
+                        true => Action::Press(number, on == "1"),
+                        false => Action::Function(number, on == "1"),
+                    }
+                }
+                ("q", _) => Action::Query,
+                _ => return None,
+            };
+            Some(Command::Action(throttle, key, action))
+        }
+        _ => None,
+    }
+}
+
+/// # Returns
+///
+/// The WiThrottle code of `direction`, with `2` for closed and `4` for thrown turnouts.
+fn turnout_code(direction: Option<SwitchDirection>) -> u8 {
+    match direction {
+        Some(SwitchDirection::Straight) => 2,
+        Some(SwitchDirection::Curved) => 4,
+        None => 1,
+    }
+}
+
+/// # Returns
+///
+/// The WiThrottle code of `power`, with `2` for an unknown state.
+fn power_code(power: PowerState) -> u8 {
+    match power {
+        PowerState::On | PowerState::Idle => 1,
+        PowerState::Off => 0,
+        PowerState::Unknown => 2,
+    }
+}
+
+/// A connected throttle app.
+struct Client {
+    /// The state shared by all clients
+    shared: Arc<Shared>,
+    /// The locomotives acquired by the app
+    locos: Vec<Loco>,
+    /// Whether the app enabled the heartbeat
+    heartbeat: bool,
+}
+
+impl Client {
+    /// # Returns
+    ///
+    /// The lines greeting the app with the version, the turnouts, the power and the heartbeat.
+    fn greeting(&self) -> Vec<String> {
+        let layout = self.shared.layout.lock().unwrap();
+        let mut lines = vec![
+            "VN2.0".to_string(),
+            "RL0".to_string(),
+            format!("PPA{}", power_code(layout.power)),
+            "PTT]\\[Turnouts}|{Turnout]\\[Closed}|{2]\\[Thrown}|{4]\\[Unknown}|{1".to_string(),
+        ];
+
+        let mut turnouts: Vec<_> = layout.turnouts.turnouts().iter().collect();
+        if !turnouts.is_empty() {
+            turnouts.sort_by_key(|(&address, _)| address);
+            let list: String = turnouts
+                .into_iter()
+                .map(|(address, turnout)| {
+                    format!(
+                        "]\\[LT{}}}|{{{}}}|{{{}",
+                        address,
+                        address,
+                        turnout_code(turnout.direction())
+                    )
+                })
+                .collect();
+            lines.push(format!("PTL{}", list));
+        }
+        lines.push(format!("*{}", self.shared.heartbeat.as_secs()));
+        lines
+    }
+
+    /// Executes the `command` of the app.
+    ///
+    /// # Returns
+    ///
+    /// The lines answering the command.
+    async fn execute(&mut self, command: Command) -> Vec<String> {
+        let result = match command {
+            Command::Name => return vec![format!("*{}", self.shared.heartbeat.as_secs())],
+            Command::Heartbeat(Some(heartbeat)) => {
+                self.heartbeat = heartbeat;
+                Ok(Vec::new())
+            }
+            Command::Heartbeat(None) | Command::Quit => Ok(Vec::new()),
+            Command::Power(on) => {
+                let message = match on {
+                    true => Message::GpOn,
+                    false => Message::GpOff,
+                };
+                self.shared.send(message).await.map(|_| Vec::new())
+            }
+            Command::Turnout(address, direction) => {
+                let direction = direction.unwrap_or_else(|| {
+                    let layout = self.shared.layout.lock().unwrap();
+                    match layout.turnouts.state(address) {
+                        Some(SwitchDirection::Straight) => SwitchDirection::Curved,
+                        _ => SwitchDirection::Straight,
+                    }
+                });
+                let message = Message::SwReq(SwitchArg::new(address, direction, true));
+                self.shared.send(message).await.map(|_| Vec::new())
+            }
+            Command::Acquire(throttle, key, address) => self.acquire(throttle, key, address).await,
+            Command::Release(throttle, key, dispatch) => {
+                self.release(throttle, &key, dispatch).await
+            }
+            Command::Action(throttle, key, action) => self.act(throttle, &key, action).await,
+        };
+        result.unwrap_or_else(|err| vec![format!("HM{}", err)])
+    }
+
+    /// Acquires the locomotive at `address` on `throttle` with `key`.
+    async fn acquire(
+        &mut self,
+        throttle: char,
+        key: String,
+        address: AddressArg,
+    ) -> Result<Vec<String>, String> {
+        let slot = self
+            .shared
+            .resolver
+            .slot_for(address)
+            .await
+            .map_err(|err| err.to_string())?;
+        self.locos
+            .retain(|loco| loco.throttle != throttle || loco.key != key);
+
+        let mut loco = Loco {
+            throttle,
+            key,
+            slot,
+            shown: None,
+        };
+        let mut lines = vec![format!("M{}+{}{}", throttle, loco.key, SEPARATOR)];
+        lines.extend(loco.show(self.shared.slot(slot)));
+        self.locos.push(loco);
+        Ok(lines)
+    }
+
+    /// Releases the locomotives with `key` from `throttle`, putting them back to the command station if `dispatch`.
+    async fn release(
+        &mut self,
+        throttle: char,
+        key: &str,
+        dispatch: bool,
+    ) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        let mut index = 0;
+        while index < self.locos.len() {
+            let loco = &self.locos[index];
+            if loco.throttle != throttle || (key != "*" && loco.key != key) {
+                index += 1;
+                continue;
+            }
+            let loco = self.locos.remove(index);
+            if dispatch {
+                self.shared
+                    .handle
+                    .dispatch_put(loco.slot)
+                    .await
+                    .map_err(|err| err.to_string())?;
+            }
+            lines.push(format!("M{}-{}{}", throttle, loco.key, SEPARATOR));
+        }
+        Ok(lines)
+    }
+
+    /// Executes `action` on the locomotives with `key` of `throttle`.
+    async fn act(
+        &mut self,
+        throttle: char,
+        key: &str,
+        action: Action,
+    ) -> Result<Vec<String>, String> {
+        let slots: Vec<_> = self
+            .locos
+            .iter()
+            .filter(|loco| loco.throttle == throttle && (key == "*" || loco.key == key))
+            .map(|loco| loco.slot)
+            .collect();
+
+        for slot in slots {
+            let (mut dirf, mut snd) = functions(self.shared.slot(slot));
+            let message = match action {
+                Action::Speed(speed) => Message::LocoSpd(slot, speed),
+                Action::Forward(forward) => {
+                    dirf.set_dir(forward);
+                    Message::LocoDirf(slot, dirf)
+                }
+                // Functions are latching, so only pressing toggles them
+                Action::Press(_, false) | Action::Query => continue,
+                Action::Press(function @ 0..=4, true) => {
+                    dirf.set_f(function, !dirf.f(function));
+                    Message::LocoDirf(slot, dirf)
+                }
+                Action::Function(function @ 0..=4, on) => {
+                    dirf.set_f(function, on);
+                    Message::LocoDirf(slot, dirf)
+                }
+                Action::Press(function @ 5..=8, true) => {
+                    snd.set_f(function, !snd.f(function));
+                    Message::LocoSnd(slot, snd)
+                }
+                Action::Function(function @ 5..=8, on) => {
+                    snd.set_f(function, on);
+                    Message::LocoSnd(slot, snd)
+                }
+                Action::Press(..) | Action::Function(..) => continue,
+            };
+            self.shared.send(message).await?;
+        }
+
+        // A query is answered by the complete state, changes are reflected by the updates
+        let mut lines = Vec::new();
+        if action == Action::Query {
+            for loco in self.locos.iter_mut() {
+                if loco.throttle == throttle && (key == "*" || loco.key == key) {
+                    loco.shown = None;
+                    lines.extend(loco.show(self.shared.slot(loco.slot)));
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Reflects `update` of the layout to the app.
+    ///
+    /// # Returns
+    ///
+    /// The lines reporting the update.
+    fn reflect(&mut self, update: Update) -> Vec<String> {
+        match update {
+            Update::Slot(slot) => {
+                let state = self.shared.slot(slot);
+                self.locos
+                    .iter_mut()
+                    .filter(|loco| loco.slot == slot)
+                    .flat_map(|loco| loco.show(state))
+                    .collect()
+            }
+            Update::Turnout(address, direction) => {
+                vec![format!("PTA{}LT{}", turnout_code(Some(direction)), address)]
+            }
+            Update::Power(power) => vec![format!("PPA{}", power_code(power))],
+        }
+    }
+
+    /// Stops all locomotives of the app in emergency, as its heartbeat was missed.
+    async fn stop_all(&mut self) {
+        for slot in self.locos.iter().map(|loco| loco.slot).collect::<Vec<_>>() {
+            if let Err(err) = self
+                .shared
+                .send(Message::LocoSpd(slot, SpeedArg::EmergencyStop))
+                .await
+            {
+                log_error!("Could not stop slot {}: {}", slot.slot(), err);
+            }
+        }
+    }
+}
+
+/// Serves the app connected by `stream`, until it disconnects or the server stops.
+async fn serve(stream: TcpStream, address: SocketAddr, shared: Arc<Shared>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut updates = shared.updates.subscribe();
+    let mut client = Client {
+        shared,
+        locos: Vec::new(),
+        heartbeat: false,
+    };
+    let mut replies = client.greeting();
+    // The heartbeat is missed once, until the app is heard again
+    let mut deadline = None;
+
+    loop {
+        let mut answer = String::new();
+        for reply in replies.drain(..) {
+            answer.push_str(&reply);
+            answer.push('\n');
+        }
+        if !answer.is_empty() {
+            if let Err(err) = writer.write_all(answer.as_bytes()).await {
+                log_error!("Could not write to throttle {}: {}", address, err);
+                break;
+            }
+        }
+
+        let missed = deadline.filter(|_| client.heartbeat);
+        replies = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    deadline = Some(Instant::now() + client.shared.heartbeat);
+                    match parse_command(&line) {
+                        Some(Command::Quit) => break,
+                        Some(command) => client.execute(command).await,
+                        None => Vec::new(),
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    log_error!("Could not read from throttle {}: {}", address, err);
+                    break;
+                }
+            },
+            update = updates.recv() => match update {
+                Ok(update) => client.reflect(update),
+                Err(RecvError::Lagged(lost)) => {
+                    log_error!("Throttle {} lost {} updates", address, lost);
+                    Vec::new()
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = sleep_until(missed.unwrap_or_else(Instant::now)), if missed.is_some() => {
+                log_info!("Throttle {} missed its heartbeat", address);
+                client.stop_all().await;
+                deadline = None;
+                Vec::new()
+            }
+        };
+    }
+
+    log_info!("Throttle {} disconnected", address);
+}
@@ -0,0 +1,139 @@
+use crate::error::MessageParseError;
+use crate::protocol::Message;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::UdpSocket;
+
+/// The UDP port a z21 listens on.
+pub const Z21_PORT: u16 = 21105;
+
+/// Requests the serial number, used to keep the client registered.
+const LAN_GET_SERIAL_NUMBER: u16 = 0x10;
+/// Unregisters the client.
+const LAN_LOGOFF: u16 = 0x30;
+/// Sets which events are broadcast to the client.
+const LAN_SET_BROADCASTFLAGS: u16 = 0x50;
+/// A LocoNet message the z21 received from the bus.
+const LAN_LOCONET_Z21_RX: u16 = 0xA0;
+/// A LocoNet message the z21 wrote to the bus itself.
+const LAN_LOCONET_Z21_TX: u16 = 0xA1;
+/// A LocoNet message written to the bus by a LAN client.
+const LAN_LOCONET_FROM_LAN: u16 = 0xA2;
+
+/// Forwards the general, locomotive, turnout and occupancy messages of the LocoNet bus.
+const LOCONET_BROADCASTS: u32 = 0x0F00_0000;
+
+/// How often the client tells the z21 it is still there.
+/// The z21 unregisters clients silent for a minute.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// # Returns
+///
+/// The dataset with `header` and `data`, prefixed by its length.
+pub(crate) fn dataset(header: u16, data: &[u8]) -> Vec<u8> {
+    let len = (data.len() + 4) as u16;
+    let mut dataset = Vec::with_capacity(len as usize);
+    dataset.extend_from_slice(&len.to_le_bytes());
+    dataset.extend_from_slice(&header.to_le_bytes());
+    dataset.extend_from_slice(data);
+    dataset
+}
+
+/// Splits a `datagram` received from a z21 into its datasets.
+///
+/// # Returns
+///
+/// The LocoNet frames tunneled by the datagram. Other datasets are skipped.
+pub(crate) fn loconet_frames(datagram: &[u8]) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    let mut rest = datagram;
+    while rest.len() >= 4 {
+        let len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        let header = u16::from_le_bytes([rest[2], rest[3]]);
+        // A truncated dataset ends the datagram
+        if len < 4 || len > rest.len() {
+            break;
+        }
+        if let LAN_LOCONET_Z21_RX | LAN_LOCONET_Z21_TX | LAN_LOCONET_FROM_LAN = header {
+            frames.push(&rest[4..len]);
+        }
+        rest = &rest[len..];
+    }
+    frames
+}
+
+/// Registers at the z21 connected to `socket` for the LocoNet messages.
+///
+/// # Errors
+///
+/// If the registration could not be sent.
+pub(crate) async fn register(socket: &UdpSocket) -> io::Result<()> {
+    socket
+        .send(&dataset(
+            LAN_SET_BROADCASTFLAGS,
+            &LOCONET_BROADCASTS.to_le_bytes(),
+        ))
+        .await
+        .map(|_| ())
+}
+
+/// Tunnels the LocoNet frames between `bus`, the end of an in memory transport, and the z21
+/// connected to `socket`, until the other end of the transport is closed.
+///
+/// The frames written to the transport are sent to the z21 and echoed back right away,
+/// as the z21 does not return the messages of a client to itself.
+pub(crate) async fn tunnel(socket: UdpSocket, mut bus: DuplexStream) {
+    let mut written = Vec::new();
+    let mut read = [0; 256];
+    let mut datagram = [0; 1472];
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE);
+
+    loop {
+        tokio::select! {
+            len = bus.read(&mut read) => match len {
+                Ok(0) | Err(_) => break,
+                Ok(len) => {
+                    written.extend_from_slice(&read[..len]);
+                    loop {
+                        let message = match Message::parse(&written) {
+                            Ok(message) => message,
+                            Err(MessageParseError::UnexpectedEnd { .. }) => break,
+                            // The controller writes valid frames only, so the byte is skipped
+                            Err(_) => {
+                                written.remove(0);
+                                continue;
+                            }
+                        };
+                        let frame: Vec<u8> = written.drain(..message.encoded_len()).collect();
+                        if let Err(err) = socket.send(&dataset(LAN_LOCONET_FROM_LAN, &frame)).await {
+                            log_error!("Could not send to the z21: {}", err);
+                            continue;
+                        }
+                        if bus.write_all(&frame).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            },
+            received = socket.recv(&mut datagram) => match received {
+                Ok(len) => {
+                    for frame in loconet_frames(&datagram[..len]) {
+                        if bus.write_all(frame).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(err) => log_error!("Could not receive from the z21: {}", err),
+            },
+            _ = keep_alive.tick() => {
+                if let Err(err) = socket.send(&dataset(LAN_GET_SERIAL_NUMBER, &[])).await {
+                    log_error!("Could not keep the z21 connection alive: {}", err);
+                }
+            }
+        }
+    }
+
+    // The z21 would keep broadcasting until the client times out
+    let _ = socket.send(&dataset(LAN_LOGOFF, &[])).await;
+}